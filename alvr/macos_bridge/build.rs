@@ -1,10 +1,25 @@
 fn main() {
-    use std::{env, path::PathBuf, process::Command};
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+        process::Command,
+    };
 
     if env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("macos") {
         return;
     }
 
+    let build_hash = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_owned())
+        .filter(|hash| !hash.is_empty())
+        .unwrap_or_else(|| "unknown".into());
+    println!("cargo:rustc-env=ALVR_BRIDGE_BUILD_HASH={build_hash}");
+
     let output_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR must be set"));
     let air_path = output_dir.join("bgra_to_nv12.air");
     let metallib_path = output_dir.join("bgra_to_nv12.metallib");
@@ -38,15 +53,75 @@ fn main() {
         .include("src")
         .flag("-std=c11")
         .compile("alvr_macos_native_source");
+    // Only the integration tests reference the fake producer, so release
+    // builds link none of it.
+    cc::Build::new()
+        .file("src/fake_wine_writer.c")
+        .include("src")
+        .flag("-std=c11")
+        .compile("alvr_macos_fake_wine_writer");
+    // Nothing links this object; compiling it makes the C compiler check the
+    // generated header's layout asserts.
+    cc::Build::new()
+        .file("src/tracking_feedback_layout.c")
+        .include("src")
+        .flag("-std=c11")
+        .compile_intermediates();
+    cc::Build::new()
+        .file("src/power_state.m")
+        .flag("-fobjc-arc")
+        .compile("alvr_macos_power_state");
+    cc::Build::new()
+        .file("src/system_events.m")
+        .flag("-fobjc-arc")
+        .compile("alvr_macos_system_events");
+    cc::Build::new()
+        .file("src/preview.m")
+        .flag("-fobjc-arc")
+        .compile("alvr_macos_preview");
 
+    println!("cargo:rustc-link-lib=framework=AppKit");
     println!("cargo:rustc-link-lib=framework=CoreFoundation");
+    println!("cargo:rustc-link-lib=framework=CoreGraphics");
     println!("cargo:rustc-link-lib=framework=CoreVideo");
+    println!("cargo:rustc-link-lib=framework=Foundation");
+    println!("cargo:rustc-link-lib=framework=IOKit");
     println!("cargo:rustc-link-lib=framework=IOSurface");
     println!("cargo:rustc-link-lib=framework=Metal");
+    println!("cargo:rustc-link-lib=framework=QuartzCore");
     println!("cargo:rustc-link-lib=bsm");
     println!("cargo:rustc-link-lib=proc");
     println!("cargo:rerun-if-changed=src/bgra_to_nv12.metal");
     println!("cargo:rerun-if-changed=src/metal_converter.mm");
     println!("cargo:rerun-if-changed=src/native_source.c");
+    println!("cargo:rerun-if-changed=src/fake_wine_writer.c");
+    println!("cargo:rerun-if-changed=src/power_state.m");
+    println!("cargo:rerun-if-changed=src/system_events.m");
+    println!("cargo:rerun-if-changed=src/preview.m");
     println!("cargo:rerun-if-changed=src/iosurface_handoff_protocol.h");
+    println!("cargo:rerun-if-changed=src/tracking_feedback_layout.c");
+    println!("cargo:rerun-if-changed=src/tracking_feedback_layout.h");
+    // HEAD only names the branch, so a new commit changes the ref it points
+    // at instead. Cargo reruns on every build for a path that does not exist,
+    // so a branch that git gc packed is watched through packed-refs and its
+    // directory, where the next commit writes it loose again.
+    let git_dir = Path::new("../../.git");
+    println!("cargo:rerun-if-changed=../../.git/HEAD");
+    if let Some(branch_ref) = fs::read_to_string(git_dir.join("HEAD"))
+        .ok()
+        .and_then(|head| Some(git_dir.join(head.strip_prefix("ref:")?.trim())))
+    {
+        let watched = if branch_ref.exists() {
+            Some(branch_ref.as_path())
+        } else {
+            branch_ref.parent()
+        };
+        if let Some(watched) = watched {
+            println!("cargo:rerun-if-changed={}", watched.display());
+        }
+    }
+    let packed_refs = git_dir.join("packed-refs");
+    if packed_refs.exists() {
+        println!("cargo:rerun-if-changed={}", packed_refs.display());
+    }
 }
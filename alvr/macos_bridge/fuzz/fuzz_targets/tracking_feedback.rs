@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::{env, path::PathBuf, sync::LazyLock};
+
+static SEGMENT: LazyLock<PathBuf> = LazyLock::new(|| {
+    env::temp_dir().join(format!("alvr_bridge_fuzz_feedback_{}", std::process::id()))
+});
+
+fuzz_target!(|data: &[u8]| {
+    alvr_macos_bridge::fuzzing::tracking_feedback(&SEGMENT, data);
+});
@@ -1,20 +1,109 @@
-use crate::{EncodedFrame, FrameMetadata, tracking_feedback::TrackingFeedback};
-use alvr_common::{HAND_LEFT_ID, HAND_RIGHT_ID, HEAD_ID, Pose, ViewParams};
+use crate::{
+    EncodedFrame, Foveation, FrameMetadata, SurfaceFormat,
+    clock_sync::WireClock,
+    control::ClientStatus,
+    encoder::codec_name,
+    output_buffers::OUTPUT_BUFFERS,
+    recording::{TransportDump, TransportDumpEntry},
+    tracking_feedback::{BridgeStats, FeedbackCapabilities, TrackingFeedback},
+};
+use alvr_common::{
+    HAND_LEFT_ID, HAND_RIGHT_ID, HEAD_ID, LogEntry, LogSeverity, Pose, ViewParams, glam::Vec2,
+};
+use alvr_events::{EventType, StatisticsSummary};
 use alvr_filesystem::Layout;
-use alvr_server_core::{ServerCoreContext, ServerCoreEvent, ServerNegotiatedStreamingConfig};
-use alvr_session::{CodecType, SessionConfig, SteamvrHmdInitConfig};
+use alvr_packets::{BatteryInfo, Haptics};
+use alvr_server_core::{
+    HandType, ServerCoreContext, ServerCoreEvent, ServerNegotiatedStreamingConfig,
+};
+use alvr_session::{
+    BitrateMode, CodecType, CustomAudioDeviceConfig, FoveatedEncodingConfig, FrameSize,
+    H264Profile, MicrophoneDevicesConfig, SessionConfig, Settings, SteamvrHmdInitConfig,
+};
 use anyhow::{Context, Result, ensure};
 use serde_json::Value;
 use std::{
-    fs,
+    fmt, fs,
     io::ErrorKind,
-    path::Path,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
     sync::mpsc::{Receiver, TryRecvError},
-    time::Duration,
+    time::{Duration, Instant, SystemTime},
 };
 
 const DECODER_BOOTSTRAP_FRAME_LIMIT: u32 = 3;
 const NATIVE_SOCKET_BUFFER_BYTES: u64 = 8_000_000;
+// The server core does not resample game audio outside Windows.
+const GAME_AUDIO_SAMPLE_RATE: u32 = 44100;
+/// Anything longer extrapolates a head turn well past where it ends.
+pub(crate) const MAX_FIXED_POSE_PREDICTION: Duration = Duration::from_millis(100);
+/// Wider than any headset display, so a rate outside it is a bad report.
+const REFRESH_RANGE: RangeInclusive<f32> = 30.0..=240.0;
+
+/// How far ahead of each tracking sample the head and controller poses
+/// handed to the Wine side are extrapolated from the sample's velocities, so
+/// a frame is rendered for where the head will be when it is shown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PosePrediction {
+    /// Publish poses as the client sent them.
+    #[default]
+    Off,
+    Fixed(Duration),
+    /// Server core's measured motion-to-photon latency, capped by the
+    /// session's `headset.max_prediction_ms`, as ALVR's SteamVR driver uses.
+    MotionToPhoton,
+}
+
+impl PosePrediction {
+    fn lookahead(self, context: &ServerCoreContext) -> Duration {
+        match self {
+            Self::Off => Duration::ZERO,
+            Self::Fixed(lookahead) => lookahead,
+            Self::MotionToPhoton => context.get_motion_to_photon_latency(),
+        }
+    }
+}
+
+impl fmt::Display for PosePrediction {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Off => formatter.write_str("off"),
+            Self::Fixed(lookahead) => write!(formatter, "{}ms", lookahead.as_millis()),
+            Self::MotionToPhoton => formatter.write_str("auto"),
+        }
+    }
+}
+
+/// What happens when a second headset connects while one is streaming.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SecondClient {
+    /// Server core does not connect it until the first one disconnects.
+    #[default]
+    Reject,
+    /// The new headset takes the stream and the previous one is
+    /// disconnected.
+    Handover,
+}
+
+impl fmt::Display for SecondClient {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(match self {
+            Self::Reject => "reject",
+            Self::Handover => "handover",
+        })
+    }
+}
+
+/// Core Audio devices the ALVR server core streams through. macOS has no
+/// system loopback, so game audio is recorded from the input side of a
+/// loopback driver such as BlackHole that the game plays into. The headset
+/// microphone is played into the output side of another loopback device,
+/// which games then select as their input.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AudioDevices {
+    pub game_audio: Option<String>,
+    pub microphone: Option<String>,
+}
 
 #[derive(Clone, Copy)]
 struct TrackingClock {
@@ -41,21 +130,114 @@ impl DecoderBootstrap {
     }
 }
 
+/// Transport totals for the dashboard's statistics panel, which server core
+/// only fills for frames it can match to a tracking timestamp. The headset
+/// battery is carried over so the panel does not flicker to empty between
+/// server core's own summaries.
+struct DashboardStatistics {
+    window_started: Instant,
+    packets_total: usize,
+    bytes_total: u64,
+    packets: usize,
+    bytes: u64,
+    encode_latency_total: Duration,
+    hmd_battery: Option<BatteryInfo>,
+}
+
+impl DashboardStatistics {
+    fn new() -> Self {
+        Self {
+            window_started: Instant::now(),
+            packets_total: 0,
+            bytes_total: 0,
+            packets: 0,
+            bytes: 0,
+            encode_latency_total: Duration::ZERO,
+            hmd_battery: None,
+        }
+    }
+
+    fn record(&mut self, bytes: usize, encode_latency: Duration) {
+        self.packets += 1;
+        self.bytes += bytes as u64;
+        self.encode_latency_total += encode_latency;
+    }
+
+    fn take_summary(&mut self) -> StatisticsSummary {
+        let interval_secs = self
+            .window_started
+            .elapsed()
+            .as_secs_f32()
+            .max(f32::EPSILON);
+        self.packets_total += self.packets;
+        self.bytes_total += self.bytes;
+        let encode_latency = self
+            .encode_latency_total
+            .checked_div(self.packets as u32)
+            .unwrap_or_default();
+        let summary = StatisticsSummary {
+            video_packets_total: self.packets_total,
+            video_packets_per_sec: (self.packets as f32 / interval_secs) as usize,
+            video_mbytes_total: (self.bytes_total / 1_000_000) as usize,
+            video_mbits_per_sec: self.bytes as f32 * 8.0 / 1e6 / interval_secs,
+            encode_latency_ms: encode_latency.as_secs_f32() * 1000.0,
+            server_fps: (self.packets as f32 / interval_secs).round() as u32,
+            battery_hmd: self
+                .hmd_battery
+                .as_ref()
+                .map_or(0, |battery| (battery.gauge_value * 100.0) as u32),
+            hmd_plugged: self
+                .hmd_battery
+                .as_ref()
+                .is_some_and(|battery| battery.is_plugged),
+            ..Default::default()
+        };
+        self.window_started = Instant::now();
+        self.packets = 0;
+        self.bytes = 0;
+        self.encode_latency_total = Duration::ZERO;
+        summary
+    }
+}
+
 pub struct AlvrVideoSink {
     context: ServerCoreContext,
     events: Receiver<ServerCoreEvent>,
     force_keyframe: bool,
+    idr_requests: u64,
     shutdown_requested: bool,
+    restart_requested: bool,
+    client_hostname: Option<String>,
+    handovers_pending: u32,
+    driver_paused: bool,
+    playspace: Option<Vec2>,
+    headset_worn: Option<bool>,
     connected: bool,
     ever_connected: bool,
     expected_width: u32,
     expected_height: u32,
-    expected_fps: u32,
+    stream_size: (u32, u32),
+    resized_stream: Option<(u32, u32)>,
+    codec: CodecType,
+    h264_profile: H264Profile,
+    renegotiated_codec: Option<(CodecType, H264Profile)>,
+    refresh_rate: f32,
+    renegotiated_refresh: Option<f32>,
+    ten_bit: bool,
+    hdr: bool,
+    /// The session's foveation settings when the bridge asked for foveated
+    /// encoding, and what they work out to for the current connection.
+    foveation_config: Option<FoveatedEncodingConfig>,
+    foveation: Option<Foveation>,
     stream_epoch: u64,
     connection_error: Option<String>,
+    client_status: Option<ClientStatus>,
     local_view_params: Option<[ViewParams; 2]>,
+    /// The latest head pose as published to the Wine side, predicted or not.
     latest_tracking: Option<(Duration, Pose)>,
+    pose_prediction: PosePrediction,
     tracking_clock: Option<TrackingClock>,
+    wire_clock: WireClock,
     last_pose_timestamp: Duration,
     decoder_config_sent: bool,
     decoder_bootstrap: DecoderBootstrap,
@@ -66,14 +248,24 @@ pub struct AlvrVideoSink {
     feedback_pose_logged: bool,
     exact_frame_pose_logged: bool,
     feedback_controller_published: [bool; 2],
+    feedback_face_published: bool,
+    feedback_hand_published: [bool; 2],
+    transport_dump: Option<TransportDump>,
+    dashboard: DashboardStatistics,
 }
 
 impl AlvrVideoSink {
+    #[expect(clippy::too_many_arguments)]
     pub fn start(
         root: &Path,
         width: u32,
         height: u32,
         fps: u32,
+        codec: CodecType,
+        format: SurfaceFormat,
+        hdr: bool,
+        foveated: bool,
+        audio: &AudioDevices,
         runtime_generation: u64,
     ) -> Result<Self> {
         ensure!(
@@ -87,29 +279,70 @@ impl AlvrVideoSink {
         ensure!(fps > 0, "ALVR stream FPS must be positive");
         fs::create_dir_all(root)?;
         let layout = Layout::new(root);
-        ensure_native_session(&layout, width, height, fps)?;
+        check_audio_devices(audio)?;
+        let foveation_config = ensure_native_session(
+            &layout,
+            width,
+            height,
+            fps,
+            codec,
+            format.is_ten_bit(),
+            hdr,
+            foveated,
+            audio,
+        )?;
         alvr_server_core::initialize_environment(layout.clone());
         alvr_server_core::init_logging(Some(layout.session_log()), Some(layout.crash_log()));
 
         let (context, events) = ServerCoreContext::new();
+        context.set_exclusive_client(SecondClient::default() == SecondClient::Reject);
         context.start_connection();
-        let tracking_feedback = TrackingFeedback::create(runtime_generation)?;
+        let tracking_feedback = TrackingFeedback::create(
+            runtime_generation,
+            FeedbackCapabilities::for_stream(
+                width,
+                height,
+                codec,
+                format,
+                audio.game_audio.is_some(),
+            ),
+        )?;
 
         Ok(Self {
             context,
             events,
             force_keyframe: true,
+            idr_requests: 0,
             shutdown_requested: false,
+            restart_requested: false,
+            client_hostname: None,
+            handovers_pending: 0,
+            driver_paused: false,
+            playspace: None,
+            headset_worn: None,
             connected: false,
             ever_connected: false,
             expected_width: width,
             expected_height: height,
-            expected_fps: fps,
+            stream_size: (width, height),
+            resized_stream: None,
+            codec,
+            h264_profile: H264Profile::High,
+            renegotiated_codec: None,
+            refresh_rate: fps as f32,
+            renegotiated_refresh: None,
+            ten_bit: format.is_ten_bit(),
+            hdr,
+            foveation_config,
+            foveation: None,
             stream_epoch: 0,
             connection_error: None,
+            client_status: None,
             local_view_params: None,
             latest_tracking: None,
+            pose_prediction: PosePrediction::Off,
             tracking_clock: None,
+            wire_clock: WireClock::new(Instant::now()),
             last_pose_timestamp: Duration::ZERO,
             decoder_config_sent: false,
             decoder_bootstrap: DecoderBootstrap::default(),
@@ -120,6 +353,10 @@ impl AlvrVideoSink {
             feedback_pose_logged: false,
             exact_frame_pose_logged: false,
             feedback_controller_published: [false; 2],
+            feedback_face_published: false,
+            feedback_hand_published: [false; 2],
+            transport_dump: None,
+            dashboard: DashboardStatistics::new(),
         })
     }
 
@@ -128,20 +365,90 @@ impl AlvrVideoSink {
         loop {
             match self.events.try_recv() {
                 Ok(ServerCoreEvent::ClientConnected(config)) => {
+                    let hostname = self
+                        .context
+                        .streaming_clients()
+                        .into_iter()
+                        .find(|hostname| Some(hostname) != self.client_hostname.as_ref());
+                    // Only a handover lets server core connect a second
+                    // client, which then replaces the one streaming.
+                    if self.connected
+                        && let Some(previous) = self.client_hostname.take()
+                    {
+                        eprintln!(
+                            "alvr_sink handover from={previous} to={}",
+                            hostname.as_deref().unwrap_or("unknown")
+                        );
+                        self.context.disconnect_client(&previous);
+                        self.handovers_pending += 1;
+                    }
+                    self.client_hostname = hostname;
                     self.stream_epoch = self
                         .stream_epoch
                         .checked_add(1)
                         .expect("ALVR stream epoch overflow");
                     self.connected = true;
                     self.ever_connected = true;
-                    self.connection_error = validate_stream_config(
+                    self.connection_error = match validate_stream_config(
                         &config,
                         self.expected_width,
                         self.expected_height,
-                        self.expected_fps,
-                    )
-                    .err()
-                    .map(|error| error.to_string());
+                        self.ten_bit,
+                        self.hdr,
+                        self.foveation_config.as_ref(),
+                    ) {
+                        Ok(NegotiatedStream {
+                            size: stream_size,
+                            codec,
+                            h264_profile,
+                            refresh_rate,
+                            foveation,
+                        }) => {
+                            self.foveation = foveation;
+                            if refresh_rate != self.refresh_rate {
+                                eprintln!(
+                                    "alvr_sink refresh renegotiated epoch={} from={:.3} to={refresh_rate:.3}",
+                                    self.stream_epoch, self.refresh_rate,
+                                );
+                                self.refresh_rate = refresh_rate;
+                                self.renegotiated_refresh = Some(refresh_rate);
+                            }
+                            if (codec, h264_profile) != (self.codec, self.h264_profile) {
+                                eprintln!(
+                                    "alvr_sink codec renegotiated epoch={} from={} to={} h264_profile={:?}",
+                                    self.stream_epoch,
+                                    codec_name(self.codec),
+                                    codec_name(codec),
+                                    h264_profile,
+                                );
+                                self.codec = codec;
+                                self.h264_profile = h264_profile;
+                                self.renegotiated_codec = Some((codec, h264_profile));
+                            }
+                            if stream_size != self.stream_size {
+                                eprintln!(
+                                    "alvr_sink stream resized epoch={} from={}x{} to={}x{}",
+                                    self.stream_epoch,
+                                    self.stream_size.0,
+                                    self.stream_size.1,
+                                    stream_size.0,
+                                    stream_size.1,
+                                );
+                                self.stream_size = stream_size;
+                                self.resized_stream = Some(stream_size);
+                            }
+                            None
+                        }
+                        Err(error) => Some(error.to_string()),
+                    };
+                    self.client_status = Some(ClientStatus {
+                        stream_epoch: self.stream_epoch,
+                        view_width: config.transcoding_view_resolution.x,
+                        view_height: config.transcoding_view_resolution.y,
+                        refresh_rate: config.refresh_rate,
+                        codec: config.codec,
+                        contract_valid: self.connection_error.is_none(),
+                    });
                     eprintln!(
                         "alvr_sink connected epoch={} view={}x{} emulated={}x{} fps={:.3} codec={:?} foveated={} ten_bit={} gamma={:.3} hdr={} contract={}",
                         self.stream_epoch,
@@ -165,6 +472,7 @@ impl AlvrVideoSink {
                     self.local_view_params = None;
                     self.latest_tracking = None;
                     self.tracking_clock = None;
+                    self.wire_clock.reset_tracking();
                     self.last_pose_timestamp = Duration::ZERO;
                     self.decoder_config_sent = false;
                     self.decoder_bootstrap.reset();
@@ -179,17 +487,28 @@ impl AlvrVideoSink {
                     self.feedback_pose_logged = false;
                     self.exact_frame_pose_logged = false;
                     self.feedback_controller_published = [false; 2];
+                    self.feedback_face_published = false;
+                    self.feedback_hand_published = [false; 2];
+                }
+                // The replaced client's disconnect arrives after the new
+                // client connected and leaves its stream alone.
+                Ok(ServerCoreEvent::ClientDisconnected) if self.handovers_pending > 0 => {
+                    self.handovers_pending -= 1;
+                    eprintln!("alvr_sink handover previous client disconnected");
                 }
                 Ok(ServerCoreEvent::ClientDisconnected) => {
+                    self.client_hostname = None;
                     self.stream_epoch = self
                         .stream_epoch
                         .checked_add(1)
                         .expect("ALVR stream epoch overflow");
                     self.connected = false;
                     self.connection_error = None;
+                    self.client_status = None;
                     self.local_view_params = None;
                     self.latest_tracking = None;
                     self.tracking_clock = None;
+                    self.wire_clock.reset_tracking();
                     self.last_pose_timestamp = Duration::ZERO;
                     self.decoder_config_sent = false;
                     self.decoder_bootstrap.reset();
@@ -202,8 +521,16 @@ impl AlvrVideoSink {
                     self.feedback_pose_logged = false;
                     self.exact_frame_pose_logged = false;
                     self.feedback_controller_published = [false; 2];
+                    self.feedback_face_published = false;
+                    self.feedback_hand_published = [false; 2];
+                }
+                Ok(ServerCoreEvent::Battery(battery)) if battery.device_id == *HEAD_ID => {
+                    self.dashboard.hmd_battery = Some(battery);
+                }
+                Ok(ServerCoreEvent::RequestIDR) => {
+                    self.force_keyframe = true;
+                    self.idr_requests += 1;
                 }
-                Ok(ServerCoreEvent::RequestIDR) => self.force_keyframe = true,
                 Ok(ServerCoreEvent::LocalViewParams(params)) => {
                     self.local_view_params = Some(params);
                     if !self.feedback_view_logged {
@@ -233,7 +560,12 @@ impl AlvrVideoSink {
                     }
                 }
                 Ok(ServerCoreEvent::Tracking { poll_timestamp }) => {
+                    self.wire_clock
+                        .observe_tracking(poll_timestamp, Instant::now());
+                    let lookahead = self.pose_prediction.lookahead(&self.context);
+                    let target_timestamp = poll_timestamp + lookahead;
                     if let Some(motion) = self.context.get_device_motion(*HEAD_ID, poll_timestamp) {
+                        let motion = motion.predict(poll_timestamp, target_timestamp);
                         self.latest_tracking = Some((poll_timestamp, motion.pose));
                         let published = self
                             .tracking_feedback
@@ -247,8 +579,10 @@ impl AlvrVideoSink {
                         }
                         if published && !self.feedback_pose_published {
                             eprintln!(
-                                "alvr_sink OpenVR HMD pose feedback ready timestamp_ns={}",
+                                "alvr_sink OpenVR HMD pose feedback ready timestamp_ns={} prediction={} lookahead_us={}",
                                 poll_timestamp.as_nanos(),
+                                self.pose_prediction,
+                                lookahead.as_micros(),
                             );
                             self.feedback_pose_published = true;
                         }
@@ -259,6 +593,7 @@ impl AlvrVideoSink {
                         if let Some(motion) =
                             self.context.get_device_motion(device_id, poll_timestamp)
                         {
+                            let motion = motion.predict(poll_timestamp, target_timestamp);
                             let published = self.tracking_feedback.publish_controller_motion(
                                 controller_index,
                                 poll_timestamp,
@@ -279,14 +614,92 @@ impl AlvrVideoSink {
                             }
                         }
                     }
+                    for (hand_index, hand_type) in
+                        [HandType::Left, HandType::Right].into_iter().enumerate()
+                    {
+                        let skeleton = self.context.get_hand_skeleton(hand_type, poll_timestamp);
+                        if self.tracking_feedback.publish_hand_skeleton(
+                            hand_index,
+                            poll_timestamp,
+                            skeleton.as_ref(),
+                        ) && !self.feedback_hand_published[hand_index]
+                        {
+                            eprintln!(
+                                "alvr_sink OpenVR hand skeleton feedback ready hand={} timestamp_ns={}",
+                                if hand_index == 0 { "left" } else { "right" },
+                                poll_timestamp.as_nanos(),
+                            );
+                            self.feedback_hand_published[hand_index] = true;
+                        }
+                    }
+                    if let Some(face) = self.context.get_face_data(poll_timestamp)
+                        && self.tracking_feedback.publish_face(poll_timestamp, &face)
+                        && !self.feedback_face_published
+                    {
+                        eprintln!(
+                            "alvr_sink OpenVR face feedback ready timestamp_ns={} eyes={} expressions={}",
+                            poll_timestamp.as_nanos(),
+                            face.eyes_combined.is_some()
+                                || face.eyes_social.iter().any(Option::is_some),
+                            face.face_expressions.is_some(),
+                        );
+                        self.feedback_face_published = true;
+                    }
                 }
+                // The driver maps client paths itself, and the server core
+                // only sends mapped entries when a mapping is configured.
                 Ok(ServerCoreEvent::RawButtons(entries) | ServerCoreEvent::Buttons(entries)) => {
                     self.tracking_feedback.publish_buttons(&entries);
                 }
-                Ok(ServerCoreEvent::ShutdownPending | ServerCoreEvent::RestartPending) => {
-                    self.shutdown_requested = true
+                Ok(ServerCoreEvent::Battery(battery)) => {
+                    // Controller batteries have no shared-memory field yet.
+                    eprintln!(
+                        "alvr_sink battery ignored device_id={:#x} gauge={:.2} plugged={}",
+                        battery.device_id, battery.gauge_value, battery.is_plugged
+                    );
                 }
-                Ok(_) => {}
+                Ok(ServerCoreEvent::PlayspaceSync(size)) => {
+                    // SteamVR owns the chaperone; the bridge only records
+                    // what the client reported.
+                    if self.playspace != Some(size) {
+                        eprintln!(
+                            "alvr_sink playspace width={:.3} depth={:.3}",
+                            size.x, size.y
+                        );
+                        self.playspace = Some(size);
+                    }
+                }
+                Ok(ServerCoreEvent::ProximityState(worn)) => {
+                    if self.headset_worn != Some(worn) {
+                        eprintln!("alvr_sink proximity headset_worn={worn}");
+                        self.headset_worn = Some(worn);
+                    }
+                }
+                Ok(ServerCoreEvent::SetOpenvrProperty { device_id, prop }) => {
+                    // Device properties belong to the Wine driver, which
+                    // reads its own settings.
+                    eprintln!(
+                        "alvr_sink openvr_property ignored device_id={device_id:#x} key={:?}",
+                        prop.key
+                    );
+                }
+                Ok(ServerCoreEvent::CaptureFrame) => {
+                    eprintln!("WARNING alvr_sink capture_frame ignored reason=unsupported");
+                }
+                // Only the SteamVR compositor acts on the game's render
+                // latency, and the bridge never sees it.
+                Ok(ServerCoreEvent::GameRenderLatencyFeedback(_)) => {}
+                Ok(ServerCoreEvent::ShutdownPending) => {
+                    eprintln!("alvr_sink shutdown requested source=alvr");
+                    self.shutdown_requested = true;
+                }
+                Ok(ServerCoreEvent::RestartPending) => {
+                    eprintln!("alvr_sink restart requested source=alvr");
+                    self.shutdown_requested = true;
+                    self.restart_requested = true;
+                }
+                // Every variant is matched by name, so a new one from the
+                // server core fails to build here instead of being dropped.
                 Err(TryRecvError::Empty) => break,
                 Err(TryRecvError::Disconnected) => {
                     self.shutdown_requested = true;
@@ -294,6 +707,37 @@ impl AlvrVideoSink {
                 }
             }
         }
+
+        for haptics in self.tracking_feedback.take_haptics() {
+            // Rumble queued while no client is connected would fire late.
+            if self.connected {
+                self.context.send_haptics(Haptics {
+                    device_id: [*HAND_LEFT_ID, *HAND_RIGHT_ID][haptics.controller_index],
+                    duration: haptics.duration,
+                    frequency: haptics.frequency,
+                    amplitude: haptics.amplitude,
+                });
+            }
+        }
+
+        let driver_paused = self.tracking_feedback.driver_paused();
+        if driver_paused != self.driver_paused {
+            self.driver_paused = driver_paused;
+            // The server core has no standby message for the client, which
+            // keeps showing the last frame; the dashboard log says why.
+            alvr_events::send_event(EventType::Log(LogEntry {
+                severity: LogSeverity::Info,
+                content: if driver_paused {
+                    "macOS bridge standby: SteamVR is idle".into()
+                } else {
+                    "macOS bridge resumed: SteamVR is active".into()
+                },
+            }));
+            if !driver_paused {
+                self.force_keyframe = true;
+                self.resend_decoder_config();
+            }
+        }
     }
 
     pub fn frame_metadata(
@@ -322,9 +766,10 @@ impl AlvrVideoSink {
             );
             if !self.exact_frame_pose_logged || frame_id.is_multiple_of(300) {
                 eprintln!(
-                    "alvr_sink exact frame pose frame_id={frame_id} generation={generation} pose_timestamp_ns={} video_timestamp_ns={}",
+                    "alvr_sink exact frame pose frame_id={frame_id} generation={generation} pose_timestamp_ns={} video_timestamp_ns={} video_drift_ppm={:.1}",
                     timestamp.as_nanos(),
                     video_timestamp.as_nanos(),
+                    self.wire_clock.video_drift_ppm(),
                 );
                 self.exact_frame_pose_logged = true;
             }
@@ -342,6 +787,8 @@ impl AlvrVideoSink {
             self.last_pose_timestamp,
         );
         self.last_pose_timestamp = pose_timestamp;
+        self.wire_clock
+            .assign(video_timestamp, Some(tracking_timestamp), Instant::now());
 
         Ok(Some(FrameMetadata {
             frame_id,
@@ -376,6 +823,9 @@ impl AlvrVideoSink {
         let local_view_params = self.local_view_params.unwrap_or(fallback_view_params);
         let pose_timestamp = video_timestamp.max(self.last_pose_timestamp);
         self.last_pose_timestamp = pose_timestamp;
+        // The producer's fallback pose has no tracking sample behind it.
+        self.wire_clock
+            .assign(video_timestamp, None, Instant::now());
         eprintln!(
             "alvr_sink decoder bootstrap frame_id={frame_id} index={}/{} source_pose_timestamp_ns={} video_timestamp_ns={}",
             self.decoder_bootstrap.submitted,
@@ -396,14 +846,60 @@ impl AlvrVideoSink {
         }))
     }
 
+    /// The new encode size when a client negotiated a different transcoding
+    /// resolution than the previous connection. Frames for the new stream
+    /// epoch must be encoded at this size.
+    pub fn take_stream_resize(&mut self) -> Option<(u32, u32)> {
+        self.resized_stream.take()
+    }
+
+    /// The codec and H.264 profile a new connection settled on, when they
+    /// differ from what the encoder was last built for.
+    pub fn take_codec_change(&mut self) -> Option<(CodecType, H264Profile)> {
+        self.renegotiated_codec.take()
+    }
+
+    /// The client's display refresh in Hz, when a new connection settled on
+    /// a different one than the encoder was last built for.
+    pub fn take_refresh_change(&mut self) -> Option<f32> {
+        self.renegotiated_refresh.take()
+    }
+
+    /// How the Metal pass has to compress frames for the connected client,
+    /// or `None` for a plain stream.
+    pub fn foveation(&self) -> Option<Foveation> {
+        self.foveation
+    }
+
     pub fn take_force_keyframe(&mut self) -> bool {
         self.poll_events();
         std::mem::take(&mut self.force_keyframe)
     }
 
+    /// Whether the next submitted frame has to be a keyframe, without
+    /// clearing the request.
+    pub(crate) fn keyframe_pending(&self) -> bool {
+        self.force_keyframe
+    }
+
+    /// Sends the next keyframe's parameter sets to the client again, as on a
+    /// new connection. Called when the encoder session has been replaced.
+    pub(crate) fn resend_decoder_config(&mut self) {
+        self.decoder_config_sent = false;
+        self.decoder_bootstrap.reset();
+    }
+
+    /// Keyframe requests ALVR has raised since the sink started. Server core
+    /// raises one when the client reports a decode error or lost packets, or
+    /// when a send to the client fails.
+    pub fn idr_requests(&self) -> u64 {
+        self.idr_requests
+    }
+
     pub fn send(&mut self, mut frame: EncodedFrame) -> Result<bool> {
         self.poll_events();
         if !self.connected || frame.metadata.stream_epoch != self.stream_epoch {
+            OUTPUT_BUFFERS.recycle_frame(frame);
             return Ok(false);
         }
         ensure!(
@@ -417,23 +913,64 @@ impl AlvrVideoSink {
         {
             ensure!(
                 !config_nals.is_empty(),
-                "VideoToolbox keyframe did not include HEVC decoder configuration"
+                "VideoToolbox keyframe did not include {} decoder configuration",
+                codec_name(self.codec)
             );
-            self.context
-                .set_video_config_nals(config_nals, CodecType::Hevc);
+            if let Some(dump) = self.transport_dump.as_mut() {
+                let offset = dump.write(&config_nals)?;
+                dump.index(
+                    offset,
+                    TransportDumpEntry {
+                        kind: "config",
+                        stream_epoch: self.stream_epoch,
+                        video_timestamp: frame.metadata.video_timestamp,
+                        keyframe: frame.is_keyframe,
+                        transported: true,
+                    },
+                )?;
+            }
+            self.context.set_video_config_nals(config_nals, self.codec);
             self.decoder_config_sent = true;
         }
         if self.connected && !self.decoder_config_sent {
             self.force_keyframe = true;
+            OUTPUT_BUFFERS.recycle_frame(frame);
             return Ok(false);
         }
+        // Parameter sets are only sent once per stream.
+        if let Some(config_nals) = frame.decoder_config_nals.take() {
+            OUTPUT_BUFFERS.recycle(config_nals);
+        }
+        let dump_offset = self
+            .transport_dump
+            .as_mut()
+            .map(|dump| dump.write(&frame.nal_data))
+            .transpose()?;
+        let frame_bytes = frame.nal_data.len();
+        let wire_timestamp = self
+            .wire_clock
+            .take(frame.metadata.video_timestamp)
+            .unwrap_or(frame.metadata.video_timestamp);
         let transported = self.context.send_video_nal(
-            frame.metadata.video_timestamp,
+            wire_timestamp,
             frame.metadata.global_view_params,
             frame.is_keyframe,
             frame.nal_data,
         );
+        if let (Some(dump), Some(offset)) = (self.transport_dump.as_mut(), dump_offset) {
+            dump.index(
+                offset,
+                TransportDumpEntry {
+                    kind: "frame",
+                    stream_epoch: self.stream_epoch,
+                    video_timestamp: frame.metadata.video_timestamp,
+                    keyframe: frame.is_keyframe,
+                    transported,
+                },
+            )?;
+        }
         if transported {
+            self.dashboard.record(frame_bytes, frame.encode_latency);
             ensure!(
                 self.tracking_feedback
                     .publish_frame_transported(self.stream_epoch),
@@ -444,6 +981,59 @@ impl AlvrVideoSink {
         Ok(transported)
     }
 
+    /// Marks `metadata`'s frame as presented when it arrived from Wine and
+    /// composed now, as it goes into VideoToolbox. Server core then splits
+    /// the dashboard's latency graph the way it does for the native
+    /// streamer: the Metal conversion shows as the compositor stage and the
+    /// encoder stage is VideoToolbox alone. Frames without a wire timestamp
+    /// yet are not in server core's history and are skipped.
+    pub(crate) fn report_composed(&self, metadata: &FrameMetadata, received_at: Instant) {
+        if let Some(wire_timestamp) = self.wire_clock.peek(metadata.video_timestamp) {
+            self.context
+                .report_present(wire_timestamp, received_at.elapsed());
+            self.context.report_composed(wire_timestamp, Duration::ZERO);
+        }
+    }
+
+    /// Shows a telemetry line in the dashboard's log and refreshes its
+    /// statistics panel with what was transported since the previous call.
+    pub(crate) fn publish_to_dashboard(&mut self, line: &str) {
+        alvr_common::info!("{line}");
+        alvr_events::send_event(EventType::StatisticsSummary(self.dashboard.take_summary()));
+    }
+
+    /// Raises a warning in the dashboard's log.
+    pub(crate) fn warn_dashboard(&self, line: &str) {
+        alvr_common::warn!("{line}");
+    }
+
+    /// Copies everything handed to server core into `dump` from now on.
+    /// Head and controller poses published from here on are extrapolated by
+    /// `prediction`. The pose ring keeps each sample's own tracking
+    /// timestamp, so frames still resolve to the sample they rendered.
+    pub fn set_pose_prediction(&mut self, prediction: PosePrediction) {
+        self.pose_prediction = prediction;
+    }
+
+    pub fn set_second_client(&mut self, policy: SecondClient) {
+        self.context
+            .set_exclusive_client(policy == SecondClient::Reject);
+    }
+
+    pub(crate) fn set_transport_dump(&mut self, dump: TransportDump) {
+        self.transport_dump = Some(dump);
+    }
+
+    pub fn requested_bitrate_bps(&self) -> Option<u64> {
+        if !self.connected {
+            return None;
+        }
+        self.context
+            .get_dynamic_encoder_params()
+            .map(|params| params.bitrate_bps as u64)
+            .filter(|bitrate_bps| *bitrate_bps > 0)
+    }
+
     pub fn ever_connected(&self) -> bool {
         self.ever_connected
     }
@@ -452,9 +1042,43 @@ impl AlvrVideoSink {
         self.shutdown_requested
     }
 
+    /// Whether the shutdown came from an ALVR restart, which the bridge
+    /// turns into a failed exit so launchd starts it again.
+    pub fn restart_requested(&self) -> bool {
+        self.restart_requested
+    }
+
+    /// Whether the Wine driver has paused submission because SteamVR went
+    /// idle. Resuming forces a keyframe with fresh parameter sets.
+    pub(crate) fn driver_paused(&self) -> bool {
+        self.driver_paused
+    }
+
+    /// Called when a signal stops the bridge: the tracking feedback mapping
+    /// is removed after the driver sees `shutdown`, instead of being kept for
+    /// a restart.
+    pub fn remove_shared_memory_on_drop(&mut self) {
+        self.tracking_feedback.remove_on_drop();
+    }
+
+    /// Tells the driver how the pipeline behind it is doing, so it can
+    /// throttle while there is no client or the encoder falls behind.
+    pub(crate) fn publish_bridge_stats(&mut self, stats: BridgeStats) {
+        self.tracking_feedback.publish_bridge_stats(stats);
+    }
+
+    /// The tracking feedback header as the driver would see it now.
+    pub(crate) fn shared_memory_snapshot(&self) -> String {
+        self.tracking_feedback.header_snapshot()
+    }
+
     pub fn connection_error(&self) -> Option<&str> {
         self.connection_error.as_deref()
     }
+
+    pub fn client_status(&self) -> Option<ClientStatus> {
+        self.client_status
+    }
 }
 
 fn map_pose_timestamp(
@@ -475,7 +1099,156 @@ fn map_pose_timestamp(
     mapped.max(previous_pose_timestamp)
 }
 
-fn ensure_native_session(layout: &Layout, width: u32, height: u32, fps: u32) -> Result<()> {
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct SessionEncodingSettings {
+    pub codec: Option<CodecType>,
+    pub fps: Option<u32>,
+    pub bitrate_bps: Option<u64>,
+    pub stream_size: Option<(u32, u32)>,
+    pub hdr: Option<bool>,
+}
+
+pub(crate) fn load_session_encoding_settings(
+    root: &Path,
+    native_width: u32,
+    native_height: u32,
+) -> Result<Option<SessionEncodingSettings>> {
+    read_session_encoding_settings(&Layout::new(root).session(), native_width, native_height)
+}
+
+/// The port server core streams on, from the saved session or ALVR's default.
+pub(crate) fn load_session_stream_port(root: &Path) -> Result<u16> {
+    let session = read_session(&Layout::new(root).session())?.unwrap_or_default();
+    Ok(session.to_settings().connection.stream_port)
+}
+
+fn read_session_encoding_settings(
+    session_path: &Path,
+    native_width: u32,
+    native_height: u32,
+) -> Result<Option<SessionEncodingSettings>> {
+    Ok(read_session(session_path)?.map(|session| {
+        session_encoding_settings(&session.to_settings(), native_width, native_height)
+    }))
+}
+
+fn read_session(session_path: &Path) -> Result<Option<SessionConfig>> {
+    let contents = match fs::read_to_string(session_path) {
+        Ok(contents) if !contents.trim().is_empty() => contents,
+        Ok(_) => return Ok(None),
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("failed to read {}", session_path.display()));
+        }
+    };
+    serde_json::from_str(&contents)
+        .with_context(|| format!("failed to parse {}", session_path.display()))
+        .map(Some)
+}
+
+pub(crate) struct SessionWatcher {
+    path: PathBuf,
+    native_width: u32,
+    native_height: u32,
+    modified: Option<SystemTime>,
+    current: SessionEncodingSettings,
+}
+
+impl SessionWatcher {
+    pub fn new(root: &Path, native_width: u32, native_height: u32) -> Result<Self> {
+        let path = Layout::new(root).session();
+        let modified = modified_time(&path);
+        let current =
+            read_session_encoding_settings(&path, native_width, native_height)?.unwrap_or_default();
+        Ok(Self {
+            path,
+            native_width,
+            native_height,
+            modified,
+            current,
+        })
+    }
+
+    /// Returns the previous and new settings when the session file changed
+    /// any encoding setting since the last poll.
+    pub fn poll(&mut self) -> Option<(SessionEncodingSettings, SessionEncodingSettings)> {
+        let modified = modified_time(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        let next =
+            match read_session_encoding_settings(&self.path, self.native_width, self.native_height)
+            {
+                Ok(next) => next?,
+                Err(error) => {
+                    // The dashboard may be mid-write; retry on the next poll.
+                    eprintln!("WARNING session_reload skipped error={error:#}");
+                    self.modified = None;
+                    return None;
+                }
+            };
+        (next != self.current).then(|| (std::mem::replace(&mut self.current, next), next))
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn session_encoding_settings(
+    settings: &Settings,
+    native_width: u32,
+    native_height: u32,
+) -> SessionEncodingSettings {
+    let video = &settings.video;
+    let native_eye_width = native_width / 2;
+    let (eye_width, eye_height) = match &video.transcoding_view_resolution {
+        FrameSize::Scale(scale) => (
+            (native_eye_width as f32 * scale) as u32,
+            (native_height as f32 * scale) as u32,
+        ),
+        FrameSize::Absolute { width, height } => (
+            *width,
+            height.unwrap_or_else(|| {
+                (u64::from(*width) * u64::from(native_height) / u64::from(native_eye_width.max(1)))
+                    as u32
+            }),
+        ),
+    };
+    // ALVR streams need a width divisible by 64 and a height divisible by 32.
+    let width = eye_width * 2 / 64 * 64;
+    let height = eye_height / 32 * 32;
+
+    SessionEncodingSettings {
+        codec: Some(video.preferred_codec),
+        fps: (video.preferred_fps >= 1.0).then(|| video.preferred_fps.round() as u32),
+        bitrate_bps: match &video.bitrate.mode {
+            BitrateMode::ConstantMbps(mbps) => Some(mbps.saturating_mul(1_000_000)),
+            BitrateMode::Adaptive { .. } => None,
+        },
+        stream_size: (width > 0 && height > 0).then_some((width, height)),
+        hdr: video.encoder_config.hdr.enable,
+    }
+}
+
+/// Returns the session's foveated encoding settings when `foveated` is set,
+/// since the client decompresses with exactly those.
+#[expect(clippy::too_many_arguments)]
+fn ensure_native_session(
+    layout: &Layout,
+    width: u32,
+    height: u32,
+    fps: u32,
+    codec: CodecType,
+    ten_bit: bool,
+    hdr: bool,
+    foveated: bool,
+    audio: &AudioDevices,
+) -> Result<Option<FoveatedEncodingConfig>> {
     let session_path = layout.session();
     let mut session = match fs::read_to_string(&session_path) {
         Ok(contents) if !contents.trim().is_empty() => serde_json::from_str(&contents)
@@ -490,26 +1263,53 @@ fn ensure_native_session(layout: &Layout, width: u32, height: u32, fps: u32) ->
         }
     };
 
-    if configure_native_session(&mut session, width, height, fps)? {
+    let audio_changed = configure_session_audio(&mut session, audio)?;
+    let stream_changed = configure_native_session(
+        &mut session,
+        width,
+        height,
+        fps,
+        codec,
+        ten_bit,
+        hdr,
+        foveated,
+    )?;
+    if stream_changed || audio_changed {
         let temporary_path = session_path.with_extension("json.macos-bridge.tmp");
         fs::write(&temporary_path, serde_json::to_vec_pretty(&session)?)
             .with_context(|| format!("failed to write {}", temporary_path.display()))?;
         fs::rename(&temporary_path, &session_path).with_context(|| {
             format!(
-                "failed to replace {} with the HEVC session",
-                session_path.display()
+                "failed to replace {} with the {} session",
+                session_path.display(),
+                codec_name(codec)
             )
         })?;
     }
 
-    Ok(())
+    if !foveated {
+        return Ok(None);
+    }
+    let session_config: SessionConfig = serde_json::from_value(session)
+        .context("failed to deserialize configured native session")?;
+    Ok(session_config
+        .to_settings()
+        .video
+        .foveated_encoding
+        .as_option()
+        .cloned())
 }
 
+#[expect(clippy::too_many_arguments)]
 fn configure_native_session(
     session: &mut Value,
     width: u32,
     height: u32,
     fps: u32,
+    codec: CodecType,
+    ten_bit: bool,
+    hdr: bool,
+    foveated: bool,
 ) -> Result<bool> {
     ensure!(
         width > 0 && width.is_multiple_of(64),
@@ -569,7 +1369,11 @@ fn configure_native_session(
         ),
         (
             "/session_settings/video/preferred_codec/variant",
-            Value::String("Hevc".into()),
+            serde_json::to_value(codec)?,
+        ),
+        (
+            "/session_settings/video/encoder_config/h264_profile/variant",
+            serde_json::to_value(H264Profile::High)?,
         ),
         (
             "/session_settings/video/transcoding_view_resolution/variant",
@@ -606,7 +1410,7 @@ fn configure_native_session(
         ("/session_settings/video/preferred_fps", Value::from(fps)),
         (
             "/session_settings/video/foveated_encoding/enabled",
-            Value::Bool(false),
+            Value::Bool(foveated),
         ),
         (
             "/session_settings/video/encoder_config/use_10bit/set",
@@ -614,7 +1418,7 @@ fn configure_native_session(
         ),
         (
             "/session_settings/video/encoder_config/use_10bit/content",
-            Value::Bool(false),
+            Value::Bool(ten_bit),
         ),
         (
             "/session_settings/video/encoder_config/encoding_gamma/set",
@@ -630,7 +1434,7 @@ fn configure_native_session(
         ),
         (
             "/session_settings/video/encoder_config/hdr/enable/content",
-            Value::Bool(false),
+            Value::Bool(hdr),
         ),
     ] {
         let target = session
@@ -659,26 +1463,155 @@ fn configure_native_session(
     Ok(*session != original_session)
 }
 
+fn configure_session_audio(session: &mut Value, audio: &AudioDevices) -> Result<bool> {
+    let mut edits = Vec::new();
+    if let Some(device) = &audio.game_audio {
+        edits.extend([
+            (
+                "/session_settings/audio/game_audio/enabled",
+                Value::Bool(true),
+            ),
+            (
+                "/session_settings/audio/game_audio/content/device/set",
+                Value::Bool(true),
+            ),
+            (
+                "/session_settings/audio/game_audio/content/device/content/variant",
+                Value::String("NameSubstring".into()),
+            ),
+            (
+                "/session_settings/audio/game_audio/content/device/content/NameSubstring",
+                Value::String(device.clone()),
+            ),
+            // Muting would silence the loopback device the game plays into.
+            (
+                "/session_settings/audio/game_audio/content/mute_when_streaming",
+                Value::Bool(false),
+            ),
+        ]);
+    }
+    if let Some(device) = &audio.microphone {
+        // One loopback device is both the sink ALVR plays into and the
+        // source games record from.
+        edits.extend([
+            (
+                "/session_settings/audio/microphone/enabled",
+                Value::Bool(true),
+            ),
+            (
+                "/session_settings/audio/microphone/content/devices/variant",
+                Value::String("Custom".into()),
+            ),
+            (
+                "/session_settings/audio/microphone/content/devices/Custom/sink/variant",
+                Value::String("NameSubstring".into()),
+            ),
+            (
+                "/session_settings/audio/microphone/content/devices/Custom/sink/NameSubstring",
+                Value::String(device.clone()),
+            ),
+            (
+                "/session_settings/audio/microphone/content/devices/Custom/source/variant",
+                Value::String("NameSubstring".into()),
+            ),
+            (
+                "/session_settings/audio/microphone/content/devices/Custom/source/NameSubstring",
+                Value::String(device.clone()),
+            ),
+        ]);
+    }
+    let original_session = session.clone();
+    for (path, value) in edits {
+        let target = session
+            .pointer_mut(path)
+            .with_context(|| format!("session is missing {path}"))?;
+        *target = value;
+    }
+
+    Ok(*session != original_session)
+}
+
+fn check_audio_devices(audio: &AudioDevices) -> Result<()> {
+    if let Some(name) = &audio.game_audio {
+        let device = alvr_audio::new_output(Some(&CustomAudioDeviceConfig::NameSubstring(
+            name.clone(),
+        )))
+        .with_context(|| {
+            format!(
+                "game audio device {name:?} is missing; install a loopback driver such as BlackHole"
+            )
+        })?;
+        let sample_rate = alvr_audio::input_sample_rate(&device)
+            .with_context(|| format!("game audio device {name:?} cannot be recorded"))?;
+        ensure!(
+            sample_rate == GAME_AUDIO_SAMPLE_RATE,
+            "game audio device {name:?} runs at {sample_rate} Hz but ALVR streams game audio at {GAME_AUDIO_SAMPLE_RATE} Hz; set it to 44.1 kHz in Audio MIDI Setup"
+        );
+    }
+    if let Some(name) = &audio.microphone {
+        ensure!(
+            audio.game_audio.as_ref() != Some(name),
+            "game audio and the microphone cannot share {name:?}; the headset would hear itself"
+        );
+        let device = CustomAudioDeviceConfig::NameSubstring(name.clone());
+        alvr_audio::new_virtual_microphone_pair(MicrophoneDevicesConfig::Custom {
+            sink: device.clone(),
+            source: device,
+        })
+        .with_context(|| {
+            format!(
+                "microphone device {name:?} needs both an output and an input; use a loopback driver such as BlackHole"
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+/// What server core settled on for one client connection. The size follows
+/// the session's transcoding resolution, shrunk by foveated encoding when it
+/// is on; the codec and H.264 profile follow the client's decoder
+/// capabilities, so they can differ from the session's preferred codec.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct NegotiatedStream {
+    size: (u32, u32),
+    codec: CodecType,
+    /// `High` unless the codec is H.264.
+    h264_profile: H264Profile,
+    refresh_rate: f32,
+    foveation: Option<Foveation>,
+}
+
 fn validate_stream_config(
     config: &ServerNegotiatedStreamingConfig,
     width: u32,
     height: u32,
-    fps: u32,
-) -> Result<()> {
+    ten_bit: bool,
+    hdr: bool,
+    foveation: Option<&FoveatedEncodingConfig>,
+) -> Result<NegotiatedStream> {
     let per_eye_width = width / 2;
     ensure!(
-        config.codec == CodecType::Hevc,
-        "ALVR negotiated {:?}, expected HEVC",
-        config.codec
+        config.codec != CodecType::AV1,
+        "ALVR negotiated AV1, which VideoToolbox cannot encode"
     );
     ensure!(
-        config.transcoding_view_resolution.x == per_eye_width
-            && config.transcoding_view_resolution.y == height,
-        "ALVR negotiated transcoding view {}x{}, expected {}x{}",
+        !ten_bit || config.codec == CodecType::Hevc,
+        "ALVR negotiated {} for a 10-bit native frame, which needs HEVC Main10",
+        codec_name(config.codec)
+    );
+    // SteamVR renders at the emulated size for its whole run, but the
+    // transcoding size follows the session each time a client connects.
+    let stream_width = config.transcoding_view_resolution.x * 2;
+    let stream_height = config.transcoding_view_resolution.y;
+    ensure!(
+        stream_width > 0
+            && stream_width.is_multiple_of(64)
+            && stream_height > 0
+            && stream_height.is_multiple_of(32),
+        "ALVR negotiated transcoding view {}x{}; the stream must be a positive multiple of 64x32",
         config.transcoding_view_resolution.x,
-        config.transcoding_view_resolution.y,
-        per_eye_width,
-        height
+        config.transcoding_view_resolution.y
     );
     ensure!(
         config.emulated_headset_view_resolution.x == per_eye_width
@@ -689,18 +1622,43 @@ fn validate_stream_config(
         per_eye_width,
         height
     );
+    // The client picks the supported rate nearest the session's preferred
+    // one, so the encoder and pacing follow whatever it reports.
     ensure!(
-        (config.refresh_rate - fps as f32).abs() < 0.1,
-        "ALVR negotiated {:.3} Hz, expected {fps} Hz",
-        config.refresh_rate
+        config.refresh_rate.is_finite() && REFRESH_RANGE.contains(&config.refresh_rate),
+        "ALVR negotiated {:.3} Hz, outside {}-{} Hz",
+        config.refresh_rate,
+        REFRESH_RANGE.start(),
+        REFRESH_RANGE.end()
     );
     ensure!(
-        !config.enable_foveated_encoding,
-        "ALVR negotiated foveated encoding for an unfoveated native frame"
+        config.enable_foveated_encoding == foveation.is_some(),
+        "ALVR negotiated {} for {} native frame",
+        if config.enable_foveated_encoding {
+            "foveated encoding"
+        } else {
+            "no foveated encoding"
+        },
+        if foveation.is_some() {
+            "a foveated"
+        } else {
+            "an unfoveated"
+        }
     );
+    let foveation = foveation
+        .map(|foveation| {
+            Foveation::new(
+                foveation,
+                config.transcoding_view_resolution.x,
+                config.transcoding_view_resolution.y,
+            )
+        })
+        .transpose()?;
     ensure!(
-        !config.use_10bit_encoder,
-        "ALVR negotiated 10-bit encoding for an 8-bit native frame"
+        config.use_10bit_encoder == ten_bit,
+        "ALVR negotiated {}-bit encoding for a {}-bit native frame",
+        if config.use_10bit_encoder { 10 } else { 8 },
+        if ten_bit { 10 } else { 8 }
     );
     ensure!(
         (config.encoding_gamma - 1.0).abs() < 0.001,
@@ -708,19 +1666,69 @@ fn validate_stream_config(
         config.encoding_gamma
     );
     ensure!(
-        !config.enable_hdr,
-        "ALVR negotiated HDR for an SDR native frame"
+        config.enable_hdr == hdr,
+        "ALVR negotiated {} for an {} native frame",
+        if config.enable_hdr { "HDR" } else { "SDR" },
+        if hdr { "HDR10" } else { "SDR" }
     );
-    Ok(())
+    Ok(NegotiatedStream {
+        size: foveation.map_or((stream_width, stream_height), |foveation| {
+            foveation.stream_size
+        }),
+        codec: config.codec,
+        h264_profile: if config.codec == CodecType::H264 {
+            config.h264_profile
+        } else {
+            H264Profile::High
+        },
+        refresh_rate: config.refresh_rate,
+        foveation,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use alvr_common::glam::UVec2;
-    use alvr_session::H264Profile;
+    use alvr_common::{glam::UVec2, settings_schema::Switch};
     use serde_json::json;
 
+    #[test]
+    fn dashboard_statistics_cover_one_window_and_keep_totals() {
+        let mut statistics = DashboardStatistics::new();
+        statistics.record(1_500_000, Duration::from_millis(4));
+        statistics.record(500_000, Duration::from_millis(6));
+
+        let summary = statistics.take_summary();
+        assert_eq!(summary.video_packets_total, 2);
+        assert_eq!(summary.video_mbytes_total, 2);
+        assert!((summary.encode_latency_ms - 5.0).abs() < 1e-3);
+
+        statistics.record(1_000_000, Duration::from_millis(2));
+        let summary = statistics.take_summary();
+        assert_eq!(summary.video_packets_total, 3);
+        assert_eq!(summary.video_mbytes_total, 3);
+        assert!((summary.encode_latency_ms - 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn dashboard_statistics_carry_the_headset_battery() {
+        let mut statistics = DashboardStatistics::new();
+        let summary = statistics.take_summary();
+        assert_eq!(summary.battery_hmd, 0);
+        assert!(!summary.hmd_plugged);
+
+        statistics.hmd_battery = Some(BatteryInfo {
+            device_id: *HEAD_ID,
+            gauge_value: 0.75,
+            is_plugged: true,
+        });
+        let summary = statistics.take_summary();
+        assert_eq!(summary.battery_hmd, 75);
+        assert!(summary.hmd_plugged);
+        // It stays until the next report instead of resetting each window.
+        assert_eq!(statistics.take_summary().battery_hmd, 75);
+    }
+
     #[test]
     fn configures_native_stream_without_replacing_other_session_data() {
         let mut session = serde_json::to_value(SessionConfig::default()).unwrap();
@@ -734,7 +1742,19 @@ mod tests {
             }
         });
 
-        assert!(configure_native_session(&mut session, 2752, 1792, 90).unwrap());
+        assert!(
+            configure_native_session(
+                &mut session,
+                2752,
+                1792,
+                90,
+                CodecType::Hevc,
+                false,
+                false,
+                false
+            )
+            .unwrap()
+        );
         assert_eq!(
             session.pointer("/session_settings/video/preferred_codec/variant"),
             Some(&Value::String("Hevc".into()))
@@ -800,7 +1820,239 @@ mod tests {
                 &session_config.to_settings(),
             )
         );
-        assert!(!configure_native_session(&mut session, 2752, 1792, 90).unwrap());
+        assert!(
+            !configure_native_session(
+                &mut session,
+                2752,
+                1792,
+                90,
+                CodecType::Hevc,
+                false,
+                false,
+                false
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn routes_game_audio_through_the_loopback_device() {
+        let mut session = serde_json::to_value(SessionConfig::default()).unwrap();
+        assert!(!configure_session_audio(&mut session, &AudioDevices::default()).unwrap());
+
+        let audio = AudioDevices {
+            game_audio: Some("BlackHole 2ch".into()),
+            microphone: None,
+        };
+        assert!(configure_session_audio(&mut session, &audio).unwrap());
+        assert!(!configure_session_audio(&mut session, &audio).unwrap());
+
+        let settings = serde_json::from_value::<SessionConfig>(session)
+            .unwrap()
+            .to_settings();
+        let Switch::Enabled(game_audio) = settings.audio.game_audio else {
+            panic!("game audio should be enabled");
+        };
+        assert!(matches!(
+            game_audio.device,
+            Some(CustomAudioDeviceConfig::NameSubstring(ref name)) if name == "BlackHole 2ch"
+        ));
+        assert!(!game_audio.mute_when_streaming);
+    }
+
+    #[test]
+    fn plays_the_headset_microphone_into_a_loopback_device() {
+        let mut session = serde_json::to_value(SessionConfig::default()).unwrap();
+        let audio = AudioDevices {
+            game_audio: None,
+            microphone: Some("BlackHole 16ch".into()),
+        };
+
+        assert!(configure_session_audio(&mut session, &audio).unwrap());
+
+        let settings = serde_json::from_value::<SessionConfig>(session)
+            .unwrap()
+            .to_settings();
+        let Switch::Enabled(microphone) = settings.audio.microphone else {
+            panic!("microphone should be enabled");
+        };
+        let MicrophoneDevicesConfig::Custom { sink, source } = microphone.devices else {
+            panic!("microphone should use custom devices");
+        };
+        for device in [sink, source] {
+            assert!(matches!(
+                device,
+                CustomAudioDeviceConfig::NameSubstring(ref name) if name == "BlackHole 16ch"
+            ));
+        }
+    }
+
+    #[test]
+    fn reads_encoding_settings_from_the_session() {
+        let mut settings = SessionConfig::default().to_settings();
+        settings.video.preferred_codec = CodecType::Hevc;
+        settings.video.preferred_fps = 72.0;
+        settings.video.bitrate.mode = BitrateMode::ConstantMbps(80);
+        settings.video.transcoding_view_resolution = FrameSize::Scale(0.5);
+
+        assert_eq!(
+            session_encoding_settings(&settings, 3664, 1920),
+            SessionEncodingSettings {
+                codec: Some(CodecType::Hevc),
+                fps: Some(72),
+                bitrate_bps: Some(80_000_000),
+                stream_size: Some((1792, 960)),
+                hdr: None,
+            }
+        );
+
+        settings.video.transcoding_view_resolution = FrameSize::Absolute {
+            width: 1376,
+            height: None,
+        };
+        assert_eq!(
+            session_encoding_settings(&settings, 3664, 1920).stream_size,
+            Some((2752, 1440))
+        );
+    }
+
+    #[test]
+    fn reports_session_encoding_changes_once() {
+        let root = std::env::temp_dir().join(format!(
+            "alvr-bridge-session-{}-{}",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        let path = Layout::new(&root).session();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut session = serde_json::to_value(SessionConfig::default()).unwrap();
+        session["session_settings"]["video"]["bitrate"]["mode"]["variant"] = json!("ConstantMbps");
+        session["session_settings"]["video"]["bitrate"]["mode"]["ConstantMbps"] = json!(30);
+        fs::write(&path, serde_json::to_vec(&session).unwrap()).unwrap();
+
+        let mut watcher = SessionWatcher::new(&root, 3664, 1920).unwrap();
+        assert_eq!(watcher.poll(), None);
+
+        session["session_settings"]["video"]["bitrate"]["mode"]["ConstantMbps"] = json!(40);
+        fs::write(&path, serde_json::to_vec(&session).unwrap()).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(2))
+            .unwrap();
+        let (previous, next) = watcher.poll().unwrap();
+        assert_eq!(previous.bitrate_bps, Some(30_000_000));
+        assert_eq!(next.bitrate_bps, Some(40_000_000));
+        assert_eq!(watcher.poll(), None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn configures_an_h264_high_profile_session() {
+        let mut session = serde_json::to_value(SessionConfig::default()).unwrap();
+
+        assert!(
+            configure_native_session(
+                &mut session,
+                2752,
+                1792,
+                90,
+                CodecType::H264,
+                false,
+                false,
+                false
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            session.pointer("/session_settings/video/preferred_codec/variant"),
+            Some(&Value::String("H264".into()))
+        );
+        assert_eq!(
+            session.pointer("/session_settings/video/encoder_config/h264_profile/variant"),
+            Some(&Value::String("High".into()))
+        );
+    }
+
+    #[test]
+    fn requests_and_validates_ten_bit_for_p010_streams() {
+        let mut session = serde_json::to_value(SessionConfig::default()).unwrap();
+
+        assert!(
+            configure_native_session(
+                &mut session,
+                2752,
+                1792,
+                90,
+                CodecType::Hevc,
+                true,
+                false,
+                false
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            session.pointer("/session_settings/video/encoder_config/use_10bit/content"),
+            Some(&Value::Bool(true))
+        );
+
+        let config = ServerNegotiatedStreamingConfig {
+            transcoding_view_resolution: UVec2::new(1376, 1792),
+            emulated_headset_view_resolution: UVec2::new(1376, 1792),
+            refresh_rate: 90.0,
+            enable_foveated_encoding: false,
+            codec: CodecType::Hevc,
+            h264_profile: H264Profile::High,
+            use_10bit_encoder: true,
+            encoding_gamma: 1.0,
+            enable_hdr: false,
+        };
+        validate_stream_config(&config, 2752, 1792, true, false, None).unwrap();
+        assert!(validate_stream_config(&config, 2752, 1792, false, false, None).is_err());
+    }
+
+    #[test]
+    fn requests_and_validates_hdr_for_hdr10_streams() {
+        let mut session = serde_json::to_value(SessionConfig::default()).unwrap();
+
+        assert!(
+            configure_native_session(
+                &mut session,
+                2752,
+                1792,
+                90,
+                CodecType::Hevc,
+                true,
+                true,
+                false
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            session.pointer("/session_settings/video/encoder_config/hdr/enable/content"),
+            Some(&Value::Bool(true))
+        );
+
+        let mut config = ServerNegotiatedStreamingConfig {
+            transcoding_view_resolution: UVec2::new(1376, 1792),
+            emulated_headset_view_resolution: UVec2::new(1376, 1792),
+            refresh_rate: 90.0,
+            enable_foveated_encoding: false,
+            codec: CodecType::Hevc,
+            h264_profile: H264Profile::High,
+            use_10bit_encoder: true,
+            encoding_gamma: 1.0,
+            enable_hdr: true,
+        };
+        validate_stream_config(&config, 2752, 1792, true, true, None).unwrap();
+        assert!(validate_stream_config(&config, 2752, 1792, true, false, None).is_err());
+        config.enable_hdr = false;
+        assert!(validate_stream_config(&config, 2752, 1792, true, true, None).is_err());
     }
 
     #[test]
@@ -817,9 +2069,143 @@ mod tests {
             enable_hdr: false,
         };
 
-        validate_stream_config(&config, 2752, 1792, 90).unwrap();
+        validate_stream_config(&config, 2752, 1792, false, false, None).unwrap();
+        config.codec = CodecType::AV1;
+        assert!(validate_stream_config(&config, 2752, 1792, false, false, None).is_err());
+        config.codec = CodecType::Hevc;
         config.enable_foveated_encoding = true;
-        assert!(validate_stream_config(&config, 2752, 1792, 90).is_err());
+        assert!(validate_stream_config(&config, 2752, 1792, false, false, None).is_err());
+    }
+
+    #[test]
+    fn accepts_a_renegotiated_transcoding_resolution() {
+        let mut config = ServerNegotiatedStreamingConfig {
+            transcoding_view_resolution: UVec2::new(1024, 1344),
+            emulated_headset_view_resolution: UVec2::new(1376, 1792),
+            refresh_rate: 90.0,
+            enable_foveated_encoding: false,
+            codec: CodecType::Hevc,
+            h264_profile: H264Profile::High,
+            use_10bit_encoder: false,
+            encoding_gamma: 1.0,
+            enable_hdr: false,
+        };
+
+        assert_eq!(
+            validate_stream_config(&config, 2752, 1792, false, false, None)
+                .unwrap()
+                .size,
+            (2048, 1344)
+        );
+        config.transcoding_view_resolution = UVec2::new(1000, 1344);
+        assert!(validate_stream_config(&config, 2752, 1792, false, false, None).is_err());
+        config.transcoding_view_resolution = UVec2::new(1024, 1344);
+        config.emulated_headset_view_resolution = UVec2::new(1024, 1344);
+        assert!(validate_stream_config(&config, 2752, 1792, false, false, None).is_err());
+    }
+
+    #[test]
+    fn follows_the_client_refresh_rate() {
+        let mut config = ServerNegotiatedStreamingConfig {
+            transcoding_view_resolution: UVec2::new(1376, 1792),
+            emulated_headset_view_resolution: UVec2::new(1376, 1792),
+            refresh_rate: 72.0,
+            enable_foveated_encoding: false,
+            codec: CodecType::Hevc,
+            h264_profile: H264Profile::High,
+            use_10bit_encoder: false,
+            encoding_gamma: 1.0,
+            enable_hdr: false,
+        };
+
+        for refresh_rate in [72.0, 90.0, 120.0] {
+            config.refresh_rate = refresh_rate;
+            assert_eq!(
+                validate_stream_config(&config, 2752, 1792, false, false, None)
+                    .unwrap()
+                    .refresh_rate,
+                refresh_rate
+            );
+        }
+        config.refresh_rate = f32::NAN;
+        assert!(validate_stream_config(&config, 2752, 1792, false, false, None).is_err());
+        config.refresh_rate = 0.0;
+        assert!(validate_stream_config(&config, 2752, 1792, false, false, None).is_err());
+    }
+
+    #[test]
+    fn follows_the_negotiated_codec_and_h264_profile() {
+        let mut config = ServerNegotiatedStreamingConfig {
+            transcoding_view_resolution: UVec2::new(1376, 1792),
+            emulated_headset_view_resolution: UVec2::new(1376, 1792),
+            refresh_rate: 90.0,
+            enable_foveated_encoding: false,
+            codec: CodecType::H264,
+            h264_profile: H264Profile::High,
+            use_10bit_encoder: false,
+            encoding_gamma: 1.0,
+            enable_hdr: false,
+        };
+
+        config.h264_profile = H264Profile::Baseline;
+        let negotiated = validate_stream_config(&config, 2752, 1792, false, false, None).unwrap();
+        assert_eq!(
+            (negotiated.codec, negotiated.h264_profile),
+            (CodecType::H264, H264Profile::Baseline)
+        );
+        config.use_10bit_encoder = true;
+        assert!(validate_stream_config(&config, 2752, 1792, true, false, None).is_err());
+
+        config.codec = CodecType::Hevc;
+        let negotiated = validate_stream_config(&config, 2752, 1792, true, false, None).unwrap();
+        assert_eq!(negotiated.h264_profile, H264Profile::High);
+    }
+
+    #[test]
+    fn encodes_the_compressed_size_for_foveated_streams() {
+        let mut session = serde_json::to_value(SessionConfig::default()).unwrap();
+        configure_native_session(
+            &mut session,
+            2752,
+            1792,
+            90,
+            CodecType::Hevc,
+            false,
+            false,
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            session.pointer("/session_settings/video/foveated_encoding/enabled"),
+            Some(&Value::Bool(true))
+        );
+        let settings = serde_json::from_value::<SessionConfig>(session)
+            .unwrap()
+            .to_settings();
+        let foveation = settings.video.foveated_encoding.as_option().unwrap();
+
+        let mut config = ServerNegotiatedStreamingConfig {
+            transcoding_view_resolution: UVec2::new(1376, 1792),
+            emulated_headset_view_resolution: UVec2::new(1376, 1792),
+            refresh_rate: 90.0,
+            enable_foveated_encoding: true,
+            codec: CodecType::Hevc,
+            h264_profile: H264Profile::High,
+            use_10bit_encoder: false,
+            encoding_gamma: 1.0,
+            enable_hdr: false,
+        };
+        let negotiated =
+            validate_stream_config(&config, 2752, 1792, false, false, Some(foveation)).unwrap();
+        assert_eq!(negotiated.size, (1664, 928));
+        assert!(negotiated.foveation.is_some());
+
+        // A client without foveated decoding gets a plain stream from server
+        // core, which the bridge cannot produce once it has asked for one.
+        config.enable_foveated_encoding = false;
+        assert!(
+            validate_stream_config(&config, 2752, 1792, false, false, Some(foveation)).is_err()
+        );
     }
 
     #[test]
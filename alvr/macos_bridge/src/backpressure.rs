@@ -0,0 +1,88 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Measures how long frames wait in VideoToolbox, so the bridge can skip a
+/// frame it knows would only queue behind them. Left alone, a backlog holds
+/// pool leases until the loop runs out and Wine finds no free slot, which
+/// drops whichever frames happen to arrive then. Skipping at the bridge
+/// releases the slot at once, keeps the encoder on the newest frames, and
+/// counts the drop where it happened.
+pub(crate) struct EncodeQueue {
+    /// In frame intervals, so the limit follows the client's refresh rate.
+    limit_frames: u32,
+    submitted: VecDeque<Instant>,
+    max_delay: Duration,
+}
+
+impl EncodeQueue {
+    pub fn new(limit_frames: u32) -> Self {
+        Self {
+            limit_frames,
+            submitted: VecDeque::new(),
+            max_delay: Duration::ZERO,
+        }
+    }
+
+    pub fn submitted(&mut self, now: Instant) {
+        self.submitted.push_back(now);
+    }
+
+    /// Encoders return frames in submission order, so whatever the session
+    /// no longer holds is the oldest. A replaced session holds nothing.
+    fn sync(&mut self, pending: usize) {
+        let completed = self.submitted.len().saturating_sub(pending);
+        self.submitted.drain(..completed);
+    }
+
+    /// How long the oldest frame still in the encoder has waited.
+    pub fn delay(&self, now: Instant) -> Duration {
+        self.submitted.front().map_or(Duration::ZERO, |since| {
+            now.saturating_duration_since(*since)
+        })
+    }
+
+    /// Whether a frame arriving now would wait behind more than the limit,
+    /// given how many frames the session still holds. Records the delay for
+    /// the cadence line either way.
+    pub fn is_behind(&mut self, now: Instant, frame_interval: Duration, pending: usize) -> bool {
+        self.sync(pending);
+        let delay = self.delay(now);
+        self.max_delay = self.max_delay.max(delay);
+        !delay.is_zero() && delay > frame_interval * self.limit_frames
+    }
+
+    /// The longest delay seen since the last call.
+    pub fn take_max_delay(&mut self) -> Duration {
+        std::mem::take(&mut self.max_delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_only_while_the_oldest_frame_is_past_the_limit() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let interval = Duration::from_millis(10);
+        let mut queue = EncodeQueue::new(3);
+
+        assert!(!queue.is_behind(at(0), interval, 0));
+        queue.submitted(at(0));
+        queue.submitted(at(10));
+        queue.submitted(at(20));
+        assert!(!queue.is_behind(at(30), interval, 3));
+        assert!(queue.is_behind(at(31), interval, 3));
+
+        assert!(!queue.is_behind(at(31), interval, 2));
+        assert_eq!(queue.delay(at(31)), Duration::from_millis(21));
+        assert_eq!(queue.take_max_delay(), Duration::from_millis(31));
+        assert_eq!(queue.take_max_delay(), Duration::ZERO);
+
+        assert!(!queue.is_behind(at(1_000), interval, 0));
+        assert_eq!(queue.delay(at(1_000)), Duration::ZERO);
+    }
+}
@@ -0,0 +1,341 @@
+use crate::{
+    EncodedFrame, FrameMetadata, NativeVideoEncoder, NativeVideoEncoderConfig, ProbeConfig,
+    SurfaceFormat, SurfacePool, VideoEncoder,
+    encoder::{codec_name, periodic_keyframe},
+    latency::{LatencySummary, LatencyWindow},
+    metal::MetalConverter,
+    native_source::{DEFAULT_SOURCE_SLOTS, NativeSource, SourceFormat},
+    output_buffers::OUTPUT_BUFFERS,
+};
+use alvr_common::ViewParams;
+use alvr_session::{CodecType, H264Profile};
+use anyhow::{Result, bail, ensure};
+use std::{
+    env,
+    ffi::c_void,
+    ptr::NonNull,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+unsafe extern "C" {
+    fn IOSurfaceLock(surface: *mut c_void, options: u32, seed: *mut u32) -> i32;
+    fn IOSurfaceUnlock(surface: *mut c_void, options: u32, seed: *mut u32) -> i32;
+    fn IOSurfaceGetBaseAddress(surface: *mut c_void) -> *mut c_void;
+    fn IOSurfaceGetBytesPerRow(surface: *mut c_void) -> usize;
+}
+
+const OUTPUT_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy)]
+struct BenchProfile {
+    name: &'static str,
+    codec: CodecType,
+    h264_profile: H264Profile,
+    format: SurfaceFormat,
+}
+
+const PROFILES: [BenchProfile; 5] = [
+    BenchProfile {
+        name: "hevc_main",
+        codec: CodecType::Hevc,
+        h264_profile: H264Profile::High,
+        format: SurfaceFormat::Nv12,
+    },
+    BenchProfile {
+        name: "hevc_main10",
+        codec: CodecType::Hevc,
+        h264_profile: H264Profile::High,
+        format: SurfaceFormat::P010,
+    },
+    BenchProfile {
+        name: "h264_high",
+        codec: CodecType::H264,
+        h264_profile: H264Profile::High,
+        format: SurfaceFormat::Nv12,
+    },
+    BenchProfile {
+        name: "h264_main",
+        codec: CodecType::H264,
+        h264_profile: H264Profile::Main,
+        format: SurfaceFormat::Nv12,
+    },
+    BenchProfile {
+        name: "h264_baseline",
+        codec: CodecType::H264,
+        h264_profile: H264Profile::Baseline,
+        format: SurfaceFormat::Nv12,
+    },
+];
+
+#[derive(Debug, Clone, Copy)]
+struct BenchResult {
+    frames: u64,
+    elapsed: Duration,
+    bytes: u64,
+    convert: LatencySummary,
+    convert_gpu: LatencySummary,
+    encode: LatencySummary,
+}
+
+impl BenchResult {
+    /// Frames the whole pipeline finished per second with nothing pacing it,
+    /// which is the highest frame rate this Mac could sustain.
+    fn max_fps(&self) -> f64 {
+        self.frames as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Runs synthetic frames through the Metal conversion and VideoToolbox as
+/// fast as they go, once per codec and profile, and prints the conversion
+/// time, the encode latency distribution, and the frame rate each sustained.
+/// The size, frame count, bitrate and rate control come from the usual
+/// settings; `ALVR_BRIDGE_CODEC` limits the run to that codec's profiles.
+/// A pipeline depth above one runs each profile at depth one as well, and
+/// reports the encode latency the deeper pipeline added.
+pub fn run_bench() -> Result<()> {
+    let config = ProbeConfig::from_env()?;
+    ensure!(
+        config.frame_count > 0,
+        "bench needs a frame count; pass --frames or set ALVR_BRIDGE_FRAMES"
+    );
+    let codec = env::var_os("ALVR_BRIDGE_CODEC").map(|_| config.codec);
+    let profiles = bench_profiles(codec);
+    if profiles.is_empty() {
+        bail!(
+            "no bench profiles for {}; VideoToolbox cannot encode it",
+            codec_name(config.codec)
+        );
+    }
+    println!(
+        "bench started resolution={}x{} frames={} bitrate_bps={} pipeline_depth={} profiles={}",
+        config.width,
+        config.height,
+        config.frame_count,
+        config.bitrate_bps,
+        config.pipeline_depth,
+        profiles.len()
+    );
+    let depths = if config.pipeline_depth > 1 {
+        vec![1, config.pipeline_depth]
+    } else {
+        vec![1]
+    };
+    let mut fastest: Option<(&str, u32, f64)> = None;
+    for profile in profiles {
+        let mut baseline: Option<Duration> = None;
+        for &depth in &depths {
+            let result = match bench_profile(&config, &profile, depth) {
+                Ok(result) => result,
+                Err(error) => {
+                    println!(
+                        "bench profile={} pipeline_depth={depth} skipped error={error:#}",
+                        profile.name
+                    );
+                    continue;
+                }
+            };
+            let added_latency = baseline
+                .map(|baseline| {
+                    format!(
+                        " added_latency_us={}",
+                        result.encode.p50.saturating_sub(baseline).as_micros()
+                    )
+                })
+                .unwrap_or_default();
+            println!(
+                "bench profile={} pipeline_depth={depth} frames={} elapsed_ms={} convert_p50_us={} convert_p99_us={} convert_gpu_p50_us={} encode_p50_us={} encode_p99_us={} encode_max_us={} mean_frame_bytes={} max_fps={:.1}{added_latency}",
+                profile.name,
+                result.frames,
+                result.elapsed.as_millis(),
+                result.convert.p50.as_micros(),
+                result.convert.p99.as_micros(),
+                result.convert_gpu.p50.as_micros(),
+                result.encode.p50.as_micros(),
+                result.encode.p99.as_micros(),
+                result.encode.max.as_micros(),
+                result.bytes / result.frames.max(1),
+                result.max_fps()
+            );
+            if depth == 1 {
+                baseline = Some(result.encode.p50);
+            }
+            if fastest.is_none_or(|(_, _, fps)| result.max_fps() > fps) {
+                fastest = Some((profile.name, depth, result.max_fps()));
+            }
+        }
+    }
+    let Some((name, depth, fps)) = fastest else {
+        bail!("every bench profile failed");
+    };
+    println!("bench finished fastest={name} pipeline_depth={depth} max_fps={fps:.1}");
+    Ok(())
+}
+
+fn bench_profiles(codec: Option<CodecType>) -> Vec<BenchProfile> {
+    PROFILES
+        .into_iter()
+        .filter(|profile| codec.is_none_or(|codec| profile.codec == codec))
+        .collect()
+}
+
+fn bench_profile(
+    config: &ProbeConfig,
+    profile: &BenchProfile,
+    pipeline_depth: u32,
+) -> Result<BenchResult> {
+    let (width, height) = (config.width, config.height);
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)?
+        .as_nanos()
+        .max(1) as u64;
+    let source = NativeSource::new(
+        &format!("com.alvr.bench.{}.{nonce}", std::process::id()),
+        nonce,
+        width,
+        height,
+        profile.format,
+        SourceFormat::Bgra,
+        DEFAULT_SOURCE_SLOTS,
+    )?;
+    let mut slots = Vec::new();
+    for slot in 0..DEFAULT_SOURCE_SLOTS {
+        let surface = source.surface(slot)?;
+        fill_synthetic_frame(surface, height, slot)?;
+        slots.push(surface);
+    }
+    let mut converter = MetalConverter::new()?;
+    converter.set_color_matrix(config.color.matrix);
+    let pool = SurfacePool::with_color_space(
+        width,
+        height,
+        config.buffer_count,
+        profile.format,
+        config.color,
+    )?;
+    let (mut encoder, _) = NativeVideoEncoder::new(NativeVideoEncoderConfig {
+        codec: profile.codec,
+        h264_profile: profile.h264_profile,
+        format: profile.format,
+        hdr: None,
+        pipeline_depth,
+        ..config.encoder_config()
+    })?;
+
+    let mut convert = LatencyWindow::default();
+    let mut convert_gpu = LatencyWindow::default();
+    let mut encode = LatencyWindow::default();
+    let mut bytes = 0u64;
+    let mut record = |outputs: Vec<EncodedFrame>| {
+        for output in outputs {
+            encode.record(output.encode_latency);
+            bytes += (output.nal_data.len()
+                + output.decoder_config_nals.as_ref().map_or(0, Vec::len))
+                as u64;
+            OUTPUT_BUFFERS.recycle_frame(output);
+        }
+    };
+    let frame_interval = Duration::from_secs_f64(1.0 / f64::from(config.fps));
+    let started = Instant::now();
+    for frame_id in 0..config.frame_count {
+        let lease = loop {
+            if let Some(lease) = pool.try_acquire()? {
+                break lease;
+            }
+            ensure!(
+                encoder.pending_count() > 0,
+                "surface pool is exhausted with no encodes pending"
+            );
+            record(encoder.wait_for_output(OUTPUT_TIMEOUT)?);
+        };
+        let slot = slots[(frame_id % slots.len() as u64) as usize];
+        let timing = converter.convert_raw(slot, lease.cv_pixel_buffer(), width, height)?;
+        convert.record(timing.wall);
+        convert_gpu.record(timing.gpu);
+        // Timestamps advance at the configured rate so rate control
+        // budgets bits as it would in a session.
+        let video_timestamp = frame_interval.mul_f64(frame_id as f64);
+        let metadata = FrameMetadata {
+            frame_id,
+            stream_epoch: 0,
+            video_timestamp,
+            pose_timestamp: video_timestamp,
+            global_view_params: [ViewParams::DUMMY; 2],
+        };
+        let force_keyframe = periodic_keyframe(config.keyframe_interval, frame_id);
+        record(encoder.submit(lease, metadata, force_keyframe)?);
+    }
+    record(encoder.finish()?);
+    let elapsed = started.elapsed();
+
+    Ok(BenchResult {
+        frames: config.frame_count,
+        elapsed,
+        bytes,
+        convert: convert.take(),
+        convert_gpu: convert_gpu.take(),
+        encode: encode.take(),
+    })
+}
+
+/// Fills a source slot with a gradient under per-slot noise, so every frame
+/// differs from the last and the encoder has detail to spend bits on, as a
+/// game frame would give it.
+fn fill_synthetic_frame(surface: NonNull<c_void>, height: u32, slot: u32) -> Result<()> {
+    let surface = surface.as_ptr();
+    ensure!(
+        unsafe { IOSurfaceLock(surface, 0, std::ptr::null_mut()) } == 0,
+        "failed to lock the bench source surface"
+    );
+    let base = unsafe { IOSurfaceGetBaseAddress(surface) }.cast::<u8>();
+    let row_bytes = unsafe { IOSurfaceGetBytesPerRow(surface) };
+    for y in 0..height as usize {
+        let row = unsafe { std::slice::from_raw_parts_mut(base.add(y * row_bytes), row_bytes) };
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            pixel.copy_from_slice(&synthetic_pixel(x, y, slot));
+        }
+    }
+    unsafe { IOSurfaceUnlock(surface, 0, std::ptr::null_mut()) };
+    Ok(())
+}
+
+fn synthetic_pixel(x: usize, y: usize, slot: u32) -> [u8; 4] {
+    let mut hash = (x as u32).wrapping_mul(0x9e37_79b1)
+        ^ (y as u32).wrapping_mul(0x85eb_ca77)
+        ^ slot.wrapping_mul(0xc2b2_ae3d);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x2c1b_3c6d);
+    hash ^= hash >> 12;
+    let noise = (hash & 0x3f) as u8;
+    let shift = slot as usize * 48;
+    [
+        ((x + shift) / 4) as u8 ^ noise,
+        ((y + shift) / 4) as u8 ^ noise,
+        ((x + y) / 8) as u8,
+        0xff,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_the_profiles_to_the_chosen_codec() {
+        assert_eq!(bench_profiles(None).len(), PROFILES.len());
+        let names = |codec| {
+            bench_profiles(Some(codec))
+                .into_iter()
+                .map(|profile| profile.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(CodecType::Hevc), ["hevc_main", "hevc_main10"]);
+        assert_eq!(names(CodecType::H264).len(), 3);
+        assert!(names(CodecType::AV1).is_empty());
+    }
+
+    #[test]
+    fn gives_each_source_slot_different_content() {
+        assert_ne!(synthetic_pixel(100, 50, 0), synthetic_pixel(100, 50, 1));
+        assert_eq!(synthetic_pixel(100, 50, 2)[3], 0xff);
+    }
+}
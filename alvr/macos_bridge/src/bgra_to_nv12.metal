@@ -7,6 +7,35 @@ struct ConversionParams {
     uint output_eye_width;
     uint source_height;
     uint output_height;
+    float code_scale;
+    float code_to_unorm;
+    float sharpen_strength;
+    // 1 selects contrast-adaptive sharpening, 0 the plain unsharp mask.
+    uint sharpen_adaptive;
+    // Kr and Kb of the YCbCr matrix, and the code ranges it maps into:
+    // 16 + 219 / 224 for limited range, 0 + 255 / 255 for full range.
+    float matrix_kr;
+    float matrix_kb;
+    float luma_offset;
+    float luma_range;
+    float chroma_range;
+    // ALVR's foveated encoding, in the client's aligned values. `foveated`
+    // is 0 when the output is a plain scale of the source.
+    uint foveated;
+    float eye_ratio_x;
+    float eye_ratio_y;
+    float center_size_x;
+    float center_size_y;
+    float center_shift_x;
+    float center_shift_y;
+    float edge_ratio_x;
+    float edge_ratio_y;
+    // The colour transparent pixels are composited over, for a client's
+    // chroma-key passthrough. `alpha_keyed` is 0 when alpha is ignored.
+    uint alpha_keyed;
+    float alpha_key_r;
+    float alpha_key_g;
+    float alpha_key_b;
 };
 
 constexpr sampler bilinear_sampler(
@@ -18,20 +47,50 @@ static float scaled_center(uint output, uint source_extent, uint output_extent)
     return (float(output) + 0.5f) * float(source_extent) / float(output_extent);
 }
 
+// ALVR's axis-aligned foveated compression, as in its
+// CompressAxisAlignedPixelShader: maps a point in the compressed eye to the
+// point of the full eye it shows, both as fractions of the eye. The centre
+// keeps full resolution; each edge is squeezed by its edge ratio, blending
+// linearly into the centre. Points in the alignment padding map past the
+// eye and end up clamped to its border.
+static float2 foveated_eye_position(float2 compressed, constant ConversionParams &params) {
+    float2 center_size = float2(params.center_size_x, params.center_size_y);
+    float2 center_shift = float2(params.center_shift_x, params.center_shift_y);
+    float2 edge_ratio = float2(params.edge_ratio_x, params.edge_ratio_y);
+    float2 uv = compressed / float2(params.eye_ratio_x, params.eye_ratio_y);
+
+    float2 c0 = (1.0f - center_size) * 0.5f;
+    float2 c1 = (edge_ratio - 1.0f) * c0 * (center_shift + 1.0f) / edge_ratio;
+    float2 c2 = (edge_ratio - 1.0f) * center_size + 1.0f;
+    float2 lo_bound = c0 * (center_shift + 1.0f) / c2;
+    float2 hi_bound = c0 * (center_shift - 1.0f) / c2 + 1.0f;
+
+    float2 center = uv * c2 / edge_ratio + c1;
+    float2 left = mix(uv * c2, center, uv / lo_bound);
+    float2 right = mix((uv - 1.0f) * c2 + 1.0f, center, (1.0f - uv) / (1.0f - hi_bound));
+    return select(select(center, left, uv < lo_bound), right, uv > hi_bound);
+}
+
 static float2 source_position(
     uint output_x,
     uint output_y,
     constant ConversionParams &params) {
     uint eye = output_x / params.output_eye_width;
     uint eye_x = output_x - eye * params.output_eye_width;
-    float source_eye_x = clamp(
-        scaled_center(eye_x, params.source_eye_width, params.output_eye_width),
-        0.5f,
-        float(params.source_eye_width) - 0.5f);
-    float source_y = clamp(
-        scaled_center(output_y, params.source_height, params.output_height),
-        0.5f,
-        float(params.source_height) - 0.5f);
+    float2 source_eye;
+    if (params.foveated != 0) {
+        float2 compressed = float2(
+            (float(eye_x) + 0.5f) / float(params.output_eye_width),
+            (float(output_y) + 0.5f) / float(params.output_height));
+        source_eye = foveated_eye_position(compressed, params) *
+                     float2(float(params.source_eye_width), float(params.source_height));
+    } else {
+        source_eye = float2(
+            scaled_center(eye_x, params.source_eye_width, params.output_eye_width),
+            scaled_center(output_y, params.source_height, params.output_height));
+    }
+    float source_eye_x = clamp(source_eye.x, 0.5f, float(params.source_eye_width) - 0.5f);
+    float source_y = clamp(source_eye.y, 0.5f, float(params.source_height) - 0.5f);
     return float2(float(eye * params.source_eye_width) + source_eye_x, source_y);
 }
 
@@ -40,14 +99,107 @@ static float3 sample_rgb(
     uint output_x,
     uint output_y,
     constant ConversionParams &params) {
-    return source.sample(
-        bilinear_sampler,
-        source_position(output_x, output_y, params)).rgb;
+    float4 texel = source.sample(bilinear_sampler, source_position(output_x, output_y, params));
+    if (params.alpha_keyed == 0) {
+        return texel.rgb;
+    }
+    float3 key = float3(params.alpha_key_r, params.alpha_key_g, params.alpha_key_b);
+    return mix(key, texel.rgb, texel.a);
+}
+
+static float quantize(float code, constant ConversionParams &params) {
+    return rint(code * params.code_scale) * params.code_to_unorm;
+}
+
+static float unit_luma(float3 rgb, constant ConversionParams &params) {
+    float kr = params.matrix_kr;
+    float kb = params.matrix_kb;
+    return dot(rgb, float3(kr, 1.0f - kr - kb, kb));
+}
+
+static float luma_code(float3 rgb, constant ConversionParams &params) {
+    return params.luma_offset + params.luma_range * unit_luma(rgb, params);
+}
+
+// The four output neighbours of `output`, clamped to its own eye so the
+// sharpening kernel never reaches across the stereo boundary.
+static void eye_neighbours(
+    uint2 output,
+    constant ConversionParams &params,
+    thread uint2 *neighbours) {
+    uint eye = output.x / params.output_eye_width;
+    int eye_start = int(eye * params.output_eye_width);
+    int eye_end = eye_start + int(params.output_eye_width) - 1;
+    int last_row = int(params.output_height) - 1;
+    int2 position = int2(output);
+    int2 offsets[4] = {int2(-1, 0), int2(1, 0), int2(0, -1), int2(0, 1)};
+    for (uint index = 0; index < 4; index++) {
+        int2 neighbour = position + offsets[index];
+        neighbours[index] = uint2(
+            clamp(neighbour.x, eye_start, eye_end),
+            clamp(neighbour.y, 0, last_row));
+    }
+}
+
+// Contrast-adaptive sharpening after AMD's FidelityFX CAS, on luma
+// normalized to its range. The negative lobe on the four neighbours
+// shrinks as their span nears black or white, so edges that already have
+// contrast, like text, gain little halo while soft detail is lifted.
+static float adaptive_sharpen(
+    float center,
+    thread const float *neighbours,
+    constant ConversionParams &params) {
+    float unit_center = (center - params.luma_offset) / params.luma_range;
+    float minimum = unit_center;
+    float maximum = unit_center;
+    float neighbour_sum = 0.0f;
+    for (uint index = 0; index < 4; index++) {
+        float neighbour = (neighbours[index] - params.luma_offset) / params.luma_range;
+        minimum = min(minimum, neighbour);
+        maximum = max(maximum, neighbour);
+        neighbour_sum += neighbour;
+    }
+    float amplitude =
+        sqrt(saturate(min(minimum, 1.0f - maximum) / max(maximum, 1.0e-5f)));
+    float weight = -amplitude / mix(8.0f, 5.0f, params.sharpen_strength);
+    float sharpened = (unit_center + weight * neighbour_sum) / (1.0f + 4.0f * weight);
+    return params.luma_offset + params.luma_range * saturate(sharpened);
+}
+
+// Sharpens luma in 8-bit code values and keeps it inside luma range. The
+// unsharp mask moves the centre away from the mean of its four neighbours
+// by `sharpen_strength`. Chroma is left alone.
+static float sharpen(
+    float center,
+    thread const float *neighbours,
+    constant ConversionParams &params) {
+    if (params.sharpen_adaptive != 0) {
+        return adaptive_sharpen(center, neighbours, params);
+    }
+    float neighbour_sum = neighbours[0] + neighbours[1] + neighbours[2] + neighbours[3];
+    return clamp(
+        center + params.sharpen_strength * (center - neighbour_sum * 0.25f),
+        params.luma_offset,
+        params.luma_offset + params.luma_range);
 }
 
-static float luma(float3 rgb) {
-    return (16.0f + 219.0f * dot(rgb, float3(0.2126f, 0.7152f, 0.0722f)))
-        / 255.0f;
+static float luma(
+    texture2d<float, access::sample> source,
+    uint2 output,
+    float3 rgb,
+    constant ConversionParams &params) {
+    float center = luma_code(rgb, params);
+    if (params.sharpen_strength <= 0.0f) {
+        return quantize(center, params);
+    }
+    uint2 neighbours[4];
+    eye_neighbours(output, params, neighbours);
+    float neighbour_codes[4];
+    for (uint index = 0; index < 4; index++) {
+        neighbour_codes[index] =
+            luma_code(sample_rgb(source, neighbours[index].x, neighbours[index].y, params), params);
+    }
+    return quantize(sharpen(center, neighbour_codes, params), params);
 }
 
 kernel void bgra_to_nv12(
@@ -67,17 +219,80 @@ kernel void bgra_to_nv12(
     float3 rgb_01 = sample_rgb(source, output_origin.x, output_origin.y + 1, params);
     float3 rgb_11 = sample_rgb(source, output_origin.x + 1, output_origin.y + 1, params);
 
-    destination_y.write(float4(luma(rgb_00), 0.0f, 0.0f, 1.0f), output_origin);
-    destination_y.write(
-        float4(luma(rgb_10), 0.0f, 0.0f, 1.0f), output_origin + uint2(1, 0));
-    destination_y.write(
-        float4(luma(rgb_01), 0.0f, 0.0f, 1.0f), output_origin + uint2(0, 1));
-    destination_y.write(
-        float4(luma(rgb_11), 0.0f, 0.0f, 1.0f), output_origin + uint2(1, 1));
+    float3 rgb_block[4] = {rgb_00, rgb_10, rgb_01, rgb_11};
+    for (uint index = 0; index < 4; index++) {
+        uint2 output = output_origin + uint2(index % 2, index / 2);
+        destination_y.write(
+            float4(luma(source, output, rgb_block[index], params), 0.0f, 0.0f, 1.0f), output);
+    }
 
     float3 rgb = (rgb_00 + rgb_10 + rgb_01 + rgb_11) * 0.25f;
-    float y = dot(rgb, float3(0.2126f, 0.7152f, 0.0722f));
-    float cb = (128.0f + 112.0f * (rgb.b - y) / (1.0f - 0.0722f)) / 255.0f;
-    float cr = (128.0f + 112.0f * (rgb.r - y) / (1.0f - 0.2126f)) / 255.0f;
+    float y = unit_luma(rgb, params);
+    float chroma_half_range = params.chroma_range * 0.5f;
+    float cb = quantize(
+        128.0f + chroma_half_range * (rgb.b - y) / (1.0f - params.matrix_kb), params);
+    float cr = quantize(
+        128.0f + chroma_half_range * (rgb.r - y) / (1.0f - params.matrix_kr), params);
     destination_uv.write(float4(cb, cr, 0.0f, 1.0f), chroma_position);
 }
+
+// The chroma sample for an output 2x2 block, in source chroma-plane pixels.
+// It sits at the centre of the block's four luma positions and is clamped to
+// the half-sample inside its own eye, so the eyes do not bleed into each
+// other even when the source eye width is odd.
+static float2 source_chroma_position(uint2 output_origin, constant ConversionParams &params) {
+    float2 luma_center = (source_position(output_origin.x, output_origin.y, params) +
+                          source_position(output_origin.x + 1, output_origin.y + 1, params)) *
+                         0.5f;
+    uint eye = output_origin.x / params.output_eye_width;
+    float eye_start = float(eye * params.source_eye_width) * 0.5f;
+    float eye_end = float((eye + 1) * params.source_eye_width) * 0.5f;
+    return float2(
+        clamp(luma_center.x * 0.5f, eye_start + 0.5f, eye_end - 0.5f),
+        clamp(
+            luma_center.y * 0.5f,
+            0.5f,
+            max(float(params.source_height) * 0.5f - 0.5f, 0.5f)));
+}
+
+static float sample_y(
+    texture2d<float, access::sample> source_y,
+    uint2 output,
+    constant ConversionParams &params) {
+    return source_y.sample(bilinear_sampler, source_position(output.x, output.y, params)).r;
+}
+
+kernel void nv12_scale(
+    texture2d<float, access::sample> source_y [[texture(0)]],
+    texture2d<float, access::write> destination_y [[texture(1)]],
+    texture2d<float, access::write> destination_uv [[texture(2)]],
+    texture2d<float, access::sample> source_uv [[texture(3)]],
+    constant ConversionParams &params [[buffer(0)]],
+    uint2 chroma_position [[thread_position_in_grid]]) {
+    uint output_width = params.output_eye_width * 2;
+    uint2 output_origin = chroma_position * 2;
+    if (output_origin.x >= output_width || output_origin.y >= params.output_height) {
+        return;
+    }
+
+    for (uint dy = 0; dy < 2; dy++) {
+        for (uint dx = 0; dx < 2; dx++) {
+            uint2 output = output_origin + uint2(dx, dy);
+            float y = sample_y(source_y, output, params);
+            if (params.sharpen_strength > 0.0f) {
+                uint2 neighbours[4];
+                eye_neighbours(output, params, neighbours);
+                float neighbour_codes[4];
+                for (uint index = 0; index < 4; index++) {
+                    neighbour_codes[index] = sample_y(source_y, neighbours[index], params) * 255.0f;
+                }
+                y = sharpen(y * 255.0f, neighbour_codes, params) / 255.0f;
+            }
+            destination_y.write(float4(y, 0.0f, 0.0f, 1.0f), output);
+        }
+    }
+    float2 uv = source_uv.sample(
+        bilinear_sampler,
+        source_chroma_position(output_origin, params)).rg;
+    destination_uv.write(float4(uv, 0.0f, 1.0f), chroma_position);
+}
@@ -0,0 +1,67 @@
+use crate::{
+    CadenceReport, NativeCadenceReport, NativeProbeSummary, NativeSourceConfig, ProbeConfig,
+    ProbeSummary, run_native_source_probe, run_surface_probe,
+};
+use anyhow::Result;
+use std::{env, fmt};
+
+/// One bridge run, selected by `ALVR_BRIDGE_INPUT`.
+#[derive(Debug, Clone)]
+pub enum Bridge {
+    Surface(ProbeConfig),
+    IoSurface(NativeSourceConfig),
+}
+
+impl Bridge {
+    pub fn from_env() -> Result<Self> {
+        Ok(match env::var("ALVR_BRIDGE_INPUT").as_deref() {
+            Ok("iosurface") => Self::IoSurface(NativeSourceConfig::from_env()?),
+            _ => Self::Surface(ProbeConfig::from_env()?),
+        })
+    }
+
+    pub fn run(self, mut report: impl FnMut(BridgeReport)) -> Result<BridgeSummary> {
+        Ok(match self {
+            Self::Surface(config) => {
+                BridgeSummary::Surface(run_surface_probe(config, |cadence| {
+                    report(BridgeReport::Surface(cadence))
+                })?)
+            }
+            Self::IoSurface(config) => {
+                BridgeSummary::IoSurface(run_native_source_probe(config, |cadence| {
+                    report(BridgeReport::IoSurface(cadence))
+                })?)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BridgeReport {
+    Surface(CadenceReport),
+    IoSurface(NativeCadenceReport),
+}
+
+impl fmt::Display for BridgeReport {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Surface(report) => report.fmt(formatter),
+            Self::IoSurface(report) => report.fmt(formatter),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BridgeSummary {
+    Surface(ProbeSummary),
+    IoSurface(NativeProbeSummary),
+}
+
+impl fmt::Display for BridgeSummary {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Surface(summary) => summary.fmt(formatter),
+            Self::IoSurface(summary) => summary.fmt(formatter),
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use crate::filter::Nv12Frame;
+use anyhow::{Context, Result, ensure};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const CAPTURE_MAGIC: [u8; 8] = *b"ALVRNV12";
+const HEADER_BYTES: u64 = 16;
+
+/// Writes converted NV12 frames, the exact encoder input, to a capture file:
+/// a 16-byte header (magic, width, height) followed by each frame's video
+/// timestamp and id and its tightly packed luma and chroma planes.
+pub(crate) struct FrameCaptureWriter {
+    path: PathBuf,
+    file: BufWriter<File>,
+    width: usize,
+    height: usize,
+    frames: u64,
+}
+
+impl FrameCaptureWriter {
+    pub fn create(path: &Path, width: u32, height: u32) -> Result<Self> {
+        ensure!(
+            width.is_multiple_of(2) && height.is_multiple_of(2),
+            "frame capture needs even dimensions"
+        );
+        let mut file = BufWriter::new(
+            File::create(path)
+                .with_context(|| format!("failed to create frame capture {}", path.display()))?,
+        );
+        file.write_all(&CAPTURE_MAGIC)?;
+        file.write_all(&width.to_le_bytes())?;
+        file.write_all(&height.to_le_bytes())?;
+        println!(
+            "frame_capture started path={} shape={width}x{height}",
+            path.display()
+        );
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+            width: width as usize,
+            height: height as usize,
+            frames: 0,
+        })
+    }
+
+    pub fn write(
+        &mut self,
+        frame: &mut Nv12Frame<'_>,
+        frame_id: u64,
+        video_timestamp: Duration,
+    ) -> Result<()> {
+        ensure!(
+            frame.width == self.width && frame.height == self.height,
+            "frame capture is {}x{} but the frame is {}x{}",
+            self.width,
+            self.height,
+            frame.width,
+            frame.height
+        );
+        let timestamp_ns = u64::try_from(video_timestamp.as_nanos()).unwrap_or(u64::MAX);
+        self.file.write_all(&timestamp_ns.to_le_bytes())?;
+        self.file.write_all(&frame_id.to_le_bytes())?;
+        for row in 0..self.height {
+            let start = row * frame.luma_row_bytes;
+            self.file
+                .write_all(&frame.luma[start..start + self.width])?;
+        }
+        for row in 0..self.height / 2 {
+            let start = row * frame.chroma_row_bytes;
+            self.file
+                .write_all(&frame.chroma[start..start + self.width])?;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+impl Drop for FrameCaptureWriter {
+    fn drop(&mut self) {
+        if let Err(error) = self.file.flush() {
+            eprintln!(
+                "WARNING frame_capture flush failed path={} error={error}",
+                self.path.display()
+            );
+        }
+        println!(
+            "frame_capture finished path={} frames={}",
+            self.path.display(),
+            self.frames
+        );
+    }
+}
+
+/// Plays a capture back in a loop. Timestamps are rebased to the first frame
+/// and keep increasing across loops, so a replay paces exactly like the run
+/// that recorded it.
+pub(crate) struct FrameCaptureReader {
+    path: PathBuf,
+    file: BufReader<File>,
+    width: usize,
+    height: usize,
+    first_timestamp: Option<Duration>,
+    last_timestamp: Duration,
+    loop_offset: Duration,
+    frame_interval: Duration,
+}
+
+impl FrameCaptureReader {
+    pub fn open(path: &Path, fps: u32) -> Result<Self> {
+        let mut file = BufReader::new(
+            File::open(path)
+                .with_context(|| format!("failed to open frame capture {}", path.display()))?,
+        );
+        let mut header = [0; HEADER_BYTES as usize];
+        file.read_exact(&mut header)
+            .with_context(|| format!("frame capture {} has no header", path.display()))?;
+        ensure!(
+            header[..8] == CAPTURE_MAGIC,
+            "{} is not a bridge frame capture",
+            path.display()
+        );
+        let width = u32::from_le_bytes(header[8..12].try_into()?) as usize;
+        let height = u32::from_le_bytes(header[12..16].try_into()?) as usize;
+        ensure!(
+            width > 0 && height > 0 && width.is_multiple_of(2) && height.is_multiple_of(2),
+            "frame capture {} has invalid dimensions {width}x{height}",
+            path.display()
+        );
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+            width,
+            height,
+            first_timestamp: None,
+            last_timestamp: Duration::ZERO,
+            loop_offset: Duration::ZERO,
+            frame_interval: Duration::from_secs_f64(1.0 / f64::from(fps.max(1))),
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height as u32
+    }
+
+    /// Reads the next frame header and returns its offset from the start of
+    /// the replay. The pixels follow through `read_pixels`.
+    pub fn next_timestamp(&mut self) -> Result<Duration> {
+        let mut header = [0; 16];
+        if let Err(error) = self.file.read_exact(&mut header) {
+            ensure!(
+                error.kind() == ErrorKind::UnexpectedEof && self.first_timestamp.is_some(),
+                "failed to read frame capture {}: {error}",
+                self.path.display()
+            );
+            self.file.seek(SeekFrom::Start(HEADER_BYTES))?;
+            self.file.read_exact(&mut header)?;
+            self.loop_offset = self.last_timestamp + self.frame_interval;
+        }
+        let timestamp = Duration::from_nanos(u64::from_le_bytes(header[..8].try_into()?));
+        let first_timestamp = *self.first_timestamp.get_or_insert(timestamp);
+        let offset = self.loop_offset + timestamp.saturating_sub(first_timestamp);
+        self.last_timestamp = offset;
+        Ok(offset)
+    }
+
+    pub fn read_pixels(&mut self, frame: &mut Nv12Frame<'_>) -> Result<()> {
+        ensure!(
+            frame.width == self.width && frame.height == self.height,
+            "frame capture is {}x{} but the surface is {}x{}",
+            self.width,
+            self.height,
+            frame.width,
+            frame.height
+        );
+        for row in 0..self.height {
+            let start = row * frame.luma_row_bytes;
+            self.file
+                .read_exact(&mut frame.luma[start..start + self.width])?;
+        }
+        for row in 0..self.height / 2 {
+            let start = row * frame.chroma_row_bytes;
+            self.file
+                .read_exact(&mut frame.chroma[start..start + self.width])
+                .with_context(|| format!("frame capture {} is truncated", self.path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn planes(width: usize, height: usize, value: u8) -> (Vec<u8>, Vec<u8>) {
+        (
+            vec![value; (width + 4) * height],
+            vec![value / 2; (width + 4) * height / 2],
+        )
+    }
+
+    fn frame<'a>(width: usize, height: usize, planes: &'a mut (Vec<u8>, Vec<u8>)) -> Nv12Frame<'a> {
+        Nv12Frame {
+            width,
+            height,
+            luma: &mut planes.0,
+            luma_row_bytes: width + 4,
+            chroma: &mut planes.1,
+            chroma_row_bytes: width + 4,
+        }
+    }
+
+    #[test]
+    fn replays_frames_in_a_loop_with_rebased_timestamps() {
+        let path = std::env::temp_dir().join(format!(
+            "alvr-bridge-capture-{}-{}.nv12",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        let mut writer = FrameCaptureWriter::create(&path, 4, 2).unwrap();
+        for (index, value) in [40, 80].into_iter().enumerate() {
+            let mut source = planes(4, 2, value);
+            writer
+                .write(
+                    &mut frame(4, 2, &mut source),
+                    index as u64,
+                    Duration::from_millis(500 + index as u64 * 10),
+                )
+                .unwrap();
+        }
+        drop(writer);
+
+        let mut reader = FrameCaptureReader::open(&path, 100).unwrap();
+        assert_eq!((reader.width(), reader.height()), (4, 2));
+        let mut timestamps = Vec::new();
+        let mut lumas = Vec::new();
+        for _ in 0..3 {
+            timestamps.push(reader.next_timestamp().unwrap());
+            let mut target = planes(4, 2, 14);
+            reader.read_pixels(&mut frame(4, 2, &mut target)).unwrap();
+            assert_eq!(&target.0[4..8], [14; 4], "row padding must be left alone");
+            lumas.push(target.0[0]);
+        }
+
+        assert_eq!(
+            timestamps,
+            [
+                Duration::ZERO,
+                Duration::from_millis(10),
+                Duration::from_millis(20)
+            ]
+        );
+        assert_eq!(lumas, [40, 80, 40]);
+        fs::remove_file(path).unwrap();
+    }
+}
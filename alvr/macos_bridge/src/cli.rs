@@ -0,0 +1,363 @@
+use crate::service::SERVICE_DEFAULTS;
+use anyhow::{Context, Result, bail};
+use std::{env, ffi::OsString};
+
+const VALUE_FLAGS: [(&str, &str); 43] = [
+    ("--input", "ALVR_BRIDGE_INPUT"),
+    ("--codec", "ALVR_BRIDGE_CODEC"),
+    ("--encoder", "ALVR_BRIDGE_ENCODER"),
+    ("--bit-depth", "ALVR_BRIDGE_BIT_DEPTH"),
+    ("--color-matrix", "ALVR_BRIDGE_COLOR_MATRIX"),
+    ("--color-range", "ALVR_BRIDGE_COLOR_RANGE"),
+    ("--bitrate", "ALVR_BRIDGE_BITRATE_BPS"),
+    ("--rate-control", "ALVR_BRIDGE_RATE_CONTROL"),
+    ("--fps", "ALVR_BRIDGE_FPS"),
+    ("--width", "ALVR_BRIDGE_WIDTH"),
+    ("--height", "ALVR_BRIDGE_HEIGHT"),
+    ("--keyframe-interval", "ALVR_BRIDGE_KEYFRAME_INTERVAL"),
+    ("--keyframe-interval-ms", "ALVR_BRIDGE_KEYFRAME_INTERVAL_MS"),
+    ("--pipeline-depth", "ALVR_BRIDGE_PIPELINE_DEPTH"),
+    ("--vt-properties", "ALVR_BRIDGE_VT_PROPERTIES"),
+    ("--frames", "ALVR_BRIDGE_FRAMES"),
+    ("--buffer-count", "ALVR_BRIDGE_BUFFER_COUNT"),
+    ("--filters", "ALVR_BRIDGE_FILTERS"),
+    ("--root", "ALVR_BRIDGE_ROOT"),
+    ("--tracking-shm", "ALVR_BRIDGE_TRACKING_SHM"),
+    ("--tracking-shm-name", "ALVR_BRIDGE_TRACKING_SHM_NAME"),
+    ("--tracking-session", "ALVR_BRIDGE_TRACKING_SESSION"),
+    ("--producer-timeout", "ALVR_BRIDGE_PRODUCER_TIMEOUT_SECS"),
+    ("--frame-timeout", "ALVR_BRIDGE_FRAME_TIMEOUT_SECS"),
+    ("--client-timeout", "ALVR_BRIDGE_CLIENT_TIMEOUT_SECS"),
+    ("--pose-timeout", "ALVR_BRIDGE_POSE_TIMEOUT_SECS"),
+    ("--encoder-stall", "ALVR_BRIDGE_ENCODER_STALL_SECS"),
+    ("--max-encode-queue", "ALVR_BRIDGE_MAX_ENCODE_QUEUE_FRAMES"),
+    ("--peak-bitrate", "ALVR_BRIDGE_PEAK_BITRATE_PERCENT"),
+    ("--peak-window-ms", "ALVR_BRIDGE_PEAK_WINDOW_MS"),
+    ("--pose-prediction", "ALVR_BRIDGE_POSE_PREDICTION"),
+    ("--second-client", "ALVR_BRIDGE_SECOND_CLIENT"),
+    ("--game-audio-device", "ALVR_BRIDGE_GAME_AUDIO_DEVICE"),
+    ("--microphone-device", "ALVR_BRIDGE_MICROPHONE_DEVICE"),
+    ("--record", "ALVR_BRIDGE_RECORD"),
+    ("--restream-port", "ALVR_BRIDGE_RESTREAM_PORT"),
+    ("--transport-dump", "ALVR_BRIDGE_TRANSPORT_DUMP"),
+    ("--capture", "ALVR_BRIDGE_CAPTURE"),
+    ("--replay", "ALVR_BRIDGE_REPLAY"),
+    ("--metrics-port", "ALVR_BRIDGE_METRICS_PORT"),
+    ("--log-level", "ALVR_BRIDGE_LOG_LEVEL"),
+    ("--log-format", "ALVR_BRIDGE_LOG_FORMAT"),
+    ("--trace", "ALVR_BRIDGE_TRACE_FILE"),
+];
+
+const NUMERIC_FLAGS: [&str; 19] = [
+    "--bitrate",
+    "--fps",
+    "--width",
+    "--height",
+    "--keyframe-interval",
+    "--keyframe-interval-ms",
+    "--pipeline-depth",
+    "--frames",
+    "--buffer-count",
+    "--producer-timeout",
+    "--frame-timeout",
+    "--client-timeout",
+    "--pose-timeout",
+    "--encoder-stall",
+    "--max-encode-queue",
+    "--peak-bitrate",
+    "--peak-window-ms",
+    "--metrics-port",
+    "--restream-port",
+];
+
+pub const USAGE: &str = "\
+usage: alvr_macos_bridge [options]
+       alvr_macos_bridge --service [options]
+       alvr_macos_bridge install-service [options]
+       alvr_macos_bridge uninstall-service
+       alvr_macos_bridge doctor [options]
+       alvr_macos_bridge bench [--resolution <w>x<h>] [--frames <count>] [options]
+       alvr_macos_bridge --status [--json]
+
+options (each sets the named variable unless it is already in the environment):
+  --input <surface|iosurface>     ALVR_BRIDGE_INPUT
+  --codec <hevc|h264|av1>         ALVR_BRIDGE_CODEC
+  --encoder <backend>             ALVR_BRIDGE_ENCODER
+  --bit-depth <8|10>              ALVR_BRIDGE_BIT_DEPTH
+  --color-matrix <709|601>        ALVR_BRIDGE_COLOR_MATRIX
+  --color-range <limited|full>    ALVR_BRIDGE_COLOR_RANGE
+  --bitrate <bps>                 ALVR_BRIDGE_BITRATE_BPS
+  --rate-control <mode>           ALVR_BRIDGE_RATE_CONTROL
+  --fps <fps>                     ALVR_BRIDGE_FPS
+  --width <pixels>                ALVR_BRIDGE_WIDTH
+  --height <pixels>               ALVR_BRIDGE_HEIGHT
+  --resolution <w>x<h>            ALVR_BRIDGE_WIDTH and ALVR_BRIDGE_HEIGHT
+  --keyframe-interval <frames>    ALVR_BRIDGE_KEYFRAME_INTERVAL
+  --keyframe-interval-ms <ms>     ALVR_BRIDGE_KEYFRAME_INTERVAL_MS
+  --pipeline-depth <frames>       ALVR_BRIDGE_PIPELINE_DEPTH
+  --vt-properties <key=value,...> ALVR_BRIDGE_VT_PROPERTIES
+  --frames <count>                ALVR_BRIDGE_FRAMES
+  --buffer-count <count>          ALVR_BRIDGE_BUFFER_COUNT
+  --filters <list>                ALVR_BRIDGE_FILTERS
+  --root <dir>                    ALVR_BRIDGE_ROOT
+  --tracking-shm <path>           ALVR_BRIDGE_TRACKING_SHM
+  --tracking-shm-name </name>     ALVR_BRIDGE_TRACKING_SHM_NAME
+  --tracking-session <id>         ALVR_BRIDGE_TRACKING_SESSION
+  --producer-timeout <seconds>    ALVR_BRIDGE_PRODUCER_TIMEOUT_SECS
+  --frame-timeout <seconds>       ALVR_BRIDGE_FRAME_TIMEOUT_SECS
+  --client-timeout <seconds>      ALVR_BRIDGE_CLIENT_TIMEOUT_SECS
+  --pose-timeout <seconds>        ALVR_BRIDGE_POSE_TIMEOUT_SECS
+  --encoder-stall <seconds>       ALVR_BRIDGE_ENCODER_STALL_SECS
+  --max-encode-queue <frames>     ALVR_BRIDGE_MAX_ENCODE_QUEUE_FRAMES
+  --peak-bitrate <percent>        ALVR_BRIDGE_PEAK_BITRATE_PERCENT
+  --peak-window-ms <ms>           ALVR_BRIDGE_PEAK_WINDOW_MS
+  --pose-prediction <off|auto|ms> ALVR_BRIDGE_POSE_PREDICTION
+  --second-client <policy>        ALVR_BRIDGE_SECOND_CLIENT
+  --game-audio-device <name>      ALVR_BRIDGE_GAME_AUDIO_DEVICE
+  --microphone-device <name>      ALVR_BRIDGE_MICROPHONE_DEVICE
+  --record <path>                 ALVR_BRIDGE_RECORD
+  --restream-port <port>          ALVR_BRIDGE_RESTREAM_PORT
+  --transport-dump <path>         ALVR_BRIDGE_TRANSPORT_DUMP
+  --capture <path>                ALVR_BRIDGE_CAPTURE
+  --replay <path>                 ALVR_BRIDGE_REPLAY
+  --metrics-port <port>           ALVR_BRIDGE_METRICS_PORT
+  --log-level <error|warn|info>   ALVR_BRIDGE_LOG_LEVEL
+  --log-format <text|json>        ALVR_BRIDGE_LOG_FORMAT
+  --trace <path>                  ALVR_BRIDGE_TRACE_FILE
+  --connect                       ALVR_BRIDGE_CONNECT=1
+  --test-pattern                  ALVR_BRIDGE_TEST_PATTERN=1
+  --preview                       ALVR_BRIDGE_PREVIEW=1
+  --service                       run as the launchd job, defaulting to the
+                                  IOSurface producer, a real client, and no
+                                  frame limit
+  -h, --help                      print this help";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliCommand {
+    Run,
+    Service,
+    InstallService,
+    UninstallService,
+    Status { json: bool },
+    Doctor,
+    Bench,
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cli {
+    pub command: CliCommand,
+    pub env: Vec<(&'static str, String)>,
+}
+
+impl Cli {
+    pub fn parse(args: Vec<OsString>) -> Result<Self> {
+        let mut args = pico_args::Arguments::from_vec(args);
+        if args.contains(["-h", "--help"]) {
+            return Ok(Self {
+                command: CliCommand::Help,
+                env: Vec::new(),
+            });
+        }
+        let subcommand = args.subcommand().context("invalid subcommand")?;
+        let status = args.contains("--status");
+        let service = args.contains("--service");
+        let json = args.contains("--json");
+
+        let mut overrides = Vec::new();
+        for (flag, name) in VALUE_FLAGS {
+            let Some(value) = args
+                .opt_value_from_str::<_, String>(flag)
+                .with_context(|| format!("invalid {flag}"))?
+            else {
+                continue;
+            };
+            if NUMERIC_FLAGS.contains(&flag) && value.parse::<u64>().is_err() {
+                bail!("invalid {flag}: expected a non-negative integer, got {value:?}");
+            }
+            overrides.push((name, value));
+        }
+        if let Some(resolution) = args
+            .opt_value_from_str::<_, String>("--resolution")
+            .context("invalid --resolution")?
+        {
+            let Some((width, height)) = resolution.split_once('x').filter(|(width, height)| {
+                width.parse::<u32>().is_ok() && height.parse::<u32>().is_ok()
+            }) else {
+                bail!("invalid --resolution: expected <width>x<height>, got {resolution:?}");
+            };
+            if overrides
+                .iter()
+                .any(|(name, _)| matches!(*name, "ALVR_BRIDGE_WIDTH" | "ALVR_BRIDGE_HEIGHT"))
+            {
+                bail!("--resolution replaces --width and --height; pass one or the other");
+            }
+            overrides.push(("ALVR_BRIDGE_WIDTH", width.into()));
+            overrides.push(("ALVR_BRIDGE_HEIGHT", height.into()));
+        }
+        if args.contains("--connect") {
+            overrides.push(("ALVR_BRIDGE_CONNECT", "1".into()));
+        }
+        if args.contains("--test-pattern") {
+            overrides.push(("ALVR_BRIDGE_TEST_PATTERN", "1".into()));
+        }
+        if args.contains("--preview") {
+            overrides.push(("ALVR_BRIDGE_PREVIEW", "1".into()));
+        }
+
+        let remaining = args.finish();
+        if !remaining.is_empty() {
+            bail!("unexpected arguments {remaining:?}\n{USAGE}");
+        }
+        if json && !status {
+            bail!("--json only applies to --status");
+        }
+        if status && service {
+            bail!("--status and --service are exclusive");
+        }
+
+        let command = match subcommand.as_deref() {
+            None if status => CliCommand::Status { json },
+            None if service => CliCommand::Service,
+            None => CliCommand::Run,
+            Some(_) if status || service => {
+                bail!("subcommands take neither --status nor --service")
+            }
+            Some("install-service") => CliCommand::InstallService,
+            Some("uninstall-service") if overrides.is_empty() => CliCommand::UninstallService,
+            Some("uninstall-service") => bail!("uninstall-service takes no options"),
+            Some("doctor") => CliCommand::Doctor,
+            Some("bench") => CliCommand::Bench,
+            Some(other) => bail!("unknown subcommand {other:?}\n{USAGE}"),
+        };
+        if command == CliCommand::Service {
+            for (name, value) in SERVICE_DEFAULTS {
+                if !overrides.iter().any(|(set, _)| *set == name) {
+                    overrides.push((name, value.into()));
+                }
+            }
+        }
+
+        Ok(Self {
+            command,
+            env: overrides,
+        })
+    }
+
+    /// Overrides whose variable is not already set, so the environment wins
+    /// over the command line.
+    pub fn unset_env(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        self.env
+            .iter()
+            .filter(|(name, _)| env::var_os(name).is_none())
+            .map(|(name, value)| (*name, value.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli> {
+        Cli::parse(args.iter().map(OsString::from).collect())
+    }
+
+    #[test]
+    fn maps_flags_to_bridge_variables() {
+        let cli = parse(&[
+            "--codec",
+            "h264",
+            "--bitrate",
+            "30000000",
+            "--fps",
+            "72",
+            "--keyframe-interval",
+            "144",
+            "--tracking-shm",
+            "/tmp/bridge-a.shm",
+            "--connect",
+        ])
+        .unwrap();
+
+        assert_eq!(cli.command, CliCommand::Run);
+        assert_eq!(
+            cli.env,
+            [
+                ("ALVR_BRIDGE_CODEC", "h264".to_string()),
+                ("ALVR_BRIDGE_BITRATE_BPS", "30000000".to_string()),
+                ("ALVR_BRIDGE_FPS", "72".to_string()),
+                ("ALVR_BRIDGE_KEYFRAME_INTERVAL", "144".to_string()),
+                ("ALVR_BRIDGE_TRACKING_SHM", "/tmp/bridge-a.shm".to_string()),
+                ("ALVR_BRIDGE_CONNECT", "1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_status_queries() {
+        assert_eq!(
+            parse(&["--status", "--json"]).unwrap().command,
+            CliCommand::Status { json: true }
+        );
+        assert_eq!(parse(&["-h"]).unwrap().command, CliCommand::Help);
+        assert!(parse(&["--json"]).is_err());
+    }
+
+    #[test]
+    fn parses_service_commands() {
+        let cli = parse(&["--service", "--frames", "900"]).unwrap();
+        assert_eq!(cli.command, CliCommand::Service);
+        assert_eq!(
+            cli.env,
+            [
+                ("ALVR_BRIDGE_FRAMES", "900".to_string()),
+                ("ALVR_BRIDGE_INPUT", "iosurface".to_string()),
+                ("ALVR_BRIDGE_CONNECT", "1".to_string()),
+                ("ALVR_BRIDGE_PRODUCER_TIMEOUT_SECS", "86400".to_string()),
+            ]
+        );
+
+        let cli = parse(&["install-service", "--codec", "h264"]).unwrap();
+        assert_eq!(cli.command, CliCommand::InstallService);
+        assert_eq!(cli.env, [("ALVR_BRIDGE_CODEC", "h264".to_string())]);
+        assert_eq!(
+            parse(&["uninstall-service"]).unwrap().command,
+            CliCommand::UninstallService
+        );
+        assert!(parse(&["uninstall-service", "--fps", "90"]).is_err());
+        assert!(parse(&["install-service", "--service"]).is_err());
+        assert!(parse(&["--status", "--service"]).is_err());
+        assert!(parse(&["reinstall-service"]).is_err());
+    }
+
+    #[test]
+    fn parses_the_doctor_with_run_options() {
+        let cli = parse(&["doctor", "--codec", "h264"]).unwrap();
+        assert_eq!(cli.command, CliCommand::Doctor);
+        assert_eq!(cli.env, [("ALVR_BRIDGE_CODEC", "h264".to_string())]);
+        assert!(parse(&["doctor", "--service"]).is_err());
+    }
+
+    #[test]
+    fn splits_the_bench_resolution_into_width_and_height() {
+        let cli = parse(&["bench", "--resolution", "4000x2000", "--frames", "1000"]).unwrap();
+        assert_eq!(cli.command, CliCommand::Bench);
+        assert_eq!(
+            cli.env,
+            [
+                ("ALVR_BRIDGE_FRAMES", "1000".to_string()),
+                ("ALVR_BRIDGE_WIDTH", "4000".to_string()),
+                ("ALVR_BRIDGE_HEIGHT", "2000".to_string()),
+            ]
+        );
+        assert!(parse(&["bench", "--resolution", "4k"]).is_err());
+        assert!(parse(&["--resolution", "4000x2000", "--width", "3664"]).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_and_malformed_arguments() {
+        assert!(parse(&["--bitrate", "fast"]).is_err());
+        assert!(parse(&["--fps"]).is_err());
+        assert!(parse(&["--resolution", "4k"]).is_err());
+    }
+}
@@ -0,0 +1,302 @@
+use std::{
+    collections::VecDeque,
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+/// Readings kept for one fit, about three seconds at 90 Hz.
+const WINDOW_LEN: usize = 256;
+/// Real clocks disagree by far less than this. A steeper fit comes from
+/// queueing delay rather than drift.
+const MAX_DRIFT_PPM: f64 = 500.0;
+/// A fit over less source time than this cannot tell drift from jitter.
+const MIN_DRIFT_SPAN: Duration = Duration::from_secs(1);
+/// Tracking timestamps a frame can be attributed to, newest last.
+const RECENT_TRACKING_LEN: usize = 64;
+/// Frames assigned a wire timestamp that have not been sent yet.
+const PENDING_LEN: usize = 64;
+
+/// Maps one clock onto another from pairs of readings, each taken as a
+/// message crosses from the source side to the local side. Every pair is late
+/// by however long its message queued, so the map follows the lower envelope:
+/// it anchors on the smallest offset in the newer half of the window, and the
+/// slope from the smallest offset in the older half corrects for the two
+/// clocks running at slightly different rates.
+#[derive(Debug, Default)]
+pub(crate) struct ClockSync {
+    readings: VecDeque<(Duration, Duration)>,
+}
+
+impl ClockSync {
+    /// Records that `source` was read no later than `local`. A source clock
+    /// that steps backwards, as a relaunched producer's does, starts over.
+    pub fn observe(&mut self, source: Duration, local: Duration) {
+        if self.readings.back().is_some_and(|(last, _)| source < *last) {
+            self.readings.clear();
+        }
+        self.readings.push_back((source, local));
+        if self.readings.len() > WINDOW_LEN {
+            self.readings.pop_front();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.readings.clear();
+    }
+
+    pub fn to_local(&self, source: Duration) -> Option<Duration> {
+        let ((anchor_source, anchor_local), slope) = self.fit()?;
+        Some(from_nanos(
+            nanos(anchor_local) + (nanos(source) - nanos(anchor_source)) * slope,
+        ))
+    }
+
+    pub fn to_source(&self, local: Duration) -> Option<Duration> {
+        let ((anchor_source, anchor_local), slope) = self.fit()?;
+        Some(from_nanos(
+            nanos(anchor_source) + (nanos(local) - nanos(anchor_local)) / slope,
+        ))
+    }
+
+    /// How much faster the local clock runs, in parts per million.
+    pub fn drift_ppm(&self) -> f64 {
+        self.fit().map_or(0.0, |(_, slope)| (slope - 1.0) * 1e6)
+    }
+
+    fn fit(&self) -> Option<((Duration, Duration), f64)> {
+        let lowest = |range: Range<usize>| {
+            self.readings
+                .range(range)
+                .min_by(|a, b| offset(**a).total_cmp(&offset(**b)))
+                .copied()
+        };
+        let half = self.readings.len() / 2;
+        let newer = lowest(half..self.readings.len())?;
+        let Some(older) = lowest(0..half).filter(|older| newer.0 >= older.0 + MIN_DRIFT_SPAN)
+        else {
+            return Some((newer, 1.0));
+        };
+        let slope = (nanos(newer.1) - nanos(older.1)) / (nanos(newer.0) - nanos(older.0));
+        let max_drift = MAX_DRIFT_PPM / 1e6;
+        Some((newer, slope.clamp(1.0 - max_drift, 1.0 + max_drift)))
+    }
+}
+
+/// Picks the timestamp each frame goes out with. ALVR keys its statistics
+/// by the tracking timestamp a frame was rendered for, so a frame rendered
+/// with a tracking sample's pose takes that sample's timestamp. Any other
+/// frame is placed on the tracking timeline by way of the bridge's monotonic
+/// clock: the producer's clock maps onto it from frame arrivals, the
+/// tracking clock from sample arrivals, and the frame takes the newest
+/// sample at or before that point. Until tracking arrives, frames keep the
+/// producer's own timestamp.
+pub(crate) struct WireClock {
+    origin: Instant,
+    video: ClockSync,
+    tracking: ClockSync,
+    recent_tracking: VecDeque<Duration>,
+    pending: VecDeque<(Duration, Duration)>,
+    last: Option<Duration>,
+}
+
+impl WireClock {
+    pub fn new(origin: Instant) -> Self {
+        Self {
+            origin,
+            video: ClockSync::default(),
+            tracking: ClockSync::default(),
+            recent_tracking: VecDeque::new(),
+            pending: VecDeque::new(),
+            last: None,
+        }
+    }
+
+    pub fn observe_tracking(&mut self, timestamp: Duration, now: Instant) {
+        self.tracking.observe(timestamp, self.local(now));
+        if self.recent_tracking.back() != Some(&timestamp) {
+            self.recent_tracking.push_back(timestamp);
+        }
+        if self.recent_tracking.len() > RECENT_TRACKING_LEN {
+            self.recent_tracking.pop_front();
+        }
+    }
+
+    /// Forgets the tracking timeline, which belongs to one client connection.
+    /// The producer's clock fit carries over.
+    pub fn reset_tracking(&mut self) {
+        self.tracking.reset();
+        self.recent_tracking.clear();
+        self.pending.clear();
+        self.last = None;
+    }
+
+    /// Assigns the wire timestamp for a frame that arrived at `now`, keeping
+    /// timestamps on the tracking timeline strictly increasing.
+    pub fn assign(
+        &mut self,
+        video_timestamp: Duration,
+        pose_timestamp: Option<Duration>,
+        now: Instant,
+    ) -> Duration {
+        self.video.observe(video_timestamp, self.local(now));
+        let wire = match pose_timestamp.or_else(|| self.translate(video_timestamp)) {
+            Some(timestamp) => {
+                let timestamp = self.last.map_or(timestamp, |last| {
+                    timestamp.max(last + Duration::from_nanos(1))
+                });
+                self.last = Some(timestamp);
+                timestamp
+            }
+            None => video_timestamp,
+        };
+        self.pending.push_back((video_timestamp, wire));
+        if self.pending.len() > PENDING_LEN {
+            self.pending.pop_front();
+        }
+        wire
+    }
+
+    /// The wire timestamp assigned to the frame with `video_timestamp`,
+    /// leaving it for `take`.
+    pub fn peek(&self, video_timestamp: Duration) -> Option<Duration> {
+        self.pending
+            .iter()
+            .find(|(pending, _)| *pending == video_timestamp)
+            .map(|(_, wire)| *wire)
+    }
+
+    /// The wire timestamp assigned to the frame with `video_timestamp`.
+    /// Frames that were dropped after assignment are skipped over.
+    pub fn take(&mut self, video_timestamp: Duration) -> Option<Duration> {
+        while let Some((pending, wire)) = self.pending.pop_front() {
+            if pending == video_timestamp {
+                return Some(wire);
+            }
+            if pending > video_timestamp {
+                self.pending.push_front((pending, wire));
+                break;
+            }
+        }
+        None
+    }
+
+    pub fn video_drift_ppm(&self) -> f64 {
+        self.video.drift_ppm()
+    }
+
+    fn translate(&self, video_timestamp: Duration) -> Option<Duration> {
+        let local = self.video.to_local(video_timestamp)?;
+        let translated = self.tracking.to_source(local)?;
+        Some(
+            self.recent_tracking
+                .iter()
+                .rev()
+                .find(|timestamp| **timestamp <= translated)
+                .copied()
+                .unwrap_or(translated),
+        )
+    }
+
+    fn local(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.origin)
+    }
+}
+
+fn offset((source, local): (Duration, Duration)) -> f64 {
+    nanos(local) - nanos(source)
+}
+
+fn nanos(duration: Duration) -> f64 {
+    duration.as_nanos() as f64
+}
+
+fn from_nanos(nanos: f64) -> Duration {
+    Duration::from_nanos(nanos.max(0.0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_the_lower_envelope_and_measures_drift() {
+        let mut sync = ClockSync::default();
+        // The local clock runs 200 ppm fast, 5 s ahead, and every reading
+        // queues for between 0 and 3 ms.
+        for index in 0..200u64 {
+            let source = Duration::from_millis(1_000 + index * 11);
+            let local = Duration::from_nanos((source.as_nanos() as f64 * 1.0002) as u64)
+                + Duration::from_secs(5)
+                + Duration::from_millis(index * 7 % 4);
+            sync.observe(source, local);
+        }
+
+        assert!((sync.drift_ppm() - 200.0).abs() < 1.0);
+        let source = Duration::from_millis(3_200);
+        let local = sync.to_local(source).unwrap();
+        let expected = Duration::from_nanos((source.as_nanos() as f64 * 1.0002) as u64)
+            + Duration::from_secs(5);
+        assert!(local.abs_diff(expected) < Duration::from_micros(50));
+        assert!(sync.to_source(local).unwrap().abs_diff(source) < Duration::from_micros(1));
+    }
+
+    #[test]
+    fn starts_over_when_the_source_clock_steps_back() {
+        let mut sync = ClockSync::default();
+        sync.observe(Duration::from_secs(100), Duration::from_secs(1));
+        sync.observe(Duration::from_secs(2), Duration::from_secs(3));
+
+        assert_eq!(
+            sync.to_local(Duration::from_secs(4)),
+            Some(Duration::from_secs(5))
+        );
+    }
+
+    #[test]
+    fn attributes_frames_to_tracking_samples() {
+        let origin = Instant::now();
+        let mut clock = WireClock::new(origin);
+        let at = |millis| origin + Duration::from_millis(millis);
+        // The producer's clock reads 50 s at the bridge's 0, and tracking
+        // timestamps run 900 s ahead of the bridge.
+        assert_eq!(
+            clock.assign(Duration::from_secs(50), None, at(0)),
+            Duration::from_secs(50)
+        );
+        for millis in [10, 21, 32] {
+            clock.observe_tracking(
+                Duration::from_secs(900) + Duration::from_millis(millis),
+                at(millis),
+            );
+        }
+
+        let translated = clock.assign(
+            Duration::from_secs(50) + Duration::from_millis(30),
+            None,
+            at(30),
+        );
+        assert_eq!(
+            translated,
+            Duration::from_secs(900) + Duration::from_millis(21)
+        );
+        let exact = Duration::from_secs(900) + Duration::from_millis(32);
+        assert_eq!(
+            clock.assign(Duration::from_secs(51), Some(exact), at(40)),
+            exact
+        );
+        // A reused pose still moves the wire timestamp forward.
+        assert_eq!(
+            clock.assign(Duration::from_secs(52), Some(exact), at(50)),
+            exact + Duration::from_nanos(1)
+        );
+
+        assert_eq!(clock.peek(Duration::from_secs(51)), Some(exact));
+        assert_eq!(clock.take(Duration::from_secs(51)), Some(exact));
+        assert_eq!(clock.peek(Duration::from_secs(51)), None);
+        assert_eq!(clock.take(Duration::from_secs(51)), None);
+        assert_eq!(
+            clock.take(Duration::from_secs(52)),
+            Some(exact + Duration::from_nanos(1))
+        );
+    }
+}
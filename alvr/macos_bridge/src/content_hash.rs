@@ -0,0 +1,120 @@
+/// Every eighth row is sampled, counting rows on through each plane.
+const ROW_STEP: u64 = 8;
+/// Bytes hashed at each sampled spot, one cache line.
+const TILE_BYTES: usize = 64;
+/// Distance between the starts of neighbouring tiles in a sampled row.
+const TILE_STEP: usize = 512;
+
+/// A 64-bit hash of a fixed, sparse sample of a frame, for telling a repeated
+/// frame from a new one without reading the whole slot. It reads a 64-byte
+/// tile every 512 bytes of every eighth row, about 1/64 of the frame. Each
+/// sampled row shifts its tiles along by three tiles, so every column is
+/// covered within 64 rows. A change that touches no sampled tile is missed,
+/// which the duplicate skip bounds by encoding a repeat periodically anyway.
+pub(crate) struct SampledContentHash {
+    row: u64,
+    length: u64,
+    // Four independent multiply chains, so one tile is not waiting on one
+    // multiply per word.
+    lanes: [u64; 4],
+}
+
+impl SampledContentHash {
+    pub fn new() -> Self {
+        Self {
+            row: 0,
+            length: 0,
+            lanes: [1, 2, 3, 4],
+        }
+    }
+
+    /// Takes the frame's rows in order. Only the sampled tiles of `row` are
+    /// read.
+    pub fn visit_row(&mut self, row: &[u8]) {
+        let index = self.row;
+        self.row += 1;
+        // Rows that are not read still count, so frames of different
+        // geometry never compare equal.
+        self.length += row.len() as u64;
+        if index % ROW_STEP != 0 {
+            return;
+        }
+        let stagger = (index / ROW_STEP) as usize * 3 * TILE_BYTES % TILE_STEP;
+        let first = if stagger < row.len() { stagger } else { 0 };
+        for start in (first..row.len()).step_by(TILE_STEP) {
+            self.mix_tile(&row[start..row.len().min(start + TILE_BYTES)]);
+        }
+    }
+
+    fn mix_tile(&mut self, tile: &[u8]) {
+        let mut words = tile.chunks_exact(8);
+        for (index, word) in words.by_ref().enumerate() {
+            let lane = &mut self.lanes[index % 4];
+            *lane = mix(*lane, u64::from_ne_bytes(word.try_into().unwrap()));
+        }
+        let tail = words
+            .remainder()
+            .iter()
+            .fold(0, |tail, &byte| (tail << 8) | u64::from(byte));
+        self.lanes[0] = mix(self.lanes[0], tail);
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.lanes
+            .iter()
+            .fold(self.length, |hash, &lane| mix(hash, lane))
+    }
+}
+
+fn mix(lane: u64, word: u64) -> u64 {
+    let lane = (lane ^ word).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    lane ^ (lane >> 29)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROW_BYTES: usize = 2048;
+
+    fn hash(frame: &[u8]) -> u64 {
+        let mut hash = SampledContentHash::new();
+        frame.chunks(ROW_BYTES).for_each(|row| hash.visit_row(row));
+        hash.finish()
+    }
+
+    #[test]
+    fn detects_one_pixel_changed_inside_a_sampled_tile() {
+        let frame = (0..ROW_BYTES * 64)
+            .map(|index| (index % 251) as u8)
+            .collect::<Vec<_>>();
+        assert_eq!(hash(&frame), hash(&frame.clone()));
+
+        // Row 8 is the second sampled row, so its tiles start 192 bytes in.
+        // Changing one BGRA pixel in the middle of its second tile is seen.
+        let mut changed = frame.clone();
+        let pixel = 8 * ROW_BYTES + 192 + TILE_STEP + 32;
+        changed[pixel..pixel + 4].copy_from_slice(&[1, 2, 3, 4]);
+        assert_ne!(hash(&frame), hash(&changed));
+
+        // Outside every tile, or on a row between samples, it is not.
+        let mut missed = frame.clone();
+        missed[8 * ROW_BYTES + 192 + TILE_BYTES] ^= 0xff;
+        missed[9 * ROW_BYTES] ^= 0xff;
+        assert_eq!(hash(&frame), hash(&missed));
+    }
+
+    #[test]
+    fn covers_every_column_within_64_rows() {
+        let frame = vec![0; ROW_BYTES * 64];
+        let unchanged = hash(&frame);
+        for column in 0..ROW_BYTES {
+            let seen = (0..64).step_by(ROW_STEP as usize).any(|row| {
+                let mut changed = frame.clone();
+                changed[row * ROW_BYTES + column] = 1;
+                hash(&changed) != unchanged
+            });
+            assert!(seen, "column {column}");
+        }
+    }
+}
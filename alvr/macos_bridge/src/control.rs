@@ -0,0 +1,425 @@
+use crate::{frame_stats::FrameStats, latency::LatencySummary, metrics::MetricsServer};
+use alvr_session::CodecType;
+use anyhow::{Context, Result, bail, ensure};
+use serde_json::{Map, Value, json};
+use std::{
+    fs,
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    os::unix::{
+        fs::{FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+const CONTROL_IO_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_REQUEST_BYTES: u64 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BridgeState {
+    #[default]
+    Starting,
+    WaitingForProducer,
+    WaitingForClient,
+    Standby,
+    Streaming,
+    Closing,
+}
+
+impl BridgeState {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Starting => "starting",
+            Self::WaitingForProducer => "waiting_for_producer",
+            Self::WaitingForClient => "waiting_for_client",
+            Self::Standby => "standby",
+            Self::Streaming => "streaming",
+            Self::Closing => "closing",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClientStatus {
+    pub stream_epoch: u64,
+    pub view_width: u32,
+    pub view_height: u32,
+    pub refresh_rate: f32,
+    pub codec: CodecType,
+    pub contract_valid: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StatusMetrics {
+    pub received: u64,
+    pub submitted: u64,
+    pub encoded: u64,
+    pub transported: u64,
+    pub dropped: u64,
+    /// Frames skipped because the encoder was behind, before the bridge
+    /// committed to them; `dropped` counts frames it lost afterwards.
+    pub backpressure_drops: u64,
+    /// Frames skipped to hold the stream under its peak bitrate, likewise
+    /// outside `dropped`.
+    pub rate_limit_drops: u64,
+    pub encoded_bytes: u64,
+    pub transported_bytes: u64,
+    pub keyframes: u64,
+    /// Gaps in the producer's frame ids: frames it never handed over.
+    pub producer_gaps: u64,
+    /// Frames whose pixels did not match the producer's checksum.
+    pub checksum_mismatches: u64,
+    /// Encoded frames per second over the last telemetry interval.
+    pub frame_rate: f64,
+    /// The encoder's current target, which adaptive bitrate and the
+    /// degradation ladder move away from the configured one.
+    pub bitrate_bps: u64,
+    pub encode_latency: LatencySummary,
+    /// Encoded frame sizes over the last telemetry interval, and the latest
+    /// keyframe interval.
+    pub frames: FrameStats,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BridgeStatus {
+    pub state: BridgeState,
+    pub input: &'static str,
+    pub codec: CodecType,
+    pub width: u32,
+    pub height: u32,
+    pub fps: f64,
+    pub bitrate_bps: u64,
+    pub pipeline_depth: u32,
+    pub buffer_count: usize,
+    pub connect_to_alvr: bool,
+    pub client: Option<ClientStatus>,
+    pub metrics: StatusMetrics,
+}
+
+impl BridgeStatus {
+    fn to_json(&self, uptime: Duration) -> Value {
+        json!({
+            "state": self.state.as_str(),
+            "pid": std::process::id(),
+            "uptime_ms": u64::try_from(uptime.as_millis()).unwrap_or(u64::MAX),
+            "client": self.client.map(|client| json!({
+                "stream_epoch": client.stream_epoch,
+                "view_width": client.view_width,
+                "view_height": client.view_height,
+                "refresh_rate": client.refresh_rate,
+                "codec": format!("{:?}", client.codec),
+                "contract_valid": client.contract_valid,
+            })),
+            "settings": {
+                "input": self.input,
+                "codec": format!("{:?}", self.codec),
+                "width": self.width,
+                "height": self.height,
+                "fps": self.fps,
+                "bitrate_bps": self.bitrate_bps,
+                "pipeline_depth": self.pipeline_depth,
+                "buffer_count": self.buffer_count,
+                "connect_to_alvr": self.connect_to_alvr,
+            },
+            "metrics": {
+                "received": self.metrics.received,
+                "submitted": self.metrics.submitted,
+                "encoded": self.metrics.encoded,
+                "transported": self.metrics.transported,
+                "dropped": self.metrics.dropped,
+                "backpressure_drops": self.metrics.backpressure_drops,
+                "rate_limit_drops": self.metrics.rate_limit_drops,
+                "encoded_bytes": self.metrics.encoded_bytes,
+                "transported_bytes": self.metrics.transported_bytes,
+                "keyframes": self.metrics.keyframes,
+                "producer_gaps": self.metrics.producer_gaps,
+                "checksum_mismatches": self.metrics.checksum_mismatches,
+                "frame_rate": self.metrics.frame_rate,
+                "bitrate_bps": self.metrics.bitrate_bps,
+                "encode_p50_us": u64::try_from(self.metrics.encode_latency.p50.as_micros()).unwrap_or(u64::MAX),
+                "encode_p99_us": u64::try_from(self.metrics.encode_latency.p99.as_micros()).unwrap_or(u64::MAX),
+                "frame_bytes_p50": self.metrics.frames.frame_bytes_p50,
+                "frame_bytes_p99": self.metrics.frames.frame_bytes_p99,
+                "frame_bytes_max": self.metrics.frames.frame_bytes_max,
+                "keyframe_interval_frames": self.metrics.frames.keyframe_interval_frames,
+                "keyframe_interval_ms": u64::try_from(self.metrics.frames.keyframe_interval.as_millis()).unwrap_or(u64::MAX),
+            },
+        })
+    }
+}
+
+pub(crate) struct ControlServer {
+    path: PathBuf,
+    status: Arc<Mutex<BridgeStatus>>,
+    started: Instant,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    metrics: Option<MetricsServer>,
+}
+
+impl ControlServer {
+    pub fn bind(path: &Path, initial: BridgeStatus) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            ensure!(
+                metadata.file_type().is_socket(),
+                "control socket path exists and is not a socket: {}",
+                path.display()
+            );
+            if UnixStream::connect(path).is_ok() {
+                bail!(
+                    "another bridge is already serving control socket {}",
+                    path.display()
+                );
+            }
+            fs::remove_file(path)
+                .with_context(|| format!("failed to remove stale {}", path.display()))?;
+        }
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("failed to bind control socket {}", path.display()))?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("failed to secure {}", path.display()))?;
+
+        let status = Arc::new(Mutex::new(initial));
+        let stop = Arc::new(AtomicBool::new(false));
+        let started = Instant::now();
+        let thread = thread::Builder::new()
+            .name("bridge-control".into())
+            .spawn({
+                let status = Arc::clone(&status);
+                let stop = Arc::clone(&stop);
+                move || {
+                    for stream in listener.incoming() {
+                        if stop.load(Ordering::Acquire) {
+                            break;
+                        }
+                        let result = stream.map_err(anyhow::Error::from).and_then(|stream| {
+                            serve_request(stream, || {
+                                lock_status(&status).to_json(started.elapsed())
+                            })
+                        });
+                        if let Err(error) = result {
+                            eprintln!("control_socket request failed: {error:#}");
+                        }
+                    }
+                }
+            })
+            .context("failed to spawn control socket thread")?;
+        eprintln!("control_socket listening path={}", path.display());
+
+        Ok(Self {
+            path: path.to_owned(),
+            status,
+            started,
+            stop,
+            thread: Some(thread),
+            metrics: None,
+        })
+    }
+
+    /// Also serves this status as Prometheus metrics on a loopback port.
+    pub fn serve_metrics(&mut self, port: u16) -> Result<()> {
+        self.metrics = Some(MetricsServer::bind(
+            port,
+            Arc::clone(&self.status),
+            self.started,
+        )?);
+        Ok(())
+    }
+
+    pub fn update(&self, update: impl FnOnce(&mut BridgeStatus)) {
+        update(&mut lock_status(&self.status));
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        let _ = UnixStream::connect(&self.path);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub(crate) fn lock_status(status: &Mutex<BridgeStatus>) -> MutexGuard<'_, BridgeStatus> {
+    status.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+fn serve_request(stream: UnixStream, status: impl FnOnce() -> Value) -> Result<()> {
+    stream.set_read_timeout(Some(CONTROL_IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(CONTROL_IO_TIMEOUT))?;
+    let mut request = String::new();
+    match BufReader::new(&stream)
+        .take(MAX_REQUEST_BYTES)
+        .read_line(&mut request)
+    {
+        Ok(_) => {}
+        Err(error) if error.kind() == ErrorKind::UnexpectedEof => {}
+        Err(error) => return Err(error.into()),
+    }
+    let response = match request.trim() {
+        "" => return Ok(()),
+        "status" => status(),
+        command => json!({ "error": format!("unknown control command {command:?}") }),
+    };
+    let mut stream = stream;
+    serde_json::to_writer(&mut stream, &response)?;
+    stream.write_all(b"\n")?;
+    Ok(())
+}
+
+pub fn query_status(path: &Path) -> Result<Value> {
+    let mut stream = UnixStream::connect(path)
+        .with_context(|| format!("no bridge is listening on {}", path.display()))?;
+    stream.set_read_timeout(Some(CONTROL_IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(CONTROL_IO_TIMEOUT))?;
+    stream.write_all(b"status\n")?;
+    let mut response = String::new();
+    BufReader::new(stream)
+        .read_line(&mut response)
+        .context("failed to read bridge status")?;
+    let status: Value =
+        serde_json::from_str(&response).context("bridge returned a malformed status")?;
+    if let Some(error) = status.get("error").and_then(Value::as_str) {
+        bail!("bridge rejected status request: {error}");
+    }
+    Ok(status)
+}
+
+pub fn status_line(status: &Value) -> String {
+    let mut fields = Vec::new();
+    flatten_status("", status, &mut fields);
+    format!("bridge status {}", fields.join(" "))
+}
+
+fn flatten_status(prefix: &str, value: &Value, fields: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => flatten_object(prefix, map, fields),
+        Value::String(value) => fields.push(format!("{prefix}={value}")),
+        value => fields.push(format!("{prefix}={value}")),
+    }
+}
+
+fn flatten_object(prefix: &str, map: &Map<String, Value>, fields: &mut Vec<String>) {
+    for (key, value) in map {
+        let key = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{prefix}_{key}")
+        };
+        flatten_status(&key, value, fields);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> BridgeStatus {
+        BridgeStatus {
+            state: BridgeState::Streaming,
+            input: "iosurface",
+            codec: CodecType::Hevc,
+            width: 2752,
+            height: 1792,
+            fps: 90.0,
+            bitrate_bps: 50_000_000,
+            pipeline_depth: 1,
+            buffer_count: 6,
+            connect_to_alvr: true,
+            client: Some(ClientStatus {
+                stream_epoch: 3,
+                view_width: 1376,
+                view_height: 1792,
+                refresh_rate: 90.0,
+                codec: CodecType::Hevc,
+                contract_valid: true,
+            }),
+            metrics: StatusMetrics {
+                submitted: 12,
+                transported: 10,
+                frames: FrameStats {
+                    frame_bytes_p99: 120_000,
+                    keyframe_interval_frames: 90,
+                    keyframe_interval: Duration::from_secs(1),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn serializes_state_settings_client_and_metrics() {
+        let json = status().to_json(Duration::from_millis(1500));
+
+        assert_eq!(json["state"], "streaming");
+        assert_eq!(json["uptime_ms"], 1500);
+        assert_eq!(json["client"]["codec"], "Hevc");
+        assert_eq!(json["client"]["view_width"], 1376);
+        assert_eq!(json["settings"]["bitrate_bps"], 50_000_000);
+        assert_eq!(json["metrics"]["transported"], 10);
+        assert_eq!(json["metrics"]["frame_bytes_p99"], 120_000);
+        assert_eq!(json["metrics"]["keyframe_interval_frames"], 90);
+        assert_eq!(json["metrics"]["keyframe_interval_ms"], 1000);
+    }
+
+    #[test]
+    fn reports_a_null_client_while_waiting() {
+        let json = BridgeStatus {
+            state: BridgeState::WaitingForClient,
+            client: None,
+            ..status()
+        }
+        .to_json(Duration::ZERO);
+
+        assert_eq!(json["state"], "waiting_for_client");
+        assert!(json["client"].is_null());
+    }
+
+    #[test]
+    fn flattens_nested_status_into_one_line() {
+        let line = status_line(&json!({
+            "state": "streaming",
+            "client": null,
+            "metrics": { "submitted": 4 },
+        }));
+
+        assert_eq!(
+            line,
+            "bridge status client=null metrics_submitted=4 state=streaming"
+        );
+    }
+
+    #[test]
+    fn answers_status_over_the_control_socket() {
+        let path = std::env::temp_dir().join(format!(
+            "alvr-bridge-control-{}-{}.sock",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        let server = ControlServer::bind(&path, status()).unwrap();
+        server.update(|status| status.metrics.submitted = 99);
+
+        let json = query_status(&path).unwrap();
+        assert_eq!(json["metrics"]["submitted"], 99);
+        assert_eq!(json["settings"]["input"], "iosurface");
+
+        drop(server);
+        assert!(!path.exists());
+    }
+}
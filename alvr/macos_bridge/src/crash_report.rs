@@ -0,0 +1,172 @@
+use crate::{
+    log_file::local_timestamp, native_source::BRIDGE_BUILD_VERSION, probe::alvr_root_from_env,
+};
+use alvr_filesystem::Layout;
+use anyhow::Result;
+use std::{
+    backtrace::Backtrace,
+    collections::{BTreeMap, VecDeque},
+    env,
+    fmt::Write as _,
+    fs,
+    panic::{self, PanicHookInfo},
+    path::PathBuf,
+    sync::{Mutex, OnceLock, TryLockError},
+};
+
+/// Printed lines kept for the report, enough to cover a few cadence
+/// intervals and whatever warnings led up to the failure.
+const RECENT_LINES: usize = 200;
+
+/// What the bridge knew shortly before it died. The frame loop records its
+/// snapshots as it goes, since a panic hook cannot reach the loop's state.
+struct CrashState {
+    lines: VecDeque<String>,
+    sections: BTreeMap<&'static str, String>,
+}
+
+/// Set once the reporter is installed. Kept apart from the state so a panic
+/// raised while the state is locked can still write its report.
+static LOG_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+static STATE: Mutex<CrashState> = Mutex::new(CrashState {
+    lines: VecDeque::new(),
+    sections: BTreeMap::new(),
+});
+
+/// Keeps the most recent printed lines. Called from the log file's copy
+/// threads, so the lines are only there while the log file is installed.
+pub(crate) fn remember_line(line: &str) {
+    if LOG_DIR.get().is_none() {
+        return;
+    }
+    let mut state = STATE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if state.lines.len() == RECENT_LINES {
+        state.lines.pop_front();
+    }
+    state.lines.push_back(line.to_owned());
+}
+
+/// Replaces one named section of the next report, such as `encoder` or
+/// `counters`.
+pub(crate) fn record(section: &'static str, snapshot: String) {
+    if LOG_DIR.get().is_some() {
+        STATE
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .sections
+            .insert(section, snapshot);
+    }
+}
+
+/// Writes a diagnostic bundle to ALVR's log directory when the bridge
+/// panics, and lets `write_crash_report` do the same for a fatal error. The
+/// bundle holds the reason, a backtrace for panics, the last encoder
+/// configuration, frame counters and shared memory header as of the last
+/// cadence report, and the last printed lines. The previous panic hook still
+/// runs first, so the panic message reaches the terminal and the log as
+/// before. Off with `ALVR_BRIDGE_CRASH_REPORTS=0`.
+pub fn install_crash_reporter() -> Result<()> {
+    if env::var("ALVR_BRIDGE_CRASH_REPORTS").as_deref() == Ok("0") {
+        return Ok(());
+    }
+    let _ = LOG_DIR.set(Layout::new(&alvr_root_from_env()?).log_dir);
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        previous(info);
+        let mut reason = format!("panic message={}", panic_message(info));
+        if let Some(location) = info.location() {
+            let _ = write!(reason, " location={location}");
+        }
+        let backtrace = Backtrace::force_capture().to_string();
+        if let Some(path) = write_report(&reason, Some(&backtrace)) {
+            eprintln!("crash_report path={}", path.display());
+        }
+    }));
+    Ok(())
+}
+
+/// The path of the bundle written for an error that ends the run, or `None`
+/// when crash reports are off or the file could not be written.
+pub fn write_crash_report(error: &anyhow::Error) -> Option<PathBuf> {
+    write_report(&format!("error message={error:#}"), None)
+}
+
+fn write_report(reason: &str, backtrace: Option<&str>) -> Option<PathBuf> {
+    let log_dir = LOG_DIR.get()?;
+    // A poisoned lock is used as is. One that is held, possibly by the
+    // panicking thread itself, is not waited for and leaves the state out.
+    let state = match STATE.try_lock() {
+        Ok(state) => Some(state),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    };
+    let timestamp = local_timestamp();
+    let mut report = format!(
+        "alvr_macos_bridge crash report\ntime={timestamp}\nversion={BRIDGE_BUILD_VERSION}\npid={}\n{reason}\n",
+        std::process::id()
+    );
+    if let Some(backtrace) = backtrace {
+        let _ = write!(report, "\n[backtrace]\n{backtrace}\n");
+    }
+    if let Some(state) = &state {
+        for (section, snapshot) in &state.sections {
+            let _ = write!(report, "\n[{section}]\n{snapshot}\n");
+        }
+        let _ = write!(report, "\n[recent_log] lines={}\n", state.lines.len());
+        for line in &state.lines {
+            report.push_str(line);
+            report.push('\n');
+        }
+    }
+    drop(state);
+
+    let file_name = format!(
+        "macos_bridge_crash_{}.txt",
+        timestamp.replace([' ', ':', '.'], "-")
+    );
+    let path = log_dir.join(file_name);
+    fs::create_dir_all(log_dir).ok()?;
+    fs::write(&path, report).ok()?;
+    Some(path)
+}
+
+fn panic_message<'a>(info: &'a PanicHookInfo<'_>) -> &'a str {
+    if let Some(message) = info.payload().downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = info.payload().downcast_ref::<String>() {
+        message
+    } else {
+        "<non-string payload>"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundles_the_recorded_state_into_the_log_dir() {
+        let dir = env::temp_dir().join(format!("alvr_bridge_crash_test_{}", std::process::id()));
+        LOG_DIR.set(dir.clone()).unwrap();
+        record("encoder", "codec=Hevc width=2064".into());
+        record("counters", "received=9 encoded=8".into());
+        record("counters", "received=10 encoded=9".into());
+        for index in 0..(RECENT_LINES + 5) {
+            remember_line(&format!("line {index}"));
+        }
+
+        let path = write_crash_report(&anyhow::anyhow!("IOSurface producer pid=7 exited")).unwrap();
+        let report = fs::read_to_string(&path).unwrap();
+
+        assert!(path.starts_with(&dir));
+        assert!(report.contains("error message=IOSurface producer pid=7 exited\n"));
+        assert!(report.contains("[encoder]\ncodec=Hevc width=2064\n"));
+        assert!(report.contains("[counters]\nreceived=10 encoded=9\n"));
+        assert!(report.contains(&format!("[recent_log] lines={RECENT_LINES}\nline 5\n")));
+        assert!(report.ends_with(&format!("line {}\n", RECENT_LINES + 4)));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
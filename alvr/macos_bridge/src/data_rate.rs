@@ -0,0 +1,103 @@
+use std::time::{Duration, Instant};
+
+/// Holds the encoded stream under a peak rate over a short window, the way a
+/// VBV buffer would. The VideoToolbox binding does not expose
+/// `DataRateLimits`, so the encoder only gets the average bitrate and after a
+/// scene change it can emit several times the target in a few frames, which
+/// overflows Wi-Fi queues. The limit is enforced here instead, by skipping
+/// frames: output fills a bucket that drains at the peak rate, and while the
+/// bucket is over a window's worth, the bridge skips frames before encoding
+/// them until it drains.
+pub(crate) struct DataRateLimiter {
+    peak_percent: u32,
+    window: Duration,
+    peak_bytes_per_second: f64,
+    level_bytes: f64,
+    drained_at: Option<Instant>,
+    max_fill: f64,
+}
+
+impl DataRateLimiter {
+    pub fn new(peak_percent: u32, window: Duration, bitrate_bps: u64) -> Self {
+        let mut limiter = Self {
+            peak_percent,
+            window,
+            peak_bytes_per_second: 0.0,
+            level_bytes: 0.0,
+            drained_at: None,
+            max_fill: 0.0,
+        };
+        limiter.set_bitrate(bitrate_bps);
+        limiter
+    }
+
+    /// Follows the encoder's target, which adaptive bitrate and the
+    /// degradation ladder move.
+    pub fn set_bitrate(&mut self, bitrate_bps: u64) {
+        self.peak_bytes_per_second =
+            bitrate_bps as f64 / 8.0 * f64::from(self.peak_percent) / 100.0;
+    }
+
+    pub fn peak_bps(&self) -> u64 {
+        (self.peak_bytes_per_second * 8.0) as u64
+    }
+
+    fn capacity_bytes(&self) -> f64 {
+        self.peak_bytes_per_second * self.window.as_secs_f64()
+    }
+
+    fn drain(&mut self, now: Instant) {
+        if let Some(drained_at) = self.drained_at {
+            let elapsed = now.saturating_duration_since(drained_at).as_secs_f64();
+            self.level_bytes = (self.level_bytes - elapsed * self.peak_bytes_per_second).max(0.0);
+        }
+        self.drained_at = Some(now);
+    }
+
+    pub fn sent(&mut self, now: Instant, bytes: u64) {
+        self.drain(now);
+        self.level_bytes += bytes as f64;
+        self.max_fill = self.max_fill.max(self.level_bytes / self.capacity_bytes());
+    }
+
+    /// Whether the last window's output already reached the peak, so a frame
+    /// encoded now would push the stream past it.
+    pub fn is_over(&mut self, now: Instant) -> bool {
+        self.drain(now);
+        self.level_bytes >= self.capacity_bytes()
+    }
+
+    /// The fullest the bucket got since the last call, as a percentage of a
+    /// window at the peak rate. Over 100 means one burst exceeded the peak on
+    /// its own, as a keyframe can.
+    pub fn take_max_fill_percent(&mut self) -> u64 {
+        (std::mem::take(&mut self.max_fill) * 100.0).round() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_until_a_burst_drains_at_the_peak_rate() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        // 8 Mbps at 150% peaks at 1.5 MB/s, or 150 KB per 100 ms window.
+        let mut limiter = DataRateLimiter::new(150, Duration::from_millis(100), 8_000_000);
+        assert_eq!(limiter.peak_bps(), 12_000_000);
+
+        assert!(!limiter.is_over(at(0)));
+        limiter.sent(at(0), 300_000);
+        assert!(limiter.is_over(at(0)));
+        assert!(limiter.is_over(at(99)));
+        assert!(!limiter.is_over(at(101)));
+        assert_eq!(limiter.take_max_fill_percent(), 200);
+        assert_eq!(limiter.take_max_fill_percent(), 0);
+
+        limiter.set_bitrate(16_000_000);
+        limiter.sent(at(101), 51_500);
+        assert!(!limiter.is_over(at(101)));
+        assert_eq!(limiter.take_max_fill_percent(), 67);
+    }
+}
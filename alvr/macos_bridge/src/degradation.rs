@@ -0,0 +1,384 @@
+use std::{fmt, time::Duration};
+
+const OVERLOAD_INTERVALS: u32 = 3;
+const HEADROOM_INTERVALS: u32 = 10;
+const OVERLOAD_DROP_PERCENT: u64 = 5;
+const OVERLOAD_BUSY_PERCENT: u128 = 80;
+const HEADROOM_BUSY_PERCENT: u128 = 50;
+const BITRATE_RETARGET_PERCENT: u64 = 10;
+// Keyframe requests per interval that mean the client is losing packets
+// rather than just joining or starting a recording.
+const LOSS_IDR_REQUESTS: u64 = 3;
+
+const LADDER: [QualityLevel; 5] = [
+    QualityLevel {
+        bitrate_percent: 100,
+        resolution_percent: 100,
+        fps_divisor: 1,
+    },
+    QualityLevel {
+        bitrate_percent: 75,
+        resolution_percent: 100,
+        fps_divisor: 1,
+    },
+    QualityLevel {
+        bitrate_percent: 50,
+        resolution_percent: 100,
+        fps_divisor: 1,
+    },
+    QualityLevel {
+        bitrate_percent: 50,
+        resolution_percent: 75,
+        fps_divisor: 1,
+    },
+    QualityLevel {
+        bitrate_percent: 50,
+        resolution_percent: 75,
+        fps_divisor: 2,
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct QualityLevel {
+    pub bitrate_percent: u32,
+    pub resolution_percent: u32,
+    pub fps_divisor: u32,
+}
+
+impl QualityLevel {
+    pub fn bitrate_bps(self, target_bps: u64) -> u64 {
+        (target_bps / 100 * u64::from(self.bitrate_percent)).max(1)
+    }
+
+    pub fn keeps_frame(self, received: u64) -> bool {
+        received.is_multiple_of(u64::from(self.fps_divisor))
+    }
+}
+
+// Each bitrate change restarts the VideoToolbox session and costs an IDR, so
+// small moves in ALVR's adaptive target are not worth following.
+pub(crate) fn retarget_bitrate(active_bps: u64, requested_bps: u64) -> Option<u64> {
+    let delta = active_bps.abs_diff(requested_bps);
+    (requested_bps > 0 && delta * 100 >= active_bps * BITRATE_RETARGET_PERCENT)
+        .then_some(requested_bps)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct LoadCounters {
+    pub received: u64,
+    pub dropped: u64,
+    pub busy_total: Duration,
+    pub busy_count: u64,
+    pub encoder_pending: usize,
+    pub idr_requests: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pressure {
+    Overloaded(&'static str),
+    Steady,
+    Headroom,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Transition {
+    pub from: QualityLevel,
+    pub to: QualityLevel,
+    pub down: bool,
+    pub level: usize,
+    pub levels: usize,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "degradation step={} level={}/{} bitrate_percent={} resolution_percent={} fps_divisor={} reason={}",
+            if self.down { "down" } else { "up" },
+            self.level,
+            self.levels - 1,
+            self.to.bitrate_percent,
+            self.to.resolution_percent,
+            self.to.fps_divisor,
+            self.reason
+        )
+    }
+}
+
+pub(crate) struct DegradationLadder {
+    levels: Vec<QualityLevel>,
+    index: usize,
+    /// The highest rung headroom may climb back to while the Mac is under
+    /// thermal or power pressure.
+    floor: usize,
+    frame_budget: Duration,
+    last: LoadCounters,
+    overloaded_intervals: u32,
+    headroom_intervals: u32,
+}
+
+impl DegradationLadder {
+    pub fn new(fps: u32, resolution_supported: bool) -> Self {
+        let mut levels = LADDER
+            .iter()
+            .map(|level| QualityLevel {
+                resolution_percent: if resolution_supported {
+                    level.resolution_percent
+                } else {
+                    100
+                },
+                ..*level
+            })
+            .collect::<Vec<_>>();
+        levels.dedup();
+        Self {
+            levels,
+            index: 0,
+            floor: 0,
+            frame_budget: Duration::from_secs_f64(1.0 / f64::from(fps.max(1))),
+            last: LoadCounters::default(),
+            overloaded_intervals: 0,
+            headroom_intervals: 0,
+        }
+    }
+
+    /// Follows a renegotiated client refresh rate.
+    pub fn set_frame_budget(&mut self, frame_budget: Duration) {
+        self.frame_budget = frame_budget;
+    }
+
+    /// Holds the ladder at or below rung `floor`, stepping down to it at
+    /// once. Lowering the floor leaves the rung alone, and headroom climbs
+    /// back as usual, counted from the change.
+    pub fn set_floor(&mut self, floor: usize, reason: &'static str) -> Option<Transition> {
+        self.floor = floor.min(self.levels.len() - 1);
+        self.headroom_intervals = 0;
+        (self.index < self.floor).then(|| self.step(self.floor, reason))
+    }
+
+    pub fn level(&self) -> QualityLevel {
+        self.levels[self.index]
+    }
+
+    pub fn observe(&mut self, counters: LoadCounters) -> Option<Transition> {
+        let pressure = self.pressure(counters);
+        self.last = counters;
+        match pressure {
+            Pressure::Overloaded(reason) => {
+                self.headroom_intervals = 0;
+                self.overloaded_intervals += 1;
+                // A lossy link keeps corrupting frames until the bitrate
+                // drops, so it steps at once instead of waiting it out.
+                let intervals = if reason == "client_loss" {
+                    1
+                } else {
+                    OVERLOAD_INTERVALS
+                };
+                (self.overloaded_intervals >= intervals && self.index + 1 < self.levels.len())
+                    .then(|| self.step(self.index + 1, reason))
+            }
+            Pressure::Headroom => {
+                self.overloaded_intervals = 0;
+                self.headroom_intervals += 1;
+                (self.headroom_intervals >= HEADROOM_INTERVALS && self.index > self.floor)
+                    .then(|| self.step(self.index - 1, "headroom"))
+            }
+            Pressure::Steady => {
+                self.overloaded_intervals = 0;
+                self.headroom_intervals = 0;
+                None
+            }
+        }
+    }
+
+    fn pressure(&self, counters: LoadCounters) -> Pressure {
+        let received = counters.received.saturating_sub(self.last.received);
+        let dropped = counters.dropped.saturating_sub(self.last.dropped);
+        let busy_count = counters.busy_count.saturating_sub(self.last.busy_count);
+        let idr_requests = counters.idr_requests.saturating_sub(self.last.idr_requests);
+        let busy_average = counters
+            .busy_total
+            .saturating_sub(self.last.busy_total)
+            .checked_div(u32::try_from(busy_count).unwrap_or(u32::MAX))
+            .unwrap_or_default();
+        let budget = self.frame_budget.as_nanos();
+
+        if idr_requests >= LOSS_IDR_REQUESTS {
+            Pressure::Overloaded("client_loss")
+        } else if received == 0 {
+            Pressure::Steady
+        } else if dropped * 100 > received * OVERLOAD_DROP_PERCENT {
+            Pressure::Overloaded("dropped_frames")
+        } else if busy_average.as_nanos() * 100 > budget * OVERLOAD_BUSY_PERCENT {
+            Pressure::Overloaded("conversion_time")
+        } else if counters.encoder_pending > 2 * self.level().fps_divisor as usize {
+            Pressure::Overloaded("encoder_backlog")
+        } else if dropped == 0
+            && idr_requests == 0
+            && busy_average.as_nanos() * 100 < budget * HEADROOM_BUSY_PERCENT
+        {
+            Pressure::Headroom
+        } else {
+            Pressure::Steady
+        }
+    }
+
+    fn step(&mut self, index: usize, reason: &'static str) -> Transition {
+        let from = self.level();
+        let down = index > self.index;
+        self.index = index;
+        self.overloaded_intervals = 0;
+        self.headroom_intervals = 0;
+        Transition {
+            from,
+            to: self.level(),
+            down,
+            level: index,
+            levels: self.levels.len(),
+            reason,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interval(
+        ladder: &DegradationLadder,
+        frames: u64,
+        dropped: u64,
+        busy: Duration,
+    ) -> LoadCounters {
+        LoadCounters {
+            received: ladder.last.received + frames,
+            dropped: ladder.last.dropped + dropped,
+            busy_total: ladder.last.busy_total + busy * frames as u32,
+            busy_count: ladder.last.busy_count + frames,
+            encoder_pending: 0,
+            idr_requests: ladder.last.idr_requests,
+        }
+    }
+
+    #[test]
+    fn follows_alvr_bitrate_only_past_the_retarget_threshold() {
+        assert_eq!(retarget_bitrate(50_000_000, 52_000_000), None);
+        assert_eq!(retarget_bitrate(50_000_000, 46_000_000), None);
+        assert_eq!(retarget_bitrate(50_000_000, 55_000_000), Some(55_000_000));
+        assert_eq!(retarget_bitrate(50_000_000, 20_000_000), Some(20_000_000));
+        assert_eq!(retarget_bitrate(50_000_000, 0), None);
+    }
+
+    #[test]
+    fn steps_down_only_after_sustained_overload() {
+        let mut ladder = DegradationLadder::new(90, true);
+
+        for _ in 0..OVERLOAD_INTERVALS - 1 {
+            let counters = interval(&ladder, 90, 10, Duration::from_millis(2));
+            assert_eq!(ladder.observe(counters), None);
+        }
+        let counters = interval(&ladder, 90, 10, Duration::from_millis(2));
+        let transition = ladder.observe(counters).unwrap();
+
+        assert_eq!(transition.reason, "dropped_frames");
+        assert_eq!(transition.to.bitrate_percent, 75);
+        assert!(
+            transition
+                .to_string()
+                .starts_with("degradation step=down level=1/4")
+        );
+    }
+
+    #[test]
+    fn steps_down_at_once_when_the_client_reports_loss() {
+        let mut ladder = DegradationLadder::new(90, true);
+
+        let mut counters = interval(&ladder, 90, 0, Duration::from_millis(1));
+        counters.idr_requests += LOSS_IDR_REQUESTS - 1;
+        assert_eq!(ladder.observe(counters), None);
+
+        let mut counters = interval(&ladder, 90, 0, Duration::from_millis(1));
+        counters.idr_requests += LOSS_IDR_REQUESTS;
+        let transition = ladder.observe(counters).unwrap();
+
+        assert_eq!(transition.reason, "client_loss");
+        assert_eq!(transition.to.bitrate_percent, 75);
+    }
+
+    #[test]
+    fn walks_bitrate_then_resolution_then_fps() {
+        let mut ladder = DegradationLadder::new(90, true);
+        let mut visited = vec![ladder.level()];
+
+        for _ in 0..OVERLOAD_INTERVALS * 8 {
+            let counters = interval(&ladder, 90, 0, Duration::from_millis(10));
+            if let Some(transition) = ladder.observe(counters) {
+                assert_eq!(transition.reason, "conversion_time");
+                visited.push(transition.to);
+            }
+        }
+
+        assert_eq!(visited, LADDER);
+    }
+
+    #[test]
+    fn climbs_back_after_sustained_headroom() {
+        let mut ladder = DegradationLadder::new(90, true);
+        for _ in 0..OVERLOAD_INTERVALS {
+            let counters = interval(&ladder, 90, 10, Duration::ZERO);
+            ladder.observe(counters);
+        }
+        assert_eq!(ladder.level().bitrate_percent, 75);
+
+        let mut transition = None;
+        for _ in 0..HEADROOM_INTERVALS {
+            let counters = interval(&ladder, 90, 0, Duration::from_millis(1));
+            transition = ladder.observe(counters);
+        }
+
+        let transition = transition.unwrap();
+        assert_eq!(transition.reason, "headroom");
+        assert_eq!(transition.to, LADDER[0]);
+        assert!(
+            transition
+                .to_string()
+                .starts_with("degradation step=up level=0/4")
+        );
+    }
+
+    #[test]
+    fn collapses_resolution_rungs_when_the_stream_size_is_fixed() {
+        let ladder = DegradationLadder::new(90, false);
+
+        assert_eq!(ladder.levels.len(), 4);
+        assert!(
+            ladder
+                .levels
+                .iter()
+                .all(|level| level.resolution_percent == 100)
+        );
+        assert_eq!(ladder.levels[3].fps_divisor, 2);
+    }
+
+    #[test]
+    fn holds_the_thermal_floor_until_it_lifts() {
+        let mut ladder = DegradationLadder::new(90, false);
+
+        let transition = ladder.set_floor(usize::MAX, "thermal_pressure").unwrap();
+        assert!(transition.down);
+        assert_eq!(transition.to.fps_divisor, 2);
+        for _ in 0..HEADROOM_INTERVALS {
+            let counters = interval(&ladder, 90, 0, Duration::from_millis(1));
+            assert_eq!(ladder.observe(counters), None);
+        }
+
+        assert_eq!(ladder.set_floor(0, "thermal_pressure"), None);
+        let mut transition = None;
+        for _ in 0..HEADROOM_INTERVALS {
+            let counters = interval(&ladder, 90, 0, Duration::from_millis(1));
+            transition = ladder.observe(counters);
+        }
+        assert_eq!(transition.unwrap().reason, "headroom");
+    }
+}
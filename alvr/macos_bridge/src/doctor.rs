@@ -0,0 +1,321 @@
+use crate::{
+    EncoderBackend, NativeVideoEncoder, ProbeConfig, alvr_sink::load_session_stream_port,
+    encoder::codec_name, encoder_hardware_support, preflight::configuration_problems,
+    probe::alvr_root_from_env, tracking_feedback::check_feedback_access,
+};
+use alvr_session::CodecType;
+use anyhow::{Result, ensure};
+use std::{
+    env,
+    ffi::OsStr,
+    fmt,
+    io::ErrorKind,
+    net::{Ipv4Addr, TcpListener, UdpSocket},
+    process::Command,
+};
+
+const SOCKETFILTERFW: &str = "/usr/libexec/ApplicationFirewall/socketfilterfw";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl Outcome {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Pass => "pass",
+            Self::Warn => "warn",
+            Self::Fail => "fail",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Check {
+    name: &'static str,
+    outcome: Outcome,
+    detail: String,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(name, Outcome::Pass, detail)
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(name, Outcome::Warn, detail)
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(name, Outcome::Fail, detail)
+    }
+
+    fn new(name: &'static str, outcome: Outcome, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            outcome,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "doctor check={} result={} detail={}",
+            self.name,
+            self.outcome.name(),
+            self.detail
+        )
+    }
+}
+
+/// Checks the parts of this Mac that most often keep a headset from
+/// connecting, with the same environment and options a run would use, and
+/// prints one line per check saying what to change. Fails if any check
+/// failed; warnings point at things worth a look that may be fine.
+pub fn run_doctor() -> Result<()> {
+    let checks = doctor_checks();
+    for check in &checks {
+        println!("{check}");
+    }
+    let count = |outcome| {
+        checks
+            .iter()
+            .filter(|check| check.outcome == outcome)
+            .count()
+    };
+    let failed = count(Outcome::Fail);
+    println!(
+        "doctor finished checks={} warnings={} failures={failed}",
+        checks.len(),
+        count(Outcome::Warn)
+    );
+    ensure!(failed == 0, "doctor found {failed} failing checks");
+    Ok(())
+}
+
+fn doctor_checks() -> Vec<Check> {
+    let mut checks = Vec::new();
+    let config = ProbeConfig::from_env();
+    checks.push(match &config {
+        Ok(config) => Check::pass(
+            "configuration",
+            format!(
+                "{} {}x{} at {} fps",
+                codec_name(config.codec),
+                config.width,
+                config.height,
+                config.fps
+            ),
+        ),
+        Err(error) => Check::fail("configuration", format!("{error:#}")),
+    });
+    checks.push(match encoder_hardware_support(CodecType::Hevc) {
+        Ok(_) => Check::pass(
+            "videotoolbox_hevc",
+            "VideoToolbox reports a hardware HEVC encoder",
+        ),
+        Err(error) => Check::fail(
+            "videotoolbox_hevc",
+            format!(
+                "{error:#}; this Mac cannot stream HEVC, so pick H.264 in the ALVR dashboard and set ALVR_BRIDGE_CODEC=h264"
+            ),
+        ),
+    });
+    if let Ok(config) = &config {
+        checks.push(encoder_check(config));
+        let problems = configuration_problems(config.preflight_input(None));
+        checks.push(if problems.is_empty() {
+            Check::pass(
+                "encode_limits",
+                "the configured stream fits the encoder and memory",
+            )
+        } else {
+            Check::fail("encode_limits", problems.join("; "))
+        });
+    }
+    checks.push(match check_feedback_access() {
+        Ok(locations) => Check::pass("shared_memory", format!("writable {locations}")),
+        Err(error) => Check::fail(
+            "shared_memory",
+            format!(
+                "{error:#}; the driver in Wine gets tracking through this segment, so fix its permissions or point ALVR_BRIDGE_TRACKING_SHM elsewhere"
+            ),
+        ),
+    });
+    checks.push(
+        match alvr_root_from_env().and_then(|root| load_session_stream_port(&root)) {
+            Ok(port) => stream_port_check(port),
+            Err(error) => Check::fail("stream_port", format!("{error:#}")),
+        },
+    );
+    checks.push(firewall_check());
+    checks
+}
+
+/// Opens the encoder a run would, which also catches another process holding
+/// the hardware encoder.
+fn encoder_check(config: &ProbeConfig) -> Check {
+    let name = codec_name(config.codec);
+    if config.encoder_backend == EncoderBackend::Software {
+        return Check::pass(
+            "hardware_encoder",
+            "not used; ALVR_BRIDGE_ENCODER=software encodes on the CPU",
+        );
+    }
+    match NativeVideoEncoder::new(config.encoder_config()) {
+        Ok(_) => Check::pass(
+            "hardware_encoder",
+            format!(
+                "opened a hardware {name} session at {}x{}",
+                config.width, config.height
+            ),
+        ),
+        Err(error) if config.encoder_backend.falls_back(config.codec) => Check::warn(
+            "hardware_encoder",
+            format!(
+                "{error:#}; the bridge will fall back to the software encoder, which costs CPU and suits smaller streams"
+            ),
+        ),
+        Err(error) => Check::fail(
+            "hardware_encoder",
+            format!(
+                "{error:#}; quit screen recorders or other apps using the {name} encoder, or lower ALVR_BRIDGE_WIDTH/ALVR_BRIDGE_HEIGHT"
+            ),
+        ),
+    }
+}
+
+/// Server core listens for the stream on every interface. A port in use
+/// usually means another ALVR server, or a running bridge, has it.
+fn stream_port_check(port: u16) -> Check {
+    let mut busy = Vec::new();
+    for (protocol, result) in [
+        (
+            "UDP",
+            UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)).map(drop),
+        ),
+        (
+            "TCP",
+            TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).map(drop),
+        ),
+    ] {
+        match result {
+            Ok(()) => {}
+            Err(error) if error.kind() == ErrorKind::AddrInUse => busy.push(protocol),
+            Err(error) => {
+                return Check::fail(
+                    "stream_port",
+                    format!("cannot listen on {protocol} {port}: {error}"),
+                );
+            }
+        }
+    }
+    if busy.is_empty() {
+        Check::pass("stream_port", format!("UDP and TCP {port} are free"))
+    } else {
+        Check::warn(
+            "stream_port",
+            format!(
+                "{} {port} already in use; quit other ALVR servers or bridges, or change the stream port in the ALVR dashboard",
+                busy.join(" and ")
+            ),
+        )
+    }
+}
+
+fn firewall_check() -> Check {
+    let query = |args: &[&OsStr]| {
+        Command::new(SOCKETFILTERFW)
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    };
+    let Some(global_state) = query(&["--getglobalstate".as_ref()]) else {
+        return Check::warn(
+            "firewall",
+            "could not query the application firewall; check System Settings > Network > Firewall",
+        );
+    };
+    let block_all = query(&["--getblockall".as_ref()]).unwrap_or_default();
+    let app_state = env::current_exe()
+        .ok()
+        .and_then(|exe| query(&["--getappblocked".as_ref(), exe.as_os_str()]))
+        .unwrap_or_default();
+    firewall_verdict(&global_state, &block_all, &app_state)
+}
+
+/// Reads `socketfilterfw` output, whose wording varies between macOS
+/// releases, by keyword.
+fn firewall_verdict(global_state: &str, block_all: &str, app_state: &str) -> Check {
+    let global_state = global_state.to_ascii_lowercase();
+    if global_state.contains("disabled") || global_state.contains("state = 0") {
+        return Check::pass("firewall", "the application firewall is off");
+    }
+    if block_all.to_ascii_lowercase().contains("enabled") {
+        return Check::fail(
+            "firewall",
+            "the firewall blocks all incoming connections, so no headset can connect; turn off Block all incoming connections in System Settings > Network > Firewall > Options",
+        );
+    }
+    let app_state = app_state.to_ascii_lowercase();
+    if app_state.contains("permitted") {
+        Check::pass("firewall", "the firewall is on and allows this binary")
+    } else if app_state.contains("blocked") && !app_state.contains("not blocked") {
+        Check::fail(
+            "firewall",
+            "the firewall blocks this binary; allow it in System Settings > Network > Firewall > Options",
+        )
+    } else {
+        Check::warn(
+            "firewall",
+            "the firewall is on and has no rule for this binary; allow it when macOS asks on the first connection",
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_firewall_state_by_keyword() {
+        let off = firewall_verdict("Firewall is disabled. (State = 0)", "", "");
+        assert_eq!(off.outcome, Outcome::Pass);
+
+        let on = "Firewall is enabled. (State = 1)";
+        let block_all = firewall_verdict(on, "Firewall has block all state set to enabled.", "");
+        assert_eq!(block_all.outcome, Outcome::Fail);
+        assert!(block_all.detail.contains("Block all incoming connections"));
+
+        let no_block_all = "Firewall has block all state set to disabled.";
+        let allowed = firewall_verdict(on, no_block_all, "The application /bin/x is permitted");
+        assert_eq!(allowed.outcome, Outcome::Pass);
+        let blocked = firewall_verdict(on, no_block_all, "The application /bin/x is blocked");
+        assert_eq!(blocked.outcome, Outcome::Fail);
+        assert_eq!(
+            firewall_verdict(on, no_block_all, "").outcome,
+            Outcome::Warn
+        );
+    }
+
+    #[test]
+    fn warns_when_the_stream_port_is_taken() {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let check = stream_port_check(port);
+
+        assert_eq!(check.outcome, Outcome::Warn);
+        assert!(check.to_string().starts_with(&format!(
+            "doctor check=stream_port result=warn detail=TCP {port} already in use"
+        )));
+    }
+}
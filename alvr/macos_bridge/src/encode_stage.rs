@@ -0,0 +1,440 @@
+use crate::{
+    AlvrVideoSink, EncodedFrame, FrameMetadata, HardwareEncoderSupport, NativeVideoEncoderConfig,
+    SurfaceLease,
+    encoder::{SourceEncoder, codec_name},
+    probe::{DispatchCounts, dispatch_outputs},
+    recording::StreamRecorder,
+    restream::Restreamer,
+    surface::SlotCopy,
+};
+use alvr_session::CodecType;
+use anyhow::{Context, Result, anyhow, bail};
+use std::{
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender, SyncSender, TryRecvError},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use tracing::info_span;
+
+/// How long the encode thread waits on VideoToolbox output before looking
+/// for the next frame again.
+const OUTPUT_POLL: Duration = Duration::from_millis(1);
+
+/// The ALVR sink, shared by the acquisition loop, which reads the client's
+/// state and builds each frame's metadata, and the encode thread, which
+/// sends. Neither side holds it across a call into the other.
+#[derive(Clone)]
+pub(crate) struct SharedSink(Arc<Mutex<Option<AlvrVideoSink>>>);
+
+impl SharedSink {
+    pub fn new(sink: Option<AlvrVideoSink>) -> Self {
+        Self(Arc::new(Mutex::new(sink)))
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, Option<AlvrVideoSink>> {
+        self.0.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+/// A frame ready to encode. Either way its source slot is already back with
+/// the producer.
+pub(crate) enum EncodeInput {
+    /// The Metal pass's output.
+    Converted(SurfaceLease),
+    /// The slot copied as it was, for VideoToolbox to take unconverted.
+    Copied(SlotCopy),
+}
+
+pub(crate) struct EncodeJob {
+    pub input: EncodeInput,
+    pub metadata: FrameMetadata,
+    pub force_keyframe: bool,
+}
+
+/// Frames the encode thread sent, for the loop's accounting.
+pub(crate) struct Sent {
+    pub dispatch: DispatchCounts,
+    /// Frames still in flight afterwards.
+    pub pending: usize,
+    pub at: Instant,
+}
+
+enum Command {
+    Encode(EncodeJob),
+    /// Finishes the session, sending everything it held, then opens one
+    /// with the config, or stays closed for `None`.
+    Restart(Option<NativeVideoEncoderConfig>),
+    /// Drops the session unfinished and opens one with the config.
+    Recreate(NativeVideoEncoderConfig),
+}
+
+enum Event {
+    Sent(Sent),
+    /// A restart or recreate finished.
+    Done,
+}
+
+/// The encode/send half of the IOSurface loop. The acquisition loop hands
+/// each frame over through a channel with room for one more, so a slow
+/// encode or send holds up the handoff, never a producer slot. The session
+/// is opened on the thread and never leaves it.
+pub(crate) struct EncodeStage {
+    commands: Option<SyncSender<Command>>,
+    events: Receiver<Result<Event>>,
+    in_flight: Arc<AtomicUsize>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl EncodeStage {
+    pub fn spawn<E, F>(
+        mut open: F,
+        config: NativeVideoEncoderConfig,
+        sink: SharedSink,
+        recorder: Option<StreamRecorder>,
+        restream: Option<Restreamer>,
+        timing_sei: bool,
+    ) -> Result<(Self, HardwareEncoderSupport)>
+    where
+        E: SourceEncoder + 'static,
+        F: FnMut(NativeVideoEncoderConfig) -> Result<(E, HardwareEncoderSupport)> + Send + 'static,
+    {
+        let (commands, command_rx) = mpsc::sync_channel(1);
+        let (events, event_rx) = mpsc::channel();
+        let (opened, opened_rx) = mpsc::channel();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let thread = thread::Builder::new()
+            .name("bridge-encode".into())
+            .spawn({
+                let in_flight = Arc::clone(&in_flight);
+                move || {
+                    let session = match open(config) {
+                        Ok((session, hardware_support)) => {
+                            let _ = opened.send(Ok(hardware_support));
+                            session
+                        }
+                        Err(error) => {
+                            let _ = opened.send(Err(error));
+                            return;
+                        }
+                    };
+                    let mut worker = EncodeThread {
+                        session: Some(session),
+                        open,
+                        codec: config.codec,
+                        sink,
+                        recorder,
+                        restream,
+                        timing_sei,
+                        in_flight,
+                        events,
+                    };
+                    if let Err(error) = worker.serve(&command_rx) {
+                        let _ = worker.events.send(Err(error));
+                    }
+                }
+            })
+            .context("failed to spawn encode thread")?;
+        let stage = Self {
+            commands: Some(commands),
+            events: event_rx,
+            in_flight,
+            thread: Some(thread),
+        };
+        let hardware_support = opened_rx
+            .recv()
+            .context("encode thread exited before opening a session")??;
+        Ok((stage, hardware_support))
+    }
+
+    /// Frames handed over and not yet sent, the one waiting in the channel
+    /// included.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+
+    /// Hands a frame to the thread, waiting while the channel is full.
+    pub fn submit(&mut self, job: EncodeJob) -> Result<()> {
+        self.in_flight.fetch_add(1, Ordering::AcqRel);
+        self.send(Command::Encode(job))
+    }
+
+    /// The next batch the thread sent, if one is waiting.
+    pub fn try_sent(&self) -> Result<Option<Sent>> {
+        loop {
+            match self.events.try_recv() {
+                Ok(Ok(Event::Sent(sent))) => return Ok(Some(sent)),
+                Ok(Ok(Event::Done)) => {}
+                Ok(Err(error)) => return Err(error),
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => bail!("the encode thread exited"),
+            }
+        }
+    }
+
+    /// Finishes the session and opens one with `config`, or leaves it
+    /// closed for `None`. Returns once every frame handed over before it has
+    /// gone out through `sent`. A replacement session starts on an IDR.
+    pub fn restart(
+        &mut self,
+        config: Option<NativeVideoEncoderConfig>,
+        sent: impl FnMut(Sent),
+    ) -> Result<()> {
+        self.send(Command::Restart(config))?;
+        self.wait_done(sent)
+    }
+
+    /// Replaces a session that may no longer answer, dropping what it held
+    /// rather than waiting on it. The new session starts on an IDR.
+    pub fn recreate(
+        &mut self,
+        config: NativeVideoEncoderConfig,
+        sent: impl FnMut(Sent),
+    ) -> Result<()> {
+        self.send(Command::Recreate(config))?;
+        self.wait_done(sent)
+    }
+
+    fn send(&self, command: Command) -> Result<()> {
+        let commands = self
+            .commands
+            .as_ref()
+            .expect("the command channel stays open until drop");
+        if commands.send(command).is_ok() {
+            return Ok(());
+        }
+        // The thread reports why it stopped before it lets go of the channel.
+        loop {
+            match self.events.recv() {
+                Ok(Err(error)) => return Err(error),
+                Ok(Ok(_)) => {}
+                Err(_) => bail!("the encode thread exited"),
+            }
+        }
+    }
+
+    fn wait_done(&self, mut sent: impl FnMut(Sent)) -> Result<()> {
+        loop {
+            match self.events.recv() {
+                Ok(Ok(Event::Sent(batch))) => sent(batch),
+                Ok(Ok(Event::Done)) => return Ok(()),
+                Ok(Err(error)) => return Err(error),
+                Err(_) => bail!("the encode thread exited"),
+            }
+        }
+    }
+}
+
+impl Drop for EncodeStage {
+    fn drop(&mut self) {
+        // A closed channel ends the thread. Whatever the session still holds
+        // is dropped with it.
+        drop(self.commands.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct EncodeThread<E, F> {
+    session: Option<E>,
+    open: F,
+    /// The latest session's codec, which the recording and restream follow.
+    codec: CodecType,
+    sink: SharedSink,
+    recorder: Option<StreamRecorder>,
+    restream: Option<Restreamer>,
+    timing_sei: bool,
+    in_flight: Arc<AtomicUsize>,
+    events: Sender<Result<Event>>,
+}
+
+impl<E, F> EncodeThread<E, F>
+where
+    E: SourceEncoder,
+    F: FnMut(NativeVideoEncoderConfig) -> Result<(E, HardwareEncoderSupport)>,
+{
+    /// Runs commands until the loop closes the channel. While the session
+    /// holds frames, it checks for output between commands instead of
+    /// blocking on the next one.
+    fn serve(&mut self, commands: &Receiver<Command>) -> Result<()> {
+        loop {
+            let pending = self
+                .session
+                .as_ref()
+                .is_some_and(|session| session.pending_count() > 0);
+            let command = if pending {
+                match commands.try_recv() {
+                    Ok(command) => command,
+                    Err(TryRecvError::Empty) => {
+                        let session = self
+                            .session
+                            .as_mut()
+                            .expect("pending frames need a session");
+                        let outputs = session.wait_for_output(OUTPUT_POLL)?;
+                        self.send(outputs)?;
+                        continue;
+                    }
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            } else {
+                match commands.recv() {
+                    Ok(command) => command,
+                    Err(_) => return Ok(()),
+                }
+            };
+            match command {
+                Command::Encode(job) => self.encode(job)?,
+                Command::Restart(config) => {
+                    if let Some(session) = self.session.as_mut() {
+                        let outputs = session.finish()?;
+                        self.send(outputs)?;
+                    }
+                    self.session = None;
+                    if let Some(config) = config {
+                        self.open_session(config)?;
+                    }
+                    self.report(Event::Done)?;
+                }
+                Command::Recreate(config) => {
+                    self.session = None;
+                    // Nothing queued behind the recreate reached the old
+                    // session, and what it held is gone with it.
+                    self.in_flight.store(0, Ordering::Release);
+                    self.open_session(config)?;
+                    self.report(Event::Done)?;
+                }
+            }
+        }
+    }
+
+    fn encode(&mut self, job: EncodeJob) -> Result<()> {
+        let EncodeJob {
+            input,
+            metadata,
+            force_keyframe,
+        } = job;
+        let session = self
+            .session
+            .as_mut()
+            .context("a frame reached the encode thread with no session open")?;
+        let outputs = info_span!(
+            "encode",
+            frame_id = metadata.frame_id,
+            keyframe = force_keyframe
+        )
+        .in_scope(|| match input {
+            EncodeInput::Converted(lease) => session.submit(lease, metadata, force_keyframe),
+            EncodeInput::Copied(copy) => session.submit_source(copy, metadata, force_keyframe),
+        })?;
+        self.send(outputs)
+    }
+
+    /// A replacement session starts a new GOP, so its first frame is an IDR
+    /// whatever the periodic cadence says. A recording cannot change codec
+    /// midway, so a new codec stops it.
+    fn open_session(&mut self, config: NativeVideoEncoderConfig) -> Result<()> {
+        if config.codec != self.codec {
+            self.codec = config.codec;
+            if self.recorder.take().is_some() {
+                eprintln!(
+                    "WARNING recording stopped because the client negotiated {}",
+                    codec_name(config.codec)
+                );
+            }
+            if let Some(restreamer) = self.restream.as_mut() {
+                restreamer.set_codec(config.codec);
+            }
+        }
+        let mut session = (self.open)(config)?.0;
+        session.request_idr();
+        self.session = Some(session);
+        Ok(())
+    }
+
+    /// Sends what the session returned and reports it to the loop.
+    fn send(&mut self, outputs: Vec<EncodedFrame>) -> Result<()> {
+        if outputs.is_empty() {
+            return Ok(());
+        }
+        let dispatch = info_span!("send").in_scope(|| {
+            dispatch_outputs(
+                outputs,
+                &mut self.sink.lock(),
+                &mut self.recorder,
+                &mut self.restream,
+                self.timing_sei.then_some(self.codec),
+            )
+        })?;
+        let encoded = usize::try_from(dispatch.encoded).unwrap_or(usize::MAX);
+        let pending = self.in_flight.fetch_sub(encoded, Ordering::AcqRel) - encoded;
+        self.report(Event::Sent(Sent {
+            dispatch,
+            pending,
+            at: Instant::now(),
+        }))
+    }
+
+    fn report(&self, event: Event) -> Result<()> {
+        self.events
+            .send(Ok(event))
+            .map_err(|_| anyhow!("the IOSurface loop stopped listening to the encode thread"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        SurfacePool,
+        mock_encoder::{MockEncoder, mock_config},
+        probe::default_stereo_view_params,
+    };
+
+    #[test]
+    fn sends_every_frame_handed_over_before_a_restart_returns() -> Result<()> {
+        let config = mock_config().encoder_config();
+        let (mut stage, _) = EncodeStage::spawn(
+            |config: NativeVideoEncoderConfig| {
+                Ok((
+                    MockEncoder::new(config.codec),
+                    MockEncoder::hardware_support(),
+                ))
+            },
+            config,
+            SharedSink::new(None),
+            None,
+            None,
+            false,
+        )?;
+        let pool = SurfacePool::new(config.width, config.height, 3, config.format)?;
+        for frame_id in 0..3 {
+            let lease = pool.try_acquire()?.context("pool exhausted")?;
+            stage.submit(EncodeJob {
+                input: EncodeInput::Converted(lease),
+                metadata: FrameMetadata {
+                    frame_id,
+                    stream_epoch: 0,
+                    video_timestamp: Duration::from_millis(frame_id * 11),
+                    pose_timestamp: Duration::from_millis(frame_id * 11),
+                    global_view_params: default_stereo_view_params(config.width, config.height),
+                },
+                force_keyframe: frame_id == 0,
+            })?;
+        }
+
+        let mut encoded = 0;
+        while let Some(sent) = stage.try_sent()? {
+            encoded += sent.dispatch.encoded;
+        }
+        stage.restart(None, |sent| encoded += sent.dispatch.encoded)?;
+
+        assert_eq!(encoded, 3);
+        assert_eq!(stage.in_flight(), 0);
+        assert_eq!(pool.stats().available, 3);
+        Ok(())
+    }
+}
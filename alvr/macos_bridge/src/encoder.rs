@@ -1,52 +1,296 @@
-use crate::{FrameMetadata, SurfaceLease, SurfaceLeaseId, contract::FrameOrderValidator};
-use anyhow::{Context, Result, anyhow, ensure};
+#[cfg(feature = "software-encoder")]
+use crate::software_encoder::SoftwareEncoder;
+use crate::{
+    FrameMetadata, HdrMetadata, SurfaceFormat, SurfaceLease, SurfaceLeaseId,
+    contract::FrameOrderValidator, crash_report, output_buffers::OUTPUT_BUFFERS, surface::SlotCopy,
+};
+use alvr_session::{CodecType, H264Profile};
+use anyhow::{Context, Result, anyhow, bail, ensure};
 use shiguredo_video_toolbox::{
     CodecConfig, EncodeOptions, EncodedFrame as VideoToolboxFrame, Encoder, EncoderConfig,
-    Error as VideoToolboxError, FnEncodeHandler, HevcEncoderConfig, HevcProfile, PixelFormat,
+    Error as VideoToolboxError, FnEncodeHandler, H264EncoderConfig,
+    H264Profile as VideoToolboxH264Profile, HevcEncoderConfig, HevcProfile, PixelFormat,
     VideoCodecType, supported_codecs,
 };
 use std::{
+    ffi::c_void,
+    fmt,
     num::NonZeroU32,
     sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 const NAL_START_CODE: [u8; 4] = [0, 0, 0, 1];
+/// Deeper pipelines stop adding throughput well before this, and each frame
+/// of depth costs about a frame interval of latency.
+pub(crate) const MAX_PIPELINE_DEPTH: u32 = 4;
+const AV1_ENCODE_UNAVAILABLE: &str = "VideoToolbox exposes AV1 decode only; Apple Silicon media engines, M3 included, have no AV1 encoder";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct HardwareEncoderSupport {
-    pub hevc_supported: bool,
+    pub codec_supported: bool,
     pub hardware_accelerated: bool,
     pub supports_frame_reordering: bool,
 }
 
-pub fn hevc_hardware_support() -> Result<HardwareEncoderSupport> {
+pub fn encoder_hardware_support(codec: CodecType) -> Result<HardwareEncoderSupport> {
+    let name = codec_name(codec);
+    let video_codec = match codec {
+        CodecType::Hevc => VideoCodecType::Hevc,
+        CodecType::H264 => VideoCodecType::H264,
+        CodecType::AV1 => bail!(AV1_ENCODE_UNAVAILABLE),
+    };
     let info = supported_codecs()
         .into_iter()
-        .find(|info| info.codec == VideoCodecType::Hevc)
-        .context("VideoToolbox did not report HEVC encoder information")?;
+        .find(|info| info.codec == video_codec)
+        .with_context(|| format!("VideoToolbox did not report {name} encoder information"))?;
     let support = HardwareEncoderSupport {
-        hevc_supported: info.encoding.supported,
+        codec_supported: info.encoding.supported,
         hardware_accelerated: info.encoding.hardware_accelerated,
         supports_frame_reordering: info.encoding.supports_frame_reordering,
     };
     ensure!(
-        support.hevc_supported,
-        "VideoToolbox HEVC encode is unavailable"
+        support.codec_supported,
+        "VideoToolbox {name} encode is unavailable"
     );
     ensure!(
         support.hardware_accelerated,
-        "VideoToolbox did not report a hardware-accelerated HEVC encoder"
+        "VideoToolbox did not report a hardware-accelerated {name} encoder"
     );
     Ok(support)
 }
 
+pub fn codec_name(codec: CodecType) -> &'static str {
+    match codec {
+        CodecType::H264 => "H.264",
+        CodecType::Hevc => "HEVC",
+        CodecType::AV1 => "AV1",
+    }
+}
+
+/// Whether the forced-keyframe cadence lands on the `index`th submitted frame.
+/// An interval of zero keeps only the first IDR and leaves the rest to client
+/// requests.
+pub(crate) fn periodic_keyframe(keyframe_interval: u32, index: u64) -> bool {
+    index
+        .checked_rem(u64::from(keyframe_interval))
+        .map_or(index == 0, |offset| offset == 0)
+}
+
+/// The encoder frame rate for a client refresh rate, as `(fps,
+/// fps_denominator)`. Whole rates are exact, rates a thousandth slow of a
+/// whole rate (59.94, 119.88) go over 1001, and anything else over 1000.
+pub(crate) fn frame_rate_ratio(refresh_hz: f32) -> (u32, u32) {
+    let refresh_hz = f64::from(refresh_hz);
+    let near = |value: f64| (value - value.round()).abs() < 0.005;
+    if near(refresh_hz) {
+        (refresh_hz.round() as u32, 1)
+    } else if near(refresh_hz * 1.001) {
+        ((refresh_hz * 1.001).round() as u32 * 1000, 1001)
+    } else {
+        ((refresh_hz * 1000.0).round() as u32, 1000)
+    }
+}
+
+/// Which encoder the bridge opens. `Auto` uses VideoToolbox and, when it
+/// cannot open a session for H.264, the software encoder if this build has
+/// one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EncoderBackend {
+    #[default]
+    Auto,
+    VideoToolbox,
+    Software,
+}
+
+impl EncoderBackend {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::VideoToolbox => "videotoolbox",
+            Self::Software => "software",
+        }
+    }
+
+    /// Whether a VideoToolbox failure for `codec` gives way to the software
+    /// encoder rather than failing the run.
+    pub(crate) fn falls_back(self, codec: CodecType) -> bool {
+        self == Self::Auto && SOFTWARE_ENCODER_BUILT && codec == CodecType::H264
+    }
+}
+
+pub(crate) const SOFTWARE_ENCODER_BUILT: bool = cfg!(feature = "software-encoder");
+
+/// What the software encoder reports in place of VideoToolbox's inventory.
+pub(crate) const SOFTWARE_ENCODER_SUPPORT: HardwareEncoderSupport = HardwareEncoderSupport {
+    codec_supported: true,
+    hardware_accelerated: false,
+    supports_frame_reordering: false,
+};
+
+/// How VideoToolbox spends bits. The binding exposes no QP controls, so a
+/// constant-QP cap is not on offer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RateControl {
+    /// Hold the average near `bitrate_bps`, following adaptive bitrate.
+    #[default]
+    AverageBitrate,
+    /// Leave the bitrate unconstrained so every frame gets VideoToolbox's
+    /// default quality, however many bits that takes.
+    Quality,
+}
+
+impl RateControl {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::AverageBitrate => "bitrate",
+            Self::Quality => "quality",
+        }
+    }
+}
+
+/// The session properties a user may set past the bridge's own choices,
+/// named as VideoToolbox names them. The encoder binding builds the session
+/// from its own config and does not hand out the session, so only the
+/// properties that config carries can be overridden.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VideoToolboxOverrides {
+    pub real_time: Option<bool>,
+    pub prioritize_encoding_speed_over_quality: Option<bool>,
+    pub maximize_power_efficiency: Option<bool>,
+    pub allow_temporal_compression: Option<bool>,
+}
+
+const OVERRIDE_KEYS: &str = "RealTime, PrioritizeEncodingSpeedOverQuality, MaximizePowerEfficiency, AllowTemporalCompression";
+
+impl VideoToolboxOverrides {
+    /// Parses `Key=value` pairs separated by commas. Keys may carry the
+    /// `kVTCompressionPropertyKey_` prefix.
+    pub fn parse_list(list: &str) -> Result<Self> {
+        let mut overrides = Self::default();
+        for entry in list
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (key, value) = entry
+                .split_once('=')
+                .with_context(|| format!("expected Key=value, not {entry:?}"))?;
+            let key = key.trim();
+            let key = key
+                .strip_prefix("kVTCompressionPropertyKey_")
+                .unwrap_or(key);
+            let slot = match key {
+                "RealTime" => &mut overrides.real_time,
+                "PrioritizeEncodingSpeedOverQuality" => {
+                    &mut overrides.prioritize_encoding_speed_over_quality
+                }
+                "MaximizePowerEfficiency" => &mut overrides.maximize_power_efficiency,
+                "AllowTemporalCompression" => &mut overrides.allow_temporal_compression,
+                "AllowFrameReordering" => bail!(
+                    "AllowFrameReordering stays off: reordered frames would reach ALVR out of presentation order"
+                ),
+                _ => bail!(
+                    "{key} cannot be set: the encoder binding does not expose the session, so only {OVERRIDE_KEYS} can be overridden"
+                ),
+            };
+            ensure!(slot.is_none(), "{key} is set twice");
+            let value = match value.trim().to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" => true,
+                "0" | "false" | "no" => false,
+                value => bail!("{key} takes true or false, not {value:?}"),
+            };
+            *slot = Some(value);
+        }
+        Ok(overrides)
+    }
+}
+
+impl fmt::Display for VideoToolboxOverrides {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries = [
+            ("RealTime", self.real_time),
+            (
+                "PrioritizeEncodingSpeedOverQuality",
+                self.prioritize_encoding_speed_over_quality,
+            ),
+            ("MaximizePowerEfficiency", self.maximize_power_efficiency),
+            ("AllowTemporalCompression", self.allow_temporal_compression),
+        ];
+        let mut separator = "";
+        for (key, value) in entries {
+            if let Some(value) = value {
+                write!(formatter, "{separator}{key}={value}")?;
+                separator = ",";
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
-pub struct NativeHevcEncoderConfig {
+pub struct NativeVideoEncoderConfig {
+    pub codec: CodecType,
+    /// Ignored unless `codec` is H.264.
+    pub h264_profile: H264Profile,
+    pub format: SurfaceFormat,
     pub width: u32,
     pub height: u32,
+    /// The frame rate is `fps` over `fps_denominator`, so 60000 over 1001
+    /// is 59.94 Hz.
     pub fps: u32,
+    pub fps_denominator: u32,
+    /// Ignored under `RateControl::Quality`.
     pub bitrate_bps: u64,
+    pub rate_control: RateControl,
+    /// Zero disables periodic keyframes, including VideoToolbox's backstop.
+    pub keyframe_interval: u32,
+    /// VideoToolbox's time-based keyframe limit. `None` uses twice
+    /// `keyframe_interval`, or no limit when that is zero.
+    pub keyframe_interval_duration: Option<Duration>,
+    /// HDR10 metadata to send in SEI ahead of each IDR. Requires Main10.
+    pub hdr: Option<HdrMetadata>,
+    /// How many frames VideoToolbox may hold before it must emit the oldest.
+    /// One emits each frame before starting the next; more let the media
+    /// engine overlap frames, for throughput at large sizes, and delay each
+    /// frame by about one more frame interval.
+    pub pipeline_depth: u32,
+    /// Session properties set in place of the bridge's defaults.
+    pub overrides: VideoToolboxOverrides,
+}
+
+/// The encode stage of the bridge loop. `NativeVideoEncoder` drives
+/// VideoToolbox; tests and alternative backends can supply their own so the
+/// loop runs without a hardware encoder. Every submitted lease must come back
+/// through exactly one `EncodedFrame`, in submission order.
+pub trait VideoEncoder {
+    fn codec(&self) -> CodecType;
+
+    fn hardware_support(&self) -> HardwareEncoderSupport;
+
+    /// Queues one frame; `force_keyframe` requests an IDR for it. Returns any
+    /// frames that completed meanwhile.
+    fn submit(
+        &mut self,
+        lease: SurfaceLease,
+        metadata: FrameMetadata,
+        force_keyframe: bool,
+    ) -> Result<Vec<EncodedFrame>>;
+
+    fn drain_ready(&mut self) -> Result<Vec<EncodedFrame>>;
+
+    fn wait_for_output(&mut self, timeout: Duration) -> Result<Vec<EncodedFrame>>;
+
+    /// Flushes every pending frame at the end of a run.
+    fn finish(&mut self) -> Result<Vec<EncodedFrame>>;
+
+    /// Changes the target bitrate, returning frames flushed on the way.
+    fn set_bitrate(&mut self, bitrate_bps: u64) -> Result<Vec<EncodedFrame>>;
+
+    fn pending_count(&self) -> usize;
+
+    /// Makes the next submitted frame an IDR, whatever its caller asks for.
+    fn request_idr(&mut self);
 }
 
 pub struct EncodedFrame {
@@ -55,90 +299,182 @@ pub struct EncodedFrame {
     pub nal_data: Vec<u8>,
     pub is_keyframe: bool,
     pub decoder_config_nals: Option<Vec<u8>>,
+    /// Time from submission until VideoToolbox's output callback ran.
+    pub encode_latency: Duration,
 }
 
 struct PendingFrame {
     lease_id: SurfaceLeaseId,
     metadata: FrameMetadata,
-    lease: SurfaceLease,
+    buffer: PendingBuffer,
+    submitted_at: Instant,
+}
+
+/// Keeps the submitted pixels out of their pool until VideoToolbox is done
+/// reading them.
+enum PendingBuffer {
+    Lease(SurfaceLease),
+    SlotCopy(SlotCopy),
 }
 
 type VideoToolboxResult = std::result::Result<VideoToolboxFrame<PendingFrame>, VideoToolboxError>;
+/// Callback results are stamped as they arrive so encode latency does not
+/// include however long the frame waited in the channel.
+type VideoToolboxOutput = (Instant, VideoToolboxResult);
 type VideoToolboxEncoder = Encoder<FnEncodeHandler<PendingFrame>>;
 
-pub struct NativeHevcEncoder {
+pub struct NativeVideoEncoder {
     encoder: VideoToolboxEncoder,
-    output_rx: Receiver<VideoToolboxResult>,
+    output_rx: Receiver<VideoToolboxOutput>,
+    config: NativeVideoEncoderConfig,
+    support: HardwareEncoderSupport,
     width: u32,
     height: u32,
     order: FrameOrderValidator,
     pending_count: usize,
+    hdr_sei: Option<Vec<u8>>,
+    idr_requested: bool,
 }
 
-impl NativeHevcEncoder {
-    pub fn new(config: NativeHevcEncoderConfig) -> Result<(Self, HardwareEncoderSupport)> {
+impl NativeVideoEncoder {
+    pub fn new(config: NativeVideoEncoderConfig) -> Result<(Self, HardwareEncoderSupport)> {
+        let name = codec_name(config.codec);
         ensure!(
             config.width > 0 && config.width.is_multiple_of(2),
-            "HEVC width must be even"
+            "{name} width must be even"
         );
         ensure!(
             config.height > 0 && config.height.is_multiple_of(2),
-            "HEVC height must be even"
+            "{name} height must be even"
+        );
+        ensure!(
+            config.fps > 0 && config.fps_denominator > 0,
+            "{name} FPS must be greater than zero"
         );
-        ensure!(config.fps > 0, "HEVC FPS must be greater than zero");
         ensure!(
             config.bitrate_bps > 0,
-            "HEVC bitrate must be greater than zero"
+            "{name} bitrate must be greater than zero"
+        );
+        ensure!(
+            (1..=MAX_PIPELINE_DEPTH).contains(&config.pipeline_depth),
+            "{name} pipeline depth must be between 1 and {MAX_PIPELINE_DEPTH}"
         );
 
-        let support = hevc_hardware_support()?;
-        let keyframe_interval = config
-            .fps
-            .checked_mul(2)
-            .and_then(NonZeroU32::new)
-            .context("HEVC keyframe interval overflow")?;
+        ensure!(
+            !config.format.is_ten_bit() || config.codec == CodecType::Hevc,
+            "10-bit encoding requires HEVC Main10, not {name}"
+        );
+        ensure!(
+            config.hdr.is_none() || config.format.is_ten_bit(),
+            "HDR10 requires 10-bit HEVC Main10"
+        );
+
+        crash_report::record("encoder", format!("{config:?}"));
+
+        let support = encoder_hardware_support(config.codec)?;
+        let codec = match config.codec {
+            CodecType::Hevc => CodecConfig::Hevc(HevcEncoderConfig {
+                profile: if config.format.is_ten_bit() {
+                    HevcProfile::Main10
+                } else {
+                    HevcProfile::Main
+                },
+                allow_open_gop: false,
+            }),
+            CodecType::H264 => CodecConfig::H264(H264EncoderConfig {
+                profile: match config.h264_profile {
+                    H264Profile::High => VideoToolboxH264Profile::High,
+                    H264Profile::Main => VideoToolboxH264Profile::Main,
+                    H264Profile::Baseline => VideoToolboxH264Profile::Baseline,
+                },
+            }),
+            CodecType::AV1 => bail!(AV1_ENCODE_UNAVAILABLE),
+        };
+        // The caller forces keyframes on its own cadence; VideoToolbox's limit
+        // is a backstop at twice that interval.
+        let keyframe_interval = match config.keyframe_interval {
+            0 => None,
+            interval => Some(
+                interval
+                    .checked_mul(2)
+                    .and_then(NonZeroU32::new)
+                    .with_context(|| format!("{name} keyframe interval is too large"))?,
+            ),
+        };
+        let keyframe_interval_duration = config.keyframe_interval_duration.or_else(|| {
+            keyframe_interval.map(|interval| {
+                Duration::from_secs_f64(
+                    f64::from(interval.get()) * f64::from(config.fps_denominator)
+                        / f64::from(config.fps),
+                )
+            })
+        });
+        ensure!(
+            keyframe_interval_duration != Some(Duration::ZERO),
+            "{name} keyframe interval duration must be positive"
+        );
         let (output_tx, output_rx) = mpsc::channel();
         let handler = FnEncodeHandler::new(move |result: VideoToolboxResult| {
-            let _ = output_tx.send(result);
+            let _ = output_tx.send((Instant::now(), result));
         });
         let encoder = Encoder::new(
             EncoderConfig {
                 width: config.width,
                 height: config.height,
-                codec: CodecConfig::Hevc(HevcEncoderConfig {
-                    profile: HevcProfile::Main,
-                    allow_open_gop: false,
-                }),
-                pixel_format: PixelFormat::Nv12,
-                average_bitrate: Some(config.bitrate_bps),
+                codec,
+                pixel_format: match config.format {
+                    SurfaceFormat::Nv12 => PixelFormat::Nv12,
+                    SurfaceFormat::P010 => PixelFormat::P010,
+                },
+                average_bitrate: match config.rate_control {
+                    RateControl::AverageBitrate => Some(config.bitrate_bps),
+                    RateControl::Quality => None,
+                },
                 fps_numerator: config.fps,
-                fps_denominator: 1,
-                prioritize_encoding_speed_over_quality: true,
-                real_time: true,
-                maximize_power_efficiency: false,
+                fps_denominator: config.fps_denominator,
+                prioritize_encoding_speed_over_quality: config
+                    .overrides
+                    .prioritize_encoding_speed_over_quality
+                    .unwrap_or(true),
+                real_time: config.overrides.real_time.unwrap_or(true),
+                maximize_power_efficiency: config
+                    .overrides
+                    .maximize_power_efficiency
+                    .unwrap_or(false),
                 allow_frame_reordering: false,
-                allow_temporal_compression: true,
-                max_key_frame_interval: Some(keyframe_interval),
-                max_key_frame_interval_duration: Some(Duration::from_secs(2)),
-                max_frame_delay_count: NonZeroU32::new(1),
+                allow_temporal_compression: config
+                    .overrides
+                    .allow_temporal_compression
+                    .unwrap_or(true),
+                max_key_frame_interval: keyframe_interval,
+                max_key_frame_interval_duration: keyframe_interval_duration,
+                max_frame_delay_count: NonZeroU32::new(config.pipeline_depth),
             },
             handler,
         )
-        .context("failed to create VideoToolbox HEVC encoder")?;
+        .with_context(|| format!("failed to create VideoToolbox {name} encoder"))?;
 
         Ok((
             Self {
                 encoder,
                 output_rx,
+                config,
+                support,
                 width: config.width,
                 height: config.height,
                 order: FrameOrderValidator::default(),
                 pending_count: 0,
+                hdr_sei: config.hdr.as_ref().map(HdrMetadata::sei_nal),
+                idr_requested: false,
             },
             support,
         ))
     }
 
+    pub fn codec(&self) -> CodecType {
+        self.config.codec
+    }
+
     pub fn submit(
         &mut self,
         lease: SurfaceLease,
@@ -153,36 +489,104 @@ impl NativeHevcEncoder {
             self.width,
             self.height
         );
-        self.order.validate(&metadata)?;
         let pixel_buffer = lease.cv_pixel_buffer().as_ptr();
         let pending = PendingFrame {
             lease_id: lease.id(),
             metadata,
-            lease,
+            buffer: PendingBuffer::Lease(lease),
+            submitted_at: Instant::now(),
+        };
+        self.encode(pixel_buffer, pending, force_keyframe)?;
+        self.drain_ready()
+    }
+
+    pub(crate) fn submit_source(
+        &mut self,
+        source: SlotCopy,
+        metadata: FrameMetadata,
+        force_keyframe: bool,
+    ) -> Result<Vec<EncodedFrame>> {
+        let buffer = source.buffer();
+        ensure!(
+            buffer.width() == self.width && buffer.height() == self.height,
+            "slot copy dimensions {}x{} do not match encoder dimensions {}x{}",
+            buffer.width(),
+            buffer.height(),
+            self.width,
+            self.height
+        );
+        let pixel_buffer = buffer.cv_pixel_buffer().as_ptr();
+        let pending = PendingFrame {
+            lease_id: SurfaceLeaseId {
+                surface_id: buffer.surface_id(),
+                generation: metadata.frame_id,
+            },
+            metadata,
+            buffer: PendingBuffer::SlotCopy(source),
+            submitted_at: Instant::now(),
         };
+        self.encode(pixel_buffer, pending, force_keyframe)?;
+        self.drain_ready()
+    }
 
+    fn encode(
+        &mut self,
+        pixel_buffer: *mut c_void,
+        pending: PendingFrame,
+        force_keyframe: bool,
+    ) -> Result<()> {
+        let metadata = pending.metadata;
+        self.order.validate(&metadata)?;
         unsafe {
             self.encoder.encode_pixel_buffer(
                 pixel_buffer,
                 &EncodeOptions {
-                    force_key_frame: force_keyframe,
+                    force_key_frame: force_keyframe || self.idr_requested,
                 },
                 pending,
             )
         }
         .context("failed to submit IOSurface-backed CVPixelBuffer to VideoToolbox")?;
 
+        // Cleared only once VideoToolbox has taken the frame, so a failed
+        // submit leaves the request for the next one.
+        self.idr_requested = false;
         self.order.record_validated(metadata);
         self.pending_count += 1;
-        self.drain_ready()
+        Ok(())
+    }
+
+    /// Blocks until VideoToolbox returns at least one frame, then drains
+    /// anything else that is ready. Returns no frames on timeout or when
+    /// nothing is pending.
+    pub fn wait_for_output(&mut self, timeout: Duration) -> Result<Vec<EncodedFrame>> {
+        if self.pending_count == 0 {
+            return Ok(Vec::new());
+        }
+        let (completed_at, result) = match self.output_rx.recv_timeout(timeout) {
+            Ok(output) => output,
+            Err(RecvTimeoutError::Timeout) => return Ok(Vec::new()),
+            Err(RecvTimeoutError::Disconnected) => {
+                bail!("VideoToolbox callback channel disconnected")
+            }
+        };
+        self.pending_count -= 1;
+        let frame = result
+            .map_err(|error| {
+                anyhow!(error).context("VideoToolbox failed to encode a submitted frame")
+            })
+            .and_then(|frame| complete_frame(frame, completed_at, self.hdr_sei.as_deref()))?;
+        let mut outputs = vec![frame];
+        outputs.extend(self.drain_ready()?);
+        Ok(outputs)
     }
 
     pub fn drain_ready(&mut self) -> Result<Vec<EncodedFrame>> {
         let mut outputs = Vec::new();
         let mut first_error = None;
         loop {
-            let result = match self.output_rx.try_recv() {
-                Ok(result) => result,
+            let (completed_at, result) = match self.output_rx.try_recv() {
+                Ok(output) => output,
                 Err(TryRecvError::Empty) => break,
                 Err(TryRecvError::Disconnected) => {
                     return Err(anyhow!("VideoToolbox callback channel disconnected"));
@@ -193,7 +597,7 @@ impl NativeHevcEncoder {
                 .checked_sub(1)
                 .context("VideoToolbox emitted a callback without a pending frame")?;
             match result {
-                Ok(frame) => match complete_frame(frame) {
+                Ok(frame) => match complete_frame(frame, completed_at, self.hdr_sei.as_deref()) {
                     Ok(frame) => outputs.push(frame),
                     Err(error) if first_error.is_none() => first_error = Some(error),
                     Err(_) => {}
@@ -229,9 +633,198 @@ impl NativeHevcEncoder {
     pub fn pending_count(&self) -> usize {
         self.pending_count
     }
+
+    pub fn request_idr(&mut self) {
+        self.idr_requested = true;
+    }
+
+    /// VideoToolbox sessions take their bitrate at creation, so this flushes
+    /// the current session and opens a replacement.
+    pub fn set_bitrate(&mut self, bitrate_bps: u64) -> Result<Vec<EncodedFrame>> {
+        let outputs = self.finish()?;
+        let (mut encoder, _) = Self::new(NativeVideoEncoderConfig {
+            bitrate_bps,
+            ..self.config
+        })?;
+        encoder.idr_requested = self.idr_requested;
+        *self = encoder;
+        Ok(outputs)
+    }
+}
+
+impl VideoEncoder for NativeVideoEncoder {
+    fn codec(&self) -> CodecType {
+        NativeVideoEncoder::codec(self)
+    }
+
+    fn hardware_support(&self) -> HardwareEncoderSupport {
+        self.support
+    }
+
+    fn submit(
+        &mut self,
+        lease: SurfaceLease,
+        metadata: FrameMetadata,
+        force_keyframe: bool,
+    ) -> Result<Vec<EncodedFrame>> {
+        NativeVideoEncoder::submit(self, lease, metadata, force_keyframe)
+    }
+
+    fn drain_ready(&mut self) -> Result<Vec<EncodedFrame>> {
+        NativeVideoEncoder::drain_ready(self)
+    }
+
+    fn wait_for_output(&mut self, timeout: Duration) -> Result<Vec<EncodedFrame>> {
+        NativeVideoEncoder::wait_for_output(self, timeout)
+    }
+
+    fn finish(&mut self) -> Result<Vec<EncodedFrame>> {
+        NativeVideoEncoder::finish(self)
+    }
+
+    fn set_bitrate(&mut self, bitrate_bps: u64) -> Result<Vec<EncodedFrame>> {
+        NativeVideoEncoder::set_bitrate(self, bitrate_bps)
+    }
+
+    fn pending_count(&self) -> usize {
+        NativeVideoEncoder::pending_count(self)
+    }
+
+    fn request_idr(&mut self) {
+        NativeVideoEncoder::request_idr(self);
+    }
+}
+
+/// The encode stage of the IOSurface loop. Zero-copy encode hands a copy of
+/// the producer's slot, unconverted, to VideoToolbox, which only
+/// `NativeVideoEncoder` can take; other encoders reject it and run with the
+/// Metal pass.
+pub(crate) trait SourceEncoder: VideoEncoder {
+    fn submit_source(
+        &mut self,
+        source: SlotCopy,
+        metadata: FrameMetadata,
+        force_keyframe: bool,
+    ) -> Result<Vec<EncodedFrame>>;
+}
+
+impl SourceEncoder for NativeVideoEncoder {
+    fn submit_source(
+        &mut self,
+        source: SlotCopy,
+        metadata: FrameMetadata,
+        force_keyframe: bool,
+    ) -> Result<Vec<EncodedFrame>> {
+        NativeVideoEncoder::submit_source(self, source, metadata, force_keyframe)
+    }
+}
+
+/// The encoder the bridge loops run, chosen by `EncoderBackend`.
+pub(crate) enum BridgeEncoder {
+    VideoToolbox(NativeVideoEncoder),
+    #[cfg(feature = "software-encoder")]
+    Software(SoftwareEncoder),
+}
+
+macro_rules! each_encoder {
+    ($encoder:expr, $inner:ident => $body:expr) => {
+        match $encoder {
+            BridgeEncoder::VideoToolbox($inner) => $body,
+            #[cfg(feature = "software-encoder")]
+            BridgeEncoder::Software($inner) => $body,
+        }
+    };
+}
+
+impl BridgeEncoder {
+    pub fn open(
+        backend: EncoderBackend,
+        config: NativeVideoEncoderConfig,
+    ) -> Result<(Self, HardwareEncoderSupport)> {
+        if backend == EncoderBackend::Software {
+            return Self::open_software(config);
+        }
+        match NativeVideoEncoder::new(config) {
+            Ok((encoder, support)) => Ok((Self::VideoToolbox(encoder), support)),
+            Err(error) if backend.falls_back(config.codec) => {
+                eprintln!(
+                    "WARNING VideoToolbox could not open an encoder, so the software encoder takes over: {error:#}"
+                );
+                Self::open_software(config)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    #[cfg(feature = "software-encoder")]
+    fn open_software(config: NativeVideoEncoderConfig) -> Result<(Self, HardwareEncoderSupport)> {
+        let encoder = SoftwareEncoder::new(config)?;
+        Ok((Self::Software(encoder), SOFTWARE_ENCODER_SUPPORT))
+    }
+
+    #[cfg(not(feature = "software-encoder"))]
+    fn open_software(_config: NativeVideoEncoderConfig) -> Result<(Self, HardwareEncoderSupport)> {
+        bail!(
+            "this bridge was built without the software encoder; rebuild it with --features software-encoder"
+        )
+    }
+}
+
+impl VideoEncoder for BridgeEncoder {
+    fn codec(&self) -> CodecType {
+        each_encoder!(self, encoder => encoder.codec())
+    }
+
+    fn hardware_support(&self) -> HardwareEncoderSupport {
+        each_encoder!(self, encoder => encoder.hardware_support())
+    }
+
+    fn submit(
+        &mut self,
+        lease: SurfaceLease,
+        metadata: FrameMetadata,
+        force_keyframe: bool,
+    ) -> Result<Vec<EncodedFrame>> {
+        each_encoder!(self, encoder => encoder.submit(lease, metadata, force_keyframe))
+    }
+
+    fn drain_ready(&mut self) -> Result<Vec<EncodedFrame>> {
+        each_encoder!(self, encoder => encoder.drain_ready())
+    }
+
+    fn wait_for_output(&mut self, timeout: Duration) -> Result<Vec<EncodedFrame>> {
+        each_encoder!(self, encoder => encoder.wait_for_output(timeout))
+    }
+
+    fn finish(&mut self) -> Result<Vec<EncodedFrame>> {
+        each_encoder!(self, encoder => encoder.finish())
+    }
+
+    fn set_bitrate(&mut self, bitrate_bps: u64) -> Result<Vec<EncodedFrame>> {
+        each_encoder!(self, encoder => encoder.set_bitrate(bitrate_bps))
+    }
+
+    fn pending_count(&self) -> usize {
+        each_encoder!(self, encoder => encoder.pending_count())
+    }
+
+    fn request_idr(&mut self) {
+        each_encoder!(self, encoder => encoder.request_idr())
+    }
 }
 
-impl Drop for NativeHevcEncoder {
+impl SourceEncoder for BridgeEncoder {
+    fn submit_source(
+        &mut self,
+        source: SlotCopy,
+        metadata: FrameMetadata,
+        force_keyframe: bool,
+    ) -> Result<Vec<EncodedFrame>> {
+        each_encoder!(self, encoder => encoder.submit_source(source, metadata, force_keyframe))
+    }
+}
+
+impl Drop for NativeVideoEncoder {
     fn drop(&mut self) {
         if self.pending_count == 0 {
             return;
@@ -249,21 +842,47 @@ impl Drop for NativeHevcEncoder {
     }
 }
 
-fn complete_frame(frame: VideoToolboxFrame<PendingFrame>) -> Result<EncodedFrame> {
+fn complete_frame(
+    mut frame: VideoToolboxFrame<PendingFrame>,
+    completed_at: Instant,
+    hdr_sei: Option<&[u8]>,
+) -> Result<EncodedFrame> {
     let PendingFrame {
         lease_id,
         metadata,
-        lease,
+        buffer,
+        submitted_at,
     } = frame.user_data;
-    let nal_data = avcc_to_annexb(&frame.data)?;
+    // Annex B start codes are as long as AVCC's NAL lengths, so most frames
+    // are rewritten where VideoToolbox left them. Only an HDR keyframe, with
+    // its SEI in front, is copied.
+    let nal_data = match hdr_sei.filter(|_| frame.keyframe) {
+        Some(sei) => {
+            let mut nal_data = OUTPUT_BUFFERS.take(sei.len() + frame.data.len());
+            nal_data.extend_from_slice(sei);
+            append_annexb(&frame.data, &mut nal_data)?;
+            nal_data
+        }
+        None => {
+            let mut nal_data = std::mem::take(&mut frame.data);
+            annexb_in_place(&mut nal_data)?;
+            nal_data
+        }
+    };
     let decoder_config_nals = if frame.keyframe {
-        let mut config = Vec::new();
-        for nal in frame
-            .vps_list
-            .iter()
-            .chain(frame.sps_list.iter())
-            .chain(frame.pps_list.iter())
-        {
+        let parameter_sets = || {
+            frame
+                .vps_list
+                .iter()
+                .chain(frame.sps_list.iter())
+                .chain(frame.pps_list.iter())
+        };
+        let mut config = OUTPUT_BUFFERS.take(
+            parameter_sets()
+                .map(|nal| NAL_START_CODE.len() + nal.len())
+                .sum(),
+        );
+        for nal in parameter_sets() {
             config.extend_from_slice(&NAL_START_CODE);
             config.extend_from_slice(nal);
         }
@@ -271,7 +890,7 @@ fn complete_frame(frame: VideoToolboxFrame<PendingFrame>) -> Result<EncodedFrame
     } else {
         None
     };
-    drop(lease);
+    drop(buffer);
 
     Ok(EncodedFrame {
         lease_id,
@@ -279,33 +898,93 @@ fn complete_frame(frame: VideoToolboxFrame<PendingFrame>) -> Result<EncodedFrame
         nal_data,
         is_keyframe: frame.keyframe,
         decoder_config_nals,
+        encode_latency: completed_at.saturating_duration_since(submitted_at),
     })
 }
 
-fn avcc_to_annexb(data: &[u8]) -> Result<Vec<u8>> {
-    let mut annexb = Vec::with_capacity(data.len() + 64);
+pub(crate) fn append_annexb(data: &[u8], annexb: &mut Vec<u8>) -> Result<()> {
     let mut offset = 0;
-
     while offset < data.len() {
-        ensure!(
-            data.len() - offset >= 4,
-            "AVCC frame has {} trailing bytes without a NAL length",
-            data.len() - offset
-        );
-        let nal_length = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+        let nal_length = avcc_nal_length(data, offset)?;
         offset += 4;
-        ensure!(nal_length > 0, "AVCC frame contains an empty NAL unit");
-        ensure!(
-            nal_length <= data.len() - offset,
-            "AVCC NAL length {nal_length} exceeds the remaining {} bytes",
-            data.len() - offset
-        );
         annexb.extend_from_slice(&NAL_START_CODE);
         annexb.extend_from_slice(&data[offset..offset + nal_length]);
         offset += nal_length;
     }
+    Ok(())
+}
+
+/// Overwrites each NAL length with a start code. A malformed frame fails
+/// partway through and is discarded.
+pub(crate) fn annexb_in_place(data: &mut [u8]) -> Result<()> {
+    let mut offset = 0;
+    while offset < data.len() {
+        let nal_length = avcc_nal_length(data, offset)?;
+        data[offset..offset + 4].copy_from_slice(&NAL_START_CODE);
+        offset += 4 + nal_length;
+    }
+    Ok(())
+}
+
+/// The length of the NAL whose four-byte AVCC prefix starts at `offset`,
+/// checked against what is left of the frame.
+fn avcc_nal_length(data: &[u8], offset: usize) -> Result<usize> {
+    ensure!(
+        data.len() - offset >= 4,
+        "AVCC frame has {} trailing bytes without a NAL length",
+        data.len() - offset
+    );
+    let nal_length = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+    ensure!(nal_length > 0, "AVCC frame contains an empty NAL unit");
+    ensure!(
+        nal_length <= data.len() - offset - 4,
+        "AVCC NAL length {nal_length} exceeds the remaining {} bytes",
+        data.len() - offset - 4
+    );
+    Ok(nal_length)
+}
+
+/// The NAL units of an Annex B buffer, without their start codes.
+pub(crate) fn annexb_nals(data: &[u8]) -> Vec<&[u8]> {
+    let mut starts = Vec::new();
+    let mut index = 0;
+    while index + 3 <= data.len() {
+        if data[index..index + 3] == [0, 0, 1] {
+            starts.push(index);
+            index += 3;
+        } else {
+            index += 1;
+        }
+    }
+    starts
+        .iter()
+        .enumerate()
+        .map(|(position, &start)| {
+            let end = starts.get(position + 1).copied().unwrap_or(data.len());
+            let mut nal = &data[start + 3..end];
+            // A four-byte start code leaves its leading zero on the NAL
+            // before it.
+            while let [rest @ .., 0] = nal {
+                nal = rest;
+            }
+            nal
+        })
+        .filter(|nal| !nal.is_empty())
+        .collect()
+}
+
+pub(crate) fn nal_type(codec: CodecType, nal: &[u8]) -> u8 {
+    match codec {
+        CodecType::Hevc => (nal[0] >> 1) & 0x3f,
+        _ => nal[0] & 0x1f,
+    }
+}
 
-    Ok(annexb)
+pub(crate) fn is_parameter_set(codec: CodecType, nal: &[u8]) -> bool {
+    match codec {
+        CodecType::Hevc => (32..=34).contains(&nal_type(codec, nal)),
+        _ => matches!(nal_type(codec, nal), 7 | 8),
+    }
 }
 
 #[cfg(test)]
@@ -313,16 +992,76 @@ mod tests {
     use super::*;
 
     #[test]
-    fn converts_multiple_avcc_nals_to_annex_b() {
-        let avcc = [0, 0, 0, 2, 0xaa, 0xbb, 0, 0, 0, 1, 0xcc];
+    fn splits_annex_b_with_either_start_code() {
+        let data = [0, 0, 0, 1, 0x40, 1, 0, 0, 1, 0x42, 0, 0, 3, 1];
+        assert_eq!(annexb_nals(&data), [&[0x40, 1][..], &[0x42, 0, 0, 3, 1]]);
+    }
+
+    #[test]
+    fn parses_overrides_by_videotoolbox_key() {
+        let overrides = VideoToolboxOverrides::parse_list(
+            "kVTCompressionPropertyKey_RealTime=false, MaximizePowerEfficiency=1",
+        )
+        .unwrap();
+        assert_eq!(overrides.real_time, Some(false));
+        assert_eq!(overrides.maximize_power_efficiency, Some(true));
+        assert_eq!(overrides.allow_temporal_compression, None);
+        assert_eq!(
+            overrides.to_string(),
+            "RealTime=false,MaximizePowerEfficiency=true"
+        );
         assert_eq!(
-            avcc_to_annexb(&avcc).unwrap(),
-            [0, 0, 0, 1, 0xaa, 0xbb, 0, 0, 0, 1, 0xcc]
+            VideoToolboxOverrides::parse_list("").unwrap(),
+            VideoToolboxOverrides::default()
         );
+
+        assert!(VideoToolboxOverrides::parse_list("RealTime").is_err());
+        assert!(VideoToolboxOverrides::parse_list("RealTime=maybe").is_err());
+        assert!(VideoToolboxOverrides::parse_list("RealTime=1,RealTime=0").is_err());
+        assert!(VideoToolboxOverrides::parse_list("AllowFrameReordering=true").is_err());
+        let error = VideoToolboxOverrides::parse_list("DataRateLimits=1000000").unwrap_err();
+        assert!(error.to_string().contains("does not expose the session"));
+    }
+
+    #[test]
+    fn converts_multiple_avcc_nals_to_annex_b() {
+        let avcc = [0, 0, 0, 2, 0xaa, 0xbb, 0, 0, 0, 1, 0xcc];
+        let mut annexb = vec![0x4e];
+        append_annexb(&avcc, &mut annexb).unwrap();
+        assert_eq!(annexb, [0x4e, 0, 0, 0, 1, 0xaa, 0xbb, 0, 0, 0, 1, 0xcc]);
     }
 
     #[test]
     fn rejects_truncated_avcc_nals() {
-        assert!(avcc_to_annexb(&[0, 0, 0, 4, 1, 2]).is_err());
+        assert!(append_annexb(&[0, 0, 0, 4, 1, 2], &mut Vec::new()).is_err());
+        assert!(annexb_in_place(&mut [0, 0, 0, 4, 1, 2]).is_err());
+        assert!(annexb_in_place(&mut [0, 0, 0, 1, 1, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn rewrites_avcc_lengths_in_place() {
+        let mut data = [0, 0, 0, 2, 0xaa, 0xbb, 0, 0, 0, 1, 0xcc];
+        let mut copied = Vec::new();
+        append_annexb(&data, &mut copied).unwrap();
+
+        annexb_in_place(&mut data).unwrap();
+        assert_eq!(data, [0, 0, 0, 1, 0xaa, 0xbb, 0, 0, 0, 1, 0xcc]);
+        assert_eq!(data[..], copied[..]);
+    }
+
+    #[test]
+    fn a_zero_keyframe_interval_keeps_only_the_first_idr() {
+        assert!(periodic_keyframe(0, 0));
+        assert!(!(1..1000).any(|index| periodic_keyframe(0, index)));
+        assert!(periodic_keyframe(72, 144));
+        assert!(!periodic_keyframe(72, 145));
+    }
+
+    #[test]
+    fn expresses_client_refresh_rates_as_ratios() {
+        assert_eq!(frame_rate_ratio(72.0), (72, 1));
+        assert_eq!(frame_rate_ratio(119.999_99), (120, 1));
+        assert_eq!(frame_rate_ratio(59.94), (60_000, 1001));
+        assert_eq!(frame_rate_ratio(80.5), (80_500, 1000));
     }
 }
@@ -0,0 +1,371 @@
+/* The producer side of the IOSurface handoff, as the Wine side implements
+ * it, for tests that drive the bridge loop without Wine. Only the mach
+ * transport and pixel writes live here; the frame state machine (slot
+ * generations, frame ids, self-tests, startup barrier) is in
+ * fake_wine_writer.rs. Nothing outside the tests references these symbols,
+ * so the linker leaves them out of the bridge. */
+
+#include <bootstrap.h>
+#include <CoreFoundation/CoreFoundation.h>
+#include <IOSurface/IOSurface.h>
+#include <mach/mach.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <time.h>
+#include <unistd.h>
+
+#include "iosurface_handoff_protocol.h"
+
+enum
+{
+    max_writer_slot_count = 8,
+    lookup_retry_us = 10000
+};
+
+struct writer_request_message
+{
+    mach_msg_header_t header;
+    struct alvr_iosurface_request payload;
+};
+
+struct writer_offer_message
+{
+    mach_msg_header_t header;
+    mach_msg_body_t body;
+    mach_msg_port_descriptor_t surface_port;
+    struct alvr_iosurface_offer payload;
+};
+
+struct writer_frame_ready_message
+{
+    mach_msg_header_t header;
+    struct alvr_iosurface_frame_ready payload;
+};
+
+struct writer_slot_release_message
+{
+    mach_msg_header_t header;
+    struct alvr_iosurface_slot_release payload;
+};
+
+union writer_receive_message
+{
+    struct writer_offer_message offer;
+    struct writer_slot_release_message release;
+    uint8_t bytes[sizeof(struct writer_offer_message) + MAX_TRAILER_SIZE];
+};
+
+struct alvr_fake_wine_writer
+{
+    mach_port_t service_port;
+    mach_port_t reply_port;
+    uint32_t slot_count;
+    IOSurfaceRef surfaces[max_writer_slot_count];
+};
+
+void alvr_fake_wine_writer_destroy(void *opaque_writer);
+
+static void writer_set_error(char *buffer, size_t capacity, const char *message)
+{
+    if (buffer && capacity) snprintf(buffer, capacity, "%s", message);
+}
+
+static void writer_set_mach_error(char *buffer,
+                                  size_t capacity,
+                                  const char *operation,
+                                  kern_return_t result)
+{
+    if (buffer && capacity)
+        snprintf(buffer,
+                 capacity,
+                 "%s failed: %d (%s)",
+                 operation,
+                 result,
+                 mach_error_string(result));
+}
+
+static uint64_t writer_monotonic_milliseconds(void)
+{
+    struct timespec timestamp;
+
+    if (clock_gettime(CLOCK_MONOTONIC, &timestamp) != 0) return 0;
+    return (uint64_t)timestamp.tv_sec * UINT64_C(1000) +
+           (uint64_t)timestamp.tv_nsec / UINT64_C(1000000);
+}
+
+static kern_return_t writer_receive(mach_port_t port,
+                                    union writer_receive_message *message,
+                                    mach_msg_timeout_t timeout_ms)
+{
+    memset(message, 0, sizeof(*message));
+    return mach_msg(&message->offer.header,
+                    MACH_RCV_MSG | MACH_RCV_TIMEOUT,
+                    0,
+                    sizeof(*message),
+                    port,
+                    timeout_ms,
+                    MACH_PORT_NULL);
+}
+
+/* The bridge checks in its service before it waits for a producer, so the
+ * lookup is retried until then. */
+static kern_return_t look_up_service(const char *service_name,
+                                     uint32_t timeout_ms,
+                                     mach_port_t *service_port)
+{
+    const uint64_t deadline_ms = writer_monotonic_milliseconds() + timeout_ms;
+    kern_return_t result;
+
+    for (;;)
+    {
+        result = bootstrap_look_up(bootstrap_port, service_name, service_port);
+        if (result == KERN_SUCCESS ||
+            writer_monotonic_milliseconds() >= deadline_ms)
+            return result;
+        usleep(lookup_retry_us);
+    }
+}
+
+static kern_return_t send_request(struct alvr_fake_wine_writer *writer,
+                                  uint64_t session_nonce,
+                                  const char *build_version,
+                                  uint32_t timeout_ms)
+{
+    struct writer_request_message message = {0};
+
+    message.header.msgh_bits =
+        MACH_MSGH_BITS(MACH_MSG_TYPE_COPY_SEND, MACH_MSG_TYPE_MAKE_SEND_ONCE);
+    message.header.msgh_size = sizeof(message);
+    message.header.msgh_remote_port = writer->service_port;
+    message.header.msgh_local_port = writer->reply_port;
+    message.header.msgh_id = ALVR_IOSURFACE_MESSAGE_REQUEST;
+    message.payload.session_nonce = session_nonce;
+    message.payload.protocol_version = ALVR_IOSURFACE_PROTOCOL_VERSION;
+    message.payload.client_pid = (uint32_t)getpid();
+    snprintf(message.payload.producer_build_version,
+             sizeof(message.payload.producer_build_version),
+             "%s",
+             build_version);
+    return mach_msg(&message.header,
+                    MACH_SEND_MSG | MACH_SEND_TIMEOUT,
+                    message.header.msgh_size,
+                    0,
+                    MACH_PORT_NULL,
+                    timeout_ms,
+                    MACH_PORT_NULL);
+}
+
+/* Imports every slot the way the Wine side does at startup: one request per
+ * slot, each answered by an offer carrying the slot's IOSurface. */
+void *alvr_fake_wine_writer_connect(const char *service_name,
+                                    uint64_t session_nonce,
+                                    const char *build_version,
+                                    uint32_t slot_count,
+                                    uint32_t timeout_ms,
+                                    char *error_buffer,
+                                    size_t error_capacity)
+{
+    struct alvr_fake_wine_writer *writer;
+    kern_return_t result;
+
+    if (!service_name || !build_version || !slot_count ||
+        slot_count > max_writer_slot_count)
+    {
+        writer_set_error(error_buffer, error_capacity, "invalid fake writer configuration");
+        return NULL;
+    }
+    writer = calloc(1, sizeof(*writer));
+    if (!writer)
+    {
+        writer_set_error(error_buffer, error_capacity, "fake writer allocation failed");
+        return NULL;
+    }
+    writer->slot_count = slot_count;
+    result = look_up_service(service_name, timeout_ms, &writer->service_port);
+    if (result != KERN_SUCCESS)
+    {
+        writer_set_mach_error(error_buffer, error_capacity, "bootstrap_look_up", result);
+        alvr_fake_wine_writer_destroy(writer);
+        return NULL;
+    }
+    result = mach_port_allocate(
+        mach_task_self(), MACH_PORT_RIGHT_RECEIVE, &writer->reply_port);
+    if (result != KERN_SUCCESS)
+    {
+        writer_set_mach_error(error_buffer, error_capacity, "mach_port_allocate", result);
+        alvr_fake_wine_writer_destroy(writer);
+        return NULL;
+    }
+    for (uint32_t index = 0; index < slot_count; ++index)
+    {
+        union writer_receive_message received;
+        const struct alvr_iosurface_offer *offer;
+        mach_port_t surface_port;
+
+        result = send_request(writer, session_nonce, build_version, timeout_ms);
+        if (result != KERN_SUCCESS)
+        {
+            writer_set_mach_error(error_buffer, error_capacity, "request send", result);
+            alvr_fake_wine_writer_destroy(writer);
+            return NULL;
+        }
+        result = writer_receive(writer->reply_port, &received, timeout_ms);
+        if (result != KERN_SUCCESS)
+        {
+            writer_set_mach_error(error_buffer, error_capacity, "offer receive", result);
+            alvr_fake_wine_writer_destroy(writer);
+            return NULL;
+        }
+        offer = &received.offer.payload;
+        surface_port = received.offer.surface_port.name;
+        if (received.offer.header.msgh_id != ALVR_IOSURFACE_MESSAGE_OFFER ||
+            !(received.offer.header.msgh_bits & MACH_MSGH_BITS_COMPLEX) ||
+            received.offer.body.msgh_descriptor_count != 1 ||
+            offer->protocol_version != ALVR_IOSURFACE_PROTOCOL_VERSION ||
+            offer->session_nonce != session_nonce ||
+            offer->slot_index >= slot_count ||
+            writer->surfaces[offer->slot_index])
+        {
+            mach_msg_destroy(&received.offer.header);
+            writer_set_error(error_buffer, error_capacity, "unexpected slot offer");
+            alvr_fake_wine_writer_destroy(writer);
+            return NULL;
+        }
+        writer->surfaces[offer->slot_index] = IOSurfaceLookupFromMachPort(surface_port);
+        mach_port_deallocate(mach_task_self(), surface_port);
+        if (!writer->surfaces[offer->slot_index])
+        {
+            writer_set_error(error_buffer, error_capacity, "IOSurfaceLookupFromMachPort failed");
+            alvr_fake_wine_writer_destroy(writer);
+            return NULL;
+        }
+    }
+    return writer;
+}
+
+uint32_t alvr_fake_wine_writer_surface_id(void *opaque_writer, uint32_t slot_index)
+{
+    struct alvr_fake_wine_writer *writer = opaque_writer;
+
+    if (!writer || slot_index >= writer->slot_count) return 0;
+    return IOSurfaceGetID(writer->surfaces[slot_index]);
+}
+
+/* Writes `bgra` to every pixel of a BGRA slot. */
+int alvr_fake_wine_writer_fill(void *opaque_writer,
+                               uint32_t slot_index,
+                               const uint8_t bgra[4])
+{
+    struct alvr_fake_wine_writer *writer = opaque_writer;
+    IOSurfaceRef surface;
+    uint8_t *base;
+    size_t row_bytes;
+
+    if (!writer || slot_index >= writer->slot_count) return -1;
+    surface = writer->surfaces[slot_index];
+    if (IOSurfaceGetPixelFormat(surface) != ALVR_IOSURFACE_PIXEL_FORMAT_BGRA) return -1;
+    if (IOSurfaceLock(surface, 0, NULL) != kIOReturnSuccess) return -2;
+    base = IOSurfaceGetBaseAddress(surface);
+    row_bytes = IOSurfaceGetBytesPerRow(surface);
+    for (size_t y = 0; base && y < IOSurfaceGetHeight(surface); ++y)
+    {
+        for (size_t x = 0; x < IOSurfaceGetWidth(surface); ++x)
+            memcpy(base + y * row_bytes + x * 4, bgra, 4);
+    }
+    IOSurfaceUnlock(surface, 0, NULL);
+    return base ? 0 : -2;
+}
+
+/* Sends one frame-ready with a send-once right for its slot release. The
+ * protocol version and producer pid are filled in here. */
+int alvr_fake_wine_writer_send(void *opaque_writer,
+                               const struct alvr_iosurface_frame_ready *frame,
+                               char *error_buffer,
+                               size_t error_capacity)
+{
+    struct alvr_fake_wine_writer *writer = opaque_writer;
+    struct writer_frame_ready_message message = {0};
+    kern_return_t result;
+
+    if (!writer || !frame)
+    {
+        writer_set_error(error_buffer, error_capacity, "invalid fake writer frame");
+        return -1;
+    }
+    message.header.msgh_bits =
+        MACH_MSGH_BITS(MACH_MSG_TYPE_COPY_SEND, MACH_MSG_TYPE_MAKE_SEND_ONCE);
+    message.header.msgh_size = sizeof(message);
+    message.header.msgh_remote_port = writer->service_port;
+    message.header.msgh_local_port = writer->reply_port;
+    message.header.msgh_id = ALVR_IOSURFACE_MESSAGE_FRAME_READY;
+    message.payload = *frame;
+    message.payload.protocol_version = ALVR_IOSURFACE_PROTOCOL_VERSION;
+    message.payload.producer_pid = (uint32_t)getpid();
+    result = mach_msg(&message.header,
+                      MACH_SEND_MSG | MACH_SEND_TIMEOUT,
+                      message.header.msgh_size,
+                      0,
+                      MACH_PORT_NULL,
+                      1000,
+                      MACH_PORT_NULL);
+    if (result != KERN_SUCCESS)
+    {
+        writer_set_mach_error(error_buffer, error_capacity, "frame-ready send", result);
+        return -2;
+    }
+    return 0;
+}
+
+/* Waits for the bridge to hand a slot back. Returns 1 on timeout. */
+int alvr_fake_wine_writer_receive_release(void *opaque_writer,
+                                          uint32_t timeout_ms,
+                                          struct alvr_iosurface_slot_release *release,
+                                          char *error_buffer,
+                                          size_t error_capacity)
+{
+    struct alvr_fake_wine_writer *writer = opaque_writer;
+    union writer_receive_message received;
+    kern_return_t result;
+
+    if (!writer || !release)
+    {
+        writer_set_error(error_buffer, error_capacity, "invalid fake writer release");
+        return -1;
+    }
+    result = writer_receive(writer->reply_port, &received, timeout_ms);
+    if (result == MACH_RCV_TIMED_OUT) return 1;
+    if (result != KERN_SUCCESS)
+    {
+        writer_set_mach_error(error_buffer, error_capacity, "slot release receive", result);
+        return -2;
+    }
+    if (received.release.header.msgh_id != ALVR_IOSURFACE_MESSAGE_SLOT_RELEASE ||
+        received.release.header.msgh_size != sizeof(struct writer_slot_release_message))
+    {
+        mach_msg_destroy(&received.release.header);
+        writer_set_error(error_buffer, error_capacity, "unexpected message instead of a release");
+        return -3;
+    }
+    *release = received.release.payload;
+    return 0;
+}
+
+void alvr_fake_wine_writer_destroy(void *opaque_writer)
+{
+    struct alvr_fake_wine_writer *writer = opaque_writer;
+
+    if (!writer) return;
+    for (uint32_t index = 0; index < writer->slot_count; ++index)
+    {
+        if (writer->surfaces[index]) CFRelease(writer->surfaces[index]);
+    }
+    if (writer->reply_port != MACH_PORT_NULL)
+        mach_port_mod_refs(
+            mach_task_self(), writer->reply_port, MACH_PORT_RIGHT_RECEIVE, -1);
+    if (writer->service_port != MACH_PORT_NULL)
+        mach_port_deallocate(mach_task_self(), writer->service_port);
+    free(writer);
+}
@@ -0,0 +1,304 @@
+use crate::native_source::{
+    BRIDGE_BUILD_VERSION, FRAME_FLAG_CONTENT_CRC32, FRAME_FLAG_SELF_TEST,
+    FRAME_FLAG_STARTUP_BARRIER, STATUS_PASS, STATUS_SESSION_CLOSED,
+};
+use anyhow::{Result, anyhow, bail, ensure};
+use std::{
+    ffi::{CStr, CString, c_char, c_int, c_void},
+    ptr::NonNull,
+    time::Duration,
+};
+
+const ERROR_CAPACITY: usize = 512;
+/// Frames are spaced as if the game rendered at 90 Hz.
+const FRAME_INTERVAL_NS: u64 = 11_111_111;
+const IDENTITY_POSE: [[f32; 4]; 3] = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 1.6],
+    [0.0, 0.0, 1.0, 0.0],
+];
+
+/// `struct alvr_iosurface_frame_ready` in iosurface_handoff_protocol.h.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct FrameReady {
+    session_nonce: u64,
+    frame_id: u64,
+    video_timestamp_ns: u64,
+    protocol_version: u32,
+    slot_index: u32,
+    generation: u32,
+    flags: u32,
+    surface_id: u32,
+    width: u32,
+    height: u32,
+    sample_x: u32,
+    sample_y: u32,
+    expected_bgra: [u8; 4],
+    producer_pid: u32,
+    content_crc32: u32,
+    pose_timestamp_ns: u64,
+    pose_generation: u64,
+    pose: [[f32; 4]; 3],
+}
+
+/// `struct alvr_iosurface_slot_release` in iosurface_handoff_protocol.h.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct SlotRelease {
+    session_nonce: u64,
+    frame_id: u64,
+    protocol_version: u32,
+    slot_index: u32,
+    generation: u32,
+    status: u32,
+    surface_id: u32,
+    consumer_pid: u32,
+    actual_bgra: [u8; 4],
+    reserved: u32,
+}
+
+const _: () = assert!(size_of::<FrameReady>() == 136);
+const _: () = assert!(size_of::<SlotRelease>() == 48);
+
+unsafe extern "C" {
+    fn alvr_fake_wine_writer_connect(
+        service_name: *const c_char,
+        session_nonce: u64,
+        build_version: *const c_char,
+        slot_count: u32,
+        timeout_ms: u32,
+        error_buffer: *mut c_char,
+        error_capacity: usize,
+    ) -> *mut c_void;
+    fn alvr_fake_wine_writer_surface_id(writer: *mut c_void, slot_index: u32) -> u32;
+    fn alvr_fake_wine_writer_fill(writer: *mut c_void, slot_index: u32, bgra: *const u8) -> c_int;
+    fn alvr_fake_wine_writer_send(
+        writer: *mut c_void,
+        frame: *const FrameReady,
+        error_buffer: *mut c_char,
+        error_capacity: usize,
+    ) -> c_int;
+    fn alvr_fake_wine_writer_receive_release(
+        writer: *mut c_void,
+        timeout_ms: u32,
+        release: *mut SlotRelease,
+        error_buffer: *mut c_char,
+        error_capacity: usize,
+    ) -> c_int;
+    fn alvr_fake_wine_writer_destroy(writer: *mut c_void);
+}
+
+/// Stands in for the Wine side of the IOSurface handoff: it imports the
+/// bridge's slots over the real mach protocol and writes frames into them in
+/// the order the producer does. Each slot's startup self-test comes first,
+/// then the startup barrier, then rendered frames with increasing frame ids,
+/// video timestamps, and pose generations, each slot reused only after the
+/// bridge releases it. A test runs it on its own thread against a bridge
+/// loop in the same process, so a protocol change on either side shows up
+/// as a failed run rather than at the first session with Wine.
+pub(crate) struct FakeWineWriter {
+    writer: NonNull<c_void>,
+    session_nonce: u64,
+    width: u32,
+    height: u32,
+    generations: Vec<u32>,
+    held: Vec<bool>,
+    next_slot: usize,
+    frame_id: u64,
+    pose_generation: u64,
+    closed: bool,
+}
+
+impl FakeWineWriter {
+    /// Imports every slot of the bridge's BGRA pool. The bridge only needs
+    /// to check in its service within `timeout`.
+    pub fn connect(
+        service_name: &str,
+        session_nonce: u64,
+        (width, height): (u32, u32),
+        slot_count: u32,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let service_name = CString::new(service_name)?;
+        let build_version = CString::new(BRIDGE_BUILD_VERSION)?;
+        let mut error = [0 as c_char; ERROR_CAPACITY];
+        let writer = unsafe {
+            alvr_fake_wine_writer_connect(
+                service_name.as_ptr(),
+                session_nonce,
+                build_version.as_ptr(),
+                slot_count,
+                timeout_millis(timeout),
+                error.as_mut_ptr(),
+                error.len(),
+            )
+        };
+        let writer = NonNull::new(writer).ok_or_else(|| anyhow!(error_message(&error)))?;
+        Ok(Self {
+            writer,
+            session_nonce,
+            width,
+            height,
+            generations: vec![0; slot_count as usize],
+            held: vec![false; slot_count as usize],
+            next_slot: 0,
+            frame_id: 0,
+            pose_generation: 0,
+            closed: false,
+        })
+    }
+
+    /// Runs the startup sequence: a self-test per slot, then the barrier,
+    /// returning once the bridge lets production frames through.
+    pub fn start(&mut self) -> Result<()> {
+        for slot_index in 0..self.generations.len() {
+            let bgra = [slot_index as u8 * 40, 80, 160, 255];
+            self.send(slot_index, FRAME_FLAG_SELF_TEST, bgra, 0)?;
+            ensure!(
+                self.wait_for_release()? == STATUS_PASS,
+                "self-test of slot {slot_index} failed"
+            );
+        }
+        self.send(0, FRAME_FLAG_STARTUP_BARRIER, [0, 0, 0, 255], 0)?;
+        ensure!(
+            self.wait_for_release()? == STATUS_PASS,
+            "startup barrier was not released"
+        );
+        Ok(())
+    }
+
+    /// Renders a frame filled with `bgra` into the next free slot, waiting
+    /// for the bridge to release one if all are held. Returns `false`, with
+    /// nothing sent, once the bridge has closed the session.
+    pub fn send_frame(&mut self, bgra: [u8; 4]) -> Result<bool> {
+        while !self.closed && self.held.iter().all(|held| *held) {
+            self.wait_for_release()?;
+        }
+        while !self.closed && self.receive_release(Duration::ZERO)?.is_some() {}
+        if self.closed {
+            return Ok(false);
+        }
+        let slot_count = self.held.len();
+        let slot_index = (0..slot_count)
+            .map(|offset| (self.next_slot + offset) % slot_count)
+            .find(|slot_index| !self.held[*slot_index])
+            .expect("a free slot");
+        self.next_slot = (slot_index + 1) % slot_count;
+        let pixels = (self.width * self.height) as usize;
+        let crc = crc32(bgra.iter().copied().cycle().take(pixels * 4));
+        self.send(slot_index, FRAME_FLAG_CONTENT_CRC32, bgra, crc)?;
+        Ok(true)
+    }
+
+    /// Waits until the bridge has released every held slot.
+    pub fn drain(&mut self) -> Result<()> {
+        while self.held.iter().any(|held| *held) {
+            self.wait_for_release()?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, slot_index: usize, flags: u32, bgra: [u8; 4], crc: u32) -> Result<()> {
+        let writer = self.writer.as_ptr();
+        let status =
+            unsafe { alvr_fake_wine_writer_fill(writer, slot_index as u32, bgra.as_ptr()) };
+        ensure!(status == 0, "failed to write slot {slot_index}");
+        let rendered = flags & (FRAME_FLAG_SELF_TEST | FRAME_FLAG_STARTUP_BARRIER) == 0;
+        self.frame_id += 1;
+        self.generations[slot_index] += 1;
+        let mut frame = FrameReady {
+            session_nonce: self.session_nonce,
+            frame_id: self.frame_id,
+            slot_index: slot_index as u32,
+            generation: self.generations[slot_index],
+            flags,
+            surface_id: unsafe { alvr_fake_wine_writer_surface_id(writer, slot_index as u32) },
+            width: self.width,
+            height: self.height,
+            expected_bgra: bgra,
+            content_crc32: crc,
+            ..FrameReady::default()
+        };
+        if rendered {
+            self.pose_generation += 1;
+            frame.video_timestamp_ns = self.frame_id * FRAME_INTERVAL_NS;
+            frame.pose_timestamp_ns = frame.video_timestamp_ns;
+            frame.pose_generation = self.pose_generation;
+            frame.pose = IDENTITY_POSE;
+        }
+        let mut error = [0 as c_char; ERROR_CAPACITY];
+        let status =
+            unsafe { alvr_fake_wine_writer_send(writer, &frame, error.as_mut_ptr(), error.len()) };
+        ensure!(status == 0, "{}", error_message(&error));
+        self.held[slot_index] = true;
+        Ok(())
+    }
+
+    fn wait_for_release(&mut self) -> Result<u32> {
+        self.receive_release(Duration::from_secs(10))?
+            .ok_or_else(|| anyhow!("the bridge held every slot for 10 seconds"))
+    }
+
+    fn receive_release(&mut self, timeout: Duration) -> Result<Option<u32>> {
+        let mut release = SlotRelease::default();
+        let mut error = [0 as c_char; ERROR_CAPACITY];
+        let status = unsafe {
+            alvr_fake_wine_writer_receive_release(
+                self.writer.as_ptr(),
+                timeout_millis(timeout),
+                &mut release,
+                error.as_mut_ptr(),
+                error.len(),
+            )
+        };
+        match status {
+            0 => {}
+            1 => return Ok(None),
+            _ => bail!("{}", error_message(&error)),
+        }
+        let slot_index = release.slot_index as usize;
+        ensure!(
+            release.session_nonce == self.session_nonce
+                && slot_index < self.held.len()
+                && release.generation == self.generations[slot_index]
+                && self.held[slot_index],
+            "release of frame {} slot={slot_index} generation={} does not match a held slot",
+            release.frame_id,
+            release.generation
+        );
+        self.held[slot_index] = false;
+        if release.status == STATUS_SESSION_CLOSED {
+            self.closed = true;
+        }
+        Ok(Some(release.status))
+    }
+}
+
+impl Drop for FakeWineWriter {
+    fn drop(&mut self) {
+        unsafe { alvr_fake_wine_writer_destroy(self.writer.as_ptr()) }
+    }
+}
+
+// The writer is only used from the thread it is moved to.
+unsafe impl Send for FakeWineWriter {}
+
+/// zlib's CRC-32, as the producer computes it over the pixels it wrote.
+fn crc32(bytes: impl Iterator<Item = u8>) -> u32 {
+    !bytes.fold(u32::MAX, |crc, byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+fn timeout_millis(timeout: Duration) -> u32 {
+    u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX)
+}
+
+fn error_message(buffer: &[c_char]) -> String {
+    unsafe { CStr::from_ptr(buffer.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
@@ -0,0 +1,620 @@
+use crate::FrameMetadata;
+use anyhow::{Context, Result, bail, ensure};
+use std::sync::{Mutex, MutexGuard};
+
+const LUMA_BLACK: u8 = 16;
+const LUMA_WHITE: u8 = 235;
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+
+pub type FilterFactory = fn(Option<&str>) -> Result<Box<dyn FrameFilter>>;
+
+static REGISTERED_FILTERS: Mutex<Vec<(&'static str, FilterFactory)>> = Mutex::new(Vec::new());
+
+pub struct Nv12Frame<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub luma: &'a mut [u8],
+    pub luma_row_bytes: usize,
+    pub chroma: &'a mut [u8],
+    pub chroma_row_bytes: usize,
+}
+
+impl Nv12Frame<'_> {
+    fn luma_row(&mut self, row: usize) -> &mut [u8] {
+        let start = row * self.luma_row_bytes;
+        &mut self.luma[start..start + self.width]
+    }
+
+    fn neutralize_chroma(&mut self, x: usize, y: usize) {
+        let offset = (y / 2) * self.chroma_row_bytes + (x / 2) * 2;
+        self.chroma[offset..offset + 2].fill(128);
+    }
+}
+
+pub trait FrameFilter: Send {
+    fn name(&self) -> &str;
+
+    fn apply(&mut self, frame: &mut Nv12Frame<'_>, metadata: &FrameMetadata) -> Result<()>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterSpec {
+    Sharpen(f32),
+    Gamma(f32),
+    Watermark(String),
+    DebugOverlay,
+    TestPattern,
+    Registered {
+        name: String,
+        argument: Option<String>,
+    },
+}
+
+impl FilterSpec {
+    pub fn parse_list(list: &str) -> Result<Vec<Self>> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(Self::parse)
+            .collect()
+    }
+
+    fn parse(entry: &str) -> Result<Self> {
+        let (name, argument) = match entry.split_once(':') {
+            Some((name, argument)) => (name, Some(argument)),
+            None => (entry, None),
+        };
+        let number = |default: f32| -> Result<f32> {
+            argument.map_or(Ok(default), |argument| {
+                argument
+                    .parse()
+                    .with_context(|| format!("invalid {name} filter argument {argument:?}"))
+            })
+        };
+        let spec = match name {
+            "sharpen" => Self::Sharpen(number(0.5)?),
+            "gamma" => Self::Gamma(number(1.0)?),
+            "watermark" => Self::Watermark(argument.unwrap_or("ALVR").to_owned()),
+            "overlay" => Self::DebugOverlay,
+            "pattern" => Self::TestPattern,
+            name if registered_factory(name).is_some() => Self::Registered {
+                name: name.to_owned(),
+                argument: argument.map(str::to_owned),
+            },
+            name => bail!("unknown frame filter {name:?}"),
+        };
+        Ok(spec)
+    }
+
+    fn build(&self) -> Result<Box<dyn FrameFilter>> {
+        Ok(match self {
+            Self::Sharpen(amount) => Box::new(Sharpen::new(*amount)?),
+            Self::Gamma(gamma) => Box::new(Gamma::new(*gamma)?),
+            Self::Watermark(text) => Box::new(Watermark::new(text)),
+            Self::DebugOverlay => Box::new(DebugOverlay),
+            Self::TestPattern => Box::new(TestPattern),
+            Self::Registered { name, argument } => {
+                let factory = registered_factory(name)
+                    .with_context(|| format!("frame filter {name:?} is no longer registered"))?;
+                factory(argument.as_deref())?
+            }
+        })
+    }
+}
+
+pub fn register_filter(name: &'static str, factory: FilterFactory) -> Result<()> {
+    ensure!(
+        !matches!(
+            name,
+            "sharpen" | "gamma" | "watermark" | "overlay" | "pattern"
+        ),
+        "frame filter {name:?} is built in"
+    );
+    let mut registered = lock_registered();
+    ensure!(
+        registered.iter().all(|(existing, _)| *existing != name),
+        "frame filter {name:?} is already registered"
+    );
+    registered.push((name, factory));
+    Ok(())
+}
+
+fn registered_factory(name: &str) -> Option<FilterFactory> {
+    lock_registered()
+        .iter()
+        .find(|(registered, _)| *registered == name)
+        .map(|(_, factory)| *factory)
+}
+
+fn lock_registered() -> MutexGuard<'static, Vec<(&'static str, FilterFactory)>> {
+    REGISTERED_FILTERS
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+}
+
+#[derive(Default)]
+pub struct FilterChain {
+    filters: Vec<Box<dyn FrameFilter>>,
+}
+
+impl FilterChain {
+    pub fn from_specs(specs: &[FilterSpec]) -> Result<Self> {
+        let mut chain = Self::default();
+        for spec in specs {
+            chain.push(spec.build()?);
+        }
+        Ok(chain)
+    }
+
+    pub fn push(&mut self, filter: Box<dyn FrameFilter>) {
+        self.filters.push(filter);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.filters.iter().map(|filter| filter.name()).collect()
+    }
+
+    pub fn apply(&mut self, frame: &mut Nv12Frame<'_>, metadata: &FrameMetadata) -> Result<()> {
+        for filter in &mut self.filters {
+            let name = filter.name().to_owned();
+            filter
+                .apply(frame, metadata)
+                .with_context(|| format!("frame filter {name} failed"))?;
+        }
+        Ok(())
+    }
+}
+
+struct Sharpen {
+    amount: f32,
+    previous: Vec<u8>,
+    current: Vec<u8>,
+    next: Vec<u8>,
+}
+
+impl Sharpen {
+    fn new(amount: f32) -> Result<Self> {
+        ensure!(
+            amount.is_finite() && (0.0..=4.0).contains(&amount),
+            "sharpen amount must be between 0 and 4"
+        );
+        Ok(Self {
+            amount,
+            previous: Vec::new(),
+            current: Vec::new(),
+            next: Vec::new(),
+        })
+    }
+}
+
+impl FrameFilter for Sharpen {
+    fn name(&self) -> &str {
+        "sharpen"
+    }
+
+    fn apply(&mut self, frame: &mut Nv12Frame<'_>, _: &FrameMetadata) -> Result<()> {
+        let eye_width = frame.width / 2;
+        if eye_width < 3 || frame.height < 3 {
+            return Ok(());
+        }
+        self.previous.clear();
+        self.previous.extend_from_slice(frame.luma_row(0));
+        for row in 1..frame.height - 1 {
+            self.current.clear();
+            self.current.extend_from_slice(frame.luma_row(row));
+            self.next.clear();
+            self.next.extend_from_slice(frame.luma_row(row + 1));
+            let output = frame.luma_row(row);
+            for eye_start in [0, eye_width] {
+                for x in eye_start + 1..eye_start + eye_width - 1 {
+                    let center = f32::from(self.current[x]);
+                    let neighbors = f32::from(self.previous[x])
+                        + f32::from(self.next[x])
+                        + f32::from(self.current[x - 1])
+                        + f32::from(self.current[x + 1]);
+                    let sharpened = center + self.amount * (center - neighbors / 4.0);
+                    output[x] = sharpened
+                        .round()
+                        .clamp(f32::from(LUMA_BLACK), f32::from(LUMA_WHITE))
+                        as u8;
+                }
+            }
+            std::mem::swap(&mut self.previous, &mut self.current);
+        }
+        Ok(())
+    }
+}
+
+struct Gamma {
+    table: [u8; 256],
+}
+
+impl Gamma {
+    fn new(gamma: f32) -> Result<Self> {
+        ensure!(
+            gamma.is_finite() && (0.1..=10.0).contains(&gamma),
+            "gamma must be between 0.1 and 10"
+        );
+        let range = f32::from(LUMA_WHITE - LUMA_BLACK);
+        let table = std::array::from_fn(|value| {
+            let clamped = (value as u8).clamp(LUMA_BLACK, LUMA_WHITE);
+            let normalized = f32::from(clamped - LUMA_BLACK) / range;
+            (f32::from(LUMA_BLACK) + normalized.powf(1.0 / gamma) * range).round() as u8
+        });
+        Ok(Self { table })
+    }
+}
+
+impl FrameFilter for Gamma {
+    fn name(&self) -> &str {
+        "gamma"
+    }
+
+    fn apply(&mut self, frame: &mut Nv12Frame<'_>, _: &FrameMetadata) -> Result<()> {
+        for row in 0..frame.height {
+            for value in frame.luma_row(row) {
+                *value = self.table[usize::from(*value)];
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Watermark {
+    text: String,
+}
+
+impl Watermark {
+    fn new(text: &str) -> Self {
+        Self {
+            text: text.to_ascii_uppercase(),
+        }
+    }
+}
+
+impl FrameFilter for Watermark {
+    fn name(&self) -> &str {
+        "watermark"
+    }
+
+    fn apply(&mut self, frame: &mut Nv12Frame<'_>, _: &FrameMetadata) -> Result<()> {
+        let scale = (frame.height / 240).max(1);
+        let text_width = self.text.len() * (GLYPH_WIDTH + 1) * scale;
+        let eye_width = frame.width / 2;
+        let y = frame.height.saturating_sub((GLYPH_HEIGHT + 2) * scale * 2);
+        for eye_start in [0, eye_width] {
+            let x = (eye_start + eye_width).saturating_sub(text_width + 4 * scale);
+            draw_text(frame, x.max(eye_start), y, scale, &self.text, |luma| {
+                ((u16::from(luma) + u16::from(LUMA_WHITE)) / 2) as u8
+            });
+        }
+        Ok(())
+    }
+}
+
+struct DebugOverlay;
+
+impl FrameFilter for DebugOverlay {
+    fn name(&self) -> &str {
+        "overlay"
+    }
+
+    fn apply(&mut self, frame: &mut Nv12Frame<'_>, metadata: &FrameMetadata) -> Result<()> {
+        let scale = (frame.height / 360).max(1);
+        let text = format!(
+            "F {} E {} T {}",
+            metadata.frame_id,
+            metadata.stream_epoch,
+            metadata.video_timestamp.as_millis()
+        );
+        let eye_width = frame.width / 2;
+        for eye_start in [0, eye_width] {
+            draw_text(
+                frame,
+                eye_start + 4 * scale,
+                4 * scale,
+                scale,
+                &text,
+                |_| LUMA_WHITE,
+            );
+        }
+        Ok(())
+    }
+}
+
+// 75% color bars in video-range BT.709 YCbCr.
+const PATTERN_BARS: [[u8; 3]; 8] = [
+    [180, 128, 128],
+    [168, 44, 136],
+    [145, 147, 44],
+    [133, 63, 52],
+    [63, 193, 204],
+    [51, 109, 212],
+    [28, 212, 120],
+    [16, 128, 128],
+];
+
+/// Replaces each eye with color bars and a white bar that sweeps one step
+/// per frame, so motion and dropped frames are visible on the headset.
+struct TestPattern;
+
+impl FrameFilter for TestPattern {
+    fn name(&self) -> &str {
+        "pattern"
+    }
+
+    fn apply(&mut self, frame: &mut Nv12Frame<'_>, metadata: &FrameMetadata) -> Result<()> {
+        let eye_width = frame.width / 2;
+        if eye_width < 2 {
+            return Ok(());
+        }
+        let bar_width = eye_width.div_ceil(PATTERN_BARS.len());
+        let sweep_width = (eye_width / 64).max(2);
+        let sweep_x = (metadata.frame_id as usize * sweep_width) % eye_width;
+        let in_sweep = |x: usize| (sweep_x..sweep_x + sweep_width).contains(&(x % eye_width));
+        let bar = |x: usize| PATTERN_BARS[(x % eye_width) / bar_width];
+        for row in 0..frame.height {
+            for (x, luma) in frame.luma_row(row).iter_mut().enumerate() {
+                *luma = if in_sweep(x) { LUMA_WHITE } else { bar(x)[0] };
+            }
+        }
+        for row in 0..frame.height.div_ceil(2) {
+            let start = row * frame.chroma_row_bytes;
+            let chroma = &mut frame.chroma[start..start + frame.width / 2 * 2];
+            for (pair, chroma) in chroma.chunks_exact_mut(2).enumerate() {
+                let x = pair * 2;
+                let [_, cb, cr] = if in_sweep(x) { [0, 128, 128] } else { bar(x) };
+                chroma.copy_from_slice(&[cb, cr]);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn draw_text(
+    frame: &mut Nv12Frame<'_>,
+    x: usize,
+    y: usize,
+    scale: usize,
+    text: &str,
+    shade: impl Fn(u8) -> u8,
+) {
+    for (index, character) in text.chars().enumerate() {
+        let glyph = glyph(character);
+        let glyph_x = x + index * (GLYPH_WIDTH + 1) * scale;
+        for (row, bits) in glyph.iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let pixel_x = glyph_x + column * scale + dx;
+                        let pixel_y = y + row * scale + dy;
+                        if pixel_x >= frame.width || pixel_y >= frame.height {
+                            continue;
+                        }
+                        let luma = &mut frame.luma[pixel_y * frame.luma_row_bytes + pixel_x];
+                        *luma = shade(*luma);
+                        frame.neutralize_chroma(pixel_x, pixel_y);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn glyph(character: char) -> [u8; GLYPH_HEIGHT] {
+    match character {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        _ => [0; GLYPH_HEIGHT],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_common::ViewParams;
+    use std::time::Duration;
+
+    struct TestFrame {
+        width: usize,
+        height: usize,
+        luma: Vec<u8>,
+        chroma: Vec<u8>,
+    }
+
+    impl TestFrame {
+        fn new(width: usize, height: usize, luma: u8) -> Self {
+            Self {
+                width,
+                height,
+                luma: vec![luma; width * height],
+                chroma: vec![90; width * height / 2],
+            }
+        }
+
+        fn nv12(&mut self) -> Nv12Frame<'_> {
+            Nv12Frame {
+                width: self.width,
+                height: self.height,
+                luma: &mut self.luma,
+                luma_row_bytes: self.width,
+                chroma: &mut self.chroma,
+                chroma_row_bytes: self.width,
+            }
+        }
+    }
+
+    fn metadata() -> FrameMetadata {
+        FrameMetadata {
+            frame_id: 7,
+            stream_epoch: 1,
+            video_timestamp: Duration::from_millis(80),
+            pose_timestamp: Duration::from_millis(80),
+            global_view_params: [ViewParams::default(); 2],
+        }
+    }
+
+    #[test]
+    fn parses_builtin_filters_with_arguments() {
+        assert_eq!(
+            FilterSpec::parse_list("sharpen:0.25, gamma:1.2,watermark:demo,overlay").unwrap(),
+            [
+                FilterSpec::Sharpen(0.25),
+                FilterSpec::Gamma(1.2),
+                FilterSpec::Watermark("demo".into()),
+                FilterSpec::DebugOverlay,
+            ]
+        );
+        assert!(FilterSpec::parse_list("").unwrap().is_empty());
+        assert!(FilterSpec::parse_list("blur").is_err());
+        assert!(FilterSpec::parse_list("gamma:bright").is_err());
+    }
+
+    #[test]
+    fn builds_registered_custom_filters_by_name() {
+        struct Invert;
+
+        impl FrameFilter for Invert {
+            fn name(&self) -> &str {
+                "invert"
+            }
+
+            fn apply(&mut self, frame: &mut Nv12Frame<'_>, _: &FrameMetadata) -> Result<()> {
+                for value in frame.luma.iter_mut() {
+                    *value = LUMA_WHITE - (*value - LUMA_BLACK);
+                }
+                Ok(())
+            }
+        }
+
+        register_filter("invert", |_| Ok(Box::new(Invert))).unwrap();
+        assert!(register_filter("invert", |_| Ok(Box::new(Invert))).is_err());
+        assert!(register_filter("gamma", |_| Ok(Box::new(Invert))).is_err());
+
+        let specs = FilterSpec::parse_list("invert").unwrap();
+        let mut chain = FilterChain::from_specs(&specs).unwrap();
+        let mut frame = TestFrame::new(8, 4, 40);
+        chain.apply(&mut frame.nv12(), &metadata()).unwrap();
+
+        assert_eq!(chain.names(), ["invert"]);
+        assert!(frame.luma.iter().all(|value| *value == 211));
+    }
+
+    #[test]
+    fn unit_gamma_preserves_video_range_luma() {
+        let mut chain = FilterChain::from_specs(&[FilterSpec::Gamma(1.0)]).unwrap();
+        let mut frame = TestFrame::new(4, 2, 0);
+        frame.luma = vec![0, 16, 100, 235, 255, 126, 17, 234];
+
+        chain.apply(&mut frame.nv12(), &metadata()).unwrap();
+
+        assert_eq!(frame.luma, [16, 16, 100, 235, 235, 126, 17, 234]);
+    }
+
+    #[test]
+    fn sharpen_keeps_flat_regions_and_each_eye_separate() {
+        let mut chain = FilterChain::from_specs(&[FilterSpec::Sharpen(1.0)]).unwrap();
+        let mut frame = TestFrame::new(8, 3, 100);
+        for row in 0..3 {
+            frame.luma[row * 8 + 4..row * 8 + 8].fill(200);
+        }
+        let original = frame.luma.clone();
+
+        chain.apply(&mut frame.nv12(), &metadata()).unwrap();
+
+        assert_eq!(frame.luma, original);
+    }
+
+    #[test]
+    fn sharpen_boosts_a_bright_center_pixel() {
+        let mut chain = FilterChain::from_specs(&[FilterSpec::Sharpen(1.0)]).unwrap();
+        let mut frame = TestFrame::new(8, 3, 100);
+        frame.luma[8 + 1] = 140;
+
+        chain.apply(&mut frame.nv12(), &metadata()).unwrap();
+
+        assert_eq!(frame.luma[8 + 1], 180);
+    }
+
+    #[test]
+    fn pattern_sweeps_a_bar_across_both_eyes() {
+        let mut chain = FilterChain::from_specs(&[FilterSpec::TestPattern]).unwrap();
+        let mut frame = TestFrame::new(256, 4, 16);
+
+        chain.apply(&mut frame.nv12(), &metadata()).unwrap();
+
+        // Frame 7 puts the two-pixel sweep at x=14 in each eye.
+        assert_eq!(frame.luma[14], LUMA_WHITE);
+        assert_eq!(frame.luma[128 + 15], LUMA_WHITE);
+        assert_eq!(frame.luma[0], PATTERN_BARS[0][0]);
+        assert_eq!(frame.luma[128 + 127], PATTERN_BARS[7][0]);
+        assert_eq!(&frame.chroma[14..16], [128, 128]);
+        assert_eq!(
+            &frame.chroma[32..34],
+            [PATTERN_BARS[2][1], PATTERN_BARS[2][2]]
+        );
+    }
+
+    #[test]
+    fn overlay_draws_neutral_text_in_both_eyes() {
+        let mut chain = FilterChain::from_specs(&[FilterSpec::DebugOverlay]).unwrap();
+        let mut frame = TestFrame::new(256, 64, 16);
+
+        chain.apply(&mut frame.nv12(), &metadata()).unwrap();
+
+        let eye_has_text = |start: usize| {
+            (0..frame.height).any(|row| {
+                frame.luma[row * 256 + start..row * 256 + start + 128].contains(&LUMA_WHITE)
+            })
+        };
+        assert!(eye_has_text(0));
+        assert!(eye_has_text(128));
+        assert!(frame.chroma.contains(&128));
+    }
+}
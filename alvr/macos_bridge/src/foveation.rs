@@ -0,0 +1,128 @@
+use alvr_session::FoveatedEncodingConfig;
+use anyhow::{Result, ensure};
+
+/// ALVR's fixed foveated encoding for one negotiated view size. The Metal
+/// pass squeezes each eye's periphery by the edge ratios before encoding, and
+/// the client stretches it back using the same session settings, so every
+/// value here has to come out exactly as the client computes it. That is why
+/// the arithmetic stays in `f32` and in the client's order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Foveation {
+    /// The part of each padded output eye the compressed image fills.
+    pub eye_ratio: (f32, f32),
+    /// The full-resolution centre region, as a fraction of the eye, after
+    /// snapping its edges to whole compressed pixels.
+    pub center_size: (f32, f32),
+    pub center_shift: (f32, f32),
+    pub edge_ratio: (f32, f32),
+    /// The encoded side-by-side size, each eye padded to a multiple of 32.
+    pub stream_size: (u32, u32),
+}
+
+impl Foveation {
+    pub fn new(config: &FoveatedEncodingConfig, view_width: u32, view_height: u32) -> Result<Self> {
+        ensure!(
+            view_width > 0 && view_height > 0,
+            "foveated encoding needs a non-empty view"
+        );
+        ensure!(
+            [config.center_size_x, config.center_size_y]
+                .iter()
+                .all(|size| *size > 0.0 && *size < 1.0),
+            "foveated encoding center size must be between 0 and 1"
+        );
+        ensure!(
+            [config.center_shift_x, config.center_shift_y]
+                .iter()
+                .all(|shift| (-1.0..=1.0).contains(shift)),
+            "foveated encoding center shift must be between -1 and 1"
+        );
+        ensure!(
+            config.edge_ratio_x >= 1.0 && config.edge_ratio_y >= 1.0,
+            "foveated encoding edge ratio must be at least 1"
+        );
+        let x = Axis::new(
+            view_width as f32,
+            config.center_size_x,
+            config.center_shift_x,
+            config.edge_ratio_x,
+        );
+        let y = Axis::new(
+            view_height as f32,
+            config.center_size_y,
+            config.center_shift_y,
+            config.edge_ratio_y,
+        );
+        Ok(Self {
+            eye_ratio: (x.eye_ratio, y.eye_ratio),
+            center_size: (x.center_size, y.center_size),
+            center_shift: (x.center_shift, y.center_shift),
+            edge_ratio: (config.edge_ratio_x, config.edge_ratio_y),
+            stream_size: (x.padded_extent * 2, y.padded_extent),
+        })
+    }
+}
+
+struct Axis {
+    center_size: f32,
+    center_shift: f32,
+    eye_ratio: f32,
+    padded_extent: u32,
+}
+
+impl Axis {
+    // Mirrors `foveated_encoding_shader_constants` in alvr_graphics.
+    fn new(view: f32, center_size: f32, center_shift: f32, edge_ratio: f32) -> Self {
+        let edge_size = view - center_size * view;
+        let center_size = 1. - (edge_size / (edge_ratio * 2.)).ceil() * (edge_ratio * 2.) / view;
+        let edge_size = view - center_size * view;
+        let center_shift =
+            (center_shift * edge_size / (edge_ratio * 2.)).ceil() * (edge_ratio * 2.) / edge_size;
+        let scale = center_size + (1. - center_size) / edge_ratio;
+        let optimized = scale * view;
+        let padded = (optimized / 32.).ceil() * 32.;
+        Self {
+            center_size,
+            center_shift,
+            eye_ratio: optimized / padded,
+            padded_extent: padded as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_config() -> FoveatedEncodingConfig {
+        FoveatedEncodingConfig {
+            force_enable: false,
+            center_size_x: 0.45,
+            center_size_y: 0.4,
+            center_shift_x: 0.4,
+            center_shift_y: 0.1,
+            edge_ratio_x: 4.,
+            edge_ratio_y: 5.,
+        }
+    }
+
+    #[test]
+    fn shrinks_the_stream_the_way_the_client_expects() {
+        let foveation = Foveation::new(&default_config(), 1376, 1792).unwrap();
+
+        // 806 columns pad to 832. The height lands on exactly 928 rows in
+        // f32, where f64 would round up to 960 and disagree with the client.
+        assert_eq!(foveation.stream_size, (1664, 928));
+        assert_eq!(foveation.eye_ratio, (806. / 832., 1.));
+        assert!((foveation.center_size.0 - 0.447_674_4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rejects_a_center_that_fills_the_eye() {
+        let config = FoveatedEncodingConfig {
+            center_size_x: 1.0,
+            ..default_config()
+        };
+        assert!(Foveation::new(&config, 1376, 1792).is_err());
+    }
+}
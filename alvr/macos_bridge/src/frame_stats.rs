@@ -0,0 +1,138 @@
+use std::{fmt, time::Duration};
+
+/// One encoded frame as the stats see it: its size including any parameter
+/// sets sent with it, and where it sits on the video clock.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FrameOutput {
+    pub bytes: u64,
+    pub is_keyframe: bool,
+    pub video_timestamp: Duration,
+}
+
+/// Encoded frame sizes since the last report, and the spacing of the
+/// keyframes VideoToolbox actually emitted. Taking the stats clears the
+/// sizes; the keyframe interval carries over, since a window often holds no
+/// keyframe at all.
+#[derive(Default)]
+pub(crate) struct FrameStatsWindow {
+    frame_bytes: Vec<u64>,
+    frames_since_keyframe: u64,
+    last_keyframe: Option<Duration>,
+    keyframe_interval_frames: u64,
+    keyframe_interval: Duration,
+}
+
+impl FrameStatsWindow {
+    pub fn record_dispatch(&mut self, frames: &[FrameOutput]) {
+        for frame in frames {
+            self.frame_bytes.push(frame.bytes);
+            self.frames_since_keyframe += 1;
+            if !frame.is_keyframe {
+                continue;
+            }
+            if let Some(last_keyframe) = self.last_keyframe {
+                self.keyframe_interval_frames = self.frames_since_keyframe;
+                // A reconnect rebases the video clock, which reads as zero.
+                self.keyframe_interval = frame.video_timestamp.saturating_sub(last_keyframe);
+            }
+            self.last_keyframe = Some(frame.video_timestamp);
+            self.frames_since_keyframe = 0;
+        }
+    }
+
+    pub fn take(&mut self) -> FrameStats {
+        let keyframe_interval_frames = self.keyframe_interval_frames;
+        let keyframe_interval = self.keyframe_interval;
+        let sizes = &mut self.frame_bytes;
+        if sizes.is_empty() {
+            return FrameStats {
+                keyframe_interval_frames,
+                keyframe_interval,
+                ..Default::default()
+            };
+        }
+        sizes.sort_unstable();
+        let percentile = |percent: usize| sizes[(sizes.len() * percent).div_ceil(100) - 1];
+        let stats = FrameStats {
+            frames: sizes.len() as u64,
+            frame_bytes_p50: percentile(50),
+            frame_bytes_p99: percentile(99),
+            frame_bytes_max: sizes[sizes.len() - 1],
+            keyframe_interval_frames,
+            keyframe_interval,
+        };
+        sizes.clear();
+        stats
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    pub frames: u64,
+    pub frame_bytes_p50: u64,
+    pub frame_bytes_p99: u64,
+    pub frame_bytes_max: u64,
+    /// Encoded frames from the keyframe before the latest one to the latest
+    /// one, and the video time between them. Zero until two keyframes.
+    pub keyframe_interval_frames: u64,
+    pub keyframe_interval: Duration,
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "frame_bytes_p50={} frame_bytes_p99={} frame_bytes_max={} keyframe_interval_frames={} keyframe_interval_ms={}",
+            self.frame_bytes_p50,
+            self.frame_bytes_p99,
+            self.frame_bytes_max,
+            self.keyframe_interval_frames,
+            self.keyframe_interval.as_millis()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(bytes: u64, is_keyframe: bool, millis: u64) -> FrameOutput {
+        FrameOutput {
+            bytes,
+            is_keyframe,
+            video_timestamp: Duration::from_millis(millis),
+        }
+    }
+
+    #[test]
+    fn summarizes_sizes_and_keeps_the_keyframe_interval() {
+        let mut window = FrameStatsWindow::default();
+        let mut frames = vec![frame(90_000, true, 0)];
+        frames.extend((1..100).map(|index| frame(1_000 + index, false, index * 10)));
+        frames.push(frame(80_000, true, 1_000));
+        window.record_dispatch(&frames);
+
+        let stats = window.take();
+        assert_eq!(stats.frames, 101);
+        assert_eq!(stats.frame_bytes_p50, 1_051);
+        assert_eq!(stats.frame_bytes_p99, 80_000);
+        assert_eq!(stats.frame_bytes_max, 90_000);
+        assert_eq!(stats.keyframe_interval_frames, 100);
+        assert_eq!(stats.keyframe_interval, Duration::from_secs(1));
+        assert!(
+            stats
+                .to_string()
+                .ends_with("keyframe_interval_frames=100 keyframe_interval_ms=1000")
+        );
+
+        window.record_dispatch(&[frame(500, false, 1_010)]);
+        let stats = window.take();
+        assert_eq!(stats.frames, 1);
+        assert_eq!(stats.frame_bytes_max, 500);
+        assert_eq!(stats.keyframe_interval_frames, 100);
+
+        let stats = window.take();
+        assert_eq!(stats.frames, 0);
+        assert_eq!(stats.keyframe_interval, Duration::from_secs(1));
+    }
+}
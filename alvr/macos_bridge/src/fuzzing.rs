@@ -0,0 +1,21 @@
+use crate::{encoder, tracking_feedback};
+use std::path::Path;
+
+/// AVCC to Annex B both ways the encoder does it. Copying and rewriting in
+/// place must reject the same frames and agree on the rest.
+pub fn avcc(data: &[u8]) {
+    let mut copied = Vec::new();
+    let appended = encoder::append_annexb(data, &mut copied);
+    let mut rewritten = data.to_vec();
+    let in_place = encoder::annexb_in_place(&mut rewritten);
+    assert_eq!(appended.is_ok(), in_place.is_ok());
+    if appended.is_ok() {
+        assert_eq!(copied, rewritten);
+    }
+}
+
+/// The tracking feedback header as a driver could leave or write it.
+/// `path` is a scratch file the target reuses for every input.
+pub fn tracking_feedback(path: &Path, data: &[u8]) {
+    tracking_feedback::fuzz_driver_writes(path, data);
+}
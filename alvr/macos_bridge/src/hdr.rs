@@ -0,0 +1,153 @@
+use crate::sei::sei_nal;
+use alvr_session::CodecType;
+use anyhow::{Context, Result, ensure};
+use std::env;
+
+const MASTERING_DISPLAY_COLOUR_VOLUME: u8 = 137;
+const CONTENT_LIGHT_LEVEL_INFO: u8 = 144;
+/// SEI chromaticities are in units of 0.00002.
+const CHROMATICITY_UNITS: f64 = 50_000.0;
+/// SEI mastering luminance is in units of 0.0001 cd/m².
+const LUMINANCE_UNITS: u32 = 10_000;
+
+/// HDR10 static metadata, in the units the SEI messages carry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HdrMetadata {
+    /// Mastering display primaries as (x, y), in the SEI's green, blue, red
+    /// order.
+    pub display_primaries: [(u16, u16); 3],
+    pub white_point: (u16, u16),
+    pub max_display_luminance: u32,
+    pub min_display_luminance: u32,
+    /// MaxCLL and MaxFALL, in cd/m².
+    pub max_content_light_level: u16,
+    pub max_frame_average_light_level: u16,
+}
+
+impl Default for HdrMetadata {
+    /// A 1000-nit Display P3 D65 mastering display, which is what most HDR10
+    /// games target.
+    fn default() -> Self {
+        let chromaticity = |x: f64, y: f64| {
+            (
+                (x * CHROMATICITY_UNITS).round() as u16,
+                (y * CHROMATICITY_UNITS).round() as u16,
+            )
+        };
+        Self {
+            display_primaries: [
+                chromaticity(0.265, 0.690),
+                chromaticity(0.150, 0.060),
+                chromaticity(0.680, 0.320),
+            ],
+            white_point: chromaticity(0.3127, 0.3290),
+            max_display_luminance: 1000 * LUMINANCE_UNITS,
+            min_display_luminance: 1,
+            max_content_light_level: 1000,
+            max_frame_average_light_level: 400,
+        }
+    }
+}
+
+impl HdrMetadata {
+    /// Reads the peak luminances from `ALVR_BRIDGE_HDR_MASTERING_NITS`,
+    /// `ALVR_BRIDGE_HDR_MAX_CLL`, and `ALVR_BRIDGE_HDR_MAX_FALL`, keeping the
+    /// defaults for anything unset.
+    pub(crate) fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let mastering_nits = env_number(
+            "ALVR_BRIDGE_HDR_MASTERING_NITS",
+            defaults.max_display_luminance / LUMINANCE_UNITS,
+        )?;
+        let metadata = Self {
+            max_display_luminance: mastering_nits
+                .checked_mul(LUMINANCE_UNITS)
+                .context("ALVR_BRIDGE_HDR_MASTERING_NITS is too large")?,
+            max_content_light_level: env_number(
+                "ALVR_BRIDGE_HDR_MAX_CLL",
+                defaults.max_content_light_level,
+            )?,
+            max_frame_average_light_level: env_number(
+                "ALVR_BRIDGE_HDR_MAX_FALL",
+                defaults.max_frame_average_light_level,
+            )?,
+            ..defaults
+        };
+        metadata.validate()?;
+        Ok(metadata)
+    }
+
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            self.max_display_luminance > self.min_display_luminance,
+            "HDR mastering luminance must be above the display minimum"
+        );
+        ensure!(
+            self.max_frame_average_light_level <= self.max_content_light_level,
+            "HDR MaxFALL must not exceed MaxCLL"
+        );
+        Ok(())
+    }
+
+    /// One Annex B prefix SEI NAL carrying the mastering display colour
+    /// volume and content light level messages. It goes in front of every
+    /// IDR, so a client that joins mid-stream still sees it.
+    pub(crate) fn sei_nal(&self) -> Vec<u8> {
+        let mut mastering = Vec::with_capacity(24);
+        for (x, y) in self.display_primaries {
+            mastering.extend(x.to_be_bytes());
+            mastering.extend(y.to_be_bytes());
+        }
+        mastering.extend(self.white_point.0.to_be_bytes());
+        mastering.extend(self.white_point.1.to_be_bytes());
+        mastering.extend(self.max_display_luminance.to_be_bytes());
+        mastering.extend(self.min_display_luminance.to_be_bytes());
+        let mut light_level = Vec::with_capacity(4);
+        light_level.extend(self.max_content_light_level.to_be_bytes());
+        light_level.extend(self.max_frame_average_light_level.to_be_bytes());
+        sei_nal(
+            CodecType::Hevc,
+            &[
+                (MASTERING_DISPLAY_COLOUR_VOLUME, &mastering),
+                (CONTENT_LIGHT_LEVEL_INFO, &light_level),
+            ],
+        )
+    }
+}
+
+fn env_number<T: std::str::FromStr>(name: &str, default: T) -> Result<T> {
+    env::var(name).map_or(Ok(default), |value| {
+        value
+            .parse()
+            .ok()
+            .with_context(|| format!("invalid {name}"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_mastering_and_light_level_sei_with_emulation_prevention() {
+        let nal = HdrMetadata::default().sei_nal();
+
+        assert_eq!(nal[..8], [0, 0, 0, 1, 0x4e, 0x01, 137, 24]);
+        // Green x 0.265 and y 0.690 in units of 0.00002.
+        assert_eq!(nal[8..12], [0x33, 0xc2, 0x86, 0xc4]);
+        // 1000 nits is 10,000,000 units; the minimum of one unit is written
+        // as 00 00 03 00 01 so it cannot be read as a start code.
+        assert_eq!(nal[24..28], [0x00, 0x98, 0x96, 0x80]);
+        assert_eq!(nal[28..33], [0x00, 0x00, 0x03, 0x00, 0x01]);
+        assert_eq!(nal[33..], [144, 4, 0x03, 0xe8, 0x01, 0x90, 0x80]);
+    }
+
+    #[test]
+    fn rejects_an_average_above_the_peak() {
+        let metadata = HdrMetadata {
+            max_frame_average_light_level: 1200,
+            ..HdrMetadata::default()
+        };
+        assert!(metadata.validate().is_err());
+    }
+}
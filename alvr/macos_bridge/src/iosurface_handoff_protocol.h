@@ -3,8 +3,11 @@
 
 #include <stdint.h>
 
-#define ALVR_IOSURFACE_PROTOCOL_VERSION UINT32_C(3)
+#define ALVR_IOSURFACE_PROTOCOL_VERSION UINT32_C(6)
+#define ALVR_IOSURFACE_BUILD_VERSION_CAPACITY 32
 #define ALVR_IOSURFACE_PIXEL_FORMAT_BGRA UINT32_C(0x42475241)
+#define ALVR_IOSURFACE_PIXEL_FORMAT_RGB10A2 UINT32_C(0x6C313072)
+#define ALVR_IOSURFACE_PIXEL_FORMAT_NV12 UINT32_C(0x34323076)
 
 enum alvr_iosurface_message_id
 {
@@ -20,7 +23,8 @@ enum alvr_iosurface_frame_flags
     ALVR_IOSURFACE_FRAME_SELF_TEST = 1u << 0,
     ALVR_IOSURFACE_FRAME_CONSUMER_SAMPLE = 1u << 1,
     ALVR_IOSURFACE_FRAME_FALLBACK_POSE = 1u << 2,
-    ALVR_IOSURFACE_FRAME_STARTUP_BARRIER = 1u << 3
+    ALVR_IOSURFACE_FRAME_STARTUP_BARRIER = 1u << 3,
+    ALVR_IOSURFACE_FRAME_CONTENT_CRC32 = 1u << 4
 };
 
 enum alvr_iosurface_probe_status
@@ -43,6 +47,7 @@ struct alvr_iosurface_request
     uint64_t session_nonce;
     uint32_t protocol_version;
     uint32_t client_pid;
+    char producer_build_version[ALVR_IOSURFACE_BUILD_VERSION_CAPACITY];
 };
 
 struct alvr_iosurface_offer
@@ -61,6 +66,9 @@ struct alvr_iosurface_offer
     uint32_t sample_y;
     uint8_t expected_bgra[4];
     uint32_t producer_pid;
+    uint32_t slot_count;
+    uint32_t reserved;
+    char bridge_build_version[ALVR_IOSURFACE_BUILD_VERSION_CAPACITY];
 };
 
 struct alvr_iosurface_ack
@@ -93,6 +101,7 @@ struct alvr_iosurface_frame_ready
     uint32_t sample_y;
     uint8_t expected_bgra[4];
     uint32_t producer_pid;
+    uint32_t content_crc32;
     uint64_t pose_timestamp_ns;
     uint64_t pose_generation;
     float pose[3][4];
@@ -113,15 +122,15 @@ struct alvr_iosurface_slot_release
 };
 
 #if defined(__cplusplus)
-static_assert(sizeof(struct alvr_iosurface_request) == 16);
-static_assert(sizeof(struct alvr_iosurface_offer) == 64);
+static_assert(sizeof(struct alvr_iosurface_request) == 48);
+static_assert(sizeof(struct alvr_iosurface_offer) == 104);
 static_assert(sizeof(struct alvr_iosurface_ack) == 48);
 static_assert(sizeof(struct alvr_iosurface_frame_ready) == 136);
 static_assert(sizeof(struct alvr_iosurface_slot_release) == 48);
 #else
-_Static_assert(sizeof(struct alvr_iosurface_request) == 16,
+_Static_assert(sizeof(struct alvr_iosurface_request) == 48,
                "request wire layout changed");
-_Static_assert(sizeof(struct alvr_iosurface_offer) == 64,
+_Static_assert(sizeof(struct alvr_iosurface_offer) == 104,
                "offer wire layout changed");
 _Static_assert(sizeof(struct alvr_iosurface_ack) == 48,
                "ack wire layout changed");
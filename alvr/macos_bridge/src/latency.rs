@@ -0,0 +1,135 @@
+use std::{fmt, time::Duration};
+
+/// Samples for one pipeline stage since the last report. Taking a summary
+/// clears the window, so each cadence line describes only its own interval.
+#[derive(Default)]
+pub(crate) struct LatencyWindow {
+    samples: Vec<Duration>,
+}
+
+impl LatencyWindow {
+    pub fn record(&mut self, sample: Duration) {
+        self.samples.push(sample);
+    }
+
+    pub fn take(&mut self) -> LatencySummary {
+        let samples = &mut self.samples;
+        if samples.is_empty() {
+            return LatencySummary::default();
+        }
+        samples.sort_unstable();
+        let percentile = |percent: usize| samples[(samples.len() * percent).div_ceil(100) - 1];
+        let summary = LatencySummary {
+            samples: samples.len() as u64,
+            p50: percentile(50),
+            p99: percentile(99),
+            max: samples[samples.len() - 1],
+        };
+        samples.clear();
+        summary
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencySummary {
+    pub samples: u64,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// Per-stage windows for the IOSurface path. `acquire` runs from the frame
+/// message arriving to its slot going back to the producer, `convert` is the
+/// Metal conversion wall time, `encode` runs from VideoToolbox submission to
+/// its output callback, and `send` is the time spent in server core's
+/// `send_video_nal`.
+#[derive(Default)]
+pub(crate) struct LatencyTracker {
+    pub acquire: LatencyWindow,
+    pub convert: LatencyWindow,
+    pub encode: LatencyWindow,
+    pub send: LatencyWindow,
+}
+
+impl LatencyTracker {
+    pub fn record_dispatch(&mut self, encode: &[Duration], send: &[Duration]) {
+        self.encode.samples.extend_from_slice(encode);
+        self.send.samples.extend_from_slice(send);
+    }
+
+    pub fn take(&mut self) -> LatencyBreakdown {
+        LatencyBreakdown {
+            acquire: self.acquire.take(),
+            convert: self.convert.take(),
+            encode: self.encode.take(),
+            send: self.send.take(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyBreakdown {
+    pub acquire: LatencySummary,
+    pub convert: LatencySummary,
+    pub encode: LatencySummary,
+    pub send: LatencySummary,
+}
+
+impl fmt::Display for LatencyBreakdown {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (stage, summary)) in [
+            ("acquire", self.acquire),
+            ("convert", self.convert),
+            ("encode", self.encode),
+            ("send", self.send),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if index != 0 {
+                formatter.write_str(" ")?;
+            }
+            write!(
+                formatter,
+                "{stage}_p50_us={} {stage}_p99_us={} {stage}_max_us={}",
+                summary.p50.as_micros(),
+                summary.p99.as_micros(),
+                summary.max.as_micros()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_and_clears_each_window() {
+        let mut tracker = LatencyTracker::default();
+        for micros in (1..=200).rev() {
+            tracker.encode.record(Duration::from_micros(micros));
+        }
+        tracker.send.record(Duration::from_micros(7));
+
+        let breakdown = tracker.take();
+        assert_eq!(
+            breakdown.encode,
+            LatencySummary {
+                samples: 200,
+                p50: Duration::from_micros(100),
+                p99: Duration::from_micros(198),
+                max: Duration::from_micros(200),
+            }
+        );
+        assert_eq!(breakdown.send.p99, Duration::from_micros(7));
+        assert_eq!(breakdown.acquire, LatencySummary::default());
+        assert!(
+            breakdown
+                .to_string()
+                .contains("encode_p50_us=100 encode_p99_us=198 encode_max_us=200 send_p50_us=7")
+        );
+        assert_eq!(tracker.take().encode.samples, 0);
+    }
+}
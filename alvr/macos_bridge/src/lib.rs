@@ -5,32 +5,160 @@ pub use contract::{ContractError, FrameMetadata, SurfaceLeaseId};
 #[cfg(target_os = "macos")]
 mod alvr_sink;
 #[cfg(target_os = "macos")]
+mod backpressure;
+#[cfg(target_os = "macos")]
+mod bench;
+#[cfg(target_os = "macos")]
+mod bridge;
+#[cfg(target_os = "macos")]
+mod capture;
+#[cfg(target_os = "macos")]
+mod cli;
+#[cfg(target_os = "macos")]
+mod clock_sync;
+#[cfg(target_os = "macos")]
+mod content_hash;
+#[cfg(target_os = "macos")]
+mod control;
+#[cfg(target_os = "macos")]
+mod crash_report;
+#[cfg(target_os = "macos")]
+mod data_rate;
+#[cfg(target_os = "macos")]
+mod degradation;
+#[cfg(target_os = "macos")]
+mod doctor;
+#[cfg(target_os = "macos")]
+mod encode_stage;
+#[cfg(target_os = "macos")]
 mod encoder;
+#[cfg(all(target_os = "macos", test))]
+mod fake_wine_writer;
+#[cfg(target_os = "macos")]
+mod filter;
+#[cfg(target_os = "macos")]
+mod foveation;
+#[cfg(target_os = "macos")]
+mod frame_stats;
+/// Entry points for the targets under `fuzz/`.
+#[cfg(all(target_os = "macos", feature = "fuzzing"))]
+pub mod fuzzing;
+#[cfg(target_os = "macos")]
+mod hdr;
+#[cfg(target_os = "macos")]
+mod latency;
+#[cfg(target_os = "macos")]
+mod log_file;
+#[cfg(target_os = "macos")]
+mod log_format;
+#[cfg(target_os = "macos")]
+mod loopback_http;
 #[cfg(target_os = "macos")]
 mod metal;
 #[cfg(target_os = "macos")]
+mod metrics;
+#[cfg(all(target_os = "macos", test))]
+mod mock_encoder;
+#[cfg(target_os = "macos")]
 mod native_probe;
 #[cfg(target_os = "macos")]
 mod native_source;
 #[cfg(target_os = "macos")]
+mod output_buffers;
+#[cfg(target_os = "macos")]
+mod pacing;
+#[cfg(target_os = "macos")]
+mod power_state;
+#[cfg(target_os = "macos")]
+mod preflight;
+#[cfg(target_os = "macos")]
+mod preview;
+#[cfg(target_os = "macos")]
 mod probe;
 #[cfg(target_os = "macos")]
+mod recording;
+#[cfg(target_os = "macos")]
+mod restream;
+#[cfg(target_os = "macos")]
+mod sei;
+#[cfg(target_os = "macos")]
+mod service;
+#[cfg(target_os = "macos")]
+mod signals;
+#[cfg(all(target_os = "macos", feature = "software-encoder"))]
+mod software_encoder;
+#[cfg(target_os = "macos")]
 mod surface;
 #[cfg(target_os = "macos")]
+mod system_events;
+#[cfg(target_os = "macos")]
+mod trace;
+#[cfg(target_os = "macos")]
 mod tracking_feedback;
+#[cfg(target_os = "macos")]
+mod watchdog;
 
 #[cfg(target_os = "macos")]
-pub use alvr_sink::AlvrVideoSink;
+pub use alvr_sink::{AlvrVideoSink, AudioDevices, PosePrediction, SecondClient};
+#[cfg(target_os = "macos")]
+pub use bench::run_bench;
+#[cfg(target_os = "macos")]
+pub use bridge::{Bridge, BridgeReport, BridgeSummary};
+#[cfg(target_os = "macos")]
+pub use cli::{Cli, CliCommand, USAGE as CLI_USAGE};
+#[cfg(target_os = "macos")]
+pub use control::{
+    BridgeState, BridgeStatus, ClientStatus, StatusMetrics, query_status, status_line,
+};
+#[cfg(target_os = "macos")]
+pub use crash_report::{install_crash_reporter, write_crash_report};
+#[cfg(target_os = "macos")]
+pub use doctor::run_doctor;
 #[cfg(target_os = "macos")]
 pub use encoder::{
-    EncodedFrame, HardwareEncoderSupport, NativeHevcEncoder, NativeHevcEncoderConfig,
-    hevc_hardware_support,
+    EncodedFrame, EncoderBackend, HardwareEncoderSupport, NativeVideoEncoder,
+    NativeVideoEncoderConfig, RateControl, VideoEncoder, VideoToolboxOverrides,
+    encoder_hardware_support,
 };
 #[cfg(target_os = "macos")]
+pub use filter::{FilterChain, FilterFactory, FilterSpec, FrameFilter, Nv12Frame, register_filter};
+#[cfg(target_os = "macos")]
+pub use foveation::Foveation;
+#[cfg(target_os = "macos")]
+pub use frame_stats::FrameStats;
+#[cfg(target_os = "macos")]
+pub use hdr::HdrMetadata;
+#[cfg(target_os = "macos")]
+pub use latency::{LatencyBreakdown, LatencySummary};
+#[cfg(target_os = "macos")]
+pub use log_file::LogFile;
+#[cfg(target_os = "macos")]
+pub use log_format::error_code;
+#[cfg(target_os = "macos")]
+pub use metal::SharpenMode;
+#[cfg(target_os = "macos")]
 pub use native_probe::{
-    NativeCadenceReport, NativeProbeSummary, NativeSourceConfig, run_native_source_probe,
+    NativeCadenceReport, NativeProbeSummary, NativeSourceConfig, VersionPolicy,
+    run_native_source_probe,
+};
+#[cfg(target_os = "macos")]
+pub use native_source::SourceFormat;
+#[cfg(target_os = "macos")]
+pub use preview::PreviewWindow;
+#[cfg(target_os = "macos")]
+pub use probe::{
+    CadenceReport, ProbeConfig, ProbeSummary, control_socket_from_env, run_surface_probe,
+    run_surface_probe_with_encoder,
 };
 #[cfg(target_os = "macos")]
-pub use probe::{CadenceReport, ProbeConfig, ProbeSummary, run_surface_probe};
+pub use sei::TIMING_SEI_UUID;
+#[cfg(target_os = "macos")]
+pub use service::{SERVICE_LABEL, install_service, uninstall_service};
+#[cfg(target_os = "macos")]
+pub use signals::install_shutdown_handlers;
+#[cfg(target_os = "macos")]
+pub use surface::{
+    ColorMatrix, ColorRange, ColorSpace, PoolStats, SurfaceFormat, SurfaceLease, SurfacePool,
+};
 #[cfg(target_os = "macos")]
-pub use surface::{PoolStats, SurfaceLease, SurfacePool};
+pub use trace::TraceExport;
@@ -0,0 +1,360 @@
+use crate::{
+    crash_report,
+    log_format::{LogFormat, json_line},
+    probe::alvr_root_from_env,
+};
+use alvr_filesystem::Layout;
+use anyhow::{Context, Result, bail, ensure};
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const LOG_FILE_NAME: &str = "macos_bridge.log";
+const DEFAULT_MAX_BYTES: u64 = 16 * 1024 * 1024;
+/// Rotated files kept beside the live one, `macos_bridge.log.1` the newest.
+const ROTATED_FILES: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum LogLevel {
+    Error,
+    Warn,
+    Info,
+}
+
+impl LogLevel {
+    fn from_env() -> Result<Self> {
+        match env::var("ALVR_BRIDGE_LOG_LEVEL").as_deref() {
+            Err(env::VarError::NotPresent) | Ok("info") => Ok(Self::Info),
+            Ok("warn") => Ok(Self::Warn),
+            Ok("error") => Ok(Self::Error),
+            _ => bail!("ALVR_BRIDGE_LOG_LEVEL must be error, warn, or info"),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+        }
+    }
+
+    /// The bridge's lines carry their severity only in how they start:
+    /// `WARNING` for warnings, and `Error:` or a panic's `thread '` for the
+    /// line a failed run ends on.
+    fn of_line(line: &[u8]) -> Self {
+        if line.starts_with(b"Error") || line.starts_with(b"thread '") {
+            Self::Error
+        } else if line.starts_with(b"WARNING") {
+            Self::Warn
+        } else {
+            Self::Info
+        }
+    }
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    max_bytes: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            len,
+            max_bytes,
+        })
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        if self.len > 0 && self.len + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line)?;
+        self.len += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for index in (1..ROTATED_FILES).rev() {
+            let from = rotated_path(&self.path, index);
+            if from.exists() {
+                fs::rename(from, rotated_path(&self.path, index + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        *self = Self::open(self.path.clone(), self.max_bytes)?;
+        Ok(())
+    }
+}
+
+/// Copies everything the process writes to stdout and stderr, server core
+/// and panics included, into `macos_bridge.log` in ALVR's log directory.
+/// Each stream is redirected into a pipe whose reader stamps every line with
+/// the local time, appends it to the file, and passes it on to the original
+/// stream. The file rotates by size, so a crash hours into a session still
+/// leaves its lead-up on disk. In JSON format each line is converted on the
+/// way, for the terminal and the file alike. Dropping this restores both
+/// streams and waits until every line written before the drop has been
+/// copied.
+pub struct LogFile {
+    path: Option<PathBuf>,
+    saved: Vec<(RawFd, OwnedFd)>,
+    readers: Vec<JoinHandle<()>>,
+}
+
+impl LogFile {
+    /// `None` when `ALVR_BRIDGE_LOG_FILE=0` and the format is text, which
+    /// leaves nothing to do.
+    pub fn from_env() -> Result<Option<Self>> {
+        let format = LogFormat::from_env()?;
+        let write_file = env::var("ALVR_BRIDGE_LOG_FILE").as_deref() != Ok("0");
+        if !write_file && format == LogFormat::Text {
+            return Ok(None);
+        }
+        let level = LogLevel::from_env()?;
+        let max_bytes = env::var("ALVR_BRIDGE_LOG_MAX_BYTES")
+            .map(|value| value.parse().context("invalid ALVR_BRIDGE_LOG_MAX_BYTES"))
+            .unwrap_or(Ok(DEFAULT_MAX_BYTES))?;
+        ensure!(
+            max_bytes >= 64 * 1024,
+            "ALVR_BRIDGE_LOG_MAX_BYTES must be at least 65536"
+        );
+        let path = if write_file {
+            Some(
+                Layout::new(&alvr_root_from_env()?)
+                    .log_dir
+                    .join(LOG_FILE_NAME),
+            )
+        } else {
+            None
+        };
+        Self::install(path, level, format, max_bytes).map(Some)
+    }
+
+    /// `None` when only the format applies.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    fn install(
+        path: Option<PathBuf>,
+        level: LogLevel,
+        format: LogFormat,
+        max_bytes: u64,
+    ) -> Result<Self> {
+        let file = match &path {
+            Some(path) => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)
+                        .with_context(|| format!("failed to create {}", parent.display()))?;
+                }
+                Some(Arc::new(Mutex::new(
+                    RotatingFile::open(path.clone(), max_bytes)
+                        .with_context(|| format!("failed to open {}", path.display()))?,
+                )))
+            }
+            None => None,
+        };
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+
+        let mut log = Self {
+            path,
+            saved: Vec::new(),
+            readers: Vec::new(),
+        };
+        for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+            let saved = duplicate(fd).context("failed to duplicate a standard stream")?;
+            let terminal = File::from(saved.try_clone()?);
+            let (read, write) = pipe().context("failed to create a log pipe")?;
+            ensure!(
+                unsafe { libc::dup2(write.as_raw_fd(), fd) } >= 0,
+                "failed to redirect a standard stream: {}",
+                io::Error::last_os_error()
+            );
+            log.saved.push((fd, saved));
+            let file = file.clone();
+            log.readers
+                .push(
+                    thread::Builder::new()
+                        .name("bridge-log".into())
+                        .spawn(move || {
+                            copy_lines(File::from(read), terminal, file.as_deref(), level, format)
+                        })?,
+                );
+        }
+        Ok(log)
+    }
+}
+
+impl Drop for LogFile {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        // Putting the original descriptors back closes the pipes' last write
+        // ends, so each reader drains what is left and sees end of file.
+        for (fd, saved) in self.saved.drain(..) {
+            unsafe { libc::dup2(saved.as_raw_fd(), fd) };
+        }
+        for reader in self.readers.drain(..) {
+            let _ = reader.join();
+        }
+    }
+}
+
+fn copy_lines(
+    pipe: File,
+    mut terminal: File,
+    file: Option<&Mutex<RotatingFile>>,
+    level: LogLevel,
+    format: LogFormat,
+) {
+    let mut reader = BufReader::new(pipe);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        while line
+            .last()
+            .is_some_and(|byte| *byte == b'\n' || *byte == b'\r')
+        {
+            line.pop();
+        }
+        // Crash reports keep the lead-up whatever the level filter drops.
+        crash_report::remember_line(&String::from_utf8_lossy(&line));
+        let line_level = LogLevel::of_line(&line);
+        if line_level > level {
+            continue;
+        }
+        let timestamp = local_timestamp();
+        let (shown, logged) = match format {
+            LogFormat::Text => {
+                let text = String::from_utf8_lossy(&line);
+                (format!("{text}\n"), format!("{timestamp} {text}\n"))
+            }
+            LogFormat::Json => {
+                let json = json_line(
+                    &timestamp,
+                    line_level.name(),
+                    &String::from_utf8_lossy(&line),
+                ) + "\n";
+                (json.clone(), json)
+            }
+        };
+        let _ = terminal.write_all(shown.as_bytes());
+        if let Some(Ok(mut file)) = file.map(Mutex::lock) {
+            let _ = file.write_line(logged.as_bytes());
+        }
+    }
+}
+
+fn rotated_path(path: &Path, index: u32) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{index}"));
+    PathBuf::from(rotated)
+}
+
+fn duplicate(fd: RawFd) -> io::Result<OwnedFd> {
+    let duplicate = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+    if duplicate < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(duplicate) })
+}
+
+fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+    unsafe { libc::fcntl(read.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) };
+    Ok((read, write))
+}
+
+/// `2026-10-15 13:04:05.123` in local time.
+pub(crate) fn local_timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = now.as_secs() as libc::time_t;
+    let mut tm = unsafe { std::mem::zeroed::<libc::tm>() };
+    unsafe { libc::localtime_r(&seconds, &mut tm) };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec,
+        now.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotates_by_size_and_keeps_a_bounded_history() {
+        let dir = env::temp_dir().join(format!("alvr_bridge_log_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(LOG_FILE_NAME);
+        let mut file = RotatingFile::open(path.clone(), 16).unwrap();
+
+        for index in 0..(ROTATED_FILES + 3) {
+            file.write_line(format!("line {index:02} ...\n").as_bytes())
+                .unwrap();
+        }
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("line {:02} ...\n", ROTATED_FILES + 2)
+        );
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 1)).unwrap(),
+            format!("line {:02} ...\n", ROTATED_FILES + 1)
+        );
+        assert!(rotated_path(&path, ROTATED_FILES).exists());
+        assert!(!rotated_path(&path, ROTATED_FILES + 1).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn classifies_lines_by_their_prefix() {
+        assert_eq!(
+            LogLevel::of_line(b"WARNING recording stopped\n"),
+            LogLevel::Warn
+        );
+        assert_eq!(
+            LogLevel::of_line(b"Error: producer exited\n"),
+            LogLevel::Error
+        );
+        assert_eq!(
+            LogLevel::of_line(b"thread 'main' panicked at src/lib.rs\n"),
+            LogLevel::Error
+        );
+        assert_eq!(
+            LogLevel::of_line(b"native_source summary\n"),
+            LogLevel::Info
+        );
+    }
+}
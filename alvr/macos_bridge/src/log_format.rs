@@ -0,0 +1,153 @@
+use crate::ContractError;
+use anyhow::{Result, bail};
+use serde_json::{Map, Value};
+use std::{env, io};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    pub fn from_env() -> Result<Self> {
+        match env::var("ALVR_BRIDGE_LOG_FORMAT").as_deref() {
+            Err(env::VarError::NotPresent) | Ok("text") => Ok(Self::Text),
+            Ok("json") => Ok(Self::Json),
+            _ => bail!("ALVR_BRIDGE_LOG_FORMAT must be text or json"),
+        }
+    }
+}
+
+/// One printed line as a JSON object. The bridge prints logfmt: the words
+/// before the first `key=value` pair name the event, and each pair becomes a
+/// field, typed as a number or boolean when it reads as one. Words after a
+/// pair belong to its value, since Debug output and prose can hold spaces.
+/// A leading `WARNING` or `Error:` only sets the level.
+pub(crate) fn json_line(timestamp: &str, level: &str, line: &str) -> String {
+    let mut event = Vec::new();
+    let mut fields = Vec::<(&str, String)>::new();
+    for token in line.split_whitespace() {
+        match token.split_once('=') {
+            Some((key, value)) if is_key(key) => fields.push((key, value.to_owned())),
+            _ => match fields.last_mut() {
+                Some((_, value)) => {
+                    value.push(' ');
+                    value.push_str(token);
+                }
+                None => event.push(token),
+            },
+        }
+    }
+    if matches!(event.first(), Some(&"WARNING" | &"Error:")) {
+        event.remove(0);
+    }
+
+    let mut object = Map::new();
+    object.insert("ts".into(), timestamp.into());
+    object.insert("level".into(), level.into());
+    object.insert("event".into(), event.join(" ").into());
+    object.insert(
+        "fields".into(),
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), typed(value)))
+            .collect::<Map<_, _>>()
+            .into(),
+    );
+    Value::Object(object).to_string()
+}
+
+/// A stable name for what kind of failure ended the run, for aggregation to
+/// group on without matching message text.
+pub fn error_code(error: &anyhow::Error) -> &'static str {
+    let message = error.to_string();
+    if error.chain().any(|cause| cause.is::<ContractError>()) {
+        "frame_contract"
+    } else if message.starts_with("ALVR requested a restart") {
+        "restart"
+    } else if message.starts_with("ALVR stream contract failed")
+        || message.starts_with("ALVR negotiated")
+    {
+        "stream_contract"
+    } else if message.contains("IOSurface") || message.contains("producer") {
+        "producer"
+    } else if message.contains("VideoToolbox") || message.contains("preflight") {
+        "encoder"
+    } else if message.contains("ALVR_BRIDGE_") || message.starts_with("invalid ") {
+        "config"
+    } else if error.chain().any(|cause| cause.is::<io::Error>()) {
+        "io"
+    } else {
+        "bridge"
+    }
+}
+
+fn is_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .bytes()
+            .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'_')
+}
+
+fn typed(value: String) -> Value {
+    if let Ok(integer) = value.parse::<i64>() {
+        integer.into()
+    } else if let Ok(boolean) = value.parse::<bool>() {
+        boolean.into()
+    } else if let Ok(float) = value.parse::<f64>()
+        && float.is_finite()
+    {
+        float.into()
+    } else {
+        value.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn turns_logfmt_into_typed_fields() {
+        let line = json_line(
+            "2026-10-15 13:04:05.123",
+            "warn",
+            "WARNING native_source checksum_mismatch frame_id=42 expected=0a0b actual=Some(1, 2) mbps=12.5 ok=false",
+        );
+        let value: Value = serde_json::from_str(&line).unwrap();
+
+        assert_eq!(value["level"], "warn");
+        assert_eq!(value["event"], "native_source checksum_mismatch");
+        assert_eq!(value["fields"]["frame_id"], 42);
+        assert_eq!(value["fields"]["expected"], "0a0b");
+        assert_eq!(value["fields"]["actual"], "Some(1, 2)");
+        assert_eq!(value["fields"]["mbps"], 12.5);
+        assert_eq!(value["fields"]["ok"], false);
+    }
+
+    #[test]
+    fn names_the_failure_behind_an_error() {
+        let contract = anyhow::Error::new(ContractError::VideoTimestampOutOfOrder {
+            previous: Duration::from_millis(2),
+            next: Duration::from_millis(1),
+        })
+        .context("frame rejected");
+        assert_eq!(error_code(&contract), "frame_contract");
+        assert_eq!(
+            error_code(&anyhow::anyhow!("IOSurface producer pid=7 exited")),
+            "producer"
+        );
+        assert_eq!(
+            error_code(&anyhow::anyhow!("ALVR_BRIDGE_FPS must be positive")),
+            "config"
+        );
+        assert_eq!(
+            error_code(&anyhow::anyhow!("ALVR requested a restart")),
+            "restart"
+        );
+        assert_eq!(error_code(&anyhow::anyhow!("something else")), "bridge");
+    }
+}
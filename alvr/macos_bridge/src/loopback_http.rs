@@ -0,0 +1,225 @@
+use anyhow::{Context, Result};
+use std::{
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+const MAX_REQUEST_BYTES: u64 = 8192;
+
+/// A route's answer to a GET: the content type and the body.
+pub(crate) type Response = (&'static str, Arc<[u8]>);
+
+/// A minimal HTTP/1.1 server on `127.0.0.1:<port>` for the bridge's own
+/// endpoints. Each connection carries one GET, answered from `route` by path
+/// or with a 404, and is then closed. Requests are served one at a time on
+/// the server's thread, which stops when the server drops.
+///
+/// Binding loopback keeps other machines out but not web pages in a local
+/// browser. Responses carry no CORS headers, so a page from another origin
+/// cannot read them, and a request whose `Host` is not this server's own
+/// address is refused, so a DNS-rebound name cannot pose as the same origin.
+pub(crate) struct LoopbackServer {
+    address: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LoopbackServer {
+    /// `name` labels the thread and errors. `headers` are extra CRLF-ended
+    /// header lines sent with every response.
+    pub fn bind(
+        name: &'static str,
+        port: u16,
+        io_timeout: Duration,
+        headers: &'static str,
+        route: impl Fn(&str) -> Option<Response> + Send + 'static,
+    ) -> Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .with_context(|| format!("failed to bind {name} endpoint on 127.0.0.1:{port}"))?;
+        let address = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::Builder::new()
+            .name(format!("bridge-{name}"))
+            .spawn({
+                let stop = Arc::clone(&stop);
+                move || {
+                    for stream in listener.incoming() {
+                        if stop.load(Ordering::Acquire) {
+                            break;
+                        }
+                        let result = stream.map_err(anyhow::Error::from).and_then(|stream| {
+                            serve_request(stream, address.port(), io_timeout, headers, &route)
+                        });
+                        if let Err(error) = result {
+                            eprintln!("{name} request failed: {error:#}");
+                        }
+                    }
+                }
+            })
+            .with_context(|| format!("failed to spawn {name} thread"))?;
+
+        Ok(Self {
+            address,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl Drop for LoopbackServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        // Wakes the accept loop so it sees the stop flag.
+        let _ = TcpStream::connect(self.address);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Whether a `Host` header names this server by its loopback address or as
+/// `localhost`, with the port it listens on.
+fn is_loopback_host(host: &str, port: u16) -> bool {
+    host.rsplit_once(':').is_some_and(|(name, host_port)| {
+        host_port.parse() == Ok(port)
+            && (name == "127.0.0.1" || name.eq_ignore_ascii_case("localhost"))
+    })
+}
+
+fn serve_request(
+    stream: TcpStream,
+    port: u16,
+    io_timeout: Duration,
+    headers: &str,
+    route: &impl Fn(&str) -> Option<Response>,
+) -> Result<()> {
+    stream.set_read_timeout(Some(io_timeout))?;
+    stream.set_write_timeout(Some(io_timeout))?;
+    let mut reader = BufReader::new(&stream).take(MAX_REQUEST_BYTES);
+    let mut request = String::new();
+    match reader.read_line(&mut request) {
+        Ok(_) => {}
+        Err(error) if error.kind() == ErrorKind::UnexpectedEof => {}
+        Err(error) => return Err(error.into()),
+    }
+    // Read every header, which also keeps closing the socket from
+    // resetting a client that is still sending them.
+    let mut host_allowed = false;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        if let Some((name, value)) = header.split_once(':')
+            && name.trim().eq_ignore_ascii_case("host")
+        {
+            host_allowed = is_loopback_host(value.trim(), port);
+        }
+        header.clear();
+    }
+    let mut parts = request.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Ok(());
+    };
+    let (status, content_type, body) = if !host_allowed {
+        (
+            "421 Misdirected Request",
+            "text/plain",
+            Arc::from(&b"unknown host\n"[..]),
+        )
+    } else if let Some((content_type, body)) = (method == "GET").then(|| route(path)).flatten() {
+        ("200 OK", content_type, body)
+    } else {
+        (
+            "404 Not Found",
+            "text/plain",
+            Arc::from(&b"not found\n"[..]),
+        )
+    };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n{headers}Connection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(server: &LoopbackServer, request: &str) -> String {
+        let mut stream = TcpStream::connect(server.address()).unwrap();
+        let request = request.replace("{port}", &server.address().port().to_string());
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn routes_gets_and_answers_everything_else_with_404() {
+        let server = LoopbackServer::bind(
+            "test",
+            0,
+            Duration::from_secs(2),
+            "Cache-Control: no-cache\r\n",
+            |path| (path == "/hello").then(|| ("text/plain", Arc::from(&b"hi\n"[..]))),
+        )
+        .unwrap();
+
+        let response = get(
+            &server,
+            "GET /hello HTTP/1.1\r\nHost: localhost:{port}\r\n\r\n",
+        );
+        assert_eq!(
+            response,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 3\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\nhi\n"
+        );
+        assert!(
+            get(
+                &server,
+                "GET /other HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\n\r\n"
+            )
+            .starts_with("HTTP/1.1 404 ")
+        );
+        assert!(
+            get(
+                &server,
+                "POST /hello HTTP/1.1\r\nhost: 127.0.0.1:{port}\r\n\r\n"
+            )
+            .starts_with("HTTP/1.1 404 ")
+        );
+    }
+
+    #[test]
+    fn refuses_a_foreign_or_missing_host() {
+        let server = LoopbackServer::bind("test", 0, Duration::from_secs(2), "", |_| {
+            Some(("text/plain", Arc::from(&b"secret\n"[..])))
+        })
+        .unwrap();
+
+        for request in [
+            "GET / HTTP/1.1\r\nHost: attacker.example:{port}\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: 127.0.0.1:1\r\n\r\n",
+            "GET / HTTP/1.1\r\n\r\n",
+        ] {
+            let response = get(&server, request);
+            assert!(
+                response.starts_with("HTTP/1.1 4"),
+                "{request:?}: {response}"
+            );
+            assert!(!response.contains("secret"), "{request:?}");
+        }
+    }
+}
@@ -1,15 +1,93 @@
 #[cfg(target_os = "macos")]
 fn main() -> anyhow::Result<()> {
-    if std::env::var("ALVR_BRIDGE_INPUT").as_deref() == Ok("iosurface") {
-        let config = alvr_macos_bridge::NativeSourceConfig::from_env()?;
-        let summary =
-            alvr_macos_bridge::run_native_source_probe(config, |report| println!("{report}"))?;
-        println!("{summary}");
-    } else {
-        let config = alvr_macos_bridge::ProbeConfig::from_env()?;
-        let summary = alvr_macos_bridge::run_surface_probe(config, |report| println!("{report}"))?;
-        println!("{summary}");
+    use alvr_macos_bridge::{Cli, CliCommand};
+
+    let cli = Cli::parse(std::env::args_os().skip(1).collect())?;
+    for (name, value) in cli.unset_env() {
+        // SAFETY: the bridge has not started any threads yet.
+        unsafe { std::env::set_var(name, value) };
+    }
+    match cli.command {
+        CliCommand::Help => {
+            println!("{}", alvr_macos_bridge::CLI_USAGE);
+            return Ok(());
+        }
+        CliCommand::Status { json } => {
+            let status =
+                alvr_macos_bridge::query_status(&alvr_macos_bridge::control_socket_from_env()?)?;
+            if json {
+                println!("{status}");
+            } else {
+                println!("{}", alvr_macos_bridge::status_line(&status));
+            }
+            return Ok(());
+        }
+        CliCommand::InstallService => {
+            alvr_macos_bridge::install_service()?;
+            return Ok(());
+        }
+        CliCommand::UninstallService => {
+            alvr_macos_bridge::uninstall_service()?;
+            return Ok(());
+        }
+        CliCommand::Doctor => {
+            alvr_macos_bridge::run_doctor()?;
+            return Ok(());
+        }
+        CliCommand::Bench => {
+            alvr_macos_bridge::run_bench()?;
+            return Ok(());
+        }
+        CliCommand::Service => {
+            println!(
+                "service started label={} pid={}",
+                alvr_macos_bridge::SERVICE_LABEL,
+                std::process::id()
+            );
+        }
+        CliCommand::Run => {}
+    }
+    let log_file = alvr_macos_bridge::LogFile::from_env()?;
+    if let Some(path) = log_file.as_ref().and_then(|log_file| log_file.path()) {
+        println!("log_file path={}", path.display());
+    }
+    alvr_macos_bridge::install_crash_reporter()?;
+    let trace = alvr_macos_bridge::TraceExport::from_env()?;
+    if let Some(trace) = &trace {
+        println!("trace_export path={}", trace.path().display());
+    }
+    let result = match alvr_macos_bridge::PreviewWindow::from_env() {
+        Some(preview) => preview.run(run),
+        None => run(),
+    };
+    // The error goes out while the log file still copies stderr, rather than
+    // after `main` returns, and on one line with the whole cause chain.
+    if let Err(error) = &result {
+        let code = alvr_macos_bridge::error_code(error);
+        eprintln!("Error: code={code} message={error:#}");
+        // A requested restart is not a crash, only a failed exit for launchd.
+        if code != "restart"
+            && let Some(path) = alvr_macos_bridge::write_crash_report(error)
+        {
+            eprintln!("crash_report path={}", path.display());
+        }
     }
+    // The trace is written out before the log file stops copying, so a
+    // failure to flush it still reaches the log.
+    drop(trace);
+    drop(log_file);
+    if result.is_err() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn run() -> anyhow::Result<()> {
+    alvr_macos_bridge::install_shutdown_handlers()?;
+    let bridge = alvr_macos_bridge::Bridge::from_env()?;
+    let summary = bridge.run(|report| println!("{report}"))?;
+    println!("{summary}");
     Ok(())
 }
 
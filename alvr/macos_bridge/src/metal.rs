@@ -1,6 +1,7 @@
-use crate::{SurfaceLease, native_source::NativeSourceFrame};
-use anyhow::{Result, anyhow};
+use crate::{ColorMatrix, Foveation, SurfaceLease, native_source::NativeSourceFrame};
+use anyhow::{Result, anyhow, bail};
 use std::{
+    env,
     ffi::{CStr, c_char, c_int, c_void},
     ptr::NonNull,
     time::{Duration, Instant},
@@ -9,6 +10,15 @@ use std::{
 const ERROR_CAPACITY: usize = 512;
 const METAL_LIBRARY: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/bgra_to_nv12.metallib"));
 
+/// `AlvrFoveation` in metal_converter.mm.
+#[repr(C)]
+struct FoveationParams {
+    eye_ratio: [f32; 2],
+    center_size: [f32; 2],
+    center_shift: [f32; 2],
+    edge_ratio: [f32; 2],
+}
+
 unsafe extern "C" {
     fn alvr_metal_converter_create(
         library_bytes: *const u8,
@@ -23,14 +33,53 @@ unsafe extern "C" {
         destination_buffer: *mut c_void,
         source_width: u32,
         source_height: u32,
+        sharpen_strength: f32,
+        sharpen_adaptive: u32,
+        matrix_kr: f32,
+        matrix_kb: f32,
+        foveation: *const FoveationParams,
+        alpha_key: *const [f32; 3],
         gpu_duration_ns: *mut u64,
         error_buffer: *mut c_char,
         error_capacity: usize,
     ) -> c_int;
 }
 
+/// How `ALVR_BRIDGE_SHARPEN` sharpens luma.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SharpenMode {
+    /// A fixed-strength unsharp mask.
+    #[default]
+    Unsharp,
+    /// Contrast-adaptive sharpening, which backs off where the neighbourhood
+    /// already has contrast, so text and hard edges do not ring.
+    Adaptive,
+}
+
+impl SharpenMode {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Unsharp => "unsharp",
+            Self::Adaptive => "cas",
+        }
+    }
+
+    pub(crate) fn from_env() -> Result<Self> {
+        match env::var("ALVR_BRIDGE_SHARPEN_MODE").as_deref() {
+            Err(env::VarError::NotPresent) | Ok("unsharp") => Ok(Self::Unsharp),
+            Ok("cas") => Ok(Self::Adaptive),
+            _ => bail!("ALVR_BRIDGE_SHARPEN_MODE must be unsharp or cas"),
+        }
+    }
+}
+
 pub struct MetalConverter {
     converter: NonNull<c_void>,
+    sharpen_strength: f32,
+    sharpen_mode: SharpenMode,
+    color_matrix: ColorMatrix,
+    foveation: Option<FoveationParams>,
+    alpha_key: Option<[f32; 3]>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -53,11 +102,57 @@ impl MetalConverter {
         NonNull::new(converter)
             .map(|converter| {
                 eprintln!("metal_converter resampler=bilinear eye_boundary=clamped");
-                Self { converter }
+                Self {
+                    converter,
+                    sharpen_strength: 0.0,
+                    sharpen_mode: SharpenMode::default(),
+                    color_matrix: ColorMatrix::default(),
+                    foveation: None,
+                    alpha_key: None,
+                }
             })
             .ok_or_else(|| anyhow!(error_message(&error)))
     }
 
+    /// Sharpens luma after scaling with an unsharp mask confined to each
+    /// eye. 0 disables it; 1 pushes each pixel a full step away from the mean
+    /// of its four neighbours.
+    pub fn set_sharpening(&mut self, strength: f32) {
+        self.sharpen_strength = strength;
+    }
+
+    /// Picks the sharpening filter. Under `Adaptive` the strength runs from
+    /// CAS's lightest setting just above 0 to its strongest at 1; 0 still
+    /// disables sharpening in either mode.
+    pub fn set_sharpen_mode(&mut self, mode: SharpenMode) {
+        self.sharpen_mode = mode;
+    }
+
+    /// The YCbCr matrix RGB sources are converted with. The range follows
+    /// the destination surface's pixel format.
+    pub fn set_color_matrix(&mut self, matrix: ColorMatrix) {
+        self.color_matrix = matrix;
+    }
+
+    /// Compresses each eye's periphery the way ALVR's foveated encoding
+    /// does, for a destination of `foveation.stream_size`. `None` scales
+    /// the source uniformly.
+    pub fn set_foveation(&mut self, foveation: Option<Foveation>) {
+        self.foveation = foveation.map(|foveation| FoveationParams {
+            eye_ratio: foveation.eye_ratio.into(),
+            center_size: foveation.center_size.into(),
+            center_shift: foveation.center_shift.into(),
+            edge_ratio: foveation.edge_ratio.into(),
+        });
+    }
+
+    /// Composites RGB sources over `key`, an (r, g, b) colour, by their
+    /// alpha, so a client keying that colour out shows passthrough wherever
+    /// the frame is transparent. `None` ignores alpha.
+    pub fn set_alpha_key(&mut self, key: Option<[u8; 3]>) {
+        self.alpha_key = key.map(|key| key.map(|channel| f32::from(channel) / 255.0));
+    }
+
     pub fn convert(
         &self,
         source_frame: &NativeSourceFrame<'_>,
@@ -73,7 +168,7 @@ impl MetalConverter {
         )
     }
 
-    fn convert_raw(
+    pub(crate) fn convert_raw(
         &self,
         source_surface: NonNull<c_void>,
         destination_buffer: NonNull<c_void>,
@@ -82,6 +177,7 @@ impl MetalConverter {
     ) -> Result<ConversionTiming> {
         let mut error = [0 as c_char; ERROR_CAPACITY];
         let mut gpu_duration_ns = 0;
+        let (matrix_kr, matrix_kb) = self.color_matrix.coefficients();
         let start = Instant::now();
         let status = unsafe {
             alvr_metal_converter_convert(
@@ -90,6 +186,16 @@ impl MetalConverter {
                 destination_buffer.as_ptr(),
                 source_width,
                 source_height,
+                self.sharpen_strength,
+                u32::from(self.sharpen_mode == SharpenMode::Adaptive),
+                matrix_kr,
+                matrix_kb,
+                self.foveation
+                    .as_ref()
+                    .map_or(std::ptr::null(), |foveation| foveation),
+                self.alpha_key
+                    .as_ref()
+                    .map_or(std::ptr::null(), |alpha_key| alpha_key),
                 &mut gpu_duration_ns,
                 error.as_mut_ptr(),
                 error.len(),
@@ -125,17 +231,28 @@ fn error_message(buffer: &[c_char]) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{SurfacePool, native_source::NativeSource};
+    use crate::{
+        ColorRange, ColorSpace, SurfaceFormat, SurfacePool,
+        native_source::{DEFAULT_SOURCE_SLOTS, NativeSource, SourceFormat},
+    };
+    use proptest::{collection::vec, prelude::*};
     use std::{
         ptr,
         time::{SystemTime, UNIX_EPOCH},
     };
 
+    /// Code values the GPU may differ from the reference by. Its bilinear
+    /// weights have a few fractional bits and its arithmetic is f32, so a
+    /// sample can round to either neighbour of the exact result.
+    const TOLERANCE: u8 = 2;
+
     unsafe extern "C" {
         fn IOSurfaceLock(surface: *mut c_void, options: u32, seed: *mut u32) -> i32;
         fn IOSurfaceUnlock(surface: *mut c_void, options: u32, seed: *mut u32) -> i32;
         fn IOSurfaceGetBaseAddress(surface: *mut c_void) -> *mut c_void;
         fn IOSurfaceGetBytesPerRow(surface: *mut c_void) -> usize;
+        fn IOSurfaceGetBaseAddressOfPlane(surface: *mut c_void, plane: usize) -> *mut c_void;
+        fn IOSurfaceGetBytesPerRowOfPlane(surface: *mut c_void, plane: usize) -> usize;
         fn CVPixelBufferLockBaseAddress(buffer: *mut c_void, flags: u64) -> i32;
         fn CVPixelBufferUnlockBaseAddress(buffer: *mut c_void, flags: u64) -> i32;
         fn CVPixelBufferGetBaseAddressOfPlane(buffer: *mut c_void, plane: usize) -> *mut c_void;
@@ -149,7 +266,16 @@ mod tests {
             .unwrap()
             .as_nanos() as u64;
         let service = format!("com.alvr.metal-test.{}.{}", std::process::id(), nonce);
-        let source = NativeSource::new(&service, nonce, 8, 6).unwrap();
+        let source = NativeSource::new(
+            &service,
+            nonce,
+            8,
+            6,
+            SurfaceFormat::Nv12,
+            SourceFormat::Bgra,
+            DEFAULT_SOURCE_SLOTS,
+        )
+        .unwrap();
         let source_surface = source.surface(0).unwrap();
 
         unsafe {
@@ -176,7 +302,7 @@ mod tests {
             );
         }
 
-        let pool = SurfacePool::new(4, 4, 1).unwrap();
+        let pool = SurfacePool::new(4, 4, 1, SurfaceFormat::Nv12).unwrap();
         let lease = pool.try_acquire().unwrap().unwrap();
         let converter = MetalConverter::new().unwrap();
         converter
@@ -217,6 +343,225 @@ mod tests {
         }
     }
 
+    #[test]
+    fn converts_with_the_bt601_matrix_into_full_range_nv12() {
+        let nonce = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        let service = format!("com.alvr.metal-color-test.{}.{}", std::process::id(), nonce);
+        let source = NativeSource::new(
+            &service,
+            nonce,
+            8,
+            2,
+            SurfaceFormat::Nv12,
+            SourceFormat::Bgra,
+            DEFAULT_SOURCE_SLOTS,
+        )
+        .unwrap();
+        let source_surface = source.surface(0).unwrap();
+
+        unsafe {
+            let surface = source_surface.as_ptr();
+            assert_eq!(IOSurfaceLock(surface, 0, ptr::null_mut()), 0);
+            let base = IOSurfaceGetBaseAddress(surface).cast::<u8>();
+            let row_bytes = IOSurfaceGetBytesPerRow(surface);
+            assert!(!base.is_null());
+            for y in 0..2 {
+                for x in 0..8 {
+                    let bgra = if x < 4 {
+                        [0u8, 0, 255, 255]
+                    } else {
+                        [255u8, 0, 0, 255]
+                    };
+                    ptr::copy_nonoverlapping(bgra.as_ptr(), base.add(y * row_bytes + x * 4), 4);
+                }
+            }
+            assert_eq!(IOSurfaceUnlock(surface, 0, ptr::null_mut()), 0);
+        }
+
+        let color = ColorSpace {
+            matrix: ColorMatrix::Bt601,
+            range: ColorRange::Full,
+            hdr: None,
+        };
+        let pool = SurfacePool::with_color_space(4, 2, 1, SurfaceFormat::Nv12, color).unwrap();
+        let lease = pool.try_acquire().unwrap().unwrap();
+        let mut converter = MetalConverter::new().unwrap();
+        converter.set_color_matrix(ColorMatrix::Bt601);
+        converter
+            .convert_raw(source_surface, lease.cv_pixel_buffer(), 8, 2)
+            .unwrap();
+
+        unsafe {
+            let buffer = lease.cv_pixel_buffer().as_ptr();
+            assert_eq!(CVPixelBufferLockBaseAddress(buffer, 0), 0);
+            let y_base = CVPixelBufferGetBaseAddressOfPlane(buffer, 0).cast::<u8>();
+            let uv_base = CVPixelBufferGetBaseAddressOfPlane(buffer, 1).cast::<u8>();
+            assert!(!y_base.is_null() && !uv_base.is_null());
+
+            assert!((74..=78).contains(&*y_base), "unexpected red luma");
+            assert!((27..=31).contains(&*y_base.add(3)), "unexpected blue luma");
+            assert!((83..=87).contains(&*uv_base), "unexpected red Cb");
+            assert!((253..=255).contains(&*uv_base.add(1)), "unexpected red Cr");
+            assert!((253..=255).contains(&*uv_base.add(2)), "unexpected blue Cb");
+            assert!((105..=109).contains(&*uv_base.add(3)), "unexpected blue Cr");
+            assert_eq!(CVPixelBufferUnlockBaseAddress(buffer, 0), 0);
+        }
+    }
+
+    #[test]
+    fn converts_odd_sized_eyes_from_a_padded_surface() {
+        let nonce = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        let service = format!("com.alvr.metal-odd-test.{}.{}", std::process::id(), nonce);
+        let source = NativeSource::new(
+            &service,
+            nonce,
+            6,
+            3,
+            SurfaceFormat::Nv12,
+            SourceFormat::Bgra,
+            DEFAULT_SOURCE_SLOTS,
+        )
+        .unwrap();
+        let source_surface = source.surface(0).unwrap();
+
+        unsafe {
+            assert_eq!(
+                IOSurfaceLock(source_surface.as_ptr(), 0, ptr::null_mut()),
+                0
+            );
+            let base = IOSurfaceGetBaseAddress(source_surface.as_ptr()).cast::<u8>();
+            let row_bytes = IOSurfaceGetBytesPerRow(source_surface.as_ptr());
+            assert!(!base.is_null());
+            assert!(row_bytes > 6 * 4, "expected IOSurface row padding");
+            for y in 0..3 {
+                ptr::write_bytes(base.add(y * row_bytes), 0, row_bytes);
+                for x in 0..6 {
+                    let pixel = base.add(y * row_bytes + x * 4);
+                    if x < 3 {
+                        ptr::copy_nonoverlapping([0u8, 0, 255, 255].as_ptr(), pixel, 4);
+                    } else {
+                        ptr::copy_nonoverlapping([255u8, 0, 0, 255].as_ptr(), pixel, 4);
+                    }
+                }
+            }
+            assert_eq!(
+                IOSurfaceUnlock(source_surface.as_ptr(), 0, ptr::null_mut()),
+                0
+            );
+        }
+
+        let pool = SurfacePool::new(4, 2, 1, SurfaceFormat::Nv12).unwrap();
+        let lease = pool.try_acquire().unwrap().unwrap();
+        let converter = MetalConverter::new().unwrap();
+        converter
+            .convert_raw(source_surface, lease.cv_pixel_buffer(), 6, 3)
+            .unwrap();
+
+        unsafe {
+            let buffer = lease.cv_pixel_buffer().as_ptr();
+            assert_eq!(CVPixelBufferLockBaseAddress(buffer, 0), 0);
+            let y_base = CVPixelBufferGetBaseAddressOfPlane(buffer, 0).cast::<u8>();
+            let y_stride = CVPixelBufferGetBytesPerRowOfPlane(buffer, 0);
+            let uv_base = CVPixelBufferGetBaseAddressOfPlane(buffer, 1).cast::<u8>();
+            assert!(!y_base.is_null() && !uv_base.is_null());
+
+            for row in 0..2 {
+                let y_row = y_base.add(row * y_stride);
+                for x in 0..2 {
+                    let red_y = *y_row.add(x);
+                    let blue_y = *y_row.add(2 + x);
+                    assert!((60..=66).contains(&red_y), "unexpected red luma {red_y}");
+                    assert!((29..=35).contains(&blue_y), "unexpected blue luma {blue_y}");
+                }
+            }
+            let red_cr = *uv_base.add(1);
+            let blue_cb = *uv_base.add(2);
+            assert!((237..=243).contains(&red_cr), "unexpected red Cr {red_cr}");
+            assert!(
+                (237..=243).contains(&blue_cb),
+                "unexpected blue Cb {blue_cb}"
+            );
+            assert_eq!(CVPixelBufferUnlockBaseAddress(buffer, 0), 0);
+        }
+    }
+
+    #[test]
+    fn converts_packed_rgb10a2_eyes_to_p010() {
+        let nonce = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        let service = format!("com.alvr.metal-p010-test.{}.{}", std::process::id(), nonce);
+        let source = NativeSource::new(
+            &service,
+            nonce,
+            8,
+            2,
+            SurfaceFormat::P010,
+            SourceFormat::Bgra,
+            DEFAULT_SOURCE_SLOTS,
+        )
+        .unwrap();
+        let source_surface = source.surface(0).unwrap();
+
+        unsafe {
+            assert_eq!(
+                IOSurfaceLock(source_surface.as_ptr(), 0, ptr::null_mut()),
+                0
+            );
+            let base = IOSurfaceGetBaseAddress(source_surface.as_ptr()).cast::<u8>();
+            let row_bytes = IOSurfaceGetBytesPerRow(source_surface.as_ptr());
+            assert!(!base.is_null());
+            for y in 0..2 {
+                for x in 0..8 {
+                    let red_or_blue: u32 = if x < 4 { 0x3ff << 20 } else { 0x3ff };
+                    let pixel = (red_or_blue | (3 << 30)).to_le_bytes();
+                    ptr::copy_nonoverlapping(pixel.as_ptr(), base.add(y * row_bytes + x * 4), 4);
+                }
+            }
+            assert_eq!(
+                IOSurfaceUnlock(source_surface.as_ptr(), 0, ptr::null_mut()),
+                0
+            );
+        }
+
+        let pool = SurfacePool::new(4, 2, 1, SurfaceFormat::P010).unwrap();
+        let lease = pool.try_acquire().unwrap().unwrap();
+        let converter = MetalConverter::new().unwrap();
+        converter
+            .convert_raw(source_surface, lease.cv_pixel_buffer(), 8, 2)
+            .unwrap();
+
+        unsafe {
+            let buffer = lease.cv_pixel_buffer().as_ptr();
+            assert_eq!(CVPixelBufferLockBaseAddress(buffer, 0), 0);
+            let y_base = CVPixelBufferGetBaseAddressOfPlane(buffer, 0).cast::<u16>();
+            let uv_base = CVPixelBufferGetBaseAddressOfPlane(buffer, 1).cast::<u16>();
+            assert!(!y_base.is_null() && !uv_base.is_null());
+
+            let red_y = *y_base;
+            let blue_y = *y_base.add(3);
+            assert_eq!(red_y & 0x3f, 0, "P010 luma must leave the low bits clear");
+            assert!((248..=252).contains(&(red_y >> 6)), "unexpected red luma");
+            assert!((124..=128).contains(&(blue_y >> 6)), "unexpected blue luma");
+
+            let red_cr = *uv_base.add(1) >> 6;
+            let blue_cb = *uv_base.add(2) >> 6;
+            assert!((958..=962).contains(&red_cr), "unexpected red Cr {red_cr}");
+            assert!(
+                (958..=962).contains(&blue_cb),
+                "unexpected blue Cb {blue_cb}"
+            );
+            assert_eq!(CVPixelBufferUnlockBaseAddress(buffer, 0), 0);
+        }
+    }
+
     #[test]
     fn bilinear_downscale_smooths_each_eye_without_cross_eye_bleed() {
         let nonce = SystemTime::now()
@@ -228,7 +573,16 @@ mod tests {
             std::process::id(),
             nonce
         );
-        let source = NativeSource::new(&service, nonce, 8, 2).unwrap();
+        let source = NativeSource::new(
+            &service,
+            nonce,
+            8,
+            2,
+            SurfaceFormat::Nv12,
+            SourceFormat::Bgra,
+            DEFAULT_SOURCE_SLOTS,
+        )
+        .unwrap();
         let source_surface = source.surface(0).unwrap();
 
         unsafe {
@@ -260,7 +614,7 @@ mod tests {
             );
         }
 
-        let pool = SurfacePool::new(4, 2, 1).unwrap();
+        let pool = SurfacePool::new(4, 2, 1, SurfaceFormat::Nv12).unwrap();
         let lease = pool.try_acquire().unwrap().unwrap();
         let converter = MetalConverter::new().unwrap();
         converter
@@ -299,4 +653,565 @@ mod tests {
             assert_eq!(CVPixelBufferUnlockBaseAddress(buffer, 0), 0);
         }
     }
+
+    #[test]
+    fn scales_nv12_sources_per_eye_without_converting_them() {
+        let nonce = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        let service = format!("com.alvr.metal-scale-test.{}.{}", std::process::id(), nonce);
+        let source = NativeSource::new(
+            &service,
+            nonce,
+            8,
+            4,
+            SurfaceFormat::Nv12,
+            SourceFormat::Nv12,
+            DEFAULT_SOURCE_SLOTS,
+        )
+        .unwrap();
+        let source_surface = source.surface(0).unwrap();
+
+        unsafe {
+            let surface = source_surface.as_ptr();
+            assert_eq!(IOSurfaceLock(surface, 0, ptr::null_mut()), 0);
+            let y_base = IOSurfaceGetBaseAddressOfPlane(surface, 0).cast::<u8>();
+            let y_stride = IOSurfaceGetBytesPerRowOfPlane(surface, 0);
+            let uv_base = IOSurfaceGetBaseAddressOfPlane(surface, 1).cast::<u8>();
+            let uv_stride = IOSurfaceGetBytesPerRowOfPlane(surface, 1);
+            assert!(!y_base.is_null() && !uv_base.is_null());
+            for y in 0..4 {
+                for x in 0..8 {
+                    *y_base.add(y * y_stride + x) = match x {
+                        0..4 if x % 2 == 0 => 16,
+                        0..4 => 235,
+                        _ => 200,
+                    };
+                }
+            }
+            for y in 0..2 {
+                for x in 0..4 {
+                    let (cb, cr) = if x < 2 { (90, 240) } else { (240, 110) };
+                    *uv_base.add(y * uv_stride + x * 2) = cb;
+                    *uv_base.add(y * uv_stride + x * 2 + 1) = cr;
+                }
+            }
+            assert_eq!(IOSurfaceUnlock(surface, 0, ptr::null_mut()), 0);
+        }
+
+        let pool = SurfacePool::new(4, 2, 1, SurfaceFormat::Nv12).unwrap();
+        let lease = pool.try_acquire().unwrap().unwrap();
+        let converter = MetalConverter::new().unwrap();
+        converter
+            .convert_raw(source_surface, lease.cv_pixel_buffer(), 8, 4)
+            .unwrap();
+
+        unsafe {
+            let buffer = lease.cv_pixel_buffer().as_ptr();
+            assert_eq!(CVPixelBufferLockBaseAddress(buffer, 0), 0);
+            let y_base = CVPixelBufferGetBaseAddressOfPlane(buffer, 0).cast::<u8>();
+            let y_stride = CVPixelBufferGetBytesPerRowOfPlane(buffer, 0);
+            let uv_base = CVPixelBufferGetBaseAddressOfPlane(buffer, 1).cast::<u8>();
+            assert!(!y_base.is_null() && !uv_base.is_null());
+
+            for row in 0..2 {
+                let y_row = y_base.add(row * y_stride);
+                for x in 0..2 {
+                    assert!(
+                        (124..=127).contains(&*y_row.add(x)),
+                        "unexpected filtered luma"
+                    );
+                    assert!(
+                        (199..=201).contains(&*y_row.add(2 + x)),
+                        "right eye was contaminated at the stereo boundary"
+                    );
+                }
+            }
+            assert!((89..=91).contains(&*uv_base), "unexpected left Cb");
+            assert!((239..=241).contains(&*uv_base.add(1)), "unexpected left Cr");
+            assert!(
+                (239..=241).contains(&*uv_base.add(2)),
+                "unexpected right Cb"
+            );
+            assert!(
+                (109..=111).contains(&*uv_base.add(3)),
+                "unexpected right Cr"
+            );
+            assert_eq!(CVPixelBufferUnlockBaseAddress(buffer, 0), 0);
+        }
+    }
+
+    #[test]
+    fn sharpening_steepens_edges_without_reaching_across_eyes() {
+        let nonce = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        let service = format!(
+            "com.alvr.metal-sharpen-test.{}.{}",
+            std::process::id(),
+            nonce
+        );
+        let source = NativeSource::new(
+            &service,
+            nonce,
+            8,
+            2,
+            SurfaceFormat::Nv12,
+            SourceFormat::Bgra,
+            DEFAULT_SOURCE_SLOTS,
+        )
+        .unwrap();
+        let source_surface = source.surface(0).unwrap();
+
+        unsafe {
+            let surface = source_surface.as_ptr();
+            assert_eq!(IOSurfaceLock(surface, 0, ptr::null_mut()), 0);
+            let base = IOSurfaceGetBaseAddress(surface).cast::<u8>();
+            let row_bytes = IOSurfaceGetBytesPerRow(surface);
+            assert!(!base.is_null());
+            for y in 0..2 {
+                for x in 0..8 {
+                    let gray = match x {
+                        0..2 => 64,
+                        2..4 => 192,
+                        _ => 0,
+                    };
+                    ptr::copy_nonoverlapping(
+                        [gray, gray, gray, 255].as_ptr(),
+                        base.add(y * row_bytes + x * 4),
+                        4,
+                    );
+                }
+            }
+            assert_eq!(IOSurfaceUnlock(surface, 0, ptr::null_mut()), 0);
+        }
+
+        let pool = SurfacePool::new(8, 2, 1, SurfaceFormat::Nv12).unwrap();
+        let lease = pool.try_acquire().unwrap().unwrap();
+        let mut converter = MetalConverter::new().unwrap();
+        converter.set_sharpening(1.0);
+        converter
+            .convert_raw(source_surface, lease.cv_pixel_buffer(), 8, 2)
+            .unwrap();
+
+        unsafe {
+            let buffer = lease.cv_pixel_buffer().as_ptr();
+            assert_eq!(CVPixelBufferLockBaseAddress(buffer, 0), 0);
+            let y_base = CVPixelBufferGetBaseAddressOfPlane(buffer, 0).cast::<u8>();
+            let y_stride = CVPixelBufferGetBytesPerRowOfPlane(buffer, 0);
+            assert!(!y_base.is_null());
+
+            for row in 0..2 {
+                let y_row = y_base.add(row * y_stride);
+                assert!((70..=72).contains(&*y_row), "flat luma was sharpened");
+                assert!(
+                    (42..=46).contains(&*y_row.add(1)),
+                    "dark edge was not deepened"
+                );
+                assert!(
+                    (206..=211).contains(&*y_row.add(2)),
+                    "bright edge was not lifted"
+                );
+                assert!(
+                    (180..=182).contains(&*y_row.add(3)),
+                    "sharpening reached across the stereo boundary"
+                );
+                assert_eq!(
+                    *y_row.add(4),
+                    16,
+                    "right eye was sharpened against the left"
+                );
+            }
+            assert_eq!(CVPixelBufferUnlockBaseAddress(buffer, 0), 0);
+        }
+    }
+
+    #[test]
+    fn adaptive_sharpening_is_gentler_on_high_contrast_edges() {
+        let nonce = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        let service = format!("com.alvr.metal-cas-test.{}.{}", std::process::id(), nonce);
+        let source = NativeSource::new(
+            &service,
+            nonce,
+            8,
+            2,
+            SurfaceFormat::Nv12,
+            SourceFormat::Bgra,
+            DEFAULT_SOURCE_SLOTS,
+        )
+        .unwrap();
+        let source_surface = source.surface(0).unwrap();
+
+        unsafe {
+            let surface = source_surface.as_ptr();
+            assert_eq!(IOSurfaceLock(surface, 0, ptr::null_mut()), 0);
+            let base = IOSurfaceGetBaseAddress(surface).cast::<u8>();
+            let row_bytes = IOSurfaceGetBytesPerRow(surface);
+            assert!(!base.is_null());
+            for y in 0..2 {
+                for x in 0..8 {
+                    let gray = match x {
+                        0..2 => 64,
+                        2..4 => 192,
+                        _ => 0,
+                    };
+                    ptr::copy_nonoverlapping(
+                        [gray, gray, gray, 255].as_ptr(),
+                        base.add(y * row_bytes + x * 4),
+                        4,
+                    );
+                }
+            }
+            assert_eq!(IOSurfaceUnlock(surface, 0, ptr::null_mut()), 0);
+        }
+
+        let pool = SurfacePool::new(8, 2, 1, SurfaceFormat::Nv12).unwrap();
+        let lease = pool.try_acquire().unwrap().unwrap();
+        let mut converter = MetalConverter::new().unwrap();
+        converter.set_sharpening(1.0);
+        converter.set_sharpen_mode(SharpenMode::Adaptive);
+        converter
+            .convert_raw(source_surface, lease.cv_pixel_buffer(), 8, 2)
+            .unwrap();
+
+        unsafe {
+            let buffer = lease.cv_pixel_buffer().as_ptr();
+            assert_eq!(CVPixelBufferLockBaseAddress(buffer, 0), 0);
+            let y_base = CVPixelBufferGetBaseAddressOfPlane(buffer, 0).cast::<u8>();
+            let y_stride = CVPixelBufferGetBytesPerRowOfPlane(buffer, 0);
+            assert!(!y_base.is_null());
+
+            // The unsharp mask at the same strength takes these edges to
+            // about 44 and 208.
+            for row in 0..2 {
+                let y_row = y_base.add(row * y_stride);
+                assert!((70..=72).contains(&*y_row), "flat luma was sharpened");
+                assert!(
+                    (46..=50).contains(&*y_row.add(1)),
+                    "dark edge was not deepened by the adaptive amount"
+                );
+                assert!(
+                    (202..=206).contains(&*y_row.add(2)),
+                    "bright edge was not lifted by the adaptive amount"
+                );
+                assert!(
+                    (180..=182).contains(&*y_row.add(3)),
+                    "sharpening reached across the stereo boundary"
+                );
+                assert_eq!(*y_row.add(4), 16, "black was sharpened");
+            }
+            assert_eq!(CVPixelBufferUnlockBaseAddress(buffer, 0), 0);
+        }
+    }
+
+    #[test]
+    fn composites_transparent_pixels_over_the_alpha_key() {
+        let nonce = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        let service = format!(
+            "com.alvr.metal-alpha-key-test.{}.{}",
+            std::process::id(),
+            nonce
+        );
+        let source = NativeSource::new(
+            &service,
+            nonce,
+            8,
+            2,
+            SurfaceFormat::Nv12,
+            SourceFormat::Bgra,
+            DEFAULT_SOURCE_SLOTS,
+        )
+        .unwrap();
+        let source_surface = source.surface(0).unwrap();
+
+        // A transparent red left eye and an opaque gray right eye.
+        unsafe {
+            let surface = source_surface.as_ptr();
+            assert_eq!(IOSurfaceLock(surface, 0, ptr::null_mut()), 0);
+            let base = IOSurfaceGetBaseAddress(surface).cast::<u8>();
+            let row_bytes = IOSurfaceGetBytesPerRow(surface);
+            assert!(!base.is_null());
+            for y in 0..2 {
+                for x in 0..8 {
+                    let bgra = if x < 4 {
+                        [0, 0, 255, 0]
+                    } else {
+                        [128, 128, 128, 255]
+                    };
+                    ptr::copy_nonoverlapping(bgra.as_ptr(), base.add(y * row_bytes + x * 4), 4);
+                }
+            }
+            assert_eq!(IOSurfaceUnlock(surface, 0, ptr::null_mut()), 0);
+        }
+
+        let pool = SurfacePool::new(8, 2, 1, SurfaceFormat::Nv12).unwrap();
+        let lease = pool.try_acquire().unwrap().unwrap();
+        let mut converter = MetalConverter::new().unwrap();
+        converter.set_alpha_key(Some([0, 255, 0]));
+        converter
+            .convert_raw(source_surface, lease.cv_pixel_buffer(), 8, 2)
+            .unwrap();
+
+        unsafe {
+            let buffer = lease.cv_pixel_buffer().as_ptr();
+            assert_eq!(CVPixelBufferLockBaseAddress(buffer, 0), 0);
+            let y_base = CVPixelBufferGetBaseAddressOfPlane(buffer, 0).cast::<u8>();
+            let y_stride = CVPixelBufferGetBytesPerRowOfPlane(buffer, 0);
+            let uv_base = CVPixelBufferGetBaseAddressOfPlane(buffer, 1).cast::<u8>();
+            assert!(!y_base.is_null() && !uv_base.is_null());
+
+            for row in 0..2 {
+                let y_row = y_base.add(row * y_stride);
+                for x in 0..4 {
+                    assert!(
+                        (172..=174).contains(&*y_row.add(x)),
+                        "transparent pixel did not take the key's luma"
+                    );
+                }
+                for x in 4..8 {
+                    assert!(
+                        (125..=127).contains(&*y_row.add(x)),
+                        "opaque pixel was keyed"
+                    );
+                }
+            }
+            assert!((41..=43).contains(&*uv_base), "key's Cb");
+            assert!((25..=27).contains(&*uv_base.add(1)), "key's Cr");
+            assert_eq!(CVPixelBufferUnlockBaseAddress(buffer, 0), 0);
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct ConversionCase {
+        source: (usize, usize),
+        output: (usize, usize),
+        matrix: ColorMatrix,
+        range: ColorRange,
+        pixels: Vec<[u8; 4]>,
+    }
+
+    /// Opaque BGRA pixels, with the channel extremes drawn often.
+    fn bgra_pixel() -> impl Strategy<Value = [u8; 4]> {
+        let channel = || prop_oneof![Just(0u8), Just(255u8), any::<u8>()];
+        (channel(), channel(), channel()).prop_map(|(b, g, r)| [b, g, r, 255])
+    }
+
+    /// Odd and even eye sizes, scaled up and down into every output shape
+    /// the converter accepts: widths in multiples of 4, even heights.
+    fn conversion_case() -> impl Strategy<Value = ConversionCase> {
+        let matrix = prop_oneof![
+            Just(ColorMatrix::Bt601),
+            Just(ColorMatrix::Bt709),
+            Just(ColorMatrix::Bt2020),
+        ];
+        let range = prop_oneof![Just(ColorRange::Limited), Just(ColorRange::Full)];
+        (
+            1..=13usize,
+            1..=13usize,
+            1..=6usize,
+            1..=6usize,
+            matrix,
+            range,
+        )
+            .prop_flat_map(
+                |(eye_width, height, output_quads, output_rows, matrix, range)| {
+                    let source = (eye_width * 2, height);
+                    vec(bgra_pixel(), source.0 * source.1).prop_map(move |pixels| ConversionCase {
+                        source,
+                        output: (output_quads * 4, output_rows * 2),
+                        matrix,
+                        range,
+                        pixels,
+                    })
+                },
+            )
+    }
+
+    /// `bgra_to_nv12` in f64: the same per-eye sample positions and clamped
+    /// bilinear taps, then the matrix, range and rounding. Returns the luma
+    /// plane and the interleaved CbCr plane without row padding.
+    fn reference_nv12(case: &ConversionCase) -> (Vec<u8>, Vec<u8>) {
+        let (source_width, source_height) = case.source;
+        let (output_width, output_height) = case.output;
+        let (source_eye, output_eye) = (source_width / 2, output_width / 2);
+        let (kr, kb) = case.matrix.coefficients();
+        let (kr, kb) = (f64::from(kr), f64::from(kb));
+        let (luma_offset, luma_range, chroma_range) = match case.range {
+            ColorRange::Limited => (16.0, 219.0, 224.0),
+            ColorRange::Full => (0.0, 255.0, 255.0),
+        };
+
+        let scaled_center = |output: usize, source: usize, output_extent: usize| {
+            (output as f64 + 0.5) * source as f64 / output_extent as f64
+        };
+        // Texel pair and weight of the second for a pixel-space position,
+        // clamped to the texture's edge.
+        let taps = |position: f64, extent: usize| {
+            let first = (position - 0.5).floor();
+            let index = |texel: f64| (texel.max(0.0) as usize).min(extent - 1);
+            (index(first), index(first + 1.0), position - 0.5 - first)
+        };
+        let sample = |x: usize, y: usize| {
+            let eye = x / output_eye;
+            let eye_x = scaled_center(x - eye * output_eye, source_eye, output_eye)
+                .clamp(0.5, source_eye as f64 - 0.5);
+            let source_y = scaled_center(y, source_height, output_height)
+                .clamp(0.5, source_height as f64 - 0.5);
+            let (x0, x1, fx) = taps((eye * source_eye) as f64 + eye_x, source_width);
+            let (y0, y1, fy) = taps(source_y, source_height);
+            let mut rgb = [0.0; 3];
+            for (tx, ty, weight) in [
+                (x0, y0, (1.0 - fx) * (1.0 - fy)),
+                (x1, y0, fx * (1.0 - fy)),
+                (x0, y1, (1.0 - fx) * fy),
+                (x1, y1, fx * fy),
+            ] {
+                let [b, g, r, _] = case.pixels[ty * source_width + tx];
+                for (sum, channel) in rgb.iter_mut().zip([r, g, b]) {
+                    *sum += weight * f64::from(channel) / 255.0;
+                }
+            }
+            rgb
+        };
+        let unit_luma = |[r, g, b]: [f64; 3]| kr * r + (1.0 - kr - kb) * g + kb * b;
+        let code = |value: f64| value.round_ties_even().clamp(0.0, 255.0) as u8;
+
+        let mut luma = Vec::with_capacity(output_width * output_height);
+        for y in 0..output_height {
+            for x in 0..output_width {
+                luma.push(code(luma_offset + luma_range * unit_luma(sample(x, y))));
+            }
+        }
+        let mut chroma = Vec::with_capacity(output_width * output_height / 2);
+        for block_y in (0..output_height).step_by(2) {
+            for block_x in (0..output_width).step_by(2) {
+                let mut rgb = [0.0; 3];
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let texel = sample(block_x + dx, block_y + dy);
+                    for (sum, channel) in rgb.iter_mut().zip(texel) {
+                        *sum += channel * 0.25;
+                    }
+                }
+                let y = unit_luma(rgb);
+                chroma.push(code(128.0 + chroma_range * 0.5 * (rgb[2] - y) / (1.0 - kb)));
+                chroma.push(code(128.0 + chroma_range * 0.5 * (rgb[0] - y) / (1.0 - kr)));
+            }
+        }
+        (luma, chroma)
+    }
+
+    fn convert_on_gpu(case: &ConversionCase) -> (Vec<u8>, Vec<u8>) {
+        let (source_width, source_height) = case.source;
+        let (output_width, output_height) = case.output;
+        let nonce = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        let service = format!("com.alvr.metal-prop-test.{}.{}", std::process::id(), nonce);
+        let source = NativeSource::new(
+            &service,
+            nonce,
+            source_width as u32,
+            source_height as u32,
+            SurfaceFormat::Nv12,
+            SourceFormat::Bgra,
+            DEFAULT_SOURCE_SLOTS,
+        )
+        .unwrap();
+        let source_surface = source.surface(0).unwrap();
+
+        unsafe {
+            let surface = source_surface.as_ptr();
+            assert_eq!(IOSurfaceLock(surface, 0, ptr::null_mut()), 0);
+            let base = IOSurfaceGetBaseAddress(surface).cast::<u8>();
+            let row_bytes = IOSurfaceGetBytesPerRow(surface);
+            assert!(!base.is_null());
+            for (row, pixels) in case.pixels.chunks(source_width).enumerate() {
+                ptr::copy_nonoverlapping(
+                    pixels.as_ptr().cast::<u8>(),
+                    base.add(row * row_bytes),
+                    source_width * 4,
+                );
+            }
+            assert_eq!(IOSurfaceUnlock(surface, 0, ptr::null_mut()), 0);
+        }
+
+        let color = ColorSpace {
+            matrix: case.matrix,
+            range: case.range,
+            hdr: None,
+        };
+        let pool = SurfacePool::with_color_space(
+            output_width as u32,
+            output_height as u32,
+            1,
+            SurfaceFormat::Nv12,
+            color,
+        )
+        .unwrap();
+        let lease = pool.try_acquire().unwrap().unwrap();
+        let mut converter = MetalConverter::new().unwrap();
+        converter.set_color_matrix(case.matrix);
+        converter
+            .convert_raw(
+                source_surface,
+                lease.cv_pixel_buffer(),
+                source_width as u32,
+                source_height as u32,
+            )
+            .unwrap();
+
+        unsafe {
+            let buffer = lease.cv_pixel_buffer().as_ptr();
+            assert_eq!(CVPixelBufferLockBaseAddress(buffer, 0), 0);
+            let plane = |index, rows| {
+                let base = CVPixelBufferGetBaseAddressOfPlane(buffer, index).cast::<u8>();
+                let stride = CVPixelBufferGetBytesPerRowOfPlane(buffer, index);
+                assert!(!base.is_null());
+                (0..rows)
+                    .flat_map(|row| {
+                        std::slice::from_raw_parts(base.add(row * stride), output_width)
+                    })
+                    .copied()
+                    .collect::<Vec<_>>()
+            };
+            let planes = (plane(0, output_height), plane(1, output_height / 2));
+            assert_eq!(CVPixelBufferUnlockBaseAddress(buffer, 0), 0);
+            planes
+        }
+    }
+
+    proptest! {
+        // Each case builds an IOSurface source and a Metal converter.
+        #![proptest_config(ProptestConfig::with_cases(48))]
+
+        #[test]
+        fn matches_the_reference_conversion(case in conversion_case()) {
+            let (luma, chroma) = convert_on_gpu(&case);
+            let (expected_luma, expected_chroma) = reference_nv12(&case);
+            for (plane, actual, expected) in
+                [("luma", &luma, &expected_luma), ("chroma", &chroma, &expected_chroma)]
+            {
+                prop_assert_eq!(actual.len(), expected.len());
+                for (index, (actual, expected)) in actual.iter().zip(expected).enumerate() {
+                    prop_assert!(
+                        actual.abs_diff(*expected) <= TOLERANCE,
+                        "{} sample {} is {}, reference {}",
+                        plane,
+                        index,
+                        actual,
+                        expected
+                    );
+                }
+            }
+        }
+    }
 }
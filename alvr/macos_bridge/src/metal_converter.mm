@@ -11,15 +11,59 @@ struct ConversionParams {
     uint32_t output_eye_width;
     uint32_t source_height;
     uint32_t output_height;
+    float code_scale;
+    float code_to_unorm;
+    float sharpen_strength;
+    uint32_t sharpen_adaptive;
+    float matrix_kr;
+    float matrix_kb;
+    float luma_offset;
+    float luma_range;
+    float chroma_range;
+    uint32_t foveated;
+    float eye_ratio_x;
+    float eye_ratio_y;
+    float center_size_x;
+    float center_size_y;
+    float center_shift_x;
+    float center_shift_y;
+    float edge_ratio_x;
+    float edge_ratio_y;
+    uint32_t alpha_keyed;
+    float alpha_key_r;
+    float alpha_key_g;
+    float alpha_key_b;
+};
+
+// ALVR foveated encoding parameters, as (x, y) pairs.
+struct AlvrFoveation {
+    float eye_ratio[2];
+    float center_size[2];
+    float center_shift[2];
+    float edge_ratio[2];
 };
 
 struct MetalConverter {
     id<MTLDevice> device;
     id<MTLCommandQueue> queue;
     id<MTLComputePipelineState> pipeline;
+    id<MTLComputePipelineState> scale_pipeline;
     CVMetalTextureCacheRef texture_cache;
 };
 
+static bool source_texture_format(IOSurfaceRef surface, MTLPixelFormat *format) {
+    switch (IOSurfaceGetPixelFormat(surface)) {
+    case kCVPixelFormatType_32BGRA:
+        *format = MTLPixelFormatBGRA8Unorm;
+        return true;
+    case kCVPixelFormatType_ARGB2101010LEPacked:
+        *format = MTLPixelFormatBGR10A2Unorm;
+        return true;
+    default:
+        return false;
+    }
+}
+
 static void set_error(char *buffer, size_t capacity, const char *message) {
     if (buffer != nullptr && capacity != 0) {
         std::snprintf(buffer, capacity, "%s", message);
@@ -56,13 +100,19 @@ extern "C" void *alvr_metal_converter_create(
             return nullptr;
         }
         id<MTLFunction> function = [library newFunctionWithName:@"bgra_to_nv12"];
-        if (function == nil) {
-            set_error(error_buffer, error_capacity, "bgra_to_nv12 function is missing");
+        id<MTLFunction> scale_function = [library newFunctionWithName:@"nv12_scale"];
+        if (function == nil || scale_function == nil) {
+            set_error(
+                error_buffer, error_capacity, "bgra_to_nv12 or nv12_scale function is missing");
             return nullptr;
         }
         id<MTLComputePipelineState> pipeline =
             [device newComputePipelineStateWithFunction:function error:&error];
-        if (pipeline == nil) {
+        id<MTLComputePipelineState> scale_pipeline =
+            pipeline == nil
+                ? nil
+                : [device newComputePipelineStateWithFunction:scale_function error:&error];
+        if (pipeline == nil || scale_pipeline == nil) {
             set_error(
                 error_buffer,
                 error_capacity,
@@ -86,6 +136,7 @@ extern "C" void *alvr_metal_converter_create(
             device,
             queue,
             pipeline,
+            scale_pipeline,
             texture_cache,
         };
         return converter;
@@ -109,6 +160,12 @@ extern "C" int alvr_metal_converter_convert(
     CVPixelBufferRef destination_buffer,
     uint32_t source_width,
     uint32_t source_height,
+    float sharpen_strength,
+    uint32_t sharpen_adaptive,
+    float matrix_kr,
+    float matrix_kb,
+    const AlvrFoveation *foveation,
+    const float *alpha_key,
     uint64_t *gpu_duration_ns,
     char *error_buffer,
     size_t error_capacity) {
@@ -119,7 +176,7 @@ extern "C" int alvr_metal_converter_convert(
         auto *converter = static_cast<MetalConverter *>(opaque_converter);
         if (converter == nullptr || source_surface == nullptr ||
             destination_buffer == nullptr || source_width == 0 || source_height == 0 ||
-            source_width % 4 != 0 || source_height % 2 != 0) {
+            source_width % 2 != 0 || !(sharpen_strength >= 0.0f && sharpen_strength <= 1.0f)) {
             set_error(error_buffer, error_capacity, "invalid Metal conversion arguments");
             return 1;
         }
@@ -134,19 +191,55 @@ extern "C" int alvr_metal_converter_convert(
             set_error(error_buffer, error_capacity, "destination CVPixelBuffer shape mismatch");
             return 2;
         }
+        OSType destination_format = CVPixelBufferGetPixelFormatType(destination_buffer);
+        bool ten_bit = destination_format == kCVPixelFormatType_420YpCbCr10BiPlanarVideoRange ||
+                       destination_format == kCVPixelFormatType_420YpCbCr10BiPlanarFullRange;
+        bool full_range = destination_format == kCVPixelFormatType_420YpCbCr8BiPlanarFullRange ||
+                          destination_format == kCVPixelFormatType_420YpCbCr10BiPlanarFullRange;
+        if (!ten_bit && !full_range &&
+            destination_format != kCVPixelFormatType_420YpCbCr8BiPlanarVideoRange) {
+            set_error(error_buffer, error_capacity, "destination CVPixelBuffer is not NV12 or P010");
+            return 2;
+        }
+        // NV12 sources are already video-range YCbCr, so they only need
+        // scaling; packed RGB sources are converted on the way.
+        bool nv12_source = IOSurfaceGetPixelFormat(source_surface) ==
+                           kCVPixelFormatType_420YpCbCr8BiPlanarVideoRange;
+        MTLPixelFormat source_format = MTLPixelFormatInvalid;
+        if (nv12_source) {
+            if (ten_bit || full_range || source_height % 2 != 0) {
+                set_error(
+                    error_buffer,
+                    error_capacity,
+                    "NV12 sources only scale into 8-bit video-range NV12");
+                return 3;
+            }
+        } else if (!source_texture_format(source_surface, &source_format)) {
+            set_error(
+                error_buffer, error_capacity, "source IOSurface is not BGRA8, RGB10A2, or NV12");
+            return 3;
+        }
 
-        MTLTextureDescriptor *source_descriptor =
-            [MTLTextureDescriptor texture2DDescriptorWithPixelFormat:MTLPixelFormatBGRA8Unorm
-                                                               width:source_width
-                                                              height:source_height
-                                                           mipmapped:NO];
-        source_descriptor.storageMode = MTLStorageModeShared;
-        source_descriptor.usage = MTLTextureUsageShaderRead;
-        id<MTLTexture> source_texture =
-            [converter->device newTextureWithDescriptor:source_descriptor
-                                               iosurface:source_surface
-                                                   plane:0];
-        if (source_texture == nil) {
+        auto source_plane_texture = [&](MTLPixelFormat format, uint32_t width, uint32_t height,
+                                        NSUInteger plane) {
+            MTLTextureDescriptor *descriptor =
+                [MTLTextureDescriptor texture2DDescriptorWithPixelFormat:format
+                                                                   width:width
+                                                                  height:height
+                                                               mipmapped:NO];
+            descriptor.storageMode = MTLStorageModeShared;
+            descriptor.usage = MTLTextureUsageShaderRead;
+            return [converter->device newTextureWithDescriptor:descriptor
+                                                     iosurface:source_surface
+                                                         plane:plane];
+        };
+        id<MTLTexture> source_texture = source_plane_texture(
+            nv12_source ? MTLPixelFormatR8Unorm : source_format, source_width, source_height, 0);
+        id<MTLTexture> source_uv_texture =
+            nv12_source ? source_plane_texture(
+                              MTLPixelFormatRG8Unorm, source_width / 2, source_height / 2, 1)
+                        : nil;
+        if (source_texture == nil || (nv12_source && source_uv_texture == nil)) {
             set_error(error_buffer, error_capacity, "source IOSurface texture creation failed");
             return 3;
         }
@@ -157,7 +250,7 @@ extern "C" int alvr_metal_converter_convert(
             converter->texture_cache,
             destination_buffer,
             nullptr,
-            MTLPixelFormatR8Unorm,
+            ten_bit ? MTLPixelFormatR16Unorm : MTLPixelFormatR8Unorm,
             output_width,
             output_height,
             0,
@@ -168,7 +261,7 @@ extern "C" int alvr_metal_converter_convert(
             converter->texture_cache,
             destination_buffer,
             nullptr,
-            MTLPixelFormatRG8Unorm,
+            ten_bit ? MTLPixelFormatRG16Unorm : MTLPixelFormatRG8Unorm,
             output_width / 2,
             output_height / 2,
             1,
@@ -197,16 +290,46 @@ extern "C" int alvr_metal_converter_convert(
             output_width / 2,
             source_height,
             output_height,
+            ten_bit ? 4.0f : 1.0f,
+            ten_bit ? 64.0f / 65535.0f : 1.0f / 255.0f,
+            sharpen_strength,
+            sharpen_adaptive,
+            matrix_kr,
+            matrix_kb,
+            full_range ? 0.0f : 16.0f,
+            full_range ? 255.0f : 219.0f,
+            full_range ? 255.0f : 224.0f,
+            foveation != nullptr ? 1u : 0u,
         };
-        [encoder setComputePipelineState:converter->pipeline];
+        if (foveation != nullptr) {
+            params.eye_ratio_x = foveation->eye_ratio[0];
+            params.eye_ratio_y = foveation->eye_ratio[1];
+            params.center_size_x = foveation->center_size[0];
+            params.center_size_y = foveation->center_size[1];
+            params.center_shift_x = foveation->center_shift[0];
+            params.center_shift_y = foveation->center_shift[1];
+            params.edge_ratio_x = foveation->edge_ratio[0];
+            params.edge_ratio_y = foveation->edge_ratio[1];
+        }
+        if (alpha_key != nullptr) {
+            params.alpha_keyed = 1;
+            params.alpha_key_r = alpha_key[0];
+            params.alpha_key_g = alpha_key[1];
+            params.alpha_key_b = alpha_key[2];
+        }
+        id<MTLComputePipelineState> pipeline =
+            nv12_source ? converter->scale_pipeline : converter->pipeline;
+        [encoder setComputePipelineState:pipeline];
         [encoder setTexture:source_texture atIndex:0];
         [encoder setTexture:y_texture atIndex:1];
         [encoder setTexture:uv_texture atIndex:2];
+        if (nv12_source) {
+            [encoder setTexture:source_uv_texture atIndex:3];
+        }
         [encoder setBytes:&params length:sizeof(params) atIndex:0];
         MTLSize grid = MTLSizeMake(output_width / 2, output_height / 2, 1);
-        NSUInteger thread_width = converter->pipeline.threadExecutionWidth;
-        NSUInteger thread_height =
-            converter->pipeline.maxTotalThreadsPerThreadgroup / thread_width;
+        NSUInteger thread_width = pipeline.threadExecutionWidth;
+        NSUInteger thread_height = pipeline.maxTotalThreadsPerThreadgroup / thread_width;
         MTLSize threads = MTLSizeMake(thread_width, thread_height, 1);
         [encoder dispatchThreads:grid threadsPerThreadgroup:threads];
         [encoder endEncoding];
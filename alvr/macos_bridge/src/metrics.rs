@@ -0,0 +1,296 @@
+use crate::{
+    control::{BridgeState, BridgeStatus, lock_status},
+    loopback_http::LoopbackServer,
+};
+use anyhow::Result;
+use std::{
+    fmt::Write as _,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+const SCRAPE_IO_TIMEOUT: Duration = Duration::from_secs(2);
+const STATES: [BridgeState; 6] = [
+    BridgeState::Starting,
+    BridgeState::WaitingForProducer,
+    BridgeState::WaitingForClient,
+    BridgeState::Standby,
+    BridgeState::Streaming,
+    BridgeState::Closing,
+];
+
+/// Serves the control socket's status as Prometheus text on
+/// `127.0.0.1:<port>/metrics`. It only binds loopback; anything remote has to
+/// go through a scraper running on the same Mac.
+pub(crate) struct MetricsServer {
+    server: LoopbackServer,
+}
+
+impl MetricsServer {
+    pub fn bind(port: u16, status: Arc<Mutex<BridgeStatus>>, started: Instant) -> Result<Self> {
+        let server = LoopbackServer::bind("metrics", port, SCRAPE_IO_TIMEOUT, "", move |path| {
+            (path == "/metrics").then(|| {
+                let text = render_metrics(&lock_status(&status), started.elapsed());
+                (
+                    "text/plain; version=0.0.4; charset=utf-8",
+                    Arc::from(text.into_bytes()),
+                )
+            })
+        })?;
+        eprintln!(
+            "metrics listening address=http://{}/metrics",
+            server.address()
+        );
+        Ok(Self { server })
+    }
+}
+
+fn render_metrics(status: &BridgeStatus, uptime: Duration) -> String {
+    let metrics = &status.metrics;
+    let mut output = String::new();
+    let mut family = |name: &str, kind: &str, help: &str, samples: &[(&str, f64)]| {
+        let _ = writeln!(output, "# HELP alvr_bridge_{name} {help}");
+        let _ = writeln!(output, "# TYPE alvr_bridge_{name} {kind}");
+        for (labels, value) in samples {
+            let _ = writeln!(output, "alvr_bridge_{name}{labels} {value}");
+        }
+    };
+    family(
+        "uptime_seconds",
+        "gauge",
+        "Time since the bridge started.",
+        &[("", uptime.as_secs_f64())],
+    );
+    let states = STATES.map(|state| {
+        (
+            format!("{{state=\"{}\"}}", state.as_str()),
+            f64::from(u8::from(status.state == state)),
+        )
+    });
+    let states = states
+        .iter()
+        .map(|(labels, value)| (labels.as_str(), *value))
+        .collect::<Vec<_>>();
+    family("state", "gauge", "Current bridge state.", &states);
+    family(
+        "client_connected",
+        "gauge",
+        "Whether an ALVR client has negotiated a stream.",
+        &[("", f64::from(u8::from(status.client.is_some())))],
+    );
+    family(
+        "frames_total",
+        "counter",
+        "Frames at each pipeline stage.",
+        &[
+            ("{stage=\"received\"}", metrics.received as f64),
+            ("{stage=\"submitted\"}", metrics.submitted as f64),
+            ("{stage=\"encoded\"}", metrics.encoded as f64),
+            ("{stage=\"transported\"}", metrics.transported as f64),
+        ],
+    );
+    family(
+        "dropped_frames_total",
+        "counter",
+        "Frames the bridge dropped, and producer frame ids that never arrived.",
+        &[
+            ("{side=\"bridge\"}", metrics.dropped as f64),
+            ("{side=\"producer\"}", metrics.producer_gaps as f64),
+        ],
+    );
+    family(
+        "backpressure_drops_total",
+        "counter",
+        "Frames the bridge skipped because earlier ones were still waiting in the encoder.",
+        &[("", metrics.backpressure_drops as f64)],
+    );
+    family(
+        "rate_limit_drops_total",
+        "counter",
+        "Frames the bridge skipped to hold the stream under its peak bitrate.",
+        &[("", metrics.rate_limit_drops as f64)],
+    );
+    family(
+        "checksum_mismatches_total",
+        "counter",
+        "Frames whose pixels did not match the producer's content checksum.",
+        &[("", metrics.checksum_mismatches as f64)],
+    );
+    family(
+        "bytes_total",
+        "counter",
+        "Encoded bytes, and bytes server core accepted for transport.",
+        &[
+            ("{stage=\"encoded\"}", metrics.encoded_bytes as f64),
+            ("{stage=\"transported\"}", metrics.transported_bytes as f64),
+        ],
+    );
+    family(
+        "keyframes_total",
+        "counter",
+        "Keyframes emitted by the encoder.",
+        &[("", metrics.keyframes as f64)],
+    );
+    family(
+        "frame_rate",
+        "gauge",
+        "Encoded frames per second over the last telemetry interval.",
+        &[("", metrics.frame_rate)],
+    );
+    family(
+        "target_fps",
+        "gauge",
+        "Configured frame rate.",
+        &[("", status.fps)],
+    );
+    family(
+        "bitrate_bps",
+        "gauge",
+        "Current encoder target bitrate.",
+        &[("", metrics.bitrate_bps as f64)],
+    );
+    family(
+        "encode_latency_seconds",
+        "gauge",
+        "VideoToolbox submit-to-output latency over the last telemetry interval.",
+        &[
+            (
+                "{quantile=\"0.5\"}",
+                metrics.encode_latency.p50.as_secs_f64(),
+            ),
+            (
+                "{quantile=\"0.99\"}",
+                metrics.encode_latency.p99.as_secs_f64(),
+            ),
+            ("{quantile=\"1\"}", metrics.encode_latency.max.as_secs_f64()),
+        ],
+    );
+    family(
+        "frame_bytes",
+        "gauge",
+        "Encoded frame size, parameter sets included, over the last telemetry interval.",
+        &[
+            ("{quantile=\"0.5\"}", metrics.frames.frame_bytes_p50 as f64),
+            ("{quantile=\"0.99\"}", metrics.frames.frame_bytes_p99 as f64),
+            ("{quantile=\"1\"}", metrics.frames.frame_bytes_max as f64),
+        ],
+    );
+    family(
+        "keyframe_interval_frames",
+        "gauge",
+        "Encoded frames between the two latest keyframes.",
+        &[("", metrics.frames.keyframe_interval_frames as f64)],
+    );
+    family(
+        "keyframe_interval_seconds",
+        "gauge",
+        "Video time between the two latest keyframes.",
+        &[("", metrics.frames.keyframe_interval.as_secs_f64())],
+    );
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::StatusMetrics;
+    use crate::frame_stats::FrameStats;
+    use crate::latency::LatencySummary;
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+    };
+
+    fn status() -> BridgeStatus {
+        BridgeStatus {
+            state: BridgeState::Streaming,
+            input: "iosurface",
+            fps: 90.0,
+            metrics: StatusMetrics {
+                encoded: 900,
+                dropped: 3,
+                backpressure_drops: 4,
+                rate_limit_drops: 6,
+                producer_gaps: 2,
+                checksum_mismatches: 1,
+                frame_rate: 89.5,
+                bitrate_bps: 40_000_000,
+                encode_latency: LatencySummary {
+                    samples: 90,
+                    p50: Duration::from_micros(4_000),
+                    p99: Duration::from_micros(9_500),
+                    max: Duration::from_millis(12),
+                },
+                frames: FrameStats {
+                    frame_bytes_p50: 55_000,
+                    keyframe_interval_frames: 180,
+                    keyframe_interval: Duration::from_secs(2),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn renders_prometheus_text() {
+        let text = render_metrics(&status(), Duration::from_secs(5));
+
+        for line in [
+            "# TYPE alvr_bridge_frames_total counter",
+            "alvr_bridge_frames_total{stage=\"encoded\"} 900",
+            "alvr_bridge_state{state=\"streaming\"} 1",
+            "alvr_bridge_state{state=\"standby\"} 0",
+            "alvr_bridge_state{state=\"closing\"} 0",
+            "alvr_bridge_client_connected 0",
+            "alvr_bridge_dropped_frames_total{side=\"producer\"} 2",
+            "alvr_bridge_backpressure_drops_total 4",
+            "alvr_bridge_rate_limit_drops_total 6",
+            "alvr_bridge_checksum_mismatches_total 1",
+            "alvr_bridge_frame_rate 89.5",
+            "alvr_bridge_bitrate_bps 40000000",
+            "alvr_bridge_encode_latency_seconds{quantile=\"0.99\"} 0.0095",
+            "alvr_bridge_frame_bytes{quantile=\"0.5\"} 55000",
+            "alvr_bridge_keyframe_interval_frames 180",
+            "alvr_bridge_keyframe_interval_seconds 2",
+        ] {
+            assert!(text.lines().any(|candidate| candidate == line), "{line}");
+        }
+    }
+
+    #[test]
+    fn serves_metrics_over_http() {
+        let server =
+            MetricsServer::bind(0, Arc::new(Mutex::new(status())), Instant::now()).unwrap();
+
+        let mut stream = TcpStream::connect(server.server.address()).unwrap();
+        stream
+            .write_all(
+                format!(
+                    "GET /metrics HTTP/1.1\r\nHost: {}\r\n\r\n",
+                    server.server.address()
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("alvr_bridge_keyframes_total 0\n"));
+
+        let mut stream = TcpStream::connect(server.server.address()).unwrap();
+        stream
+            .write_all(
+                format!(
+                    "GET / HTTP/1.1\r\nHost: {}\r\n\r\n",
+                    server.server.address()
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
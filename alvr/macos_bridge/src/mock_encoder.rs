@@ -0,0 +1,155 @@
+use crate::{
+    AudioDevices, ColorSpace, EncodedFrame, EncoderBackend, FrameMetadata, HardwareEncoderSupport,
+    PosePrediction, ProbeConfig, RateControl, SecondClient, SurfaceFormat, SurfaceLease,
+    VideoEncoder, VideoToolboxOverrides, encoder::SourceEncoder, surface::SlotCopy,
+};
+use alvr_session::CodecType;
+use anyhow::{Result, bail};
+use std::{collections::VecDeque, path::PathBuf, time::Duration};
+
+/// An encoder that emits a fixed NAL unit per frame, for driving the probe
+/// loops without VideoToolbox.
+pub(crate) struct MockEncoder {
+    codec: CodecType,
+    pending: VecDeque<(SurfaceLease, FrameMetadata, bool)>,
+    idr_requested: bool,
+}
+
+impl MockEncoder {
+    pub fn new(codec: CodecType) -> Self {
+        Self {
+            codec,
+            pending: VecDeque::new(),
+            idr_requested: false,
+        }
+    }
+
+    pub fn hardware_support() -> HardwareEncoderSupport {
+        HardwareEncoderSupport {
+            codec_supported: true,
+            hardware_accelerated: false,
+            supports_frame_reordering: false,
+        }
+    }
+
+    fn complete_one(&mut self) -> Vec<EncodedFrame> {
+        self.pending
+            .pop_front()
+            .map(|(lease, metadata, is_keyframe)| EncodedFrame {
+                lease_id: lease.id(),
+                metadata,
+                nal_data: vec![0, 0, 0, 1, 0x26],
+                is_keyframe,
+                decoder_config_nals: is_keyframe.then(|| vec![0, 0, 0, 1, 0x40]),
+                encode_latency: Duration::ZERO,
+            })
+            .into_iter()
+            .collect()
+    }
+}
+
+impl VideoEncoder for MockEncoder {
+    fn codec(&self) -> CodecType {
+        self.codec
+    }
+
+    fn hardware_support(&self) -> HardwareEncoderSupport {
+        Self::hardware_support()
+    }
+
+    // Holds each frame until the next submit so the loop has to recycle
+    // leases through a pending encode.
+    fn submit(
+        &mut self,
+        lease: SurfaceLease,
+        metadata: FrameMetadata,
+        force_keyframe: bool,
+    ) -> Result<Vec<EncodedFrame>> {
+        let outputs = self.drain_ready()?;
+        let is_keyframe = force_keyframe || std::mem::take(&mut self.idr_requested);
+        self.pending.push_back((lease, metadata, is_keyframe));
+        Ok(outputs)
+    }
+
+    fn drain_ready(&mut self) -> Result<Vec<EncodedFrame>> {
+        let mut outputs = Vec::new();
+        while self.pending.len() > 1 {
+            outputs.extend(self.complete_one());
+        }
+        Ok(outputs)
+    }
+
+    fn wait_for_output(&mut self, _timeout: Duration) -> Result<Vec<EncodedFrame>> {
+        Ok(self.complete_one())
+    }
+
+    fn finish(&mut self) -> Result<Vec<EncodedFrame>> {
+        let mut outputs = Vec::new();
+        while !self.pending.is_empty() {
+            outputs.extend(self.complete_one());
+        }
+        Ok(outputs)
+    }
+
+    fn set_bitrate(&mut self, _bitrate_bps: u64) -> Result<Vec<EncodedFrame>> {
+        self.finish()
+    }
+
+    fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn request_idr(&mut self) {
+        self.idr_requested = true;
+    }
+}
+
+impl SourceEncoder for MockEncoder {
+    fn submit_source(
+        &mut self,
+        _source: SlotCopy,
+        _metadata: FrameMetadata,
+        _force_keyframe: bool,
+    ) -> Result<Vec<EncodedFrame>> {
+        bail!("the mock encoder has no zero-copy path")
+    }
+}
+
+/// A small HEVC probe that runs ten frames as fast as the loop allows.
+pub(crate) fn mock_config() -> ProbeConfig {
+    ProbeConfig {
+        codec: CodecType::Hevc,
+        encoder_backend: EncoderBackend::Auto,
+        format: SurfaceFormat::Nv12,
+        color: ColorSpace::default(),
+        foveated_encoding: false,
+        width: 64,
+        height: 32,
+        fps: 1000,
+        bitrate_bps: 1_000_000,
+        rate_control: RateControl::AverageBitrate,
+        keyframe_interval: 4,
+        keyframe_interval_duration: None,
+        pipeline_depth: 1,
+        vt_overrides: VideoToolboxOverrides::default(),
+        frame_count: 10,
+        buffer_count: 2,
+        telemetry_interval: 5,
+        connect_to_alvr: false,
+        session_settings: false,
+        alvr_root: PathBuf::new(),
+        control_socket: None,
+        metrics_port: None,
+        filters: Vec::new(),
+        test_pattern: false,
+        audio: AudioDevices::default(),
+        record_path: None,
+        restream_port: None,
+        transport_dump: None,
+        capture_path: None,
+        replay_path: None,
+        timing_sei: false,
+        pose_prediction: PosePrediction::Off,
+        second_client: SecondClient::Reject,
+    }
+}
@@ -1,21 +1,56 @@
 use crate::{
-    AlvrVideoSink, FrameMetadata, HardwareEncoderSupport, NativeHevcEncoder,
-    NativeHevcEncoderConfig, PoolStats, SurfacePool,
-    metal::MetalConverter,
+    AlvrVideoSink, ColorSpace, EncoderBackend, FrameMetadata, HardwareEncoderSupport,
+    NativeVideoEncoderConfig, PoolStats, RateControl, SurfacePool,
+    alvr_sink::SessionWatcher,
+    backpressure::EncodeQueue,
+    capture::FrameCaptureWriter,
+    control::{BridgeState, ControlServer, StatusMetrics},
+    crash_report,
+    data_rate::DataRateLimiter,
+    degradation::{DegradationLadder, LoadCounters, retarget_bitrate},
+    encode_stage::{EncodeInput, EncodeJob, EncodeStage, Sent, SharedSink},
+    encoder::{BridgeEncoder, SourceEncoder, codec_name, frame_rate_ratio, periodic_keyframe},
+    frame_stats::{FrameStats, FrameStatsWindow},
+    latency::{LatencyBreakdown, LatencySummary, LatencyTracker},
+    metal::{ConversionTiming, MetalConverter, SharpenMode},
     native_source::{
-        NativeSource, SOURCE_SLOT_COUNT, STATUS_COPY_FAILED, STATUS_FRAME_DROPPED, STATUS_PASS,
-        STATUS_SESSION_CLOSED,
+        AuthenticatedProducer, BRIDGE_BUILD_VERSION, DEFAULT_SOURCE_SLOTS, NativeSource,
+        SOURCE_SLOT_RANGE, STATUS_COPY_FAILED, STATUS_FRAME_DROPPED, STATUS_PASS,
+        STATUS_SESSION_CLOSED, SourceFormat,
     },
-    probe::{ProbeConfig, default_stereo_view_params, dispatch_outputs},
+    pacing::{FramePacer, Pace},
+    power_state::PowerMonitor,
+    preflight::{PreflightInput, PreflightSource, check_renegotiated_size, run_preflight},
+    preview::{PreviewFeed, preview_enabled},
+    probe::{
+        DEFAULT_HEIGHT, DEFAULT_WIDTH, DispatchCounts, ProbeConfig, default_stereo_view_params,
+        stream_state,
+    },
+    recording::StreamRecorder,
+    restream::Restreamer,
+    signals::shutdown_signal,
+    system_events::SystemEvents,
+    tracking_feedback::BridgeStats,
+    watchdog::{EncoderWatchdog, MAX_CONSECUTIVE_RECOVERIES},
 };
-use anyhow::{Context, Result, ensure};
+use alvr_session::H264Profile;
+use anyhow::{Context, Result, bail, ensure};
 use std::{
-    env, fmt,
+    env, fmt, thread,
     time::{Duration, Instant},
 };
+use tracing::info_span;
 
-const PRODUCER_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(600);
-const EXACT_POSE_STARTUP_TIMEOUT: Duration = Duration::from_secs(90);
+/// How often a held frame checks for a newer one behind it.
+const PACING_POLL: Duration = Duration::from_micros(500);
+/// The longest Mach receive timeout, which an unbounded handshake waits out.
+const MAX_PRODUCER_TIMEOUT: Duration = Duration::from_millis(u32::MAX as u64);
+/// The longest a run of repeated frames goes without one being encoded, so
+/// the client's reprojection never drifts far from a current pose.
+const DUPLICATE_REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+/// Past a second the window stops catching the bursts that overflow Wi-Fi
+/// queues and starts averaging them away.
+const MAX_PEAK_WINDOW: Duration = Duration::from_secs(1);
 
 #[derive(Debug, Clone)]
 pub struct NativeSourceConfig {
@@ -24,6 +59,67 @@ pub struct NativeSourceConfig {
     pub session_nonce: u64,
     pub source_width: u32,
     pub source_height: u32,
+    pub source_format: SourceFormat,
+    pub source_slots: u32,
+    pub version_policy: VersionPolicy,
+    /// How long to wait for the producer handshake, including after a
+    /// producer restart. `None` waits as long as Mach allows, about 49 days.
+    pub producer_timeout: Option<Duration>,
+    /// How long a live producer may go without sending a frame. `None`
+    /// never gives up on it.
+    pub frame_timeout: Option<Duration>,
+    /// How long connect mode may go without a client, at startup or after a
+    /// disconnect, before the run fails. `None` waits forever.
+    pub client_timeout: Option<Duration>,
+    pub pose_timeout: Duration,
+    pub degrade_under_load: bool,
+    pub adaptive_bitrate: bool,
+    pub zero_copy: bool,
+    pub reconnect: bool,
+    pub latest_frame_wins: bool,
+    /// Skip a frame when the oldest one still in the encoder has waited
+    /// more than this many frame intervals. `None` encodes every frame.
+    pub max_encode_queue_frames: Option<u32>,
+    /// Skip frames while the encoded stream over `peak_window` exceeds this
+    /// percentage of the target bitrate. `None` leaves the peak to
+    /// VideoToolbox.
+    pub peak_bitrate_percent: Option<u32>,
+    pub peak_window: Duration,
+    pub pacing: bool,
+    /// How long VideoToolbox may hold submitted frames without any output
+    /// before its session is replaced. `None` disables the watchdog.
+    pub encoder_stall: Option<Duration>,
+    /// Replace the encoder session after the Mac wakes or its displays are
+    /// reconfigured.
+    pub system_events: bool,
+    pub verify_checksums: bool,
+    /// Skip encoding a frame whose pixels hash the same as the last one
+    /// submitted.
+    pub skip_duplicates: bool,
+    pub standby: bool,
+    /// Show the producer's frames in the window `PreviewWindow` opens.
+    pub preview: bool,
+    pub sharpen: f32,
+    pub sharpen_mode: SharpenMode,
+    /// The (r, g, b) colour transparent source pixels are composited over,
+    /// for a client's chroma-key passthrough. `None` ignores alpha.
+    pub alpha_key: Option<[u8; 3]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionPolicy {
+    Warn,
+    Refuse,
+}
+
+impl VersionPolicy {
+    fn from_env() -> Result<Self> {
+        match env::var("ALVR_BRIDGE_VERSION_POLICY").as_deref() {
+            Err(env::VarError::NotPresent) | Ok("warn") => Ok(Self::Warn),
+            Ok("refuse") => Ok(Self::Refuse),
+            _ => bail!("ALVR_BRIDGE_VERSION_POLICY must be warn or refuse"),
+        }
+    }
 }
 
 impl NativeSourceConfig {
@@ -32,9 +128,48 @@ impl NativeSourceConfig {
         let config = Self {
             source_height: env_u32("ALVR_IOSURFACE_SOURCE_HEIGHT", probe.height)?,
             source_width: env_u32("ALVR_IOSURFACE_SOURCE_WIDTH", probe.width)?,
+            source_format: SourceFormat::from_env()?,
+            source_slots: env_u32("ALVR_IOSURFACE_SLOTS", DEFAULT_SOURCE_SLOTS)?,
             service_name: env::var("ALVR_IOSURFACE_POOL_SERVICE")
                 .context("ALVR_IOSURFACE_POOL_SERVICE is required for iosurface input")?,
             session_nonce: required_env_u64("ALVR_IOSURFACE_POOL_NONCE")?,
+            version_policy: VersionPolicy::from_env()?,
+            producer_timeout: env_timeout("ALVR_BRIDGE_PRODUCER_TIMEOUT_SECS", 600)?,
+            frame_timeout: env_timeout("ALVR_BRIDGE_FRAME_TIMEOUT_SECS", 60)?,
+            client_timeout: env_timeout("ALVR_BRIDGE_CLIENT_TIMEOUT_SECS", 0)?,
+            pose_timeout: env_secs("ALVR_BRIDGE_POSE_TIMEOUT_SECS", 90)?,
+            degrade_under_load: env::var("ALVR_BRIDGE_DEGRADATION").as_deref() != Ok("0"),
+            // Quality mode has no bitrate to follow.
+            adaptive_bitrate: probe.rate_control == RateControl::AverageBitrate
+                && env::var("ALVR_BRIDGE_ADAPTIVE_BITRATE").as_deref() != Ok("0"),
+            zero_copy: env::var("ALVR_BRIDGE_ZERO_COPY").as_deref() == Ok("1"),
+            reconnect: env::var("ALVR_BRIDGE_RECONNECT").as_deref() != Ok("0"),
+            latest_frame_wins: env::var("ALVR_BRIDGE_LATEST_FRAME").as_deref() != Ok("0"),
+            max_encode_queue_frames: Some(env_u32("ALVR_BRIDGE_MAX_ENCODE_QUEUE_FRAMES", 3)?)
+                .filter(|frames| *frames > 0),
+            peak_bitrate_percent: Some(env_u32("ALVR_BRIDGE_PEAK_BITRATE_PERCENT", 0)?)
+                .filter(|percent| *percent > 0),
+            peak_window: Duration::from_millis(u64::from(env_u32(
+                "ALVR_BRIDGE_PEAK_WINDOW_MS",
+                100,
+            )?)),
+            pacing: env::var("ALVR_BRIDGE_PACING").as_deref() != Ok("0"),
+            encoder_stall: (env::var("ALVR_BRIDGE_ENCODER_WATCHDOG").as_deref() != Ok("0"))
+                .then(|| env_secs("ALVR_BRIDGE_ENCODER_STALL_SECS", 3))
+                .transpose()?,
+            system_events: env::var("ALVR_BRIDGE_SYSTEM_EVENTS").as_deref() != Ok("0"),
+            verify_checksums: env::var("ALVR_BRIDGE_VERIFY_CHECKSUMS").as_deref() == Ok("1"),
+            skip_duplicates: env::var("ALVR_BRIDGE_SKIP_DUPLICATES").as_deref() == Ok("1"),
+            standby: env::var("ALVR_BRIDGE_STANDBY").as_deref() != Ok("0"),
+            preview: preview_enabled(),
+            sharpen: env::var("ALVR_BRIDGE_SHARPEN").map_or(Ok(0.0), |value| {
+                value.parse().context("invalid ALVR_BRIDGE_SHARPEN")
+            })?,
+            sharpen_mode: SharpenMode::from_env()?,
+            alpha_key: env::var("ALVR_BRIDGE_ALPHA_KEY")
+                .ok()
+                .map(|value| parse_alpha_key(&value))
+                .transpose()?,
             probe,
         };
         config.validate()?;
@@ -50,21 +185,113 @@ impl NativeSourceConfig {
             self.session_nonce != 0,
             "IOSurface session nonce must be nonzero"
         );
+        // The Metal pass samples each eye independently, so odd eye widths
+        // and heights only need the two eyes to be the same width.
+        ensure!(
+            self.source_width > 0 && self.source_width.is_multiple_of(2),
+            "IOSurface source width must be positive and even"
+        );
+        ensure!(
+            self.source_height > 0,
+            "IOSurface source height must be positive"
+        );
+        ensure!(
+            SOURCE_SLOT_RANGE.contains(&self.source_slots),
+            "IOSurface slot count must be between {} and {}",
+            SOURCE_SLOT_RANGE.start(),
+            SOURCE_SLOT_RANGE.end()
+        );
+        ensure!(
+            !self.probe.test_pattern,
+            "the test pattern replaces the producer's frames; unset ALVR_BRIDGE_TEST_PATTERN or use surface input"
+        );
         ensure!(
-            self.source_width > 0 && self.source_width.is_multiple_of(4),
-            "IOSurface source width must be positive and divisible by four"
+            self.probe.replay_path.is_none(),
+            "frame replay runs through surface input; unset ALVR_BRIDGE_INPUT=iosurface"
         );
         ensure!(
-            self.source_height > 0 && self.source_height.is_multiple_of(2),
-            "IOSurface source height must be positive and even"
+            (0.0..=1.0).contains(&self.sharpen),
+            "ALVR_BRIDGE_SHARPEN must be between 0 and 1"
         );
+        ensure!(
+            self.producer_timeout
+                .is_none_or(|timeout| timeout <= MAX_PRODUCER_TIMEOUT),
+            "ALVR_BRIDGE_PRODUCER_TIMEOUT_SECS must be at most {}; use 0 to wait forever",
+            MAX_PRODUCER_TIMEOUT.as_secs()
+        );
+        ensure!(
+            self.client_timeout.is_none() || self.probe.connect_to_alvr,
+            "ALVR_BRIDGE_CLIENT_TIMEOUT_SECS only applies with ALVR_BRIDGE_CONNECT=1"
+        );
+        if let Some(percent) = self.peak_bitrate_percent {
+            ensure!(
+                percent >= 100,
+                "ALVR_BRIDGE_PEAK_BITRATE_PERCENT must be at least 100, the target itself"
+            );
+            ensure!(
+                self.probe.rate_control == RateControl::AverageBitrate,
+                "ALVR_BRIDGE_PEAK_BITRATE_PERCENT limits peaks over a target bitrate, which quality mode does not have"
+            );
+            ensure!(
+                (1..=MAX_PEAK_WINDOW.as_millis()).contains(&self.peak_window.as_millis()),
+                "ALVR_BRIDGE_PEAK_WINDOW_MS must be between 1 and {}",
+                MAX_PEAK_WINDOW.as_millis()
+            );
+        }
+        ensure!(
+            !self.encodes_in_place() || self.probe.capture_path.is_none(),
+            "zero-copy encode has no NV12 surface to capture; unset ALVR_BRIDGE_ZERO_COPY"
+        );
+        ensure!(
+            !self.encodes_in_place() || self.probe.encoder_backend != EncoderBackend::Software,
+            "the software encoder reads NV12 surfaces, which zero-copy and NV12 sources skip; unset ALVR_BRIDGE_ZERO_COPY or send BGRA"
+        );
+        if self.encodes_in_place() {
+            ensure!(
+                !self.probe.format.is_ten_bit(),
+                "zero-copy encode only supports 8-bit BGRA and NV12 sources"
+            );
+            ensure!(
+                self.probe.filters.is_empty(),
+                "zero-copy encode bypasses the NV12 surface that frame filters run on"
+            );
+            ensure!(
+                self.sharpen == 0.0,
+                "sharpening runs in the Metal pass, which zero-copy and NV12 sources skip"
+            );
+            ensure!(
+                self.alpha_key.is_none(),
+                "alpha keying runs in the Metal pass, which zero-copy and NV12 sources skip"
+            );
+            ensure!(
+                self.probe.color == ColorSpace::default(),
+                "zero-copy and NV12 sources stay BT.709 limited range SDR; unset ALVR_BRIDGE_COLOR_MATRIX, ALVR_BRIDGE_COLOR_RANGE, and ALVR_BRIDGE_HDR"
+            );
+        }
         Ok(())
     }
+
+    /// NV12 slots skip the Metal pass whenever the stream is the source's
+    /// size; BGRA slots only when `ALVR_BRIDGE_ZERO_COPY` also asks
+    /// VideoToolbox to convert them. Any other stream size goes through the
+    /// Metal pass, which scales.
+    fn encodes_in_place(&self) -> bool {
+        self.zero_copy || self.source_format == SourceFormat::Nv12
+    }
+
+    /// The software encoder cannot take a source buffer directly, so a loop
+    /// that encodes in place stays on VideoToolbox rather than falling back.
+    fn encoder_backend(&self) -> EncoderBackend {
+        match self.probe.encoder_backend {
+            EncoderBackend::Auto if self.encodes_in_place() => EncoderBackend::VideoToolbox,
+            backend => backend,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct NativeCadenceReport {
-    pub fps: u32,
+    pub frame_interval: Duration,
     pub received: u64,
     pub submitted: u64,
     pub encoded: u64,
@@ -78,6 +305,10 @@ pub struct NativeCadenceReport {
     pub dropped: u64,
     pub not_ready_drops: u64,
     pub pool_exhausted_drops: u64,
+    pub backpressure_drops: u64,
+    pub rate_limit_drops: u64,
+    pub producer_gaps: u64,
+    pub checksum_mismatches: u64,
     pub black_consumer_samples: u64,
     pub visible_consumer_samples: u64,
     pub pose_paired: u64,
@@ -85,25 +316,35 @@ pub struct NativeCadenceReport {
     pub pose_bootstrap: u64,
     pub pose_generation_gaps: u64,
     pub pose_timestamp_reuses: u64,
+    pub rejected_messages: u64,
     pub conversion_average: Duration,
     pub conversion_max: Duration,
     pub conversion_gpu_average: Duration,
     pub conversion_gpu_max: Duration,
+    pub slot_hold_average: Duration,
+    pub slot_hold_max: Duration,
     pub pool_available: usize,
+    /// The longest any frame had waited in the encoder when a new one
+    /// arrived.
+    pub encode_queue_max: Duration,
+    /// The fullest the peak data-rate window got, as a percentage of it.
+    pub rate_window_max_percent: u64,
+    pub frames: FrameStats,
+    pub latency: LatencyBreakdown,
 }
 
 impl fmt::Display for NativeCadenceReport {
     fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             formatter,
-            "native_source cadence received={} submitted={} encoded={} alvr_sent={} encoded_bytes={} transported_bytes={} encoded_mbps={:.3} keyframes={} keyframe_bytes={} max_frame_bytes={} video_span_ms={} dropped={} not_ready_drops={} pool_exhausted_drops={} black_consumer_samples={} visible_consumer_samples={} pose_paired={} pose_fallback={} pose_bootstrap={} pose_generation_gaps={} pose_timestamp_reuses={} conversion_avg_us={} conversion_max_us={} conversion_gpu_avg_us={} conversion_gpu_max_us={} pool_available={}",
+            "native_source cadence received={} submitted={} encoded={} alvr_sent={} encoded_bytes={} transported_bytes={} encoded_mbps={:.3} keyframes={} keyframe_bytes={} max_frame_bytes={} video_span_ms={} dropped={} not_ready_drops={} pool_exhausted_drops={} backpressure_drops={} encode_queue_max_us={} rate_limit_drops={} rate_window_max_pct={} producer_gaps={} checksum_mismatches={} black_consumer_samples={} visible_consumer_samples={} pose_paired={} pose_fallback={} pose_bootstrap={} pose_generation_gaps={} pose_timestamp_reuses={} rejected_messages={} conversion_avg_us={} conversion_max_us={} conversion_gpu_avg_us={} conversion_gpu_max_us={} slot_hold_avg_us={} slot_hold_max_us={} pool_available={} {} {}",
             self.received,
             self.submitted,
             self.encoded,
             self.transported,
             self.encoded_bytes,
             self.transported_bytes,
-            normalized_megabits_per_second(self.encoded_bytes, self.encoded, self.fps),
+            normalized_megabits_per_second(self.encoded_bytes, self.encoded, self.frame_interval),
             self.keyframes,
             self.keyframe_bytes,
             self.max_frame_bytes,
@@ -111,6 +352,12 @@ impl fmt::Display for NativeCadenceReport {
             self.dropped,
             self.not_ready_drops,
             self.pool_exhausted_drops,
+            self.backpressure_drops,
+            self.encode_queue_max.as_micros(),
+            self.rate_limit_drops,
+            self.rate_window_max_percent,
+            self.producer_gaps,
+            self.checksum_mismatches,
             self.black_consumer_samples,
             self.visible_consumer_samples,
             self.pose_paired,
@@ -118,18 +365,23 @@ impl fmt::Display for NativeCadenceReport {
             self.pose_bootstrap,
             self.pose_generation_gaps,
             self.pose_timestamp_reuses,
+            self.rejected_messages,
             self.conversion_average.as_micros(),
             self.conversion_max.as_micros(),
             self.conversion_gpu_average.as_micros(),
             self.conversion_gpu_max.as_micros(),
+            self.slot_hold_average.as_micros(),
+            self.slot_hold_max.as_micros(),
             self.pool_available,
+            self.frames,
+            self.latency,
         )
     }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct NativeProbeSummary {
-    pub fps: u32,
+    pub frame_interval: Duration,
     pub self_tests: u64,
     pub received_frames: u64,
     pub submitted_frames: u64,
@@ -144,6 +396,18 @@ pub struct NativeProbeSummary {
     pub dropped_frames: u64,
     pub not_ready_drops: u64,
     pub pool_exhausted_drops: u64,
+    pub backpressure_drops: u64,
+    pub rate_limit_drops: u64,
+    pub decimated_drops: u64,
+    pub superseded_drops: u64,
+    pub paced_drops: u64,
+    pub vsync_repeats: u64,
+    pub standby_drops: u64,
+    pub duplicate_skips: u64,
+    pub encoder_stalls: u64,
+    pub producer_gaps: u64,
+    pub checksums_verified: u64,
+    pub checksum_mismatches: u64,
     pub black_consumer_samples: u64,
     pub visible_consumer_samples: u64,
     pub pose_paired: u64,
@@ -152,11 +416,14 @@ pub struct NativeProbeSummary {
     pub pose_generation_gaps: u64,
     pub pose_timestamp_reuses: u64,
     pub last_pose_generation: u64,
+    pub rejected_messages: u64,
     pub wall_elapsed: Duration,
     pub conversion_average: Duration,
     pub conversion_max: Duration,
     pub conversion_gpu_average: Duration,
     pub conversion_gpu_max: Duration,
+    pub slot_hold_average: Duration,
+    pub slot_hold_max: Duration,
     pub pool_stats: PoolStats,
     pub hardware_support: HardwareEncoderSupport,
     pub connected_to_alvr: bool,
@@ -166,7 +433,7 @@ impl fmt::Display for NativeProbeSummary {
     fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             formatter,
-            "native_source summary self_tests={} received={} submitted={} encoded={} alvr_sent={} encoded_bytes={} transported_bytes={} encoded_mbps={:.3} keyframes={} keyframe_bytes={} max_frame_bytes={} video_span_ms={} dropped={} not_ready_drops={} pool_exhausted_drops={} black_consumer_samples={} visible_consumer_samples={} pose_paired={} pose_fallback={} pose_bootstrap={} pose_generation_gaps={} pose_timestamp_reuses={} last_pose_generation={} wall_ms={} conversion_avg_us={} conversion_max_us={} conversion_gpu_avg_us={} conversion_gpu_max_us={} pool_available={}/{} leases_acquired={} leases_recycled={} hardware_hevc={} alvr_connected={}",
+            "native_source summary self_tests={} received={} submitted={} encoded={} alvr_sent={} encoded_bytes={} transported_bytes={} encoded_mbps={:.3} keyframes={} keyframe_bytes={} max_frame_bytes={} video_span_ms={} dropped={} not_ready_drops={} pool_exhausted_drops={} backpressure_drops={} rate_limit_drops={} decimated_drops={} superseded_drops={} paced_drops={} vsync_repeats={} standby_drops={} duplicate_skips={} encoder_stalls={} producer_gaps={} checksums_verified={} checksum_mismatches={} black_consumer_samples={} visible_consumer_samples={} pose_paired={} pose_fallback={} pose_bootstrap={} pose_generation_gaps={} pose_timestamp_reuses={} last_pose_generation={} rejected_messages={} wall_ms={} conversion_avg_us={} conversion_max_us={} conversion_gpu_avg_us={} conversion_gpu_max_us={} slot_hold_avg_us={} slot_hold_max_us={} pool_available={}/{} leases_acquired={} leases_recycled={} hardware_hevc={} alvr_connected={}",
             self.self_tests,
             self.received_frames,
             self.submitted_frames,
@@ -174,7 +441,11 @@ impl fmt::Display for NativeProbeSummary {
             self.transported_frames,
             self.encoded_bytes,
             self.transported_bytes,
-            normalized_megabits_per_second(self.encoded_bytes, self.encoded_frames, self.fps),
+            normalized_megabits_per_second(
+                self.encoded_bytes,
+                self.encoded_frames,
+                self.frame_interval,
+            ),
             self.keyframes,
             self.keyframe_bytes,
             self.max_frame_bytes,
@@ -182,6 +453,18 @@ impl fmt::Display for NativeProbeSummary {
             self.dropped_frames,
             self.not_ready_drops,
             self.pool_exhausted_drops,
+            self.backpressure_drops,
+            self.rate_limit_drops,
+            self.decimated_drops,
+            self.superseded_drops,
+            self.paced_drops,
+            self.vsync_repeats,
+            self.standby_drops,
+            self.duplicate_skips,
+            self.encoder_stalls,
+            self.producer_gaps,
+            self.checksums_verified,
+            self.checksum_mismatches,
             self.black_consumer_samples,
             self.visible_consumer_samples,
             self.pose_paired,
@@ -190,11 +473,14 @@ impl fmt::Display for NativeProbeSummary {
             self.pose_generation_gaps,
             self.pose_timestamp_reuses,
             self.last_pose_generation,
+            self.rejected_messages,
             self.wall_elapsed.as_millis(),
             self.conversion_average.as_micros(),
             self.conversion_max.as_micros(),
             self.conversion_gpu_average.as_micros(),
             self.conversion_gpu_max.as_micros(),
+            self.slot_hold_average.as_micros(),
+            self.slot_hold_max.as_micros(),
             self.pool_stats.available,
             self.pool_stats.capacity,
             self.pool_stats.acquired,
@@ -207,230 +493,547 @@ impl fmt::Display for NativeProbeSummary {
 
 pub fn run_native_source_probe(
     config: NativeSourceConfig,
-    mut report: impl FnMut(NativeCadenceReport),
+    report: impl FnMut(NativeCadenceReport),
+) -> Result<NativeProbeSummary> {
+    config.validate()?;
+    let backend = config.encoder_backend();
+    run_preflight(
+        config.probe.preflight_input(Some(PreflightSource {
+            width: config.source_width,
+            height: config.source_height,
+            format: config.source_format,
+            slots: config.source_slots,
+        })),
+        backend,
+    )?;
+    run_native_source_probe_with_encoder(config, report, move |encoder_config| {
+        BridgeEncoder::open(backend, encoder_config)
+    })
+}
+
+/// Runs the IOSurface loop with encoder sessions from `open_encoder`,
+/// skipping the VideoToolbox preflight, so tests can drive it with a mock
+/// encoder and a simulated producer.
+pub(crate) fn run_native_source_probe_with_encoder<E: SourceEncoder + 'static>(
+    config: NativeSourceConfig,
+    report: impl FnMut(NativeCadenceReport),
+    open_encoder: impl FnMut(NativeVideoEncoderConfig) -> Result<(E, HardwareEncoderSupport)>
+    + Send
+    + 'static,
 ) -> Result<NativeProbeSummary> {
     config.validate()?;
+    let control = config.probe.start_control_server("iosurface")?;
+    let mut filters = config.probe.filter_chain()?;
+    let recorder = config.probe.start_recorder()?;
+    let restream = config.probe.start_restream()?;
+    let mut capture = config
+        .probe
+        .capture_path
+        .as_deref()
+        .map(|path| FrameCaptureWriter::create(path, config.probe.width, config.probe.height))
+        .transpose()?;
     let source = NativeSource::new(
         &config.service_name,
         config.session_nonce,
         config.source_width,
         config.source_height,
+        config.probe.format,
+        config.source_format,
+        config.source_slots,
     )?;
     println!(
-        "native_source launchd service checked in name={}",
-        config.service_name
+        "native_source launchd service checked in name={} format={} slots={}",
+        config.service_name,
+        config.source_format.name(),
+        source.slot_count()
     );
-    let converter = MetalConverter::new()?;
-    let pool = SurfacePool::new(
+    // Frames that skip the Metal pass are copied out of their slot, so the
+    // slot goes back to the producer before the encode, as a converted one
+    // does.
+    let slot_copies = config
+        .encodes_in_place()
+        .then(|| source.slot_copies(config.probe.buffer_count))
+        .transpose()?;
+    let source_size = (config.source_width, config.source_height);
+    // Set with each negotiated stream size; a foveated stream always takes
+    // the Metal pass, which does the compression.
+    let mut foveated = false;
+    if slot_copies.is_some() {
+        println!(
+            "native_source zero_copy enabled conversion={} active={}",
+            match config.source_format {
+                SourceFormat::Bgra => "videotoolbox",
+                SourceFormat::Nv12 => "none",
+            },
+            (config.probe.width, config.probe.height) == source_size
+        );
+    }
+    let mut converter = MetalConverter::new()?;
+    converter.set_sharpening(config.sharpen);
+    converter.set_sharpen_mode(config.sharpen_mode);
+    if config.sharpen > 0.0 {
+        println!(
+            "native_source sharpen strength={} mode={}",
+            config.sharpen,
+            config.sharpen_mode.name()
+        );
+    }
+    converter.set_alpha_key(config.alpha_key);
+    if let Some([red, green, blue]) = config.alpha_key {
+        println!("native_source alpha_key color={red:02x}{green:02x}{blue:02x}");
+    }
+    converter.set_color_matrix(config.probe.color.matrix);
+    println!(
+        "native_source color matrix={} range={} transfer={}",
+        config.probe.color.matrix.name(),
+        config.probe.color.range.name(),
+        config.probe.color.transfer_name()
+    );
+    println!(
+        "native_source rate_control mode={} adaptive_bitrate={} pipeline_depth={} vt_properties={}",
+        config.probe.rate_control.name(),
+        config.adaptive_bitrate,
+        config.probe.pipeline_depth,
+        config.probe.vt_overrides
+    );
+    let mut pool = SurfacePool::with_color_space(
         config.probe.width,
         config.probe.height,
         config.probe.buffer_count,
+        config.probe.format,
+        config.probe.color,
     )?;
-    let (mut encoder, hardware_support) = NativeHevcEncoder::new(NativeHevcEncoderConfig {
-        width: config.probe.width,
-        height: config.probe.height,
-        fps: config.probe.fps,
-        bitrate_bps: config.probe.bitrate_bps,
-    })?;
-    let fallback_view_params = default_stereo_view_params(config.probe.width, config.probe.height);
+    // Filled in once the producer is through its handshake. The encode
+    // thread holds its own handle from the start.
+    let sink = SharedSink::new(None);
+    let (mut encoder, hardware_support) = NativeEncoder::open(
+        open_encoder,
+        NativeVideoEncoderConfig {
+            codec: config.probe.codec,
+            h264_profile: H264Profile::High,
+            format: config.probe.format,
+            width: config.probe.width,
+            height: config.probe.height,
+            fps: config.probe.fps,
+            fps_denominator: 1,
+            bitrate_bps: config.probe.bitrate_bps,
+            rate_control: config.probe.rate_control,
+            keyframe_interval: config.probe.keyframe_interval,
+            keyframe_interval_duration: config.probe.keyframe_interval_duration,
+            hdr: config.probe.color.hdr,
+            pipeline_depth: config.probe.pipeline_depth,
+            overrides: config.probe.vt_overrides,
+        },
+        &sink,
+        recorder,
+        restream,
+        config.probe.timing_sei,
+    )?;
+    let mut fallback_view_params =
+        default_stereo_view_params(config.probe.width, config.probe.height);
 
-    println!(
-        "native_source awaiting producer handshake timeout_ms={}",
-        PRODUCER_HANDSHAKE_TIMEOUT.as_millis()
-    );
-    let producer = source.accept_producer(PRODUCER_HANDSHAKE_TIMEOUT)?;
-    println!(
-        "{}",
-        producer_handshake_message(
-            &config.service_name,
-            config.session_nonce,
-            std::process::id(),
-            producer.pid,
-            producer.pid_version,
-            producer.start_token,
-            config.source_width,
-            config.source_height,
-        )
-    );
-    let mut self_test_slots = [false; SOURCE_SLOT_COUNT];
-    for _ in 0..SOURCE_SLOT_COUNT {
-        let frame = source
-            .next_frame(Duration::from_secs(60))?
-            .context("IOSurface producer did not send all startup self-tests")?;
-        let validation_status = frame.validation_status();
-        if validation_status != STATUS_PASS {
-            let frame_id = frame.frame_id();
-            let slot_index = frame.slot_index();
-            let generation = frame.generation();
-            let expected = frame.expected_bgra();
-            let actual = frame.actual_bgra();
-            frame.release(validation_status)?;
-            anyhow::bail!(
-                "IOSurface self-test {frame_id} slot={slot_index} generation={generation} failed validation status={validation_status} expected={expected:?} actual={actual:?}"
-            );
-        }
-        ensure!(
-            frame.is_self_test(),
-            "received a production frame before startup self-tests completed"
-        );
-        let slot_index = usize::try_from(frame.slot_index())
-            .context("IOSurface self-test slot index does not fit usize")?;
-        ensure!(
-            slot_index < SOURCE_SLOT_COUNT,
-            "IOSurface self-test slot {slot_index} is out of range"
-        );
-        ensure!(
-            !self_test_slots[slot_index],
-            "IOSurface slot {slot_index} was self-tested more than once"
-        );
-        self_test_slots[slot_index] = true;
-        frame.release(STATUS_PASS)?;
+    if let Some(control) = &control {
+        control.update(|status| status.state = BridgeState::WaitingForProducer);
     }
-    ensure!(
-        self_test_slots.iter().all(|passed| *passed),
-        "not every IOSurface slot passed startup self-test"
-    );
-    let mut sink = config
+    let mut producer = handshake_producer(&source, &config)?;
+    *sink.lock() = config.probe.start_alvr_sink(config.session_nonce)?;
+    let mut session_watcher = config
         .probe
-        .connect_to_alvr
-        .then(|| {
-            AlvrVideoSink::start(
-                &config.probe.alvr_root,
-                config.probe.width,
-                config.probe.height,
-                config.probe.fps,
-                config.session_nonce,
-            )
-        })
+        .session_settings
+        .then(|| SessionWatcher::new(&config.probe.alvr_root, DEFAULT_WIDTH, DEFAULT_HEIGHT))
         .transpose()?;
-    let startup_barrier = source
-        .next_frame(Duration::from_secs(60))?
-        .context("IOSurface producer did not send the startup barrier")?;
-    let startup_barrier_status = startup_barrier.validation_status();
-    ensure!(
-        startup_barrier.is_startup_barrier(),
-        "received a production frame before the startup barrier"
-    );
-    startup_barrier.release(startup_barrier_status)?;
-    ensure!(
-        startup_barrier_status == STATUS_PASS,
-        "IOSurface startup barrier failed validation status={startup_barrier_status}"
-    );
-    println!("native_source producer startup barrier released");
-    if sink.is_some() {
+    release_startup_barrier(&source)?;
+    if sink.lock().is_some() {
         println!("native_source ALVR client telemetry enabled");
     }
     println!(
         "native_source startup self-tests passed slots={}",
-        SOURCE_SLOT_COUNT
+        source.slot_count()
     );
     let start = Instant::now();
     let mut last_frame_at = start;
-    let self_tests = SOURCE_SLOT_COUNT as u64;
-    let mut received = 0;
-    let mut submitted = 0;
-    let mut encoded = 0;
-    let mut transported = 0;
-    let mut encoded_bytes = 0u64;
-    let mut transported_bytes = 0u64;
-    let mut keyframes = 0u64;
-    let mut keyframe_bytes = 0u64;
-    let mut max_frame_bytes = 0u64;
-    let mut first_submitted_video_timestamp = None;
-    let mut last_submitted_video_timestamp = None;
-    let mut dropped = 0;
-    let mut not_ready_drops = 0;
-    let mut pool_exhausted_drops = 0;
-    let mut black_consumer_samples = 0;
-    let mut visible_consumer_samples = 0;
-    let mut pose_paired = 0;
-    let mut pose_fallback = 0;
-    let mut pose_bootstrap = 0;
-    let mut pose_generation_gaps = 0;
-    let mut pose_timestamp_reuses = 0;
-    let mut last_pose_generation = 0;
+    let self_tests = u64::from(source.slot_count());
+    let mut counters = NativeCounters::default();
+    let mut output = NativeOutput {
+        // A deeper pipeline holds frames on purpose, which is not a backlog.
+        encode_queue: config
+            .max_encode_queue_frames
+            .map(|frames| EncodeQueue::new(frames + config.probe.pipeline_depth - 1)),
+        rate_limiter: config.peak_bitrate_percent.map(|percent| {
+            DataRateLimiter::new(percent, config.peak_window, config.probe.bitrate_bps)
+        }),
+        watchdog: config.encoder_stall.map(EncoderWatchdog::new),
+        report,
+    };
+    if let Some(limiter) = &output.rate_limiter {
+        println!(
+            "native_source peak_bitrate peak_bps={} window_ms={}",
+            limiter.peak_bps(),
+            config.peak_window.as_millis()
+        );
+    }
+    let mut pacer = config
+        .pacing
+        .then(|| FramePacer::new(config.probe.fps as f32));
+    let mut standby_since: Option<Instant> = None;
+    let mut preview = config
+        .preview
+        .then(|| PreviewFeed::new(source.width(), source.height()));
+    let mut last_producer_frame_id = None;
+    let mut ladder = config
+        .degrade_under_load
+        .then(|| DegradationLadder::new(config.probe.fps, false));
+    let mut power = PowerMonitor::new();
+    let mut system_events = config.system_events.then(SystemEvents::new);
+    let mut power_floor = None;
+    println!("native_source power_state {}", power.state());
+    if config.probe.connect_to_alvr {
+        println!(
+            "native_source no_client={} client_timeout_ms={}",
+            if config.standby { "standby" } else { "proceed" },
+            config
+                .client_timeout
+                .map_or("none".to_string(), |timeout| timeout
+                    .as_millis()
+                    .to_string())
+        );
+    }
+    let mut client_wait_started: Option<Instant> = None;
+    let mut alvr_bitrate_bps = config.probe.bitrate_bps;
     let mut last_pose_timestamp = None;
-    let mut conversion_total = Duration::ZERO;
-    let mut conversion_max = Duration::ZERO;
-    let mut conversion_gpu_total = Duration::ZERO;
-    let mut conversion_gpu_max = Duration::ZERO;
-    let mut conversion_count = 0u64;
     let mut closing = false;
+    let mut interrupted = false;
+    // A newer frame taken off the port while deciding whether to skip the
+    // current one, handled next in its place.
+    let mut queued_frame = None;
     let mut closing_timeouts = 0;
     let mut exact_pose_wait_started: Option<Instant> = None;
-
-    macro_rules! report_cadence {
-        () => {
-            report(NativeCadenceReport {
-                fps: config.probe.fps,
-                received,
-                submitted,
-                encoded,
-                transported,
-                encoded_bytes,
-                transported_bytes,
-                keyframes,
-                keyframe_bytes,
-                max_frame_bytes,
-                video_span: submitted_video_span(
-                    first_submitted_video_timestamp,
-                    last_submitted_video_timestamp,
-                    config.probe.fps,
-                ),
-                dropped,
-                not_ready_drops,
-                pool_exhausted_drops,
-                black_consumer_samples,
-                visible_consumer_samples,
-                pose_paired,
-                pose_fallback,
-                pose_bootstrap,
-                pose_generation_gaps,
-                pose_timestamp_reuses,
-                conversion_average: conversion_average(conversion_total, conversion_count),
-                conversion_max,
-                conversion_gpu_average: conversion_average(conversion_gpu_total, conversion_count),
-                conversion_gpu_max,
-                pool_available: pool.stats().available,
-            });
-        };
-    }
+    let mut frame_interval = Duration::from_secs_f64(1.0 / f64::from(config.probe.fps));
+    let mut video_offset = Duration::ZERO;
+    let mut rebase_video_clock = false;
+    let mut reconnects = 0u64;
 
     loop {
-        let dispatch = dispatch_outputs(encoder.drain_ready()?, &mut sink)?;
-        encoded += dispatch.encoded;
-        transported += dispatch.transported;
-        encoded_bytes = encoded_bytes.saturating_add(dispatch.encoded_bytes);
-        transported_bytes = transported_bytes.saturating_add(dispatch.transported_bytes);
-        keyframes += dispatch.keyframes;
-        keyframe_bytes = keyframe_bytes.saturating_add(dispatch.keyframe_bytes);
-        max_frame_bytes = max_frame_bytes.max(dispatch.max_frame_bytes);
-        if let Some(sink) = sink.as_mut() {
+        encoder.poll(&mut output, &mut counters)?;
+        if let Some(sink) = sink.lock().as_mut() {
             sink.poll_events();
             if let Some(error) = sink.connection_error() {
                 anyhow::bail!("ALVR stream contract failed: {error}");
             }
-            if sink.shutdown_requested() {
+            if sink.shutdown_requested() && !interrupted {
+                println!(
+                    "native_source shutdown source=alvr restart={}",
+                    sink.restart_requested()
+                );
+                interrupted = true;
                 closing = true;
             }
         }
+        if !interrupted && let Some(signal) = shutdown_signal() {
+            println!("native_source shutdown signal={signal}");
+            interrupted = true;
+            closing = true;
+            if let Some(sink) = sink.lock().as_mut() {
+                sink.remove_shared_memory_on_drop();
+            }
+        }
+        if let Some(event) = system_events.as_mut().and_then(SystemEvents::poll) {
+            println!("native_source system_event={}", event.name());
+            if event.invalidates_encoder() {
+                // Frames the old session still holds are lost with it, and
+                // the producer's pacing restarts from here.
+                if let Some(pacer) = pacer.as_mut() {
+                    pacer.reset();
+                }
+                encoder.recreate(event.name(), &mut output, &mut counters, &sink)?;
+            }
+        }
+        if let Some(previous) = power.poll() {
+            let state = power.state();
+            let line = format!(
+                "native_source power_state {state} previous_thermal={} previous_low_power={}",
+                previous.thermal.name(),
+                previous.low_power
+            );
+            println!("{line}");
+            if state.quality_floor() > previous.quality_floor() {
+                if let Some(sink) = sink.lock().as_ref() {
+                    sink.warn_dashboard(&line);
+                }
+                if ladder.is_none() {
+                    eprintln!(
+                        "WARNING native_source power_state {state} but ALVR_BRIDGE_DEGRADATION=0 holds quality"
+                    );
+                }
+            }
+        }
+        // Thermal pressure holds the degradation ladder down until it passes,
+        // so the encoder sheds work before the throttled media engine drops
+        // frames.
+        let floor = power.state().quality_floor();
+        if power_floor.replace(floor) != Some(floor)
+            && let Some(ladder) = ladder.as_mut()
+            && let Some(transition) = ladder.set_floor(floor, "power_state")
+        {
+            println!("native_source {transition}");
+            if let Some(sink) = sink.lock().as_ref() {
+                sink.warn_dashboard(&format!("native_source {transition}"));
+            }
+            if transition.to.bitrate_percent != transition.from.bitrate_percent {
+                encoder.config.bitrate_bps = transition.to.bitrate_bps(alvr_bitrate_bps);
+                encoder.restart(&mut output, &mut counters)?;
+            }
+        }
+        // A connection can change the codec, the size, or both; the encoder
+        // is rebuilt once for whatever changed.
+        let codec_change = sink
+            .lock()
+            .as_mut()
+            .and_then(AlvrVideoSink::take_codec_change);
+        if let Some((codec, h264_profile)) = codec_change {
+            println!(
+                "native_source codec_change from={} to={} h264_profile={h264_profile:?}",
+                codec_name(encoder.config.codec),
+                codec_name(codec)
+            );
+            encoder.config.codec = codec;
+            encoder.config.h264_profile = h264_profile;
+        }
+        let refresh_change = sink
+            .lock()
+            .as_mut()
+            .and_then(AlvrVideoSink::take_refresh_change);
+        if let Some(refresh_hz) = refresh_change {
+            let (fps, fps_denominator) = frame_rate_ratio(refresh_hz);
+            println!(
+                "native_source refresh_change from={}/{} to={fps}/{fps_denominator} refresh_hz={refresh_hz:.3}",
+                encoder.config.fps, encoder.config.fps_denominator
+            );
+            encoder.config.fps = fps;
+            encoder.config.fps_denominator = fps_denominator;
+            frame_interval = Duration::from_secs_f64(1.0 / f64::from(refresh_hz));
+            if let Some(control) = &control {
+                control.update(|status| {
+                    status.fps = f64::from(fps) / f64::from(fps_denominator);
+                });
+            }
+            if let Some(ladder) = ladder.as_mut() {
+                ladder.set_frame_budget(frame_interval);
+            }
+        }
+        // Taken outside the `if let`, whose body restarts the encoder while
+        // the encode thread may be waiting on the sink.
+        let stream_resize = sink
+            .lock()
+            .as_mut()
+            .and_then(AlvrVideoSink::take_stream_resize);
+        if let Some((width, height)) = stream_resize {
+            check_renegotiated_size(PreflightInput {
+                codec: encoder.config.codec,
+                width,
+                height,
+                ..config.probe.preflight_input(None)
+            })?;
+            println!(
+                "native_source stream_resize from={}x{} to={width}x{height}",
+                encoder.config.width, encoder.config.height
+            );
+            let foveation = sink.lock().as_ref().and_then(AlvrVideoSink::foveation);
+            converter.set_foveation(foveation);
+            foveated = foveation.is_some();
+            if let Some(foveation) = foveation {
+                println!(
+                    "native_source foveation eye_ratio={:.4}x{:.4} center={:.4}x{:.4} shift={:.4}x{:.4} edge_ratio={}x{}",
+                    foveation.eye_ratio.0,
+                    foveation.eye_ratio.1,
+                    foveation.center_size.0,
+                    foveation.center_size.1,
+                    foveation.center_shift.0,
+                    foveation.center_shift.1,
+                    foveation.edge_ratio.0,
+                    foveation.edge_ratio.1
+                );
+            }
+            if slot_copies.is_some() {
+                println!(
+                    "native_source zero_copy active={} source={}x{}",
+                    (width, height) == source_size && !foveated,
+                    source_size.0,
+                    source_size.1
+                );
+            }
+            // Flush the old size first so every lease is back in the old
+            // pool, then rebuild both at the negotiated size. The Metal pass
+            // scales the fixed source slots into whatever lease it is given.
+            encoder.config.width = width;
+            encoder.config.height = height;
+            encoder.restart(&mut output, &mut counters)?;
+            pool = SurfacePool::with_color_space(
+                width,
+                height,
+                config.probe.buffer_count,
+                config.probe.format,
+                config.probe.color,
+            )?;
+            fallback_view_params = default_stereo_view_params(width, height);
+            if capture.take().is_some() {
+                eprintln!(
+                    "WARNING frame_capture stopped because the stream size changed to {width}x{height}"
+                );
+            }
+        } else if codec_change.is_some() {
+            check_renegotiated_size(PreflightInput {
+                codec: encoder.config.codec,
+                width: encoder.config.width,
+                height: encoder.config.height,
+                ..config.probe.preflight_input(None)
+            })?;
+            encoder.restart(&mut output, &mut counters)?;
+        } else if refresh_change.is_some() {
+            encoder.restart(&mut output, &mut counters)?;
+        }
+        let standby_reason = sink.lock().as_ref().map(|sink| {
+            if sink.client_status().is_none() {
+                Some("no_client")
+            } else if sink.driver_paused() {
+                Some("driver_paused")
+            } else {
+                None
+            }
+        });
+        if let Some(standby_reason) = standby_reason
+            && config.standby
+            && !closing
+        {
+            if encoder.is_open()
+                && let Some(reason) = standby_reason
+            {
+                encoder.close(&mut output, &mut counters)?;
+                standby_since = Some(Instant::now());
+                println!(
+                    "native_source standby entered reason={reason} encoded={}",
+                    counters.encoded
+                );
+            } else if !encoder.is_open() && standby_reason.is_none() {
+                encoder.open_session(&mut output, &mut counters)?;
+                println!(
+                    "native_source standby exited after_ms={} standby_drops={}",
+                    standby_since
+                        .take()
+                        .map_or(0, |since| since.elapsed().as_millis()),
+                    counters.standby_drops
+                );
+            }
+        }
+        if let Some(timeout) = config.client_timeout
+            && let Some(sink) = sink.lock().as_ref()
+            && !closing
+        {
+            if sink.client_status().is_some() {
+                client_wait_started = None;
+            } else {
+                let started = *client_wait_started.get_or_insert_with(Instant::now);
+                ensure!(
+                    started.elapsed() < timeout,
+                    "no ALVR client connected within {} seconds",
+                    timeout.as_secs()
+                );
+            }
+        }
+        let refresh_hz = sink
+            .lock()
+            .as_ref()
+            .and_then(AlvrVideoSink::client_status)
+            .map_or(config.probe.fps as f32, |client| client.refresh_rate);
+        if let Some(pacer) = pacer.as_mut()
+            && pacer.set_refresh(refresh_hz)
+        {
+            println!(
+                "native_source pacing refresh_hz={refresh_hz:.3} interval_us={}",
+                pacer.interval().as_micros()
+            );
+        }
+        let state = if closing {
+            BridgeState::Closing
+        } else if !encoder.is_open() {
+            BridgeState::Standby
+        } else {
+            stream_state(sink.lock().as_ref())
+        };
+        counters.publish_status(
+            state,
+            encoder.pending_count(),
+            encoder.config.bitrate_bps,
+            &mut sink.lock(),
+            control.as_ref(),
+        );
 
-        let Some(frame) = source.next_frame(Duration::from_millis(250))? else {
+        let Some(frame) = (match queued_frame.take() {
+            Some(frame) => Some(frame),
+            None => {
+                info_span!("acquire").in_scope(|| source.next_frame(Duration::from_millis(250)))?
+            }
+        }) else {
             if closing {
                 closing_timeouts += 1;
                 if closing_timeouts >= 4 {
                     break;
                 }
-            } else {
+            } else if !producer.is_alive() {
                 ensure!(
-                    last_frame_at.elapsed() < Duration::from_secs(60),
-                    "IOSurface producer was idle for 60 seconds"
+                    config.reconnect,
+                    "IOSurface producer pid={} exited",
+                    producer.pid
+                );
+                output.report_cadence(
+                    &mut counters,
+                    sink.lock().as_mut(),
+                    frame_interval,
+                    &source,
+                    &pool,
+                );
+                reconnects += 1;
+                println!(
+                    "native_source producer_exited pid={} reconnects={reconnects}",
+                    producer.pid
+                );
+                // The new producer's frame ids restart, so the encoder's order
+                // check starts over and the client gets an IDR.
+                encoder.restart(&mut output, &mut counters)?;
+                if let Some(control) = &control {
+                    control.update(|status| status.state = BridgeState::WaitingForProducer);
+                }
+                producer = handshake_producer(&source, &config)?;
+                release_startup_barrier(&source)?;
+                last_frame_at = Instant::now();
+                counters.last_pose_generation = 0;
+                last_pose_timestamp = None;
+                last_producer_frame_id = None;
+                exact_pose_wait_started = None;
+                rebase_video_clock = true;
+                if let Some(pacer) = pacer.as_mut() {
+                    pacer.reset();
+                }
+            } else if let Some(timeout) = config.frame_timeout
+                && !sink
+                    .lock()
+                    .as_ref()
+                    .is_some_and(AlvrVideoSink::driver_paused)
+            {
+                ensure!(
+                    last_frame_at.elapsed() < timeout,
+                    "IOSurface producer was idle for {} seconds",
+                    timeout.as_secs()
                 );
             }
             continue;
         };
         last_frame_at = Instant::now();
+        let frame_received_at = last_frame_at;
         closing_timeouts = 0;
+        // Output sent while this frame was awaited counts before the stall
+        // check below.
+        encoder.poll(&mut output, &mut counters)?;
 
         let validation_status = frame.validation_status();
         if validation_status != STATUS_PASS {
@@ -451,31 +1054,232 @@ pub fn run_native_source_probe(
             anyhow::bail!("received a duplicate IOSurface self-test after startup completed");
         }
 
-        received += 1;
+        counters.received += 1;
+        let producer_frame_id = frame.frame_id();
+        // Covers the frame from here until it is handed to ALVR or dropped,
+        // with convert, encode, and send nested inside.
+        let _frame_span = info_span!("frame", frame_id = producer_frame_id).entered();
+        // A session that has gone quiet while frames keep arriving is
+        // replaced.
+        if let Some(watchdog) = output.watchdog.as_mut()
+            && encoder.is_open()
+            && let Some(waited) = watchdog.stalled(frame_received_at)
+        {
+            counters.encoder_stalls += 1;
+            let line = format!(
+                "native_source encoder_stalled waited_ms={} pending={} stalls={}",
+                waited.as_millis(),
+                encoder.pending_count(),
+                counters.encoder_stalls
+            );
+            eprintln!("WARNING {line}");
+            if let Some(sink) = sink.lock().as_ref() {
+                sink.warn_dashboard(&line);
+            }
+            let recoveries = watchdog.recovered();
+            ensure!(
+                recoveries <= MAX_CONSECUTIVE_RECOVERIES,
+                "VideoToolbox stalled {recoveries} times in a row without output"
+            );
+            encoder.recreate("stall", &mut output, &mut counters, &sink)?;
+        }
+        if let Some(last) = last_producer_frame_id
+            && producer_frame_id > last + 1
+        {
+            counters.producer_gaps += producer_frame_id - last - 1;
+        }
+        last_producer_frame_id = Some(producer_frame_id);
+        // Ahead of standby, so the window shows Wine's frames while no
+        // client is connected.
+        if let Some(preview) = preview.as_mut() {
+            preview.offer(&frame)?;
+        }
+        if !encoder.is_open() && !closing {
+            counters.standby_drops += 1;
+            frame.release(STATUS_FRAME_DROPPED)?;
+            if counters.received % config.probe.telemetry_interval == 0 {
+                output.report_cadence(
+                    &mut counters,
+                    sink.lock().as_mut(),
+                    frame_interval,
+                    &source,
+                    &pool,
+                );
+            }
+            continue;
+        }
+        if config.verify_checksums
+            && let Some(checksum) = frame.content_checksum()?
+        {
+            counters.checksums_verified += 1;
+            if !checksum.matches() {
+                counters.checksum_mismatches += 1;
+                eprintln!(
+                    "WARNING native_source checksum_mismatch frame_id={producer_frame_id} slot={} expected={:08x} actual={:08x} count={}",
+                    frame.slot_index(),
+                    checksum.expected,
+                    checksum.actual,
+                    counters.checksum_mismatches
+                );
+            }
+        }
         if closing {
             frame.release(STATUS_SESSION_CLOSED)?;
             continue;
         }
+        // A newer frame already queued behind this one makes it stale, so
+        // skip straight to the newest. Only a frame that passed the producer
+        // checks counts: anything else on the port is rejected by the poll
+        // and leaves this frame in place. Consumer samples and decoder
+        // bootstrap frames are kept because the run's accounting depends on
+        // them.
+        if config.latest_frame_wins && !consumer_sample && !frame.is_fallback_pose() {
+            queued_frame = source.next_frame(Duration::ZERO)?;
+            if queued_frame
+                .as_ref()
+                .is_some_and(|newer| newer.validation_status() == STATUS_PASS)
+            {
+                counters.superseded_drops += 1;
+                frame.release(STATUS_FRAME_DROPPED)?;
+                continue;
+            }
+        }
+        // With frames already waiting in the encoder past the limit, this one
+        // would only lengthen the backlog. Its slot goes straight back to the
+        // producer, and the encoder picks up again at the newest frame.
+        if let Some(queue) = output.encode_queue.as_mut()
+            && encoder.is_open()
+            && !consumer_sample
+            && !frame.is_fallback_pose()
+            && queue.is_behind(frame_received_at, frame_interval, encoder.pending_count())
+        {
+            counters.backpressure_drops += 1;
+            frame.release(STATUS_FRAME_DROPPED)?;
+            if counters.received % config.probe.telemetry_interval == 0 {
+                output.report_cadence(
+                    &mut counters,
+                    sink.lock().as_mut(),
+                    frame_interval,
+                    &source,
+                    &pool,
+                );
+            }
+            continue;
+        }
+        // The stream already sent a window's worth at the peak rate, so this
+        // frame waits out the burst the same way.
+        if let Some(limiter) = output.rate_limiter.as_mut()
+            && encoder.is_open()
+            && !consumer_sample
+            && !frame.is_fallback_pose()
+            && limiter.is_over(frame_received_at)
+        {
+            counters.rate_limit_drops += 1;
+            frame.release(STATUS_FRAME_DROPPED)?;
+            if counters.received % config.probe.telemetry_interval == 0 {
+                output.report_cadence(
+                    &mut counters,
+                    sink.lock().as_mut(),
+                    frame_interval,
+                    &source,
+                    &pool,
+                );
+            }
+            continue;
+        }
+        // A frame early for its vsync waits for the slot, and gives way to
+        // any newer frame that arrives in the meantime.
+        if let Some(pacer) = &pacer
+            && !consumer_sample
+            && !frame.is_fallback_pose()
+            && let Pace::Hold(until) = pacer.pace(Instant::now())
+        {
+            while queued_frame.is_none() {
+                let remaining = until.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    break;
+                }
+                thread::sleep(remaining.min(PACING_POLL));
+                queued_frame = source.next_frame(Duration::ZERO)?;
+            }
+            if queued_frame
+                .as_ref()
+                .is_some_and(|newer| newer.validation_status() == STATUS_PASS)
+            {
+                counters.paced_drops += 1;
+                frame.release(STATUS_FRAME_DROPPED)?;
+                continue;
+            }
+        }
+        let paced_at = Instant::now();
+        if let Some(ladder) = &ladder
+            && !frame.is_fallback_pose()
+            && !ladder.level().keeps_frame(counters.received)
+        {
+            counters.decimated_drops += 1;
+            frame.release(STATUS_FRAME_DROPPED)?;
+            continue;
+        }
+        // A frame identical to the last one submitted gives the client
+        // nothing its reprojection of that frame does not. Nothing is sent in
+        // its place, the same as for a vsync the producer missed.
+        let content_hash =
+            if config.skip_duplicates && !consumer_sample && !frame.is_fallback_pose() {
+                let hash = frame.content_hash()?;
+                if encoder.last_content.is_some_and(|(last, submitted_at)| {
+                    last == hash && submitted_at.elapsed() < DUPLICATE_REFRESH_INTERVAL
+                }) && !sink
+                    .lock()
+                    .as_ref()
+                    .is_some_and(AlvrVideoSink::keyframe_pending)
+                {
+                    counters.duplicate_skips += 1;
+                    frame.release(STATUS_FRAME_DROPPED)?;
+                    if counters.received % config.probe.telemetry_interval == 0 {
+                        output.report_cadence(
+                            &mut counters,
+                            sink.lock().as_mut(),
+                            frame_interval,
+                            &source,
+                            &pool,
+                        );
+                    }
+                    continue;
+                }
+                Some(hash)
+            } else {
+                None
+            };
         let frame_id = frame.frame_id();
-        let video_timestamp = frame.video_timestamp();
+        // Keep video timestamps increasing when a relaunched producer's
+        // clock starts behind the last frame the client received.
+        if std::mem::take(&mut rebase_video_clock)
+            && let Some(last) = counters.last_submitted_video_timestamp
+        {
+            video_offset = (last + frame_interval).saturating_sub(frame.video_timestamp());
+        }
+        let video_timestamp = frame.video_timestamp() + video_offset;
         let (pose_generation, pose_timestamp, frame_pose) = frame.frame_pose()?;
         let fallback_pose = frame.is_fallback_pose();
         let mut decoder_bootstrap_frame = false;
         if frame.is_fallback_pose() {
-            pose_fallback += 1;
+            counters.pose_fallback += 1;
         } else {
             exact_pose_wait_started = None;
-            pose_paired += 1;
-            if last_pose_generation != 0 && pose_generation > last_pose_generation + 1 {
-                pose_generation_gaps += pose_generation - last_pose_generation - 1;
+            counters.pose_paired += 1;
+            if counters.last_pose_generation != 0
+                && pose_generation > counters.last_pose_generation + 1
+            {
+                counters.pose_generation_gaps +=
+                    pose_generation - counters.last_pose_generation - 1;
             }
             if last_pose_timestamp == Some(pose_timestamp) {
-                pose_timestamp_reuses += 1;
+                counters.pose_timestamp_reuses += 1;
             }
-            last_pose_generation = pose_generation;
+            counters.last_pose_generation = pose_generation;
             last_pose_timestamp = Some(pose_timestamp);
         }
-        let metadata = if let Some(sink) = sink.as_mut() {
+        let metadata = if let Some(sink) = sink.lock().as_mut() {
             let metadata = if fallback_pose {
                 sink.bootstrap_frame_metadata(
                     frame_id,
@@ -492,28 +1296,39 @@ pub fn run_native_source_probe(
                 )?
             };
             let Some(metadata) = metadata else {
-                dropped += 1;
-                not_ready_drops += 1;
+                counters.dropped += 1;
+                counters.not_ready_drops += 1;
                 let exact_pose_wait_elapsed =
                     exact_pose_wait_started.map(|started| started.elapsed());
                 let exact_pose_wait_timed_out = fallback_pose
                     && exact_pose_wait_elapsed
-                        .is_some_and(|elapsed| elapsed >= EXACT_POSE_STARTUP_TIMEOUT);
+                        .is_some_and(|elapsed| elapsed >= config.pose_timeout);
                 frame.release(STATUS_FRAME_DROPPED)?;
-                if received % config.probe.telemetry_interval == 0 || exact_pose_wait_timed_out {
-                    report_cadence!();
+                if counters.received % config.probe.telemetry_interval == 0
+                    || exact_pose_wait_timed_out
+                {
+                    output.report_cadence(
+                        &mut counters,
+                        Some(sink),
+                        frame_interval,
+                        &source,
+                        &pool,
+                    );
                 }
                 if exact_pose_wait_timed_out {
                     anyhow::bail!(
-                        "ALVR exact render pose did not become ready within {} seconds after decoder bootstrap: received={received} dropped={dropped} pose_bootstrap={pose_bootstrap} wait_ms={}",
-                        EXACT_POSE_STARTUP_TIMEOUT.as_secs(),
+                        "ALVR exact render pose did not become ready within {} seconds after decoder bootstrap: received={} dropped={} pose_bootstrap={} wait_ms={}",
+                        config.pose_timeout.as_secs(),
+                        counters.received,
+                        counters.dropped,
+                        counters.pose_bootstrap,
                         exact_pose_wait_elapsed.unwrap_or_default().as_millis(),
                     );
                 }
                 continue;
             };
             if fallback_pose {
-                pose_bootstrap += 1;
+                counters.pose_bootstrap += 1;
                 decoder_bootstrap_frame = true;
                 exact_pose_wait_started = Some(Instant::now());
             }
@@ -527,88 +1342,218 @@ pub fn run_native_source_probe(
                 global_view_params: fallback_view_params,
             }
         };
-        let Some(lease) = pool.try_acquire()? else {
-            dropped += 1;
-            pool_exhausted_drops += 1;
+        let close_after_frame = counters.submitted + 1 >= config.probe.frame_count;
+        let release_status = if close_after_frame {
+            STATUS_SESSION_CLOSED
+        } else {
+            STATUS_PASS
+        };
+        let copies = slot_copies
+            .as_ref()
+            .filter(|_| encoder.size() == source_size && !foveated);
+        let input = match copies {
+            Some(copies) => copies.try_acquire().map(EncodeInput::Copied),
+            None => pool.try_acquire()?.map(EncodeInput::Converted),
+        };
+        let Some(mut input) = input else {
+            counters.dropped += 1;
+            counters.pool_exhausted_drops += 1;
             frame.release(STATUS_FRAME_DROPPED)?;
-            if received % config.probe.telemetry_interval == 0 {
-                report_cadence!();
+            if counters.received % config.probe.telemetry_interval == 0 {
+                output.report_cadence(
+                    &mut counters,
+                    sink.lock().as_mut(),
+                    frame_interval,
+                    &source,
+                    &pool,
+                );
             }
             continue;
         };
-
-        let conversion_timing =
-            converter.convert(&frame, &lease, source.width(), source.height())?;
-        conversion_total += conversion_timing.wall;
-        conversion_max = conversion_max.max(conversion_timing.wall);
-        conversion_gpu_total += conversion_timing.gpu;
-        conversion_gpu_max = conversion_gpu_max.max(conversion_timing.gpu);
-        conversion_count += 1;
-        let close_after_frame = submitted + 1 >= config.probe.frame_count;
-        frame.release(if close_after_frame {
-            STATUS_SESSION_CLOSED
-        } else {
-            STATUS_PASS
+        // Either way the frame is out of the slot once this returns, and the
+        // slot goes straight back to the producer.
+        let conversion_timing = info_span!("convert").in_scope(|| match &input {
+            EncodeInput::Converted(lease) => {
+                converter.convert(&frame, lease, source.width(), source.height())
+            }
+            EncodeInput::Copied(copy) => {
+                let copy_started = Instant::now();
+                frame.copy_to(copy).map(|()| ConversionTiming {
+                    wall: copy_started.elapsed(),
+                    gpu: Duration::ZERO,
+                })
+            }
         })?;
+        counters.record_conversion(conversion_timing);
+        frame.release(release_status)?;
+        counters.record_slot_hold(frame_received_at);
+        if let EncodeInput::Converted(lease) = &mut input {
+            if let Some(capture) = capture.as_mut() {
+                lease.with_nv12_frame(|frame| {
+                    capture.write(frame, metadata.frame_id, metadata.video_timestamp)
+                })?;
+            }
+            if !filters.is_empty() {
+                lease.with_nv12_frame(|frame| filters.apply(frame, &metadata))?;
+            }
+        }
 
-        let requested_keyframe = sink
+        // Taken whatever else forces this frame, so a request is never left
+        // over for the next one.
+        let keyframe_requested = sink
+            .lock()
             .as_mut()
             .is_some_and(AlvrVideoSink::take_force_keyframe);
-        let force_keyframe = decoder_bootstrap_frame
-            || submitted % u64::from(config.probe.fps) == 0
-            || requested_keyframe;
-        first_submitted_video_timestamp.get_or_insert(metadata.video_timestamp);
-        last_submitted_video_timestamp = Some(metadata.video_timestamp);
-        let outputs = encoder.submit(lease, metadata, force_keyframe)?;
-        submitted += 1;
+        let force_keyframe = keyframe_requested
+            || decoder_bootstrap_frame
+            || periodic_keyframe(config.probe.keyframe_interval, counters.submitted);
+        counters
+            .first_submitted_video_timestamp
+            .get_or_insert(metadata.video_timestamp);
+        counters.last_submitted_video_timestamp = Some(metadata.video_timestamp);
+        encoder.last_content = content_hash.map(|hash| (hash, Instant::now()));
+        if let Some(sink) = sink.lock().as_ref() {
+            sink.report_composed(&metadata, frame_received_at);
+        }
+        let encode_started = Instant::now();
+        encoder.submit(EncodeJob {
+            input,
+            metadata,
+            force_keyframe,
+        })?;
+        counters.submitted += 1;
+        if let Some(watchdog) = output.watchdog.as_mut() {
+            watchdog.submitted(paced_at);
+        }
+        if let Some(queue) = output.encode_queue.as_mut() {
+            queue.submitted(encode_started);
+        }
+        if let Some(pacer) = pacer.as_mut() {
+            counters.vsync_repeats += pacer.submitted(paced_at);
+        }
         if consumer_sample {
             if visible_consumer_sample {
-                visible_consumer_samples += 1;
+                counters.visible_consumer_samples += 1;
             } else {
-                black_consumer_samples += 1;
+                counters.black_consumer_samples += 1;
                 println!(
-                    "native_source black consumer sample submitted frame_id={frame_id} count={black_consumer_samples}"
+                    "native_source black consumer sample submitted frame_id={frame_id} count={}",
+                    counters.black_consumer_samples
                 );
             }
         }
-        let dispatch = dispatch_outputs(outputs, &mut sink)?;
-        encoded += dispatch.encoded;
-        transported += dispatch.transported;
-        encoded_bytes = encoded_bytes.saturating_add(dispatch.encoded_bytes);
-        transported_bytes = transported_bytes.saturating_add(dispatch.transported_bytes);
-        keyframes += dispatch.keyframes;
-        keyframe_bytes = keyframe_bytes.saturating_add(dispatch.keyframe_bytes);
-        max_frame_bytes = max_frame_bytes.max(dispatch.max_frame_bytes);
+        encoder.poll(&mut output, &mut counters)?;
 
-        if received % config.probe.telemetry_interval == 0 || close_after_frame {
-            report_cadence!();
+        if counters.received % config.probe.telemetry_interval == 0 || close_after_frame {
+            output.report_cadence(
+                &mut counters,
+                sink.lock().as_mut(),
+                frame_interval,
+                &source,
+                &pool,
+            );
+        }
+        // Read ahead of the `if let` chains below, whose bodies restart the
+        // encoder while the encode thread may be waiting on the sink.
+        let (requested_bps, idr_requests) = sink.lock().as_ref().map_or((None, 0), |sink| {
+            (sink.requested_bitrate_bps(), sink.idr_requests())
+        });
+        if config.adaptive_bitrate
+            && !close_after_frame
+            && let Some(requested_bps) = requested_bps
+            && let Some(target_bps) = retarget_bitrate(alvr_bitrate_bps, requested_bps)
+        {
+            alvr_bitrate_bps = target_bps;
+            let bitrate_bps = ladder
+                .as_ref()
+                .map_or(target_bps, |ladder| ladder.level().bitrate_bps(target_bps));
+            println!(
+                "native_source adaptive_bitrate requested_bps={requested_bps} previous_bps={} bitrate_bps={bitrate_bps}",
+                encoder.config.bitrate_bps
+            );
+            encoder.config.bitrate_bps = bitrate_bps;
+            encoder.restart(&mut output, &mut counters)?;
+        }
+        if counters.received % config.probe.telemetry_interval == 0
+            && !close_after_frame
+            && let Some((previous, next)) = session_watcher.as_mut().and_then(SessionWatcher::poll)
+        {
+            println!(
+                "native_source session_reload codec={:?} fps={:?} bitrate_bps={:?} stream_size={:?} hdr={:?}",
+                next.codec, next.fps, next.bitrate_bps, next.stream_size, next.hdr
+            );
+            if next.codec != previous.codec || next.hdr != previous.hdr {
+                eprintln!(
+                    "WARNING session_reload codec and hdr are fixed for the bridge run and apply after it restarts"
+                );
+            }
+            if next.stream_size != previous.stream_size || next.fps != previous.fps {
+                eprintln!(
+                    "WARNING session_reload resolution and refresh rate apply when the client next connects"
+                );
+            }
+            if let Some(bitrate_bps) = next.bitrate_bps
+                && next.bitrate_bps != previous.bitrate_bps
+                && config.probe.rate_control == RateControl::AverageBitrate
+            {
+                alvr_bitrate_bps = bitrate_bps;
+                encoder.config.bitrate_bps = ladder.as_ref().map_or(bitrate_bps, |ladder| {
+                    ladder.level().bitrate_bps(bitrate_bps)
+                });
+                encoder.restart(&mut output, &mut counters)?;
+            }
+        }
+        if counters.received % config.probe.telemetry_interval == 0
+            && !close_after_frame
+            && let Some(ladder) = ladder.as_mut()
+            && let Some(transition) = ladder.observe(LoadCounters {
+                received: counters.received,
+                dropped: counters.dropped,
+                busy_total: counters.conversion_total,
+                busy_count: counters.conversion_count,
+                encoder_pending: encoder.pending_count(),
+                idr_requests,
+            })
+        {
+            println!("native_source {transition}");
+            if transition.to.bitrate_percent != transition.from.bitrate_percent {
+                encoder.config.bitrate_bps = transition.to.bitrate_bps(alvr_bitrate_bps);
+                encoder.restart(&mut output, &mut counters)?;
+            }
         }
         if close_after_frame {
             closing = true;
         }
     }
 
-    let dispatch = dispatch_outputs(encoder.finish()?, &mut sink)?;
-    encoded += dispatch.encoded;
-    transported += dispatch.transported;
-    encoded_bytes = encoded_bytes.saturating_add(dispatch.encoded_bytes);
-    transported_bytes = transported_bytes.saturating_add(dispatch.transported_bytes);
-    keyframes += dispatch.keyframes;
-    keyframe_bytes = keyframe_bytes.saturating_add(dispatch.keyframe_bytes);
-    max_frame_bytes = max_frame_bytes.max(dispatch.max_frame_bytes);
+    counters.publish_status(
+        BridgeState::Closing,
+        encoder.pending_count(),
+        encoder.config.bitrate_bps,
+        &mut sink.lock(),
+        control.as_ref(),
+    );
+    encoder.close(&mut output, &mut counters)?;
     let pool_stats = pool.stats();
     ensure!(
-        submitted == config.probe.frame_count,
-        "submitted {submitted} frames, expected {}",
+        counters.submitted == config.probe.frame_count || interrupted,
+        "submitted {} frames, expected {}",
+        counters.submitted,
         config.probe.frame_count
     );
     ensure!(
-        encoded == submitted,
-        "VideoToolbox emitted {encoded} frames for {submitted} submissions"
+        counters.encoded == counters.submitted,
+        "VideoToolbox emitted {} frames for {} submissions",
+        counters.encoded,
+        counters.submitted
     );
     ensure!(
-        pose_paired + pose_fallback == submitted + dropped,
-        "render-pose accounting mismatch: paired={pose_paired} fallback={pose_fallback} submitted={submitted} dropped={dropped}"
+        counters.pose_paired + counters.pose_fallback == counters.submitted + counters.dropped,
+        "render-pose accounting mismatch: paired={} fallback={} submitted={} dropped={}",
+        counters.pose_paired,
+        counters.pose_fallback,
+        counters.submitted,
+        counters.dropped
     );
     ensure!(
         pool_stats.available == pool_stats.capacity && pool_stats.acquired == pool_stats.recycled,
@@ -619,62 +1564,515 @@ pub fn run_native_source_probe(
         pool_stats.recycled
     );
     ensure!(
-        visible_content_observed(black_consumer_samples, visible_consumer_samples),
-        "consumer sampling never observed visible content: black_samples={black_consumer_samples}"
+        visible_content_observed(
+            counters.black_consumer_samples,
+            counters.visible_consumer_samples
+        ),
+        "consumer sampling never observed visible content: black_samples={}",
+        counters.black_consumer_samples
     );
-    let connected_to_alvr = sink.as_mut().is_some_and(|sink| {
+    let connected_to_alvr = sink.lock().as_mut().is_some_and(|sink| {
         sink.poll_events();
         sink.ever_connected()
     });
     ensure!(
-        !config.probe.connect_to_alvr || connected_to_alvr,
+        !config.probe.connect_to_alvr || connected_to_alvr || interrupted,
         "ALVR transport probe never reached ClientConnected"
     );
     ensure!(
-        !config.probe.connect_to_alvr || transported > 0,
+        !config.probe.connect_to_alvr || counters.transported > 0 || interrupted,
         "ALVR transport connected but no native-source frames were sent"
     );
+    // A clean exit would keep launchd from starting the bridge again.
+    ensure!(
+        !sink
+            .lock()
+            .as_ref()
+            .is_some_and(AlvrVideoSink::restart_requested),
+        "ALVR requested a restart"
+    );
 
     Ok(NativeProbeSummary {
-        fps: config.probe.fps,
+        frame_interval,
         self_tests,
-        received_frames: received,
-        submitted_frames: submitted,
-        encoded_frames: encoded,
-        transported_frames: transported,
-        encoded_bytes,
-        transported_bytes,
-        keyframes,
-        keyframe_bytes,
-        max_frame_bytes,
-        video_span: submitted_video_span(
-            first_submitted_video_timestamp,
-            last_submitted_video_timestamp,
-            config.probe.fps,
-        ),
-        dropped_frames: dropped,
-        not_ready_drops,
-        pool_exhausted_drops,
-        black_consumer_samples,
-        visible_consumer_samples,
-        pose_paired,
-        pose_fallback,
-        pose_bootstrap,
-        pose_generation_gaps,
-        pose_timestamp_reuses,
-        last_pose_generation,
+        received_frames: counters.received,
+        submitted_frames: counters.submitted,
+        encoded_frames: counters.encoded,
+        transported_frames: counters.transported,
+        encoded_bytes: counters.encoded_bytes,
+        transported_bytes: counters.transported_bytes,
+        keyframes: counters.keyframes,
+        keyframe_bytes: counters.keyframe_bytes,
+        max_frame_bytes: counters.max_frame_bytes,
+        video_span: counters.video_span(frame_interval),
+        dropped_frames: counters.dropped,
+        not_ready_drops: counters.not_ready_drops,
+        pool_exhausted_drops: counters.pool_exhausted_drops,
+        backpressure_drops: counters.backpressure_drops,
+        rate_limit_drops: counters.rate_limit_drops,
+        decimated_drops: counters.decimated_drops,
+        superseded_drops: counters.superseded_drops,
+        paced_drops: counters.paced_drops,
+        vsync_repeats: counters.vsync_repeats,
+        standby_drops: counters.standby_drops,
+        duplicate_skips: counters.duplicate_skips,
+        encoder_stalls: counters.encoder_stalls,
+        producer_gaps: counters.producer_gaps,
+        checksums_verified: counters.checksums_verified,
+        checksum_mismatches: counters.checksum_mismatches,
+        black_consumer_samples: counters.black_consumer_samples,
+        visible_consumer_samples: counters.visible_consumer_samples,
+        pose_paired: counters.pose_paired,
+        pose_fallback: counters.pose_fallback,
+        pose_bootstrap: counters.pose_bootstrap,
+        pose_generation_gaps: counters.pose_generation_gaps,
+        pose_timestamp_reuses: counters.pose_timestamp_reuses,
+        last_pose_generation: counters.last_pose_generation,
+        rejected_messages: source.rejected_messages(),
         wall_elapsed: start.elapsed(),
-        conversion_average: conversion_average(conversion_total, conversion_count),
-        conversion_max,
-        conversion_gpu_average: conversion_average(conversion_gpu_total, conversion_count),
-        conversion_gpu_max,
+        conversion_average: average_duration(counters.conversion_total, counters.conversion_count),
+        conversion_max: counters.conversion_max,
+        conversion_gpu_average: average_duration(
+            counters.conversion_gpu_total,
+            counters.conversion_count,
+        ),
+        conversion_gpu_max: counters.conversion_gpu_max,
+        slot_hold_average: average_duration(counters.slot_hold_total, counters.slot_hold_count),
+        slot_hold_max: counters.slot_hold_max,
         pool_stats,
         hardware_support,
         connected_to_alvr,
     })
 }
 
-fn conversion_average(total: Duration, count: u64) -> Duration {
+/// The IOSurface loop's running totals, reported on the telemetry cadence
+/// and in the final summary.
+#[derive(Default)]
+struct NativeCounters {
+    received: u64,
+    submitted: u64,
+    encoded: u64,
+    transported: u64,
+    encoded_bytes: u64,
+    transported_bytes: u64,
+    keyframes: u64,
+    keyframe_bytes: u64,
+    max_frame_bytes: u64,
+    first_submitted_video_timestamp: Option<Duration>,
+    last_submitted_video_timestamp: Option<Duration>,
+    dropped: u64,
+    not_ready_drops: u64,
+    pool_exhausted_drops: u64,
+    backpressure_drops: u64,
+    rate_limit_drops: u64,
+    decimated_drops: u64,
+    superseded_drops: u64,
+    paced_drops: u64,
+    vsync_repeats: u64,
+    standby_drops: u64,
+    duplicate_skips: u64,
+    encoder_stalls: u64,
+    producer_gaps: u64,
+    checksums_verified: u64,
+    checksum_mismatches: u64,
+    black_consumer_samples: u64,
+    visible_consumer_samples: u64,
+    pose_paired: u64,
+    pose_fallback: u64,
+    pose_bootstrap: u64,
+    pose_generation_gaps: u64,
+    pose_timestamp_reuses: u64,
+    last_pose_generation: u64,
+    conversion_total: Duration,
+    conversion_max: Duration,
+    conversion_gpu_total: Duration,
+    conversion_gpu_max: Duration,
+    conversion_count: u64,
+    slot_hold_total: Duration,
+    slot_hold_max: Duration,
+    slot_hold_count: u64,
+    latency: LatencyTracker,
+    frame_stats: FrameStatsWindow,
+    status: StatusWindow,
+}
+
+/// What the status endpoint and the driver's shared memory show between
+/// telemetry reports, taken from the last completed interval.
+struct StatusWindow {
+    started: Instant,
+    encoded: u64,
+    frame_rate: f64,
+    encode_latency: LatencySummary,
+    frames: FrameStats,
+}
+
+impl Default for StatusWindow {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            encoded: 0,
+            frame_rate: 0.0,
+            encode_latency: LatencySummary::default(),
+            frames: FrameStats::default(),
+        }
+    }
+}
+
+impl NativeCounters {
+    fn record_dispatch(&mut self, dispatch: &DispatchCounts) {
+        self.encoded += dispatch.encoded;
+        self.transported += dispatch.transported;
+        self.encoded_bytes = self.encoded_bytes.saturating_add(dispatch.encoded_bytes);
+        self.transported_bytes = self
+            .transported_bytes
+            .saturating_add(dispatch.transported_bytes);
+        self.keyframes += dispatch.keyframes;
+        self.keyframe_bytes = self.keyframe_bytes.saturating_add(dispatch.keyframe_bytes);
+        self.max_frame_bytes = self.max_frame_bytes.max(dispatch.max_frame_bytes);
+        self.latency
+            .record_dispatch(&dispatch.encode_latencies, &dispatch.send_latencies);
+        self.frame_stats.record_dispatch(&dispatch.frames);
+    }
+
+    fn record_conversion(&mut self, timing: ConversionTiming) {
+        self.conversion_total += timing.wall;
+        self.conversion_max = self.conversion_max.max(timing.wall);
+        self.conversion_gpu_total += timing.gpu;
+        self.conversion_gpu_max = self.conversion_gpu_max.max(timing.gpu);
+        self.conversion_count += 1;
+        self.latency.convert.record(timing.wall);
+    }
+
+    /// Time from a frame message arriving to its slot going back to the
+    /// producer. The slot is released once the frame is converted or copied
+    /// out of it, ahead of filters, encode, and ALVR send.
+    fn record_slot_hold(&mut self, received_at: Instant) {
+        let hold = received_at.elapsed();
+        self.slot_hold_total += hold;
+        self.slot_hold_max = self.slot_hold_max.max(hold);
+        self.slot_hold_count += 1;
+        self.latency.acquire.record(hold);
+    }
+
+    fn video_span(&self, frame_interval: Duration) -> Duration {
+        submitted_video_span(
+            self.first_submitted_video_timestamp,
+            self.last_submitted_video_timestamp,
+            frame_interval,
+        )
+    }
+
+    /// Closes the telemetry interval: the status window moves on, and the
+    /// latency and frame-size windows start over.
+    fn cadence(
+        &mut self,
+        frame_interval: Duration,
+        rejected_messages: u64,
+        pool_available: usize,
+        encode_queue_max: Duration,
+        rate_window_max_percent: u64,
+    ) -> NativeCadenceReport {
+        let latency = self.latency.take();
+        let window_elapsed = self.status.started.elapsed();
+        if !window_elapsed.is_zero() {
+            self.status.frame_rate =
+                (self.encoded - self.status.encoded) as f64 / window_elapsed.as_secs_f64();
+        }
+        self.status.started = Instant::now();
+        self.status.encoded = self.encoded;
+        self.status.encode_latency = latency.encode;
+        self.status.frames = self.frame_stats.take();
+        NativeCadenceReport {
+            frame_interval,
+            received: self.received,
+            submitted: self.submitted,
+            encoded: self.encoded,
+            transported: self.transported,
+            encoded_bytes: self.encoded_bytes,
+            transported_bytes: self.transported_bytes,
+            keyframes: self.keyframes,
+            keyframe_bytes: self.keyframe_bytes,
+            max_frame_bytes: self.max_frame_bytes,
+            video_span: self.video_span(frame_interval),
+            dropped: self.dropped,
+            not_ready_drops: self.not_ready_drops,
+            pool_exhausted_drops: self.pool_exhausted_drops,
+            backpressure_drops: self.backpressure_drops,
+            rate_limit_drops: self.rate_limit_drops,
+            producer_gaps: self.producer_gaps,
+            checksum_mismatches: self.checksum_mismatches,
+            black_consumer_samples: self.black_consumer_samples,
+            visible_consumer_samples: self.visible_consumer_samples,
+            pose_paired: self.pose_paired,
+            pose_fallback: self.pose_fallback,
+            pose_bootstrap: self.pose_bootstrap,
+            pose_generation_gaps: self.pose_generation_gaps,
+            pose_timestamp_reuses: self.pose_timestamp_reuses,
+            rejected_messages,
+            conversion_average: average_duration(self.conversion_total, self.conversion_count),
+            conversion_max: self.conversion_max,
+            conversion_gpu_average: average_duration(
+                self.conversion_gpu_total,
+                self.conversion_count,
+            ),
+            conversion_gpu_max: self.conversion_gpu_max,
+            slot_hold_average: average_duration(self.slot_hold_total, self.slot_hold_count),
+            slot_hold_max: self.slot_hold_max,
+            pool_available,
+            encode_queue_max,
+            rate_window_max_percent,
+            frames: self.status.frames,
+            latency,
+        }
+    }
+
+    /// Shows the bridge's state and the last interval's rates to the driver
+    /// and the status endpoint.
+    fn publish_status(
+        &self,
+        state: BridgeState,
+        encoder_queue_depth: usize,
+        bitrate_bps: u64,
+        sink: &mut Option<AlvrVideoSink>,
+        control: Option<&ControlServer>,
+    ) {
+        if let Some(sink) = sink.as_mut() {
+            sink.publish_bridge_stats(BridgeStats {
+                state,
+                encoder_queue_depth,
+                frame_rate: self.status.frame_rate,
+                encode_latency: self.status.encode_latency,
+                bitrate_bps,
+            });
+        }
+        if let Some(control) = control {
+            control.update(|status| {
+                status.state = state;
+                status.client = sink.as_ref().and_then(AlvrVideoSink::client_status);
+                status.metrics = StatusMetrics {
+                    received: self.received,
+                    submitted: self.submitted,
+                    encoded: self.encoded,
+                    transported: self.transported,
+                    dropped: self.dropped,
+                    backpressure_drops: self.backpressure_drops,
+                    rate_limit_drops: self.rate_limit_drops,
+                    encoded_bytes: self.encoded_bytes,
+                    transported_bytes: self.transported_bytes,
+                    keyframes: self.keyframes,
+                    producer_gaps: self.producer_gaps,
+                    checksum_mismatches: self.checksum_mismatches,
+                    frame_rate: self.status.frame_rate,
+                    bitrate_bps,
+                    encode_latency: self.status.encode_latency,
+                    frames: self.status.frames,
+                };
+            });
+        }
+    }
+}
+
+/// The limits that read how much the encode thread sent, and where cadence
+/// reports go.
+struct NativeOutput<R> {
+    encode_queue: Option<EncodeQueue>,
+    rate_limiter: Option<DataRateLimiter>,
+    watchdog: Option<EncoderWatchdog>,
+    report: R,
+}
+
+impl<R: FnMut(NativeCadenceReport)> NativeOutput<R> {
+    /// Accounts for frames the encode thread sent. Every batch, whether sent
+    /// after an encode, polled, or flushed by a restart, comes through here.
+    fn record(&mut self, sent: Sent, counters: &mut NativeCounters) {
+        let Sent {
+            dispatch,
+            pending,
+            at,
+        } = sent;
+        if dispatch.encoded > 0
+            && let Some(watchdog) = self.watchdog.as_mut()
+        {
+            watchdog.output(at, pending);
+        }
+        if let Some(limiter) = self.rate_limiter.as_mut() {
+            limiter.sent(at, dispatch.encoded_bytes);
+        }
+        counters.record_dispatch(&dispatch);
+    }
+
+    fn report_cadence(
+        &mut self,
+        counters: &mut NativeCounters,
+        sink: Option<&mut AlvrVideoSink>,
+        frame_interval: Duration,
+        source: &NativeSource,
+        pool: &SurfacePool,
+    ) {
+        let cadence = counters.cadence(
+            frame_interval,
+            source.rejected_messages(),
+            pool.stats().available,
+            self.encode_queue
+                .as_mut()
+                .map_or(Duration::ZERO, EncodeQueue::take_max_delay),
+            self.rate_limiter
+                .as_mut()
+                .map_or(0, DataRateLimiter::take_max_fill_percent),
+        );
+        crash_report::record("counters", cadence.to_string());
+        if let Some(sink) = sink {
+            crash_report::record("shared_memory", sink.shared_memory_snapshot());
+            sink.publish_to_dashboard(&cadence.to_string());
+        }
+        (self.report)(cadence);
+    }
+}
+
+/// The encode thread and the settings its session is rebuilt with. `open`
+/// is false while in standby: no client is connected or the driver paused,
+/// so the session is released and producer frames are returned
+/// unconverted.
+struct NativeEncoder {
+    stage: EncodeStage,
+    open: bool,
+    config: NativeVideoEncoderConfig,
+    /// The hash of the last frame submitted to the current session, and
+    /// when it went in.
+    last_content: Option<(u64, Instant)>,
+}
+
+impl NativeEncoder {
+    fn open<E: SourceEncoder + 'static>(
+        open: impl FnMut(NativeVideoEncoderConfig) -> Result<(E, HardwareEncoderSupport)>
+        + Send
+        + 'static,
+        config: NativeVideoEncoderConfig,
+        sink: &SharedSink,
+        recorder: Option<StreamRecorder>,
+        restream: Option<Restreamer>,
+        timing_sei: bool,
+    ) -> Result<(Self, HardwareEncoderSupport)> {
+        let (stage, hardware_support) =
+            EncodeStage::spawn(open, config, sink.clone(), recorder, restream, timing_sei)?;
+        let encoder = Self {
+            stage,
+            open: true,
+            config,
+            last_content: None,
+        };
+        Ok((encoder, hardware_support))
+    }
+
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.config.width, self.config.height)
+    }
+
+    fn pending_count(&self) -> usize {
+        self.stage.in_flight()
+    }
+
+    fn submit(&mut self, job: EncodeJob) -> Result<()> {
+        self.stage.submit(job)
+    }
+
+    /// Accounts for whatever the encode thread sent since the last call.
+    fn poll<R: FnMut(NativeCadenceReport)>(
+        &mut self,
+        output: &mut NativeOutput<R>,
+        counters: &mut NativeCounters,
+    ) -> Result<()> {
+        while let Some(sent) = self.stage.try_sent()? {
+            output.record(sent, counters);
+        }
+        Ok(())
+    }
+
+    /// Leaves standby with a session opened from the current `config`.
+    fn open_session<R: FnMut(NativeCadenceReport)>(
+        &mut self,
+        output: &mut NativeOutput<R>,
+        counters: &mut NativeCounters,
+    ) -> Result<()> {
+        self.stage
+            .restart(Some(self.config), |sent| output.record(sent, counters))?;
+        self.open = true;
+        self.last_content = None;
+        Ok(())
+    }
+
+    /// Flushes the session and opens a new one with the current `config`.
+    /// In standby only the bitrate is recorded; the session is rebuilt with
+    /// it when a client connects.
+    fn restart<R: FnMut(NativeCadenceReport)>(
+        &mut self,
+        output: &mut NativeOutput<R>,
+        counters: &mut NativeCounters,
+    ) -> Result<()> {
+        self.stage
+            .restart(self.open.then_some(self.config), |sent| {
+                output.record(sent, counters)
+            })?;
+        if let Some(limiter) = output.rate_limiter.as_mut() {
+            limiter.set_bitrate(self.config.bitrate_bps);
+        }
+        self.last_content = None;
+        if let Some(watchdog) = output.watchdog.as_mut() {
+            watchdog.reset();
+        }
+        Ok(())
+    }
+
+    /// Replaces a session that may no longer answer. It is dropped rather
+    /// than finished, since a flush could wait on it forever. The new session
+    /// opens on an IDR, and its parameter sets go to the client again.
+    fn recreate<R: FnMut(NativeCadenceReport)>(
+        &mut self,
+        reason: &str,
+        output: &mut NativeOutput<R>,
+        counters: &mut NativeCounters,
+        sink: &SharedSink,
+    ) -> Result<()> {
+        if self.open {
+            self.stage
+                .recreate(self.config, |sent| output.record(sent, counters))?;
+            self.last_content = None;
+            if let Some(sink) = sink.lock().as_mut() {
+                sink.resend_decoder_config();
+            }
+            println!(
+                "native_source encoder_recreated reason={reason} bitrate_bps={}",
+                self.config.bitrate_bps
+            );
+        }
+        if let Some(watchdog) = output.watchdog.as_mut() {
+            watchdog.reset();
+        }
+        Ok(())
+    }
+
+    /// Enters standby, or ends the run, sending whatever the session still
+    /// holds first.
+    fn close<R: FnMut(NativeCadenceReport)>(
+        &mut self,
+        output: &mut NativeOutput<R>,
+        counters: &mut NativeCounters,
+    ) -> Result<()> {
+        self.stage
+            .restart(None, |sent| output.record(sent, counters))?;
+        self.open = false;
+        if let Some(watchdog) = output.watchdog.as_mut() {
+            watchdog.reset();
+        }
+        Ok(())
+    }
+}
+
+fn average_duration(total: Duration, count: u64) -> Duration {
     if count == 0 {
         Duration::ZERO
     } else {
@@ -682,20 +2080,22 @@ fn conversion_average(total: Duration, count: u64) -> Duration {
     }
 }
 
-fn submitted_video_span(first: Option<Duration>, last: Option<Duration>, fps: u32) -> Duration {
+fn submitted_video_span(
+    first: Option<Duration>,
+    last: Option<Duration>,
+    frame_interval: Duration,
+) -> Duration {
     match (first, last) {
-        (Some(first), Some(last)) => {
-            last.saturating_sub(first) + Duration::from_secs_f64(1.0 / f64::from(fps))
-        }
+        (Some(first), Some(last)) => last.saturating_sub(first) + frame_interval,
         _ => Duration::ZERO,
     }
 }
 
-fn normalized_megabits_per_second(bytes: u64, frames: u64, fps: u32) -> f64 {
-    if frames == 0 {
+fn normalized_megabits_per_second(bytes: u64, frames: u64, frame_interval: Duration) -> f64 {
+    if frames == 0 || frame_interval.is_zero() {
         0.0
     } else {
-        bytes as f64 * 8.0 * f64::from(fps) / frames as f64 / 1_000_000.0
+        bytes as f64 * 8.0 / frame_interval.as_secs_f64() / frames as f64 / 1_000_000.0
     }
 }
 
@@ -703,6 +2103,102 @@ fn visible_content_observed(black_samples: u64, visible_samples: u64) -> bool {
     black_samples + visible_samples == 0 || visible_samples > 0
 }
 
+/// Accepts a producer and checks every slot's startup self-test. The producer
+/// waits for the startup barrier before sending real frames.
+fn handshake_producer(
+    source: &NativeSource,
+    config: &NativeSourceConfig,
+) -> Result<AuthenticatedProducer> {
+    println!(
+        "native_source awaiting producer handshake timeout_ms={}",
+        config
+            .producer_timeout
+            .map_or("none".to_string(), |timeout| timeout
+                .as_millis()
+                .to_string())
+    );
+    let producer =
+        source.accept_producer(config.producer_timeout.unwrap_or(MAX_PRODUCER_TIMEOUT))?;
+    println!(
+        "{}",
+        producer_handshake_message(
+            &config.service_name,
+            config.session_nonce,
+            std::process::id(),
+            producer.pid,
+            producer.pid_version,
+            producer.start_token,
+            &producer.build_version,
+            config.source_width,
+            config.source_height,
+        )
+    );
+    check_producer_build(
+        BRIDGE_BUILD_VERSION,
+        &producer.build_version,
+        config.version_policy,
+    )?;
+    let mut self_test_slots = vec![false; source.slot_count() as usize];
+    for _ in 0..source.slot_count() {
+        let frame = source
+            .next_frame(Duration::from_secs(60))?
+            .context("IOSurface producer did not send all startup self-tests")?;
+        let validation_status = frame.validation_status();
+        if validation_status != STATUS_PASS {
+            let frame_id = frame.frame_id();
+            let slot_index = frame.slot_index();
+            let generation = frame.generation();
+            let expected = frame.expected_bgra();
+            let actual = frame.actual_bgra();
+            frame.release(validation_status)?;
+            anyhow::bail!(
+                "IOSurface self-test {frame_id} slot={slot_index} generation={generation} failed validation status={validation_status} expected={expected:?} actual={actual:?}"
+            );
+        }
+        ensure!(
+            frame.is_self_test(),
+            "received a production frame before startup self-tests completed"
+        );
+        let slot_index = usize::try_from(frame.slot_index())
+            .context("IOSurface self-test slot index does not fit usize")?;
+        ensure!(
+            slot_index < self_test_slots.len(),
+            "IOSurface self-test slot {slot_index} is out of range"
+        );
+        ensure!(
+            !self_test_slots[slot_index],
+            "IOSurface slot {slot_index} was self-tested more than once"
+        );
+        self_test_slots[slot_index] = true;
+        frame.release(STATUS_PASS)?;
+    }
+    ensure!(
+        self_test_slots.iter().all(|passed| *passed),
+        "not every IOSurface slot passed startup self-test"
+    );
+
+    Ok(producer)
+}
+
+fn release_startup_barrier(source: &NativeSource) -> Result<()> {
+    let startup_barrier = source
+        .next_frame(Duration::from_secs(60))?
+        .context("IOSurface producer did not send the startup barrier")?;
+    let startup_barrier_status = startup_barrier.validation_status();
+    ensure!(
+        startup_barrier.is_startup_barrier(),
+        "received a production frame before the startup barrier"
+    );
+    startup_barrier.release(startup_barrier_status)?;
+    ensure!(
+        startup_barrier_status == STATUS_PASS,
+        "IOSurface startup barrier failed validation status={startup_barrier_status}"
+    );
+    println!("native_source producer startup barrier released");
+
+    Ok(())
+}
+
 fn producer_handshake_message(
     service_name: &str,
     session_nonce: u64,
@@ -710,28 +2206,85 @@ fn producer_handshake_message(
     producer_pid: u32,
     producer_pid_version: u32,
     producer_start_token: u64,
+    producer_build_version: &str,
     source_width: u32,
     source_height: u32,
 ) -> String {
     format!(
-        "native_source producer handshake accepted service={} nonce={} bridge_pid={} producer_pid={} producer_pidversion={} producer_start_token={} source={}x{}",
+        "native_source producer handshake accepted service={} nonce={} bridge_pid={} producer_pid={} producer_pidversion={} producer_start_token={} bridge_build={} producer_build={} source={}x{}",
         service_name,
         session_nonce,
         bridge_pid,
         producer_pid,
         producer_pid_version,
         producer_start_token,
+        BRIDGE_BUILD_VERSION,
+        producer_build_version,
         source_width,
         source_height
     )
 }
 
+fn check_producer_build(
+    bridge_build: &str,
+    producer_build: &str,
+    policy: VersionPolicy,
+) -> Result<()> {
+    let release = |build: &str| build.split('+').next();
+    let mismatch = if producer_build.is_empty() {
+        "producer did not report its build"
+    } else if release(producer_build) != release(bridge_build) {
+        "producer and bridge are from different releases"
+    } else if producer_build != bridge_build {
+        "producer and bridge are different builds of the same release"
+    } else {
+        return Ok(());
+    };
+    let message = format!(
+        "native_source version mismatch: {mismatch} bridge_build={bridge_build} producer_build={producer_build}; reinstall both from the same release"
+    );
+    match policy {
+        VersionPolicy::Warn => {
+            eprintln!("WARNING {message} policy=warn");
+            Ok(())
+        }
+        VersionPolicy::Refuse => bail!("{message} policy=refuse"),
+    }
+}
+
 fn env_u32(name: &str, default: u32) -> Result<u32> {
     env::var(name).map_or(Ok(default), |value| {
         value.parse().with_context(|| format!("invalid {name}"))
     })
 }
 
+/// Like `env_secs`, except that 0 means no timeout.
+fn env_timeout(name: &str, default: u64) -> Result<Option<Duration>> {
+    let seconds: u64 = env::var(name).map_or(Ok(default), |value| {
+        value.parse().with_context(|| format!("invalid {name}"))
+    })?;
+    Ok((seconds > 0).then(|| Duration::from_secs(seconds)))
+}
+
+fn env_secs(name: &str, default: u64) -> Result<Duration> {
+    let seconds = env::var(name).map_or(Ok(default), |value| {
+        value.parse().with_context(|| format!("invalid {name}"))
+    })?;
+    ensure!(seconds > 0, "{name} must be at least one second");
+    Ok(Duration::from_secs(seconds))
+}
+
+/// `ALVR_BRIDGE_ALPHA_KEY` as `rrggbb` hex, the form ALVR's chroma key
+/// settings show.
+fn parse_alpha_key(value: &str) -> Result<[u8; 3]> {
+    ensure!(
+        value.len() == 6 && value.bytes().all(|byte| byte.is_ascii_hexdigit()),
+        "ALVR_BRIDGE_ALPHA_KEY must be an rrggbb hex color"
+    );
+    let channel = |index: usize| u8::from_str_radix(&value[index..index + 2], 16);
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
 fn required_env_u64(name: &str) -> Result<u64> {
     let value = env::var(name).with_context(|| format!("{name} is required"))?;
     let parsed = value
@@ -744,20 +2297,30 @@ fn required_env_u64(name: &str) -> Result<u64> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        fake_wine_writer::FakeWineWriter,
+        mock_encoder::{MockEncoder, mock_config},
+    };
+    use std::{process, time::SystemTime};
 
     #[test]
     fn includes_one_frame_interval_in_video_span() {
         let first = Duration::from_secs(10);
         let last = first + Duration::from_millis(22);
-        let span = submitted_video_span(Some(first), Some(last), 90);
+        let span =
+            submitted_video_span(Some(first), Some(last), Duration::from_secs_f64(1.0 / 90.0));
 
         assert!((span.as_secs_f64() - (0.022 + 1.0 / 90.0)).abs() < 0.000_001);
     }
 
     #[test]
     fn reports_encoded_megabits_per_second() {
-        assert!((normalized_megabits_per_second(50_000, 72, 90) - 0.5).abs() < 0.001);
-        assert_eq!(normalized_megabits_per_second(50_000, 0, 90), 0.0);
+        let frame_interval = Duration::from_secs_f64(1.0 / 90.0);
+        assert!((normalized_megabits_per_second(50_000, 72, frame_interval) - 0.5).abs() < 0.001);
+        assert_eq!(
+            normalized_megabits_per_second(50_000, 0, frame_interval),
+            0.0
+        );
     }
 
     #[test]
@@ -777,10 +2340,171 @@ mod tests {
                 9002,
                 77,
                 1_721_278_802_123_456,
+                "21.0.0-dev12+0123456789ab",
                 3240,
                 1800,
             ),
-            "native_source producer handshake accepted service=com.alvr.fixture nonce=42 bridge_pid=4321 producer_pid=9002 producer_pidversion=77 producer_start_token=1721278802123456 source=3240x1800"
+            format!(
+                "native_source producer handshake accepted service=com.alvr.fixture nonce=42 bridge_pid=4321 producer_pid=9002 producer_pidversion=77 producer_start_token=1721278802123456 bridge_build={BRIDGE_BUILD_VERSION} producer_build=21.0.0-dev12+0123456789ab source=3240x1800"
+            )
+        );
+    }
+
+    #[test]
+    fn parses_an_alpha_key_color() {
+        assert_eq!(parse_alpha_key("00ff80").unwrap(), [0, 255, 128]);
+        assert_eq!(parse_alpha_key("FF0000").unwrap(), [255, 0, 0]);
+        for invalid in ["", "0f0", "00ff8", "00ff800", "#00ff8", "00gg00"] {
+            assert!(parse_alpha_key(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn accepts_a_producer_from_the_same_build() {
+        let build = "21.0.0-dev12+0123456789ab";
+
+        assert!(check_producer_build(build, build, VersionPolicy::Refuse).is_ok());
+    }
+
+    #[test]
+    fn refuses_mismatched_producer_builds_only_when_configured() {
+        let bridge = "21.0.0-dev12+0123456789ab";
+
+        for producer in ["", "21.0.0-dev11+0123456789ab", "21.0.0-dev12+ba9876543210"] {
+            assert!(check_producer_build(bridge, producer, VersionPolicy::Warn).is_ok());
+            assert!(check_producer_build(bridge, producer, VersionPolicy::Refuse).is_err());
+        }
+    }
+
+    /// A BGRA source of three 64x32 slots under a fresh service name.
+    fn test_config() -> NativeSourceConfig {
+        let session_nonce = SystemTime::UNIX_EPOCH.elapsed().unwrap().as_nanos() as u64 | 1;
+        NativeSourceConfig {
+            probe: mock_config(),
+            service_name: format!(
+                "com.alvr.native-loop-test.{}.{session_nonce}",
+                process::id()
+            ),
+            session_nonce,
+            source_width: 64,
+            source_height: 32,
+            source_format: SourceFormat::Bgra,
+            source_slots: 3,
+            version_policy: VersionPolicy::Refuse,
+            producer_timeout: Some(Duration::from_secs(10)),
+            frame_timeout: Some(Duration::from_secs(10)),
+            client_timeout: None,
+            pose_timeout: Duration::from_secs(10),
+            degrade_under_load: false,
+            adaptive_bitrate: false,
+            zero_copy: false,
+            reconnect: false,
+            latest_frame_wins: false,
+            max_encode_queue_frames: None,
+            peak_bitrate_percent: None,
+            peak_window: Duration::from_millis(100),
+            pacing: false,
+            encoder_stall: None,
+            system_events: false,
+            verify_checksums: true,
+            skip_duplicates: false,
+            standby: false,
+            preview: false,
+            sharpen: 0.0,
+            sharpen_mode: SharpenMode::Unsharp,
+            alpha_key: None,
+        }
+    }
+
+    #[test]
+    fn bounds_the_connection_timeouts() {
+        let mut config = test_config();
+        config.producer_timeout = None;
+        config.frame_timeout = None;
+        config.validate().unwrap();
+
+        config.producer_timeout = Some(MAX_PRODUCER_TIMEOUT + Duration::from_secs(1));
+        assert!(config.validate().is_err());
+        config.producer_timeout = Some(Duration::from_secs(86_400));
+        config.validate().unwrap();
+
+        config.client_timeout = Some(Duration::from_secs(30));
+        assert!(config.validate().is_err());
+        config.probe.connect_to_alvr = true;
+        config.validate().unwrap();
+    }
+
+    /// Runs the loop with a mock encoder against a fake Wine producer that
+    /// renders frames of the colours `frame_color` picks until the bridge
+    /// closes the session. Returns the summary and how many frames were sent.
+    fn run_against_simulated_producer(
+        configure: impl FnOnce(&mut NativeSourceConfig),
+        frame_color: impl Fn(u64) -> [u8; 4] + Send + 'static,
+    ) -> (NativeProbeSummary, u64) {
+        let mut config = test_config();
+        let session_nonce = config.session_nonce;
+        configure(&mut config);
+        let service_name = config.service_name.clone();
+        let producer = thread::spawn(move || -> Result<u64> {
+            let mut writer = FakeWineWriter::connect(
+                &service_name,
+                session_nonce,
+                (64, 32),
+                3,
+                Duration::from_secs(10),
+            )?;
+            writer.start()?;
+            let mut sent = 0;
+            while writer.send_frame(frame_color(sent))? {
+                sent += 1;
+            }
+            writer.drain()?;
+            Ok(sent)
+        });
+
+        let summary = run_native_source_probe_with_encoder(
+            config,
+            |_| {},
+            |config| {
+                Ok((
+                    MockEncoder::new(config.codec),
+                    MockEncoder::hardware_support(),
+                ))
+            },
+        )
+        .unwrap();
+        (summary, producer.join().unwrap().unwrap())
+    }
+
+    #[test]
+    fn runs_the_loop_against_a_simulated_wine_producer() {
+        let (summary, sent) =
+            run_against_simulated_producer(|_| {}, |sent| [sent as u8, 0x40, 0x80, 0xff]);
+
+        assert_eq!(summary.self_tests, 3);
+        assert_eq!(summary.submitted_frames, 10);
+        assert_eq!(summary.encoded_frames, 10);
+        assert_eq!(summary.received_frames, sent);
+        assert_eq!(summary.checksums_verified, sent);
+        assert_eq!(summary.checksum_mismatches, 0);
+        assert_eq!(summary.producer_gaps, 0);
+        assert_eq!(summary.rejected_messages, 0);
+    }
+
+    #[test]
+    fn skips_frames_that_repeat_the_last_one() {
+        let (summary, sent) = run_against_simulated_producer(
+            |config| config.skip_duplicates = true,
+            |sent| [(sent / 3) as u8, 0x40, 0x80, 0xff],
         );
+
+        // Only a repeat within the refresh interval is skipped, so on a slow
+        // machine some repeats are still encoded. Frames that arrive after
+        // the tenth closes the session are neither.
+        assert!(summary.duplicate_skips > 0);
+        assert_eq!(summary.submitted_frames, 10);
+        assert_eq!(summary.encoded_frames, 10);
+        assert!(summary.received_frames >= summary.submitted_frames + summary.duplicate_skips);
+        assert_eq!(summary.received_frames, sent);
     }
 }
@@ -17,7 +17,8 @@
 
 enum
 {
-    source_slot_count = 3,
+    min_source_slot_count = 2,
+    max_source_slot_count = 8,
     import_send_timeout_ms = 5000,
     release_send_timeout_ms = 200
 };
@@ -81,9 +82,11 @@ struct alvr_native_source_frame
     uint8_t actual_bgra[4];
     uint32_t reply_port;
     uint32_t validation_status;
+    uint32_t content_crc32;
+    uint32_t reserved;
 };
 
-_Static_assert(sizeof(struct alvr_native_source_frame) == 128,
+_Static_assert(sizeof(struct alvr_native_source_frame) == 136,
                "native source frame ABI changed");
 
 struct alvr_native_source
@@ -93,19 +96,25 @@ struct alvr_native_source
     uint64_t last_frame_id;
     uint64_t last_video_timestamp_ns;
     uint64_t last_pose_generation;
+    uint64_t rejected_messages;
     uint32_t width;
     uint32_t height;
+    uint32_t slot_count;
     uint32_t producer_pid;
     uint32_t producer_pidversion;
     uint64_t producer_start_token;
+    char bridge_build_version[ALVR_IOSURFACE_BUILD_VERSION_CAPACITY];
+    char producer_build_version[ALVR_IOSURFACE_BUILD_VERSION_CAPACITY];
     mach_port_t receive_port;
-    struct source_slot slots[source_slot_count];
+    struct source_slot slots[max_source_slot_count];
 };
 
 void alvr_native_source_destroy(void *opaque_source);
 uint32_t alvr_native_source_producer_pid(void *opaque_source);
 uint32_t alvr_native_source_producer_pidversion(void *opaque_source);
 uint64_t alvr_native_source_producer_start_token(void *opaque_source);
+const char *alvr_native_source_producer_build_version(void *opaque_source);
+uint64_t alvr_native_source_rejected_messages(void *opaque_source);
 
 static void set_error(char *buffer, size_t capacity, const char *message)
 {
@@ -158,8 +167,100 @@ static bool dictionary_set_size(CFMutableDictionaryRef dictionary,
     return true;
 }
 
-static IOSurfaceRef create_surface(uint32_t width, uint32_t height)
+static CFDictionaryRef create_plane_info(size_t width,
+                                         size_t height,
+                                         size_t bytes_per_element,
+                                         size_t offset,
+                                         size_t *plane_size)
 {
+    const size_t bytes_per_row = IOSurfaceAlignProperty(
+        kIOSurfacePlaneBytesPerRow, width * bytes_per_element);
+    CFMutableDictionaryRef plane = CFDictionaryCreateMutable(
+        kCFAllocatorDefault,
+        0,
+        &kCFTypeDictionaryKeyCallBacks,
+        &kCFTypeDictionaryValueCallBacks);
+
+    *plane_size = IOSurfaceAlignProperty(
+        kIOSurfacePlaneSize, bytes_per_row * height);
+    if (!plane) return NULL;
+    if (!dictionary_set_size(plane, kIOSurfacePlaneWidth, width) ||
+        !dictionary_set_size(plane, kIOSurfacePlaneHeight, height) ||
+        !dictionary_set_size(
+            plane, kIOSurfacePlaneBytesPerElement, bytes_per_element) ||
+        !dictionary_set_size(plane, kIOSurfacePlaneBytesPerRow, bytes_per_row) ||
+        !dictionary_set_size(plane, kIOSurfacePlaneOffset, offset) ||
+        !dictionary_set_size(plane, kIOSurfacePlaneSize, *plane_size))
+    {
+        CFRelease(plane);
+        return NULL;
+    }
+    return plane;
+}
+
+/* Video-range NV12 in two planes: full-size luma, then interleaved CbCr at
+ * half resolution. The slots start black (16, 128) so an unrendered frame
+ * encodes as black rather than green. */
+static IOSurfaceRef create_nv12_surface(uint32_t width, uint32_t height)
+{
+    size_t luma_size = 0;
+    size_t chroma_size = 0;
+    CFDictionaryRef planes[2] = {NULL, NULL};
+    CFArrayRef plane_info = NULL;
+    CFMutableDictionaryRef properties = NULL;
+    IOSurfaceRef surface = NULL;
+
+    planes[0] = create_plane_info(width, height, 1, 0, &luma_size);
+    if (planes[0])
+        planes[1] = create_plane_info(
+            width / 2, height / 2, 2, luma_size, &chroma_size);
+    if (planes[1])
+        plane_info = CFArrayCreate(
+            kCFAllocatorDefault, (const void **)planes, 2, &kCFTypeArrayCallBacks);
+    if (plane_info)
+        properties = CFDictionaryCreateMutable(
+            kCFAllocatorDefault,
+            0,
+            &kCFTypeDictionaryKeyCallBacks,
+            &kCFTypeDictionaryValueCallBacks);
+    if (properties &&
+        dictionary_set_size(properties, kIOSurfaceWidth, width) &&
+        dictionary_set_size(properties, kIOSurfaceHeight, height) &&
+        dictionary_set_size(
+            properties, kIOSurfaceAllocSize, luma_size + chroma_size) &&
+        dictionary_set_size(
+            properties, kIOSurfacePixelFormat, ALVR_IOSURFACE_PIXEL_FORMAT_NV12))
+    {
+        CFDictionarySetValue(properties, kIOSurfacePlaneInfo, plane_info);
+        surface = IOSurfaceCreate(properties);
+    }
+    if (properties) CFRelease(properties);
+    if (plane_info) CFRelease(plane_info);
+    if (planes[1]) CFRelease(planes[1]);
+    if (planes[0]) CFRelease(planes[0]);
+    if (!surface) return NULL;
+    if (IOSurfaceLock(surface, 0, NULL) != kIOReturnSuccess)
+    {
+        CFRelease(surface);
+        return NULL;
+    }
+    memset(IOSurfaceGetBaseAddressOfPlane(surface, 0),
+           16,
+           IOSurfaceGetBytesPerRowOfPlane(surface, 0) * height);
+    memset(IOSurfaceGetBaseAddressOfPlane(surface, 1),
+           128,
+           IOSurfaceGetBytesPerRowOfPlane(surface, 1) * (height / 2));
+    IOSurfaceUnlock(surface, 0, NULL);
+    return surface;
+}
+
+static IOSurfaceRef create_surface(uint32_t width,
+                                   uint32_t height,
+                                   uint32_t pixel_format)
+{
+    if (pixel_format == ALVR_IOSURFACE_PIXEL_FORMAT_NV12)
+        return create_nv12_surface(width, height);
+
     const size_t bytes_per_row = IOSurfaceAlignProperty(
         kIOSurfaceBytesPerRow, (size_t)width * 4);
     const size_t alloc_size = bytes_per_row * height;
@@ -178,7 +279,7 @@ static IOSurfaceRef create_surface(uint32_t width, uint32_t height)
         !dictionary_set_size(properties, kIOSurfaceAllocSize, alloc_size) ||
         !dictionary_set_size(properties,
                              kIOSurfacePixelFormat,
-                             ALVR_IOSURFACE_PIXEL_FORMAT_BGRA))
+                             pixel_format))
     {
         CFRelease(properties);
         return NULL;
@@ -301,6 +402,10 @@ static const char *import_request_rejection_reason(
     if (!sender_start_token) return "process-start-token";
     if (request->payload.protocol_version != ALVR_IOSURFACE_PROTOCOL_VERSION)
         return "protocol-version";
+    if (!memchr(request->payload.producer_build_version,
+                '\0',
+                sizeof(request->payload.producer_build_version)))
+        return "build-version";
     if (request->payload.session_nonce != source->session_nonce) return "session-nonce";
     if (request->payload.client_pid != (uint32_t)sender_pid) return "client-pid";
     if (source->producer_pid && (uint32_t)sender_pid != source->producer_pid)
@@ -311,6 +416,10 @@ static const char *import_request_rejection_reason(
     if (source->producer_start_token &&
         sender_start_token != source->producer_start_token)
         return "producer-start-token";
+    if (source->producer_pid &&
+        strcmp(request->payload.producer_build_version,
+               source->producer_build_version) != 0)
+        return "producer-build-version";
     return NULL;
 }
 
@@ -384,44 +493,75 @@ static kern_return_t send_release(
     return result;
 }
 
+/* NV12 samples report (Y, Cb, Cr, 255) in the four sample bytes, so the
+ * self-test and the consumer samples keep one shape for every format. The
+ * surface must already be locked. */
+static bool copy_pixel(IOSurfaceRef surface,
+                       size_t x,
+                       size_t y,
+                       uint8_t pixel[4])
+{
+    if (IOSurfaceGetPixelFormat(surface) == ALVR_IOSURFACE_PIXEL_FORMAT_NV12)
+    {
+        const uint8_t *luma = IOSurfaceGetBaseAddressOfPlane(surface, 0);
+        const uint8_t *chroma = IOSurfaceGetBaseAddressOfPlane(surface, 1);
+
+        if (!luma || !chroma) return false;
+        chroma += (y / 2) * IOSurfaceGetBytesPerRowOfPlane(surface, 1) +
+                  (x / 2) * 2;
+        pixel[0] = luma[y * IOSurfaceGetBytesPerRowOfPlane(surface, 0) + x];
+        pixel[1] = chroma[0];
+        pixel[2] = chroma[1];
+        pixel[3] = 255;
+        return true;
+    }
+
+    const uint8_t *base = IOSurfaceGetBaseAddress(surface);
+    if (!base) return false;
+    memcpy(pixel, base + y * IOSurfaceGetBytesPerRow(surface) + x * 4, 4);
+    return true;
+}
+
 static uint32_t read_sample(IOSurfaceRef surface,
                             uint32_t x,
                             uint32_t y,
                             uint8_t actual_bgra[4])
 {
-    const uint8_t *base;
-    size_t bytes_per_row;
+    bool copied;
 
     if (x >= IOSurfaceGetWidth(surface) || y >= IOSurfaceGetHeight(surface))
         return ALVR_IOSURFACE_PROBE_METADATA_MISMATCH;
     if (IOSurfaceLock(surface, kIOSurfaceLockReadOnly, NULL) != kIOReturnSuccess)
         return ALVR_IOSURFACE_PROBE_LOCK_FAILED;
-    base = IOSurfaceGetBaseAddress(surface);
-    bytes_per_row = IOSurfaceGetBytesPerRow(surface);
-    if (!base)
-    {
-        IOSurfaceUnlock(surface, kIOSurfaceLockReadOnly, NULL);
-        return ALVR_IOSURFACE_PROBE_LOCK_FAILED;
-    }
-    memcpy(actual_bgra, base + (size_t)y * bytes_per_row + (size_t)x * 4, 4);
+    copied = copy_pixel(surface, x, y, actual_bgra);
     IOSurfaceUnlock(surface, kIOSurfaceLockReadOnly, NULL);
-    return ALVR_IOSURFACE_PROBE_PASS;
+    return copied ? ALVR_IOSURFACE_PROBE_PASS : ALVR_IOSURFACE_PROBE_LOCK_FAILED;
+}
+
+static uint32_t pixel_brightness(const uint8_t *pixel, uint32_t pixel_format)
+{
+    uint32_t packed;
+
+    if (pixel_format == ALVR_IOSURFACE_PIXEL_FORMAT_NV12)
+        return (uint32_t)pixel[0] * 3;
+    if (pixel_format != ALVR_IOSURFACE_PIXEL_FORMAT_RGB10A2)
+        return (uint32_t)pixel[0] + pixel[1] + pixel[2];
+    memcpy(&packed, pixel, sizeof(packed));
+    return ((packed & 0x3ff) + ((packed >> 10) & 0x3ff) + ((packed >> 20) & 0x3ff)) >> 2;
 }
 
 static uint32_t find_nonblack_sample(IOSurfaceRef surface,
                                      uint8_t actual_bgra[4])
 {
-    const uint8_t *base;
+    const uint32_t pixel_format = IOSurfaceGetPixelFormat(surface);
     size_t width = IOSurfaceGetWidth(surface);
     size_t height = IOSurfaceGetHeight(surface);
-    size_t bytes_per_row;
+    uint8_t pixel[4];
 
     memset(actual_bgra, 0, 4);
     if (IOSurfaceLock(surface, kIOSurfaceLockReadOnly, NULL) != kIOReturnSuccess)
         return ALVR_IOSURFACE_PROBE_LOCK_FAILED;
-    base = IOSurfaceGetBaseAddress(surface);
-    bytes_per_row = IOSurfaceGetBytesPerRow(surface);
-    if (!base)
+    if (!copy_pixel(surface, 0, 0, pixel))
     {
         IOSurfaceUnlock(surface, kIOSurfaceLockReadOnly, NULL);
         return ALVR_IOSURFACE_PROBE_LOCK_FAILED;
@@ -431,8 +571,8 @@ static uint32_t find_nonblack_sample(IOSurfaceRef surface,
     {
         for (size_t x = 8; x < width; x += 16)
         {
-            const uint8_t *pixel = base + y * bytes_per_row + x * 4;
-            if ((uint32_t)pixel[0] + pixel[1] + pixel[2] >= 96)
+            copy_pixel(surface, x, y, pixel);
+            if (pixel_brightness(pixel, pixel_format) >= 96)
             {
                 memcpy(actual_bgra, pixel, 4);
                 IOSurfaceUnlock(surface, kIOSurfaceLockReadOnly, NULL);
@@ -444,8 +584,8 @@ static uint32_t find_nonblack_sample(IOSurfaceRef surface,
     {
         for (size_t x = 0; x < width; ++x)
         {
-            const uint8_t *pixel = base + y * bytes_per_row + x * 4;
-            if ((uint32_t)pixel[0] + pixel[1] + pixel[2] >= 96)
+            copy_pixel(surface, x, y, pixel);
+            if (pixel_brightness(pixel, pixel_format) >= 96)
             {
                 memcpy(actual_bgra, pixel, 4);
                 IOSurfaceUnlock(surface, kIOSurfaceLockReadOnly, NULL);
@@ -458,16 +598,29 @@ static uint32_t find_nonblack_sample(IOSurfaceRef surface,
 }
 
 void *alvr_native_source_create(const char *service_name,
+                                const char *bridge_build_version,
                                 uint64_t session_nonce,
                                 uint32_t width,
                                 uint32_t height,
+                                uint32_t pixel_format,
+                                uint32_t slot_count,
                                 char *error_buffer,
                                 size_t error_capacity)
 {
     struct alvr_native_source *source;
     kern_return_t result;
 
-    if (!service_name || !*service_name || !session_nonce || !width || !height)
+    if (!service_name || !*service_name || !bridge_build_version ||
+        !*bridge_build_version ||
+        strlen(bridge_build_version) >= ALVR_IOSURFACE_BUILD_VERSION_CAPACITY ||
+        !session_nonce || !width || !height ||
+        slot_count < min_source_slot_count ||
+        slot_count > max_source_slot_count ||
+        (pixel_format != ALVR_IOSURFACE_PIXEL_FORMAT_BGRA &&
+         pixel_format != ALVR_IOSURFACE_PIXEL_FORMAT_RGB10A2 &&
+         pixel_format != ALVR_IOSURFACE_PIXEL_FORMAT_NV12) ||
+        (pixel_format == ALVR_IOSURFACE_PIXEL_FORMAT_NV12 &&
+         ((width | height) & 1)))
     {
         set_error(error_buffer, error_capacity, "invalid native source configuration");
         return NULL;
@@ -482,15 +635,19 @@ void *alvr_native_source_create(const char *service_name,
     source->session_nonce = session_nonce;
     source->width = width;
     source->height = height;
+    source->slot_count = slot_count;
+    memcpy(source->bridge_build_version,
+           bridge_build_version,
+           strlen(bridge_build_version) + 1);
     if (!source->service_name)
     {
         set_error(error_buffer, error_capacity, "service name allocation failed");
         free(source);
         return NULL;
     }
-    for (uint32_t index = 0; index < source_slot_count; ++index)
+    for (uint32_t index = 0; index < slot_count; ++index)
     {
-        source->slots[index].surface = create_surface(width, height);
+        source->slots[index].surface = create_surface(width, height, pixel_format);
         if (!source->slots[index].surface)
         {
             set_error(error_buffer, error_capacity, "IOSurface pool creation failed");
@@ -525,7 +682,17 @@ int alvr_native_source_accept(void *opaque_source,
         set_error(error_buffer, error_capacity, "native source is null");
         return -1;
     }
-    for (uint32_t slot_index = 0; slot_index < source_slot_count; ++slot_index)
+    /* A relaunched producer is a new process with its own frame counters. */
+    source->producer_pid = 0;
+    source->producer_pidversion = 0;
+    source->producer_start_token = 0;
+    memset(source->producer_build_version, 0, sizeof(source->producer_build_version));
+    source->last_frame_id = 0;
+    source->last_video_timestamp_ns = 0;
+    source->last_pose_generation = 0;
+    for (uint32_t slot_index = 0; slot_index < source->slot_count; ++slot_index)
+        source->slots[slot_index].last_generation = 0;
+    for (uint32_t slot_index = 0; slot_index < source->slot_count; ++slot_index)
     {
         union receive_message received;
         mach_port_t surface_port = MACH_PORT_NULL;
@@ -586,6 +753,9 @@ int alvr_native_source_accept(void *opaque_source,
         source->producer_pid = (uint32_t)sender_pid;
         source->producer_pidversion = sender_pidversion;
         source->producer_start_token = sender_start_token;
+        memcpy(source->producer_build_version,
+               received.request.payload.producer_build_version,
+               sizeof(source->producer_build_version));
 
         surface_port = IOSurfaceCreateMachPort(
             source->slots[slot_index].surface);
@@ -614,11 +784,18 @@ int alvr_native_source_accept(void *opaque_source,
         offer.surface_id = source->slots[slot_index].surface_id;
         offer.width = source->width;
         offer.height = source->height;
-        offer.bytes_per_row = IOSurfaceGetBytesPerRow(
-            source->slots[slot_index].surface);
+        offer.bytes_per_row =
+            IOSurfaceGetPlaneCount(source->slots[slot_index].surface)
+                ? IOSurfaceGetBytesPerRowOfPlane(
+                      source->slots[slot_index].surface, 0)
+                : IOSurfaceGetBytesPerRow(source->slots[slot_index].surface);
         offer.pixel_format = IOSurfaceGetPixelFormat(
             source->slots[slot_index].surface);
+        offer.slot_count = source->slot_count;
         offer.producer_pid = getpid();
+        memcpy(offer.bridge_build_version,
+               source->bridge_build_version,
+               sizeof(offer.bridge_build_version));
         result = send_offer(
             received.request.header.msgh_remote_port,
             surface_port,
@@ -656,6 +833,20 @@ uint64_t alvr_native_source_producer_start_token(void *opaque_source)
     return source ? source->producer_start_token : 0;
 }
 
+const char *alvr_native_source_producer_build_version(void *opaque_source)
+{
+    struct alvr_native_source *source = opaque_source;
+
+    return source ? source->producer_build_version : "";
+}
+
+uint64_t alvr_native_source_rejected_messages(void *opaque_source)
+{
+    struct alvr_native_source *source = opaque_source;
+
+    return source ? source->rejected_messages : 0;
+}
+
 int alvr_native_source_next_frame(void *opaque_source,
                                   uint32_t timeout_ms,
                                   struct alvr_native_source_frame *output,
@@ -688,12 +879,16 @@ int alvr_native_source_next_frame(void *opaque_source,
             : timeout_ms;
         const char *rejection_reason = NULL;
 
-        if (!receive_timeout) return 1;
+        /* A zero timeout polls: it takes whatever is already queued,
+         * rejecting foreign messages as usual, and returns 1 once the port
+         * is empty. */
+        if (!receive_timeout && timeout_ms) return 1;
         result = receive_message(
             source->receive_port, &received, receive_timeout);
         if (result == MACH_RCV_TIMED_OUT || result == MACH_RCV_INTERRUPTED) return 1;
         if (result == MACH_RCV_TOO_LARGE)
         {
+            ++source->rejected_messages;
             fprintf(stderr,
                     "native_source rejected frame-ready "
                     "reason=message-too-large\n");
@@ -723,6 +918,7 @@ int alvr_native_source_next_frame(void *opaque_source,
             rejection_reason = "producer-pidversion";
         if (!rejection_reason) break;
 
+        ++source->rejected_messages;
         fprintf(stderr,
                 "native_source rejected frame-ready reason=%s sender_pid=%d "
                 "producer_pid=%u sender_pidversion=%u producer_pidversion=%u\n",
@@ -750,20 +946,22 @@ int alvr_native_source_next_frame(void *opaque_source,
     output->sample_x = frame->sample_x;
     output->sample_y = frame->sample_y;
     memcpy(output->expected_bgra, frame->expected_bgra, 4);
+    output->content_crc32 = frame->content_crc32;
     output->reply_port = received.frame.header.msgh_remote_port;
     received.frame.header.msgh_remote_port = MACH_PORT_NULL;
     output->validation_status = ALVR_IOSURFACE_PROBE_PASS;
 
     if (frame->protocol_version != ALVR_IOSURFACE_PROTOCOL_VERSION ||
         frame->session_nonce != source->session_nonce ||
-        frame->slot_index >= source_slot_count ||
+        frame->slot_index >= source->slot_count ||
         sender_pid <= 0 ||
         frame->producer_pid != (uint32_t)sender_pid ||
         frame->producer_pid != source->producer_pid ||
         (frame->flags & ~(ALVR_IOSURFACE_FRAME_SELF_TEST |
                           ALVR_IOSURFACE_FRAME_CONSUMER_SAMPLE |
                           ALVR_IOSURFACE_FRAME_FALLBACK_POSE |
-                          ALVR_IOSURFACE_FRAME_STARTUP_BARRIER)))
+                          ALVR_IOSURFACE_FRAME_STARTUP_BARRIER |
+                          ALVR_IOSURFACE_FRAME_CONTENT_CRC32)))
     {
         output->validation_status = ALVR_IOSURFACE_PROBE_PROTOCOL_MISMATCH;
         return 0;
@@ -823,14 +1021,265 @@ int alvr_native_source_next_frame(void *opaque_source,
     return 0;
 }
 
+typedef void (*row_visitor)(const uint8_t *bytes, size_t length, void *state);
+
+// Locks the slot for reading and hands each row of each plane to `visit`,
+// skipping the padding past the last pixel of a row.
+static int visit_slot_rows(struct alvr_native_source *source,
+                           uint32_t slot_index,
+                           row_visitor visit,
+                           void *state)
+{
+    IOSurfaceRef surface;
+    size_t plane_count;
+
+    if (!source || slot_index >= source->slot_count) return -1;
+    surface = source->slots[slot_index].surface;
+    if (IOSurfaceLock(surface, kIOSurfaceLockReadOnly, NULL) != kIOReturnSuccess)
+        return -2;
+    plane_count = IOSurfaceGetPlaneCount(surface);
+    for (size_t plane = 0; plane < (plane_count ? plane_count : 1); ++plane)
+    {
+        const uint8_t *base = plane_count
+            ? IOSurfaceGetBaseAddressOfPlane(surface, plane)
+            : IOSurfaceGetBaseAddress(surface);
+        const size_t row_bytes = plane_count
+            ? IOSurfaceGetWidthOfPlane(surface, plane) *
+                  IOSurfaceGetBytesPerElementOfPlane(surface, plane)
+            : IOSurfaceGetWidth(surface) * IOSurfaceGetBytesPerElement(surface);
+        const size_t stride = plane_count
+            ? IOSurfaceGetBytesPerRowOfPlane(surface, plane)
+            : IOSurfaceGetBytesPerRow(surface);
+        const size_t rows = plane_count
+            ? IOSurfaceGetHeightOfPlane(surface, plane)
+            : IOSurfaceGetHeight(surface);
+
+        if (!base)
+        {
+            IOSurfaceUnlock(surface, kIOSurfaceLockReadOnly, NULL);
+            return -2;
+        }
+        for (size_t row = 0; row < rows; ++row)
+            visit(base + row * stride, row_bytes, state);
+    }
+    IOSurfaceUnlock(surface, kIOSurfaceLockReadOnly, NULL);
+    return 0;
+}
+
+struct crc32_state
+{
+    uint32_t table[256];
+    uint32_t crc;
+};
+
+static void crc32_row(const uint8_t *bytes, size_t length, void *opaque_state)
+{
+    struct crc32_state *state = opaque_state;
+
+    for (size_t index = 0; index < length; ++index)
+        state->crc = (state->crc >> 8) ^ state->table[(state->crc ^ bytes[index]) & 0xff];
+}
+
+/* CRC-32 (the zlib polynomial) over each plane's visible bytes in plane order,
+ * skipping row padding, so the producer can hash its own copy of the pixels
+ * without knowing the surface's row pitch. */
+int alvr_native_source_content_crc32(void *opaque_source,
+                                     uint32_t slot_index,
+                                     uint32_t *crc32)
+{
+    struct crc32_state state = {.crc = UINT32_MAX};
+    int status;
+
+    if (!crc32) return -1;
+    for (uint32_t index = 0; index < 256; ++index)
+    {
+        uint32_t value = index;
+        for (int bit = 0; bit < 8; ++bit)
+            value = (value >> 1) ^ (UINT32_C(0xEDB88320) & (0u - (value & 1)));
+        state.table[index] = value;
+    }
+    status = visit_slot_rows(opaque_source, slot_index, crc32_row, &state);
+    if (status != 0) return status;
+    *crc32 = ~state.crc;
+    return 0;
+}
+
+/* Hands each row of the slot to `visit` while it is locked, so the bridge
+ * can hash the rows it samples without copying the slot. Rows it skips are
+ * never read. */
+int alvr_native_source_visit_rows(void *opaque_source,
+                                  uint32_t slot_index,
+                                  row_visitor visit,
+                                  void *state)
+{
+    if (!visit) return -1;
+    return visit_slot_rows(opaque_source, slot_index, visit, state);
+}
+
+static uint8_t clamp_channel(int32_t value)
+{
+    return value < 0 ? 0 : value > 255 ? 255 : (uint8_t)value;
+}
+
+/* NV12 goes through BT.709 limited-range coefficients scaled by 256, and
+ * 10-bit slots keep the top 8 bits of each channel. */
+static void thumbnail_pixel(const uint8_t pixel[4], uint32_t pixel_format, uint8_t out[4])
+{
+    if (pixel_format == ALVR_IOSURFACE_PIXEL_FORMAT_NV12)
+    {
+        const int32_t luma = 298 * ((int32_t)pixel[0] - 16) + 128;
+        const int32_t blue_difference = (int32_t)pixel[1] - 128;
+        const int32_t red_difference = (int32_t)pixel[2] - 128;
+
+        out[0] = clamp_channel((luma + 541 * blue_difference) >> 8);
+        out[1] = clamp_channel((luma - 55 * blue_difference - 136 * red_difference) >> 8);
+        out[2] = clamp_channel((luma + 459 * red_difference) >> 8);
+    }
+    else if (pixel_format == ALVR_IOSURFACE_PIXEL_FORMAT_RGB10A2)
+    {
+        uint32_t packed;
+
+        memcpy(&packed, pixel, sizeof(packed));
+        out[0] = (uint8_t)(packed >> 2);
+        out[1] = (uint8_t)(packed >> 12);
+        out[2] = (uint8_t)(packed >> 22);
+    }
+    else
+    {
+        memcpy(out, pixel, 3);
+    }
+    out[3] = 255;
+}
+
+/* Point-samples the slot down to a width x height BGRA image for the preview
+ * window. Reading one pixel per output pixel keeps it well under a
+ * millisecond for a window-sized image, whatever the slot size. */
+int alvr_native_source_thumbnail(void *opaque_source,
+                                 uint32_t slot_index,
+                                 uint8_t *bgra,
+                                 uint32_t width,
+                                 uint32_t height)
+{
+    struct alvr_native_source *source = opaque_source;
+    IOSurfaceRef surface;
+    uint32_t pixel_format;
+    size_t source_width;
+    size_t source_height;
+    uint8_t pixel[4];
+
+    if (!source || slot_index >= source->slot_count || !bgra || !width || !height) return -1;
+    surface = source->slots[slot_index].surface;
+    pixel_format = IOSurfaceGetPixelFormat(surface);
+    source_width = IOSurfaceGetWidth(surface);
+    source_height = IOSurfaceGetHeight(surface);
+    if (IOSurfaceLock(surface, kIOSurfaceLockReadOnly, NULL) != kIOReturnSuccess)
+        return -2;
+    for (uint32_t y = 0; y < height; ++y)
+    {
+        const size_t source_y = ((size_t)y * 2 + 1) * source_height / ((size_t)height * 2);
+
+        for (uint32_t x = 0; x < width; ++x)
+        {
+            const size_t source_x = ((size_t)x * 2 + 1) * source_width / ((size_t)width * 2);
+
+            if (!copy_pixel(surface, source_x, source_y, pixel))
+            {
+                IOSurfaceUnlock(surface, kIOSurfaceLockReadOnly, NULL);
+                return -2;
+            }
+            thumbnail_pixel(pixel, pixel_format, bgra + ((size_t)y * width + x) * 4);
+        }
+    }
+    IOSurfaceUnlock(surface, kIOSurfaceLockReadOnly, NULL);
+    return 0;
+}
+
 void *alvr_native_source_surface(void *opaque_source, uint32_t slot_index)
 {
     struct alvr_native_source *source = opaque_source;
 
-    if (!source || slot_index >= source_slot_count) return NULL;
+    if (!source || slot_index >= source->slot_count) return NULL;
     return source->slots[slot_index].surface;
 }
 
+/* A bridge-owned surface in the slots' size and format, for a frame that is
+ * encoded without conversion to be copied into so its slot can go back to the
+ * producer first. The caller releases it. */
+void *alvr_native_source_create_slot_copy(void *opaque_source)
+{
+    struct alvr_native_source *source = opaque_source;
+
+    if (!source || source->slot_count == 0) return NULL;
+    return create_surface(source->width,
+                          source->height,
+                          IOSurfaceGetPixelFormat(source->slots[0].surface));
+}
+
+/* Copies every plane of the slot into a surface from
+ * alvr_native_source_create_slot_copy, row by row where the pitches differ. */
+int alvr_native_source_copy_slot(void *opaque_source,
+                                 uint32_t slot_index,
+                                 void *opaque_destination)
+{
+    struct alvr_native_source *source = opaque_source;
+    IOSurfaceRef destination = opaque_destination;
+    IOSurfaceRef surface;
+    size_t plane_count;
+
+    if (!source || !destination || slot_index >= source->slot_count) return -1;
+    surface = source->slots[slot_index].surface;
+    plane_count = IOSurfaceGetPlaneCount(surface);
+    if (IOSurfaceGetPixelFormat(destination) != IOSurfaceGetPixelFormat(surface) ||
+        IOSurfaceGetWidth(destination) != IOSurfaceGetWidth(surface) ||
+        IOSurfaceGetHeight(destination) != IOSurfaceGetHeight(surface) ||
+        IOSurfaceGetPlaneCount(destination) != plane_count)
+        return -1;
+    if (IOSurfaceLock(surface, kIOSurfaceLockReadOnly, NULL) != kIOReturnSuccess)
+        return -2;
+    if (IOSurfaceLock(destination, 0, NULL) != kIOReturnSuccess)
+    {
+        IOSurfaceUnlock(surface, kIOSurfaceLockReadOnly, NULL);
+        return -2;
+    }
+    for (size_t plane = 0; plane < (plane_count ? plane_count : 1); ++plane)
+    {
+        const uint8_t *from = plane_count
+            ? IOSurfaceGetBaseAddressOfPlane(surface, plane)
+            : IOSurfaceGetBaseAddress(surface);
+        uint8_t *to = plane_count
+            ? IOSurfaceGetBaseAddressOfPlane(destination, plane)
+            : IOSurfaceGetBaseAddress(destination);
+        const size_t row_bytes = plane_count
+            ? IOSurfaceGetWidthOfPlane(surface, plane) *
+                  IOSurfaceGetBytesPerElementOfPlane(surface, plane)
+            : IOSurfaceGetWidth(surface) * IOSurfaceGetBytesPerElement(surface);
+        const size_t from_stride = plane_count
+            ? IOSurfaceGetBytesPerRowOfPlane(surface, plane)
+            : IOSurfaceGetBytesPerRow(surface);
+        const size_t to_stride = plane_count
+            ? IOSurfaceGetBytesPerRowOfPlane(destination, plane)
+            : IOSurfaceGetBytesPerRow(destination);
+        const size_t rows = plane_count
+            ? IOSurfaceGetHeightOfPlane(surface, plane)
+            : IOSurfaceGetHeight(surface);
+
+        if (!from || !to)
+        {
+            IOSurfaceUnlock(destination, 0, NULL);
+            IOSurfaceUnlock(surface, kIOSurfaceLockReadOnly, NULL);
+            return -2;
+        }
+        if (from_stride == to_stride)
+            memcpy(to, from, from_stride * rows);
+        else
+            for (size_t row = 0; row < rows; ++row)
+                memcpy(to + row * to_stride, from + row * from_stride, row_bytes);
+    }
+    IOSurfaceUnlock(destination, 0, NULL);
+    IOSurfaceUnlock(surface, kIOSurfaceLockReadOnly, NULL);
+    return 0;
+}
+
 int alvr_native_source_release(void *opaque_source,
                                struct alvr_native_source_frame *frame,
                                uint32_t status,
@@ -854,7 +1303,7 @@ int alvr_native_source_release(void *opaque_source,
     release.protocol_version = ALVR_IOSURFACE_PROTOCOL_VERSION;
     release.slot_index = frame->slot_index;
     release.generation = frame->generation;
-    release.status = frame->slot_index < source_slot_count
+    release.status = frame->slot_index < source->slot_count
         ? status
         : ALVR_IOSURFACE_PROBE_PROTOCOL_MISMATCH;
     release.surface_id = frame->surface_id;
@@ -875,7 +1324,7 @@ void alvr_native_source_destroy(void *opaque_source)
 
     if (!source) return;
     destroy_receive_port(&source->receive_port);
-    for (uint32_t index = 0; index < source_slot_count; ++index)
+    for (uint32_t index = 0; index < source->slot_count; ++index)
     {
         if (source->slots[index].surface)
             CFRelease(source->slots[index].surface);
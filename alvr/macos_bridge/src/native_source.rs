@@ -1,25 +1,48 @@
+use crate::{
+    SurfaceFormat,
+    content_hash::SampledContentHash,
+    surface::{SlotCopy, SlotCopyPool, SourcePixelBuffer},
+};
 use alvr_common::{
     Pose,
     glam::{Mat3, Quat, Vec3},
 };
-use anyhow::{Context, Result, anyhow, ensure};
+use anyhow::{Context, Result, anyhow, bail, ensure};
 use std::{
+    env,
     ffi::{CStr, CString, c_char, c_int, c_void},
+    ops::RangeInclusive,
     ptr::NonNull,
     time::Duration,
 };
 
 const ERROR_CAPACITY: usize = 512;
 const VISIBLE_COLOR_THRESHOLD: u32 = 96;
-pub const SOURCE_SLOT_COUNT: usize = 3;
+const PIXEL_FORMAT_BGRA: u32 = u32::from_be_bytes(*b"BGRA");
+const PIXEL_FORMAT_RGB10A2: u32 = u32::from_be_bytes(*b"l10r");
+const PIXEL_FORMAT_NV12: u32 = u32::from_be_bytes(*b"420v");
+/// Slots the bridge allocates unless `ALVR_IOSURFACE_SLOTS` says otherwise.
+/// Fewer slots hold less queued latency; more let a slow encode pass without
+/// the producer dropping frames.
+pub const DEFAULT_SOURCE_SLOTS: u32 = 3;
+pub const SOURCE_SLOT_RANGE: RangeInclusive<u32> = 2..=8;
 pub const FRAME_FLAG_SELF_TEST: u32 = 1;
 pub const FRAME_FLAG_CONSUMER_SAMPLE: u32 = 1 << 1;
 pub const FRAME_FLAG_FALLBACK_POSE: u32 = 1 << 2;
 pub const FRAME_FLAG_STARTUP_BARRIER: u32 = 1 << 3;
+pub const FRAME_FLAG_CONTENT_CRC32: u32 = 1 << 4;
 pub const STATUS_PASS: u32 = 0;
 pub const STATUS_COPY_FAILED: u32 = 8;
 pub const STATUS_SESSION_CLOSED: u32 = 9;
 pub const STATUS_FRAME_DROPPED: u32 = 10;
+pub const BRIDGE_BUILD_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    "+",
+    env!("ALVR_BRIDGE_BUILD_HASH")
+);
+const BUILD_VERSION_CAPACITY: usize = 32;
+
+const _: () = assert!(BRIDGE_BUILD_VERSION.len() < BUILD_VERSION_CAPACITY);
 
 #[repr(C)]
 #[derive(Clone, Copy, Default)]
@@ -41,16 +64,21 @@ struct RawSourceFrame {
     actual_bgra: [u8; 4],
     reply_port: u32,
     validation_status: u32,
+    content_crc32: u32,
+    reserved: u32,
 }
 
-const _: () = assert!(size_of::<RawSourceFrame>() == 128);
+const _: () = assert!(size_of::<RawSourceFrame>() == 136);
 
 unsafe extern "C" {
     fn alvr_native_source_create(
         service_name: *const c_char,
+        bridge_build_version: *const c_char,
         session_nonce: u64,
         width: u32,
         height: u32,
+        pixel_format: u32,
+        slot_count: u32,
         error_buffer: *mut c_char,
         error_capacity: usize,
     ) -> *mut c_void;
@@ -63,6 +91,8 @@ unsafe extern "C" {
     fn alvr_native_source_producer_pid(source: *mut c_void) -> u32;
     fn alvr_native_source_producer_pidversion(source: *mut c_void) -> u32;
     fn alvr_native_source_producer_start_token(source: *mut c_void) -> u64;
+    fn alvr_native_source_producer_build_version(source: *mut c_void) -> *const c_char;
+    fn alvr_native_source_rejected_messages(source: *mut c_void) -> u64;
     fn alvr_native_source_next_frame(
         source: *mut c_void,
         timeout_ms: u32,
@@ -71,6 +101,30 @@ unsafe extern "C" {
         error_capacity: usize,
     ) -> c_int;
     fn alvr_native_source_surface(source: *mut c_void, slot_index: u32) -> *mut c_void;
+    fn alvr_native_source_create_slot_copy(source: *mut c_void) -> *mut c_void;
+    fn alvr_native_source_copy_slot(
+        source: *mut c_void,
+        slot_index: u32,
+        destination: *mut c_void,
+    ) -> c_int;
+    fn alvr_native_source_content_crc32(
+        source: *mut c_void,
+        slot_index: u32,
+        crc32: *mut u32,
+    ) -> c_int;
+    fn alvr_native_source_visit_rows(
+        source: *mut c_void,
+        slot_index: u32,
+        visit: unsafe extern "C" fn(bytes: *const u8, length: usize, state: *mut c_void),
+        state: *mut c_void,
+    ) -> c_int;
+    fn alvr_native_source_thumbnail(
+        source: *mut c_void,
+        slot_index: u32,
+        bgra: *mut u8,
+        width: u32,
+        height: u32,
+    ) -> c_int;
     fn alvr_native_source_release(
         source: *mut c_void,
         frame: *mut RawSourceFrame,
@@ -81,16 +135,86 @@ unsafe extern "C" {
     fn alvr_native_source_destroy(source: *mut c_void);
 }
 
+#[link(name = "CoreFoundation", kind = "framework")]
+unsafe extern "C" {
+    fn CFRelease(cf: *const c_void);
+}
+
+/// Pixel layout the producer renders into. BGRA slots (RGB10A2 for 10-bit
+/// streams) go through the Metal pass or VideoToolbox's own conversion. NV12
+/// slots are already what the encoder takes, so they skip conversion and are
+/// only copied out of the slot before the encode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SourceFormat {
+    #[default]
+    Bgra,
+    Nv12,
+}
+
+impl SourceFormat {
+    pub fn from_env() -> Result<Self> {
+        match env::var("ALVR_IOSURFACE_SOURCE_FORMAT").as_deref() {
+            Err(env::VarError::NotPresent) | Ok("" | "bgra") => Ok(Self::Bgra),
+            Ok("nv12") => Ok(Self::Nv12),
+            _ => bail!("ALVR_IOSURFACE_SOURCE_FORMAT must be bgra or nv12"),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Bgra => "bgra",
+            Self::Nv12 => "nv12",
+        }
+    }
+
+    /// Bytes in one slot. RGB10A2 packs into the same four bytes as BGRA.
+    pub(crate) fn frame_bytes(self, width: u32, height: u32) -> u64 {
+        let pixels = u64::from(width) * u64::from(height);
+        match self {
+            Self::Bgra => pixels * 4,
+            Self::Nv12 => pixels * 3 / 2,
+        }
+    }
+}
+
 pub struct NativeSource {
     source: NonNull<c_void>,
     width: u32,
     height: u32,
+    pixel_format: u32,
+    slot_count: u32,
 }
 
 pub struct AuthenticatedProducer {
     pub pid: u32,
     pub pid_version: u32,
     pub start_token: u64,
+    pub build_version: String,
+}
+
+impl AuthenticatedProducer {
+    /// Whether the producer process still exists. A recycled pid is caught by
+    /// the pid version check when it sends a frame.
+    pub fn is_alive(&self) -> bool {
+        let Ok(pid) = libc::pid_t::try_from(self.pid) else {
+            return false;
+        };
+        unsafe { libc::kill(pid, 0) == 0 }
+        || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+}
+
+/// The producer's CRC-32 of a slot's pixels next to the bridge's own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentChecksum {
+    pub expected: u32,
+    pub actual: u32,
+}
+
+impl ContentChecksum {
+    pub fn matches(self) -> bool {
+        self.expected == self.actual
+    }
 }
 
 pub struct NativeSourceFrame<'a> {
@@ -100,7 +224,15 @@ pub struct NativeSourceFrame<'a> {
 }
 
 impl NativeSource {
-    pub fn new(service_name: &str, session_nonce: u64, width: u32, height: u32) -> Result<Self> {
+    pub fn new(
+        service_name: &str,
+        session_nonce: u64,
+        width: u32,
+        height: u32,
+        format: SurfaceFormat,
+        source_format: SourceFormat,
+        slot_count: u32,
+    ) -> Result<Self> {
         ensure!(
             session_nonce != 0,
             "IOSurface session nonce must be nonzero"
@@ -109,14 +241,39 @@ impl NativeSource {
             width > 0 && height > 0,
             "IOSurface source dimensions must be nonzero"
         );
+        ensure!(
+            SOURCE_SLOT_RANGE.contains(&slot_count),
+            "IOSurface slot count must be between {} and {}",
+            SOURCE_SLOT_RANGE.start(),
+            SOURCE_SLOT_RANGE.end()
+        );
+        let pixel_format = match source_format {
+            SourceFormat::Nv12 => {
+                ensure!(
+                    !format.is_ten_bit(),
+                    "NV12 IOSurface sources are 8-bit; use bgra for 10-bit streams"
+                );
+                ensure!(
+                    width.is_multiple_of(2) && height.is_multiple_of(2),
+                    "NV12 IOSurface sources need even dimensions"
+                );
+                PIXEL_FORMAT_NV12
+            }
+            SourceFormat::Bgra if format.is_ten_bit() => PIXEL_FORMAT_RGB10A2,
+            SourceFormat::Bgra => PIXEL_FORMAT_BGRA,
+        };
         let service_name = CString::new(service_name)?;
+        let bridge_build_version = CString::new(BRIDGE_BUILD_VERSION)?;
         let mut error = [0 as c_char; ERROR_CAPACITY];
         let source = unsafe {
             alvr_native_source_create(
                 service_name.as_ptr(),
+                bridge_build_version.as_ptr(),
                 session_nonce,
                 width,
                 height,
+                pixel_format,
+                slot_count,
                 error.as_mut_ptr(),
                 error.len(),
             )
@@ -126,6 +283,8 @@ impl NativeSource {
                 source,
                 width,
                 height,
+                pixel_format,
+                slot_count,
             })
             .ok_or_else(|| anyhow!(error_message(&error)))
     }
@@ -150,6 +309,13 @@ impl NativeSource {
             unsafe { alvr_native_source_producer_pidversion(self.source.as_ptr()) };
         let producer_start_token =
             unsafe { alvr_native_source_producer_start_token(self.source.as_ptr()) };
+        let producer_build_version = unsafe {
+            CStr::from_ptr(alvr_native_source_producer_build_version(
+                self.source.as_ptr(),
+            ))
+        }
+        .to_string_lossy()
+        .into_owned();
         ensure!(
             producer_pid != 0 && producer_pid_version != 0 && producer_start_token != 0,
             "IOSurface producer handshake returned incomplete authenticated identity"
@@ -158,6 +324,7 @@ impl NativeSource {
             pid: producer_pid,
             pid_version: producer_pid_version,
             start_token: producer_start_token,
+            build_version: producer_build_version,
         })
     }
 
@@ -187,19 +354,48 @@ impl NativeSource {
         }
     }
 
+    /// Frame-ready messages dropped before validation because their shape or
+    /// sender identity was wrong. They never reach `next_frame`'s caller.
+    pub fn rejected_messages(&self) -> u64 {
+        unsafe { alvr_native_source_rejected_messages(self.source.as_ptr()) }
+    }
+
     pub fn surface(&self, slot_index: u32) -> Result<NonNull<c_void>> {
         ensure!(
-            slot_index < SOURCE_SLOT_COUNT as u32,
+            slot_index < self.slot_count,
             "IOSurface slot index is out of range"
         );
         NonNull::new(unsafe { alvr_native_source_surface(self.source.as_ptr(), slot_index) })
             .context("native source returned a null IOSurface")
     }
 
+    /// `capacity` bridge-owned surfaces matching the slots, for frames that
+    /// are encoded without conversion to be copied into.
+    pub(crate) fn slot_copies(&self, capacity: usize) -> Result<SlotCopyPool> {
+        let buffers = (0..capacity)
+            .map(|_| {
+                let surface = NonNull::new(unsafe {
+                    alvr_native_source_create_slot_copy(self.source.as_ptr())
+                })
+                .context("failed to create an IOSurface slot copy")?;
+                // The pixel buffer keeps its own reference to the surface.
+                let buffer = SourcePixelBuffer::wrap(surface);
+                unsafe { CFRelease(surface.as_ptr().cast_const()) };
+                buffer
+            })
+            .collect::<Result<Vec<_>>>()?;
+        SlotCopyPool::new(buffers)
+    }
+
     pub fn width(&self) -> u32 {
         self.width
     }
 
+    /// Slots offered to the producer, one per import request.
+    pub fn slot_count(&self) -> u32 {
+        self.slot_count
+    }
+
     pub fn height(&self) -> u32 {
         self.height
     }
@@ -257,7 +453,7 @@ impl NativeSourceFrame<'_> {
     }
 
     pub fn is_visible_consumer_sample(&self) -> bool {
-        is_visible_consumer_sample(&self.raw)
+        is_visible_consumer_sample(&self.raw, self.source.pixel_format)
     }
 
     pub fn is_fallback_pose(&self) -> bool {
@@ -284,6 +480,101 @@ impl NativeSourceFrame<'_> {
         self.source.surface(self.raw.slot_index)
     }
 
+    /// Hashes the slot and pairs it with the producer's checksum, or returns
+    /// `None` when the producer sent none. It reads every pixel of the slot,
+    /// so callers only ask when verification is enabled.
+    pub fn content_checksum(&self) -> Result<Option<ContentChecksum>> {
+        if self.raw.flags & FRAME_FLAG_CONTENT_CRC32 == 0 {
+            return Ok(None);
+        }
+        let mut actual = 0;
+        let status = unsafe {
+            alvr_native_source_content_crc32(
+                self.source.source.as_ptr(),
+                self.raw.slot_index,
+                &mut actual,
+            )
+        };
+        ensure!(
+            status == 0,
+            "failed to checksum IOSurface slot {}",
+            self.raw.slot_index
+        );
+        Ok(Some(ContentChecksum {
+            expected: self.raw.content_crc32,
+            actual,
+        }))
+    }
+
+    /// A 64-bit hash of a sparse sample of the slot, for telling a repeated
+    /// frame from a new one. It reads about 1/64 of the pixels; see
+    /// `SampledContentHash`.
+    pub fn content_hash(&self) -> Result<u64> {
+        unsafe extern "C" fn visit_row(bytes: *const u8, length: usize, state: *mut c_void) {
+            let hash = unsafe { &mut *state.cast::<SampledContentHash>() };
+            hash.visit_row(unsafe { std::slice::from_raw_parts(bytes, length) });
+        }
+
+        let mut hash = SampledContentHash::new();
+        let status = unsafe {
+            alvr_native_source_visit_rows(
+                self.source.source.as_ptr(),
+                self.raw.slot_index,
+                visit_row,
+                (&raw mut hash).cast(),
+            )
+        };
+        ensure!(
+            status == 0,
+            "failed to hash IOSurface slot {}",
+            self.raw.slot_index
+        );
+        Ok(hash.finish())
+    }
+
+    /// Samples the slot down to a `width` x `height` BGRA image, whatever
+    /// the slot's own format.
+    pub fn thumbnail(&self, width: u32, height: u32, bgra: &mut [u8]) -> Result<()> {
+        ensure!(
+            bgra.len() == width as usize * height as usize * 4,
+            "thumbnail buffer holds {} bytes for {width}x{height}",
+            bgra.len()
+        );
+        let status = unsafe {
+            alvr_native_source_thumbnail(
+                self.source.source.as_ptr(),
+                self.raw.slot_index,
+                bgra.as_mut_ptr(),
+                width,
+                height,
+            )
+        };
+        ensure!(
+            status == 0,
+            "failed to sample IOSurface slot {}",
+            self.raw.slot_index
+        );
+        Ok(())
+    }
+
+    /// Copies the slot into `copy`, after which the slot can be released
+    /// while the copy is encoded.
+    pub(crate) fn copy_to(&self, copy: &SlotCopy) -> Result<()> {
+        let status = unsafe {
+            alvr_native_source_copy_slot(
+                self.source.source.as_ptr(),
+                self.raw.slot_index,
+                copy.buffer().surface().as_ptr(),
+            )
+        };
+        ensure!(
+            status == 0,
+            "failed to copy IOSurface slot {}",
+            self.raw.slot_index
+        );
+        Ok(())
+    }
+
     pub fn release(mut self, status: u32) -> Result<()> {
         self.release_inner(status)
     }
@@ -330,13 +621,25 @@ fn error_message(buffer: &[c_char]) -> String {
         .into_owned()
 }
 
-fn is_visible_consumer_sample(frame: &RawSourceFrame) -> bool {
+fn is_visible_consumer_sample(frame: &RawSourceFrame, pixel_format: u32) -> bool {
     frame.flags & FRAME_FLAG_CONSUMER_SAMPLE != 0
-        && frame.actual_bgra[..3]
+        && sample_brightness(frame.actual_bgra, pixel_format) >= VISIBLE_COLOR_THRESHOLD
+}
+
+/// NV12 samples arrive as (Y, Cb, Cr, 255), so luma alone stands in for the
+/// three color channels.
+fn sample_brightness(pixel: [u8; 4], pixel_format: u32) -> u32 {
+    match pixel_format {
+        PIXEL_FORMAT_NV12 => u32::from(pixel[0]) * 3,
+        PIXEL_FORMAT_RGB10A2 => {
+            let packed = u32::from_le_bytes(pixel);
+            ((packed & 0x3ff) + ((packed >> 10) & 0x3ff) + ((packed >> 20) & 0x3ff)) >> 2
+        }
+        _ => pixel[..3]
             .iter()
             .map(|component| u32::from(*component))
-            .sum::<u32>()
-            >= VISIBLE_COLOR_THRESHOLD
+            .sum(),
+    }
 }
 
 fn pose_from_matrix34(matrix: [[f32; 4]; 3]) -> Pose {
@@ -355,6 +658,25 @@ fn pose_from_matrix34(matrix: [[f32; 4]; 3]) -> Pose {
 mod tests {
     use super::*;
 
+    fn producer(pid: u32) -> AuthenticatedProducer {
+        AuthenticatedProducer {
+            pid,
+            pid_version: 1,
+            start_token: 1,
+            build_version: String::new(),
+        }
+    }
+
+    #[test]
+    fn notices_when_the_producer_process_exits() {
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let exited = producer(child.id());
+        child.wait().unwrap();
+
+        assert!(producer(std::process::id()).is_alive());
+        assert!(!exited.is_alive());
+    }
+
     #[test]
     fn converts_openvr_matrix_to_pose() {
         let pose = pose_from_matrix34([
@@ -375,18 +697,206 @@ mod tests {
             actual_bgra: [32, 32, 32, 0],
             ..Default::default()
         };
-        assert!(is_visible_consumer_sample(&visible));
+        assert!(is_visible_consumer_sample(&visible, PIXEL_FORMAT_BGRA));
 
         let alpha_only = RawSourceFrame {
             actual_bgra: [0, 0, 0, 255],
             ..visible
         };
-        assert!(!is_visible_consumer_sample(&alpha_only));
+        assert!(!is_visible_consumer_sample(&alpha_only, PIXEL_FORMAT_BGRA));
 
         let metadata_only = RawSourceFrame {
             flags: 0,
             ..visible
         };
-        assert!(!is_visible_consumer_sample(&metadata_only));
+        assert!(!is_visible_consumer_sample(
+            &metadata_only,
+            PIXEL_FORMAT_BGRA
+        ));
+    }
+
+    #[test]
+    fn classifies_packed_ten_bit_samples_by_rgb_channels() {
+        let red = RawSourceFrame {
+            flags: FRAME_FLAG_CONSUMER_SAMPLE,
+            actual_bgra: ((400u32 << 20) | (3 << 30)).to_le_bytes(),
+            ..Default::default()
+        };
+        assert!(is_visible_consumer_sample(&red, PIXEL_FORMAT_RGB10A2));
+        assert!(!is_visible_consumer_sample(&red, PIXEL_FORMAT_BGRA));
+
+        let alpha_only = RawSourceFrame {
+            actual_bgra: (3u32 << 30).to_le_bytes(),
+            ..red
+        };
+        assert!(!is_visible_consumer_sample(
+            &alpha_only,
+            PIXEL_FORMAT_RGB10A2
+        ));
+    }
+
+    #[test]
+    fn classifies_nv12_samples_by_luma() {
+        let bright = RawSourceFrame {
+            flags: FRAME_FLAG_CONSUMER_SAMPLE,
+            actual_bgra: [40, 128, 128, 255],
+            ..Default::default()
+        };
+        assert!(is_visible_consumer_sample(&bright, PIXEL_FORMAT_NV12));
+
+        let black = RawSourceFrame {
+            actual_bgra: [16, 128, 128, 255],
+            ..bright
+        };
+        assert!(!is_visible_consumer_sample(&black, PIXEL_FORMAT_NV12));
+        assert!(is_visible_consumer_sample(&black, PIXEL_FORMAT_BGRA));
+    }
+
+    #[test]
+    fn allocates_biplanar_nv12_slots_the_encoder_can_wrap() {
+        let nonce = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        let service = format!("com.alvr.nv12-test.{}.{}", std::process::id(), nonce);
+        let source = NativeSource::new(
+            &service,
+            nonce,
+            64,
+            32,
+            SurfaceFormat::Nv12,
+            SourceFormat::Nv12,
+            DEFAULT_SOURCE_SLOTS,
+        )
+        .unwrap();
+
+        let pixel_buffer =
+            crate::surface::SourcePixelBuffer::wrap(source.surface(0).unwrap()).unwrap();
+        assert_eq!((pixel_buffer.width(), pixel_buffer.height()), (64, 32));
+        assert!(
+            NativeSource::new(
+                &service,
+                nonce,
+                64,
+                32,
+                SurfaceFormat::P010,
+                SourceFormat::Nv12,
+                DEFAULT_SOURCE_SLOTS
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn slot_copies_match_the_slots_and_return_to_their_pool() {
+        let nonce = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        let service = format!("com.alvr.copy-test.{}.{}", std::process::id(), nonce);
+        let source = NativeSource::new(
+            &service,
+            nonce,
+            64,
+            32,
+            SurfaceFormat::Nv12,
+            SourceFormat::Nv12,
+            DEFAULT_SOURCE_SLOTS,
+        )
+        .unwrap();
+
+        let copies = source.slot_copies(2).unwrap();
+        let first = copies.try_acquire().unwrap();
+        let second = copies.try_acquire().unwrap();
+        assert!(copies.try_acquire().is_none());
+        assert_eq!((first.buffer().width(), first.buffer().height()), (64, 32));
+        assert_ne!(first.buffer().surface_id(), second.buffer().surface_id());
+        let surface_id = first.buffer().surface_id();
+        drop(first);
+        assert_eq!(
+            copies.try_acquire().unwrap().buffer().surface_id(),
+            surface_id
+        );
+    }
+
+    #[test]
+    fn checksums_each_plane_without_row_padding() {
+        let nonce = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        let service = format!("com.alvr.crc-test.{}.{}", std::process::id(), nonce);
+        let source = NativeSource::new(
+            &service,
+            nonce,
+            64,
+            32,
+            SurfaceFormat::Nv12,
+            SourceFormat::Nv12,
+            DEFAULT_SOURCE_SLOTS,
+        )
+        .unwrap();
+        let frame = |flags, content_crc32| NativeSourceFrame {
+            source: &source,
+            raw: RawSourceFrame {
+                flags,
+                content_crc32,
+                slot_index: 1,
+                ..Default::default()
+            },
+            released: true,
+        };
+
+        assert_eq!(frame(0, 0).content_checksum().unwrap(), None);
+        // zlib's crc32 of 64x32 luma bytes of 16 followed by 64x16 of 128.
+        let checksum = frame(FRAME_FLAG_CONTENT_CRC32, 0x1767_c359)
+            .content_checksum()
+            .unwrap()
+            .unwrap();
+        assert!(checksum.matches(), "{checksum:x?}");
+        assert!(
+            !frame(FRAME_FLAG_CONTENT_CRC32, 0)
+                .content_checksum()
+                .unwrap()
+                .unwrap()
+                .matches()
+        );
+    }
+
+    #[test]
+    fn allocates_the_configured_number_of_slots() {
+        let nonce = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        let service = format!("com.alvr.slots-test.{}.{}", std::process::id(), nonce);
+        let source = NativeSource::new(
+            &service,
+            nonce,
+            64,
+            32,
+            SurfaceFormat::Nv12,
+            SourceFormat::Bgra,
+            *SOURCE_SLOT_RANGE.end(),
+        )
+        .unwrap();
+
+        assert_eq!(source.slot_count(), 8);
+        assert!(source.surface(7).is_ok());
+        assert!(source.surface(8).is_err());
+        for slot_count in [1, 9] {
+            assert!(
+                NativeSource::new(
+                    &format!("{service}.{slot_count}"),
+                    nonce,
+                    64,
+                    32,
+                    SurfaceFormat::Nv12,
+                    SourceFormat::Bgra,
+                    slot_count
+                )
+                .is_err()
+            );
+        }
     }
 }
@@ -0,0 +1,98 @@
+use crate::EncodedFrame;
+use std::sync::{Mutex, PoisonError};
+
+/// Enough for the frames in flight between VideoToolbox and the sink, plus
+/// a keyframe's parameter sets.
+const KEPT_BUFFERS: usize = 8;
+
+/// Buffers for encoded frames, shared by every encoder session. At 72 to
+/// 120 Hz a fresh `Vec` per frame, and another for a keyframe's parameter
+/// sets, keeps the allocator busy on the hot path, so each frame's Annex B
+/// data is written into a buffer a finished frame gave back. A frame ALVR
+/// transports hands its buffer to server core for good; frames that are
+/// dropped, only recorded, or rewrapped with a timing SEI give theirs back.
+pub(crate) static OUTPUT_BUFFERS: BufferPool = BufferPool::new();
+
+pub(crate) struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+}
+
+impl BufferPool {
+    const fn new() -> Self {
+        Self {
+            buffers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// An empty buffer with room for `capacity` bytes: the smallest kept one
+    /// that fits, otherwise the largest, grown once to fit.
+    pub fn take(&self, capacity: usize) -> Vec<u8> {
+        let mut buffers = self.buffers.lock().unwrap_or_else(PoisonError::into_inner);
+        let fitting = buffers
+            .iter()
+            .enumerate()
+            .filter(|(_, buffer)| buffer.capacity() >= capacity)
+            .min_by_key(|(_, buffer)| buffer.capacity())
+            .map(|(index, _)| index);
+        let mut buffer = match fitting {
+            Some(index) => buffers.swap_remove(index),
+            None => buffers
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, buffer)| buffer.capacity())
+                .map(|(index, _)| index)
+                .map(|index| buffers.swap_remove(index))
+                .unwrap_or_default(),
+        };
+        drop(buffers);
+        buffer.reserve(capacity);
+        buffer
+    }
+
+    pub fn recycle(&self, mut buffer: Vec<u8>) {
+        if buffer.capacity() == 0 {
+            return;
+        }
+        buffer.clear();
+        let mut buffers = self.buffers.lock().unwrap_or_else(PoisonError::into_inner);
+        if buffers.len() < KEPT_BUFFERS {
+            buffers.push(buffer);
+        }
+    }
+
+    /// Gives back both of a frame's buffers.
+    pub fn recycle_frame(&self, frame: EncodedFrame) {
+        self.recycle(frame.nal_data);
+        if let Some(config) = frame.decoder_config_nals {
+            self.recycle(config);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hands_back_the_smallest_buffer_that_fits() {
+        let pool = BufferPool::new();
+        pool.recycle(Vec::with_capacity(64));
+        pool.recycle(Vec::with_capacity(4096));
+        let mut used = Vec::with_capacity(512);
+        used.extend_from_slice(&[1, 2, 3]);
+        pool.recycle(used);
+
+        let buffer = pool.take(100);
+        assert!(buffer.is_empty());
+        assert!((512..4096).contains(&buffer.capacity()));
+        let grown = pool.take(8192);
+        assert!(grown.capacity() >= 8192);
+        assert_eq!(pool.take(1).capacity(), 64);
+        assert!(pool.buffers.lock().unwrap().is_empty());
+
+        for _ in 0..(KEPT_BUFFERS + 2) {
+            pool.recycle(Vec::with_capacity(16));
+        }
+        assert_eq!(pool.buffers.lock().unwrap().len(), KEPT_BUFFERS);
+    }
+}
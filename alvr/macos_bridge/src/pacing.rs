@@ -0,0 +1,138 @@
+use std::time::{Duration, Instant};
+
+/// A frame this close before its vsync slot is on time; Wine's present
+/// jitter stays well inside a quarter of an interval.
+const ON_TIME_DIVISOR: u32 = 4;
+/// A gap this long is a stalled producer rather than a slow one, so the
+/// vsync phase starts over instead of counting the gap as repeats.
+const STALL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Pace {
+    Submit,
+    /// The coming vsync already has a frame. Hold this one until the given
+    /// time, and drop it if a newer frame arrives first.
+    Hold(Instant),
+}
+
+/// Spaces encoder submissions one client vsync apart. The phase follows the
+/// producer: each submitted frame claims the vsync slot it lands in, and the
+/// next frame is due one interval after that slot. A producer running faster
+/// than the client has its surplus frames held and superseded, so each vsync
+/// gets the newest one. A slower producer leaves slots empty, which the
+/// client fills by showing its last frame again; those are counted as
+/// repeats.
+#[derive(Debug)]
+pub(crate) struct FramePacer {
+    refresh_hz: f32,
+    interval: Duration,
+    next_slot: Option<Instant>,
+}
+
+impl FramePacer {
+    pub fn new(refresh_hz: f32) -> Self {
+        Self {
+            refresh_hz,
+            interval: Duration::from_secs_f64(1.0 / f64::from(refresh_hz)),
+            next_slot: None,
+        }
+    }
+
+    /// Follows a new client refresh rate, returning whether it changed.
+    /// Rates that cannot be a display refresh are ignored.
+    pub fn set_refresh(&mut self, refresh_hz: f32) -> bool {
+        if !refresh_hz.is_finite() || refresh_hz < 1.0 || refresh_hz == self.refresh_hz {
+            return false;
+        }
+        *self = Self::new(refresh_hz);
+        true
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn pace(&self, now: Instant) -> Pace {
+        match self.next_slot {
+            Some(slot) if now + self.on_time() < slot => Pace::Hold(slot - self.on_time()),
+            _ => Pace::Submit,
+        }
+    }
+
+    /// Records a frame submitted at `now`, returning how many vsyncs passed
+    /// without one.
+    pub fn submitted(&mut self, now: Instant) -> u64 {
+        let (slot, repeats) = match self.next_slot {
+            Some(slot) if now > slot && now - slot < STALL => {
+                let repeats = ((now - slot).as_nanos() / self.interval.as_nanos()) as u32;
+                (slot + self.interval * repeats, u64::from(repeats))
+            }
+            Some(slot) if now <= slot => (slot, 0),
+            _ => (now, 0),
+        };
+        self.next_slot = Some(slot + self.interval);
+        repeats
+    }
+
+    /// Forgets the vsync phase, for a producer that starts over.
+    pub fn reset(&mut self) {
+        self.next_slot = None;
+    }
+
+    fn on_time(&self) -> Duration {
+        self.interval / ON_TIME_DIVISOR
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holds_a_second_frame_for_the_same_vsync() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(90.0);
+        let interval = pacer.interval();
+
+        assert_eq!(pacer.pace(start), Pace::Submit);
+        assert_eq!(pacer.submitted(start), 0);
+        let early = start + interval / 2;
+        assert_eq!(
+            pacer.pace(early),
+            Pace::Hold(start + interval - interval / 4)
+        );
+        // Jitter inside a quarter interval still makes the slot.
+        assert_eq!(pacer.pace(start + interval - interval / 5), Pace::Submit);
+    }
+
+    #[test]
+    fn counts_the_vsyncs_a_slow_producer_leaves_empty() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(120.0);
+        let producer_interval = pacer.interval() * 2;
+
+        let repeats: u64 = (0..6)
+            .map(|frame| {
+                let now = start + producer_interval * frame;
+                assert_eq!(pacer.pace(now), Pace::Submit);
+                pacer.submitted(now)
+            })
+            .sum();
+
+        // 60 Hz into 120 Hz: every frame after the first skips one vsync.
+        assert_eq!(repeats, 5);
+    }
+
+    #[test]
+    fn starts_over_after_a_stall_or_a_new_refresh() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(72.0);
+        pacer.submitted(start);
+
+        assert_eq!(pacer.submitted(start + Duration::from_secs(2)), 0);
+        assert!(!pacer.set_refresh(72.0));
+        assert!(!pacer.set_refresh(f32::NAN));
+        assert!(pacer.set_refresh(120.0));
+        assert_eq!(pacer.pace(start + Duration::from_secs(2)), Pace::Submit);
+    }
+}
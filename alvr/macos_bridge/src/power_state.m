@@ -0,0 +1,48 @@
+#import <Foundation/Foundation.h>
+
+#include <stdatomic.h>
+#include <stdint.h>
+
+// Low byte: NSProcessInfoThermalState. Bit 8: Low Power Mode.
+#define POWER_STATE_LOW_POWER (1u << 8)
+
+static _Atomic uint32_t power_state;
+static id thermal_observer;
+static id power_observer;
+
+static uint32_t read_power_state(void) {
+    NSProcessInfo *info = NSProcessInfo.processInfo;
+    uint32_t state = (uint32_t)info.thermalState & 0xff;
+    if (info.lowPowerModeEnabled) {
+        state |= POWER_STATE_LOW_POWER;
+    }
+    return state;
+}
+
+// Registers for thermal and power notifications once per process. The
+// system posts them from its own queue, so no run loop is needed; each one
+// re-reads both values so a missed notification heals on the next.
+uint32_t alvr_power_state_subscribe(void) {
+    static dispatch_once_t once;
+    dispatch_once(&once, ^{
+        atomic_store(&power_state, read_power_state());
+        void (^update)(NSNotification *) = ^(NSNotification *notification) {
+            (void)notification;
+            atomic_store(&power_state, read_power_state());
+        };
+        NSNotificationCenter *center = NSNotificationCenter.defaultCenter;
+        thermal_observer = [center addObserverForName:NSProcessInfoThermalStateDidChangeNotification
+                                               object:nil
+                                                queue:nil
+                                           usingBlock:update];
+        power_observer = [center addObserverForName:NSProcessInfoPowerStateDidChangeNotification
+                                             object:nil
+                                              queue:nil
+                                         usingBlock:update];
+    });
+    return atomic_load(&power_state);
+}
+
+uint32_t alvr_power_state(void) {
+    return atomic_load(&power_state);
+}
@@ -0,0 +1,115 @@
+use std::fmt;
+
+unsafe extern "C" {
+    fn alvr_power_state_subscribe() -> u32;
+    fn alvr_power_state() -> u32;
+}
+
+const THERMAL_MASK: u32 = 0xff;
+const LOW_POWER: u32 = 1 << 8;
+
+/// `NSProcessInfoThermalState`. From `Serious` on, macOS throttles the CPU
+/// and media engine, so encode times grow until frames start to drop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum ThermalState {
+    Nominal,
+    Fair,
+    Serious,
+    Critical,
+}
+
+impl ThermalState {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Nominal => "nominal",
+            Self::Fair => "fair",
+            Self::Serious => "serious",
+            Self::Critical => "critical",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PowerState {
+    pub thermal: ThermalState,
+    pub low_power: bool,
+}
+
+impl PowerState {
+    fn from_raw(raw: u32) -> Self {
+        Self {
+            thermal: match raw & THERMAL_MASK {
+                0 => ThermalState::Nominal,
+                1 => ThermalState::Fair,
+                2 => ThermalState::Serious,
+                _ => ThermalState::Critical,
+            },
+            low_power: raw & LOW_POWER != 0,
+        }
+    }
+
+    /// The degradation rung the bridge may not climb above in this state.
+    /// Critical asks for the bottom rung, whatever the ladder's length.
+    pub(crate) fn quality_floor(self) -> usize {
+        match self.thermal {
+            ThermalState::Nominal => usize::from(self.low_power),
+            ThermalState::Fair => 1,
+            ThermalState::Serious => 2,
+            ThermalState::Critical => usize::MAX,
+        }
+    }
+}
+
+impl fmt::Display for PowerState {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "thermal={} low_power={}",
+            self.thermal.name(),
+            self.low_power
+        )
+    }
+}
+
+/// Follows macOS thermal-state and Low Power Mode notifications. The
+/// observers live for the whole process and only store the latest state, so
+/// polling it from the frame loop costs one atomic load.
+pub(crate) struct PowerMonitor {
+    last: PowerState,
+}
+
+impl PowerMonitor {
+    pub fn new() -> Self {
+        Self {
+            last: PowerState::from_raw(unsafe { alvr_power_state_subscribe() }),
+        }
+    }
+
+    pub fn state(&self) -> PowerState {
+        self.last
+    }
+
+    /// The previous state, when it changed since the last poll.
+    pub fn poll(&mut self) -> Option<PowerState> {
+        let state = PowerState::from_raw(unsafe { alvr_power_state() });
+        (state != self.last).then(|| std::mem::replace(&mut self.last, state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_thermal_pressure_to_a_quality_floor() {
+        let nominal = PowerState::from_raw(0);
+        assert_eq!(nominal.quality_floor(), 0);
+        assert_eq!(PowerState::from_raw(LOW_POWER).quality_floor(), 1);
+        assert_eq!(PowerState::from_raw(2).quality_floor(), 2);
+        assert_eq!(PowerState::from_raw(3).quality_floor(), usize::MAX);
+        assert_eq!(
+            PowerState::from_raw(2 | LOW_POWER).to_string(),
+            "thermal=serious low_power=true"
+        );
+    }
+}
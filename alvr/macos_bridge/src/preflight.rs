@@ -0,0 +1,331 @@
+use crate::{
+    EncoderBackend, HardwareEncoderSupport, SurfaceFormat,
+    encoder::{SOFTWARE_ENCODER_SUPPORT, codec_name},
+    encoder_hardware_support,
+    native_source::SourceFormat,
+};
+use alvr_session::CodecType;
+use anyhow::{Result, bail, ensure};
+use std::{ffi::c_void, mem};
+
+const MAX_HEVC_SIZE: (u32, u32) = (8192, 4320);
+const MAX_H264_SIZE: (u32, u32) = (4096, 4096);
+const MAX_LUMA_SAMPLE_RATE: u64 = 4_278_190_080;
+const MAX_SOURCE_DIMENSION: u32 = 16384;
+const MAX_MEMORY_SHARE_PERCENT: u64 = 25;
+const MEBIBYTE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PreflightInput {
+    pub codec: CodecType,
+    pub format: SurfaceFormat,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub buffer_count: usize,
+    pub source: Option<PreflightSource>,
+}
+
+/// The producer-facing IOSurface slots, which are allocated on top of the
+/// encoder pool.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PreflightSource {
+    pub width: u32,
+    pub height: u32,
+    pub format: SourceFormat,
+    pub slots: u32,
+}
+
+impl PreflightInput {
+    fn surface_bytes(&self) -> u64 {
+        let surface = u64::from(self.width) * u64::from(self.height) * 3 / 2
+            * self.format.bytes_per_sample() as u64;
+        let pool = surface.saturating_mul(self.buffer_count as u64);
+        let source = self.source.map_or(0, |source| {
+            source.format.frame_bytes(source.width, source.height) * u64::from(source.slots)
+        });
+        pool.saturating_add(source)
+    }
+}
+
+pub(crate) fn run_preflight(input: PreflightInput, backend: EncoderBackend) -> Result<()> {
+    let encoder = match backend {
+        EncoderBackend::Software => Ok(SOFTWARE_ENCODER_SUPPORT),
+        _ => encoder_hardware_support(input.codec).map_err(|error| format!("{error:#}")),
+    };
+    let encoder = match encoder {
+        Err(error) if backend.falls_back(input.codec) => {
+            eprintln!(
+                "WARNING {error}; this Mac cannot hardware-encode {}, so the software encoder will take over",
+                codec_name(input.codec)
+            );
+            Ok(SOFTWARE_ENCODER_SUPPORT)
+        }
+        encoder => encoder,
+    };
+    let problems = preflight_problems(input, encoder, physical_memory_bytes());
+    if !problems.is_empty() {
+        bail!("preflight failed:\n  - {}", problems.join("\n  - "));
+    }
+    println!(
+        "preflight passed encode={}x{}@{} surfaces_mib={}",
+        input.width,
+        input.height,
+        input.fps,
+        input.surface_bytes().div_ceil(MEBIBYTE),
+    );
+    Ok(())
+}
+
+/// Checks a size ALVR negotiated after startup against the limits preflight
+/// applied to the configured one, before the pool and encoder are rebuilt.
+pub(crate) fn check_renegotiated_size(input: PreflightInput) -> Result<()> {
+    let problems = encoder_limit_problems(&input);
+    ensure!(
+        problems.is_empty(),
+        "ALVR renegotiated the stream at {}x{}, which this bridge cannot encode: {}",
+        input.width,
+        input.height,
+        problems.join("; ")
+    );
+    Ok(())
+}
+
+fn preflight_problems(
+    input: PreflightInput,
+    encoder: std::result::Result<HardwareEncoderSupport, String>,
+    physical_memory: Option<u64>,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Err(error) = encoder {
+        problems.push(format!(
+            "{error}; this Mac cannot hardware-encode {}, so choose another ALVR_BRIDGE_CODEC",
+            codec_name(input.codec)
+        ));
+    }
+    problems.extend(resource_problems(input, physical_memory));
+    problems
+}
+
+/// The preflight problems other than encoder availability, for callers that
+/// check the encoder themselves.
+pub(crate) fn configuration_problems(input: PreflightInput) -> Vec<String> {
+    resource_problems(input, physical_memory_bytes())
+}
+
+fn resource_problems(input: PreflightInput, physical_memory: Option<u64>) -> Vec<String> {
+    let mut problems = encoder_limit_problems(&input);
+    if let Some(PreflightSource { width, height, .. }) = input.source
+        && (width > MAX_SOURCE_DIMENSION || height > MAX_SOURCE_DIMENSION)
+    {
+        problems.push(format!(
+            "source {width}x{height} exceeds the {MAX_SOURCE_DIMENSION}px Metal texture limit; lower ALVR_IOSURFACE_SOURCE_WIDTH/ALVR_IOSURFACE_SOURCE_HEIGHT or the SteamVR render scale"
+        ));
+    }
+    if let Some(physical_memory) = physical_memory {
+        let surface_bytes = input.surface_bytes();
+        let budget = physical_memory / 100 * MAX_MEMORY_SHARE_PERCENT;
+        if surface_bytes > budget {
+            problems.push(format!(
+                "{} {} buffers and {} source slots need {} MiB, more than {MAX_MEMORY_SHARE_PERCENT}% of this Mac's {} MiB; lower ALVR_BRIDGE_BUFFER_COUNT, ALVR_IOSURFACE_SLOTS, or the resolution",
+                input.buffer_count,
+                input.format.name(),
+                input.source.map_or(0, |source| source.slots),
+                surface_bytes.div_ceil(MEBIBYTE),
+                physical_memory / MEBIBYTE
+            ));
+        }
+    }
+    problems
+}
+
+fn encoder_limit_problems(input: &PreflightInput) -> Vec<String> {
+    let mut problems = Vec::new();
+    let name = codec_name(input.codec);
+    let (max_width, max_height) = match input.codec {
+        CodecType::H264 => MAX_H264_SIZE,
+        CodecType::Hevc | CodecType::AV1 => MAX_HEVC_SIZE,
+    };
+    if input.width > max_width || input.height > max_height {
+        problems.push(format!(
+            "resolution {}x{} exceeds the VideoToolbox {name} limit of {max_width}x{max_height}; lower ALVR_BRIDGE_WIDTH/ALVR_BRIDGE_HEIGHT or the SteamVR render scale",
+            input.width, input.height
+        ));
+    }
+    let sample_rate = u64::from(input.width) * u64::from(input.height) * u64::from(input.fps);
+    if sample_rate > MAX_LUMA_SAMPLE_RATE {
+        problems.push(format!(
+            "{}x{} at {} fps needs {} Msamples/s, above level 6.2's {} Msamples/s; lower ALVR_BRIDGE_FPS or the resolution",
+            input.width,
+            input.height,
+            input.fps,
+            sample_rate / 1_000_000,
+            MAX_LUMA_SAMPLE_RATE / 1_000_000
+        ));
+    }
+    problems
+}
+
+fn physical_memory_bytes() -> Option<u64> {
+    let mut bytes = 0u64;
+    let mut size = mem::size_of::<u64>();
+    let status = unsafe {
+        libc::sysctlbyname(
+            c"hw.memsize".as_ptr(),
+            (&raw mut bytes).cast::<c_void>(),
+            &mut size,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    (status == 0 && size == mem::size_of::<u64>() && bytes > 0).then_some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUPPORTED: HardwareEncoderSupport = HardwareEncoderSupport {
+        codec_supported: true,
+        hardware_accelerated: true,
+        supports_frame_reordering: true,
+    };
+    const SIXTEEN_GIB: u64 = 16 * 1024 * MEBIBYTE;
+
+    fn input(width: u32, height: u32) -> PreflightInput {
+        PreflightInput {
+            codec: CodecType::Hevc,
+            format: SurfaceFormat::Nv12,
+            width,
+            height,
+            fps: 90,
+            buffer_count: 6,
+            source: Some(PreflightSource {
+                width,
+                height,
+                format: SourceFormat::Bgra,
+                slots: 3,
+            }),
+        }
+    }
+
+    #[test]
+    fn accepts_a_typical_headset_configuration() {
+        assert!(preflight_problems(input(3664, 1920), Ok(SUPPORTED), Some(SIXTEEN_GIB)).is_empty());
+    }
+
+    #[test]
+    fn names_the_setting_to_lower_for_oversized_resolutions() {
+        let problems = preflight_problems(input(10240, 2560), Ok(SUPPORTED), Some(SIXTEEN_GIB));
+
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("resolution 10240x2560 exceeds"));
+        assert!(problems[0].contains("SteamVR render scale"));
+    }
+
+    #[test]
+    fn applies_the_tighter_h264_size_limit() {
+        let h264 = PreflightInput {
+            codec: CodecType::H264,
+            ..input(5120, 2560)
+        };
+
+        let problems = preflight_problems(h264, Ok(SUPPORTED), Some(SIXTEEN_GIB));
+
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("VideoToolbox H.264 limit of 4096x4096"));
+        assert!(preflight_problems(input(5120, 2560), Ok(SUPPORTED), Some(SIXTEEN_GIB)).is_empty());
+    }
+
+    #[test]
+    fn collects_every_problem_instead_of_stopping_at_the_first() {
+        let problems = preflight_problems(
+            PreflightInput {
+                fps: 240,
+                source: Some(PreflightSource {
+                    width: 20000,
+                    ..input(8192, 4320).source.unwrap()
+                }),
+                ..input(8192, 4320)
+            },
+            Err("VideoToolbox HEVC encode is unavailable".into()),
+            Some(2 * 1024 * MEBIBYTE),
+        );
+
+        assert_eq!(problems.len(), 4);
+        assert!(problems[0].starts_with("VideoToolbox HEVC encode is unavailable"));
+        assert!(problems[1].contains("ALVR_BRIDGE_FPS"));
+        assert!(problems[2].contains("ALVR_IOSURFACE_SOURCE_WIDTH"));
+        assert!(problems[3].contains("ALVR_BRIDGE_BUFFER_COUNT"));
+    }
+
+    #[test]
+    fn counts_two_bytes_per_sample_for_p010_surfaces() {
+        let nv12 = input(3664, 1920);
+        let p010 = PreflightInput {
+            format: SurfaceFormat::P010,
+            ..nv12
+        };
+        let source_bytes = 3664 * 1920 * 4 * 3;
+
+        assert_eq!(
+            p010.surface_bytes() - source_bytes,
+            2 * (nv12.surface_bytes() - source_bytes)
+        );
+    }
+
+    #[test]
+    fn sizes_nv12_source_slots_at_one_and_a_half_bytes_per_pixel() {
+        let bgra = input(7680, 3840);
+        let nv12 = PreflightInput {
+            source: Some(PreflightSource {
+                format: SourceFormat::Nv12,
+                ..bgra.source.unwrap()
+            }),
+            ..bgra
+        };
+
+        assert_eq!(
+            bgra.surface_bytes() - nv12.surface_bytes(),
+            7680 * 3840 * 5 / 2 * 3
+        );
+        assert!(preflight_problems(nv12, Ok(SUPPORTED), Some(SIXTEEN_GIB)).is_empty());
+    }
+
+    #[test]
+    fn rechecks_a_renegotiated_size_against_the_codec_limits() {
+        let configured = PreflightInput {
+            fps: 60,
+            ..input(3840, 1920)
+        };
+
+        assert!(
+            check_renegotiated_size(PreflightInput {
+                width: 8192,
+                height: 4096,
+                ..configured
+            })
+            .is_ok()
+        );
+        let error = check_renegotiated_size(PreflightInput {
+            codec: CodecType::H264,
+            width: 8192,
+            height: 4096,
+            ..configured
+        })
+        .unwrap_err()
+        .to_string();
+        assert!(error.starts_with("ALVR renegotiated the stream at 8192x4096"));
+        assert!(error.contains("H.264 limit of 4096x4096"));
+    }
+
+    #[test]
+    fn skips_the_memory_check_when_the_total_is_unknown() {
+        let huge_pool = PreflightInput {
+            buffer_count: 10_000,
+            ..input(3664, 1920)
+        };
+
+        assert!(preflight_problems(huge_pool, Ok(SUPPORTED), None).is_empty());
+    }
+}
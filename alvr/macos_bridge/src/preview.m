@@ -0,0 +1,137 @@
+#import <AppKit/AppKit.h>
+#import <QuartzCore/QuartzCore.h>
+
+#include <signal.h>
+#include <stdatomic.h>
+#include <stdint.h>
+#include <unistd.h>
+
+@interface AlvrPreviewDelegate : NSObject <NSApplicationDelegate>
+@end
+
+@implementation AlvrPreviewDelegate
+// Quitting from the Dock asks the bridge to stop like Ctrl-C does, so the
+// frame loop drains instead of AppKit exiting the process under it.
+- (NSApplicationTerminateReply)applicationShouldTerminate:(NSApplication *)sender {
+    (void)sender;
+    kill(getpid(), SIGTERM);
+    return NSTerminateCancel;
+}
+@end
+
+static NSWindow *window;
+static AlvrPreviewDelegate *delegate;
+static _Atomic bool stopped;
+static NSLock *pending_lock;
+static CGImageRef pending_image;
+
+static NSLock *shared_pending_lock(void) {
+    static dispatch_once_t once;
+    dispatch_once(&once, ^{
+        pending_lock = [NSLock new];
+    });
+    return pending_lock;
+}
+
+static void show_pending_image(void) {
+    [shared_pending_lock() lock];
+    CGImageRef image = pending_image;
+    pending_image = NULL;
+    [shared_pending_lock() unlock];
+    if (image == NULL) {
+        return;
+    }
+    NSView *view = window.contentView;
+    [CATransaction begin];
+    [CATransaction setDisableActions:YES];
+    view.layer.contents = (__bridge id)image;
+    [CATransaction commit];
+    CGImageRelease(image);
+}
+
+// Runs AppKit on the calling thread, which must be the process's main thread,
+// until `alvr_preview_stop`. The window starts at the thumbnail size and
+// scales what it is given to fit.
+void alvr_preview_run(uint32_t width, uint32_t height) {
+    @autoreleasepool {
+        NSApplication *application = NSApplication.sharedApplication;
+        delegate = [AlvrPreviewDelegate new];
+        application.delegate = delegate;
+        [application setActivationPolicy:NSApplicationActivationPolicyRegular];
+
+        window = [[NSWindow alloc]
+            initWithContentRect:NSMakeRect(0, 0, width, height)
+                      styleMask:NSWindowStyleMaskTitled | NSWindowStyleMaskClosable |
+                                NSWindowStyleMaskMiniaturizable | NSWindowStyleMaskResizable
+                        backing:NSBackingStoreBuffered
+                          defer:NO];
+        window.title = @"ALVR macOS bridge preview";
+        window.releasedWhenClosed = NO;
+        window.contentAspectRatio = NSMakeSize(width, height);
+        NSView *view = window.contentView;
+        view.wantsLayer = YES;
+        view.layer.backgroundColor = CGColorGetConstantColor(kCGColorBlack);
+        view.layer.contentsGravity = kCAGravityResizeAspect;
+        [window center];
+        [window makeKeyAndOrderFront:nil];
+        [application activateIgnoringOtherApps:YES];
+
+        if (!atomic_load(&stopped)) {
+            [application run];
+        }
+        [window orderOut:nil];
+    }
+}
+
+// Ends `alvr_preview_run`. The stop is queued on the main thread, so it takes
+// effect once the run loop is running even if it is called before.
+void alvr_preview_stop(void) {
+    atomic_store(&stopped, true);
+    dispatch_async(dispatch_get_main_queue(), ^{
+        [NSApp stop:nil];
+        // `stop:` only takes effect after the next event.
+        NSEvent *event = [NSEvent otherEventWithType:NSEventTypeApplicationDefined
+                                            location:NSZeroPoint
+                                       modifierFlags:0
+                                           timestamp:0
+                                        windowNumber:0
+                                             context:nil
+                                             subtype:0
+                                               data1:0
+                                               data2:0];
+        [NSApp postEvent:event atStart:YES];
+    });
+}
+
+// Copies a BGRA image for the window. At most one image waits for the main
+// thread; a newer one replaces it, so a busy or absent main thread holds one
+// image rather than a backlog.
+void alvr_preview_show(const uint8_t *bgra, uint32_t width, uint32_t height) {
+    if (bgra == NULL || width == 0 || height == 0) {
+        return;
+    }
+    CFDataRef data = CFDataCreate(NULL, bgra, (CFIndex)width * height * 4);
+    CGDataProviderRef provider = CGDataProviderCreateWithCFData(data);
+    CGColorSpaceRef color_space = CGColorSpaceCreateWithName(kCGColorSpaceSRGB);
+    CGImageRef image = CGImageCreate(width, height, 8, 32, (size_t)width * 4, color_space,
+                                     kCGBitmapByteOrder32Little | kCGImageAlphaNoneSkipFirst,
+                                     provider, NULL, false, kCGRenderingIntentDefault);
+    CGColorSpaceRelease(color_space);
+    CGDataProviderRelease(provider);
+    CFRelease(data);
+    if (image == NULL) {
+        return;
+    }
+
+    [shared_pending_lock() lock];
+    CGImageRef replaced = pending_image;
+    pending_image = image;
+    [shared_pending_lock() unlock];
+    if (replaced != NULL) {
+        CGImageRelease(replaced);
+    } else {
+        dispatch_async(dispatch_get_main_queue(), ^{
+            show_pending_image();
+        });
+    }
+}
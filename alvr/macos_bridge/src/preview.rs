@@ -0,0 +1,106 @@
+use crate::native_source::NativeSourceFrame;
+use anyhow::Result;
+use std::{
+    env,
+    panic::resume_unwind,
+    thread,
+    time::{Duration, Instant},
+};
+
+unsafe extern "C" {
+    fn alvr_preview_run(width: u32, height: u32);
+    fn alvr_preview_stop();
+    fn alvr_preview_show(bgra: *const u8, width: u32, height: u32);
+}
+
+/// Wide enough to tell a game from a loading screen, small enough that
+/// sampling it costs well under a millisecond.
+const PREVIEW_WIDTH: u32 = 640;
+/// Ten images a second show that frames are moving without taking time
+/// from the encoder.
+const PREVIEW_INTERVAL: Duration = Duration::from_millis(100);
+
+pub(crate) fn preview_enabled() -> bool {
+    env::var("ALVR_BRIDGE_PREVIEW").as_deref() == Ok("1")
+}
+
+/// A window on the Mac that shows the frames Wine hands the bridge, before
+/// any conversion or encode. AppKit has to own the main thread, so the
+/// bridge itself runs on another one while the window is open.
+pub struct PreviewWindow {
+    _private: (),
+}
+
+impl PreviewWindow {
+    /// `None` unless `ALVR_BRIDGE_PREVIEW=1`.
+    pub fn from_env() -> Option<Self> {
+        preview_enabled().then_some(Self { _private: () })
+    }
+
+    /// Runs `body` on a new thread while the window runs on this one, which
+    /// must be the process's main thread, and closes the window when `body`
+    /// returns.
+    pub fn run<T: Send>(self, body: impl FnOnce() -> T + Send) -> T {
+        thread::scope(|scope| {
+            let worker = scope.spawn(|| {
+                let result = body();
+                unsafe { alvr_preview_stop() };
+                result
+            });
+            unsafe { alvr_preview_run(PREVIEW_WIDTH, PREVIEW_WIDTH * 9 / 16) };
+            worker.join().unwrap_or_else(|panic| resume_unwind(panic))
+        })
+    }
+}
+
+/// Samples producer frames for the preview window at a fixed rate.
+pub(crate) struct PreviewFeed {
+    width: u32,
+    height: u32,
+    bgra: Vec<u8>,
+    last_shown: Option<Instant>,
+}
+
+impl PreviewFeed {
+    pub fn new(source_width: u32, source_height: u32) -> Self {
+        let (width, height) = thumbnail_size(source_width, source_height);
+        Self {
+            width,
+            height,
+            bgra: vec![0; width as usize * height as usize * 4],
+            last_shown: None,
+        }
+    }
+
+    pub fn offer(&mut self, frame: &NativeSourceFrame<'_>) -> Result<()> {
+        if self
+            .last_shown
+            .is_some_and(|shown| shown.elapsed() < PREVIEW_INTERVAL)
+        {
+            return Ok(());
+        }
+        frame.thumbnail(self.width, self.height, &mut self.bgra)?;
+        unsafe { alvr_preview_show(self.bgra.as_ptr(), self.width, self.height) };
+        self.last_shown = Some(Instant::now());
+        Ok(())
+    }
+}
+
+/// At most `PREVIEW_WIDTH` wide, keeping the source's aspect ratio.
+fn thumbnail_size(source_width: u32, source_height: u32) -> (u32, u32) {
+    let width = PREVIEW_WIDTH.min(source_width).max(1);
+    let height = u64::from(width) * u64::from(source_height) / u64::from(source_width.max(1));
+    (width, (height as u32).max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_source_aspect_ratio() {
+        assert_eq!(thumbnail_size(4128, 2240), (640, 347));
+        assert_eq!(thumbnail_size(320, 240), (320, 240));
+        assert_eq!(thumbnail_size(8192, 1), (640, 1));
+    }
+}
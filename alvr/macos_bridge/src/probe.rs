@@ -1,49 +1,204 @@
 use crate::{
-    AlvrVideoSink, EncodedFrame, FrameMetadata, HardwareEncoderSupport, NativeHevcEncoder,
-    NativeHevcEncoderConfig, PoolStats, SurfacePool,
+    AlvrVideoSink, AudioDevices, ColorMatrix, ColorRange, ColorSpace, EncodedFrame, EncoderBackend,
+    FrameMetadata, HardwareEncoderSupport, HdrMetadata, NativeVideoEncoderConfig, PoolStats,
+    PosePrediction, RateControl, SecondClient, SurfaceFormat, SurfacePool, VideoEncoder,
+    VideoToolboxOverrides,
+    alvr_sink::{
+        MAX_FIXED_POSE_PREDICTION, SessionEncodingSettings, load_session_encoding_settings,
+    },
+    capture::FrameCaptureReader,
+    control::{BridgeState, BridgeStatus, ControlServer, StatusMetrics},
+    encoder::{
+        BridgeEncoder, MAX_PIPELINE_DEPTH, SOFTWARE_ENCODER_BUILT, codec_name, periodic_keyframe,
+    },
+    filter::{FilterChain, FilterSpec},
+    frame_stats::FrameOutput,
+    output_buffers::OUTPUT_BUFFERS,
+    preflight::{PreflightInput, PreflightSource, run_preflight},
+    recording::{StreamRecorder, TransportDump},
+    restream::Restreamer,
+    sei::timing_sei_nal,
+    signals::shutdown_signal,
 };
 use alvr_common::{Fov, Pose, ViewParams, glam::Vec3};
+use alvr_session::{CodecType, H264Profile};
 use anyhow::{Context, Result, ensure};
 use std::{
     env, fmt,
     path::PathBuf,
     thread,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
+pub(crate) const DEFAULT_WIDTH: u32 = 3664;
+pub(crate) const DEFAULT_HEIGHT: u32 = 1920;
+/// The software encoder's default, half the hardware default on each side,
+/// which one or two cores can keep up with at 90 Hz.
+pub(crate) const SOFTWARE_DEFAULT_WIDTH: u32 = DEFAULT_WIDTH / 2;
+pub(crate) const SOFTWARE_DEFAULT_HEIGHT: u32 = DEFAULT_HEIGHT / 2;
+
 #[derive(Debug, Clone)]
 pub struct ProbeConfig {
+    pub codec: CodecType,
+    pub encoder_backend: EncoderBackend,
+    pub format: SurfaceFormat,
+    pub color: ColorSpace,
+    /// Asks ALVR for foveated encoding with the session's foveation
+    /// settings. Only the IOSurface input's Metal pass can compress for it.
+    pub foveated_encoding: bool,
     pub width: u32,
     pub height: u32,
     pub fps: u32,
     pub bitrate_bps: u64,
+    pub rate_control: RateControl,
+    pub keyframe_interval: u32,
+    pub keyframe_interval_duration: Option<Duration>,
+    pub pipeline_depth: u32,
+    pub vt_overrides: VideoToolboxOverrides,
     pub frame_count: u64,
     pub buffer_count: usize,
     pub telemetry_interval: u64,
     pub connect_to_alvr: bool,
+    pub session_settings: bool,
     pub alvr_root: PathBuf,
+    pub control_socket: Option<PathBuf>,
+    pub metrics_port: Option<u16>,
+    pub filters: Vec<FilterSpec>,
+    pub test_pattern: bool,
+    pub audio: AudioDevices,
+    pub record_path: Option<PathBuf>,
+    pub restream_port: Option<u16>,
+    pub transport_dump: Option<PathBuf>,
+    pub capture_path: Option<PathBuf>,
+    pub replay_path: Option<PathBuf>,
+    pub timing_sei: bool,
+    pub pose_prediction: PosePrediction,
+    pub second_client: SecondClient,
 }
 
 impl ProbeConfig {
     pub fn from_env() -> Result<Self> {
-        let fps = env_u32("ALVR_BRIDGE_FPS", 90)?;
-        let alvr_root = match env::var_os("ALVR_BRIDGE_ROOT") {
-            Some(root) => PathBuf::from(root),
-            None => env::var_os("HOME")
-                .map(PathBuf::from)
-                .context("HOME is required unless ALVR_BRIDGE_ROOT is set")?
-                .join("Library/Application Support/ALVR/macos_bridge"),
+        let alvr_root = alvr_root_from_env()?;
+        let connect_to_alvr = env_bool("ALVR_BRIDGE_CONNECT", false)?;
+        let session_settings = connect_to_alvr && env_bool("ALVR_BRIDGE_SESSION_SETTINGS", false)?;
+        let session = if session_settings {
+            let session =
+                load_session_encoding_settings(&alvr_root, DEFAULT_WIDTH, DEFAULT_HEIGHT)?
+                    .unwrap_or_default();
+            println!(
+                "session_settings loaded codec={:?} fps={:?} bitrate_bps={:?} stream_size={:?}",
+                session.codec, session.fps, session.bitrate_bps, session.stream_size
+            );
+            session
+        } else {
+            SessionEncodingSettings::default()
         };
+        let encoder_backend = env_encoder_backend("ALVR_BRIDGE_ENCODER")?;
+        let (default_width, default_height) =
+            session
+                .stream_size
+                .unwrap_or(if encoder_backend == EncoderBackend::Software {
+                    (SOFTWARE_DEFAULT_WIDTH, SOFTWARE_DEFAULT_HEIGHT)
+                } else {
+                    (DEFAULT_WIDTH, DEFAULT_HEIGHT)
+                });
+        let fps = env_u32("ALVR_BRIDGE_FPS", session.fps.unwrap_or(90))?;
+        let hdr = env_bool("ALVR_BRIDGE_HDR", session.hdr.unwrap_or(false))?
+            .then(HdrMetadata::from_env)
+            .transpose()?;
+        let control_socket = env_bool("ALVR_BRIDGE_CONTROL", true)?
+            .then(control_socket_from_env)
+            .transpose()?;
         let config = Self {
-            width: env_u32("ALVR_BRIDGE_WIDTH", 3664)?,
-            height: env_u32("ALVR_BRIDGE_HEIGHT", 1920)?,
+            codec: env_codec(
+                "ALVR_BRIDGE_CODEC",
+                session.codec.unwrap_or(CodecType::Hevc),
+            )?,
+            encoder_backend,
+            format: env_bit_depth("ALVR_BRIDGE_BIT_DEPTH")?,
+            color: ColorSpace {
+                matrix: env_color_matrix(
+                    "ALVR_BRIDGE_COLOR_MATRIX",
+                    if hdr.is_some() {
+                        ColorMatrix::Bt2020
+                    } else {
+                        ColorMatrix::Bt709
+                    },
+                )?,
+                range: env_color_range("ALVR_BRIDGE_COLOR_RANGE")?,
+                hdr,
+            },
+            foveated_encoding: env_bool("ALVR_BRIDGE_FOVEATED_ENCODING", false)?,
+            width: env_u32("ALVR_BRIDGE_WIDTH", default_width)?,
+            height: env_u32("ALVR_BRIDGE_HEIGHT", default_height)?,
             fps,
-            bitrate_bps: env_u64("ALVR_BRIDGE_BITRATE_BPS", 50_000_000)?,
+            bitrate_bps: env_u64(
+                "ALVR_BRIDGE_BITRATE_BPS",
+                session.bitrate_bps.unwrap_or(50_000_000),
+            )?,
+            rate_control: env_rate_control("ALVR_BRIDGE_RATE_CONTROL")?,
+            keyframe_interval: env_u32("ALVR_BRIDGE_KEYFRAME_INTERVAL", fps)?,
+            keyframe_interval_duration: env::var("ALVR_BRIDGE_KEYFRAME_INTERVAL_MS")
+                .ok()
+                .filter(|millis| !millis.is_empty())
+                .map(|millis| {
+                    millis
+                        .parse()
+                        .map(Duration::from_millis)
+                        .context("invalid ALVR_BRIDGE_KEYFRAME_INTERVAL_MS")
+                })
+                .transpose()?,
+            pipeline_depth: env_u32("ALVR_BRIDGE_PIPELINE_DEPTH", 1)?,
+            vt_overrides: env::var("ALVR_BRIDGE_VT_PROPERTIES")
+                .map(|list| {
+                    VideoToolboxOverrides::parse_list(&list)
+                        .context("invalid ALVR_BRIDGE_VT_PROPERTIES")
+                })
+                .unwrap_or(Ok(VideoToolboxOverrides::default()))?,
             frame_count: env_u64("ALVR_BRIDGE_FRAMES", 180)?,
             buffer_count: env_usize("ALVR_BRIDGE_BUFFER_COUNT", 6)?,
             telemetry_interval: env_u64("ALVR_BRIDGE_TELEMETRY_INTERVAL", u64::from(fps))?,
-            connect_to_alvr: env_bool("ALVR_BRIDGE_CONNECT", false)?,
+            connect_to_alvr,
+            session_settings,
             alvr_root,
+            control_socket,
+            metrics_port: env::var("ALVR_BRIDGE_METRICS_PORT")
+                .ok()
+                .filter(|port| !port.is_empty())
+                .map(|port| port.parse().context("invalid ALVR_BRIDGE_METRICS_PORT"))
+                .transpose()?,
+            filters: env::var("ALVR_BRIDGE_FILTERS")
+                .map(|list| FilterSpec::parse_list(&list).context("invalid ALVR_BRIDGE_FILTERS"))
+                .unwrap_or(Ok(Vec::new()))?,
+            test_pattern: env_bool("ALVR_BRIDGE_TEST_PATTERN", false)?,
+            audio: AudioDevices {
+                game_audio: env::var("ALVR_BRIDGE_GAME_AUDIO_DEVICE")
+                    .ok()
+                    .filter(|name| !name.is_empty()),
+                microphone: env::var("ALVR_BRIDGE_MICROPHONE_DEVICE")
+                    .ok()
+                    .filter(|name| !name.is_empty()),
+            },
+            record_path: env::var_os("ALVR_BRIDGE_RECORD")
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+            restream_port: env::var("ALVR_BRIDGE_RESTREAM_PORT")
+                .ok()
+                .filter(|port| !port.is_empty())
+                .map(|port| port.parse().context("invalid ALVR_BRIDGE_RESTREAM_PORT"))
+                .transpose()?,
+            transport_dump: env::var_os("ALVR_BRIDGE_TRANSPORT_DUMP")
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+            capture_path: env::var_os("ALVR_BRIDGE_CAPTURE")
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+            replay_path: env::var_os("ALVR_BRIDGE_REPLAY")
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+            timing_sei: env_bool("ALVR_BRIDGE_TIMING_SEI", false)?,
+            pose_prediction: env_pose_prediction("ALVR_BRIDGE_POSE_PREDICTION")?,
+            second_client: env_second_client("ALVR_BRIDGE_SECOND_CLIENT")?,
         };
         config.validate()?;
         Ok(config)
@@ -68,11 +223,89 @@ impl ProbeConfig {
                 "ALVR-connected probe height must be divisible by 32"
             );
         }
+        ensure!(
+            !self.format.is_ten_bit() || self.codec == CodecType::Hevc,
+            "10-bit encoding requires HEVC Main10; set ALVR_BRIDGE_CODEC=hevc"
+        );
+        if self.encoder_backend == EncoderBackend::Software {
+            ensure!(
+                SOFTWARE_ENCODER_BUILT,
+                "ALVR_BRIDGE_ENCODER=software needs a bridge built with --features software-encoder"
+            );
+            ensure!(
+                self.codec == CodecType::H264 && !self.format.is_ten_bit(),
+                "the software encoder produces 8-bit H.264 only; set ALVR_BRIDGE_CODEC=h264"
+            );
+        }
+        ensure!(
+            !self.format.is_ten_bit() || self.filters.is_empty(),
+            "frame filters only support 8-bit NV12; unset ALVR_BRIDGE_FILTERS for 10-bit"
+        );
+        ensure!(
+            !self.format.is_ten_bit() || !self.test_pattern,
+            "the test pattern only supports 8-bit NV12; unset ALVR_BRIDGE_TEST_PATTERN for 10-bit"
+        );
+        ensure!(
+            !self.format.is_ten_bit()
+                || (self.capture_path.is_none() && self.replay_path.is_none()),
+            "frame capture and replay only support 8-bit NV12"
+        );
+        if self.color.hdr.is_some() {
+            ensure!(
+                self.format.is_ten_bit(),
+                "HDR10 needs 10-bit HEVC Main10; set ALVR_BRIDGE_BIT_DEPTH=10"
+            );
+            ensure!(
+                self.color.range == ColorRange::Limited,
+                "HDR10 is limited range; unset ALVR_BRIDGE_COLOR_RANGE"
+            );
+        }
+        ensure!(
+            (self.color.matrix == ColorMatrix::Bt2020) == self.color.hdr.is_some(),
+            "the BT.2020 matrix is used by HDR mode and only HDR mode; unset ALVR_BRIDGE_COLOR_MATRIX"
+        );
+        // Filters, the test pattern, and captures write and read luma codes
+        // on the CPU, and all of them assume 16..235.
+        ensure!(
+            self.color.range == ColorRange::Limited
+                || (self.filters.is_empty()
+                    && !self.test_pattern
+                    && self.capture_path.is_none()
+                    && self.replay_path.is_none()),
+            "frame filters, the test pattern, and capture or replay need limited range; unset ALVR_BRIDGE_COLOR_RANGE"
+        );
+        ensure!(
+            !self.test_pattern || self.replay_path.is_none(),
+            "ALVR_BRIDGE_TEST_PATTERN and ALVR_BRIDGE_REPLAY both replace the probe frames; choose one"
+        );
+        ensure!(
+            self.metrics_port.is_none() || self.control_socket.is_some(),
+            "ALVR_BRIDGE_METRICS_PORT serves the control socket's status; unset ALVR_BRIDGE_CONTROL=0"
+        );
+        ensure!(
+            self.restream_port.is_none() || self.codec != CodecType::AV1,
+            "ALVR_BRIDGE_RESTREAM_PORT packages HEVC and H.264 only; unset it or choose another ALVR_BRIDGE_CODEC"
+        );
         ensure!(self.fps > 0, "probe FPS must be greater than zero");
         ensure!(
             self.bitrate_bps > 0,
             "probe bitrate must be greater than zero"
         );
+        ensure!(
+            self.keyframe_interval_duration != Some(Duration::ZERO),
+            "ALVR_BRIDGE_KEYFRAME_INTERVAL_MS must be greater than zero"
+        );
+        ensure!(
+            (1..=MAX_PIPELINE_DEPTH).contains(&self.pipeline_depth),
+            "ALVR_BRIDGE_PIPELINE_DEPTH must be between 1 and {MAX_PIPELINE_DEPTH}"
+        );
+        if let PosePrediction::Fixed(lookahead) = self.pose_prediction {
+            ensure!(
+                lookahead <= MAX_FIXED_POSE_PREDICTION,
+                "ALVR_BRIDGE_POSE_PREDICTION must be at most {} ms",
+                MAX_FIXED_POSE_PREDICTION.as_millis()
+            );
+        }
         ensure!(
             self.frame_count > 0,
             "probe frame count must be greater than zero"
@@ -88,6 +321,139 @@ impl ProbeConfig {
         );
         Ok(())
     }
+
+    pub(crate) fn preflight_input(&self, source: Option<PreflightSource>) -> PreflightInput {
+        PreflightInput {
+            codec: self.codec,
+            format: self.format,
+            width: self.width,
+            height: self.height,
+            fps: self.fps,
+            buffer_count: self.buffer_count,
+            source,
+        }
+    }
+
+    pub(crate) fn encoder_config(&self) -> NativeVideoEncoderConfig {
+        NativeVideoEncoderConfig {
+            codec: self.codec,
+            h264_profile: H264Profile::High,
+            format: self.format,
+            width: self.width,
+            height: self.height,
+            fps: self.fps,
+            fps_denominator: 1,
+            bitrate_bps: self.bitrate_bps,
+            rate_control: self.rate_control,
+            keyframe_interval: self.keyframe_interval,
+            keyframe_interval_duration: self.keyframe_interval_duration,
+            hdr: self.color.hdr,
+            pipeline_depth: self.pipeline_depth,
+            overrides: self.vt_overrides,
+        }
+    }
+
+    pub(crate) fn initial_status(&self, input: &'static str) -> BridgeStatus {
+        BridgeStatus {
+            state: BridgeState::Starting,
+            input,
+            codec: self.codec,
+            width: self.width,
+            height: self.height,
+            fps: f64::from(self.fps),
+            bitrate_bps: self.bitrate_bps,
+            pipeline_depth: self.pipeline_depth,
+            buffer_count: self.buffer_count,
+            connect_to_alvr: self.connect_to_alvr,
+            client: None,
+            metrics: StatusMetrics {
+                bitrate_bps: self.bitrate_bps,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub(crate) fn start_control_server(
+        &self,
+        input: &'static str,
+    ) -> Result<Option<ControlServer>> {
+        let Some(path) = self.control_socket.as_deref() else {
+            return Ok(None);
+        };
+        let mut control = ControlServer::bind(path, self.initial_status(input))?;
+        if let Some(port) = self.metrics_port {
+            control.serve_metrics(port)?;
+        }
+        Ok(Some(control))
+    }
+
+    pub(crate) fn start_alvr_sink(&self, runtime_generation: u64) -> Result<Option<AlvrVideoSink>> {
+        if !self.connect_to_alvr {
+            return Ok(None);
+        }
+        let mut sink = AlvrVideoSink::start(
+            &self.alvr_root,
+            self.width,
+            self.height,
+            self.fps,
+            self.codec,
+            self.format,
+            self.color.hdr.is_some(),
+            self.foveated_encoding,
+            &self.audio,
+            runtime_generation,
+        )?;
+        sink.set_pose_prediction(self.pose_prediction);
+        sink.set_second_client(self.second_client);
+        if let Some(path) = &self.transport_dump {
+            sink.set_transport_dump(TransportDump::create(path)?);
+        }
+        Ok(Some(sink))
+    }
+
+    pub(crate) fn start_recorder(&self) -> Result<Option<StreamRecorder>> {
+        self.record_path
+            .as_deref()
+            .map(|path| StreamRecorder::create(path, self.codec))
+            .transpose()
+    }
+
+    pub(crate) fn start_restream(&self) -> Result<Option<Restreamer>> {
+        self.restream_port
+            .map(|port| Restreamer::bind(port, self.codec))
+            .transpose()
+    }
+
+    pub(crate) fn filter_chain(&self) -> Result<FilterChain> {
+        let specs = self
+            .test_pattern
+            .then_some(FilterSpec::TestPattern)
+            .into_iter()
+            .chain(self.filters.iter().cloned())
+            .collect::<Vec<_>>();
+        let chain = FilterChain::from_specs(&specs)?;
+        if !chain.is_empty() {
+            println!("frame_filters enabled chain={}", chain.names().join(","));
+        }
+        Ok(chain)
+    }
+}
+
+pub fn control_socket_from_env() -> Result<PathBuf> {
+    match env::var_os("ALVR_BRIDGE_CONTROL_SOCKET") {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => Ok(alvr_root_from_env()?.join("control.sock")),
+    }
+}
+
+pub(crate) fn alvr_root_from_env() -> Result<PathBuf> {
+    match env::var_os("ALVR_BRIDGE_ROOT") {
+        Some(root) => Ok(PathBuf::from(root)),
+        None => Ok(env::var_os("HOME")
+            .map(PathBuf::from)
+            .context("HOME is required unless ALVR_BRIDGE_ROOT is set")?
+            .join("Library/Application Support/ALVR/macos_bridge")),
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -174,28 +540,78 @@ impl fmt::Display for ProbeSummary {
 
 pub fn run_surface_probe(
     config: ProbeConfig,
+    report: impl FnMut(CadenceReport),
+) -> Result<ProbeSummary> {
+    config.validate()?;
+    run_preflight(config.preflight_input(None), config.encoder_backend)?;
+    let (encoder, _) = BridgeEncoder::open(config.encoder_backend, config.encoder_config())?;
+    run_surface_probe_with_encoder(config, encoder, report)
+}
+
+/// Runs the finite probe loop against any `VideoEncoder`, skipping the
+/// VideoToolbox preflight so a test or alternative backend can stand in for
+/// the hardware encoder.
+pub fn run_surface_probe_with_encoder<E: VideoEncoder>(
+    config: ProbeConfig,
+    mut encoder: E,
     mut report: impl FnMut(CadenceReport),
 ) -> Result<ProbeSummary> {
     config.validate()?;
-    let pool = SurfacePool::new(config.width, config.height, config.buffer_count)?;
-    let (mut encoder, hardware_support) = NativeHevcEncoder::new(NativeHevcEncoderConfig {
-        width: config.width,
-        height: config.height,
-        fps: config.fps,
-        bitrate_bps: config.bitrate_bps,
-    })?;
-    let mut sink = config
-        .connect_to_alvr
-        .then(|| {
-            AlvrVideoSink::start(
-                &config.alvr_root,
-                config.width,
-                config.height,
-                config.fps,
-                0,
-            )
-        })
+    ensure!(
+        encoder.codec() == config.codec,
+        "encoder produces {} but the probe was configured for {}",
+        codec_name(encoder.codec()),
+        codec_name(config.codec)
+    );
+    let hardware_support = encoder.hardware_support();
+    let timing_sei = config.timing_sei.then_some(config.codec);
+    ensure!(
+        config.capture_path.is_none(),
+        "frame capture records IOSurface input; set ALVR_BRIDGE_INPUT=iosurface"
+    );
+    ensure!(
+        !config.foveated_encoding,
+        "foveated encoding compresses frames in the Metal pass; set ALVR_BRIDGE_INPUT=iosurface"
+    );
+    let mut replay = config
+        .replay_path
+        .as_deref()
+        .map(|path| FrameCaptureReader::open(path, config.fps))
         .transpose()?;
+    if let Some(replay) = &replay {
+        ensure!(
+            (replay.width(), replay.height()) == (config.width, config.height),
+            "frame capture is {}x{} but the probe encodes {}x{}; set ALVR_BRIDGE_WIDTH/ALVR_BRIDGE_HEIGHT to match",
+            replay.width(),
+            replay.height(),
+            config.width,
+            config.height
+        );
+    }
+    let control = config.start_control_server("probe")?;
+    let mut filters = config.filter_chain()?;
+    let mut recorder = config.start_recorder()?;
+    let mut restream = config.start_restream()?;
+    let pool = SurfacePool::with_color_space(
+        config.width,
+        config.height,
+        config.buffer_count,
+        config.format,
+        config.color,
+    )?;
+    println!(
+        "surface_probe color matrix={} range={} transfer={}",
+        config.color.matrix.name(),
+        config.color.range.name(),
+        config.color.transfer_name()
+    );
+    println!(
+        "surface_probe rate_control mode={} bitrate_bps={} vt_properties={}",
+        config.rate_control.name(),
+        config.bitrate_bps,
+        config.vt_overrides
+    );
+    let mut sink = config.start_alvr_sink(0)?;
     let fallback_view_params = default_stereo_view_params(config.width, config.height);
     let frame_interval = Duration::from_secs_f64(1.0 / f64::from(config.fps));
     let start = Instant::now();
@@ -207,24 +623,75 @@ pub fn run_surface_probe(
     let mut total_deadline_miss_max = Duration::ZERO;
     let mut last_video_timestamp = Duration::ZERO;
     let mut last_pose_timestamp = Duration::ZERO;
+    let mut interrupted = false;
 
     for frame_id in 0..config.frame_count {
+        if let Some(signal) = shutdown_signal() {
+            println!("surface_probe shutdown signal={signal}");
+            interrupted = true;
+            if let Some(sink) = sink.as_mut() {
+                sink.remove_shared_memory_on_drop();
+            }
+            break;
+        }
+        if let Some(control) = &control {
+            control.update(|status| {
+                status.state = stream_state(sink.as_ref());
+                status.client = sink.as_ref().and_then(AlvrVideoSink::client_status);
+                status.metrics = StatusMetrics {
+                    received: frame_id,
+                    submitted,
+                    encoded,
+                    transported,
+                    bitrate_bps: config.bitrate_bps,
+                    ..Default::default()
+                };
+            });
+        }
         if let Some(sink) = sink.as_mut() {
             sink.poll_events();
             if let Some(error) = sink.connection_error() {
                 anyhow::bail!("ALVR stream contract failed: {error}");
             }
+            if let Some((width, height)) = sink.take_stream_resize() {
+                anyhow::bail!(
+                    "ALVR renegotiated the stream at {width}x{height}; the finite probe keeps its {}x{} surfaces, so use the IOSurface bridge or match ALVR_BRIDGE_WIDTH/ALVR_BRIDGE_HEIGHT",
+                    config.width,
+                    config.height
+                );
+            }
+            if let Some((codec, h264_profile)) = sink.take_codec_change() {
+                anyhow::bail!(
+                    "ALVR negotiated {} (H.264 profile {h264_profile:?}) for the client; the finite probe keeps the encoder it started with, so use the IOSurface bridge or set ALVR_BRIDGE_CODEC to match",
+                    codec_name(codec)
+                );
+            }
+            if let Some(refresh_hz) = sink.take_refresh_change() {
+                anyhow::bail!(
+                    "ALVR negotiated {refresh_hz:.3} Hz for the client; the finite probe keeps its {} FPS encoder, so use the IOSurface bridge or set ALVR_BRIDGE_FPS to match",
+                    config.fps
+                );
+            }
             if sink.shutdown_requested() {
                 break;
             }
         }
 
-        let target = start + frame_interval.mul_f64(frame_id as f64);
+        let target = match replay.as_mut() {
+            Some(replay) => start + replay.next_timestamp()?,
+            None => start + frame_interval.mul_f64(frame_id as f64),
+        };
         if let Some(sleep_duration) = target.checked_duration_since(Instant::now()) {
             thread::sleep(sleep_duration);
         }
 
-        let dispatch = dispatch_outputs(encoder.drain_ready()?, &mut sink)?;
+        let dispatch = dispatch_outputs(
+            encoder.drain_ready()?,
+            &mut sink,
+            &mut recorder,
+            &mut restream,
+            timing_sei,
+        )?;
         encoded += dispatch.encoded;
         transported += dispatch.transported;
         let acquire_deadline = Instant::now() + Duration::from_secs(1);
@@ -232,20 +699,31 @@ pub fn run_surface_probe(
             if let Some(lease) = pool.try_acquire()? {
                 break lease;
             }
-            let dispatch = dispatch_outputs(encoder.drain_ready()?, &mut sink)?;
-            encoded += dispatch.encoded;
-            transported += dispatch.transported;
+            let remaining = acquire_deadline.saturating_duration_since(Instant::now());
             ensure!(
-                Instant::now() < acquire_deadline,
+                !remaining.is_zero() && encoder.pending_count() > 0,
                 "surface pool remained exhausted for one second with {} encoder frames pending",
                 encoder.pending_count()
             );
-            thread::sleep(Duration::from_micros(100));
+            // Every lease the pool is missing is held by a pending encode, so
+            // the next VideoToolbox callback is what frees one.
+            let dispatch = dispatch_outputs(
+                encoder.wait_for_output(remaining)?,
+                &mut sink,
+                &mut recorder,
+                &mut restream,
+                timing_sei,
+            )?;
+            encoded += dispatch.encoded;
+            transported += dispatch.transported;
         };
         cadence.observe_available(pool.stats().available);
 
         let source_start = Instant::now();
-        lease.write_probe_marker(frame_id)?;
+        match replay.as_mut() {
+            Some(replay) => lease.with_nv12_frame(|frame| replay.read_pixels(frame))?,
+            None => lease.write_probe_marker(frame_id)?,
+        }
         let source_elapsed = source_start.elapsed();
 
         let video_timestamp = start.elapsed();
@@ -265,16 +743,23 @@ pub fn run_surface_probe(
         };
         last_video_timestamp = metadata.video_timestamp;
         last_pose_timestamp = metadata.pose_timestamp;
-        let requested_keyframe = sink
+        if sink
             .as_mut()
-            .is_some_and(AlvrVideoSink::take_force_keyframe);
-        let force_keyframe = frame_id % u64::from(config.fps) == 0 || requested_keyframe;
+            .is_some_and(AlvrVideoSink::take_force_keyframe)
+        {
+            encoder.request_idr();
+        }
+        let force_keyframe = periodic_keyframe(config.keyframe_interval, frame_id);
+        if !filters.is_empty() {
+            lease.with_nv12_frame(|frame| filters.apply(frame, &metadata))?;
+        }
 
         let encode_start = Instant::now();
         let outputs = encoder.submit(lease, metadata, force_keyframe)?;
         let encode_elapsed = encode_start.elapsed();
         submitted += 1;
-        let dispatch = dispatch_outputs(outputs, &mut sink)?;
+        let dispatch =
+            dispatch_outputs(outputs, &mut sink, &mut recorder, &mut restream, timing_sei)?;
         encoded += dispatch.encoded;
         transported += dispatch.transported;
 
@@ -295,11 +780,23 @@ pub fn run_surface_probe(
             encode_elapsed,
             deadline_miss,
         ) {
+            if let Some(sink) = sink.as_mut() {
+                sink.publish_to_dashboard(&cadence_report.to_string());
+            }
             report(cadence_report);
         }
     }
 
-    let dispatch = dispatch_outputs(encoder.finish()?, &mut sink)?;
+    if let Some(control) = &control {
+        control.update(|status| status.state = BridgeState::Closing);
+    }
+    let dispatch = dispatch_outputs(
+        encoder.finish()?,
+        &mut sink,
+        &mut recorder,
+        &mut restream,
+        timing_sei,
+    )?;
     encoded += dispatch.encoded;
     transported += dispatch.transported;
     if let Some(cadence_report) = cadence.finish(
@@ -310,13 +807,16 @@ pub fn run_surface_probe(
         },
         start.elapsed(),
     ) {
+        if let Some(sink) = sink.as_mut() {
+            sink.publish_to_dashboard(&cadence_report.to_string());
+        }
         report(cadence_report);
     }
 
     let pool_stats = pool.stats();
     ensure!(
         encoded == submitted,
-        "VideoToolbox emitted {encoded} frames for {submitted} submissions"
+        "encoder emitted {encoded} frames for {submitted} submissions"
     );
     ensure!(
         pool_stats.available == pool_stats.capacity,
@@ -335,11 +835,11 @@ pub fn run_surface_probe(
         sink.ever_connected()
     });
     ensure!(
-        !config.connect_to_alvr || connected_to_alvr,
+        !config.connect_to_alvr || connected_to_alvr || interrupted,
         "ALVR transport probe never reached ClientConnected; a fresh or changed session may have primed restart settings, so rerun the same bounded command"
     );
     ensure!(
-        !config.connect_to_alvr || transported > 0,
+        !config.connect_to_alvr || transported > 0 || interrupted,
         "ALVR transport connected but no encoded frames were sent"
     );
 
@@ -371,14 +871,29 @@ pub(crate) struct DispatchCounts {
     pub keyframes: u64,
     pub keyframe_bytes: u64,
     pub max_frame_bytes: u64,
+    pub frames: Vec<FrameOutput>,
+    pub encode_latencies: Vec<Duration>,
+    pub send_latencies: Vec<Duration>,
 }
 
 pub(crate) fn dispatch_outputs(
     outputs: Vec<EncodedFrame>,
     sink: &mut Option<AlvrVideoSink>,
+    recorder: &mut Option<StreamRecorder>,
+    restream: &mut Option<Restreamer>,
+    timing_sei: Option<CodecType>,
 ) -> Result<DispatchCounts> {
     let mut counts = DispatchCounts::default();
-    for output in outputs {
+    for mut output in outputs {
+        if let Some(codec) = timing_sei {
+            // Stamped here rather than at encode completion so the send time
+            // covers any wait behind earlier frames.
+            let sei = timing_sei_nal(codec, &output.metadata, SystemTime::now());
+            let mut nal_data = OUTPUT_BUFFERS.take(sei.len() + output.nal_data.len());
+            nal_data.extend_from_slice(&sei);
+            nal_data.extend_from_slice(&output.nal_data);
+            OUTPUT_BUFFERS.recycle(std::mem::replace(&mut output.nal_data, nal_data));
+        }
         let frame_bytes =
             output.nal_data.len() + output.decoder_config_nals.as_ref().map_or(0, Vec::len);
         let frame_bytes = u64::try_from(frame_bytes).unwrap_or(u64::MAX);
@@ -389,11 +904,32 @@ pub(crate) fn dispatch_outputs(
             counts.keyframes += 1;
             counts.keyframe_bytes = counts.keyframe_bytes.saturating_add(frame_bytes);
         }
-        if let Some(sink) = sink.as_mut()
-            && sink.send(output)?
+        counts.frames.push(FrameOutput {
+            bytes: frame_bytes,
+            is_keyframe: output.is_keyframe,
+            video_timestamp: output.metadata.video_timestamp,
+        });
+        counts.encode_latencies.push(output.encode_latency);
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.record(&output)?;
+        }
+        // Spectators are not worth ending the session over.
+        if let Some(restreamer) = restream.as_mut()
+            && let Err(error) = restreamer.push(&output)
         {
-            counts.transported += 1;
-            counts.transported_bytes = counts.transported_bytes.saturating_add(frame_bytes);
+            eprintln!("WARNING restream stopped: {error:#}");
+            *restream = None;
+        }
+        if let Some(sink) = sink.as_mut() {
+            let send_started = Instant::now();
+            let sent = sink.send(output)?;
+            counts.send_latencies.push(send_started.elapsed());
+            if sent {
+                counts.transported += 1;
+                counts.transported_bytes = counts.transported_bytes.saturating_add(frame_bytes);
+            }
+        } else {
+            OUTPUT_BUFFERS.recycle_frame(output);
         }
     }
     Ok(counts)
@@ -491,6 +1027,13 @@ impl CadenceAccumulator {
     }
 }
 
+pub(crate) fn stream_state(sink: Option<&AlvrVideoSink>) -> BridgeState {
+    match sink {
+        Some(sink) if sink.client_status().is_none() => BridgeState::WaitingForClient,
+        _ => BridgeState::Streaming,
+    }
+}
+
 pub(crate) fn default_stereo_view_params(width: u32, height: u32) -> [ViewParams; 2] {
     let eye_width = width / 2;
     let horizontal_half_fov = std::f32::consts::FRAC_PI_4;
@@ -537,6 +1080,92 @@ fn env_usize(name: &str, default: usize) -> Result<usize> {
         .unwrap_or(Ok(default))
 }
 
+fn env_codec(name: &str, default: CodecType) -> Result<CodecType> {
+    env::var(name)
+        .map(|value| match value.to_ascii_lowercase().as_str() {
+            "hevc" | "h265" => Ok(CodecType::Hevc),
+            "h264" | "avc" => Ok(CodecType::H264),
+            "av1" => Ok(CodecType::AV1),
+            _ => anyhow::bail!("invalid {name}: expected hevc, h264, or av1"),
+        })
+        .unwrap_or(Ok(default))
+}
+
+fn env_bit_depth(name: &str) -> Result<SurfaceFormat> {
+    env::var(name)
+        .map(|value| match value.as_str() {
+            "8" => Ok(SurfaceFormat::Nv12),
+            "10" => Ok(SurfaceFormat::P010),
+            _ => anyhow::bail!("invalid {name}: expected 8 or 10"),
+        })
+        .unwrap_or(Ok(SurfaceFormat::Nv12))
+}
+
+fn env_color_matrix(name: &str, default: ColorMatrix) -> Result<ColorMatrix> {
+    env::var(name)
+        .map(|value| match value.to_ascii_lowercase().as_str() {
+            "709" | "bt709" => Ok(ColorMatrix::Bt709),
+            "601" | "bt601" => Ok(ColorMatrix::Bt601),
+            "2020" | "bt2020" => Ok(ColorMatrix::Bt2020),
+            _ => anyhow::bail!("invalid {name}: expected 709, 601, or 2020"),
+        })
+        .unwrap_or(Ok(default))
+}
+
+fn env_encoder_backend(name: &str) -> Result<EncoderBackend> {
+    env::var(name)
+        .map(|value| match value.to_ascii_lowercase().as_str() {
+            "auto" => Ok(EncoderBackend::Auto),
+            "videotoolbox" | "vt" => Ok(EncoderBackend::VideoToolbox),
+            "software" | "openh264" => Ok(EncoderBackend::Software),
+            _ => anyhow::bail!("invalid {name}: expected auto, videotoolbox, or software"),
+        })
+        .unwrap_or(Ok(EncoderBackend::default()))
+}
+
+fn env_rate_control(name: &str) -> Result<RateControl> {
+    env::var(name)
+        .map(|value| match value.to_ascii_lowercase().as_str() {
+            "bitrate" | "abr" => Ok(RateControl::AverageBitrate),
+            "quality" => Ok(RateControl::Quality),
+            _ => anyhow::bail!("invalid {name}: expected bitrate or quality"),
+        })
+        .unwrap_or(Ok(RateControl::default()))
+}
+
+fn env_pose_prediction(name: &str) -> Result<PosePrediction> {
+    env::var(name)
+        .map(|value| match value.to_ascii_lowercase().as_str() {
+            "off" | "0" => Ok(PosePrediction::Off),
+            "auto" => Ok(PosePrediction::MotionToPhoton),
+            millis => millis
+                .parse()
+                .map(|millis| PosePrediction::Fixed(Duration::from_millis(millis)))
+                .with_context(|| format!("invalid {name}: expected off, auto, or milliseconds")),
+        })
+        .unwrap_or(Ok(PosePrediction::default()))
+}
+
+fn env_second_client(name: &str) -> Result<SecondClient> {
+    env::var(name)
+        .map(|value| match value.to_ascii_lowercase().as_str() {
+            "reject" => Ok(SecondClient::Reject),
+            "handover" => Ok(SecondClient::Handover),
+            _ => anyhow::bail!("invalid {name}: expected reject or handover"),
+        })
+        .unwrap_or(Ok(SecondClient::default()))
+}
+
+fn env_color_range(name: &str) -> Result<ColorRange> {
+    env::var(name)
+        .map(|value| match value.to_ascii_lowercase().as_str() {
+            "limited" | "video" => Ok(ColorRange::Limited),
+            "full" => Ok(ColorRange::Full),
+            _ => anyhow::bail!("invalid {name}: expected limited or full"),
+        })
+        .unwrap_or(Ok(ColorRange::default()))
+}
+
 fn env_bool(name: &str, default: bool) -> Result<bool> {
     env::var(name)
         .map(|value| match value.as_str() {
@@ -546,3 +1175,137 @@ fn env_bool(name: &str, default: bool) -> Result<bool> {
         })
         .unwrap_or(Ok(default))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_encoder::{MockEncoder, mock_config};
+
+    #[test]
+    fn runs_the_probe_loop_against_an_injected_encoder() {
+        let encoder = MockEncoder::new(CodecType::Hevc);
+        let mut reports = 0;
+        let summary =
+            run_surface_probe_with_encoder(mock_config(), encoder, |_| reports += 1).unwrap();
+
+        assert_eq!(summary.submitted_frames, 10);
+        assert_eq!(summary.encoded_frames, 10);
+        assert_eq!(summary.pool_stats.acquired, 10);
+        assert_eq!(summary.pool_stats.recycled, 10);
+        assert!(!summary.hardware_support.hardware_accelerated);
+        assert!(reports > 0);
+    }
+
+    #[test]
+    fn rejects_an_encoder_for_another_codec() {
+        let config = ProbeConfig {
+            codec: CodecType::H264,
+            ..mock_config()
+        };
+        let encoder = MockEncoder::new(CodecType::Hevc);
+        assert!(run_surface_probe_with_encoder(config, encoder, |_| {}).is_err());
+    }
+
+    #[test]
+    fn accepts_a_keyframe_duration_only_when_positive() {
+        let config = |duration| ProbeConfig {
+            keyframe_interval: 0,
+            keyframe_interval_duration: duration,
+            ..mock_config()
+        };
+        assert!(config(None).validate().is_ok());
+        assert!(config(Some(Duration::from_millis(500))).validate().is_ok());
+        assert!(config(Some(Duration::ZERO)).validate().is_err());
+    }
+
+    #[test]
+    fn bounds_the_pipeline_depth() {
+        let config = |pipeline_depth| ProbeConfig {
+            pipeline_depth,
+            ..mock_config()
+        };
+        assert!(config(0).validate().is_err());
+        assert!(config(1).validate().is_ok());
+        assert!(config(MAX_PIPELINE_DEPTH).validate().is_ok());
+        assert!(config(MAX_PIPELINE_DEPTH + 1).validate().is_err());
+    }
+
+    #[test]
+    fn limits_the_software_encoder_to_8_bit_h264() {
+        let config = |codec| ProbeConfig {
+            codec,
+            encoder_backend: EncoderBackend::Software,
+            ..mock_config()
+        };
+        assert!(config(CodecType::Hevc).validate().is_err());
+        assert_eq!(
+            config(CodecType::H264).validate().is_ok(),
+            SOFTWARE_ENCODER_BUILT
+        );
+        assert!(
+            ProbeConfig {
+                format: SurfaceFormat::P010,
+                ..config(CodecType::H264)
+            }
+            .validate()
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn caps_a_fixed_pose_prediction() {
+        let config = |pose_prediction| ProbeConfig {
+            pose_prediction,
+            ..mock_config()
+        };
+        assert!(config(PosePrediction::MotionToPhoton).validate().is_ok());
+        assert!(
+            config(PosePrediction::Fixed(MAX_FIXED_POSE_PREDICTION))
+                .validate()
+                .is_ok()
+        );
+        assert!(
+            config(PosePrediction::Fixed(Duration::from_millis(250)))
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn full_range_rejects_the_cpu_paths_that_assume_limited_range() {
+        let color = ColorSpace {
+            matrix: ColorMatrix::Bt601,
+            range: ColorRange::Full,
+            hdr: None,
+        };
+        assert!(
+            ProbeConfig {
+                color,
+                ..mock_config()
+            }
+            .validate()
+            .is_ok()
+        );
+        assert!(
+            ProbeConfig {
+                color,
+                test_pattern: true,
+                ..mock_config()
+            }
+            .validate()
+            .is_err()
+        );
+        assert!(
+            ProbeConfig {
+                color: ColorSpace {
+                    range: ColorRange::Limited,
+                    ..color
+                },
+                test_pattern: true,
+                ..mock_config()
+            }
+            .validate()
+            .is_ok()
+        );
+    }
+}
@@ -0,0 +1,275 @@
+use crate::{EncodedFrame, encoder::codec_name};
+use alvr_session::CodecType;
+use anyhow::{Context, Result};
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Writes every encoded frame to an Annex B elementary stream alongside the
+/// ALVR transport. Video timestamps go to a matroska v2 timestamp file next to
+/// it, so `mkvmerge --timestamps 0:<path>.timestamps <path>` rebuilds the
+/// original timing.
+pub(crate) struct StreamRecorder {
+    path: PathBuf,
+    stream: File,
+    timestamps: File,
+    first_timestamp: Option<Duration>,
+    frames: u64,
+    bytes: u64,
+}
+
+impl StreamRecorder {
+    pub fn create(path: &Path, codec: CodecType) -> Result<Self> {
+        let stream = File::create(path)
+            .with_context(|| format!("failed to create recording {}", path.display()))?;
+        let timestamps_path = timestamps_path(path);
+        let mut timestamps = File::create(&timestamps_path).with_context(|| {
+            format!(
+                "failed to create recording timestamps {}",
+                timestamps_path.display()
+            )
+        })?;
+        timestamps.write_all(b"# timestamp format v2\n")?;
+        println!(
+            "recording started path={} codec={} timestamps={}",
+            path.display(),
+            codec_name(codec),
+            timestamps_path.display()
+        );
+        Ok(Self {
+            path: path.to_owned(),
+            stream,
+            timestamps,
+            first_timestamp: None,
+            frames: 0,
+            bytes: 0,
+        })
+    }
+
+    /// Appends one frame. Frames before the first keyframe are skipped so
+    /// the file starts decodable; each keyframe carries its parameter sets.
+    pub fn record(&mut self, frame: &EncodedFrame) -> Result<()> {
+        let first_timestamp = match self.first_timestamp {
+            Some(timestamp) => timestamp,
+            None if frame.is_keyframe => {
+                *self.first_timestamp.insert(frame.metadata.video_timestamp)
+            }
+            None => return Ok(()),
+        };
+        let config_nals = frame.decoder_config_nals.as_deref().unwrap_or_default();
+        self.stream
+            .write_all(config_nals)
+            .and_then(|()| self.stream.write_all(&frame.nal_data))
+            .with_context(|| format!("failed to write recording {}", self.path.display()))?;
+        let offset = frame
+            .metadata
+            .video_timestamp
+            .saturating_sub(first_timestamp);
+        writeln!(self.timestamps, "{:.3}", offset.as_secs_f64() * 1000.0)?;
+        self.frames += 1;
+        self.bytes += (config_nals.len() + frame.nal_data.len()) as u64;
+        Ok(())
+    }
+}
+
+impl Drop for StreamRecorder {
+    fn drop(&mut self) {
+        println!(
+            "recording finished path={} frames={} bytes={}",
+            self.path.display(),
+            self.frames,
+            self.bytes
+        );
+    }
+}
+
+/// A byte-exact copy of what the ALVR sink hands to server core: decoder
+/// configuration passed to `set_video_config_nals` and every buffer passed to
+/// `send_video_nal`, including ones server core declined. `<path>.index` has
+/// one CSV row per buffer so a client decode failure can be matched to the
+/// bytes that caused it.
+pub(crate) struct TransportDump {
+    path: PathBuf,
+    stream: File,
+    index: File,
+    offset: u64,
+}
+
+impl TransportDump {
+    pub fn create(path: &Path) -> Result<Self> {
+        let stream = File::create(path)
+            .with_context(|| format!("failed to create transport dump {}", path.display()))?;
+        let index_path = sidecar_path(path, "index");
+        let mut index = File::create(&index_path).with_context(|| {
+            format!(
+                "failed to create transport dump index {}",
+                index_path.display()
+            )
+        })?;
+        index.write_all(
+            b"offset,bytes,kind,stream_epoch,video_timestamp_ns,keyframe,transported\n",
+        )?;
+        println!(
+            "transport_dump started path={} index={}",
+            path.display(),
+            index_path.display()
+        );
+        Ok(Self {
+            path: path.to_owned(),
+            stream,
+            index,
+            offset: 0,
+        })
+    }
+
+    /// Appends `bytes` and returns where they start, for the matching
+    /// `index` call once server core has answered.
+    pub fn write(&mut self, bytes: &[u8]) -> Result<u64> {
+        self.stream
+            .write_all(bytes)
+            .with_context(|| format!("failed to write transport dump {}", self.path.display()))?;
+        let offset = self.offset;
+        self.offset += bytes.len() as u64;
+        Ok(offset)
+    }
+
+    pub fn index(&mut self, offset: u64, entry: TransportDumpEntry) -> Result<()> {
+        writeln!(
+            self.index,
+            "{offset},{},{},{},{},{},{}",
+            self.offset - offset,
+            entry.kind,
+            entry.stream_epoch,
+            entry.video_timestamp.as_nanos(),
+            u8::from(entry.keyframe),
+            u8::from(entry.transported),
+        )
+        .with_context(|| format!("failed to index transport dump {}", self.path.display()))
+    }
+}
+
+pub(crate) struct TransportDumpEntry {
+    pub kind: &'static str,
+    pub stream_epoch: u64,
+    pub video_timestamp: Duration,
+    pub keyframe: bool,
+    pub transported: bool,
+}
+
+fn timestamps_path(path: &Path) -> PathBuf {
+    sidecar_path(path, "timestamps")
+}
+
+fn sidecar_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FrameMetadata, SurfaceLeaseId};
+    use alvr_common::ViewParams;
+    use std::fs;
+
+    fn frame(frame_id: u64, keyframe: bool) -> EncodedFrame {
+        EncodedFrame {
+            lease_id: SurfaceLeaseId {
+                surface_id: 1,
+                generation: frame_id,
+            },
+            metadata: FrameMetadata {
+                frame_id,
+                stream_epoch: 1,
+                video_timestamp: Duration::from_millis(1000 + frame_id * 11),
+                pose_timestamp: Duration::from_millis(1000 + frame_id * 11),
+                global_view_params: [ViewParams::DUMMY; 2],
+            },
+            nal_data: vec![0, 0, 0, 1, frame_id as u8],
+            is_keyframe: keyframe,
+            decoder_config_nals: keyframe.then(|| vec![0, 0, 0, 1, 0x40]),
+            encode_latency: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn records_from_the_first_keyframe_with_timestamps() {
+        let path = std::env::temp_dir().join(format!(
+            "alvr-bridge-recording-{}-{}.hevc",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        let mut recorder = StreamRecorder::create(&path, CodecType::Hevc).unwrap();
+        recorder.record(&frame(1, false)).unwrap();
+        recorder.record(&frame(2, true)).unwrap();
+        recorder.record(&frame(3, false)).unwrap();
+        drop(recorder);
+
+        assert_eq!(
+            fs::read(&path).unwrap(),
+            [0, 0, 0, 1, 0x40, 0, 0, 0, 1, 2, 0, 0, 0, 1, 3]
+        );
+        assert_eq!(
+            fs::read_to_string(timestamps_path(&path)).unwrap(),
+            "# timestamp format v2\n0.000\n11.000\n"
+        );
+        fs::remove_file(timestamps_path(&path)).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn indexes_each_transport_buffer() {
+        let path = std::env::temp_dir().join(format!(
+            "alvr-bridge-transport-{}-{}.bin",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        let mut dump = TransportDump::create(&path).unwrap();
+        let offset = dump.write(&[0, 0, 0, 1, 0x40]).unwrap();
+        dump.index(
+            offset,
+            TransportDumpEntry {
+                kind: "config",
+                stream_epoch: 1,
+                video_timestamp: Duration::from_millis(5),
+                keyframe: true,
+                transported: true,
+            },
+        )
+        .unwrap();
+        let offset = dump.write(&[0, 0, 0, 1, 0x26, 0xaa]).unwrap();
+        dump.index(
+            offset,
+            TransportDumpEntry {
+                kind: "frame",
+                stream_epoch: 1,
+                video_timestamp: Duration::from_millis(5),
+                keyframe: true,
+                transported: false,
+            },
+        )
+        .unwrap();
+        drop(dump);
+
+        assert_eq!(fs::read(&path).unwrap().len(), 11);
+        assert_eq!(
+            fs::read_to_string(sidecar_path(&path, "index")).unwrap(),
+            "offset,bytes,kind,stream_epoch,video_timestamp_ns,keyframe,transported\n\
+             0,5,config,1,5000000,1,1\n\
+             5,6,frame,1,5000000,1,0\n"
+        );
+        fs::remove_file(sidecar_path(&path, "index")).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+}
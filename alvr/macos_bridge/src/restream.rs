@@ -0,0 +1,964 @@
+use crate::{
+    EncodedFrame,
+    encoder::{annexb_nals, is_parameter_set, nal_type},
+    loopback_http::LoopbackServer,
+};
+use alvr_session::CodecType;
+use anyhow::{Context, Result, ensure};
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+const TIMESCALE: u32 = 90_000;
+/// A segment is cut at the first keyframe after this much video, so segments
+/// are as long as the keyframe interval allows.
+const TARGET_SEGMENT: Duration = Duration::from_secs(2);
+/// The live playlist lists this many segments; older ones are forgotten.
+const LIVE_SEGMENTS: usize = 6;
+// A segment at streaming bitrates is several megabytes.
+const REQUEST_IO_TIMEOUT: Duration = Duration::from_secs(10);
+const PLAYER_PAGE: &str = "<!doctype html>\n<title>ALVR macOS bridge</title>\n\
+<body style=\"margin:0;background:#000\">\n\
+<video src=\"index.m3u8\" controls autoplay muted playsinline \
+style=\"width:100vw;height:100vh\"></video>\n";
+
+/// Remuxes the encoded stream into fragmented MP4 and serves it as a live HLS
+/// playlist on `127.0.0.1:<port>`, so a browser can watch without a second
+/// encode. Segments only start on keyframes, which the bridge does not add
+/// for the restream's sake.
+pub(crate) struct Restreamer {
+    packager: Arc<Mutex<HlsPackager>>,
+    server: LoopbackServer,
+}
+
+impl Restreamer {
+    pub fn bind(port: u16, codec: CodecType) -> Result<Self> {
+        let packager = Arc::new(Mutex::new(HlsPackager::new(codec)));
+        let server = LoopbackServer::bind(
+            "restream",
+            port,
+            REQUEST_IO_TIMEOUT,
+            "Cache-Control: no-cache\r\n",
+            {
+                let packager = Arc::clone(&packager);
+                move |path| lock(&packager).file(path)
+            },
+        )?;
+        eprintln!("restream listening address=http://{}/", server.address());
+
+        Ok(Self { packager, server })
+    }
+
+    pub fn push(&mut self, frame: &EncodedFrame) -> Result<()> {
+        lock(&self.packager).push(frame)
+    }
+
+    /// Takes effect with the keyframe that opens the renegotiated stream.
+    pub fn set_codec(&mut self, codec: CodecType) {
+        lock(&self.packager).codec = codec;
+    }
+}
+
+fn lock(packager: &Mutex<HlsPackager>) -> std::sync::MutexGuard<'_, HlsPackager> {
+    packager.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+struct InitSegment {
+    id: u64,
+    data: Arc<[u8]>,
+}
+
+struct MediaSegment {
+    sequence: u64,
+    init_id: u64,
+    duration: Duration,
+    data: Arc<[u8]>,
+}
+
+struct Sample {
+    decode_time: u64,
+    size: u32,
+    keyframe: bool,
+}
+
+struct OpenSegment {
+    init_id: u64,
+    samples: Vec<Sample>,
+    payload: Vec<u8>,
+}
+
+/// Cuts frames into keyframe-aligned fMP4 segments and keeps the last few
+/// for the playlist. New parameter sets, from a renegotiated size or codec,
+/// start a new initialization segment behind a discontinuity.
+struct HlsPackager {
+    codec: CodecType,
+    parameter_sets: Vec<u8>,
+    inits: VecDeque<InitSegment>,
+    segments: VecDeque<MediaSegment>,
+    next_init_id: u64,
+    next_sequence: u64,
+    discontinuity_sequence: u64,
+    open: Option<OpenSegment>,
+    first_timestamp: Option<Duration>,
+}
+
+impl HlsPackager {
+    fn new(codec: CodecType) -> Self {
+        Self {
+            codec,
+            parameter_sets: Vec::new(),
+            inits: VecDeque::new(),
+            segments: VecDeque::new(),
+            next_init_id: 0,
+            next_sequence: 0,
+            discontinuity_sequence: 0,
+            open: None,
+            first_timestamp: None,
+        }
+    }
+
+    fn push(&mut self, frame: &EncodedFrame) -> Result<()> {
+        ensure!(
+            self.codec != CodecType::AV1,
+            "AV1 cannot be restreamed; only HEVC and H.264 are packaged"
+        );
+        if frame.is_keyframe
+            && let Some(config) = frame.decoder_config_nals.as_deref()
+            && config != self.parameter_sets
+        {
+            let data = init_segment(self.codec, config)?;
+            self.inits.push_back(InitSegment {
+                id: self.next_init_id,
+                data: data.into(),
+            });
+            self.next_init_id += 1;
+            self.parameter_sets = config.to_vec();
+        }
+        // Nothing plays before the first parameter sets.
+        let Some(init_id) = self.inits.back().map(|init| init.id) else {
+            return Ok(());
+        };
+        let first_timestamp = *self
+            .first_timestamp
+            .get_or_insert(frame.metadata.video_timestamp);
+        let mut decode_time = ticks(
+            frame
+                .metadata
+                .video_timestamp
+                .saturating_sub(first_timestamp),
+        );
+        if let Some(last) = self.open.as_ref().and_then(|open| open.samples.last()) {
+            decode_time = decode_time.max(last.decode_time + 1);
+        }
+        if frame.is_keyframe
+            && self.open.as_ref().is_some_and(|open| {
+                open.init_id != init_id
+                    || decode_time - open.samples[0].decode_time >= ticks(TARGET_SEGMENT)
+            })
+        {
+            self.close_segment(decode_time);
+        }
+        if self.open.is_none() && !frame.is_keyframe {
+            return Ok(());
+        }
+        let open = self.open.get_or_insert_with(|| OpenSegment {
+            init_id,
+            samples: Vec::new(),
+            payload: Vec::new(),
+        });
+        let start = open.payload.len();
+        // Parameter sets live in the initialization segment.
+        for nal in annexb_nals(&frame.nal_data) {
+            if !is_parameter_set(self.codec, nal) {
+                open.payload.extend(u32::try_from(nal.len())?.to_be_bytes());
+                open.payload.extend_from_slice(nal);
+            }
+        }
+        open.samples.push(Sample {
+            decode_time,
+            size: u32::try_from(open.payload.len() - start)?,
+            keyframe: frame.is_keyframe,
+        });
+        Ok(())
+    }
+
+    fn close_segment(&mut self, end_time: u64) {
+        let Some(open) = self.open.take() else {
+            return;
+        };
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        let start_time = open.samples[0].decode_time;
+        self.segments.push_back(MediaSegment {
+            sequence,
+            init_id: open.init_id,
+            duration: Duration::from_secs_f64(
+                (end_time - start_time) as f64 / f64::from(TIMESCALE),
+            ),
+            data: media_segment(sequence, &open.samples, end_time, &open.payload).into(),
+        });
+        while self.segments.len() > LIVE_SEGMENTS {
+            let removed = self.segments.pop_front().expect("segments is not empty");
+            // The first listed segment carries no discontinuity tag, so the
+            // one that was in front of the new first segment drops out.
+            if self
+                .segments
+                .front()
+                .is_some_and(|next| next.init_id != removed.init_id)
+            {
+                self.discontinuity_sequence += 1;
+            }
+        }
+        let oldest_init = self
+            .segments
+            .front()
+            .map_or(open.init_id, |segment| segment.init_id);
+        self.inits.retain(|init| init.id >= oldest_init);
+    }
+
+    fn playlist(&self) -> String {
+        let target_duration = self
+            .segments
+            .iter()
+            .map(|segment| segment.duration.as_secs_f64().ceil() as u64)
+            .max()
+            .unwrap_or(0)
+            .max(TARGET_SEGMENT.as_secs());
+        let mut playlist = format!(
+            "#EXTM3U\n#EXT-X-VERSION:7\n#EXT-X-TARGETDURATION:{target_duration}\n#EXT-X-MEDIA-SEQUENCE:{}\n#EXT-X-DISCONTINUITY-SEQUENCE:{}\n#EXT-X-INDEPENDENT-SEGMENTS\n",
+            self.segments
+                .front()
+                .map_or(self.next_sequence, |segment| segment.sequence),
+            self.discontinuity_sequence,
+        );
+        let mut previous_init = None;
+        for segment in &self.segments {
+            if previous_init != Some(segment.init_id) {
+                if previous_init.is_some() {
+                    playlist.push_str("#EXT-X-DISCONTINUITY\n");
+                }
+                let _ = writeln!(playlist, "#EXT-X-MAP:URI=\"init-{}.mp4\"", segment.init_id);
+                previous_init = Some(segment.init_id);
+            }
+            let _ = writeln!(
+                playlist,
+                "#EXTINF:{:.3},\nsegment-{}.m4s",
+                segment.duration.as_secs_f64(),
+                segment.sequence
+            );
+        }
+        playlist
+    }
+
+    fn file(&self, path: &str) -> Option<(&'static str, Arc<[u8]>)> {
+        let name = path.strip_prefix('/')?;
+        if name.is_empty() {
+            return Some((
+                "text/html; charset=utf-8",
+                Arc::from(PLAYER_PAGE.as_bytes()),
+            ));
+        }
+        if name == "index.m3u8" {
+            return Some((
+                "application/vnd.apple.mpegurl",
+                Arc::from(self.playlist().into_bytes()),
+            ));
+        }
+        if let Some(id) = name
+            .strip_prefix("init-")
+            .and_then(|rest| rest.strip_suffix(".mp4"))
+        {
+            let id = id.parse::<u64>().ok()?;
+            let init = self.inits.iter().find(|init| init.id == id)?;
+            return Some(("video/mp4", Arc::clone(&init.data)));
+        }
+        let sequence = name
+            .strip_prefix("segment-")?
+            .strip_suffix(".m4s")?
+            .parse::<u64>()
+            .ok()?;
+        let segment = self
+            .segments
+            .iter()
+            .find(|segment| segment.sequence == sequence)?;
+        Some(("video/iso.segment", Arc::clone(&segment.data)))
+    }
+}
+
+fn ticks(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos() * u128::from(TIMESCALE) / 1_000_000_000).unwrap_or(u64::MAX)
+}
+
+/// What the initialization segment needs from the sequence parameter set.
+#[derive(Debug, PartialEq, Eq)]
+struct SequenceInfo {
+    width: u32,
+    height: u32,
+    chroma_format_idc: u32,
+    bit_depth_luma_minus8: u32,
+    bit_depth_chroma_minus8: u32,
+    /// HEVC only: the general profile, tier and level bytes `hvcC` copies.
+    profile_tier_level: Vec<u8>,
+    sub_layers: u8,
+    temporal_id_nested: bool,
+}
+
+/// Removes emulation prevention bytes.
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for &byte in data {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+    rbsp
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32> {
+        let mut value = 0u64;
+        for _ in 0..count {
+            let byte = *self
+                .data
+                .get(self.position / 8)
+                .context("parameter set ends early")?;
+            value = (value << 1) | u64::from((byte >> (7 - self.position % 8)) & 1);
+            self.position += 1;
+        }
+        Ok(u32::try_from(value)?)
+    }
+
+    fn flag(&mut self) -> Result<bool> {
+        Ok(self.bits(1)? == 1)
+    }
+
+    fn skip(&mut self, count: usize) -> Result<()> {
+        self.position += count;
+        ensure!(
+            self.position <= self.data.len() * 8,
+            "parameter set ends early"
+        );
+        Ok(())
+    }
+
+    fn ue(&mut self) -> Result<u32> {
+        let mut zeros = 0;
+        while !self.flag()? {
+            zeros += 1;
+            ensure!(zeros < 32, "exp-Golomb code is too long");
+        }
+        Ok(((1u64 << zeros) - 1 + u64::from(self.bits(zeros)?)).try_into()?)
+    }
+
+    fn se(&mut self) -> Result<i32> {
+        let code = self.ue()?;
+        let magnitude = i32::try_from(code.div_ceil(2))?;
+        Ok(if code % 2 == 1 { magnitude } else { -magnitude })
+    }
+}
+
+fn parse_hevc_sps(nal: &[u8]) -> Result<SequenceInfo> {
+    let rbsp = unescape(nal.get(2..).context("HEVC SPS is empty")?);
+    let mut reader = BitReader::new(&rbsp);
+    reader.bits(4)?;
+    let max_sub_layers_minus1 = reader.bits(3)? as u8;
+    let temporal_id_nested = reader.flag()?;
+    let profile_tier_level = rbsp.get(1..13).context("HEVC SPS ends early")?.to_vec();
+    reader.skip(96)?;
+    let mut sub_layers_present = Vec::new();
+    for _ in 0..max_sub_layers_minus1 {
+        sub_layers_present.push((reader.flag()?, reader.flag()?));
+    }
+    if max_sub_layers_minus1 > 0 {
+        reader.skip(2 * (8 - usize::from(max_sub_layers_minus1)))?;
+    }
+    for (profile_present, level_present) in sub_layers_present {
+        if profile_present {
+            reader.skip(88)?;
+        }
+        if level_present {
+            reader.skip(8)?;
+        }
+    }
+    reader.ue()?;
+    let chroma_format_idc = reader.ue()?;
+    if chroma_format_idc == 3 {
+        reader.skip(1)?;
+    }
+    let mut width = reader.ue()?;
+    let mut height = reader.ue()?;
+    if reader.flag()? {
+        let (left, right, top, bottom) = (reader.ue()?, reader.ue()?, reader.ue()?, reader.ue()?);
+        let (sub_width, sub_height) = match chroma_format_idc {
+            1 => (2, 2),
+            2 => (2, 1),
+            _ => (1, 1),
+        };
+        width = width.saturating_sub(sub_width * (left + right));
+        height = height.saturating_sub(sub_height * (top + bottom));
+    }
+    Ok(SequenceInfo {
+        width,
+        height,
+        chroma_format_idc,
+        bit_depth_luma_minus8: reader.ue()?,
+        bit_depth_chroma_minus8: reader.ue()?,
+        profile_tier_level,
+        sub_layers: max_sub_layers_minus1 + 1,
+        temporal_id_nested,
+    })
+}
+
+fn parse_h264_sps(nal: &[u8]) -> Result<SequenceInfo> {
+    let rbsp = unescape(nal.get(1..).context("H.264 SPS is empty")?);
+    let mut reader = BitReader::new(&rbsp);
+    let profile_idc = reader.bits(8)?;
+    reader.skip(16)?;
+    reader.ue()?;
+    let mut chroma_format_idc = 1;
+    let mut bit_depth_luma_minus8 = 0;
+    let mut bit_depth_chroma_minus8 = 0;
+    if matches!(
+        profile_idc,
+        100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+    ) {
+        chroma_format_idc = reader.ue()?;
+        if chroma_format_idc == 3 {
+            reader.skip(1)?;
+        }
+        bit_depth_luma_minus8 = reader.ue()?;
+        bit_depth_chroma_minus8 = reader.ue()?;
+        reader.skip(1)?;
+        if reader.flag()? {
+            let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for list in 0..lists {
+                if reader.flag()? {
+                    skip_scaling_list(&mut reader, if list < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+    reader.ue()?;
+    match reader.ue()? {
+        0 => {
+            reader.ue()?;
+        }
+        1 => {
+            reader.skip(1)?;
+            reader.se()?;
+            reader.se()?;
+            for _ in 0..reader.ue()? {
+                reader.se()?;
+            }
+        }
+        _ => {}
+    }
+    reader.ue()?;
+    reader.skip(1)?;
+    let width_in_macroblocks = reader.ue()? + 1;
+    let height_in_map_units = reader.ue()? + 1;
+    let frame_mbs_only = reader.flag()?;
+    if !frame_mbs_only {
+        reader.skip(1)?;
+    }
+    reader.skip(1)?;
+    let field_factor = if frame_mbs_only { 1 } else { 2 };
+    let mut width = width_in_macroblocks * 16;
+    let mut height = height_in_map_units * 16 * field_factor;
+    if reader.flag()? {
+        let (left, right, top, bottom) = (reader.ue()?, reader.ue()?, reader.ue()?, reader.ue()?);
+        let (crop_width, crop_height) = match chroma_format_idc {
+            0 | 3 => (1, field_factor),
+            1 => (2, 2 * field_factor),
+            _ => (2, field_factor),
+        };
+        width = width.saturating_sub(crop_width * (left + right));
+        height = height.saturating_sub(crop_height * (top + bottom));
+    }
+    Ok(SequenceInfo {
+        width,
+        height,
+        chroma_format_idc,
+        bit_depth_luma_minus8,
+        bit_depth_chroma_minus8,
+        profile_tier_level: Vec::new(),
+        sub_layers: 1,
+        temporal_id_nested: true,
+    })
+}
+
+fn skip_scaling_list(reader: &mut BitReader<'_>, size: usize) -> Result<()> {
+    let mut last_scale = 8;
+    let mut next_scale = 8;
+    for _ in 0..size {
+        if next_scale != 0 {
+            next_scale = (last_scale + reader.se()? + 256) % 256;
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Ok(())
+}
+
+fn mp4_box(out: &mut Vec<u8>, kind: &[u8; 4], body: impl FnOnce(&mut Vec<u8>)) {
+    let start = out.len();
+    out.extend([0; 4]);
+    out.extend(kind);
+    body(out);
+    let size = u32::try_from(out.len() - start).expect("MP4 box is over 4 GiB");
+    out[start..start + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+fn full_box(
+    out: &mut Vec<u8>,
+    kind: &[u8; 4],
+    version: u8,
+    flags: u32,
+    body: impl FnOnce(&mut Vec<u8>),
+) {
+    mp4_box(out, kind, |out| {
+        out.push(version);
+        out.extend(&flags.to_be_bytes()[1..]);
+        body(out);
+    });
+}
+
+const IDENTITY_MATRIX: [u32; 9] = [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000];
+
+fn init_segment(codec: CodecType, parameter_sets: &[u8]) -> Result<Vec<u8>> {
+    let nals = annexb_nals(parameter_sets);
+    let find = |wanted: u8| {
+        nals.iter()
+            .copied()
+            .find(|nal| nal_type(codec, nal) == wanted)
+            .with_context(|| format!("keyframe parameter sets lack NAL type {wanted}"))
+    };
+    let (sample_entry, config_kind, info, config) = match codec {
+        CodecType::Hevc => {
+            let (vps, sps, pps) = (find(32)?, find(33)?, find(34)?);
+            let info = parse_hevc_sps(sps)?;
+            let config = hvcc(&info, vps, sps, pps)?;
+            (b"hvc1", b"hvcC", info, config)
+        }
+        _ => {
+            let (sps, pps) = (find(7)?, find(8)?);
+            let info = parse_h264_sps(sps)?;
+            let config = avcc(&info, sps, pps)?;
+            (b"avc1", b"avcC", info, config)
+        }
+    };
+    let width = u16::try_from(info.width)?;
+    let height = u16::try_from(info.height)?;
+
+    let mut out = Vec::new();
+    mp4_box(&mut out, b"ftyp", |out| {
+        out.extend(b"iso6");
+        out.extend(0u32.to_be_bytes());
+        out.extend(b"iso6mp41");
+    });
+    mp4_box(&mut out, b"moov", |out| {
+        full_box(out, b"mvhd", 0, 0, |out| {
+            out.extend([0; 8]);
+            out.extend(TIMESCALE.to_be_bytes());
+            out.extend(0u32.to_be_bytes());
+            out.extend(0x0001_0000u32.to_be_bytes());
+            out.extend(0x0100u16.to_be_bytes());
+            out.extend([0; 10]);
+            IDENTITY_MATRIX
+                .iter()
+                .for_each(|value| out.extend(value.to_be_bytes()));
+            out.extend([0; 24]);
+            out.extend(2u32.to_be_bytes());
+        });
+        mp4_box(out, b"trak", |out| {
+            full_box(out, b"tkhd", 0, 3, |out| {
+                out.extend([0; 8]);
+                out.extend(1u32.to_be_bytes());
+                out.extend([0; 4]);
+                out.extend(0u32.to_be_bytes());
+                out.extend([0; 16]);
+                IDENTITY_MATRIX
+                    .iter()
+                    .for_each(|value| out.extend(value.to_be_bytes()));
+                out.extend((u32::from(width) << 16).to_be_bytes());
+                out.extend((u32::from(height) << 16).to_be_bytes());
+            });
+            mp4_box(out, b"mdia", |out| {
+                full_box(out, b"mdhd", 0, 0, |out| {
+                    out.extend([0; 8]);
+                    out.extend(TIMESCALE.to_be_bytes());
+                    out.extend(0u32.to_be_bytes());
+                    // "und", packed as three five-bit letters.
+                    out.extend(0x55c4u16.to_be_bytes());
+                    out.extend([0; 2]);
+                });
+                full_box(out, b"hdlr", 0, 0, |out| {
+                    out.extend([0; 4]);
+                    out.extend(b"vide");
+                    out.extend([0; 12]);
+                    out.extend(b"VideoHandler\0");
+                });
+                mp4_box(out, b"minf", |out| {
+                    full_box(out, b"vmhd", 0, 1, |out| out.extend([0; 8]));
+                    mp4_box(out, b"dinf", |out| {
+                        full_box(out, b"dref", 0, 0, |out| {
+                            out.extend(1u32.to_be_bytes());
+                            full_box(out, b"url ", 0, 1, |_| {});
+                        });
+                    });
+                    mp4_box(out, b"stbl", |out| {
+                        full_box(out, b"stsd", 0, 0, |out| {
+                            out.extend(1u32.to_be_bytes());
+                            mp4_box(out, sample_entry, |out| {
+                                out.extend([0; 6]);
+                                out.extend(1u16.to_be_bytes());
+                                out.extend([0; 16]);
+                                out.extend(width.to_be_bytes());
+                                out.extend(height.to_be_bytes());
+                                out.extend(0x0048_0000u32.to_be_bytes());
+                                out.extend(0x0048_0000u32.to_be_bytes());
+                                out.extend([0; 4]);
+                                out.extend(1u16.to_be_bytes());
+                                out.extend([0; 32]);
+                                out.extend(0x0018u16.to_be_bytes());
+                                out.extend((-1i16).to_be_bytes());
+                                mp4_box(out, config_kind, |out| out.extend(&config));
+                            });
+                        });
+                        full_box(out, b"stts", 0, 0, |out| out.extend([0; 4]));
+                        full_box(out, b"stsc", 0, 0, |out| out.extend([0; 4]));
+                        full_box(out, b"stsz", 0, 0, |out| out.extend([0; 8]));
+                        full_box(out, b"stco", 0, 0, |out| out.extend([0; 4]));
+                    });
+                });
+            });
+        });
+        mp4_box(out, b"mvex", |out| {
+            full_box(out, b"trex", 0, 0, |out| {
+                out.extend(1u32.to_be_bytes());
+                out.extend(1u32.to_be_bytes());
+                out.extend([0; 12]);
+            });
+        });
+    });
+    Ok(out)
+}
+
+fn hvcc(info: &SequenceInfo, vps: &[u8], sps: &[u8], pps: &[u8]) -> Result<Vec<u8>> {
+    let mut config = vec![1];
+    config.extend(&info.profile_tier_level);
+    // No minimum spatial segmentation, and unknown parallelism.
+    config.extend([0xf0, 0x00, 0xfc]);
+    config.push(0xfc | u8::try_from(info.chroma_format_idc)?);
+    config.push(0xf8 | u8::try_from(info.bit_depth_luma_minus8)?);
+    config.push(0xf8 | u8::try_from(info.bit_depth_chroma_minus8)?);
+    config.extend(0u16.to_be_bytes());
+    // Four-byte NAL lengths.
+    config.push((info.sub_layers << 3) | (u8::from(info.temporal_id_nested) << 2) | 3);
+    config.push(3);
+    for (kind, nal) in [(32u8, vps), (33, sps), (34, pps)] {
+        config.push(0x80 | kind);
+        config.extend(1u16.to_be_bytes());
+        config.extend(u16::try_from(nal.len())?.to_be_bytes());
+        config.extend(nal);
+    }
+    Ok(config)
+}
+
+fn avcc(info: &SequenceInfo, sps: &[u8], pps: &[u8]) -> Result<Vec<u8>> {
+    let profile = sps.get(1..4).context("H.264 SPS ends early")?;
+    let mut config = vec![1];
+    config.extend(profile);
+    config.extend([0xff, 0xe1]);
+    config.extend(u16::try_from(sps.len())?.to_be_bytes());
+    config.extend(sps);
+    config.push(1);
+    config.extend(u16::try_from(pps.len())?.to_be_bytes());
+    config.extend(pps);
+    if matches!(profile[0], 100 | 110 | 122 | 144) {
+        config.push(0xfc | u8::try_from(info.chroma_format_idc)?);
+        config.push(0xf8 | u8::try_from(info.bit_depth_luma_minus8)?);
+        config.push(0xf8 | u8::try_from(info.bit_depth_chroma_minus8)?);
+        config.push(0);
+    }
+    Ok(config)
+}
+
+fn media_segment(sequence: u64, samples: &[Sample], end_time: u64, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 128 + samples.len() * 12);
+    let mut data_offset_at = 0;
+    mp4_box(&mut out, b"moof", |out| {
+        full_box(out, b"mfhd", 0, 0, |out| {
+            out.extend((sequence as u32).to_be_bytes());
+        });
+        mp4_box(out, b"traf", |out| {
+            // The sample data offset counts from the start of `moof`.
+            full_box(out, b"tfhd", 0, 0x02_0000, |out| {
+                out.extend(1u32.to_be_bytes())
+            });
+            full_box(out, b"tfdt", 1, 0, |out| {
+                out.extend(samples[0].decode_time.to_be_bytes());
+            });
+            // Data offset, and a duration, size and flags per sample.
+            full_box(out, b"trun", 0, 0x0701, |out| {
+                out.extend((samples.len() as u32).to_be_bytes());
+                data_offset_at = out.len();
+                out.extend(0u32.to_be_bytes());
+                for (index, sample) in samples.iter().enumerate() {
+                    let next = samples
+                        .get(index + 1)
+                        .map_or(end_time, |next| next.decode_time);
+                    let duration = u32::try_from(next - sample.decode_time).unwrap_or(u32::MAX);
+                    out.extend(duration.to_be_bytes());
+                    out.extend(sample.size.to_be_bytes());
+                    // A keyframe depends on nothing; anything else depends on
+                    // earlier frames and is not a sync sample.
+                    let flags: u32 = if sample.keyframe {
+                        0x0200_0000
+                    } else {
+                        0x0101_0000
+                    };
+                    out.extend(flags.to_be_bytes());
+                }
+            });
+        });
+    });
+    let data_offset = (out.len() + 8) as u32;
+    out[data_offset_at..data_offset_at + 4].copy_from_slice(&data_offset.to_be_bytes());
+    mp4_box(&mut out, b"mdat", |out| out.extend_from_slice(payload));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FrameMetadata, SurfaceLeaseId};
+    use alvr_common::ViewParams;
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+    };
+
+    #[derive(Default)]
+    struct BitWriter {
+        bytes: Vec<u8>,
+        bits: usize,
+    }
+
+    impl BitWriter {
+        fn bits(&mut self, value: u32, count: u32) -> &mut Self {
+            for shift in (0..count).rev() {
+                if self.bits % 8 == 0 {
+                    self.bytes.push(0);
+                }
+                *self.bytes.last_mut().unwrap() |=
+                    (((value >> shift) & 1) as u8) << (7 - self.bits % 8);
+                self.bits += 1;
+            }
+            self
+        }
+
+        fn ue(&mut self, value: u32) -> &mut Self {
+            let code = value + 1;
+            let length = 32 - code.leading_zeros();
+            self.bits(0, length - 1).bits(code, length)
+        }
+
+        fn finish(&mut self) -> Vec<u8> {
+            self.bits(1, 1);
+            std::mem::take(&mut self.bytes)
+        }
+    }
+
+    // Baseline 1280x720 with no cropping.
+    fn h264_sps() -> Vec<u8> {
+        let mut sps = vec![0x67];
+        sps.extend(
+            BitWriter::default()
+                .bits(66, 8)
+                .bits(0xc0, 8)
+                .bits(31, 8)
+                .ue(0)
+                .ue(0)
+                .ue(2)
+                .ue(1)
+                .bits(0, 1)
+                .ue(79)
+                .ue(44)
+                .bits(1, 1)
+                .bits(1, 1)
+                .bits(0, 1)
+                .bits(0, 1)
+                .finish(),
+        );
+        sps
+    }
+
+    // Main profile 1920x1080, coded as 1920x1088 with 8 rows cropped.
+    fn hevc_sps() -> Vec<u8> {
+        let mut sps = vec![0x42, 0x01];
+        let mut writer = BitWriter::default();
+        writer.bits(0, 4).bits(0, 3).bits(1, 1);
+        writer
+            .bits(0x01, 8)
+            .bits(0x6000_0000, 32)
+            .bits(0x9000, 16)
+            .bits(0, 32);
+        writer.bits(93, 8);
+        writer
+            .ue(0)
+            .ue(1)
+            .ue(1920)
+            .ue(1088)
+            .bits(1, 1)
+            .ue(0)
+            .ue(0)
+            .ue(0)
+            .ue(4);
+        writer.ue(0).ue(0);
+        sps.extend(writer.finish());
+        sps
+    }
+
+    fn annexb(nals: &[&[u8]]) -> Vec<u8> {
+        nals.iter()
+            .flat_map(|nal| [&[0, 0, 0, 1][..], nal].concat())
+            .collect()
+    }
+
+    fn frame(timestamp_ms: u64, keyframe: bool) -> EncodedFrame {
+        EncodedFrame {
+            lease_id: SurfaceLeaseId {
+                surface_id: 0,
+                generation: 0,
+            },
+            metadata: FrameMetadata {
+                frame_id: timestamp_ms,
+                stream_epoch: 0,
+                video_timestamp: Duration::from_millis(timestamp_ms),
+                pose_timestamp: Duration::ZERO,
+                global_view_params: [ViewParams::DUMMY; 2],
+            },
+            nal_data: annexb(&[if keyframe {
+                &[0x65, 0x88]
+            } else {
+                &[0x41, 0x9a]
+            }]),
+            is_keyframe: keyframe,
+            decoder_config_nals: keyframe.then(|| annexb(&[&h264_sps(), &[0x68, 0xce]])),
+            encode_latency: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn reads_the_picture_size_from_either_sps() {
+        let h264 = parse_h264_sps(&h264_sps()).unwrap();
+        assert_eq!(
+            (h264.width, h264.height, h264.chroma_format_idc),
+            (1280, 720, 1)
+        );
+
+        let hevc = parse_hevc_sps(&hevc_sps()).unwrap();
+        assert_eq!((hevc.width, hevc.height), (1920, 1080));
+        assert_eq!(hevc.profile_tier_level[0], 0x01);
+        assert_eq!(hevc.profile_tier_level[11], 93);
+        assert_eq!(hevc.sub_layers, 1);
+    }
+
+    #[test]
+    fn removes_emulation_prevention_bytes() {
+        assert_eq!(unescape(&[0x42, 0, 0, 3, 1]), [0x42, 0, 0, 1]);
+    }
+
+    #[test]
+    fn cuts_segments_at_keyframes_after_the_target() {
+        let mut packager = HlsPackager::new(CodecType::H264);
+        // Frames before the first keyframe have nothing to decode against.
+        packager.push(&frame(0, false)).unwrap();
+        for timestamp_ms in (0..5000).step_by(500) {
+            packager
+                .push(&frame(1000 + timestamp_ms, timestamp_ms % 1000 == 0))
+                .unwrap();
+        }
+
+        assert_eq!(packager.segments.len(), 2);
+        assert_eq!(packager.segments[0].duration, Duration::from_secs(2));
+        let playlist = packager.playlist();
+        assert!(
+            playlist.contains("#EXT-X-MAP:URI=\"init-0.mp4\"\n#EXTINF:2.000,\nsegment-0.m4s\n")
+        );
+        assert!(!playlist.contains("DISCONTINUITY\n"));
+
+        let (_, segment) = packager.file("/segment-0.m4s").unwrap();
+        let moof_size = u32::from_be_bytes(segment[0..4].try_into().unwrap()) as usize;
+        assert_eq!(&segment[moof_size + 4..moof_size + 8], b"mdat");
+        // Four samples of a length prefix and two bytes each.
+        assert_eq!(segment.len(), moof_size + 8 + 4 * 6);
+        assert_eq!(
+            &segment[moof_size + 8..moof_size + 14],
+            [0, 0, 0, 2, 0x65, 0x88]
+        );
+
+        let (content_type, init) = packager.file("/init-0.mp4").unwrap();
+        assert_eq!(content_type, "video/mp4");
+        assert_eq!(&init[4..8], b"ftyp");
+        assert!(init.windows(4).any(|window| window == b"avcC"));
+        assert!(packager.file("/segment-9.m4s").is_none());
+    }
+
+    #[test]
+    fn new_parameter_sets_start_a_discontinuity() {
+        let mut packager = HlsPackager::new(CodecType::H264);
+        packager.push(&frame(0, true)).unwrap();
+        let mut resized = frame(500, true);
+        let mut sps = h264_sps();
+        sps[3] = 40;
+        resized.decoder_config_nals = Some(annexb(&[&sps, &[0x68, 0xce]]));
+        packager.push(&resized).unwrap();
+        packager.push(&frame(1000, false)).unwrap();
+        packager.close_segment(ticks(Duration::from_millis(1500)));
+
+        let playlist = packager.playlist();
+        assert!(
+            playlist
+                .contains("segment-0.m4s\n#EXT-X-DISCONTINUITY\n#EXT-X-MAP:URI=\"init-1.mp4\"\n")
+        );
+    }
+
+    #[test]
+    fn serves_the_playlist_over_http() {
+        let restreamer = Restreamer::bind(0, CodecType::H264).unwrap();
+
+        let mut stream = TcpStream::connect(restreamer.server.address()).unwrap();
+        stream
+            .write_all(
+                format!(
+                    "GET /index.m3u8 HTTP/1.1\r\nHost: localhost:{}\r\n\r\n",
+                    restreamer.server.address().port()
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("#EXT-X-INDEPENDENT-SEGMENTS\n"));
+    }
+}
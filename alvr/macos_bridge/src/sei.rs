@@ -0,0 +1,108 @@
+use crate::FrameMetadata;
+use alvr_session::CodecType;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const NAL_START_CODE: [u8; 4] = [0, 0, 0, 1];
+/// H.264 `nal_unit_type` 6.
+const H264_SEI_HEADER: [u8; 1] = [6];
+/// HEVC `PREFIX_SEI_NUT` (39) in layer 0 and temporal sublayer 0.
+const HEVC_PREFIX_SEI_HEADER: [u8; 2] = [39 << 1, 1];
+const USER_DATA_UNREGISTERED: u8 = 5;
+/// Identifies the bridge's timing payload among other unregistered user data:
+/// `4a5c1e0b-8f3d-4b6a-9e27-c1d08f5a7b34`.
+pub const TIMING_SEI_UUID: [u8; 16] = [
+    0x4a, 0x5c, 0x1e, 0x0b, 0x8f, 0x3d, 0x4b, 0x6a, 0x9e, 0x27, 0xc1, 0xd0, 0x8f, 0x5a, 0x7b, 0x34,
+];
+
+/// One Annex B SEI NAL holding `messages` as (payload type, payload) pairs,
+/// with emulation prevention applied.
+pub(crate) fn sei_nal(codec: CodecType, messages: &[(u8, &[u8])]) -> Vec<u8> {
+    let mut rbsp = Vec::new();
+    for (payload_type, payload) in messages {
+        rbsp.push(*payload_type);
+        let mut size = payload.len();
+        while size >= 255 {
+            rbsp.push(255);
+            size -= 255;
+        }
+        rbsp.push(size as u8);
+        rbsp.extend_from_slice(payload);
+    }
+    rbsp.push(0x80);
+
+    let mut nal = Vec::with_capacity(rbsp.len() + rbsp.len() / 64 + 8);
+    nal.extend(NAL_START_CODE);
+    match codec {
+        CodecType::H264 => nal.extend(H264_SEI_HEADER),
+        _ => nal.extend(HEVC_PREFIX_SEI_HEADER),
+    }
+    let mut zeros = 0;
+    for byte in rbsp {
+        if zeros == 2 && byte <= 3 {
+            nal.push(3);
+            zeros = 0;
+        }
+        nal.push(byte);
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+    }
+    nal
+}
+
+/// The per-frame timing SEI: [`TIMING_SEI_UUID`], then the frame id, the
+/// video timestamp in nanoseconds, and the bridge's send time in
+/// nanoseconds since the Unix epoch, each a big-endian u64.
+pub(crate) fn timing_sei_nal(
+    codec: CodecType,
+    metadata: &FrameMetadata,
+    sent_at: SystemTime,
+) -> Vec<u8> {
+    let nanos =
+        |duration: std::time::Duration| u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+    let mut payload = Vec::with_capacity(40);
+    payload.extend(TIMING_SEI_UUID);
+    payload.extend(metadata.frame_id.to_be_bytes());
+    payload.extend(nanos(metadata.video_timestamp).to_be_bytes());
+    payload.extend(nanos(sent_at.duration_since(UNIX_EPOCH).unwrap_or_default()).to_be_bytes());
+    sei_nal(codec, &[(USER_DATA_UNREGISTERED, &payload)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_common::ViewParams;
+    use std::time::Duration;
+
+    #[test]
+    fn frames_timing_payloads_for_each_codec() {
+        let metadata = FrameMetadata {
+            frame_id: 7,
+            stream_epoch: 0,
+            video_timestamp: Duration::from_nanos(0x0102_0304),
+            pose_timestamp: Duration::ZERO,
+            global_view_params: [ViewParams::DUMMY; 2],
+        };
+        let sent_at = UNIX_EPOCH + Duration::from_nanos(0x1122_3344_5566_7788);
+
+        let hevc = timing_sei_nal(CodecType::Hevc, &metadata, sent_at);
+        assert_eq!(hevc[..8], [0, 0, 0, 1, 0x4e, 0x01, 5, 40]);
+        assert_eq!(hevc[8..24], TIMING_SEI_UUID);
+        // Leading zero bytes of the frame id pick up emulation prevention.
+        assert_eq!(hevc[24..35], [0, 0, 3, 0, 0, 3, 0, 0, 3, 0, 7]);
+        assert_eq!(
+            hevc[hevc.len() - 9..],
+            [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x80]
+        );
+
+        let h264 = timing_sei_nal(CodecType::H264, &metadata, sent_at);
+        assert_eq!(h264[4..7], [6, 5, 40]);
+        assert_eq!(h264[5..], hevc[6..]);
+    }
+
+    #[test]
+    fn extends_payload_sizes_past_one_byte() {
+        let payload = [1; 300];
+        let nal = sei_nal(CodecType::Hevc, &[(5, &payload)]);
+        assert_eq!(nal[6..9], [5, 255, 45]);
+        assert_eq!(nal.len(), 6 + 3 + 300 + 1);
+    }
+}
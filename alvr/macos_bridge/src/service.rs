@@ -0,0 +1,236 @@
+use anyhow::{Context, Result, ensure};
+use std::{
+    env,
+    ffi::OsString,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+pub const SERVICE_LABEL: &str = "com.alvr.macos-bridge";
+/// What `--service` assumes when the environment does not say otherwise: the
+/// Wine producer, a real client, and no frame limit. The producer wait is a
+/// day so an idle service sits in the handshake instead of cycling.
+pub(crate) const SERVICE_DEFAULTS: [(&str, &str); 4] = [
+    ("ALVR_BRIDGE_INPUT", "iosurface"),
+    ("ALVR_BRIDGE_CONNECT", "1"),
+    ("ALVR_BRIDGE_FRAMES", "18446744073709551615"),
+    ("ALVR_BRIDGE_PRODUCER_TIMEOUT_SECS", "86400"),
+];
+const CAPTURED_PREFIXES: [&str; 2] = ["ALVR_BRIDGE_", "ALVR_IOSURFACE_"];
+
+/// Writes a LaunchAgent that runs `<binary> --service` at login and loads it
+/// into the user's GUI domain, replacing an earlier install. Every
+/// `ALVR_BRIDGE_*` and `ALVR_IOSURFACE_*` variable set now, including those
+/// from flags, is baked into the plist.
+pub fn install_service() -> Result<PathBuf> {
+    let binary = env::current_exe().context("failed to locate the bridge binary")?;
+    let home = PathBuf::from(env::var_os("HOME").context("HOME is not set")?);
+    let path = launch_agent_path(&home);
+    let environment = captured_environment(env::vars_os());
+    let log = home.join("Library/Logs/alvr_macos_bridge.log");
+    fs::create_dir_all(path.parent().context("LaunchAgents path has no parent")?)?;
+    fs::write(&path, launch_agent_plist(&binary, &environment, &log))
+        .with_context(|| format!("failed to write {}", path.display()))?;
+
+    let domain = format!("gui/{}", unsafe { libc::getuid() });
+    let _ = launchctl(&["bootout", &format!("{domain}/{SERVICE_LABEL}")]);
+    launchctl(&["bootstrap", &domain, &path.to_string_lossy()])?;
+    println!(
+        "service installed label={SERVICE_LABEL} plist={} log={}",
+        path.display(),
+        log.display()
+    );
+    Ok(path)
+}
+
+/// Unloads the LaunchAgent, which stops a running bridge, and deletes its
+/// plist. Uninstalling a service that is not installed is not an error.
+pub fn uninstall_service() -> Result<()> {
+    let home = PathBuf::from(env::var_os("HOME").context("HOME is not set")?);
+    let path = launch_agent_path(&home);
+    let domain = format!("gui/{}", unsafe { libc::getuid() });
+    if let Err(error) = launchctl(&["bootout", &format!("{domain}/{SERVICE_LABEL}")]) {
+        eprintln!("WARNING service was not loaded error={error:#}");
+    }
+    match fs::remove_file(&path) {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => {
+            return Err(error).with_context(|| format!("failed to remove {}", path.display()));
+        }
+    }
+    println!(
+        "service uninstalled label={SERVICE_LABEL} plist={}",
+        path.display()
+    );
+    Ok(())
+}
+
+/// The variables under `CAPTURED_PREFIXES`. A plist string has to be UTF-8,
+/// so one that is not is reported and left out instead of failing the
+/// install.
+fn captured_environment(vars: impl Iterator<Item = (OsString, OsString)>) -> Vec<(String, String)> {
+    vars.filter(|(name, _)| {
+        CAPTURED_PREFIXES
+            .iter()
+            .any(|prefix| name.as_encoded_bytes().starts_with(prefix.as_bytes()))
+    })
+    .filter_map(
+        |(name, value)| match (name.into_string(), value.into_string()) {
+            (Ok(name), Ok(value)) => Some((name, value)),
+            (name, _) => {
+                let name = name.unwrap_or_else(|name| name.to_string_lossy().into_owned());
+                eprintln!("WARNING service skipped_variable name={name} reason=not-utf8");
+                None
+            }
+        },
+    )
+    .collect()
+}
+
+fn launch_agent_path(home: &Path) -> PathBuf {
+    home.join(format!("Library/LaunchAgents/{SERVICE_LABEL}.plist"))
+}
+
+fn launchctl(args: &[&str]) -> Result<()> {
+    let output = Command::new("launchctl")
+        .args(args)
+        .output()
+        .context("failed to run launchctl")?;
+    ensure!(
+        output.status.success(),
+        "launchctl {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(())
+}
+
+/// Starts at login and again after any failed exit. A clean exit, such as
+/// the end of a session, leaves the job idle; when the environment names
+/// `ALVR_IOSURFACE_POOL_SERVICE`, launchd owns that Mach service and starts
+/// the bridge again as soon as Wine looks it up.
+fn launch_agent_plist(binary: &Path, environment: &[(String, String)], log: &Path) -> String {
+    let mut plist = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+        "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+        "<plist version=\"1.0\">\n<dict>\n",
+    ));
+    let _ = writeln!(
+        plist,
+        "  <key>Label</key>\n  <string>{}</string>",
+        xml_escape(SERVICE_LABEL)
+    );
+    let _ = writeln!(
+        plist,
+        "  <key>ProgramArguments</key>\n  <array>\n    <string>{}</string>\n    <string>--service</string>\n  </array>",
+        xml_escape(&binary.to_string_lossy())
+    );
+    plist.push_str("  <key>EnvironmentVariables</key>\n  <dict>\n");
+    for (name, value) in environment {
+        let _ = writeln!(
+            plist,
+            "    <key>{}</key>\n    <string>{}</string>",
+            xml_escape(name),
+            xml_escape(value)
+        );
+    }
+    plist.push_str("  </dict>\n");
+    if let Some((_, service)) = environment
+        .iter()
+        .find(|(name, _)| name == "ALVR_IOSURFACE_POOL_SERVICE")
+    {
+        let _ = writeln!(
+            plist,
+            "  <key>MachServices</key>\n  <dict>\n    <key>{}</key>\n    <true/>\n  </dict>",
+            xml_escape(service)
+        );
+    }
+    plist.push_str(concat!(
+        "  <key>RunAtLoad</key>\n  <true/>\n",
+        "  <key>KeepAlive</key>\n  <dict>\n",
+        "    <key>SuccessfulExit</key>\n    <false/>\n  </dict>\n",
+        "  <key>ProcessType</key>\n  <string>Interactive</string>\n",
+    ));
+    for key in ["StandardOutPath", "StandardErrorPath"] {
+        let _ = writeln!(
+            plist,
+            "  <key>{key}</key>\n  <string>{}</string>",
+            xml_escape(&log.to_string_lossy())
+        );
+    }
+    plist.push_str("</dict>\n</plist>\n");
+    plist
+}
+
+fn xml_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for character in value.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_a_launch_agent_that_owns_the_pool_service() {
+        let plist = launch_agent_plist(
+            Path::new("/Applications/ALVR Bridge/alvr_macos_bridge"),
+            &[
+                ("ALVR_BRIDGE_CODEC".into(), "h264".into()),
+                (
+                    "ALVR_IOSURFACE_POOL_SERVICE".into(),
+                    "com.alvr.pool.<user>".into(),
+                ),
+            ],
+            Path::new("/Users/a/Library/Logs/alvr_macos_bridge.log"),
+        );
+
+        for fragment in [
+            "<string>com.alvr.macos-bridge</string>",
+            "<string>/Applications/ALVR Bridge/alvr_macos_bridge</string>\n    <string>--service</string>",
+            "<key>ALVR_BRIDGE_CODEC</key>\n    <string>h264</string>",
+            "<key>MachServices</key>\n  <dict>\n    <key>com.alvr.pool.&lt;user&gt;</key>",
+            "<key>SuccessfulExit</key>\n    <false/>",
+            "<key>StandardErrorPath</key>\n  <string>/Users/a/Library/Logs/alvr_macos_bridge.log</string>",
+        ] {
+            assert!(plist.contains(fragment), "{fragment}\n{plist}");
+        }
+        assert!(
+            !launch_agent_plist(Path::new("/bridge"), &[], Path::new("/log"))
+                .contains("MachServices")
+        );
+    }
+
+    #[test]
+    fn skips_captured_variables_that_are_not_utf8() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let environment = captured_environment(
+            [
+                ("ALVR_BRIDGE_CODEC", b"h264".to_vec()),
+                ("ALVR_BRIDGE_LABEL", b"caf\xe9".to_vec()),
+                ("PATH", b"/usr/bin".to_vec()),
+            ]
+            .into_iter()
+            .map(|(name, value)| (OsString::from(name), OsString::from_vec(value))),
+        );
+        assert_eq!(
+            environment,
+            [("ALVR_BRIDGE_CODEC".to_owned(), "h264".to_owned())]
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use anyhow::{Result, ensure};
+use std::{
+    io, mem, ptr,
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+static SHUTDOWN_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// Turns SIGINT and SIGTERM into a flag the frame loops poll, so a stopped
+/// bridge flushes the encoder, hands the producer its slots back as closed,
+/// and lets server core disconnect the client. The handlers reset themselves,
+/// so a second signal kills the process the usual way if shutdown hangs.
+pub fn install_shutdown_handlers() -> Result<()> {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        let mut action: libc::sigaction = unsafe { mem::zeroed() };
+        action.sa_sigaction = handle_shutdown_signal as extern "C" fn(libc::c_int) as usize;
+        action.sa_flags = libc::SA_RESETHAND;
+        unsafe { libc::sigemptyset(&mut action.sa_mask) };
+        let result = unsafe { libc::sigaction(signal, &action, ptr::null_mut()) };
+        ensure!(
+            result == 0,
+            "failed to install the {} handler: {}",
+            signal_name(signal),
+            io::Error::last_os_error()
+        );
+    }
+    Ok(())
+}
+
+/// The signal that asked the bridge to stop, if one has arrived.
+pub(crate) fn shutdown_signal() -> Option<&'static str> {
+    match SHUTDOWN_SIGNAL.load(Ordering::Relaxed) {
+        0 => None,
+        signal => Some(signal_name(signal)),
+    }
+}
+
+extern "C" fn handle_shutdown_signal(signal: libc::c_int) {
+    SHUTDOWN_SIGNAL.store(signal, Ordering::Relaxed);
+}
+
+fn signal_name(signal: libc::c_int) -> &'static str {
+    match signal {
+        libc::SIGINT => "SIGINT",
+        libc::SIGTERM => "SIGTERM",
+        _ => "unknown",
+    }
+}
@@ -0,0 +1,215 @@
+use crate::{
+    EncodedFrame, FrameMetadata, HardwareEncoderSupport, NativeVideoEncoderConfig, RateControl,
+    SurfaceFormat, SurfaceLease, VideoEncoder,
+    encoder::{SOFTWARE_ENCODER_SUPPORT, SourceEncoder, annexb_nals, is_parameter_set},
+    surface::SlotCopy,
+};
+use alvr_session::CodecType;
+use anyhow::{Result, bail, ensure};
+use openh264::{
+    OpenH264API,
+    encoder::{
+        BitRate, Encoder, EncoderConfig, FrameRate, FrameType, IntraFramePeriod, RateControlMode,
+    },
+    formats::YUVSlices,
+};
+use std::time::{Duration, Instant};
+
+/// H.264 through OpenH264, for Macs where VideoToolbox cannot open a
+/// session. Each frame encodes on the calling thread inside `submit`, so
+/// nothing is ever pending and the lease returns at once. It costs a core or
+/// more at full size, which is why the software backend defaults to a smaller
+/// stream.
+pub(crate) struct SoftwareEncoder {
+    config: NativeVideoEncoderConfig,
+    encoder: Encoder,
+    /// The chroma planes split out of NV12's interleaved rows, which
+    /// OpenH264 does not read.
+    chroma_u: Vec<u8>,
+    chroma_v: Vec<u8>,
+    idr_requested: bool,
+}
+
+impl SoftwareEncoder {
+    pub fn new(config: NativeVideoEncoderConfig) -> Result<Self> {
+        ensure!(
+            config.codec == CodecType::H264,
+            "the software encoder only produces H.264, not {:?}",
+            config.codec
+        );
+        ensure!(
+            config.format == SurfaceFormat::Nv12,
+            "the software encoder requires 8-bit NV12 surfaces"
+        );
+        ensure!(
+            config.width % 2 == 0 && config.height % 2 == 0,
+            "the software encoder requires even dimensions, not {}x{}",
+            config.width,
+            config.height
+        );
+        let chroma_len = (config.width as usize / 2) * (config.height as usize / 2);
+        Ok(Self {
+            encoder: open_encoder(&config, config.bitrate_bps)?,
+            config,
+            chroma_u: vec![0; chroma_len],
+            chroma_v: vec![0; chroma_len],
+            idr_requested: false,
+        })
+    }
+}
+
+fn open_encoder(config: &NativeVideoEncoderConfig, bitrate_bps: u64) -> Result<Encoder> {
+    let rate_control_mode = match config.rate_control {
+        RateControl::AverageBitrate => RateControlMode::Bitrate,
+        RateControl::Quality => RateControlMode::Quality,
+    };
+    let encoder_config = EncoderConfig::new()
+        .bitrate(BitRate::from_bps(
+            u32::try_from(bitrate_bps).unwrap_or(u32::MAX),
+        ))
+        .max_frame_rate(FrameRate::from_hz(
+            config.fps as f32 / config.fps_denominator as f32,
+        ))
+        .rate_control_mode(rate_control_mode)
+        .intra_frame_period(IntraFramePeriod::from_num_frames(config.keyframe_interval))
+        // A skipped frame would leave its lease without an output.
+        .skip_frames(false);
+    Ok(Encoder::with_api_config(
+        OpenH264API::from_source(),
+        encoder_config,
+    )?)
+}
+
+/// Splits the parameter sets OpenH264 writes ahead of an IDR from the
+/// frame's own NAL units, as VideoToolbox reports them.
+fn split_parameter_sets(bitstream: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let mut parameter_sets = Vec::new();
+    let mut nal_data = Vec::new();
+    for nal in annexb_nals(bitstream) {
+        let output = if is_parameter_set(CodecType::H264, nal) {
+            &mut parameter_sets
+        } else {
+            &mut nal_data
+        };
+        output.extend_from_slice(&[0, 0, 0, 1]);
+        output.extend_from_slice(nal);
+    }
+    (parameter_sets, nal_data)
+}
+
+impl VideoEncoder for SoftwareEncoder {
+    fn codec(&self) -> CodecType {
+        CodecType::H264
+    }
+
+    fn hardware_support(&self) -> HardwareEncoderSupport {
+        SOFTWARE_ENCODER_SUPPORT
+    }
+
+    fn submit(
+        &mut self,
+        mut lease: SurfaceLease,
+        metadata: FrameMetadata,
+        force_keyframe: bool,
+    ) -> Result<Vec<EncodedFrame>> {
+        let submitted_at = Instant::now();
+        if force_keyframe || std::mem::take(&mut self.idr_requested) {
+            self.encoder.force_intra_frame();
+        }
+        let Self {
+            encoder,
+            chroma_u,
+            chroma_v,
+            ..
+        } = self;
+        let mut encoded = None;
+        lease.with_nv12_frame(|frame| {
+            let chroma_width = frame.width / 2;
+            for row in 0..frame.height / 2 {
+                let interleaved = &frame.chroma[row * frame.chroma_row_bytes..][..frame.width];
+                let planar = row * chroma_width..(row + 1) * chroma_width;
+                for (column, pair) in interleaved.chunks_exact(2).enumerate() {
+                    chroma_u[planar.start + column] = pair[0];
+                    chroma_v[planar.start + column] = pair[1];
+                }
+            }
+            let yuv = YUVSlices::new(
+                (&*frame.luma, chroma_u.as_slice(), chroma_v.as_slice()),
+                (frame.width, frame.height),
+                (frame.luma_row_bytes, chroma_width, chroma_width),
+            );
+            let bitstream = encoder.encode(&yuv)?;
+            encoded = Some((bitstream.to_vec(), bitstream.frame_type() == FrameType::IDR));
+            Ok(())
+        })?;
+        let Some((bitstream, is_keyframe)) = encoded else {
+            bail!("the software encoder returned no frame");
+        };
+        let (parameter_sets, nal_data) = split_parameter_sets(&bitstream);
+        Ok(vec![EncodedFrame {
+            lease_id: lease.id(),
+            metadata,
+            nal_data,
+            is_keyframe,
+            decoder_config_nals: (is_keyframe && !parameter_sets.is_empty())
+                .then_some(parameter_sets),
+            encode_latency: submitted_at.elapsed(),
+        }])
+    }
+
+    fn drain_ready(&mut self) -> Result<Vec<EncodedFrame>> {
+        Ok(Vec::new())
+    }
+
+    fn wait_for_output(&mut self, _timeout: Duration) -> Result<Vec<EncodedFrame>> {
+        Ok(Vec::new())
+    }
+
+    fn finish(&mut self) -> Result<Vec<EncodedFrame>> {
+        Ok(Vec::new())
+    }
+
+    // OpenH264 takes its bitrate at initialization, so a change reopens the
+    // encoder, which starts again with an IDR.
+    fn set_bitrate(&mut self, bitrate_bps: u64) -> Result<Vec<EncodedFrame>> {
+        self.encoder = open_encoder(&self.config, bitrate_bps)?;
+        Ok(Vec::new())
+    }
+
+    fn pending_count(&self) -> usize {
+        0
+    }
+
+    fn request_idr(&mut self) {
+        self.idr_requested = true;
+    }
+}
+
+impl SourceEncoder for SoftwareEncoder {
+    fn submit_source(
+        &mut self,
+        _source: SlotCopy,
+        _metadata: FrameMetadata,
+        _force_keyframe: bool,
+    ) -> Result<Vec<EncodedFrame>> {
+        bail!("the software encoder has no zero-copy path")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separates_parameter_sets_from_the_frame() {
+        let bitstream = [
+            0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x68, 0xce, 0, 0, 1, 0x65, 0x88, 0x84,
+        ];
+        let (parameter_sets, nal_data) = split_parameter_sets(&bitstream);
+        assert_eq!(
+            parameter_sets,
+            [0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x68, 0xce]
+        );
+        assert_eq!(nal_data, [0, 0, 0, 1, 0x65, 0x88, 0x84]);
+    }
+}
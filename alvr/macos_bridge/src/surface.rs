@@ -1,4 +1,4 @@
-use crate::SurfaceLeaseId;
+use crate::{HdrMetadata, SurfaceLeaseId, filter::Nv12Frame};
 use anyhow::{Context, Result, anyhow, ensure};
 use std::{
     collections::VecDeque,
@@ -12,6 +12,9 @@ type CVReturn = i32;
 type IOSurfaceRef = *mut c_void;
 
 const K_CV_PIXEL_FORMAT_TYPE_420V: u32 = u32::from_be_bytes(*b"420v");
+const K_CV_PIXEL_FORMAT_TYPE_420F: u32 = u32::from_be_bytes(*b"420f");
+const K_CV_PIXEL_FORMAT_TYPE_X420: u32 = u32::from_be_bytes(*b"x420");
+const K_CV_PIXEL_FORMAT_TYPE_XF20: u32 = u32::from_be_bytes(*b"xf20");
 const K_CV_RETURN_SUCCESS: CVReturn = 0;
 
 #[link(name = "CoreFoundation", kind = "framework")]
@@ -37,10 +40,14 @@ unsafe extern "C" {
     static kCVPixelBufferMetalCompatibilityKey: *const c_void;
     static kCVImageBufferYCbCrMatrixKey: *const c_void;
     static kCVImageBufferYCbCrMatrix_ITU_R_709_2: *const c_void;
+    static kCVImageBufferYCbCrMatrix_ITU_R_601_4: *const c_void;
+    static kCVImageBufferYCbCrMatrix_ITU_R_2020: *const c_void;
     static kCVImageBufferColorPrimariesKey: *const c_void;
     static kCVImageBufferColorPrimaries_ITU_R_709_2: *const c_void;
+    static kCVImageBufferColorPrimaries_ITU_R_2020: *const c_void;
     static kCVImageBufferTransferFunctionKey: *const c_void;
     static kCVImageBufferTransferFunction_ITU_R_709_2: *const c_void;
+    static kCVImageBufferTransferFunction_SMPTE_ST_2084_PQ: *const c_void;
     static kCVImageBufferChromaLocationTopFieldKey: *const c_void;
     static kCVImageBufferChromaLocationBottomFieldKey: *const c_void;
     static kCVImageBufferChromaLocation_Center: *const c_void;
@@ -53,6 +60,12 @@ unsafe extern "C" {
         pixel_buffer_attributes: *const c_void,
         pixel_buffer_out: *mut CVPixelBufferRef,
     ) -> CVReturn;
+    fn CVPixelBufferCreateWithIOSurface(
+        allocator: *const c_void,
+        surface: IOSurfaceRef,
+        pixel_buffer_attributes: *const c_void,
+        pixel_buffer_out: *mut CVPixelBufferRef,
+    ) -> CVReturn;
     fn CVPixelBufferGetIOSurface(pixel_buffer: CVPixelBufferRef) -> IOSurfaceRef;
     fn CVPixelBufferGetPixelFormatType(pixel_buffer: CVPixelBufferRef) -> u32;
     fn CVPixelBufferGetWidth(pixel_buffer: CVPixelBufferRef) -> usize;
@@ -128,6 +141,117 @@ impl Drop for CfObject {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SurfaceFormat {
+    #[default]
+    Nv12,
+    P010,
+}
+
+impl SurfaceFormat {
+    pub fn is_ten_bit(self) -> bool {
+        self == Self::P010
+    }
+
+    pub(crate) fn bytes_per_sample(self) -> usize {
+        match self {
+            Self::Nv12 => 1,
+            Self::P010 => 2,
+        }
+    }
+
+    fn pixel_format_type(self, range: ColorRange) -> u32 {
+        match (self, range) {
+            (Self::Nv12, ColorRange::Limited) => K_CV_PIXEL_FORMAT_TYPE_420V,
+            (Self::Nv12, ColorRange::Full) => K_CV_PIXEL_FORMAT_TYPE_420F,
+            (Self::P010, ColorRange::Limited) => K_CV_PIXEL_FORMAT_TYPE_X420,
+            (Self::P010, ColorRange::Full) => K_CV_PIXEL_FORMAT_TYPE_XF20,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Nv12 => "NV12",
+            Self::P010 => "P010",
+        }
+    }
+
+    fn write_samples(self, row: *mut u8, value: u8, count: usize) {
+        match self {
+            Self::Nv12 => unsafe { ptr::write_bytes(row, value, count) },
+            Self::P010 => {
+                let sample = (u16::from(value) << 8).to_ne_bytes();
+                for index in 0..count {
+                    unsafe { ptr::copy_nonoverlapping(sample.as_ptr(), row.add(index * 2), 2) };
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMatrix {
+    Bt601,
+    #[default]
+    Bt709,
+    /// Non-constant luminance BT.2020, used only by HDR mode.
+    Bt2020,
+}
+
+impl ColorMatrix {
+    /// The red and blue luma weights, Kr and Kb.
+    pub(crate) fn coefficients(self) -> (f32, f32) {
+        match self {
+            Self::Bt601 => (0.299, 0.114),
+            Self::Bt709 => (0.2126, 0.0722),
+            Self::Bt2020 => (0.2627, 0.0593),
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Bt601 => "bt601",
+            Self::Bt709 => "bt709",
+            Self::Bt2020 => "bt2020",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorRange {
+    #[default]
+    Limited,
+    Full,
+}
+
+impl ColorRange {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Limited => "limited",
+            Self::Full => "full",
+        }
+    }
+}
+
+/// How the pool's surfaces encode color. The range picks the pixel format
+/// (`420v`/`x420` or `420f`/`xf20`) and the matrix is attached to each
+/// surface; VideoToolbox writes both into the SPS VUI, so the client decoder
+/// undoes the same conversion the Metal pass applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ColorSpace {
+    pub matrix: ColorMatrix,
+    pub range: ColorRange,
+    /// HDR10: BT.2020 primaries and the PQ transfer instead of BT.709, with
+    /// this metadata sent in SEI ahead of each IDR.
+    pub hdr: Option<HdrMetadata>,
+}
+
+impl ColorSpace {
+    pub(crate) fn transfer_name(self) -> &'static str {
+        if self.hdr.is_some() { "pq" } else { "bt709" }
+    }
+}
+
 struct PixelBufferLock {
     pixel_buffer: CVPixelBufferRef,
 }
@@ -152,20 +276,23 @@ struct NativeSurface {
     surface_id: u32,
     width: u32,
     height: u32,
+    format: SurfaceFormat,
+    color: ColorSpace,
 }
 
 // SAFETY: The wrapper is uniquely owned and moves only between mutex-protected pool state and one lease.
 unsafe impl Send for NativeSurface {}
 
 impl NativeSurface {
-    fn new(width: u32, height: u32) -> Result<Self> {
+    fn new(width: u32, height: u32, format: SurfaceFormat, color: ColorSpace) -> Result<Self> {
+        let name = format.name();
         ensure!(
             width > 0 && width.is_multiple_of(2),
-            "NV12 width must be even"
+            "{name} width must be even"
         );
         ensure!(
             height > 0 && height.is_multiple_of(2),
-            "NV12 height must be even"
+            "{name} height must be even"
         );
 
         let iosurface_properties = CfObject::dictionary(&[], &[])?;
@@ -184,7 +311,7 @@ impl NativeSurface {
                 ptr::null(),
                 width as usize,
                 height as usize,
-                K_CV_PIXEL_FORMAT_TYPE_420V,
+                format.pixel_format_type(color.range),
                 attributes.ptr,
                 &mut pixel_buffer,
             )
@@ -205,9 +332,11 @@ impl NativeSurface {
             surface_id: unsafe { IOSurfaceGetID(iosurface) },
             width,
             height,
+            format,
+            color,
         };
         surface.validate_layout()?;
-        surface.set_color_attachments();
+        set_color_attachments(surface.pixel_buffer, color);
         surface.initialize_neutral()?;
 
         Ok(surface)
@@ -216,8 +345,10 @@ impl NativeSurface {
     fn validate_layout(&self) -> Result<()> {
         ensure!(
             unsafe { CVPixelBufferGetPixelFormatType(self.pixel_buffer) }
-                == K_CV_PIXEL_FORMAT_TYPE_420V,
-            "CVPixelBuffer is not video-range NV12"
+                == self.format.pixel_format_type(self.color.range),
+            "CVPixelBuffer is not {}-range {}",
+            self.color.range.name(),
+            self.format.name()
         );
         ensure!(
             unsafe { CVPixelBufferGetWidth(self.pixel_buffer) } == self.width as usize
@@ -226,97 +357,61 @@ impl NativeSurface {
         );
         ensure!(
             unsafe { CVPixelBufferGetPlaneCount(self.pixel_buffer) } == 2,
-            "NV12 CVPixelBuffer must expose two planes"
+            "{} CVPixelBuffer must expose two planes",
+            self.format.name()
         );
         Ok(())
     }
 
     fn initialize_neutral(&self) -> Result<()> {
         let _lock = lock_pixel_buffer(self.pixel_buffer)?;
-        let luma = pixel_plane(self.pixel_buffer, 0)?;
-        let chroma = pixel_plane(self.pixel_buffer, 1)?;
-        validate_plane(
-            &luma,
-            self.width as usize,
-            self.height as usize,
-            self.width as usize,
-        )?;
-        validate_plane(
-            &chroma,
-            self.width as usize / 2,
-            self.height as usize / 2,
-            self.width as usize,
-        )?;
+        let (luma, chroma) = self.planes()?;
 
         for row in 0..luma.height {
-            unsafe {
-                ptr::write_bytes(luma.data.add(row * luma.row_bytes), 32, self.width as usize)
-            };
+            self.format.write_samples(
+                unsafe { luma.data.add(row * luma.row_bytes) },
+                32,
+                self.width as usize,
+            );
         }
         for row in 0..chroma.height {
-            unsafe {
-                ptr::write_bytes(
-                    chroma.data.add(row * chroma.row_bytes),
-                    128,
-                    self.width as usize,
-                )
-            };
+            self.format.write_samples(
+                unsafe { chroma.data.add(row * chroma.row_bytes) },
+                128,
+                self.width as usize,
+            );
         }
 
         Ok(())
     }
 
-    fn set_color_attachments(&self) {
-        const SHOULD_PROPAGATE: u32 = 1;
-        unsafe {
-            CVBufferSetAttachment(
-                self.pixel_buffer,
-                kCVImageBufferYCbCrMatrixKey,
-                kCVImageBufferYCbCrMatrix_ITU_R_709_2,
-                SHOULD_PROPAGATE,
-            );
-            CVBufferSetAttachment(
-                self.pixel_buffer,
-                kCVImageBufferColorPrimariesKey,
-                kCVImageBufferColorPrimaries_ITU_R_709_2,
-                SHOULD_PROPAGATE,
-            );
-            CVBufferSetAttachment(
-                self.pixel_buffer,
-                kCVImageBufferTransferFunctionKey,
-                kCVImageBufferTransferFunction_ITU_R_709_2,
-                SHOULD_PROPAGATE,
-            );
-            CVBufferSetAttachment(
-                self.pixel_buffer,
-                kCVImageBufferChromaLocationTopFieldKey,
-                kCVImageBufferChromaLocation_Center,
-                SHOULD_PROPAGATE,
-            );
-            CVBufferSetAttachment(
-                self.pixel_buffer,
-                kCVImageBufferChromaLocationBottomFieldKey,
-                kCVImageBufferChromaLocation_Center,
-                SHOULD_PROPAGATE,
-            );
-        }
+    fn planes(&self) -> Result<(PixelPlane, PixelPlane)> {
+        let row_bytes = self.width as usize * self.format.bytes_per_sample();
+        let luma = pixel_plane(self.pixel_buffer, 0)?;
+        let chroma = pixel_plane(self.pixel_buffer, 1)?;
+        validate_plane(&luma, self.width as usize, self.height as usize, row_bytes)?;
+        validate_plane(
+            &chroma,
+            self.width as usize / 2,
+            self.height as usize / 2,
+            row_bytes,
+        )?;
+        Ok((luma, chroma))
     }
 
     fn write_probe_marker(&mut self, frame_id: u64) -> Result<()> {
         let _lock = lock_pixel_buffer(self.pixel_buffer)?;
-        let luma = pixel_plane(self.pixel_buffer, 0)?;
-        validate_plane(
-            &luma,
-            self.width as usize,
-            self.height as usize,
-            self.width as usize,
-        )?;
+        let (luma, _) = self.planes()?;
 
         let marker_rows = 16.min(luma.height);
         let marker_width = 256.min(self.width as usize);
         let value = 48 + (frame_id % 160) as u8;
         for row in 0..marker_rows {
-            unsafe { ptr::write_bytes(luma.data.add(row * luma.row_bytes), value, marker_width) };
+            self.format.write_samples(
+                unsafe { luma.data.add(row * luma.row_bytes) },
+                value,
+                marker_width,
+            );
         }
         unsafe {
             ptr::copy_nonoverlapping(
@@ -328,6 +423,31 @@ impl NativeSurface {
 
         Ok(())
     }
+    fn with_nv12_frame(
+        &mut self,
+        apply: impl FnOnce(&mut Nv12Frame<'_>) -> Result<()>,
+    ) -> Result<()> {
+        ensure!(
+            self.format == SurfaceFormat::Nv12,
+            "frame filters require 8-bit NV12 surfaces"
+        );
+        let _lock = lock_pixel_buffer(self.pixel_buffer)?;
+        let (luma, chroma) = self.planes()?;
+
+        let mut frame = Nv12Frame {
+            width: self.width as usize,
+            height: self.height as usize,
+            luma: unsafe {
+                std::slice::from_raw_parts_mut(luma.data, luma.row_bytes * luma.height)
+            },
+            luma_row_bytes: luma.row_bytes,
+            chroma: unsafe {
+                std::slice::from_raw_parts_mut(chroma.data, chroma.row_bytes * chroma.height)
+            },
+            chroma_row_bytes: chroma.row_bytes,
+        };
+        apply(&mut frame)
+    }
 }
 
 impl Drop for NativeSurface {
@@ -364,14 +484,24 @@ pub struct SurfaceLease {
 }
 
 impl SurfacePool {
-    pub fn new(width: u32, height: u32, capacity: usize) -> Result<Self> {
+    pub fn new(width: u32, height: u32, capacity: usize, format: SurfaceFormat) -> Result<Self> {
+        Self::with_color_space(width, height, capacity, format, ColorSpace::default())
+    }
+
+    pub fn with_color_space(
+        width: u32,
+        height: u32,
+        capacity: usize,
+        format: SurfaceFormat,
+        color: ColorSpace,
+    ) -> Result<Self> {
         ensure!(
             capacity > 0,
             "surface pool capacity must be greater than zero"
         );
         let mut available = VecDeque::with_capacity(capacity);
         for _ in 0..capacity {
-            available.push_back(NativeSurface::new(width, height)?);
+            available.push_back(NativeSurface::new(width, height, format, color)?);
         }
 
         Ok(Self {
@@ -442,6 +572,16 @@ impl SurfaceLease {
             .write_probe_marker(frame_id)
     }
 
+    pub fn with_nv12_frame(
+        &mut self,
+        apply: impl FnOnce(&mut Nv12Frame<'_>) -> Result<()>,
+    ) -> Result<()> {
+        self.surface
+            .as_mut()
+            .expect("surface lease must own a surface")
+            .with_nv12_frame(apply)
+    }
+
     fn surface(&self) -> &NativeSurface {
         self.surface
             .as_ref()
@@ -449,6 +589,120 @@ impl SurfaceLease {
     }
 }
 
+pub(crate) struct SourcePixelBuffer {
+    pixel_buffer: CVPixelBufferRef,
+    surface_id: u32,
+    width: u32,
+    height: u32,
+}
+
+// SAFETY: The wrapper owns one CVPixelBuffer reference and moves only between mutex-protected copy pool state and one encode.
+unsafe impl Send for SourcePixelBuffer {}
+
+/// Bridge-owned surfaces in the source slots' own size and format. A frame
+/// encoded without conversion is copied into one, so its slot goes back to
+/// the producer before VideoToolbox reads the frame.
+pub(crate) struct SlotCopyPool {
+    available: Arc<Mutex<Vec<SourcePixelBuffer>>>,
+}
+
+/// A surface taken from a `SlotCopyPool`, returned to it on drop.
+pub(crate) struct SlotCopy {
+    buffer: Option<SourcePixelBuffer>,
+    pool: Arc<Mutex<Vec<SourcePixelBuffer>>>,
+}
+
+impl SourcePixelBuffer {
+    pub fn wrap(surface: NonNull<c_void>) -> Result<Self> {
+        let mut pixel_buffer = ptr::null_mut();
+        let status = unsafe {
+            CVPixelBufferCreateWithIOSurface(
+                ptr::null(),
+                surface.as_ptr(),
+                ptr::null(),
+                &mut pixel_buffer,
+            )
+        };
+        cv_check(status, "CVPixelBufferCreateWithIOSurface")?;
+        ensure!(
+            !pixel_buffer.is_null(),
+            "CVPixelBufferCreateWithIOSurface returned null"
+        );
+        // In-place encodes only run with the default color space.
+        set_color_attachments(pixel_buffer, ColorSpace::default());
+
+        Ok(Self {
+            pixel_buffer,
+            surface_id: unsafe { IOSurfaceGetID(surface.as_ptr()) },
+            width: unsafe { CVPixelBufferGetWidth(pixel_buffer) } as u32,
+            height: unsafe { CVPixelBufferGetHeight(pixel_buffer) } as u32,
+        })
+    }
+
+    pub fn cv_pixel_buffer(&self) -> NonNull<c_void> {
+        NonNull::new(self.pixel_buffer).expect("CVPixelBuffer pointer must be non-null")
+    }
+
+    pub fn surface(&self) -> NonNull<c_void> {
+        NonNull::new(unsafe { CVPixelBufferGetIOSurface(self.pixel_buffer) })
+            .expect("source pixel buffer must be IOSurface-backed")
+    }
+
+    pub fn surface_id(&self) -> u32 {
+        self.surface_id
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+impl SlotCopyPool {
+    pub fn new(buffers: Vec<SourcePixelBuffer>) -> Result<Self> {
+        ensure!(
+            !buffers.is_empty(),
+            "slot copy pool capacity must be greater than zero"
+        );
+        Ok(Self {
+            available: Arc::new(Mutex::new(buffers)),
+        })
+    }
+
+    pub fn try_acquire(&self) -> Option<SlotCopy> {
+        let buffer = lock_copies(&self.available).pop()?;
+        Some(SlotCopy {
+            buffer: Some(buffer),
+            pool: Arc::clone(&self.available),
+        })
+    }
+}
+
+impl SlotCopy {
+    pub fn buffer(&self) -> &SourcePixelBuffer {
+        self.buffer
+            .as_ref()
+            .expect("slot copy must own a pixel buffer")
+    }
+}
+
+impl Drop for SlotCopy {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.buffer.take() {
+            lock_copies(&self.pool).push(buffer);
+        }
+    }
+}
+
+impl Drop for SourcePixelBuffer {
+    fn drop(&mut self) {
+        unsafe { CFRelease(self.pixel_buffer.cast_const()) }
+    }
+}
+
 impl Drop for SurfaceLease {
     fn drop(&mut self) {
         if let Some(surface) = self.surface.take() {
@@ -460,10 +714,66 @@ impl Drop for SurfaceLease {
     }
 }
 
+/// SDR primaries and transfer stay BT.709 whatever the matrix: the producer
+/// renders sRGB-primaried content, and only the YCbCr encoding changes.
+fn set_color_attachments(pixel_buffer: CVPixelBufferRef, color: ColorSpace) {
+    const SHOULD_PROPAGATE: u32 = 1;
+    unsafe {
+        CVBufferSetAttachment(
+            pixel_buffer,
+            kCVImageBufferYCbCrMatrixKey,
+            match color.matrix {
+                ColorMatrix::Bt601 => kCVImageBufferYCbCrMatrix_ITU_R_601_4,
+                ColorMatrix::Bt709 => kCVImageBufferYCbCrMatrix_ITU_R_709_2,
+                ColorMatrix::Bt2020 => kCVImageBufferYCbCrMatrix_ITU_R_2020,
+            },
+            SHOULD_PROPAGATE,
+        );
+        CVBufferSetAttachment(
+            pixel_buffer,
+            kCVImageBufferColorPrimariesKey,
+            if color.hdr.is_some() {
+                kCVImageBufferColorPrimaries_ITU_R_2020
+            } else {
+                kCVImageBufferColorPrimaries_ITU_R_709_2
+            },
+            SHOULD_PROPAGATE,
+        );
+        CVBufferSetAttachment(
+            pixel_buffer,
+            kCVImageBufferTransferFunctionKey,
+            if color.hdr.is_some() {
+                kCVImageBufferTransferFunction_SMPTE_ST_2084_PQ
+            } else {
+                kCVImageBufferTransferFunction_ITU_R_709_2
+            },
+            SHOULD_PROPAGATE,
+        );
+        CVBufferSetAttachment(
+            pixel_buffer,
+            kCVImageBufferChromaLocationTopFieldKey,
+            kCVImageBufferChromaLocation_Center,
+            SHOULD_PROPAGATE,
+        );
+        CVBufferSetAttachment(
+            pixel_buffer,
+            kCVImageBufferChromaLocationBottomFieldKey,
+            kCVImageBufferChromaLocation_Center,
+            SHOULD_PROPAGATE,
+        );
+    }
+}
+
 fn lock_state(state: &Arc<Mutex<PoolState>>) -> MutexGuard<'_, PoolState> {
     state.lock().unwrap_or_else(|error| error.into_inner())
 }
 
+fn lock_copies(
+    copies: &Arc<Mutex<Vec<SourcePixelBuffer>>>,
+) -> MutexGuard<'_, Vec<SourcePixelBuffer>> {
+    copies.lock().unwrap_or_else(|error| error.into_inner())
+}
+
 fn lock_pixel_buffer(pixel_buffer: CVPixelBufferRef) -> Result<PixelBufferLock> {
     cv_check(
         unsafe { CVPixelBufferLockBaseAddress(pixel_buffer, 0) },
@@ -520,7 +830,7 @@ mod tests {
 
     #[test]
     fn bounded_pool_recycles_the_same_surface_with_a_new_generation() -> Result<()> {
-        let pool = SurfacePool::new(64, 64, 2)?;
+        let pool = SurfacePool::new(64, 64, 2, SurfaceFormat::Nv12)?;
         let first = pool.try_acquire()?.unwrap();
         let second = pool.try_acquire()?.unwrap();
         let first_id = first.id();
@@ -540,4 +850,42 @@ mod tests {
         assert_eq!(stats.acquired, stats.recycled);
         Ok(())
     }
+
+    #[test]
+    fn p010_surfaces_start_neutral_in_the_high_ten_bits() -> Result<()> {
+        let pool = SurfacePool::new(64, 64, 1, SurfaceFormat::P010)?;
+        let lease = pool.try_acquire()?.unwrap();
+        let surface = lease.surface();
+        let _lock = lock_pixel_buffer(surface.pixel_buffer)?;
+        let (luma, chroma) = surface.planes()?;
+
+        let sample = |plane: &PixelPlane, index: usize| unsafe {
+            u16::from_ne_bytes([*plane.data.add(index * 2), *plane.data.add(index * 2 + 1)])
+        };
+        assert_eq!(sample(&luma, 63) >> 6, 128);
+        assert_eq!(sample(&chroma, 0) >> 6, 512);
+        assert_eq!(sample(&chroma, 1) >> 6, 512);
+        Ok(())
+    }
+
+    #[test]
+    fn full_range_pools_allocate_full_range_surfaces() -> Result<()> {
+        let color = ColorSpace {
+            matrix: ColorMatrix::Bt601,
+            range: ColorRange::Full,
+            hdr: None,
+        };
+        for (format, expected) in [
+            (SurfaceFormat::Nv12, K_CV_PIXEL_FORMAT_TYPE_420F),
+            (SurfaceFormat::P010, K_CV_PIXEL_FORMAT_TYPE_XF20),
+        ] {
+            let pool = SurfacePool::with_color_space(64, 64, 1, format, color)?;
+            let lease = pool.try_acquire()?.unwrap();
+            assert_eq!(
+                unsafe { CVPixelBufferGetPixelFormatType(lease.surface().pixel_buffer) },
+                expected
+            );
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,114 @@
+#import <CoreGraphics/CoreGraphics.h>
+#import <Foundation/Foundation.h>
+#import <IOKit/IOMessage.h>
+#import <IOKit/pwr_mgt/IOPMLib.h>
+
+#include <stdatomic.h>
+#include <stdint.h>
+
+#define MAX_DISPLAYS 16
+
+static _Atomic uint64_t sleep_count;
+static _Atomic uint64_t wake_count;
+static _Atomic uint64_t display_change_count;
+static io_connect_t root_port;
+static IONotificationPortRef notify_port;
+static io_object_t notifier;
+static dispatch_source_t display_timer;
+static uint64_t display_signature;
+
+static void power_callback(void *refcon, io_service_t service, natural_t type, void *argument) {
+    (void)refcon;
+    (void)service;
+    switch (type) {
+    case kIOMessageCanSystemSleep:
+        IOAllowPowerChange(root_port, (long)argument);
+        break;
+    case kIOMessageSystemWillSleep:
+        atomic_fetch_add(&sleep_count, 1);
+        IOAllowPowerChange(root_port, (long)argument);
+        break;
+    case kIOMessageSystemHasPoweredOn:
+        atomic_fetch_add(&wake_count, 1);
+        break;
+    default:
+        break;
+    }
+}
+
+// FNV-1a over what a display change alters: the set of displays and each
+// one's bounds, pixel size, and refresh rate.
+static uint64_t hash_value(uint64_t hash, uint64_t value) {
+    for (int byte = 0; byte < 8; byte++) {
+        hash ^= (value >> (byte * 8)) & 0xff;
+        hash *= 0x100000001b3ull;
+    }
+    return hash;
+}
+
+static uint64_t read_display_signature(void) {
+    CGDirectDisplayID displays[MAX_DISPLAYS];
+    uint32_t count = 0;
+    if (CGGetOnlineDisplayList(MAX_DISPLAYS, displays, &count) != kCGErrorSuccess) {
+        return 0;
+    }
+    uint64_t hash = 0xcbf29ce484222325ull;
+    for (uint32_t index = 0; index < count; index++) {
+        CGRect bounds = CGDisplayBounds(displays[index]);
+        hash = hash_value(hash, displays[index]);
+        hash = hash_value(hash, (uint64_t)(int64_t)bounds.origin.x);
+        hash = hash_value(hash, (uint64_t)(int64_t)bounds.origin.y);
+        hash = hash_value(hash, (uint64_t)bounds.size.width);
+        hash = hash_value(hash, (uint64_t)bounds.size.height);
+        CGDisplayModeRef mode = CGDisplayCopyDisplayMode(displays[index]);
+        if (mode != NULL) {
+            hash = hash_value(hash, CGDisplayModeGetPixelWidth(mode));
+            hash = hash_value(hash, CGDisplayModeGetPixelHeight(mode));
+            hash = hash_value(hash, (uint64_t)(CGDisplayModeGetRefreshRate(mode) * 1000.0));
+            CGDisplayModeRelease(mode);
+        }
+    }
+    return hash;
+}
+
+// Registers for system sleep and wake once per process and starts a
+// once-a-second check of the display layout. Both run on a private dispatch
+// queue, so the bridge needs no run loop; the Rust side polls the counters.
+// Display reconfiguration callbacks would need a run loop on the main thread,
+// which the bridge does not have.
+void alvr_system_events_subscribe(void) {
+    static dispatch_once_t once;
+    dispatch_once(&once, ^{
+        dispatch_queue_t queue =
+            dispatch_queue_create("com.alvr.macos-bridge.system-events", DISPATCH_QUEUE_SERIAL);
+        root_port = IORegisterForSystemPower(NULL, &notify_port, power_callback, &notifier);
+        if (root_port != MACH_PORT_NULL) {
+            IONotificationPortSetDispatchQueue(notify_port, queue);
+        }
+
+        display_signature = read_display_signature();
+        display_timer = dispatch_source_create(DISPATCH_SOURCE_TYPE_TIMER, 0, 0, queue);
+        dispatch_source_set_timer(display_timer, dispatch_time(DISPATCH_TIME_NOW, NSEC_PER_SEC),
+                                  NSEC_PER_SEC, NSEC_PER_SEC / 10);
+        dispatch_source_set_event_handler(display_timer, ^{
+            uint64_t signature = read_display_signature();
+            if (signature != 0 && signature != display_signature) {
+                display_signature = signature;
+                atomic_fetch_add(&display_change_count, 1);
+            }
+        });
+        dispatch_resume(display_timer);
+    });
+}
+
+uint64_t alvr_system_sleep_count(void) {
+    return atomic_load(&sleep_count);
+}
+
+uint64_t alvr_system_wake_count(void) {
+    return atomic_load(&wake_count);
+}
+
+uint64_t alvr_display_change_count(void) {
+    return atomic_load(&display_change_count);
+}
@@ -0,0 +1,107 @@
+unsafe extern "C" {
+    fn alvr_system_events_subscribe();
+    fn alvr_system_sleep_count() -> u64;
+    fn alvr_system_wake_count() -> u64;
+    fn alvr_display_change_count() -> u64;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SystemEvent {
+    Sleep,
+    Wake,
+    DisplayChange,
+}
+
+impl SystemEvent {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sleep => "sleep",
+            Self::Wake => "wake",
+            Self::DisplayChange => "display_change",
+        }
+    }
+
+    /// Whether a VideoToolbox session from before the event should be
+    /// replaced. Across sleep the GPU and media engine are powered down, and
+    /// a display change can reset the GPU; a session may survive either, but
+    /// one that did not fails silently.
+    pub fn invalidates_encoder(self) -> bool {
+        matches!(self, Self::Wake | Self::DisplayChange)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct Counts {
+    sleep: u64,
+    wake: u64,
+    display_change: u64,
+}
+
+impl Counts {
+    fn read() -> Self {
+        unsafe {
+            Self {
+                sleep: alvr_system_sleep_count(),
+                wake: alvr_system_wake_count(),
+                display_change: alvr_display_change_count(),
+            }
+        }
+    }
+
+    /// Several events between two polls collapse into the most disruptive.
+    fn event_since(self, last: Self) -> Option<SystemEvent> {
+        if self.wake != last.wake {
+            Some(SystemEvent::Wake)
+        } else if self.display_change != last.display_change {
+            Some(SystemEvent::DisplayChange)
+        } else if self.sleep != last.sleep {
+            Some(SystemEvent::Sleep)
+        } else {
+            None
+        }
+    }
+}
+
+/// Follows macOS sleep, wake, and display reconfiguration. The native side
+/// only counts events, so polling from the frame loop costs three atomic
+/// loads.
+pub(crate) struct SystemEvents {
+    last: Counts,
+}
+
+impl SystemEvents {
+    pub fn new() -> Self {
+        unsafe { alvr_system_events_subscribe() };
+        Self {
+            last: Counts::read(),
+        }
+    }
+
+    pub fn poll(&mut self) -> Option<SystemEvent> {
+        let counts = Counts::read();
+        let event = counts.event_since(self.last);
+        self.last = counts;
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_the_most_disruptive_event_since_the_last_poll() {
+        let last = Counts::default();
+        assert_eq!(last.event_since(last), None);
+        let slept = Counts { sleep: 1, ..last };
+        assert_eq!(slept.event_since(last), Some(SystemEvent::Sleep));
+        let woke = Counts { wake: 1, ..slept };
+        assert_eq!(woke.event_since(last), Some(SystemEvent::Wake));
+        let display = Counts {
+            display_change: 2,
+            ..slept
+        };
+        assert_eq!(display.event_since(slept), Some(SystemEvent::DisplayChange));
+        assert!(!SystemEvent::Sleep.invalidates_encoder());
+    }
+}
@@ -0,0 +1,49 @@
+use anyhow::{Context, Result};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Records the frame loop's `acquire`, `frame`, and `convert` spans, and the
+/// encode thread's `encode` and `send` spans, as a Chrome trace, which
+/// `chrome://tracing` and Perfetto open as a per-thread timeline. Without it no subscriber is installed and
+/// each span costs a cached check. Dropping this writes out the file.
+pub struct TraceExport {
+    path: PathBuf,
+    _guard: FlushGuard,
+}
+
+impl TraceExport {
+    /// `None` unless `ALVR_BRIDGE_TRACE_FILE` names a file.
+    pub fn from_env() -> Result<Option<Self>> {
+        match env::var_os("ALVR_BRIDGE_TRACE_FILE") {
+            Some(path) if !path.is_empty() => Self::install(PathBuf::from(path)).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn install(path: PathBuf) -> Result<Self> {
+        // The layer creates the file on its writer thread and panics there if
+        // it cannot, so a bad path is caught here instead.
+        std::fs::File::create(&path)
+            .with_context(|| format!("failed to create trace file {}", path.display()))?;
+        let (layer, guard) = ChromeLayerBuilder::new()
+            .file(&path)
+            .include_args(true)
+            .build();
+        tracing_subscriber::registry()
+            .with(layer)
+            .try_init()
+            .context("a tracing subscriber is already installed")?;
+        Ok(Self {
+            path,
+            _guard: guard,
+        })
+    }
+}
@@ -1,29 +1,92 @@
+use crate::{LatencySummary, SurfaceFormat, control::BridgeState};
 use alvr_common::{DeviceMotion, Pose, ViewParams, glam::Mat4, inputs as inp};
-use alvr_packets::{ButtonEntry, ButtonValue};
+use alvr_packets::{ButtonEntry, ButtonValue, FaceData, FaceExpressions};
+use alvr_session::CodecType;
 use anyhow::{Context, Result, ensure};
 use memmap2::{MmapMut, MmapOptions};
 use std::{
-    fs::{File, OpenOptions},
-    mem,
-    path::Path,
+    env,
+    ffi::CString,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io, mem,
+    path::{Path, PathBuf},
     process, ptr,
     sync::atomic::{AtomicU32, AtomicU64, Ordering, fence},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 #[cfg(unix)]
-use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::os::{
+    fd::FromRawFd,
+    unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt},
+};
 
 const SHM_PATH: &str = "/tmp/alvr_frame_buffer.shm";
+const POSIX_SHM_NAME: &str = "/alvr_frame_buffer";
+const MAX_SESSION_ID_LEN: usize = 16;
+// PSHMNAMLEN on macOS, including the leading slash.
+const POSIX_SHM_NAME_MAX: usize = 31;
+// shm_open is variadic, so the mode travels as a promoted int.
+const POSIX_SHM_MODE: libc::c_uint = 0o600;
 const SHM_MAGIC: u32 = 0x414C5652;
-const SHM_VERSION: u32 = 7;
+const SHM_VERSION: u32 = 16;
 const NUM_BUFFERS: usize = 3;
 const NUM_CONTROLLERS: usize = 2;
+const POSE_RING_LEN: usize = 16;
+const INPUT_RING_LEN: usize = 8;
+const HAPTICS_RING_LEN: usize = 16;
+// The largest expression set a client sends, Meta's 70 blend shapes.
+const FACE_EXPRESSIONS_LEN: usize = 70;
+const BD_EXPRESSIONS_LEN: usize = 52;
+// OpenXR's hand joint set, palm first.
+const HAND_JOINT_COUNT: usize = 26;
+const HTC_EYE_EXPRESSIONS_LEN: usize = 14;
+const HTC_LIP_EXPRESSIONS_LEN: usize = 37;
+const MAX_HAPTICS_DURATION: Duration = Duration::from_secs(5);
+// Both sides refresh their heartbeat several times a second while running.
+const PEER_STALE_AFTER: Duration = Duration::from_secs(5);
 
 const CLIENT_STATE_WAITING: u32 = 0;
 const CLIENT_STATE_CONNECTED: u32 = 1;
 const CLIENT_STATE_STREAMING: u32 = 2;
 
+const FORMAT_NV12: u32 = 1 << 0;
+const FORMAT_P010: u32 = 1 << 1;
+const CODEC_H264: u32 = 1 << 0;
+const CODEC_HEVC: u32 = 1 << 1;
+const CODEC_AV1: u32 = 1 << 2;
+const FEATURE_POSE: u32 = 1 << 0;
+const FEATURE_CONTROLLER_INPUT: u32 = 1 << 1;
+const FEATURE_HAPTICS: u32 = 1 << 2;
+const FEATURE_GAME_AUDIO: u32 = 1 << 3;
+const FEATURE_EYE_TRACKING: u32 = 1 << 4;
+const FEATURE_FACE_TRACKING: u32 = 1 << 5;
+const FEATURE_HAND_TRACKING: u32 = 1 << 6;
+
+const FACE_EYE_COMBINED: u32 = 1 << 0;
+const FACE_EYE_LEFT: u32 = 1 << 1;
+const FACE_EYE_RIGHT: u32 = 1 << 2;
+const FACE_HTC_EYE: u32 = 1 << 3;
+const FACE_HTC_LIP: u32 = 1 << 4;
+
+const FACE_EXPRESSIONS_NONE: u32 = 0;
+const FACE_EXPRESSIONS_FB: u32 = 1;
+const FACE_EXPRESSIONS_BD: u32 = 2;
+const FACE_EXPRESSIONS_HTC: u32 = 3;
+
+const BRIDGE_STATE_STARTING: u32 = 0;
+const BRIDGE_STATE_WAITING_FOR_PRODUCER: u32 = 1;
+const BRIDGE_STATE_WAITING_FOR_CLIENT: u32 = 2;
+const BRIDGE_STATE_STANDBY: u32 = 3;
+const BRIDGE_STATE_STREAMING: u32 = 4;
+const BRIDGE_STATE_CLOSING: u32 = 5;
+
+const NEGOTIATION_NONE: u32 = 0;
+const NEGOTIATION_PROPOSED: u32 = 1;
+const NEGOTIATION_ACCEPTED: u32 = 2;
+const NEGOTIATION_REJECTED: u32 = 3;
+
 const BUTTON_SYSTEM: u64 = 1 << 0;
 const BUTTON_APPLICATION_MENU: u64 = 1 << 1;
 const BUTTON_GRIP: u64 = 1 << 2;
@@ -71,21 +134,234 @@ struct ControllerStateRaw {
     padding: [u8; 8],
 }
 
+#[repr(C)]
+struct PoseSampleRaw {
+    sequence: AtomicU32,
+    reserved: u32,
+    timestamp_ns: u64,
+    pose: [[f32; 4]; 3],
+}
+
+#[repr(C)]
+struct InputEventRaw {
+    sequence: AtomicU32,
+    controller_index: u32,
+    packet_number: u32,
+    reserved: u32,
+    tracking_timestamp_ns: u64,
+    input_update_wall_ns: u64,
+    pose: [[f32; 4]; 3],
+    buttons_pressed: u64,
+    buttons_touched: u64,
+    axes: [[f32; 2]; 5],
+}
+
+/// The latest gaze and expressions, as the client reports them. Eye
+/// orientations are (x, y, z, w) quaternions relative to the head.
+#[repr(C)]
+struct FaceStateRaw {
+    sequence: AtomicU32,
+    flags: u32,
+    tracking_timestamp_ns: u64,
+    /// Combined, left, and right gaze, each valid only when its flag is set.
+    eyes: [[f32; 4]; 3],
+    expression_format: u32,
+    expression_count: u32,
+    expressions: [f32; FACE_EXPRESSIONS_LEN],
+    padding: [u8; 8],
+}
+
+/// One hand's latest skeleton, in the same tracking space as the head and
+/// controller poses. Each joint is a position followed by an (x, y, z, w)
+/// orientation.
+#[repr(C)]
+struct HandSkeletonRaw {
+    sequence: AtomicU32,
+    tracked: AtomicU32,
+    tracking_timestamp_ns: u64,
+    joints: [[f32; 7]; HAND_JOINT_COUNT],
+}
+
+#[repr(C)]
+struct HapticsEventRaw {
+    sequence: AtomicU32,
+    controller_index: u32,
+    duration_ns: u64,
+    frequency: f32,
+    amplitude: f32,
+}
+
+/// How the bridge's own pipeline is doing, so the driver can hold back
+/// frames nobody will see. Latencies cover the last cadence window.
+#[repr(C)]
+struct BridgeStatsRaw {
+    sequence: AtomicU32,
+    state: u32,
+    encoder_queue_depth: u32,
+    frame_rate_millihz: u32,
+    encode_latency_p50_us: u32,
+    encode_latency_p99_us: u32,
+    bitrate_bps: u64,
+    update_wall_ns: u64,
+}
+
+/// What this bridge accepts from the Wine side, as bitmasks so a later
+/// bridge can offer several formats or codecs at once. Today the IOSurface
+/// pool and the ALVR session are fixed at startup, so each mask names the one
+/// format and codec the stream runs with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct FeedbackCapabilities {
+    pub formats: u32,
+    pub codecs: u32,
+    pub max_width: u32,
+    pub max_height: u32,
+    pub features: u32,
+}
+
+impl FeedbackCapabilities {
+    pub fn for_stream(
+        width: u32,
+        height: u32,
+        codec: CodecType,
+        format: SurfaceFormat,
+        game_audio: bool,
+    ) -> Self {
+        Self {
+            formats: match format {
+                SurfaceFormat::Nv12 => FORMAT_NV12,
+                SurfaceFormat::P010 => FORMAT_P010,
+            },
+            codecs: match codec {
+                CodecType::H264 => CODEC_H264,
+                CodecType::Hevc => CODEC_HEVC,
+                CodecType::AV1 => CODEC_AV1,
+            },
+            max_width: width,
+            max_height: height,
+            features: FEATURE_POSE
+                | FEATURE_CONTROLLER_INPUT
+                | FEATURE_HAPTICS
+                | FEATURE_EYE_TRACKING
+                | FEATURE_FACE_TRACKING
+                | FEATURE_HAND_TRACKING
+                | if game_audio { FEATURE_GAME_AUDIO } else { 0 },
+        }
+    }
+
+    /// Accepts a selection that speaks this layout version and picks exactly
+    /// one advertised format and codec plus a subset of the features.
+    fn check(self, protocol_version: u32, selection: DriverSelection) -> Result<()> {
+        ensure!(
+            protocol_version == SHM_VERSION,
+            "driver speaks layout version {protocol_version}, bridge {SHM_VERSION}"
+        );
+        ensure!(
+            selection.format.is_power_of_two() && selection.format & self.formats != 0,
+            "format {:#x} is not one of {:#x}",
+            selection.format,
+            self.formats
+        );
+        ensure!(
+            selection.codec.is_power_of_two() && selection.codec & self.codecs != 0,
+            "codec {:#x} is not one of {:#x}",
+            selection.codec,
+            self.codecs
+        );
+        ensure!(
+            selection.features & !self.features == 0,
+            "features {:#x} exceed {:#x}",
+            selection.features,
+            self.features
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DriverSelection {
+    format: u32,
+    codec: u32,
+    features: u32,
+}
+
+impl fmt::Display for DriverSelection {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = match self.format {
+            FORMAT_NV12 => "NV12",
+            FORMAT_P010 => "P010",
+            _ => "unknown",
+        };
+        let codec = match self.codec {
+            CODEC_H264 => "H.264",
+            CODEC_HEVC => "HEVC",
+            CODEC_AV1 => "AV1",
+            _ => "unknown",
+        };
+        let features = [
+            (FEATURE_POSE, "pose"),
+            (FEATURE_CONTROLLER_INPUT, "controller_input"),
+            (FEATURE_HAPTICS, "haptics"),
+            (FEATURE_GAME_AUDIO, "game_audio"),
+            (FEATURE_EYE_TRACKING, "eye_tracking"),
+            (FEATURE_FACE_TRACKING, "face_tracking"),
+            (FEATURE_HAND_TRACKING, "hand_tracking"),
+        ]
+        .into_iter()
+        .filter(|(bit, _)| self.features & bit != 0)
+        .map(|(_, name)| name)
+        .collect::<Vec<_>>();
+        write!(
+            formatter,
+            "format={format} codec={codec} features={}",
+            if features.is_empty() {
+                "none".to_owned()
+            } else {
+                features.join(",")
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct BridgeStats {
+    pub state: BridgeState,
+    /// Frames submitted to VideoToolbox that have not come back yet.
+    pub encoder_queue_depth: usize,
+    pub frame_rate: f64,
+    pub encode_latency: LatencySummary,
+    pub bitrate_bps: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct HapticsRequest {
+    pub controller_index: usize,
+    pub duration: Duration,
+    pub frequency: f32,
+    pub amplitude: f32,
+}
+
+/// Every scalar another process writes or reads concurrently is an atomic of
+/// the same size and alignment as the plain integer, so the layout matches
+/// `tracking_feedback_layout.h`, which the tests generate from these structs
+/// and which spells them as lock-free `std::atomic<uint32_t>` and
+/// `std::atomic<uint64_t>` in C++. Publishing follows one rule: fill in the fields,
+/// then store the flag (`initialized`, `config_set`, a sequence) with
+/// release, and readers load that flag with acquire before the fields.
 #[repr(C)]
 struct SharedMemoryHeader {
-    magic: u32,
-    version: u32,
+    magic: AtomicU32,
+    version: AtomicU32,
     initialized: AtomicU32,
     shutdown: AtomicU32,
-    config_width: u32,
-    config_height: u32,
-    config_format: u32,
+    config_width: AtomicU32,
+    config_height: AtomicU32,
+    config_format: AtomicU32,
     config_set: AtomicU32,
-    write_sequence: u64,
-    read_sequence: u64,
-    frames_written: u64,
-    frames_encoded: u64,
-    frames_dropped: u64,
+    write_sequence: AtomicU64,
+    read_sequence: AtomicU64,
+    frames_written: AtomicU64,
+    frames_encoded: AtomicU64,
+    frames_dropped: AtomicU64,
     bridge_session_id: AtomicU64,
     bridge_heartbeat_ns: AtomicU64,
     view_config_set: AtomicU32,
@@ -94,8 +370,8 @@ struct SharedMemoryHeader {
     hmd_pose_set: AtomicU32,
     hmd_pose_sequence: AtomicU32,
     frame_pose_sequence: AtomicU32,
-    hmd_pose_timestamp_ns: u64,
-    frame_pose_timestamp_ns: u64,
+    hmd_pose_timestamp_ns: AtomicU64,
+    frame_pose_timestamp_ns: AtomicU64,
     frame_pose: [[f32; 4]; 3],
     hmd_pose: [[f32; 4]; 3],
     frame_headers: [FrameHeaderRaw; NUM_BUFFERS],
@@ -111,9 +387,37 @@ struct SharedMemoryHeader {
     connect_events: AtomicU64,
     disconnect_events: AtomicU64,
     contract_failure_events: AtomicU64,
+    hmd_pose_ring_head: AtomicU64,
+    hmd_pose_ring: [PoseSampleRaw; POSE_RING_LEN],
+    input_ring_head: AtomicU64,
+    input_ring: [InputEventRaw; INPUT_RING_LEN],
+    haptics_write_head: AtomicU64,
+    haptics_read_head: AtomicU64,
+    haptics_ring: [HapticsEventRaw; HAPTICS_RING_LEN],
+    bridge_generation: AtomicU64,
+    driver_pid: AtomicU64,
+    driver_heartbeat_ns: AtomicU64,
+    bridge_formats: AtomicU32,
+    bridge_codecs: AtomicU32,
+    bridge_max_width: AtomicU32,
+    bridge_max_height: AtomicU32,
+    bridge_features: AtomicU32,
+    negotiation_state: AtomicU32,
+    driver_protocol_version: AtomicU32,
+    driver_format: AtomicU32,
+    driver_codec: AtomicU32,
+    driver_features: AtomicU32,
+    face: FaceStateRaw,
+    hands: [HandSkeletonRaw; NUM_CONTROLLERS],
+    bridge_stats: BridgeStatsRaw,
+    driver_paused: AtomicU32,
+    driver_pause_reserved: u32,
 }
 
 const _: () = {
+    assert!(mem::size_of::<AtomicU32>() == 4 && mem::align_of::<AtomicU32>() == 4);
+    assert!(mem::size_of::<AtomicU64>() == 8 && mem::align_of::<AtomicU64>() == 8);
+    assert!(mem::offset_of!(SharedMemoryHeader, config_set) == 28);
     assert!(mem::offset_of!(SharedMemoryHeader, write_sequence) == 32);
     assert!(mem::offset_of!(SharedMemoryHeader, view_config_set) == 88);
     assert!(mem::offset_of!(SharedMemoryHeader, hmd_pose_set) == 132);
@@ -124,20 +428,94 @@ const _: () = {
     assert!(mem::size_of::<FrameHeaderRaw>() == 128);
     assert!(mem::size_of::<ControllerStateRaw>() == 176);
     assert!(mem::offset_of!(SharedMemoryHeader, connect_events) == 1040);
-    assert!(mem::size_of::<SharedMemoryHeader>() == 1064);
+    assert!(mem::size_of::<PoseSampleRaw>() == 64);
+    assert!(mem::offset_of!(SharedMemoryHeader, hmd_pose_ring_head) == 1064);
+    assert!(mem::offset_of!(SharedMemoryHeader, hmd_pose_ring) == 1072);
+    assert!(mem::size_of::<InputEventRaw>() == 136);
+    assert!(mem::offset_of!(SharedMemoryHeader, input_ring_head) == 2096);
+    assert!(mem::offset_of!(SharedMemoryHeader, input_ring) == 2104);
+    assert!(mem::size_of::<HapticsEventRaw>() == 24);
+    assert!(mem::offset_of!(SharedMemoryHeader, haptics_write_head) == 3192);
+    assert!(mem::offset_of!(SharedMemoryHeader, haptics_ring) == 3208);
+    assert!(mem::offset_of!(SharedMemoryHeader, bridge_generation) == 3592);
+    assert!(mem::offset_of!(SharedMemoryHeader, driver_heartbeat_ns) == 3608);
+    assert!(mem::offset_of!(SharedMemoryHeader, bridge_formats) == 3616);
+    assert!(mem::offset_of!(SharedMemoryHeader, negotiation_state) == 3636);
+    assert!(mem::offset_of!(SharedMemoryHeader, driver_features) == 3652);
+    assert!(mem::size_of::<FaceStateRaw>() == 360);
+    assert!(mem::offset_of!(SharedMemoryHeader, face) == 3656);
+    assert!(mem::size_of::<HandSkeletonRaw>() == 744);
+    assert!(mem::offset_of!(SharedMemoryHeader, hands) == 4016);
+    assert!(mem::size_of::<BridgeStatsRaw>() == 40);
+    assert!(mem::offset_of!(SharedMemoryHeader, bridge_stats) == 5504);
+    assert!(mem::offset_of!(SharedMemoryHeader, driver_paused) == 5544);
+    assert!(mem::size_of::<SharedMemoryHeader>() == 5552);
 };
 
 pub(crate) struct TrackingFeedback {
     _file: File,
     mmap: MmapMut,
+    backing: FeedbackBacking,
+    /// What the bridge advertised. A driver's selection is checked against
+    /// this copy, since the one in the header is writable by the driver.
+    capabilities: FeedbackCapabilities,
+    remove_on_drop: bool,
+}
+
+/// Where the mapping lives, so a bridge stopped by a signal can remove it.
+enum FeedbackBacking {
+    File(PathBuf),
+    Posix(String),
 }
 
 impl TrackingFeedback {
-    pub(crate) fn create(runtime_generation: u64) -> Result<Self> {
-        Self::create_at(Path::new(SHM_PATH), runtime_generation)
+    pub(crate) fn create(
+        runtime_generation: u64,
+        capabilities: FeedbackCapabilities,
+    ) -> Result<Self> {
+        let location = FeedbackLocation::from_env()?;
+        if let Some(name) = &location.posix_name {
+            match Self::create_posix(name, runtime_generation, capabilities) {
+                Ok(feedback) => {
+                    println!("tracking_feedback backend=posix name={name}");
+                    return Ok(feedback);
+                }
+                Err(error) => eprintln!(
+                    "WARNING tracking_feedback falling back to the file backend name={name} error={error:#}"
+                ),
+            }
+        }
+        println!(
+            "tracking_feedback backend=file path={}",
+            location.path.display()
+        );
+        Self::create_at(&location.path, runtime_generation, capabilities)
     }
 
-    fn create_at(path: &Path, runtime_generation: u64) -> Result<Self> {
+    /// Maps a `shm_open` segment, which never touches the disk and is gone
+    /// after a reboot. The segment is left in place on a normal exit so a
+    /// driver that still has it mapped sees the next bridge's writes, as with
+    /// the file; only `remove_on_drop` unlinks it.
+    fn create_posix(
+        name: &str,
+        runtime_generation: u64,
+        capabilities: FeedbackCapabilities,
+    ) -> Result<Self> {
+        let (file, existing_size) = open_posix_segment(name)?;
+        Self::initialize(
+            file,
+            FeedbackBacking::Posix(name.to_owned()),
+            existing_size,
+            runtime_generation,
+            capabilities,
+        )
+    }
+
+    fn create_at(
+        path: &Path,
+        runtime_generation: u64,
+        capabilities: FeedbackCapabilities,
+    ) -> Result<Self> {
         let mut options = OpenOptions::new();
         options.read(true).write(true).create(true);
         #[cfg(unix)]
@@ -166,37 +544,77 @@ impl TrackingFeedback {
             file.set_len(header_size as u64)
                 .with_context(|| format!("failed to size {}", path.display()))?;
         }
+        Self::initialize(
+            file,
+            FeedbackBacking::File(path.to_owned()),
+            existing_size,
+            runtime_generation,
+            capabilities,
+        )
+    }
+
+    fn initialize(
+        file: File,
+        backing: FeedbackBacking,
+        existing_size: u64,
+        runtime_generation: u64,
+        capabilities: FeedbackCapabilities,
+    ) -> Result<Self> {
+        let header_size = mem::size_of::<SharedMemoryHeader>();
         let mut mmap = unsafe { MmapOptions::new().len(header_size).map_mut(&file) }
             .context("failed to map OpenVR feedback")?;
+        let header = unsafe { &*(mmap.as_ptr().cast::<SharedMemoryHeader>()) };
         let compatible_header = existing_size >= header_size as u64
-            && mmap[..4] == SHM_MAGIC.to_ne_bytes()
-            && mmap[4..8] == SHM_VERSION.to_ne_bytes();
+            && header.magic.load(Ordering::Acquire) == SHM_MAGIC
+            && header.version.load(Ordering::Acquire) == SHM_VERSION;
+        let mut previous_generation = 0;
         if compatible_header {
-            let header = unsafe { &*(mmap.as_ptr().cast::<SharedMemoryHeader>()) };
+            let owner_pid = header.bridge_pid.load(Ordering::Acquire);
+            ensure!(
+                owner_pid == u64::from(process::id())
+                    || header.shutdown.load(Ordering::Acquire) != 0
+                    || !peer_alive(
+                        owner_pid,
+                        header.bridge_heartbeat_ns.load(Ordering::Acquire),
+                        unix_time_ns()
+                    ),
+                "another bridge pid={owner_pid} is still publishing tracking feedback"
+            );
+            previous_generation = header.bridge_generation.load(Ordering::Acquire);
             header.shutdown.store(1, Ordering::SeqCst);
             header.initialized.store(0, Ordering::SeqCst);
             fence(Ordering::SeqCst);
         }
         mmap.fill(0);
 
-        let mut feedback = Self { _file: file, mmap };
+        let mut feedback = Self {
+            _file: file,
+            mmap,
+            backing,
+            capabilities,
+            remove_on_drop: false,
+        };
         let session_id = unix_time_ns() ^ u64::from(process::id());
         let heartbeat = unix_time_ns();
         let header = feedback.header_mut();
         header.shutdown.store(1, Ordering::SeqCst);
         header.initialized.store(0, Ordering::SeqCst);
         fence(Ordering::SeqCst);
-        header.magic = SHM_MAGIC;
-        header.version = SHM_VERSION;
-        header.config_width = 0;
-        header.config_height = 0;
-        header.config_format = 0;
+        // Nothing below is visible to a driver until `initialized` is
+        // released at the end.
+        header.magic.store(SHM_MAGIC, Ordering::Relaxed);
+        header.version.store(SHM_VERSION, Ordering::Relaxed);
         header.config_set.store(0, Ordering::Relaxed);
+        header.config_width.store(0, Ordering::Relaxed);
+        header.config_height.store(0, Ordering::Relaxed);
+        header.config_format.store(0, Ordering::Relaxed);
         let write_sequence = begin_feedback_write(&header.hmd_pose_sequence);
         header.view_config_set.store(0, Ordering::Relaxed);
         header.hmd_pose_set.store(0, Ordering::Relaxed);
         finish_feedback_write(&header.hmd_pose_sequence, write_sequence);
         reset_controllers(header);
+        reset_face(header);
+        reset_hands(header);
         header
             .bridge_session_id
             .store(session_id.max(1), Ordering::Relaxed);
@@ -221,16 +639,59 @@ impl TrackingFeedback {
         header.disconnect_events.store(0, Ordering::Relaxed);
         header.contract_failure_events.store(0, Ordering::Relaxed);
         finish_feedback_write(&header.telemetry_sequence, telemetry_sequence);
+        header.bridge_generation.store(
+            previous_generation.wrapping_add(1).max(1),
+            Ordering::Relaxed,
+        );
+        header
+            .bridge_formats
+            .store(capabilities.formats, Ordering::Relaxed);
+        header
+            .bridge_codecs
+            .store(capabilities.codecs, Ordering::Relaxed);
+        header
+            .bridge_max_width
+            .store(capabilities.max_width, Ordering::Relaxed);
+        header
+            .bridge_max_height
+            .store(capabilities.max_height, Ordering::Relaxed);
+        header
+            .bridge_features
+            .store(capabilities.features, Ordering::Relaxed);
+        header
+            .negotiation_state
+            .store(NEGOTIATION_NONE, Ordering::Relaxed);
         header.shutdown.store(0, Ordering::Release);
         header.initialized.store(1, Ordering::Release);
 
         Ok(feedback)
     }
 
+    /// Unlinks the file or segment once the driver has been told to shut
+    /// down. Only a bridge stopped by a signal asks for this; a normal exit
+    /// keeps the mapping for the next bridge.
+    pub(crate) fn remove_on_drop(&mut self) {
+        self.remove_on_drop = true;
+    }
+
+    /// Also checks on the driver: once its heartbeat goes stale, whatever
+    /// it left mid-write is reset so a restarted driver starts clean, and a
+    /// selection it proposed is answered.
     pub(crate) fn refresh_heartbeat(&mut self) {
-        self.header_mut()
-            .bridge_heartbeat_ns
-            .store(unix_time_ns(), Ordering::Relaxed);
+        let now = unix_time_ns();
+        let capabilities = self.capabilities;
+        let header = self.header_mut();
+        header.bridge_heartbeat_ns.store(now, Ordering::Relaxed);
+        let driver_heartbeat = header.driver_heartbeat_ns.load(Ordering::Acquire);
+        let driver_pid = header.driver_pid.load(Ordering::Acquire);
+        if driver_heartbeat != 0 && !peer_alive(driver_pid, driver_heartbeat, now) {
+            reset_stale_driver(header);
+            eprintln!(
+                "WARNING tracking_feedback driver went stale; reset its buffer states pid={driver_pid} last_heartbeat_ms_ago={}",
+                now.saturating_sub(driver_heartbeat) / 1_000_000
+            );
+        }
+        answer_driver_selection(header, capabilities);
     }
 
     pub(crate) fn publish_client_connected(&mut self, stream_epoch: u64, contract_valid: bool) {
@@ -281,6 +742,34 @@ impl TrackingFeedback {
         true
     }
 
+    pub(crate) fn publish_bridge_stats(&mut self, stats: BridgeStats) {
+        let micros = |latency: Duration| u32::try_from(latency.as_micros()).unwrap_or(u32::MAX);
+        let now = unix_time_ns();
+        let block = &mut self.header_mut().bridge_stats;
+        let write_sequence = begin_feedback_write(&block.sequence);
+        block.state = match stats.state {
+            BridgeState::Starting => BRIDGE_STATE_STARTING,
+            BridgeState::WaitingForProducer => BRIDGE_STATE_WAITING_FOR_PRODUCER,
+            BridgeState::WaitingForClient => BRIDGE_STATE_WAITING_FOR_CLIENT,
+            BridgeState::Standby => BRIDGE_STATE_STANDBY,
+            BridgeState::Streaming => BRIDGE_STATE_STREAMING,
+            BridgeState::Closing => BRIDGE_STATE_CLOSING,
+        };
+        block.encoder_queue_depth = u32::try_from(stats.encoder_queue_depth).unwrap_or(u32::MAX);
+        block.frame_rate_millihz = (stats.frame_rate * 1000.0).round() as u32;
+        block.encode_latency_p50_us = micros(stats.encode_latency.p50);
+        block.encode_latency_p99_us = micros(stats.encode_latency.p99);
+        block.bitrate_bps = stats.bitrate_bps;
+        block.update_wall_ns = now;
+        finish_feedback_write(&block.sequence, write_sequence);
+    }
+
+    /// Whether the driver has said SteamVR went idle. It keeps submitting
+    /// frames, but they repeat the last one, so nothing needs encoding.
+    pub(crate) fn driver_paused(&self) -> bool {
+        self.header().driver_paused.load(Ordering::Acquire) != 0
+    }
+
     pub(crate) fn reset(&mut self) {
         let header = self.header_mut();
         let write_sequence = begin_feedback_write(&header.hmd_pose_sequence);
@@ -288,6 +777,8 @@ impl TrackingFeedback {
         header.hmd_pose_set.store(0, Ordering::Relaxed);
         finish_feedback_write(&header.hmd_pose_sequence, write_sequence);
         reset_controllers(header);
+        reset_face(header);
+        reset_hands(header);
     }
 
     pub(crate) fn publish_view_params(&mut self, params: [ViewParams; 2]) -> bool {
@@ -326,13 +817,13 @@ impl TrackingFeedback {
         let write_sequence = begin_feedback_write(&header.hmd_pose_sequence);
         unsafe {
             ptr::write_volatile(ptr::addr_of_mut!(header.hmd_pose), matrix);
-            ptr::write_volatile(
-                ptr::addr_of_mut!(header.hmd_pose_timestamp_ns),
-                timestamp.as_nanos() as u64,
-            );
         }
+        header
+            .hmd_pose_timestamp_ns
+            .store(timestamp.as_nanos() as u64, Ordering::Relaxed);
         header.hmd_pose_set.store(1, Ordering::Relaxed);
         finish_feedback_write(&header.hmd_pose_sequence, write_sequence);
+        push_pose_sample(header, timestamp.as_nanos() as u64, matrix);
 
         true
     }
@@ -390,16 +881,527 @@ impl TrackingFeedback {
                 updated_controllers += 1;
             }
             finish_feedback_write(&controller.sequence, write_sequence);
+            if changed {
+                push_input_event(self.header_mut(), controller_index);
+            }
         }
 
         updated_controllers
     }
 
+    /// Publishes whatever gaze and expressions arrived with one tracking
+    /// sample. Non-finite values and expression sets of an unexpected length
+    /// are left out, so the flags and format only cover what was written.
+    pub(crate) fn publish_face(&mut self, timestamp: Duration, face: &FaceData) -> bool {
+        let mut flags = 0;
+        let mut eyes = [[0.0; 4]; 3];
+        for (index, (flag, eye)) in [
+            (FACE_EYE_COMBINED, face.eyes_combined),
+            (FACE_EYE_LEFT, face.eyes_social[0]),
+            (FACE_EYE_RIGHT, face.eyes_social[1]),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if let Some(eye) = eye.filter(|eye| eye.is_finite() && eye.is_normalized()) {
+                eyes[index] = eye.to_array();
+                flags |= flag;
+            }
+        }
+        let mut expressions = [0.0; FACE_EXPRESSIONS_LEN];
+        let valid = |values: &[f32], len| {
+            values.len() == len && values.iter().all(|value| value.is_finite())
+        };
+        let (expression_format, expression_count) = match &face.face_expressions {
+            Some(FaceExpressions::Fb(values)) if valid(values, FACE_EXPRESSIONS_LEN) => {
+                expressions.copy_from_slice(values);
+                (FACE_EXPRESSIONS_FB, values.len())
+            }
+            Some(FaceExpressions::Bd(values)) if valid(values, BD_EXPRESSIONS_LEN) => {
+                expressions[..values.len()].copy_from_slice(values);
+                (FACE_EXPRESSIONS_BD, values.len())
+            }
+            Some(FaceExpressions::Htc { eye, lip }) => {
+                // The eye set comes first and the lip set after it, with a
+                // missing half left zero and its flag clear.
+                if let Some(eye) = eye
+                    .as_deref()
+                    .filter(|eye| valid(eye, HTC_EYE_EXPRESSIONS_LEN))
+                {
+                    expressions[..HTC_EYE_EXPRESSIONS_LEN].copy_from_slice(eye);
+                    flags |= FACE_HTC_EYE;
+                }
+                if let Some(lip) = lip
+                    .as_deref()
+                    .filter(|lip| valid(lip, HTC_LIP_EXPRESSIONS_LEN))
+                {
+                    expressions[HTC_EYE_EXPRESSIONS_LEN..][..HTC_LIP_EXPRESSIONS_LEN]
+                        .copy_from_slice(lip);
+                    flags |= FACE_HTC_LIP;
+                }
+                if flags & (FACE_HTC_EYE | FACE_HTC_LIP) == 0 {
+                    (FACE_EXPRESSIONS_NONE, 0)
+                } else {
+                    (
+                        FACE_EXPRESSIONS_HTC,
+                        HTC_EYE_EXPRESSIONS_LEN + HTC_LIP_EXPRESSIONS_LEN,
+                    )
+                }
+            }
+            _ => (FACE_EXPRESSIONS_NONE, 0),
+        };
+        if flags == 0 && expression_format == FACE_EXPRESSIONS_NONE {
+            return false;
+        }
+
+        let face = &mut self.header_mut().face;
+        let write_sequence = begin_feedback_write(&face.sequence);
+        face.flags = flags;
+        face.tracking_timestamp_ns = timestamp.as_nanos() as u64;
+        face.eyes = eyes;
+        face.expression_format = expression_format;
+        face.expression_count = expression_count as u32;
+        face.expressions = expressions;
+        finish_feedback_write(&face.sequence, write_sequence);
+
+        true
+    }
+
+    /// Publishes one hand's skeleton for a tracking sample, or marks the hand
+    /// untracked when the sample has none, as when the user holds
+    /// controllers. A skeleton with any invalid joint is skipped.
+    pub(crate) fn publish_hand_skeleton(
+        &mut self,
+        hand_index: usize,
+        timestamp: Duration,
+        skeleton: Option<&[Pose; HAND_JOINT_COUNT]>,
+    ) -> bool {
+        if hand_index >= NUM_CONTROLLERS {
+            return false;
+        }
+        let hand = &mut self.header_mut().hands[hand_index];
+        let Some(skeleton) = skeleton else {
+            if hand.tracked.load(Ordering::Relaxed) != 0 {
+                let write_sequence = begin_feedback_write(&hand.sequence);
+                hand.tracked.store(0, Ordering::Relaxed);
+                finish_feedback_write(&hand.sequence, write_sequence);
+            }
+            return false;
+        };
+        if !skeleton.iter().all(|joint| valid_pose(*joint)) {
+            return false;
+        }
+
+        let joints = skeleton.map(|joint| {
+            let [x, y, z] = joint.position.to_array();
+            let [qx, qy, qz, qw] = joint.orientation.to_array();
+            [x, y, z, qx, qy, qz, qw]
+        });
+        let write_sequence = begin_feedback_write(&hand.sequence);
+        hand.tracking_timestamp_ns = timestamp.as_nanos() as u64;
+        hand.joints = joints;
+        hand.tracked.store(1, Ordering::Relaxed);
+        finish_feedback_write(&hand.sequence, write_sequence);
+
+        true
+    }
+
+    /// Drains haptics the Wine side queued since the last call. Entries that
+    /// were overwritten or torn before the bridge read them are skipped.
+    pub(crate) fn take_haptics(&mut self) -> Vec<HapticsRequest> {
+        let header = self.header_mut();
+        let write_head = header.haptics_write_head.load(Ordering::Acquire);
+        // A producer that restarted its counter resumes from its new head.
+        let read_head = header
+            .haptics_read_head
+            .load(Ordering::Relaxed)
+            .min(write_head);
+        let first = read_head.max(write_head.saturating_sub(HAPTICS_RING_LEN as u64));
+        let mut requests = Vec::new();
+        for position in first..write_head {
+            let event = &header.haptics_ring[position as usize % HAPTICS_RING_LEN];
+            let sequence = event.sequence.load(Ordering::Acquire);
+            let (controller_index, duration_ns, frequency, amplitude) = unsafe {
+                (
+                    ptr::read_volatile(ptr::addr_of!(event.controller_index)),
+                    ptr::read_volatile(ptr::addr_of!(event.duration_ns)),
+                    ptr::read_volatile(ptr::addr_of!(event.frequency)),
+                    ptr::read_volatile(ptr::addr_of!(event.amplitude)),
+                )
+            };
+            fence(Ordering::Acquire);
+            if !sequence.is_multiple_of(2) || event.sequence.load(Ordering::Relaxed) != sequence {
+                continue;
+            }
+            let duration = Duration::from_nanos(duration_ns);
+            if (controller_index as usize) < NUM_CONTROLLERS
+                && duration <= MAX_HAPTICS_DURATION
+                && frequency.is_finite()
+                && frequency >= 0.0
+                && (0.0..=1.0).contains(&amplitude)
+            {
+                requests.push(HapticsRequest {
+                    controller_index: controller_index as usize,
+                    duration,
+                    frequency,
+                    amplitude,
+                });
+            }
+        }
+        header
+            .haptics_read_head
+            .store(write_head, Ordering::Release);
+
+        requests
+    }
+
+    /// The header's scalar fields as one logfmt line, for crash reports.
+    pub(crate) fn header_snapshot(&self) -> String {
+        let header = self.header();
+        let load32 = |value: &AtomicU32| value.load(Ordering::Acquire);
+        let load64 = |value: &AtomicU64| value.load(Ordering::Acquire);
+        let frame_states = header
+            .frame_headers
+            .iter()
+            .map(|frame| load32(&frame.state).to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            "magic={:#x} version={} initialized={} shutdown={} config={}x{} config_format={} config_set={} write_sequence={} read_sequence={} frames_written={} frames_encoded={} frames_dropped={} frame_states=[{frame_states}] client_state={} stream_contract_valid={} stream_epoch={} frames_transported={} connect_events={} disconnect_events={} contract_failure_events={} bridge_generation={} bridge_heartbeat_ns={} driver_pid={} driver_heartbeat_ns={} negotiation_state={} driver_protocol_version={} driver_format={} driver_codec={} driver_paused={}",
+            load32(&header.magic),
+            load32(&header.version),
+            load32(&header.initialized),
+            load32(&header.shutdown),
+            load32(&header.config_width),
+            load32(&header.config_height),
+            load32(&header.config_format),
+            load32(&header.config_set),
+            load64(&header.write_sequence),
+            load64(&header.read_sequence),
+            load64(&header.frames_written),
+            load64(&header.frames_encoded),
+            load64(&header.frames_dropped),
+            load32(&header.client_state),
+            load32(&header.stream_contract_valid),
+            load64(&header.stream_epoch),
+            load64(&header.frames_transported),
+            load64(&header.connect_events),
+            load64(&header.disconnect_events),
+            load64(&header.contract_failure_events),
+            load64(&header.bridge_generation),
+            load64(&header.bridge_heartbeat_ns),
+            load64(&header.driver_pid),
+            load64(&header.driver_heartbeat_ns),
+            load32(&header.negotiation_state),
+            load32(&header.driver_protocol_version),
+            load32(&header.driver_format),
+            load32(&header.driver_codec),
+            load32(&header.driver_paused),
+        )
+    }
+
+    fn header(&self) -> &SharedMemoryHeader {
+        unsafe { &*(self.mmap.as_ptr() as *const SharedMemoryHeader) }
+    }
+
     fn header_mut(&mut self) -> &mut SharedMemoryHeader {
         unsafe { &mut *(self.mmap.as_mut_ptr() as *mut SharedMemoryHeader) }
     }
 }
 
+/// Where the feedback segment lives. A session id gives every bridge its own
+/// file and POSIX name, so the Wine side finds its bridge by the same id
+/// instead of a fixed path; explicit locations still win.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FeedbackLocation {
+    posix_name: Option<String>,
+    path: PathBuf,
+}
+
+impl FeedbackLocation {
+    fn from_env() -> Result<Self> {
+        Self::resolve(
+            env::var_os("ALVR_BRIDGE_TRACKING_SHM")
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+            env::var("ALVR_BRIDGE_TRACKING_SHM_NAME")
+                .ok()
+                .filter(|name| !name.is_empty()),
+            env::var("ALVR_BRIDGE_TRACKING_POSIX").as_deref() == Ok("1"),
+            env::var("ALVR_BRIDGE_TRACKING_SESSION")
+                .ok()
+                .filter(|session| !session.is_empty()),
+        )
+    }
+
+    fn resolve(
+        path: Option<PathBuf>,
+        posix_name: Option<String>,
+        posix: bool,
+        session: Option<String>,
+    ) -> Result<Self> {
+        if let Some(session) = &session {
+            ensure!(
+                session.len() <= MAX_SESSION_ID_LEN
+                    && session
+                        .bytes()
+                        .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_'),
+                "ALVR_BRIDGE_TRACKING_SESSION must be at most {MAX_SESSION_ID_LEN} letters, digits, '-' or '_'"
+            );
+        }
+        let posix_name = posix_name.or_else(|| {
+            posix.then(|| match &session {
+                Some(session) => format!("/alvr_fb.{session}"),
+                None => POSIX_SHM_NAME.to_owned(),
+            })
+        });
+        let path = path.unwrap_or_else(|| match &session {
+            Some(session) => PathBuf::from(format!("/tmp/alvr_frame_buffer.{session}.shm")),
+            None => PathBuf::from(SHM_PATH),
+        });
+        Ok(Self { posix_name, path })
+    }
+}
+
+/// Opens or creates the named segment, sized for the current header. macOS
+/// only lets a segment be sized once, so a smaller one left by an older
+/// layout is unlinked and created again.
+fn open_posix_segment(name: &str) -> Result<(File, u64)> {
+    ensure!(
+        name.len() > 1
+            && name.len() <= POSIX_SHM_NAME_MAX
+            && name.starts_with('/')
+            && !name[1..].contains('/'),
+        "POSIX shared memory name must be a single /component of at most {POSIX_SHM_NAME_MAX} bytes: {name:?}"
+    );
+    let c_name = CString::new(name).context("POSIX shared memory name contains a NUL byte")?;
+    let header_size = mem::size_of::<SharedMemoryHeader>() as u64;
+    for _ in 0..2 {
+        let fd = unsafe {
+            libc::shm_open(
+                c_name.as_ptr(),
+                libc::O_RDWR | libc::O_CREAT,
+                POSIX_SHM_MODE,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error())
+                .with_context(|| format!("failed to open shared memory {name}"));
+        }
+        let file = unsafe { File::from_raw_fd(fd) };
+        let metadata = file
+            .metadata()
+            .with_context(|| format!("failed to inspect shared memory {name}"))?;
+        ensure!(
+            metadata.uid() == unsafe { libc::geteuid() },
+            "shared memory {name} belongs to another user"
+        );
+        match metadata.len() {
+            0 => {
+                file.set_len(header_size)
+                    .with_context(|| format!("failed to size shared memory {name}"))?;
+                return Ok((file, 0));
+            }
+            size if size >= header_size => return Ok((file, size)),
+            size => {
+                eprintln!(
+                    "WARNING tracking_feedback removing stale shared memory name={name} size={size}"
+                );
+                drop(file);
+                unsafe { libc::shm_unlink(c_name.as_ptr()) };
+            }
+        }
+    }
+    anyhow::bail!("shared memory {name} was recreated too small by another process")
+}
+
+/// Checks that this user can open the feedback segment the environment names,
+/// without changing a segment a driver may already have mapped. Where none
+/// exists yet, a scratch segment or file is created beside it and removed.
+/// Returns the locations checked.
+pub(crate) fn check_feedback_access() -> Result<String> {
+    let location = FeedbackLocation::from_env()?;
+    let mut checked = Vec::new();
+    if let Some(name) = &location.posix_name {
+        check_posix_access(name)?;
+        checked.push(format!("name={name}"));
+    }
+    // The file is also the fallback when the POSIX segment fails.
+    check_file_access(&location.path)?;
+    checked.push(format!("path={}", location.path.display()));
+    Ok(checked.join(" "))
+}
+
+fn check_posix_access(name: &str) -> Result<()> {
+    let c_name = CString::new(name).context("POSIX shared memory name contains a NUL byte")?;
+    let fd = unsafe { libc::shm_open(c_name.as_ptr(), libc::O_RDWR) };
+    if fd >= 0 {
+        unsafe { libc::close(fd) };
+        return Ok(());
+    }
+    let error = io::Error::last_os_error();
+    ensure!(
+        error.kind() == io::ErrorKind::NotFound,
+        "cannot open shared memory {name}: {error}"
+    );
+    let scratch = CString::new(format!("/alvr_doctor.{}", process::id()))
+        .expect("scratch name has no NUL byte");
+    let fd = unsafe {
+        libc::shm_open(
+            scratch.as_ptr(),
+            libc::O_RDWR | libc::O_CREAT | libc::O_EXCL,
+            POSIX_SHM_MODE,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error()).context("cannot create POSIX shared memory");
+    }
+    unsafe {
+        libc::close(fd);
+        libc::shm_unlink(scratch.as_ptr());
+    }
+    Ok(())
+}
+
+fn check_file_access(path: &Path) -> Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) => {
+            #[cfg(unix)]
+            ensure!(
+                metadata.is_file() && metadata.uid() == unsafe { libc::geteuid() },
+                "{} is not a regular file owned by this user; remove it",
+                path.display()
+            );
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(path)
+                .with_context(|| format!("cannot open {} for writing", path.display()))?;
+        }
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            let scratch = path.with_file_name(format!(".alvr_doctor.{}", process::id()));
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&scratch)
+                .with_context(|| format!("cannot create {}", scratch.display()))?;
+            fs::remove_file(&scratch)
+                .with_context(|| format!("failed to remove {}", scratch.display()))?;
+        }
+        Err(error) => {
+            return Err(error).with_context(|| format!("failed to inspect {}", path.display()));
+        }
+    }
+    Ok(())
+}
+
+// Readers pick the sample nearest their render time; a slot whose sequence is
+// odd or changes across the read is being overwritten and must be skipped.
+fn push_pose_sample(header: &mut SharedMemoryHeader, timestamp_ns: u64, pose: [[f32; 4]; 3]) {
+    let head = header.hmd_pose_ring_head.load(Ordering::Relaxed);
+    if head > 0 {
+        let latest = &header.hmd_pose_ring[(head - 1) as usize % POSE_RING_LEN];
+        if latest.timestamp_ns == timestamp_ns {
+            return;
+        }
+    }
+    let sample = &mut header.hmd_pose_ring[head as usize % POSE_RING_LEN];
+    let write_sequence = begin_feedback_write(&sample.sequence);
+    unsafe {
+        ptr::write_volatile(ptr::addr_of_mut!(sample.timestamp_ns), timestamp_ns);
+        ptr::write_volatile(ptr::addr_of_mut!(sample.pose), pose);
+    }
+    finish_feedback_write(&sample.sequence, write_sequence);
+    header
+        .hmd_pose_ring_head
+        .store(head.wrapping_add(1), Ordering::Release);
+}
+
+// Latest-state controller slots can hide a press and release that land between
+// two driver polls; the input ring keeps every change in order.
+fn push_input_event(header: &mut SharedMemoryHeader, controller_index: usize) {
+    let head = header.input_ring_head.load(Ordering::Relaxed);
+    let controller = &header.controllers[controller_index];
+    let (packet_number, tracking_timestamp_ns, input_update_wall_ns, pose) = (
+        controller.packet_number,
+        controller.tracking_timestamp_ns,
+        controller.input_update_wall_ns,
+        controller.pose,
+    );
+    let (buttons_pressed, buttons_touched, axes) = (
+        controller.buttons_pressed,
+        controller.buttons_touched,
+        controller.axes,
+    );
+    let event = &mut header.input_ring[head as usize % INPUT_RING_LEN];
+    let write_sequence = begin_feedback_write(&event.sequence);
+    event.controller_index = controller_index as u32;
+    event.packet_number = packet_number;
+    event.tracking_timestamp_ns = tracking_timestamp_ns;
+    event.input_update_wall_ns = input_update_wall_ns;
+    event.pose = pose;
+    event.buttons_pressed = buttons_pressed;
+    event.buttons_touched = buttons_touched;
+    event.axes = axes;
+    finish_feedback_write(&event.sequence, write_sequence);
+    header
+        .input_ring_head
+        .store(head.wrapping_add(1), Ordering::Release);
+}
+
+fn answer_driver_selection(header: &mut SharedMemoryHeader, capabilities: FeedbackCapabilities) {
+    if header.negotiation_state.load(Ordering::Acquire) != NEGOTIATION_PROPOSED {
+        return;
+    }
+    let selection = DriverSelection {
+        format: header.driver_format.load(Ordering::Relaxed),
+        codec: header.driver_codec.load(Ordering::Relaxed),
+        features: header.driver_features.load(Ordering::Relaxed),
+    };
+    let protocol_version = header.driver_protocol_version.load(Ordering::Relaxed);
+    let state = match capabilities.check(protocol_version, selection) {
+        Ok(()) => {
+            println!("tracking_feedback negotiated {selection}");
+            NEGOTIATION_ACCEPTED
+        }
+        Err(error) => {
+            eprintln!(
+                "WARNING tracking_feedback rejected the driver's selection {selection} error={error:#}"
+            );
+            NEGOTIATION_REJECTED
+        }
+    };
+    header.negotiation_state.store(state, Ordering::Release);
+}
+
+fn reset_stale_driver(header: &mut SharedMemoryHeader) {
+    header.config_set.store(0, Ordering::Release);
+    header
+        .negotiation_state
+        .store(NEGOTIATION_NONE, Ordering::Release);
+    for frame in &header.frame_headers {
+        frame.state.store(0, Ordering::Relaxed);
+    }
+    header.driver_paused.store(0, Ordering::Relaxed);
+    header.driver_pid.store(0, Ordering::Relaxed);
+    header.driver_heartbeat_ns.store(0, Ordering::Release);
+}
+
+/// A peer is live while its heartbeat is recent and, when it published a
+/// pid, that process still exists.
+fn peer_alive(pid: u64, heartbeat_ns: u64, now_ns: u64) -> bool {
+    let fresh = now_ns.saturating_sub(heartbeat_ns) < PEER_STALE_AFTER.as_nanos() as u64;
+    fresh && (pid == 0 || process_exists(pid))
+}
+
+fn process_exists(pid: u64) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    unsafe { libc::kill(pid, 0) == 0 }
+    || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
 fn reset_controllers(header: &mut SharedMemoryHeader) {
     for controller in &mut header.controllers {
         let write_sequence = begin_feedback_write(&controller.sequence);
@@ -420,6 +1422,29 @@ fn reset_controllers(header: &mut SharedMemoryHeader) {
     }
 }
 
+fn reset_face(header: &mut SharedMemoryHeader) {
+    let face = &mut header.face;
+    let write_sequence = begin_feedback_write(&face.sequence);
+    face.flags = 0;
+    face.tracking_timestamp_ns = 0;
+    face.eyes = [[0.0; 4]; 3];
+    face.expression_format = FACE_EXPRESSIONS_NONE;
+    face.expression_count = 0;
+    face.expressions = [0.0; FACE_EXPRESSIONS_LEN];
+    face.padding = [0; 8];
+    finish_feedback_write(&face.sequence, write_sequence);
+}
+
+fn reset_hands(header: &mut SharedMemoryHeader) {
+    for hand in &mut header.hands {
+        let write_sequence = begin_feedback_write(&hand.sequence);
+        hand.tracked.store(0, Ordering::Relaxed);
+        hand.tracking_timestamp_ns = 0;
+        hand.joints = [[0.0; 7]; HAND_JOINT_COUNT];
+        finish_feedback_write(&hand.sequence, write_sequence);
+    }
+}
+
 fn controller_index_for_button(path_id: u64) -> Option<usize> {
     let device_id = inp::BUTTON_INFO.get(&path_id)?.device_id;
     if device_id == *inp::HAND_LEFT_ID {
@@ -643,6 +1668,27 @@ fn set_axis(axis: &mut f32, value: f32) -> bool {
     }
 }
 
+/// Runs one input through everything the bridge reads from a driver: first
+/// as a segment left behind by an earlier bridge or driver, then as driver
+/// writes over a live mapping.
+#[cfg(feature = "fuzzing")]
+pub(crate) fn fuzz_driver_writes(path: &Path, bytes: &[u8]) {
+    let _ = fs::remove_file(path);
+    if fs::write(path, bytes).is_err() {
+        return;
+    }
+    let capabilities =
+        FeedbackCapabilities::for_stream(2048, 1024, CodecType::Hevc, SurfaceFormat::Nv12, true);
+    let Ok(mut feedback) = TrackingFeedback::create_at(path, 1, capabilities) else {
+        return;
+    };
+    let len = bytes.len().min(feedback.mmap.len());
+    feedback.mmap[..len].copy_from_slice(&bytes[..len]);
+    feedback.refresh_heartbeat();
+    feedback.take_haptics();
+    feedback.header_snapshot();
+}
+
 impl Drop for TrackingFeedback {
     fn drop(&mut self) {
         let header = self.header_mut();
@@ -656,6 +1702,29 @@ impl Drop for TrackingFeedback {
             .bridge_heartbeat_ns
             .store(unix_time_ns(), Ordering::Relaxed);
         header.shutdown.store(1, Ordering::Release);
+        if self.remove_on_drop {
+            remove_backing(&self.backing);
+        }
+    }
+}
+
+fn remove_backing(backing: &FeedbackBacking) {
+    let (kind, name, result) = match backing {
+        FeedbackBacking::File(path) => ("path", path.display().to_string(), fs::remove_file(path)),
+        FeedbackBacking::Posix(name) => {
+            let result = match CString::new(name.as_str()) {
+                Ok(c_name) if unsafe { libc::shm_unlink(c_name.as_ptr()) } == 0 => Ok(()),
+                Ok(_) => Err(io::Error::last_os_error()),
+                Err(error) => Err(error.into()),
+            };
+            ("name", name.clone(), result)
+        }
+    };
+    match result {
+        Ok(()) => println!("tracking_feedback removed {kind}={name}"),
+        Err(error) => {
+            eprintln!("WARNING tracking_feedback removal failed {kind}={name} error={error}")
+        }
     }
 }
 
@@ -730,6 +1799,14 @@ mod tests {
     };
     use std::fs;
 
+    const TEST_CAPABILITIES: FeedbackCapabilities = FeedbackCapabilities {
+        formats: FORMAT_NV12,
+        codecs: CODEC_HEVC,
+        max_width: 2048,
+        max_height: 1024,
+        features: FEATURE_POSE | FEATURE_CONTROLLER_INPUT | FEATURE_HAPTICS,
+    };
+
     #[test]
     fn publishes_openvr_view_and_pose_feedback() {
         let path = std::env::temp_dir().join(format!(
@@ -737,7 +1814,7 @@ mod tests {
             process::id(),
             unix_time_ns()
         ));
-        let mut feedback = TrackingFeedback::create_at(&path, 42).unwrap();
+        let mut feedback = TrackingFeedback::create_at(&path, 42, TEST_CAPABILITIES).unwrap();
         let params = [
             ViewParams {
                 pose: Pose {
@@ -773,8 +1850,8 @@ mod tests {
         assert!(feedback.publish_hmd_pose(Duration::from_nanos(123), pose));
 
         let header = feedback.header_mut();
-        assert_eq!(header.magic, SHM_MAGIC);
-        assert_eq!(header.version, SHM_VERSION);
+        assert_eq!(header.magic.load(Ordering::Acquire), SHM_MAGIC);
+        assert_eq!(header.version.load(Ordering::Acquire), SHM_VERSION);
         assert_eq!(header.initialized.load(Ordering::Acquire), 1);
         assert_eq!(header.shutdown.load(Ordering::Acquire), 0);
         assert_eq!(header.runtime_generation.load(Ordering::Acquire), 42);
@@ -795,7 +1872,7 @@ mod tests {
                 .load(Ordering::Acquire)
                 .is_multiple_of(2)
         );
-        assert_eq!(header.hmd_pose_timestamp_ns, 123);
+        assert_eq!(header.hmd_pose_timestamp_ns.load(Ordering::Acquire), 123);
         assert_eq!(header.hmd_pose[0][3], 1.0);
         assert_eq!(header.hmd_pose[1][3], 2.0);
         assert_eq!(header.hmd_pose[2][3], 3.0);
@@ -804,6 +1881,36 @@ mod tests {
         fs::remove_file(path).unwrap();
     }
 
+    #[test]
+    fn keeps_a_ring_of_recent_head_poses() {
+        let path = std::env::temp_dir().join(format!(
+            "alvr-tracking-pose-ring-{}-{}",
+            process::id(),
+            unix_time_ns()
+        ));
+        let mut feedback = TrackingFeedback::create_at(&path, 1, TEST_CAPABILITIES).unwrap();
+        for sample in 0..20u64 {
+            let pose = Pose {
+                orientation: Quat::IDENTITY,
+                position: Vec3::new(sample as f32, 0.0, 0.0),
+            };
+            assert!(feedback.publish_hmd_pose(Duration::from_millis(sample), pose));
+            assert!(feedback.publish_hmd_pose(Duration::from_millis(sample), pose));
+        }
+
+        let header = feedback.header_mut();
+        assert_eq!(header.hmd_pose_ring_head.load(Ordering::Acquire), 20);
+        let latest = &header.hmd_pose_ring[19 % POSE_RING_LEN];
+        assert!(latest.sequence.load(Ordering::Acquire).is_multiple_of(2));
+        assert_eq!(latest.timestamp_ns, 19_000_000);
+        assert_eq!(latest.pose[0][3], 19.0);
+        let oldest = &header.hmd_pose_ring[20 % POSE_RING_LEN];
+        assert_eq!(oldest.timestamp_ns, 4_000_000);
+
+        drop(feedback);
+        fs::remove_file(path).unwrap();
+    }
+
     #[test]
     fn publishes_controller_motion_and_legacy_input_state() {
         let path = std::env::temp_dir().join(format!(
@@ -811,7 +1918,7 @@ mod tests {
             process::id(),
             unix_time_ns()
         ));
-        let mut feedback = TrackingFeedback::create_at(&path, 43).unwrap();
+        let mut feedback = TrackingFeedback::create_at(&path, 43, TEST_CAPABILITIES).unwrap();
         let motion = DeviceMotion {
             pose: Pose {
                 orientation: Quat::IDENTITY,
@@ -866,6 +1973,30 @@ mod tests {
             assert_ne!(controller.buttons_pressed & BUTTON_A, 0);
         }
 
+        {
+            let header = feedback.header_mut();
+            assert_eq!(header.input_ring_head.load(Ordering::Acquire), 1);
+            let event = &header.input_ring[0];
+            assert!(event.sequence.load(Ordering::Acquire).is_multiple_of(2));
+            assert_eq!(event.controller_index, 0);
+            assert_eq!(event.tracking_timestamp_ns, 456);
+            assert_eq!(event.pose[0][3], -0.25);
+            assert_eq!(event.axes[0], [-0.75, 0.5]);
+            assert_ne!(event.buttons_pressed & BUTTON_A, 0);
+        }
+        let release = [ButtonEntry {
+            path_id: *inp::LEFT_X_CLICK_ID,
+            value: ButtonValue::Binary(false),
+        }];
+        assert_eq!(feedback.publish_buttons(&release), 1);
+        assert_eq!(feedback.publish_buttons(&release), 0);
+        {
+            let header = feedback.header_mut();
+            assert_eq!(header.input_ring_head.load(Ordering::Acquire), 2);
+            assert_ne!(header.input_ring[0].buttons_pressed & BUTTON_A, 0);
+            assert_eq!(header.input_ring[1].buttons_pressed & BUTTON_A, 0);
+        }
+
         feedback.reset();
         assert_eq!(
             feedback.header_mut().controllers[0]
@@ -878,6 +2009,60 @@ mod tests {
         fs::remove_file(path).unwrap();
     }
 
+    fn queue_haptics(header: &mut SharedMemoryHeader, controller_index: u32, amplitude: f32) {
+        let head = header.haptics_write_head.load(Ordering::Relaxed);
+        let event = &mut header.haptics_ring[head as usize % HAPTICS_RING_LEN];
+        let write_sequence = begin_feedback_write(&event.sequence);
+        event.controller_index = controller_index;
+        event.duration_ns = 20_000_000;
+        event.frequency = 160.0;
+        event.amplitude = amplitude;
+        finish_feedback_write(&event.sequence, write_sequence);
+        header.haptics_write_head.store(head + 1, Ordering::Release);
+    }
+
+    #[test]
+    fn drains_haptics_queued_by_the_wine_side() {
+        let path = std::env::temp_dir().join(format!(
+            "alvr-haptics-feedback-{}-{}",
+            process::id(),
+            unix_time_ns()
+        ));
+        let mut feedback = TrackingFeedback::create_at(&path, 46, TEST_CAPABILITIES).unwrap();
+        assert!(feedback.take_haptics().is_empty());
+
+        queue_haptics(feedback.header_mut(), 0, 0.5);
+        queue_haptics(feedback.header_mut(), 1, 1.0);
+        queue_haptics(feedback.header_mut(), 2, 1.0);
+        queue_haptics(feedback.header_mut(), 1, 3.0);
+        assert_eq!(
+            feedback.take_haptics(),
+            [
+                HapticsRequest {
+                    controller_index: 0,
+                    duration: Duration::from_millis(20),
+                    frequency: 160.0,
+                    amplitude: 0.5,
+                },
+                HapticsRequest {
+                    controller_index: 1,
+                    duration: Duration::from_millis(20),
+                    frequency: 160.0,
+                    amplitude: 1.0,
+                },
+            ]
+        );
+        assert!(feedback.take_haptics().is_empty());
+
+        for _ in 0..HAPTICS_RING_LEN + 4 {
+            queue_haptics(feedback.header_mut(), 0, 0.25);
+        }
+        assert_eq!(feedback.take_haptics().len(), HAPTICS_RING_LEN);
+
+        drop(feedback);
+        fs::remove_file(path).unwrap();
+    }
+
     #[test]
     fn preserves_existing_mapping_extent() {
         let path = std::env::temp_dir().join(format!(
@@ -889,7 +2074,7 @@ mod tests {
         file.set_len(4096).unwrap();
         drop(file);
 
-        let feedback = TrackingFeedback::create_at(&path, 44).unwrap();
+        let feedback = TrackingFeedback::create_at(&path, 44, TEST_CAPABILITIES).unwrap();
         let metadata = fs::metadata(&path).unwrap();
         assert_eq!(metadata.len(), 4096);
         assert_eq!(metadata.mode() & 0o777, 0o600);
@@ -905,7 +2090,7 @@ mod tests {
             process::id(),
             unix_time_ns()
         ));
-        let mut feedback = TrackingFeedback::create_at(&path, 45).unwrap();
+        let mut feedback = TrackingFeedback::create_at(&path, 45, TEST_CAPABILITIES).unwrap();
 
         feedback.publish_client_connected(1, true);
         {
@@ -964,7 +2149,7 @@ mod tests {
             process::id(),
             unix_time_ns()
         ));
-        let mut feedback = TrackingFeedback::create_at(&path, 47).unwrap();
+        let mut feedback = TrackingFeedback::create_at(&path, 47, TEST_CAPABILITIES).unwrap();
 
         feedback.publish_client_connected(1, false);
         feedback.publish_client_disconnected(2);
@@ -982,6 +2167,345 @@ mod tests {
         fs::remove_file(path).unwrap();
     }
 
+    fn posix_name(tag: &str) -> String {
+        format!("/alvr-{tag}-{}", process::id())
+    }
+
+    fn unlink_posix(name: &str) {
+        let c_name = CString::new(name).unwrap();
+        unsafe { libc::shm_unlink(c_name.as_ptr()) };
+    }
+
+    #[test]
+    fn publishes_through_a_posix_segment() {
+        let name = posix_name("fb");
+        let mut feedback = TrackingFeedback::create_posix(&name, 48, TEST_CAPABILITIES).unwrap();
+        feedback.publish_client_connected(1, true);
+
+        let header = feedback.header_mut();
+        assert_eq!(header.magic.load(Ordering::Acquire), SHM_MAGIC);
+        assert_eq!(header.initialized.load(Ordering::Acquire), 1);
+        assert_eq!(header.runtime_generation.load(Ordering::Acquire), 48);
+        assert_eq!(header.connect_events.load(Ordering::Acquire), 1);
+
+        drop(feedback);
+        let (_, size) = open_posix_segment(&name).unwrap();
+        assert!(size >= mem::size_of::<SharedMemoryHeader>() as u64);
+        unlink_posix(&name);
+    }
+
+    #[test]
+    fn removes_the_mapping_when_asked_on_drop() {
+        let path = std::env::temp_dir().join(format!(
+            "alvr-feedback-remove-{}-{}",
+            process::id(),
+            unix_time_ns()
+        ));
+        let mut feedback = TrackingFeedback::create_at(&path, 51, TEST_CAPABILITIES).unwrap();
+        feedback.remove_on_drop();
+        drop(feedback);
+        assert!(!path.exists());
+
+        let name = posix_name("remove");
+        let mut feedback = TrackingFeedback::create_posix(&name, 52, TEST_CAPABILITIES).unwrap();
+        feedback.remove_on_drop();
+        drop(feedback);
+        let c_name = CString::new(name).unwrap();
+        assert_eq!(unsafe { libc::shm_open(c_name.as_ptr(), libc::O_RDWR) }, -1);
+    }
+
+    #[test]
+    fn reinitializes_a_stale_posix_segment() {
+        let name = posix_name("stale");
+        let mut stale = TrackingFeedback::create_posix(&name, 49, TEST_CAPABILITIES).unwrap();
+        stale.publish_client_connected(3, false);
+        // Leave it as a crashed bridge would: no Drop, counters set.
+        mem::forget(stale);
+
+        let mut feedback = TrackingFeedback::create_posix(&name, 50, TEST_CAPABILITIES).unwrap();
+        let header = feedback.header_mut();
+        assert_eq!(header.initialized.load(Ordering::Acquire), 1);
+        assert_eq!(header.shutdown.load(Ordering::Acquire), 0);
+        assert_eq!(header.runtime_generation.load(Ordering::Acquire), 50);
+        assert_eq!(header.connect_events.load(Ordering::Acquire), 0);
+        assert_eq!(header.contract_failure_events.load(Ordering::Acquire), 0);
+
+        drop(feedback);
+        unlink_posix(&name);
+    }
+
+    #[test]
+    fn derives_feedback_locations_from_the_session() {
+        let resolve = |path: Option<&str>, name: Option<&str>, posix, session: Option<&str>| {
+            FeedbackLocation::resolve(
+                path.map(PathBuf::from),
+                name.map(str::to_owned),
+                posix,
+                session.map(str::to_owned),
+            )
+        };
+
+        assert_eq!(
+            resolve(None, None, false, None).unwrap(),
+            FeedbackLocation {
+                posix_name: None,
+                path: PathBuf::from(SHM_PATH),
+            }
+        );
+        assert_eq!(
+            resolve(None, None, true, Some("user-2")).unwrap(),
+            FeedbackLocation {
+                posix_name: Some("/alvr_fb.user-2".into()),
+                path: PathBuf::from("/tmp/alvr_frame_buffer.user-2.shm"),
+            }
+        );
+        assert_eq!(
+            resolve(Some("/tmp/custom.shm"), Some("/custom"), false, Some("a")).unwrap(),
+            FeedbackLocation {
+                posix_name: Some("/custom".into()),
+                path: PathBuf::from("/tmp/custom.shm"),
+            }
+        );
+        assert!(resolve(None, None, false, Some("../escape")).is_err());
+        assert!(resolve(None, None, false, Some("a-session-id-too-long")).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_posix_names() {
+        assert!(open_posix_segment("alvr").is_err());
+        assert!(open_posix_segment("/alvr/frame").is_err());
+        assert!(open_posix_segment("/alvr-frame-buffer-name-that-is-too-long").is_err());
+    }
+
+    #[test]
+    fn refuses_a_segment_another_live_bridge_publishes() {
+        let path = std::env::temp_dir().join(format!(
+            "alvr-tracking-live-owner-{}-{}",
+            process::id(),
+            unix_time_ns()
+        ));
+        let mut feedback = TrackingFeedback::create_at(&path, 51, TEST_CAPABILITIES).unwrap();
+        let header = feedback.header_mut();
+        let generation = header.bridge_generation.load(Ordering::Acquire);
+        header.bridge_pid.store(
+            u64::try_from(unsafe { libc::getppid() }).unwrap(),
+            Ordering::Release,
+        );
+        mem::forget(feedback);
+
+        assert!(TrackingFeedback::create_at(&path, 52, TEST_CAPABILITIES).is_err());
+
+        // Once the owner stops heartbeating, its segment is taken over.
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let mut mmap = unsafe { MmapOptions::new().map_mut(&file) }.unwrap();
+        let header = unsafe { &*(mmap.as_mut_ptr().cast::<SharedMemoryHeader>()) };
+        header.bridge_heartbeat_ns.store(1, Ordering::Release);
+        let mut feedback = TrackingFeedback::create_at(&path, 53, TEST_CAPABILITIES).unwrap();
+        assert_eq!(
+            feedback
+                .header_mut()
+                .bridge_generation
+                .load(Ordering::Acquire),
+            generation + 1
+        );
+
+        drop(feedback);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn resets_frame_states_left_by_a_stale_driver() {
+        let path = std::env::temp_dir().join(format!(
+            "alvr-tracking-stale-driver-{}-{}",
+            process::id(),
+            unix_time_ns()
+        ));
+        let mut feedback = TrackingFeedback::create_at(&path, 54, TEST_CAPABILITIES).unwrap();
+        {
+            let header = feedback.header_mut();
+            header.frame_headers[1].state.store(2, Ordering::Release);
+            header.config_set.store(1, Ordering::Release);
+            header
+                .driver_heartbeat_ns
+                .store(unix_time_ns(), Ordering::Release);
+        }
+        feedback.refresh_heartbeat();
+        assert_eq!(
+            feedback.header_mut().frame_headers[1]
+                .state
+                .load(Ordering::Acquire),
+            2
+        );
+
+        feedback
+            .header_mut()
+            .driver_heartbeat_ns
+            .store(1, Ordering::Release);
+        feedback.refresh_heartbeat();
+        let header = feedback.header_mut();
+        assert_eq!(header.frame_headers[1].state.load(Ordering::Acquire), 0);
+        assert_eq!(header.config_set.load(Ordering::Acquire), 0);
+        assert_eq!(header.driver_heartbeat_ns.load(Ordering::Acquire), 0);
+
+        drop(feedback);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn answers_the_driver_selection() {
+        let path = std::env::temp_dir().join(format!(
+            "alvr-tracking-negotiation-{}-{}",
+            process::id(),
+            unix_time_ns()
+        ));
+        let mut feedback = TrackingFeedback::create_at(&path, 55, TEST_CAPABILITIES).unwrap();
+        assert_eq!(
+            feedback
+                .header_mut()
+                .bridge_max_width
+                .load(Ordering::Acquire),
+            2048
+        );
+        let propose = |feedback: &mut TrackingFeedback, codec, features| {
+            let header = feedback.header_mut();
+            header
+                .driver_protocol_version
+                .store(SHM_VERSION, Ordering::Relaxed);
+            header.driver_format.store(FORMAT_NV12, Ordering::Relaxed);
+            header.driver_codec.store(codec, Ordering::Relaxed);
+            header.driver_features.store(features, Ordering::Relaxed);
+            header
+                .negotiation_state
+                .store(NEGOTIATION_PROPOSED, Ordering::Release);
+            feedback.refresh_heartbeat();
+            feedback
+                .header_mut()
+                .negotiation_state
+                .load(Ordering::Acquire)
+        };
+
+        assert_eq!(
+            propose(&mut feedback, CODEC_HEVC, FEATURE_POSE | FEATURE_HAPTICS),
+            NEGOTIATION_ACCEPTED
+        );
+        assert_eq!(
+            propose(&mut feedback, CODEC_H264, FEATURE_POSE),
+            NEGOTIATION_REJECTED
+        );
+        assert_eq!(
+            propose(&mut feedback, CODEC_HEVC, FEATURE_GAME_AUDIO),
+            NEGOTIATION_REJECTED
+        );
+        assert_eq!(
+            propose(&mut feedback, CODEC_HEVC | CODEC_H264, 0),
+            NEGOTIATION_REJECTED
+        );
+        // Widening the advertised codecs in shared memory does not widen
+        // what the bridge accepts.
+        feedback
+            .header_mut()
+            .bridge_codecs
+            .store(CODEC_HEVC | CODEC_H264, Ordering::Relaxed);
+        assert_eq!(
+            propose(&mut feedback, CODEC_H264, FEATURE_POSE),
+            NEGOTIATION_REJECTED
+        );
+
+        drop(feedback);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn publishes_gaze_and_expressions() {
+        let path = std::env::temp_dir().join(format!(
+            "alvr-tracking-face-{}-{}",
+            process::id(),
+            unix_time_ns()
+        ));
+        let mut feedback = TrackingFeedback::create_at(&path, 3, TEST_CAPABILITIES).unwrap();
+        let gaze = Quat::from_rotation_y(0.2);
+
+        assert!(!feedback.publish_face(Duration::from_millis(1), &FaceData::default()));
+        assert!(feedback.publish_face(
+            Duration::from_millis(2),
+            &FaceData {
+                eyes_combined: Some(gaze),
+                eyes_social: [Some(gaze), Some(Quat::from_xyzw(f32::NAN, 0.0, 0.0, 1.0))],
+                face_expressions: Some(FaceExpressions::Htc {
+                    eye: None,
+                    lip: Some(vec![0.5; HTC_LIP_EXPRESSIONS_LEN]),
+                }),
+            },
+        ));
+
+        let face = &feedback.header_mut().face;
+        assert!(face.sequence.load(Ordering::Acquire).is_multiple_of(2));
+        assert_eq!(face.flags, FACE_EYE_COMBINED | FACE_EYE_LEFT | FACE_HTC_LIP);
+        assert_eq!(face.tracking_timestamp_ns, 2_000_000);
+        assert_eq!(face.eyes[0], gaze.to_array());
+        assert_eq!(face.eyes[2], [0.0; 4]);
+        assert_eq!(face.expression_format, FACE_EXPRESSIONS_HTC);
+        assert_eq!(face.expression_count, 51);
+        assert_eq!(face.expressions[HTC_EYE_EXPRESSIONS_LEN - 1], 0.0);
+        assert_eq!(face.expressions[HTC_EYE_EXPRESSIONS_LEN], 0.5);
+
+        // A Meta set of the wrong length is dropped rather than truncated.
+        assert!(!feedback.publish_face(
+            Duration::from_millis(3),
+            &FaceData {
+                face_expressions: Some(FaceExpressions::Fb(vec![0.1; 63])),
+                ..FaceData::default()
+            },
+        ));
+        feedback.reset();
+        assert_eq!(feedback.header_mut().face.flags, 0);
+
+        drop(feedback);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn publishes_hand_skeletons_until_the_hand_is_lost() {
+        let path = std::env::temp_dir().join(format!(
+            "alvr-tracking-hands-{}-{}",
+            process::id(),
+            unix_time_ns()
+        ));
+        let mut feedback = TrackingFeedback::create_at(&path, 4, TEST_CAPABILITIES).unwrap();
+        let mut skeleton = [Pose::IDENTITY; HAND_JOINT_COUNT];
+        skeleton[1].position = Vec3::new(0.1, 1.2, -0.3);
+
+        assert!(feedback.publish_hand_skeleton(1, Duration::from_millis(5), Some(&skeleton)));
+        let hand = &feedback.header_mut().hands[1];
+        assert_eq!(hand.tracked.load(Ordering::Acquire), 1);
+        assert_eq!(hand.tracking_timestamp_ns, 5_000_000);
+        assert_eq!(hand.joints[1], [0.1, 1.2, -0.3, 0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(
+            feedback.header_mut().hands[0]
+                .tracked
+                .load(Ordering::Acquire),
+            0
+        );
+
+        skeleton[3].orientation = Quat::from_xyzw(0.0, 0.0, 0.0, 0.0);
+        assert!(!feedback.publish_hand_skeleton(1, Duration::from_millis(6), Some(&skeleton)));
+        assert_eq!(
+            feedback.header_mut().hands[1].tracking_timestamp_ns,
+            5_000_000
+        );
+        assert!(!feedback.publish_hand_skeleton(1, Duration::from_millis(7), None));
+        let hand = &feedback.header_mut().hands[1];
+        assert_eq!(hand.tracked.load(Ordering::Acquire), 0);
+        assert!(hand.sequence.load(Ordering::Acquire).is_multiple_of(2));
+
+        drop(feedback);
+        fs::remove_file(path).unwrap();
+    }
+
     #[test]
     fn rejects_symlinked_shared_memory_path() {
         let target = std::env::temp_dir().join(format!(
@@ -997,9 +2521,479 @@ mod tests {
         File::create(&target).unwrap();
         std::os::unix::fs::symlink(&target, &link).unwrap();
 
-        assert!(TrackingFeedback::create_at(&link, 46).is_err());
+        assert!(TrackingFeedback::create_at(&link, 46, TEST_CAPABILITIES).is_err());
 
         fs::remove_file(link).unwrap();
         fs::remove_file(target).unwrap();
     }
+
+    #[test]
+    fn publishes_bridge_stats_for_the_driver() {
+        let path = std::env::temp_dir().join(format!(
+            "alvr-tracking-feedback-stats-{}-{}",
+            process::id(),
+            unix_time_ns()
+        ));
+        let mut feedback = TrackingFeedback::create_at(&path, 42, TEST_CAPABILITIES).unwrap();
+        assert_eq!(feedback.header().bridge_stats.state, BRIDGE_STATE_STARTING);
+        assert_eq!(feedback.header().bridge_stats.update_wall_ns, 0);
+
+        feedback.publish_bridge_stats(BridgeStats {
+            state: BridgeState::WaitingForClient,
+            encoder_queue_depth: 2,
+            frame_rate: 89.9996,
+            encode_latency: LatencySummary {
+                samples: 90,
+                p50: Duration::from_micros(3_200),
+                p99: Duration::from_micros(7_900),
+                max: Duration::from_millis(9),
+            },
+            bitrate_bps: 60_000_000,
+        });
+
+        let stats = &feedback.header().bridge_stats;
+        assert_eq!(stats.sequence.load(Ordering::Acquire), 2);
+        assert_eq!(stats.state, BRIDGE_STATE_WAITING_FOR_CLIENT);
+        assert_eq!(stats.encoder_queue_depth, 2);
+        assert_eq!(stats.frame_rate_millihz, 90_000);
+        assert_eq!(stats.encode_latency_p50_us, 3_200);
+        assert_eq!(stats.encode_latency_p99_us, 7_900);
+        assert_eq!(stats.bitrate_bps, 60_000_000);
+        assert!(stats.update_wall_ns > 0);
+        drop(feedback);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reads_the_driver_pause_flag() {
+        let path = std::env::temp_dir().join(format!(
+            "alvr-tracking-feedback-pause-{}-{}",
+            process::id(),
+            unix_time_ns()
+        ));
+        let mut feedback = TrackingFeedback::create_at(&path, 43, TEST_CAPABILITIES).unwrap();
+        assert!(!feedback.driver_paused());
+
+        {
+            let header = feedback.header_mut();
+            header.driver_paused.store(1, Ordering::Release);
+            header
+                .driver_heartbeat_ns
+                .store(unix_time_ns(), Ordering::Release);
+        }
+        feedback.refresh_heartbeat();
+        assert!(feedback.driver_paused());
+
+        // A driver that dies while paused must not hold the bridge in standby.
+        feedback
+            .header_mut()
+            .driver_heartbeat_ns
+            .store(1, Ordering::Release);
+        feedback.refresh_heartbeat();
+        assert!(!feedback.driver_paused());
+
+        drop(feedback);
+        fs::remove_file(path).unwrap();
+    }
+
+    /// A `#[repr(C)]` struct as the generated C header spells it.
+    struct CStruct {
+        name: &'static str,
+        size: usize,
+        align: usize,
+        fields: Vec<CField>,
+    }
+
+    struct CField {
+        name: &'static str,
+        c_type: &'static str,
+        dimensions: Vec<(String, usize)>,
+        offset: usize,
+        size: usize,
+        align: usize,
+    }
+
+    fn pointee_layout<T>(_: *const T) -> (usize, usize) {
+        (mem::size_of::<T>(), mem::align_of::<T>())
+    }
+
+    fn c_dimension(name: &str, length: usize) -> (String, usize) {
+        if name.bytes().all(|byte| byte.is_ascii_digit()) {
+            (name.to_owned(), length)
+        } else {
+            (format!("ALVR_SHM_{name}"), length)
+        }
+    }
+
+    /// Pairs each field of a Rust struct with its C type. Offsets and sizes
+    /// come from the compiler, so only the C spelling is written by hand.
+    macro_rules! c_struct {
+        ($rust:ty => $name:literal {
+            $($field:ident: $c_type:literal $([$dimension:tt])*),* $(,)?
+        }) => {{
+            let raw = mem::MaybeUninit::<$rust>::uninit();
+            let raw = raw.as_ptr();
+            CStruct {
+                name: $name,
+                size: mem::size_of::<$rust>(),
+                align: mem::align_of::<$rust>(),
+                fields: vec![$({
+                    let (size, align) = pointee_layout(unsafe { ptr::addr_of!((*raw).$field) });
+                    CField {
+                        name: stringify!($field),
+                        c_type: $c_type,
+                        dimensions: vec![
+                            $(c_dimension(stringify!($dimension), $dimension as usize)),*
+                        ],
+                        offset: mem::offset_of!($rust, $field),
+                        size,
+                        align,
+                    }
+                }),*],
+            }
+        }};
+    }
+
+    fn shared_memory_layout() -> Vec<CStruct> {
+        vec![
+            c_struct!(FrameHeaderRaw => "alvr_shm_frame_header" {
+                state: "ALVR_SHM_ATOMIC(uint32_t)",
+                width: "uint32_t",
+                height: "uint32_t",
+                stride: "uint32_t",
+                timestamp_ns: "uint64_t",
+                frame_number: "uint64_t",
+                is_idr: "uint8_t",
+                padding: "uint8_t" [7],
+                pose: "float" [3] [4],
+                producer_publish_wall_ns: "uint64_t",
+                producer_capture_total_us: "uint32_t",
+                producer_copy_resource_us: "uint32_t",
+                producer_map_wait_us: "uint32_t",
+                producer_copy_pixels_us: "uint32_t",
+                producer_pair_copy_us: "uint32_t",
+                producer_left_capture_us: "uint32_t",
+                producer_right_capture_us: "uint32_t",
+                producer_real_submit_us: "uint32_t",
+            }),
+            c_struct!(ControllerStateRaw => "alvr_shm_controller_state" {
+                sequence: "ALVR_SHM_ATOMIC(uint32_t)",
+                connected: "ALVR_SHM_ATOMIC(uint32_t)",
+                packet_number: "uint32_t",
+                reserved: "uint32_t",
+                tracking_timestamp_ns: "uint64_t",
+                motion_update_wall_ns: "uint64_t",
+                input_update_wall_ns: "uint64_t",
+                pose: "float" [3] [4],
+                linear_velocity: "float" [3],
+                angular_velocity: "float" [3],
+                buttons_pressed: "uint64_t",
+                buttons_touched: "uint64_t",
+                axes: "float" [5] [2],
+                padding: "uint8_t" [8],
+            }),
+            c_struct!(PoseSampleRaw => "alvr_shm_pose_sample" {
+                sequence: "ALVR_SHM_ATOMIC(uint32_t)",
+                reserved: "uint32_t",
+                timestamp_ns: "uint64_t",
+                pose: "float" [3] [4],
+            }),
+            c_struct!(InputEventRaw => "alvr_shm_input_event" {
+                sequence: "ALVR_SHM_ATOMIC(uint32_t)",
+                controller_index: "uint32_t",
+                packet_number: "uint32_t",
+                reserved: "uint32_t",
+                tracking_timestamp_ns: "uint64_t",
+                input_update_wall_ns: "uint64_t",
+                pose: "float" [3] [4],
+                buttons_pressed: "uint64_t",
+                buttons_touched: "uint64_t",
+                axes: "float" [5] [2],
+            }),
+            c_struct!(FaceStateRaw => "alvr_shm_face_state" {
+                sequence: "ALVR_SHM_ATOMIC(uint32_t)",
+                flags: "uint32_t",
+                tracking_timestamp_ns: "uint64_t",
+                eyes: "float" [3] [4],
+                expression_format: "uint32_t",
+                expression_count: "uint32_t",
+                expressions: "float" [FACE_EXPRESSIONS_LEN],
+                padding: "uint8_t" [8],
+            }),
+            c_struct!(HandSkeletonRaw => "alvr_shm_hand_skeleton" {
+                sequence: "ALVR_SHM_ATOMIC(uint32_t)",
+                tracked: "ALVR_SHM_ATOMIC(uint32_t)",
+                tracking_timestamp_ns: "uint64_t",
+                joints: "float" [HAND_JOINT_COUNT] [7],
+            }),
+            c_struct!(HapticsEventRaw => "alvr_shm_haptics_event" {
+                sequence: "ALVR_SHM_ATOMIC(uint32_t)",
+                controller_index: "uint32_t",
+                duration_ns: "uint64_t",
+                frequency: "float",
+                amplitude: "float",
+            }),
+            c_struct!(BridgeStatsRaw => "alvr_shm_bridge_stats" {
+                sequence: "ALVR_SHM_ATOMIC(uint32_t)",
+                state: "uint32_t",
+                encoder_queue_depth: "uint32_t",
+                frame_rate_millihz: "uint32_t",
+                encode_latency_p50_us: "uint32_t",
+                encode_latency_p99_us: "uint32_t",
+                bitrate_bps: "uint64_t",
+                update_wall_ns: "uint64_t",
+            }),
+            c_struct!(SharedMemoryHeader => "alvr_shm_header" {
+                magic: "ALVR_SHM_ATOMIC(uint32_t)",
+                version: "ALVR_SHM_ATOMIC(uint32_t)",
+                initialized: "ALVR_SHM_ATOMIC(uint32_t)",
+                shutdown: "ALVR_SHM_ATOMIC(uint32_t)",
+                config_width: "ALVR_SHM_ATOMIC(uint32_t)",
+                config_height: "ALVR_SHM_ATOMIC(uint32_t)",
+                config_format: "ALVR_SHM_ATOMIC(uint32_t)",
+                config_set: "ALVR_SHM_ATOMIC(uint32_t)",
+                write_sequence: "ALVR_SHM_ATOMIC(uint64_t)",
+                read_sequence: "ALVR_SHM_ATOMIC(uint64_t)",
+                frames_written: "ALVR_SHM_ATOMIC(uint64_t)",
+                frames_encoded: "ALVR_SHM_ATOMIC(uint64_t)",
+                frames_dropped: "ALVR_SHM_ATOMIC(uint64_t)",
+                bridge_session_id: "ALVR_SHM_ATOMIC(uint64_t)",
+                bridge_heartbeat_ns: "ALVR_SHM_ATOMIC(uint64_t)",
+                view_config_set: "ALVR_SHM_ATOMIC(uint32_t)",
+                view_fov: "float" [2] [4],
+                view_eye_x_m: "float" [2],
+                hmd_pose_set: "ALVR_SHM_ATOMIC(uint32_t)",
+                hmd_pose_sequence: "ALVR_SHM_ATOMIC(uint32_t)",
+                frame_pose_sequence: "ALVR_SHM_ATOMIC(uint32_t)",
+                hmd_pose_timestamp_ns: "ALVR_SHM_ATOMIC(uint64_t)",
+                frame_pose_timestamp_ns: "ALVR_SHM_ATOMIC(uint64_t)",
+                frame_pose: "float" [3] [4],
+                hmd_pose: "float" [3] [4],
+                frame_headers: "struct alvr_shm_frame_header" [NUM_BUFFERS],
+                controllers: "struct alvr_shm_controller_state" [NUM_CONTROLLERS],
+                telemetry_sequence: "ALVR_SHM_ATOMIC(uint32_t)",
+                client_state: "ALVR_SHM_ATOMIC(uint32_t)",
+                stream_contract_valid: "ALVR_SHM_ATOMIC(uint32_t)",
+                telemetry_reserved: "uint32_t",
+                runtime_generation: "ALVR_SHM_ATOMIC(uint64_t)",
+                bridge_pid: "ALVR_SHM_ATOMIC(uint64_t)",
+                stream_epoch: "ALVR_SHM_ATOMIC(uint64_t)",
+                frames_transported: "ALVR_SHM_ATOMIC(uint64_t)",
+                connect_events: "ALVR_SHM_ATOMIC(uint64_t)",
+                disconnect_events: "ALVR_SHM_ATOMIC(uint64_t)",
+                contract_failure_events: "ALVR_SHM_ATOMIC(uint64_t)",
+                hmd_pose_ring_head: "ALVR_SHM_ATOMIC(uint64_t)",
+                hmd_pose_ring: "struct alvr_shm_pose_sample" [POSE_RING_LEN],
+                input_ring_head: "ALVR_SHM_ATOMIC(uint64_t)",
+                input_ring: "struct alvr_shm_input_event" [INPUT_RING_LEN],
+                haptics_write_head: "ALVR_SHM_ATOMIC(uint64_t)",
+                haptics_read_head: "ALVR_SHM_ATOMIC(uint64_t)",
+                haptics_ring: "struct alvr_shm_haptics_event" [HAPTICS_RING_LEN],
+                bridge_generation: "ALVR_SHM_ATOMIC(uint64_t)",
+                driver_pid: "ALVR_SHM_ATOMIC(uint64_t)",
+                driver_heartbeat_ns: "ALVR_SHM_ATOMIC(uint64_t)",
+                bridge_formats: "ALVR_SHM_ATOMIC(uint32_t)",
+                bridge_codecs: "ALVR_SHM_ATOMIC(uint32_t)",
+                bridge_max_width: "ALVR_SHM_ATOMIC(uint32_t)",
+                bridge_max_height: "ALVR_SHM_ATOMIC(uint32_t)",
+                bridge_features: "ALVR_SHM_ATOMIC(uint32_t)",
+                negotiation_state: "ALVR_SHM_ATOMIC(uint32_t)",
+                driver_protocol_version: "ALVR_SHM_ATOMIC(uint32_t)",
+                driver_format: "ALVR_SHM_ATOMIC(uint32_t)",
+                driver_codec: "ALVR_SHM_ATOMIC(uint32_t)",
+                driver_features: "ALVR_SHM_ATOMIC(uint32_t)",
+                face: "struct alvr_shm_face_state",
+                hands: "struct alvr_shm_hand_skeleton" [NUM_CONTROLLERS],
+                bridge_stats: "struct alvr_shm_bridge_stats",
+                driver_paused: "ALVR_SHM_ATOMIC(uint32_t)",
+                driver_pause_reserved: "uint32_t",
+            }),
+        ]
+    }
+
+    fn shared_memory_constants() -> Vec<(&'static str, String)> {
+        let string = |value: &str| format!("\"{value}\"");
+        let count = |value: usize| value.to_string();
+        let u32 = |value: u32| format!("UINT32_C({value:#x})");
+        let u64 = |value: u64| format!("UINT64_C({value:#x})");
+        vec![
+            ("PATH", string(SHM_PATH)),
+            ("POSIX_NAME", string(POSIX_SHM_NAME)),
+            ("MAGIC", u32(SHM_MAGIC)),
+            ("VERSION", count(SHM_VERSION as usize)),
+            ("NUM_BUFFERS", count(NUM_BUFFERS)),
+            ("NUM_CONTROLLERS", count(NUM_CONTROLLERS)),
+            ("POSE_RING_LEN", count(POSE_RING_LEN)),
+            ("INPUT_RING_LEN", count(INPUT_RING_LEN)),
+            ("HAPTICS_RING_LEN", count(HAPTICS_RING_LEN)),
+            ("FACE_EXPRESSIONS_LEN", count(FACE_EXPRESSIONS_LEN)),
+            ("BD_EXPRESSIONS_LEN", count(BD_EXPRESSIONS_LEN)),
+            ("HAND_JOINT_COUNT", count(HAND_JOINT_COUNT)),
+            ("HTC_EYE_EXPRESSIONS_LEN", count(HTC_EYE_EXPRESSIONS_LEN)),
+            ("HTC_LIP_EXPRESSIONS_LEN", count(HTC_LIP_EXPRESSIONS_LEN)),
+            ("CLIENT_STATE_WAITING", u32(CLIENT_STATE_WAITING)),
+            ("CLIENT_STATE_CONNECTED", u32(CLIENT_STATE_CONNECTED)),
+            ("CLIENT_STATE_STREAMING", u32(CLIENT_STATE_STREAMING)),
+            ("FORMAT_NV12", u32(FORMAT_NV12)),
+            ("FORMAT_P010", u32(FORMAT_P010)),
+            ("CODEC_H264", u32(CODEC_H264)),
+            ("CODEC_HEVC", u32(CODEC_HEVC)),
+            ("CODEC_AV1", u32(CODEC_AV1)),
+            ("FEATURE_POSE", u32(FEATURE_POSE)),
+            ("FEATURE_CONTROLLER_INPUT", u32(FEATURE_CONTROLLER_INPUT)),
+            ("FEATURE_HAPTICS", u32(FEATURE_HAPTICS)),
+            ("FEATURE_GAME_AUDIO", u32(FEATURE_GAME_AUDIO)),
+            ("FEATURE_EYE_TRACKING", u32(FEATURE_EYE_TRACKING)),
+            ("FEATURE_FACE_TRACKING", u32(FEATURE_FACE_TRACKING)),
+            ("FEATURE_HAND_TRACKING", u32(FEATURE_HAND_TRACKING)),
+            ("FACE_EYE_COMBINED", u32(FACE_EYE_COMBINED)),
+            ("FACE_EYE_LEFT", u32(FACE_EYE_LEFT)),
+            ("FACE_EYE_RIGHT", u32(FACE_EYE_RIGHT)),
+            ("FACE_HTC_EYE", u32(FACE_HTC_EYE)),
+            ("FACE_HTC_LIP", u32(FACE_HTC_LIP)),
+            ("FACE_EXPRESSIONS_NONE", u32(FACE_EXPRESSIONS_NONE)),
+            ("FACE_EXPRESSIONS_FB", u32(FACE_EXPRESSIONS_FB)),
+            ("FACE_EXPRESSIONS_BD", u32(FACE_EXPRESSIONS_BD)),
+            ("FACE_EXPRESSIONS_HTC", u32(FACE_EXPRESSIONS_HTC)),
+            ("BRIDGE_STATE_STARTING", u32(BRIDGE_STATE_STARTING)),
+            (
+                "BRIDGE_STATE_WAITING_FOR_PRODUCER",
+                u32(BRIDGE_STATE_WAITING_FOR_PRODUCER),
+            ),
+            (
+                "BRIDGE_STATE_WAITING_FOR_CLIENT",
+                u32(BRIDGE_STATE_WAITING_FOR_CLIENT),
+            ),
+            ("BRIDGE_STATE_STANDBY", u32(BRIDGE_STATE_STANDBY)),
+            ("BRIDGE_STATE_STREAMING", u32(BRIDGE_STATE_STREAMING)),
+            ("BRIDGE_STATE_CLOSING", u32(BRIDGE_STATE_CLOSING)),
+            ("NEGOTIATION_NONE", u32(NEGOTIATION_NONE)),
+            ("NEGOTIATION_PROPOSED", u32(NEGOTIATION_PROPOSED)),
+            ("NEGOTIATION_ACCEPTED", u32(NEGOTIATION_ACCEPTED)),
+            ("NEGOTIATION_REJECTED", u32(NEGOTIATION_REJECTED)),
+            ("BUTTON_SYSTEM", u64(BUTTON_SYSTEM)),
+            ("BUTTON_APPLICATION_MENU", u64(BUTTON_APPLICATION_MENU)),
+            ("BUTTON_GRIP", u64(BUTTON_GRIP)),
+            ("BUTTON_A", u64(BUTTON_A)),
+            ("BUTTON_TOUCHPAD", u64(BUTTON_TOUCHPAD)),
+            ("BUTTON_TRIGGER", u64(BUTTON_TRIGGER)),
+        ]
+    }
+
+    fn c_type_size(c_type: &str, structs: &[CStruct]) -> usize {
+        match c_type {
+            "uint8_t" => 1,
+            "uint32_t" | "float" | "ALVR_SHM_ATOMIC(uint32_t)" => 4,
+            "uint64_t" | "ALVR_SHM_ATOMIC(uint64_t)" => 8,
+            _ => {
+                structs
+                    .iter()
+                    .find(|c_struct| c_type == format!("struct {}", c_struct.name))
+                    .unwrap_or_else(|| panic!("{c_type} is declared after its first use"))
+                    .size
+            }
+        }
+    }
+
+    /// Renders `tracking_feedback_layout.h`, the layout the Wine driver
+    /// compiles against. Every field's C type has to account for its Rust
+    /// size, and the fields have to lay out back to back under C's padding
+    /// rules, so a field added on the Rust side without its C spelling fails
+    /// here rather than as a silently shifted offset in the driver.
+    fn c_header() -> String {
+        let structs = shared_memory_layout();
+        let mut header = String::from(C_HEADER_PREAMBLE);
+        for (name, value) in shared_memory_constants() {
+            header += &format!("#define ALVR_SHM_{name} {value}\n");
+        }
+        for (index, c_struct) in structs.iter().enumerate() {
+            let mut end = 0usize;
+            header += &format!("\nstruct {}\n{{\n", c_struct.name);
+            for field in &c_struct.fields {
+                let length = field
+                    .dimensions
+                    .iter()
+                    .map(|(_, length)| length)
+                    .product::<usize>();
+                assert_eq!(
+                    c_type_size(field.c_type, &structs[..index]) * length,
+                    field.size,
+                    "{}.{} is not a {}",
+                    c_struct.name,
+                    field.name,
+                    field.c_type
+                );
+                assert_eq!(
+                    field.offset,
+                    end.next_multiple_of(field.align),
+                    "{}.{} does not follow the previous field",
+                    c_struct.name,
+                    field.name
+                );
+                end = field.offset + field.size;
+                let dimensions = field
+                    .dimensions
+                    .iter()
+                    .map(|(name, _)| format!("[{name}]"))
+                    .collect::<String>();
+                header += &format!("    {} {}{dimensions};\n", field.c_type, field.name);
+            }
+            assert_eq!(
+                c_struct.size,
+                end.next_multiple_of(c_struct.align),
+                "{} has fields missing from its C spelling",
+                c_struct.name
+            );
+            header += "};\n\n";
+            header += &format!(
+                "ALVR_SHM_STATIC_ASSERT(sizeof(struct {0}) == {1},\n                       \"{0} layout changed\");\n",
+                c_struct.name, c_struct.size
+            );
+            for field in &c_struct.fields {
+                header += &format!(
+                    "ALVR_SHM_STATIC_ASSERT(offsetof(struct {0}, {1}) == {2},\n                       \"{0} layout changed\");\n",
+                    c_struct.name, field.name, field.offset
+                );
+            }
+        }
+        header + "\n#endif\n"
+    }
+
+    const C_HEADER_PREAMBLE: &str = "\
+/* Generated from tracking_feedback.rs. Do not edit; after changing the Rust
+ * layout, regenerate it with
+ *
+ *     ALVR_BRIDGE_WRITE_C_HEADER=1 cargo test -p alvr_macos_bridge c_header
+ */
+#ifndef ALVR_TRACKING_FEEDBACK_LAYOUT_H
+#define ALVR_TRACKING_FEEDBACK_LAYOUT_H
+
+#if defined(__cplusplus)
+#include <atomic>
+#include <cstddef>
+#include <cstdint>
+#define ALVR_SHM_ATOMIC(type) std::atomic<type>
+#define ALVR_SHM_STATIC_ASSERT(condition, message) static_assert(condition, message)
+#else
+#include <stdatomic.h>
+#include <stddef.h>
+#include <stdint.h>
+#define ALVR_SHM_ATOMIC(type) _Atomic(type)
+#define ALVR_SHM_STATIC_ASSERT(condition, message) _Static_assert(condition, message)
+#endif
+
+";
+
+    #[test]
+    fn c_header_matches_the_rust_layout() {
+        let header = c_header();
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/tracking_feedback_layout.h");
+        if env::var("ALVR_BRIDGE_WRITE_C_HEADER").as_deref() == Ok("1") {
+            fs::write(&path, &header).unwrap();
+        }
+        assert!(
+            fs::read_to_string(&path).unwrap() == header,
+            "src/tracking_feedback_layout.h is stale; regenerate it with ALVR_BRIDGE_WRITE_C_HEADER=1 cargo test -p alvr_macos_bridge c_header"
+        );
+    }
 }
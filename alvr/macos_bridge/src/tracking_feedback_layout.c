@@ -0,0 +1 @@
+#include "tracking_feedback_layout.h"
@@ -0,0 +1,543 @@
+/* Generated from tracking_feedback.rs. Do not edit; after changing the Rust
+ * layout, regenerate it with
+ *
+ *     ALVR_BRIDGE_WRITE_C_HEADER=1 cargo test -p alvr_macos_bridge c_header
+ */
+#ifndef ALVR_TRACKING_FEEDBACK_LAYOUT_H
+#define ALVR_TRACKING_FEEDBACK_LAYOUT_H
+
+#if defined(__cplusplus)
+#include <atomic>
+#include <cstddef>
+#include <cstdint>
+#define ALVR_SHM_ATOMIC(type) std::atomic<type>
+#define ALVR_SHM_STATIC_ASSERT(condition, message) static_assert(condition, message)
+#else
+#include <stdatomic.h>
+#include <stddef.h>
+#include <stdint.h>
+#define ALVR_SHM_ATOMIC(type) _Atomic(type)
+#define ALVR_SHM_STATIC_ASSERT(condition, message) _Static_assert(condition, message)
+#endif
+
+#define ALVR_SHM_PATH "/tmp/alvr_frame_buffer.shm"
+#define ALVR_SHM_POSIX_NAME "/alvr_frame_buffer"
+#define ALVR_SHM_MAGIC UINT32_C(0x414c5652)
+#define ALVR_SHM_VERSION 16
+#define ALVR_SHM_NUM_BUFFERS 3
+#define ALVR_SHM_NUM_CONTROLLERS 2
+#define ALVR_SHM_POSE_RING_LEN 16
+#define ALVR_SHM_INPUT_RING_LEN 8
+#define ALVR_SHM_HAPTICS_RING_LEN 16
+#define ALVR_SHM_FACE_EXPRESSIONS_LEN 70
+#define ALVR_SHM_BD_EXPRESSIONS_LEN 52
+#define ALVR_SHM_HAND_JOINT_COUNT 26
+#define ALVR_SHM_HTC_EYE_EXPRESSIONS_LEN 14
+#define ALVR_SHM_HTC_LIP_EXPRESSIONS_LEN 37
+#define ALVR_SHM_CLIENT_STATE_WAITING UINT32_C(0x0)
+#define ALVR_SHM_CLIENT_STATE_CONNECTED UINT32_C(0x1)
+#define ALVR_SHM_CLIENT_STATE_STREAMING UINT32_C(0x2)
+#define ALVR_SHM_FORMAT_NV12 UINT32_C(0x1)
+#define ALVR_SHM_FORMAT_P010 UINT32_C(0x2)
+#define ALVR_SHM_CODEC_H264 UINT32_C(0x1)
+#define ALVR_SHM_CODEC_HEVC UINT32_C(0x2)
+#define ALVR_SHM_CODEC_AV1 UINT32_C(0x4)
+#define ALVR_SHM_FEATURE_POSE UINT32_C(0x1)
+#define ALVR_SHM_FEATURE_CONTROLLER_INPUT UINT32_C(0x2)
+#define ALVR_SHM_FEATURE_HAPTICS UINT32_C(0x4)
+#define ALVR_SHM_FEATURE_GAME_AUDIO UINT32_C(0x8)
+#define ALVR_SHM_FEATURE_EYE_TRACKING UINT32_C(0x10)
+#define ALVR_SHM_FEATURE_FACE_TRACKING UINT32_C(0x20)
+#define ALVR_SHM_FEATURE_HAND_TRACKING UINT32_C(0x40)
+#define ALVR_SHM_FACE_EYE_COMBINED UINT32_C(0x1)
+#define ALVR_SHM_FACE_EYE_LEFT UINT32_C(0x2)
+#define ALVR_SHM_FACE_EYE_RIGHT UINT32_C(0x4)
+#define ALVR_SHM_FACE_HTC_EYE UINT32_C(0x8)
+#define ALVR_SHM_FACE_HTC_LIP UINT32_C(0x10)
+#define ALVR_SHM_FACE_EXPRESSIONS_NONE UINT32_C(0x0)
+#define ALVR_SHM_FACE_EXPRESSIONS_FB UINT32_C(0x1)
+#define ALVR_SHM_FACE_EXPRESSIONS_BD UINT32_C(0x2)
+#define ALVR_SHM_FACE_EXPRESSIONS_HTC UINT32_C(0x3)
+#define ALVR_SHM_BRIDGE_STATE_STARTING UINT32_C(0x0)
+#define ALVR_SHM_BRIDGE_STATE_WAITING_FOR_PRODUCER UINT32_C(0x1)
+#define ALVR_SHM_BRIDGE_STATE_WAITING_FOR_CLIENT UINT32_C(0x2)
+#define ALVR_SHM_BRIDGE_STATE_STANDBY UINT32_C(0x3)
+#define ALVR_SHM_BRIDGE_STATE_STREAMING UINT32_C(0x4)
+#define ALVR_SHM_BRIDGE_STATE_CLOSING UINT32_C(0x5)
+#define ALVR_SHM_NEGOTIATION_NONE UINT32_C(0x0)
+#define ALVR_SHM_NEGOTIATION_PROPOSED UINT32_C(0x1)
+#define ALVR_SHM_NEGOTIATION_ACCEPTED UINT32_C(0x2)
+#define ALVR_SHM_NEGOTIATION_REJECTED UINT32_C(0x3)
+#define ALVR_SHM_BUTTON_SYSTEM UINT64_C(0x1)
+#define ALVR_SHM_BUTTON_APPLICATION_MENU UINT64_C(0x2)
+#define ALVR_SHM_BUTTON_GRIP UINT64_C(0x4)
+#define ALVR_SHM_BUTTON_A UINT64_C(0x80)
+#define ALVR_SHM_BUTTON_TOUCHPAD UINT64_C(0x100000000)
+#define ALVR_SHM_BUTTON_TRIGGER UINT64_C(0x200000000)
+
+struct alvr_shm_frame_header
+{
+    ALVR_SHM_ATOMIC(uint32_t) state;
+    uint32_t width;
+    uint32_t height;
+    uint32_t stride;
+    uint64_t timestamp_ns;
+    uint64_t frame_number;
+    uint8_t is_idr;
+    uint8_t padding[7];
+    float pose[3][4];
+    uint64_t producer_publish_wall_ns;
+    uint32_t producer_capture_total_us;
+    uint32_t producer_copy_resource_us;
+    uint32_t producer_map_wait_us;
+    uint32_t producer_copy_pixels_us;
+    uint32_t producer_pair_copy_us;
+    uint32_t producer_left_capture_us;
+    uint32_t producer_right_capture_us;
+    uint32_t producer_real_submit_us;
+};
+
+ALVR_SHM_STATIC_ASSERT(sizeof(struct alvr_shm_frame_header) == 128,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, state) == 0,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, width) == 4,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, height) == 8,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, stride) == 12,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, timestamp_ns) == 16,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, frame_number) == 24,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, is_idr) == 32,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, padding) == 33,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, pose) == 40,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, producer_publish_wall_ns) == 88,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, producer_capture_total_us) == 96,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, producer_copy_resource_us) == 100,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, producer_map_wait_us) == 104,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, producer_copy_pixels_us) == 108,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, producer_pair_copy_us) == 112,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, producer_left_capture_us) == 116,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, producer_right_capture_us) == 120,
+                       "alvr_shm_frame_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_frame_header, producer_real_submit_us) == 124,
+                       "alvr_shm_frame_header layout changed");
+
+struct alvr_shm_controller_state
+{
+    ALVR_SHM_ATOMIC(uint32_t) sequence;
+    ALVR_SHM_ATOMIC(uint32_t) connected;
+    uint32_t packet_number;
+    uint32_t reserved;
+    uint64_t tracking_timestamp_ns;
+    uint64_t motion_update_wall_ns;
+    uint64_t input_update_wall_ns;
+    float pose[3][4];
+    float linear_velocity[3];
+    float angular_velocity[3];
+    uint64_t buttons_pressed;
+    uint64_t buttons_touched;
+    float axes[5][2];
+    uint8_t padding[8];
+};
+
+ALVR_SHM_STATIC_ASSERT(sizeof(struct alvr_shm_controller_state) == 176,
+                       "alvr_shm_controller_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_controller_state, sequence) == 0,
+                       "alvr_shm_controller_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_controller_state, connected) == 4,
+                       "alvr_shm_controller_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_controller_state, packet_number) == 8,
+                       "alvr_shm_controller_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_controller_state, reserved) == 12,
+                       "alvr_shm_controller_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_controller_state, tracking_timestamp_ns) == 16,
+                       "alvr_shm_controller_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_controller_state, motion_update_wall_ns) == 24,
+                       "alvr_shm_controller_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_controller_state, input_update_wall_ns) == 32,
+                       "alvr_shm_controller_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_controller_state, pose) == 40,
+                       "alvr_shm_controller_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_controller_state, linear_velocity) == 88,
+                       "alvr_shm_controller_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_controller_state, angular_velocity) == 100,
+                       "alvr_shm_controller_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_controller_state, buttons_pressed) == 112,
+                       "alvr_shm_controller_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_controller_state, buttons_touched) == 120,
+                       "alvr_shm_controller_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_controller_state, axes) == 128,
+                       "alvr_shm_controller_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_controller_state, padding) == 168,
+                       "alvr_shm_controller_state layout changed");
+
+struct alvr_shm_pose_sample
+{
+    ALVR_SHM_ATOMIC(uint32_t) sequence;
+    uint32_t reserved;
+    uint64_t timestamp_ns;
+    float pose[3][4];
+};
+
+ALVR_SHM_STATIC_ASSERT(sizeof(struct alvr_shm_pose_sample) == 64,
+                       "alvr_shm_pose_sample layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_pose_sample, sequence) == 0,
+                       "alvr_shm_pose_sample layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_pose_sample, reserved) == 4,
+                       "alvr_shm_pose_sample layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_pose_sample, timestamp_ns) == 8,
+                       "alvr_shm_pose_sample layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_pose_sample, pose) == 16,
+                       "alvr_shm_pose_sample layout changed");
+
+struct alvr_shm_input_event
+{
+    ALVR_SHM_ATOMIC(uint32_t) sequence;
+    uint32_t controller_index;
+    uint32_t packet_number;
+    uint32_t reserved;
+    uint64_t tracking_timestamp_ns;
+    uint64_t input_update_wall_ns;
+    float pose[3][4];
+    uint64_t buttons_pressed;
+    uint64_t buttons_touched;
+    float axes[5][2];
+};
+
+ALVR_SHM_STATIC_ASSERT(sizeof(struct alvr_shm_input_event) == 136,
+                       "alvr_shm_input_event layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_input_event, sequence) == 0,
+                       "alvr_shm_input_event layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_input_event, controller_index) == 4,
+                       "alvr_shm_input_event layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_input_event, packet_number) == 8,
+                       "alvr_shm_input_event layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_input_event, reserved) == 12,
+                       "alvr_shm_input_event layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_input_event, tracking_timestamp_ns) == 16,
+                       "alvr_shm_input_event layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_input_event, input_update_wall_ns) == 24,
+                       "alvr_shm_input_event layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_input_event, pose) == 32,
+                       "alvr_shm_input_event layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_input_event, buttons_pressed) == 80,
+                       "alvr_shm_input_event layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_input_event, buttons_touched) == 88,
+                       "alvr_shm_input_event layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_input_event, axes) == 96,
+                       "alvr_shm_input_event layout changed");
+
+struct alvr_shm_face_state
+{
+    ALVR_SHM_ATOMIC(uint32_t) sequence;
+    uint32_t flags;
+    uint64_t tracking_timestamp_ns;
+    float eyes[3][4];
+    uint32_t expression_format;
+    uint32_t expression_count;
+    float expressions[ALVR_SHM_FACE_EXPRESSIONS_LEN];
+    uint8_t padding[8];
+};
+
+ALVR_SHM_STATIC_ASSERT(sizeof(struct alvr_shm_face_state) == 360,
+                       "alvr_shm_face_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_face_state, sequence) == 0,
+                       "alvr_shm_face_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_face_state, flags) == 4,
+                       "alvr_shm_face_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_face_state, tracking_timestamp_ns) == 8,
+                       "alvr_shm_face_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_face_state, eyes) == 16,
+                       "alvr_shm_face_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_face_state, expression_format) == 64,
+                       "alvr_shm_face_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_face_state, expression_count) == 68,
+                       "alvr_shm_face_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_face_state, expressions) == 72,
+                       "alvr_shm_face_state layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_face_state, padding) == 352,
+                       "alvr_shm_face_state layout changed");
+
+struct alvr_shm_hand_skeleton
+{
+    ALVR_SHM_ATOMIC(uint32_t) sequence;
+    ALVR_SHM_ATOMIC(uint32_t) tracked;
+    uint64_t tracking_timestamp_ns;
+    float joints[ALVR_SHM_HAND_JOINT_COUNT][7];
+};
+
+ALVR_SHM_STATIC_ASSERT(sizeof(struct alvr_shm_hand_skeleton) == 744,
+                       "alvr_shm_hand_skeleton layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_hand_skeleton, sequence) == 0,
+                       "alvr_shm_hand_skeleton layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_hand_skeleton, tracked) == 4,
+                       "alvr_shm_hand_skeleton layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_hand_skeleton, tracking_timestamp_ns) == 8,
+                       "alvr_shm_hand_skeleton layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_hand_skeleton, joints) == 16,
+                       "alvr_shm_hand_skeleton layout changed");
+
+struct alvr_shm_haptics_event
+{
+    ALVR_SHM_ATOMIC(uint32_t) sequence;
+    uint32_t controller_index;
+    uint64_t duration_ns;
+    float frequency;
+    float amplitude;
+};
+
+ALVR_SHM_STATIC_ASSERT(sizeof(struct alvr_shm_haptics_event) == 24,
+                       "alvr_shm_haptics_event layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_haptics_event, sequence) == 0,
+                       "alvr_shm_haptics_event layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_haptics_event, controller_index) == 4,
+                       "alvr_shm_haptics_event layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_haptics_event, duration_ns) == 8,
+                       "alvr_shm_haptics_event layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_haptics_event, frequency) == 16,
+                       "alvr_shm_haptics_event layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_haptics_event, amplitude) == 20,
+                       "alvr_shm_haptics_event layout changed");
+
+struct alvr_shm_bridge_stats
+{
+    ALVR_SHM_ATOMIC(uint32_t) sequence;
+    uint32_t state;
+    uint32_t encoder_queue_depth;
+    uint32_t frame_rate_millihz;
+    uint32_t encode_latency_p50_us;
+    uint32_t encode_latency_p99_us;
+    uint64_t bitrate_bps;
+    uint64_t update_wall_ns;
+};
+
+ALVR_SHM_STATIC_ASSERT(sizeof(struct alvr_shm_bridge_stats) == 40,
+                       "alvr_shm_bridge_stats layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_bridge_stats, sequence) == 0,
+                       "alvr_shm_bridge_stats layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_bridge_stats, state) == 4,
+                       "alvr_shm_bridge_stats layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_bridge_stats, encoder_queue_depth) == 8,
+                       "alvr_shm_bridge_stats layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_bridge_stats, frame_rate_millihz) == 12,
+                       "alvr_shm_bridge_stats layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_bridge_stats, encode_latency_p50_us) == 16,
+                       "alvr_shm_bridge_stats layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_bridge_stats, encode_latency_p99_us) == 20,
+                       "alvr_shm_bridge_stats layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_bridge_stats, bitrate_bps) == 24,
+                       "alvr_shm_bridge_stats layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_bridge_stats, update_wall_ns) == 32,
+                       "alvr_shm_bridge_stats layout changed");
+
+struct alvr_shm_header
+{
+    ALVR_SHM_ATOMIC(uint32_t) magic;
+    ALVR_SHM_ATOMIC(uint32_t) version;
+    ALVR_SHM_ATOMIC(uint32_t) initialized;
+    ALVR_SHM_ATOMIC(uint32_t) shutdown;
+    ALVR_SHM_ATOMIC(uint32_t) config_width;
+    ALVR_SHM_ATOMIC(uint32_t) config_height;
+    ALVR_SHM_ATOMIC(uint32_t) config_format;
+    ALVR_SHM_ATOMIC(uint32_t) config_set;
+    ALVR_SHM_ATOMIC(uint64_t) write_sequence;
+    ALVR_SHM_ATOMIC(uint64_t) read_sequence;
+    ALVR_SHM_ATOMIC(uint64_t) frames_written;
+    ALVR_SHM_ATOMIC(uint64_t) frames_encoded;
+    ALVR_SHM_ATOMIC(uint64_t) frames_dropped;
+    ALVR_SHM_ATOMIC(uint64_t) bridge_session_id;
+    ALVR_SHM_ATOMIC(uint64_t) bridge_heartbeat_ns;
+    ALVR_SHM_ATOMIC(uint32_t) view_config_set;
+    float view_fov[2][4];
+    float view_eye_x_m[2];
+    ALVR_SHM_ATOMIC(uint32_t) hmd_pose_set;
+    ALVR_SHM_ATOMIC(uint32_t) hmd_pose_sequence;
+    ALVR_SHM_ATOMIC(uint32_t) frame_pose_sequence;
+    ALVR_SHM_ATOMIC(uint64_t) hmd_pose_timestamp_ns;
+    ALVR_SHM_ATOMIC(uint64_t) frame_pose_timestamp_ns;
+    float frame_pose[3][4];
+    float hmd_pose[3][4];
+    struct alvr_shm_frame_header frame_headers[ALVR_SHM_NUM_BUFFERS];
+    struct alvr_shm_controller_state controllers[ALVR_SHM_NUM_CONTROLLERS];
+    ALVR_SHM_ATOMIC(uint32_t) telemetry_sequence;
+    ALVR_SHM_ATOMIC(uint32_t) client_state;
+    ALVR_SHM_ATOMIC(uint32_t) stream_contract_valid;
+    uint32_t telemetry_reserved;
+    ALVR_SHM_ATOMIC(uint64_t) runtime_generation;
+    ALVR_SHM_ATOMIC(uint64_t) bridge_pid;
+    ALVR_SHM_ATOMIC(uint64_t) stream_epoch;
+    ALVR_SHM_ATOMIC(uint64_t) frames_transported;
+    ALVR_SHM_ATOMIC(uint64_t) connect_events;
+    ALVR_SHM_ATOMIC(uint64_t) disconnect_events;
+    ALVR_SHM_ATOMIC(uint64_t) contract_failure_events;
+    ALVR_SHM_ATOMIC(uint64_t) hmd_pose_ring_head;
+    struct alvr_shm_pose_sample hmd_pose_ring[ALVR_SHM_POSE_RING_LEN];
+    ALVR_SHM_ATOMIC(uint64_t) input_ring_head;
+    struct alvr_shm_input_event input_ring[ALVR_SHM_INPUT_RING_LEN];
+    ALVR_SHM_ATOMIC(uint64_t) haptics_write_head;
+    ALVR_SHM_ATOMIC(uint64_t) haptics_read_head;
+    struct alvr_shm_haptics_event haptics_ring[ALVR_SHM_HAPTICS_RING_LEN];
+    ALVR_SHM_ATOMIC(uint64_t) bridge_generation;
+    ALVR_SHM_ATOMIC(uint64_t) driver_pid;
+    ALVR_SHM_ATOMIC(uint64_t) driver_heartbeat_ns;
+    ALVR_SHM_ATOMIC(uint32_t) bridge_formats;
+    ALVR_SHM_ATOMIC(uint32_t) bridge_codecs;
+    ALVR_SHM_ATOMIC(uint32_t) bridge_max_width;
+    ALVR_SHM_ATOMIC(uint32_t) bridge_max_height;
+    ALVR_SHM_ATOMIC(uint32_t) bridge_features;
+    ALVR_SHM_ATOMIC(uint32_t) negotiation_state;
+    ALVR_SHM_ATOMIC(uint32_t) driver_protocol_version;
+    ALVR_SHM_ATOMIC(uint32_t) driver_format;
+    ALVR_SHM_ATOMIC(uint32_t) driver_codec;
+    ALVR_SHM_ATOMIC(uint32_t) driver_features;
+    struct alvr_shm_face_state face;
+    struct alvr_shm_hand_skeleton hands[ALVR_SHM_NUM_CONTROLLERS];
+    struct alvr_shm_bridge_stats bridge_stats;
+    ALVR_SHM_ATOMIC(uint32_t) driver_paused;
+    uint32_t driver_pause_reserved;
+};
+
+ALVR_SHM_STATIC_ASSERT(sizeof(struct alvr_shm_header) == 5552,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, magic) == 0,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, version) == 4,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, initialized) == 8,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, shutdown) == 12,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, config_width) == 16,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, config_height) == 20,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, config_format) == 24,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, config_set) == 28,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, write_sequence) == 32,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, read_sequence) == 40,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, frames_written) == 48,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, frames_encoded) == 56,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, frames_dropped) == 64,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, bridge_session_id) == 72,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, bridge_heartbeat_ns) == 80,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, view_config_set) == 88,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, view_fov) == 92,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, view_eye_x_m) == 124,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, hmd_pose_set) == 132,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, hmd_pose_sequence) == 136,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, frame_pose_sequence) == 140,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, hmd_pose_timestamp_ns) == 144,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, frame_pose_timestamp_ns) == 152,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, frame_pose) == 160,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, hmd_pose) == 208,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, frame_headers) == 256,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, controllers) == 640,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, telemetry_sequence) == 992,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, client_state) == 996,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, stream_contract_valid) == 1000,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, telemetry_reserved) == 1004,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, runtime_generation) == 1008,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, bridge_pid) == 1016,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, stream_epoch) == 1024,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, frames_transported) == 1032,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, connect_events) == 1040,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, disconnect_events) == 1048,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, contract_failure_events) == 1056,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, hmd_pose_ring_head) == 1064,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, hmd_pose_ring) == 1072,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, input_ring_head) == 2096,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, input_ring) == 2104,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, haptics_write_head) == 3192,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, haptics_read_head) == 3200,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, haptics_ring) == 3208,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, bridge_generation) == 3592,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, driver_pid) == 3600,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, driver_heartbeat_ns) == 3608,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, bridge_formats) == 3616,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, bridge_codecs) == 3620,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, bridge_max_width) == 3624,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, bridge_max_height) == 3628,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, bridge_features) == 3632,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, negotiation_state) == 3636,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, driver_protocol_version) == 3640,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, driver_format) == 3644,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, driver_codec) == 3648,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, driver_features) == 3652,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, face) == 3656,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, hands) == 4016,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, bridge_stats) == 5504,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, driver_paused) == 5544,
+                       "alvr_shm_header layout changed");
+ALVR_SHM_STATIC_ASSERT(offsetof(struct alvr_shm_header, driver_pause_reserved) == 5548,
+                       "alvr_shm_header layout changed");
+
+#endif
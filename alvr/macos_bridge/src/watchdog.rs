@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant};
+
+/// Recoveries in a row without any output before the bridge gives up on
+/// VideoToolbox and fails the run.
+pub(crate) const MAX_CONSECUTIVE_RECOVERIES: u32 = 3;
+
+/// Notices a VideoToolbox session that has stopped answering. After a GPU
+/// reset or display sleep the session can keep accepting frames without ever
+/// calling back, so every lease it holds stays out of the pool and the loop
+/// drops frames with nothing to say why. The watchdog measures from the
+/// oldest submitted frame that has seen no output since; frames arriving
+/// while that wait exceeds the limit mean the session is stalled.
+pub(crate) struct EncoderWatchdog {
+    stall: Duration,
+    waiting_since: Option<Instant>,
+    recoveries: u32,
+}
+
+impl EncoderWatchdog {
+    pub fn new(stall: Duration) -> Self {
+        Self {
+            stall,
+            waiting_since: None,
+            recoveries: 0,
+        }
+    }
+
+    pub fn submitted(&mut self, now: Instant) {
+        self.waiting_since.get_or_insert(now);
+    }
+
+    /// Any output shows the session is alive; whatever it still holds is
+    /// measured from now.
+    pub fn output(&mut self, now: Instant, pending: usize) {
+        self.waiting_since = (pending > 0).then_some(now);
+        self.recoveries = 0;
+    }
+
+    /// How long the session has gone without output, once past the limit.
+    pub fn stalled(&self, now: Instant) -> Option<Duration> {
+        self.waiting_since
+            .map(|since| now.saturating_duration_since(since))
+            .filter(|waited| *waited >= self.stall)
+    }
+
+    /// Called with a replacement session in place. Returns how many
+    /// recoveries have run without output in between.
+    pub fn recovered(&mut self) -> u32 {
+        self.waiting_since = None;
+        self.recoveries += 1;
+        self.recoveries
+    }
+
+    /// Called when the session is replaced for any other reason.
+    pub fn reset(&mut self) {
+        self.waiting_since = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_a_session_that_stops_answering() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut watchdog = EncoderWatchdog::new(Duration::from_secs(2));
+
+        watchdog.submitted(at(0));
+        watchdog.submitted(at(11));
+        assert_eq!(watchdog.stalled(at(1_999)), None);
+        watchdog.output(at(20), 1);
+        assert_eq!(watchdog.stalled(at(2_010)), None);
+        assert_eq!(watchdog.stalled(at(2_020)), Some(Duration::from_secs(2)));
+
+        assert_eq!(watchdog.recovered(), 1);
+        assert_eq!(watchdog.stalled(at(9_000)), None);
+        watchdog.submitted(at(9_000));
+        assert_eq!(watchdog.recovered(), 2);
+        watchdog.output(at(9_100), 0);
+        assert_eq!(watchdog.stalled(at(20_000)), None);
+        assert_eq!(watchdog.recovered(), 1);
+    }
+}
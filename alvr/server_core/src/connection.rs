@@ -37,7 +37,7 @@ use std::{
     hash::{Hash, Hasher},
     net::{IpAddr, Ipv4Addr},
     process::Command,
-    sync::{Arc, mpsc::RecvTimeoutError},
+    sync::{Arc, atomic::Ordering, mpsc::RecvTimeoutError},
     thread,
     time::{Duration, Instant},
 };
@@ -490,6 +490,41 @@ fn try_connect(
     lifecycle_state: Arc<RwLock<LifecycleState>>,
     mut client_ips: HashMap<IpAddr, String>,
 ) -> ConResult {
+    // A client replaced by a newer one is held back as well, or the two would keep taking the
+    // stream from each other.
+    let held_back = ctx.exclusive_client.load(Ordering::Relaxed) || {
+        let replaced_clients = ctx.replaced_clients.lock();
+        client_ips
+            .values()
+            .all(|hostname| replaced_clients.contains(hostname))
+    };
+    if held_back {
+        let active_client = SESSION_MANAGER
+            .read()
+            .client_list()
+            .iter()
+            .find(|(hostname, info)| {
+                !client_ips.values().any(|candidate| candidate == *hostname)
+                    && !matches!(
+                        info.connection_state,
+                        ConnectionState::Disconnected | ConnectionState::Disconnecting
+                    )
+            })
+            .map(|(hostname, _)| hostname.clone());
+        let mut rejected_clients = ctx.rejected_clients.lock();
+        if let Some(active_client) = active_client {
+            for hostname in client_ips.values() {
+                if rejected_clients.insert(hostname.clone()) {
+                    warn!("Not connecting {hostname}: {active_client} is already connected");
+                }
+            }
+
+            return Ok(());
+        }
+        rejected_clients.clear();
+        ctx.replaced_clients.lock().clear();
+    }
+
     dbg_connection!("try_connect: Finding client and creating control socket");
 
     let (socket, client_ip, connection_result) = alvr_sockets::connect_to_client(
@@ -1417,13 +1452,21 @@ fn connection_pipeline(
     alvr_common::wait_rwlock(&disconnect_notif, &mut session_manager_lock);
     dbg_connection!("connection_pipeline: Begin connection shutdown");
 
-    // This requests shutdown from threads
-    *ctx.control_sender.lock() = None;
-    *ctx.decoder_config.lock() = None;
-    *ctx.video_channel_sender.lock() = None;
-    *ctx.haptics_sender.lock() = None;
-
-    *ctx.video_recording_file.lock() = None;
+    // This requests shutdown from threads. A client that was replaced by a newer connection leaves
+    // the newer client's senders in place and its own threads stop on the connection state.
+    let owns_stream = ctx
+        .control_sender
+        .lock()
+        .as_ref()
+        .is_some_and(|sender| Arc::ptr_eq(sender, &control_sender));
+    if owns_stream {
+        *ctx.control_sender.lock() = None;
+        *ctx.decoder_config.lock() = None;
+        *ctx.video_channel_sender.lock() = None;
+        *ctx.haptics_sender.lock() = None;
+
+        *ctx.video_recording_file.lock() = None;
+    }
 
     session_manager_lock.update_client_connections(
         client_hostname,
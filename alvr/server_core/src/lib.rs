@@ -33,8 +33,8 @@ use alvr_common::{
 use alvr_events::{EventType, HapticsEvent};
 use alvr_filesystem as afs;
 use alvr_packets::{
-    BatteryInfo, ButtonEntry, ClientConnectionsAction, DecoderInitializationConfig, Haptics,
-    ServerControlPacket, VideoPacketHeader,
+    BatteryInfo, ButtonEntry, ClientConnectionsAction, DecoderInitializationConfig, FaceData,
+    Haptics, ServerControlPacket, VideoPacketHeader,
 };
 use alvr_server_io::ServerSessionManager;
 use alvr_session::{CodecType, H264Profile, OpenvrProperty, Settings, SteamvrHmdInitConfig};
@@ -125,6 +125,10 @@ pub struct ConnectionContext {
     control_sender: Mutex<Option<Arc<Mutex<ControlSocketSender<ServerControlPacket>>>>>,
     video_channel_sender: Mutex<Option<SyncSender<VideoPacket>>>,
     haptics_sender: Mutex<Option<StreamSender<Haptics>>>,
+    // When set, a client is not connected while another one is.
+    exclusive_client: AtomicBool,
+    rejected_clients: Mutex<HashSet<String>>,
+    replaced_clients: Mutex<HashSet<String>>,
 }
 
 pub fn create_recording_file(connection_context: &ConnectionContext, settings: &Settings) {
@@ -244,6 +248,9 @@ impl ServerCoreContext {
             control_sender: Mutex::new(None),
             video_channel_sender: Mutex::new(None),
             haptics_sender: Mutex::new(None),
+            exclusive_client: AtomicBool::new(false),
+            rejected_clients: Mutex::new(HashSet::new()),
+            replaced_clients: Mutex::new(HashSet::new()),
         });
 
         let webserver_runtime = Runtime::new().unwrap();
@@ -276,6 +283,47 @@ impl ServerCoreContext {
         }));
     }
 
+    /// Turns away new clients while one is connected, instead of streaming
+    /// to the newest one.
+    pub fn set_exclusive_client(&self, exclusive: bool) {
+        self.connection_context
+            .exclusive_client
+            .store(exclusive, Ordering::Relaxed);
+    }
+
+    pub fn streaming_clients(&self) -> Vec<String> {
+        SESSION_MANAGER
+            .read()
+            .client_list()
+            .iter()
+            .filter(|(_, info)| info.connection_state == ConnectionState::Streaming)
+            .map(|(hostname, _)| hostname.clone())
+            .collect()
+    }
+
+    /// Ends the stream to a client that a newer one replaces. It is not
+    /// connected again while another client is.
+    pub fn disconnect_client(&self, hostname: &str) {
+        dbg_server_core!("disconnect_client");
+
+        self.connection_context
+            .replaced_clients
+            .lock()
+            .insert(hostname.to_owned());
+
+        let mut session_manager_lock = SESSION_MANAGER.write();
+        if session_manager_lock
+            .client_list()
+            .get(hostname)
+            .is_some_and(|info| info.connection_state == ConnectionState::Streaming)
+        {
+            session_manager_lock.update_client_connections(
+                hostname.to_owned(),
+                ClientConnectionsAction::SetConnectionState(ConnectionState::Disconnecting),
+            );
+        }
+    }
+
     pub fn get_device_motion(
         &self,
         device_id: u64,
@@ -303,6 +351,16 @@ impl ServerCoreContext {
             .copied()
     }
 
+    pub fn get_face_data(&self, timestamp: Duration) -> Option<FaceData> {
+        dbg_server_core!("get_face_data: ts={timestamp:?}");
+
+        self.connection_context
+            .tracking_manager
+            .read()
+            .get_face_data(timestamp)
+            .cloned()
+    }
+
     pub fn get_motion_to_photon_latency(&self) -> Duration {
         dbg_server_core!("get_motion_to_photon_latency");
 
@@ -18,7 +18,7 @@ use alvr_common::{
     inputs as inp,
 };
 use alvr_events::{EventType, TrackingEvent};
-use alvr_packets::TrackingData;
+use alvr_packets::{FaceData, TrackingData};
 use alvr_session::{
     BodyTrackingConfig, HeadsetConfig, RecenteringMode, Settings, VMCConfig,
     settings_schema::Switch,
@@ -53,6 +53,7 @@ pub struct TrackingManager {
     inverse_recentering_origin: Pose, // client's reference space
     device_motions_history: HashMap<u64, VecDeque<(Duration, DeviceMotion)>>,
     hand_skeletons_history: [VecDeque<(Duration, [Pose; 26])>; 2],
+    face_data_history: VecDeque<(Duration, FaceData)>,
     max_history_size: usize,
 }
 
@@ -63,6 +64,7 @@ impl TrackingManager {
             inverse_recentering_origin: Pose::IDENTITY,
             device_motions_history: HashMap::new(),
             hand_skeletons_history: [VecDeque::new(), VecDeque::new()],
+            face_data_history: VecDeque::new(),
             max_history_size,
         }
     }
@@ -254,6 +256,22 @@ impl TrackingManager {
             .map(|(_, skeleton)| skeleton)
     }
 
+    // Eye orientations are relative to the head, so they need no recentering
+    pub fn report_face_data(&mut self, timestamp: Duration, face_data: FaceData) {
+        self.face_data_history.push_back((timestamp, face_data));
+
+        if self.face_data_history.len() > self.max_history_size {
+            self.face_data_history.pop_front();
+        }
+    }
+
+    pub fn get_face_data(&self, sample_timestamp: Duration) -> Option<&FaceData> {
+        self.face_data_history
+            .iter()
+            .find(|(timestamp, _)| *timestamp == sample_timestamp)
+            .map(|(_, face_data)| face_data)
+    }
+
     pub fn unrecenter_view_params(&self, view_params: &mut [ViewParams; 2]) {
         for params in view_params {
             params.pose = self.inverse_recentering_origin.inverse() * params.pose;
@@ -375,6 +393,13 @@ pub fn tracking_loop(
                 tracking_manager_lock.report_hand_skeleton(HandType::Right, timestamp, skeleton);
             }
 
+            if tracking.face.eyes_combined.is_some()
+                || tracking.face.eyes_social.iter().any(Option::is_some)
+                || tracking.face.face_expressions.is_some()
+            {
+                tracking_manager_lock.report_face_data(timestamp, tracking.face.clone());
+            }
+
             if let Some(sink) = &mut face_tracking_sink {
                 sink.send_tracking(&tracking.face);
             }